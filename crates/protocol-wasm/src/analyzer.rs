@@ -0,0 +1,434 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use eagleeyes::analysis::{fingerprint, tls_policy};
+use eagleeyes::capture::{self, Reader, Record};
+use eagleeyes::event::{self, Event};
+use eagleeyes::flow::{self, Conversation, Conversations};
+use eagleeyes::protocols::decode_by_datalink;
+use eagleeyes::timestamp::Timestamp;
+use eagleeyes::tree::{self, Layer};
+use eagleeyes::{context, expert, reassembly};
+
+use crate::exchanges::{Exchange, Matcher};
+use crate::{summarize, tag, Decoded};
+
+// Analysis of a whole capture, fed by chunks: its packets are read and decoded in order as
+// the bytes come, and each chunk returns what was found in it, so the dashboard can show a
+// capture while it loads and stop at any time. The decoders keep per-thread state, so a
+// thread (the page or a Worker) analyzes one capture at a time.
+
+/// Packets between two calls of the progress callback
+const PROGRESS_EVERY: u64 = 1000;
+/// Bytes pushed at once by `analyze_capture`
+const CHUNK: usize = 1 << 20;
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Options {
+    /// size of the capture, for the progress
+    pub size: Option<u64>,
+    /// return the decoded packets, not only the flows, exchanges and stats
+    pub packets: bool,
+    /// protocol tree of each packet
+    pub layers: bool,
+    /// text description of each packet
+    pub description: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            size: None,
+            packets: true,
+            layers: false,
+            description: false,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Packet {
+    /// from 1, as in the CLI
+    pub index: u64,
+    /// seconds since the epoch
+    pub ts: Timestamp,
+    pub original_len: u32,
+    pub captured_len: usize,
+    /// offset of the packet data in the capture, to read its bytes from the buffer
+    pub offset: u64,
+    pub if_index: u32,
+    /// uid of its conversation in the flows
+    pub flow_id: Option<String>,
+    pub decoded: Decoded,
+}
+
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Progress {
+    /// bytes of the capture read
+    pub bytes: u64,
+    /// size of the capture, when given
+    pub total: Option<u64>,
+    pub packets: u64,
+    pub done: bool,
+    pub cancelled: bool,
+}
+
+/// Protocols in the packets, each layer under the one that carries it
+#[derive(Serialize, Clone)]
+pub struct Node {
+    pub name: String,
+    pub packets: u64,
+    pub bytes: u64,
+    pub children: Vec<Node>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    pub format: Option<&'static str>,
+    pub packets: u64,
+    pub bytes: u64,
+    pub captured_bytes: u64,
+    pub first_ts: Option<Timestamp>,
+    pub last_ts: Option<Timestamp>,
+    pub duration: f64,
+    /// bytes of a record not complete yet, or cut at the end of the capture
+    pub truncated_bytes: usize,
+    pub hierarchy: Vec<Node>,
+}
+
+/// What a chunk of the capture added; the last one also has the flows and stats
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Update {
+    pub packets: Vec<Packet>,
+    /// exchanges completed, or left without a request or response at the end
+    pub exchanges: Vec<Exchange>,
+    /// findings of the analyses: signatures, indicators, fingerprints, policy...
+    pub alerts: Vec<Event>,
+    pub progress: Progress,
+    /// conversations, as `eagleeyes flows --json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flows: Option<Vec<Conversation>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+}
+
+#[wasm_bindgen]
+pub struct Analyzer {
+    options: Options,
+    reader: Reader,
+    on_progress: Option<js_sys::Function>,
+    packets: u64,
+    bytes: u64,
+    captured: u64,
+    first_ts: Option<Timestamp>,
+    last_ts: Option<Timestamp>,
+    flows: Conversations,
+    hierarchy: Vec<Node>,
+    exchanges: Matcher,
+    cancelled: bool,
+    done: bool,
+}
+
+fn to_js(value: &impl Serialize) -> Result<JsValue, JsValue> {
+    // plain objects rather than Maps, for the flattened flow of the conversations
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+fn error(e: capture::Error) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+#[wasm_bindgen]
+impl Analyzer {
+    /// Start the analysis of a capture, with options `{ size, packets, layers, description }`
+    #[wasm_bindgen(constructor)]
+    pub fn new(options: JsValue) -> Result<Analyzer, JsValue> {
+        let options = if options.is_undefined() || options.is_null() {
+            Options::default()
+        } else {
+            serde_wasm_bindgen::from_value(options)?
+        };
+        Ok(Analyzer::with_options(options))
+    }
+
+    /// Call `callback(progress)` every few thousand packets; the analysis is cancelled when
+    /// it returns `false`
+    pub fn set_progress(&mut self, callback: js_sys::Function) {
+        self.on_progress = Some(callback);
+    }
+
+    /// Analyze the next bytes of the capture
+    pub fn push(&mut self, chunk: &[u8]) -> Result<JsValue, JsValue> {
+        let callback = self.on_progress.clone();
+        let update = self
+            .push_with(chunk, &mut |p| report(callback.as_ref(), p))
+            .map_err(error)?;
+        to_js(&update)
+    }
+
+    /// End the analysis: pending exchanges and state are flushed, and the update has the
+    /// flows and stats
+    pub fn finish(&mut self) -> Result<JsValue, JsValue> {
+        to_js(&self.end())
+    }
+
+    /// Stop reading the capture; `finish` still returns what was found
+    pub fn cancel(&mut self) {
+        self.cancelled = true;
+    }
+
+    /// Conversations so far
+    pub fn flows(&self) -> Result<JsValue, JsValue> {
+        to_js(&self.flows.to_vec())
+    }
+
+    /// Stats so far
+    pub fn stats(&self) -> Result<JsValue, JsValue> {
+        to_js(&self.snapshot())
+    }
+}
+
+/// Analyze a whole capture at once, calling `on_progress(progress)` as `Analyzer` does; for
+/// a Worker, where blocking does not freeze the page
+#[wasm_bindgen]
+pub fn analyze_capture(
+    bytes: &[u8],
+    options: JsValue,
+    on_progress: Option<js_sys::Function>,
+) -> Result<JsValue, JsValue> {
+    let mut analyzer = Analyzer::new(options)?;
+    analyzer.options.size.get_or_insert(bytes.len() as u64);
+    let update = analyzer
+        .analyze(bytes, &mut |p| report(on_progress.as_ref(), p))
+        .map_err(error)?;
+    to_js(&update)
+}
+
+/// Call the progress callback, whether to go on
+fn report(callback: Option<&js_sys::Function>, progress: &Progress) -> bool {
+    let (callback, progress) = match callback.map(|c| (c, to_js(progress))) {
+        Some((callback, Ok(progress))) => (callback, progress),
+        _ => return true,
+    };
+    !matches!(callback.call1(&JsValue::NULL, &progress), Ok(v) if v == JsValue::FALSE)
+}
+
+impl Analyzer {
+    pub fn with_options(options: Options) -> Self {
+        // state left by a previous capture
+        reassembly::flush();
+        fingerprint::flush();
+        tls_policy::flush();
+        event::drain();
+        expert::drain();
+        Analyzer {
+            options,
+            reader: Reader::new(),
+            on_progress: None,
+            packets: 0,
+            bytes: 0,
+            captured: 0,
+            first_ts: None,
+            last_ts: None,
+            flows: Conversations::default(),
+            hierarchy: Vec::new(),
+            exchanges: Matcher::default(),
+            cancelled: false,
+            done: false,
+        }
+    }
+
+    /// Analyze a whole capture, returning everything found in a single update
+    pub fn analyze(
+        &mut self,
+        bytes: &[u8],
+        on_progress: &mut dyn FnMut(&Progress) -> bool,
+    ) -> Result<Update, capture::Error> {
+        let mut all = Update::default();
+        for chunk in bytes.chunks(CHUNK) {
+            let update = self.push_with(chunk, on_progress)?;
+            all.packets.extend(update.packets);
+            all.exchanges.extend(update.exchanges);
+            all.alerts.extend(update.alerts);
+            if self.cancelled {
+                break;
+            }
+        }
+        let end = self.end();
+        all.exchanges.extend(end.exchanges);
+        all.alerts.extend(end.alerts);
+        Ok(Update {
+            progress: end.progress,
+            flows: end.flows,
+            stats: end.stats,
+            ..all
+        })
+    }
+
+    /// Analyze the next bytes of the capture, calling `on_progress` every few thousand
+    /// packets and stopping when it returns false
+    pub fn push_with(
+        &mut self,
+        chunk: &[u8],
+        on_progress: &mut dyn FnMut(&Progress) -> bool,
+    ) -> Result<Update, capture::Error> {
+        let mut update = Update::default();
+        if !self.cancelled && !self.done {
+            self.reader.push(chunk);
+        }
+        while !self.cancelled && !self.done {
+            let record = match self.reader.read()? {
+                Some(record) => record,
+                None => break,
+            };
+            self.packet(record, &mut update);
+            if self.packets.is_multiple_of(PROGRESS_EVERY) && !on_progress(&self.progress()) {
+                self.cancelled = true;
+            }
+        }
+        update.progress = self.progress();
+        Ok(update)
+    }
+
+    /// Flush the decoders and report what is left, with the flows and stats
+    pub fn end(&mut self) -> Update {
+        let mut update = Update::default();
+        if !self.done {
+            self.done = true;
+            reassembly::flush();
+            fingerprint::flush();
+            tls_policy::flush();
+            update.alerts = event::drain().into_iter().filter(is_alert).collect();
+            update.exchanges = self.exchanges.finish();
+            expert::drain();
+        }
+        update.progress = self.progress();
+        update.flows = Some(self.flows.to_vec());
+        update.stats = Some(self.snapshot());
+        update
+    }
+
+    fn progress(&self) -> Progress {
+        Progress {
+            bytes: self.reader.position(),
+            total: self.options.size,
+            packets: self.packets,
+            done: self.done,
+            cancelled: self.cancelled,
+        }
+    }
+
+    fn snapshot(&self) -> Stats {
+        let duration = match (self.first_ts, self.last_ts) {
+            (Some(f), Some(l)) => l.seconds_since(f),
+            _ => 0.0,
+        };
+        Stats {
+            format: self.reader.format().map(capture::Format::name),
+            packets: self.packets,
+            bytes: self.bytes,
+            captured_bytes: self.captured,
+            first_ts: self.first_ts,
+            last_ts: self.last_ts,
+            duration,
+            truncated_bytes: self.reader.buffered(),
+            hierarchy: self.hierarchy.clone(),
+        }
+    }
+
+    fn packet(&mut self, record: Record, update: &mut Update) {
+        self.packets += 1;
+        let ts = record.ts;
+        let len = u64::from(record.len);
+        self.bytes += len;
+        self.captured += record.data.len() as u64;
+        self.first_ts.get_or_insert(ts);
+        self.last_ts = Some(ts);
+
+        context::begin_packet();
+        if let Some(time) = ts.system_time() {
+            context::set_time(time);
+        }
+        let layers = tree::dissect(&record.data, |data| {
+            decode_by_datalink(record.linktype, data)
+        });
+        let expert = expert::drain();
+        update
+            .alerts
+            .extend(event::drain().into_iter().filter(is_alert));
+
+        let summary = flow::summarize(record.linktype, &record.data);
+        let flow_id = self.flows.add(&summary, ts, len).map(|c| c.uid.clone());
+        add_path(&mut self.hierarchy, &layers, len);
+        update.exchanges.extend(self.exchanges.packet(
+            self.packets,
+            ts,
+            &layers,
+            flow_id.as_deref(),
+        ));
+        if !self.options.packets {
+            return;
+        }
+        let mut decoded = summarize(&record.data, &summary, layers, expert);
+        if !self.options.layers {
+            decoded.layers = Vec::new();
+        }
+        if !self.options.description {
+            decoded.description = None;
+        }
+        update.packets.push(Packet {
+            index: self.packets,
+            ts,
+            original_len: record.len,
+            captured_len: record.data.len(),
+            offset: record.offset,
+            if_index: record.interface,
+            flow_id,
+            decoded,
+        });
+    }
+}
+
+/// Events reported as alerts: findings, rather than descriptions of the traffic
+fn is_alert(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Alert(_)
+            | Event::FingerprintMatch(_)
+            | Event::TlsPolicy(_)
+            | Event::Credential(_)
+            | Event::IocMatch(_)
+            | Event::ProtocolMismatch(_)
+    )
+}
+
+/// Count a packet in the hierarchy, under each of its layers
+fn add_path(nodes: &mut Vec<Node>, layers: &[Layer], bytes: u64) {
+    let (layer, rest) = match layers.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let name = tag(layer.name);
+    let i = match nodes.iter().position(|n| n.name == name) {
+        Some(i) => i,
+        None => {
+            nodes.push(Node {
+                name,
+                packets: 0,
+                bytes: 0,
+                children: Vec::new(),
+            });
+            nodes.len() - 1
+        }
+    };
+    nodes[i].packets += 1;
+    nodes[i].bytes += bytes;
+    add_path(&mut nodes[i].children, rest, bytes);
+}
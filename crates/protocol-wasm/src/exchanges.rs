@@ -0,0 +1,305 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+
+use eagleeyes::context;
+use eagleeyes::flow::FlowKey;
+use eagleeyes::timestamp::Timestamp;
+use eagleeyes::tree::Layer;
+
+// Requests and their responses, paired from the protocol trees of the packets: ARP
+// requests and replies, DNS queries and responses by transaction ID, ICMP echoes by
+// identifier and sequence number, and HTTP/1.x requests and responses in the order of
+// their connection.
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    /// seconds since the epoch
+    pub start_time: Timestamp,
+    pub end_time: Timestamp,
+    /// summary of the layer of the request or response
+    pub summary: String,
+    pub packet_ids: Vec<u64>,
+}
+
+/// Endpoints of an exchange, the requester first
+#[derive(Serialize, Clone)]
+pub struct Ends {
+    pub a: String,
+    pub b: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Timing {
+    pub rtt_ms: f64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Exchange {
+    pub id: String,
+    /// "arp", "dns", "icmp" or "http1"
+    pub protocol: &'static str,
+    /// uid of the conversation, as in the flows
+    pub flow_id: Option<String>,
+    pub flow: Option<Ends>,
+    pub request: Option<Message>,
+    pub response: Option<Message>,
+    pub timing: Option<Timing>,
+    /// "complete", or "partial" when the request or the response was not seen
+    pub status: &'static str,
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Key {
+    /// sender and target addresses of the request
+    Arp(String, String),
+    /// conversation and transaction ID
+    Dns(FlowKey, u16),
+    /// requester, responder, identifier and sequence number
+    Icmp(IpAddr, IpAddr, u16, u16),
+    /// connection, whose requests are answered in order
+    Http(FlowKey),
+}
+
+enum Role {
+    Request,
+    Response,
+}
+
+/// What a packet is in an exchange
+struct Part {
+    protocol: &'static str,
+    key: Key,
+    role: Role,
+    /// endpoints, oriented as the request
+    ends: Ends,
+    summary: String,
+}
+
+struct Pending {
+    protocol: &'static str,
+    flow_id: Option<String>,
+    ends: Ends,
+    request: Message,
+}
+
+/// Requests waiting for their response
+#[derive(Default)]
+pub struct Matcher {
+    pending: HashMap<Key, VecDeque<Pending>>,
+    count: u64,
+}
+
+impl Matcher {
+    /// Pair the packet just decoded, returning the exchange it completes or that has no
+    /// request
+    pub fn packet(
+        &mut self,
+        index: u64,
+        ts: Timestamp,
+        layers: &[Layer],
+        flow_id: Option<&str>,
+    ) -> Option<Exchange> {
+        let part = part(layers)?;
+        let message = Message {
+            start_time: ts,
+            end_time: ts,
+            summary: part.summary,
+            packet_ids: vec![index],
+        };
+        match part.role {
+            Role::Request => {
+                self.pending
+                    .entry(part.key)
+                    .or_default()
+                    .push_back(Pending {
+                        protocol: part.protocol,
+                        flow_id: flow_id.map(String::from),
+                        ends: part.ends,
+                        request: message,
+                    });
+                None
+            }
+            Role::Response => {
+                let request = self.pending.get_mut(&part.key).and_then(|q| q.pop_front());
+                Some(match request {
+                    Some(p) => self.exchange(
+                        p.protocol,
+                        p.flow_id,
+                        p.ends,
+                        Some(p.request),
+                        Some(message),
+                    ),
+                    None => self.exchange(
+                        part.protocol,
+                        flow_id.map(String::from),
+                        part.ends,
+                        None,
+                        Some(message),
+                    ),
+                })
+            }
+        }
+    }
+
+    /// The requests left without a response, by time
+    pub fn finish(&mut self) -> Vec<Exchange> {
+        let mut pending: Vec<Pending> = self.pending.drain().flat_map(|(_, q)| q).collect();
+        pending.sort_by(|a, b| {
+            a.request
+                .start_time
+                .cmp(&b.request.start_time)
+                .then_with(|| a.request.packet_ids.cmp(&b.request.packet_ids))
+        });
+        pending
+            .into_iter()
+            .map(|p| self.exchange(p.protocol, p.flow_id, p.ends, Some(p.request), None))
+            .collect()
+    }
+
+    fn exchange(
+        &mut self,
+        protocol: &'static str,
+        flow_id: Option<String>,
+        ends: Ends,
+        request: Option<Message>,
+        response: Option<Message>,
+    ) -> Exchange {
+        self.count += 1;
+        let timing = match (&request, &response) {
+            (Some(q), Some(r)) => Some(Timing {
+                rtt_ms: r.start_time.seconds_since(q.start_time) * 1000.0,
+            }),
+            _ => None,
+        };
+        Exchange {
+            id: format!("{}-{}", protocol, self.count),
+            protocol,
+            flow_id,
+            flow: Some(ends),
+            status: match timing {
+                Some(_) => "complete",
+                None => "partial",
+            },
+            request,
+            response,
+            timing,
+        }
+    }
+}
+
+fn value<'a>(layer: &'a Layer, name: &str) -> Option<&'a str> {
+    layer
+        .fields
+        .iter()
+        .find(|f| f.name == name)
+        .map(|f| f.value.as_str())
+}
+
+/// Number of a field shown as `0x1234`
+fn hex(layer: &Layer, name: &str) -> Option<u32> {
+    u32::from_str_radix(value(layer, name)?.strip_prefix("0x")?, 16).ok()
+}
+
+/// Number of a field shown as `Echo Request (8)`
+fn code(layer: &Layer, name: &str) -> Option<u32> {
+    let v = value(layer, name)?.strip_suffix(')')?;
+    v[v.rfind('(')? + 1..].parse().ok()
+}
+
+fn ends(flow: &FlowKey) -> Ends {
+    Ends {
+        a: SocketAddr::new(flow.src, flow.src_port).to_string(),
+        b: SocketAddr::new(flow.dst, flow.dst_port).to_string(),
+    }
+}
+
+/// The request or response carried by the innermost layer that has some
+fn part(layers: &[Layer]) -> Option<Part> {
+    layers.iter().rev().find_map(|l| match l.name {
+        "arp" => {
+            let spa = value(l, "Sender protocol address")?.to_string();
+            let tpa = value(l, "Target protocol address")?.to_string();
+            let (role, key, (a, b)) = match value(l, "Opcode")? {
+                "REQUEST" => (
+                    Role::Request,
+                    Key::Arp(spa.clone(), tpa.clone()),
+                    (spa, tpa),
+                ),
+                "REPLY" => (
+                    Role::Response,
+                    Key::Arp(tpa.clone(), spa.clone()),
+                    (tpa, spa),
+                ),
+                _ => return None,
+            };
+            Some(Part {
+                protocol: "arp",
+                key,
+                role,
+                ends: Ends { a, b },
+                summary: l.summary.clone(),
+            })
+        }
+        "dns" => {
+            let flow = context::flow()?;
+            let id = hex(l, "Transaction ID")? as u16;
+            let (role, ends) = match hex(l, "Flags")? & 0x8000 {
+                0 => (Role::Request, ends(&flow)),
+                _ => (Role::Response, ends(&flow.reversed())),
+            };
+            Some(Part {
+                protocol: "dns",
+                key: Key::Dns(flow.canonical().0, id),
+                role,
+                ends,
+                summary: l.summary.clone(),
+            })
+        }
+        "icmpv4" | "icmpv6" => {
+            let (src, dst) = context::addrs()?;
+            let rest = hex(l, "Rest of header")?;
+            let (id, seq) = ((rest >> 16) as u16, rest as u16);
+            let (role, key) = match code(l, "Type")? {
+                8 | 128 => (Role::Request, Key::Icmp(src, dst, id, seq)),
+                0 | 129 => (Role::Response, Key::Icmp(dst, src, id, seq)),
+                _ => return None,
+            };
+            let (a, b) = match role {
+                Role::Request => (src, dst),
+                Role::Response => (dst, src),
+            };
+            Some(Part {
+                protocol: "icmp",
+                key,
+                role,
+                ends: Ends {
+                    a: a.to_string(),
+                    b: b.to_string(),
+                },
+                summary: l.summary.clone(),
+            })
+        }
+        "http" => {
+            let flow = context::flow()?;
+            let line = value(l, "Start line")?;
+            let (role, ends) = match line.strip_prefix("HTTP/") {
+                None => (Role::Request, ends(&flow)),
+                // interim responses (100 Continue) come before the final one
+                Some(status) if status.split_whitespace().nth(1)?.starts_with('1') => return None,
+                Some(_) => (Role::Response, ends(&flow.reversed())),
+            };
+            Some(Part {
+                protocol: "http1",
+                key: Key::Http(flow.canonical().0),
+                role,
+                ends,
+                summary: line.to_string(),
+            })
+        }
+        _ => None,
+    })
+}
@@ -1,5 +1,17 @@
 use wasm_bindgen::prelude::*;
 use serde::Serialize;
+use eagleeyes::analysis::geoip::{self, GeoDb, GeoInfo};
+use eagleeyes::expert::ExpertInfo;
+use eagleeyes::flow::Summary;
+use eagleeyes::protocols::{ethernet, tcp};
+use eagleeyes::tree::{self, Field, Layer};
+use eagleeyes::{context, event, expert, flow};
+
+pub mod analyzer;
+mod exchanges;
+
+// The packets are decoded by the native decoders of the library, as by the CLI: the
+// structures below only summarize their protocol tree for the packet list of the dashboard.
 
 #[derive(Serialize, Default)]
 #[serde(rename_all = "camelCase")]
@@ -17,23 +29,9 @@ pub struct L3 {
     pub proto: Option<String>,
     pub src: Option<String>,
     pub dst: Option<String>,
-    // IPv4 specific fields
-    pub version: Option<u8>,
-    pub header_len: Option<u8>,
-    pub tos: Option<u8>,
-    pub total_len: Option<u16>,
-    pub identification: Option<u16>,
-    pub flags: Option<u8>,
-    pub fragment_offset: Option<u16>,
-    pub ttl: Option<u8>,
-    pub protocol: Option<u8>,
-    pub checksum: Option<u16>,
-    // IPv6 specific fields
-    pub traffic_class: Option<u8>,
-    pub flow_label: Option<u32>,
-    pub payload_len: Option<u16>,
-    pub next_header: Option<u8>,
-    pub hop_limit: Option<u8>,
+    // address scope, and GeoIP/ASN data once databases are loaded
+    pub src_geo: Option<GeoInfo>,
+    pub dst_geo: Option<GeoInfo>,
 }
 
 #[derive(Serialize, Default)]
@@ -42,21 +40,7 @@ pub struct L4 {
     pub proto: Option<String>,
     pub src_port: Option<u16>,
     pub dst_port: Option<u16>,
-    // TCP specific fields
     pub tcp_flags: Option<String>,
-    pub tcp_seq: Option<u32>,
-    pub tcp_ack: Option<u32>,
-    pub tcp_window: Option<u16>,
-    pub tcp_checksum: Option<u16>,
-    pub tcp_urgent: Option<u16>,
-    pub tcp_data_offset: Option<u8>,
-    // UDP specific fields
-    pub udp_len: Option<u16>,
-    pub udp_checksum: Option<u16>,
-    // ICMP specific fields
-    pub icmp_type: Option<u8>,
-    pub icmp_code: Option<u8>,
-    pub icmp_checksum: Option<u16>,
 }
 
 #[derive(Serialize, Default)]
@@ -69,311 +53,97 @@ pub struct Decoded {
     pub protocol_tag: String,
     pub app_tag: Option<String>,
     pub description: Option<String>,
+    // malformed or truncated layers
+    pub expert: Vec<ExpertInfo>,
+    // layers and fields with their byte spans, as decoded by the native decoders
+    pub layers: Vec<Layer>,
 }
 
-fn mac_to_str(m: &[u8]) -> String {
-    m.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":")
-}
-fn ipv4_to_str(b: &[u8]) -> String {
-    format!("{}.{}.{}.{}", b[0], b[1], b[2], b[3])
-}
-
-fn ipv6_to_str(b: &[u8]) -> String {
-    if b.len() != 16 { return String::new(); }
-    let mut parts = [0u16; 8];
-    for i in 0..8 {
-        parts[i] = u16::from_be_bytes([b[2 * i], b[2 * i + 1]]);
-    }
-    format!(
-        "{:x}:{:x}:{:x}:{:x}:{:x}:{:x}:{:x}:{:x}",
-        parts[0], parts[1], parts[2], parts[3], parts[4], parts[5], parts[6], parts[7]
-    )
-}
-
-fn ether_type_as_str(ether_type: u16) -> &'static str {
-    match ether_type {
-        0x0200 => "PUP",
-        0x0500 => "SPRITE",
-        0x0800 => "IPv4",
-        0x0806 => "ARP",
-        0x8035 => "REVARP",
-        0x809B => "AT",
-        0x80F3 => "AARP",
-        0x8100 => "VLAN",
-        0x8137 => "IPX",
-        0x86dd => "IPv6",
-        0x88cc => "LLDP",
-        0x8847 => "MPLS_U",
-        0x8848 => "MPLS_M",
-        0x8863 => "PPPoE-Discovery",
-        0x8864 => "PPPoE-Session",
-        0x9000 => "LOOPBACK",
-        _ => "UNKNOWN",
-    }
+/// Load a MaxMind database (GeoLite2 City, Country or ASN) to enrich the addresses
+/// of the next decoded packets; may be called once per database
+#[wasm_bindgen]
+pub fn load_geoip(db: &[u8]) -> Result<(), JsValue> {
+    let mut geo = GeoDb::default();
+    geo.add(db.to_vec()).map_err(|e| JsValue::from_str(&e.to_string()))?;
+    geoip::add_db(geo);
+    Ok(())
 }
 
 #[wasm_bindgen]
 pub fn decode_packet(bytes: &[u8]) -> Result<JsValue, JsValue> {
-    use eagleeyes::protocols::{ethernet, ip as ipm};
-
-    if bytes.len() < ethernet::Header::SIZE {
-        let out = Decoded { summary: format!("Truncated frame ({}B)", bytes.len()), protocol_tag: "FRAME".into(), ..Default::default() };
-        return serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()));
-    }
-
-    // Ethernet II
-    let (eth_hdr_bytes, rest) = bytes.split_at(ethernet::Header::SIZE);
-    let mut l2 = L2::default();
-    if let Some(h) = eagleeyes::utils::cow_struct::<ethernet::Header>(eth_hdr_bytes) {
-        l2.src_mac = Some(mac_to_str(&h.shost));
-        l2.dst_mac = Some(mac_to_str(&h.dhost));
-        let mut ether_type = u16::from_be(h.ether_type);
-        let mut off = ethernet::Header::SIZE;
-        // VLAN (802.1Q)
-        if ether_type == ethernet::PROTO::VLAN && bytes.len() >= off + 4 {
-            let tci = u16::from_be_bytes([bytes[off], bytes[off + 1]]);
-            l2.vlan = Some(tci & 0x0fff);
-            ether_type = u16::from_be_bytes([bytes[off + 2], bytes[off + 3]]);
-            off += 4;
-        }
-        l2.ether_type = Some(ether_type);
-        l2.ether_type_name = Some(ether_type_as_str(ether_type).to_string());
+    let out = decode(bytes);
+    serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()))
+}
 
-        // L3
-        if ether_type == ethernet::PROTO::IPV4 && bytes.len() >= off + 20 {
-            // IPv4 header
-            let version = (bytes[off] & 0xf0) >> 4;
-            let ihl = (bytes[off] & 0x0f) as usize * 4;
-            if ihl >= 20 && bytes.len() >= off + ihl {
-                let proto = bytes[off + 9];
-                let src = ipv4_to_str(&bytes[off + 12..off + 16]);
-                let dst = ipv4_to_str(&bytes[off + 16..off + 20]);
-                let tos = bytes[off + 1];
-                let total_len = u16::from_be_bytes([bytes[off + 2], bytes[off + 3]]);
-                let identification = u16::from_be_bytes([bytes[off + 4], bytes[off + 5]]);
-                let flags_and_offset = u16::from_be_bytes([bytes[off + 6], bytes[off + 7]]);
-                let flags = ((flags_and_offset >> 13) & 0x07) as u8;
-                let fragment_offset = flags_and_offset & 0x1fff;
-                let ttl = bytes[off + 8];
-                let checksum = u16::from_be_bytes([bytes[off + 10], bytes[off + 11]]);
-                let l3 = L3 {
-                    proto: Some("IPv4".into()),
-                    src: Some(src),
-                    dst: Some(dst),
-                    version: Some(version),
-                    header_len: Some((ihl / 4) as u8),
-                    tos: Some(tos),
-                    total_len: Some(total_len),
-                    identification: Some(identification),
-                    flags: Some(flags),
-                    fragment_offset: Some(fragment_offset),
-                    ttl: Some(ttl),
-                    protocol: Some(proto),
-                    checksum: Some(checksum),
-                    ..Default::default()
-                };
-                // L4
-                let l4_start = off + ihl;
-                let mut l4 = L4::default();
-                let mut summary = String::new();
-                let mut tag = String::from("IPv4");
-                let mut app_tag: Option<String> = None;
-                if proto == ipm::PROTO::TCP && bytes.len() >= l4_start + 20 {
-                    let sp = u16::from_be_bytes([bytes[l4_start], bytes[l4_start + 1]]);
-                    let dp = u16::from_be_bytes([bytes[l4_start + 2], bytes[l4_start + 3]]);
-                    let seq = u32::from_be_bytes([bytes[l4_start + 4], bytes[l4_start + 5], bytes[l4_start + 6], bytes[l4_start + 7]]);
-                    let ack = u32::from_be_bytes([bytes[l4_start + 8], bytes[l4_start + 9], bytes[l4_start + 10], bytes[l4_start + 11]]);
-                    let data_offset = (bytes[l4_start + 12] >> 4) as usize * 4;
-                    let flags = bytes[l4_start + 13];
-                    let window = u16::from_be_bytes([bytes[l4_start + 14], bytes[l4_start + 15]]);
-                    let checksum = u16::from_be_bytes([bytes[l4_start + 16], bytes[l4_start + 17]]);
-                    let urgent = u16::from_be_bytes([bytes[l4_start + 18], bytes[l4_start + 19]]);
-                    l4 = L4 {
-                        proto: Some("TCP".into()),
-                        src_port: Some(sp),
-                        dst_port: Some(dp),
-                        tcp_flags: Some(tcp_flags(flags)),
-                        tcp_seq: Some(seq),
-                        tcp_ack: Some(ack),
-                        tcp_window: Some(window),
-                        tcp_checksum: Some(checksum),
-                        tcp_urgent: Some(urgent),
-                        tcp_data_offset: Some((data_offset / 4) as u8),
-                        ..Default::default()
-                    };
-                    summary = format!("TCP {} → {}", sp, dp);
-                    tag = "TCP".into();
-                    // heuristics examples (HTTP/1 start-line, TLS record)
-                    let payload = if bytes.len() >= l4_start + data_offset { &bytes[l4_start + data_offset..] } else { &[] };
-                    if let Some(sni) = sniff_tls_client_hello(payload) {
-                        summary = if let Some(sni) = sni { format!("TLS ClientHello SNI={}", sni) } else { "TLS".into() };
-                        tag = "TLS".into();
-                        app_tag = Some("TLS".into());
-                    } else if let Some(line) = sniff_http1_first_line(payload) {
-                        summary = line;
-                        tag = "HTTP".into();
-                        app_tag = Some("HTTP".into());
-                    } else {
-                        app_tag = tcp_app_tag(sp, dp, payload);
-                    }
-                } else if proto == ipm::PROTO::UDP && bytes.len() >= l4_start + 8 {
-                    let sp = u16::from_be_bytes([bytes[l4_start], bytes[l4_start + 1]]);
-                    let dp = u16::from_be_bytes([bytes[l4_start + 2], bytes[l4_start + 3]]);
-                    let len = u16::from_be_bytes([bytes[l4_start + 4], bytes[l4_start + 5]]);
-                    let checksum = u16::from_be_bytes([bytes[l4_start + 6], bytes[l4_start + 7]]);
-                    l4 = L4 {
-                        proto: Some("UDP".into()),
-                        src_port: Some(sp),
-                        dst_port: Some(dp),
-                        udp_len: Some(len),
-                        udp_checksum: Some(checksum),
-                        ..Default::default()
-                    };
-                    summary = format!("UDP {} → {}", sp, dp);
-                    tag = "UDP".into();
-                    app_tag = udp_app_tag(sp, dp);
-                } else if proto == ipm::PROTO::ICMP && bytes.len() >= l4_start + 8 {
-                    let icmp_type = bytes[l4_start];
-                    let icmp_code = bytes[l4_start + 1];
-                    let checksum = u16::from_be_bytes([bytes[l4_start + 2], bytes[l4_start + 3]]);
-                    l4 = L4 {
-                        proto: Some("ICMP".into()),
-                        icmp_type: Some(icmp_type),
-                        icmp_code: Some(icmp_code),
-                        icmp_checksum: Some(checksum),
-                        ..Default::default()
-                    };
-                    summary = format!("ICMP type {} code {}", icmp_type, icmp_code);
-                    tag = "ICMP".into();
-                }
-                let description = build_description(bytes, &l2, &l3, &l4);
-                let out = Decoded { l2: Some(l2), l3: Some(l3), l4: if l4.proto.is_some() { Some(l4) } else { None }, summary, protocol_tag: tag, app_tag, description: Some(description) };
-                return serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()));
-            }
-        }
-        // IPv6
-        if ether_type == ethernet::PROTO::IPV6 && bytes.len() >= off + 40 {
-            // IPv6 fixed header
-            let ver_tc_fl = u32::from_be_bytes([bytes[off], bytes[off+1], bytes[off+2], bytes[off+3]]);
-            let version = ((ver_tc_fl & 0xF0000000) >> 28) as u8;
-            let traffic_class = ((ver_tc_fl & 0x0FF00000) >> 20) as u8;
-            let flow_label = ver_tc_fl & 0x000FFFFF;
-            let payload_len = u16::from_be_bytes([bytes[off+4], bytes[off+5]]);
-            let next_header = bytes[off+6];
-            let hop_limit = bytes[off+7];
-            let src = ipv6_to_str(&bytes[off+8..off+24]);
-            let dst = ipv6_to_str(&bytes[off+24..off+40]);
-            let l3 = L3 {
-                proto: Some("IPv6".into()),
-                src: Some(src),
-                dst: Some(dst),
-                traffic_class: Some(traffic_class),
-                flow_label: Some(flow_label),
-                payload_len: Some(payload_len),
-                next_header: Some(next_header),
-                hop_limit: Some(hop_limit),
-                version: Some(version),
-                ..Default::default()
-            };
-            let l4_start = off + 40; // ignoring extension headers for now
-            let mut l4 = L4::default();
-            let mut summary = String::from("IPv6");
-            let mut tag = String::from("IPv6");
-            let mut app_tag: Option<String> = None;
-            if next_header == ipm::PROTO::TCP && bytes.len() >= l4_start + 20 {
-                let sp = u16::from_be_bytes([bytes[l4_start], bytes[l4_start + 1]]);
-                let dp = u16::from_be_bytes([bytes[l4_start + 2], bytes[l4_start + 3]]);
-                let seq = u32::from_be_bytes([bytes[l4_start + 4], bytes[l4_start + 5], bytes[l4_start + 6], bytes[l4_start + 7]]);
-                let ack = u32::from_be_bytes([bytes[l4_start + 8], bytes[l4_start + 9], bytes[l4_start + 10], bytes[l4_start + 11]]);
-                let data_offset = (bytes[l4_start + 12] >> 4) as usize * 4;
-                let flags = bytes[l4_start + 13];
-                let window = u16::from_be_bytes([bytes[l4_start + 14], bytes[l4_start + 15]]);
-                let checksum = u16::from_be_bytes([bytes[l4_start + 16], bytes[l4_start + 17]]);
-                let urgent = u16::from_be_bytes([bytes[l4_start + 18], bytes[l4_start + 19]]);
-                l4 = L4 {
-                    proto: Some("TCP".into()),
-                    src_port: Some(sp),
-                    dst_port: Some(dp),
-                    tcp_flags: Some(tcp_flags(flags)),
-                    tcp_seq: Some(seq),
-                    tcp_ack: Some(ack),
-                    tcp_window: Some(window),
-                    tcp_checksum: Some(checksum),
-                    tcp_urgent: Some(urgent),
-                    tcp_data_offset: Some((data_offset / 4) as u8),
-                    ..Default::default()
-                };
-                summary = format!("TCP {} → {}", sp, dp);
-                tag = "TCP".into();
-                let payload = if bytes.len() >= l4_start + data_offset { &bytes[l4_start + data_offset..] } else { &[] };
-                if let Some(sni) = sniff_tls_client_hello(payload) {
-                    summary = if let Some(sni) = sni { format!("TLS ClientHello SNI={}", sni) } else { "TLS".into() };
-                    tag = "TLS".into();
-                    app_tag = Some("TLS".into());
-                } else if let Some(line) = sniff_http1_first_line(payload) {
-                    summary = line;
-                    tag = "HTTP".into();
-                    app_tag = Some("HTTP".into());
-                } else {
-                    app_tag = tcp_app_tag(sp, dp, payload);
-                }
-            } else if next_header == ipm::PROTO::UDP && bytes.len() >= l4_start + 8 {
-                let sp = u16::from_be_bytes([bytes[l4_start], bytes[l4_start + 1]]);
-                let dp = u16::from_be_bytes([bytes[l4_start + 2], bytes[l4_start + 3]]);
-                let len = u16::from_be_bytes([bytes[l4_start + 4], bytes[l4_start + 5]]);
-                let checksum = u16::from_be_bytes([bytes[l4_start + 6], bytes[l4_start + 7]]);
-                l4 = L4 {
-                    proto: Some("UDP".into()),
-                    src_port: Some(sp),
-                    dst_port: Some(dp),
-                    udp_len: Some(len),
-                    udp_checksum: Some(checksum),
-                    ..Default::default()
-                };
-                summary = format!("UDP {} → {}", sp, dp);
-                tag = "UDP".into();
-                app_tag = udp_app_tag(sp, dp);
-            } else if next_header == ipm::PROTO::IPV6ICMP && bytes.len() >= l4_start + 4 {
-                let icmp_type = bytes[l4_start];
-                let icmp_code = bytes[l4_start + 1];
-                let checksum = u16::from_be_bytes([bytes[l4_start + 2], bytes[l4_start + 3]]);
-                l4 = L4 {
-                    proto: Some("ICMPv6".into()),
-                    icmp_type: Some(icmp_type),
-                    icmp_code: Some(icmp_code),
-                    icmp_checksum: Some(checksum),
-                    ..Default::default()
-                };
-                summary = format!("ICMPv6 type {} code {}", icmp_type, icmp_code);
-                tag = "ICMPv6".into();
-            }
-            let description = build_description(bytes, &l2, &l3, &l4);
-            let out = Decoded { l2: Some(l2), l3: Some(l3), l4: if l4.proto.is_some() { Some(l4) } else { None }, summary, protocol_tag: tag, app_tag, description: Some(description) };
-            return serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()));
-        }
-        if ether_type == ethernet::PROTO::LLDP {
-            let out = Decoded { l2: Some(l2), summary: "LLDP".into(), protocol_tag: "LLDP".into(), ..Default::default() };
-            return serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()));
-        }
-        if ether_type == ethernet::PROTO::MPLS_U || ether_type == ethernet::PROTO::MPLS_M {
-            let out = Decoded { l2: Some(l2), summary: "MPLS".into(), protocol_tag: "MPLS".into(), ..Default::default() };
-            return serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()));
-        }
-        if ether_type == ethernet::PROTO::PPPOE_DISC || ether_type == ethernet::PROTO::PPPOE_SESS {
-            let out = Decoded { l2: Some(l2), summary: "PPPoE".into(), protocol_tag: "PPPoE".into(), ..Default::default() };
-            return serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()));
-        }
-        if ether_type == ethernet::PROTO::ARP {
-            let out = Decoded { l2: Some(l2), summary: "ARP".into(), protocol_tag: "ARP".into(), ..Default::default() };
-            return serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()));
+/// Decode an Ethernet frame with the native decoders and summarize its tree
+pub fn decode(bytes: &[u8]) -> Decoded {
+    context::begin_packet();
+    let layers = tree::dissect(bytes, ethernet::decode);
+    let expert = expert::drain();
+    // events are for the CLI and its logs
+    event::drain();
+    summarize(bytes, &flow::summarize(1, bytes), layers, expert)
+}
+
+/// Summary of the tree of a frame just decoded, read while its context is current
+fn summarize(bytes: &[u8], summary: &Summary, layers: Vec<Layer>, expert: Vec<ExpertInfo>) -> Decoded {
+    let l2 = layer(&layers, "ethernet").map(|_| L2 {
+        src_mac: value(&layers, "ethernet", "Source").map(String::from),
+        dst_mac: value(&layers, "ethernet", "Destination").map(String::from),
+        ether_type: summary.ether_type.map(u16::from),
+        ether_type_name: summary.ether_type.map(|t| t.to_string()),
+        vlan: value(&layers, "vlan", "VLAN ID").and_then(|v| v.parse().ok()),
+    });
+    // the innermost network layer, whose addresses are the ones of the context
+    let network = layers.iter().rev().find(|l| matches!(l.name, "ipv4" | "ipv6" | "arp"));
+    let l3 = network.map(|l| {
+        let addrs = context::addrs().filter(|_| l.name != "arp");
+        L3 {
+            proto: Some(tag(l.name)),
+            src: match addrs {
+                Some((src, _)) => Some(src.to_string()),
+                None => value(&layers, "arp", "Sender protocol address").map(String::from),
+            },
+            dst: match addrs {
+                Some((_, dst)) => Some(dst.to_string()),
+                None => value(&layers, "arp", "Target protocol address").map(String::from),
+            },
+            src_geo: addrs.map(|(src, _)| geoip::enrich(src)),
+            dst_geo: addrs.map(|(_, dst)| geoip::enrich(dst)),
         }
-        // Fallback
-        let out = Decoded { l2: Some(l2), summary: format!("Ethertype 0x{:04x}", ether_type), protocol_tag: "ETH".into(), ..Default::default() };
-        return serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()));
+    });
+    let l4 = match context::flow() {
+        Some(f) => Some(L4 {
+            proto: Some(f.proto.to_string()),
+            src_port: Some(f.src_port),
+            dst_port: Some(f.dst_port),
+            tcp_flags: summary.tcp_flags.map(tcp::flags_to_str),
+        }),
+        None => layers
+            .iter()
+            .find(|l| matches!(l.name, "icmpv4" | "icmpv6"))
+            .map(|l| L4 { proto: Some(tag(l.name)), ..Default::default() }),
+    };
+
+    // the layer above the transport, as decoded: a segment the decoders did not recognize
+    // (a pure ACK, the middle of a stream) has none rather than a guess from its ports
+    let transport = layers.iter().position(|l| matches!(l.name, "tcp" | "udp"));
+    let app_tag = transport.and_then(|i| layers.get(i + 1)).map(|l| tag(l.name));
+    let (summary, protocol_tag) = match layers.last() {
+        Some(l) => (l.summary.clone(), tag(l.name)),
+        None => (format!("Truncated frame ({}B)", bytes.len()), "FRAME".into()),
+    };
+    Decoded {
+        l2,
+        l3,
+        l4,
+        summary,
+        protocol_tag,
+        app_tag,
+        description: Some(describe(bytes, &layers)),
+        expert,
+        layers,
     }
-
-    let out = Decoded { summary: "Frame".into(), protocol_tag: "ETH".into(), ..Default::default() };
-    serde_wasm_bindgen::to_value(&out).map_err(|e| JsValue::from_str(&e.to_string()))
 }
 
 #[wasm_bindgen]
@@ -383,222 +153,48 @@ pub fn decode_and_log(bytes: &[u8]) -> Result<(), JsValue> {
     Ok(())
 }
 
-fn build_description(bytes: &[u8], l2: &L2, l3: &L3, l4: &L4) -> String {
-    let mut lines = Vec::new();
-    
-    // Frame info
-    let bits = bytes.len() * 8;
-    lines.push(format!("Frame: Packet, {} bytes on wire ({} bits), {} bytes captured ({} bits)",
-        bytes.len(), bits, bytes.len(), bits));
-    
-    // Ethernet II
-    if let (Some(src_mac), Some(dst_mac)) = (&l2.src_mac, &l2.dst_mac) {
-        let eth_line = if let Some(vlan) = l2.vlan {
-            format!("Ethernet II, VLAN: {}", vlan)
-        } else {
-            "Ethernet II".to_string()
-        };
-        lines.push(eth_line);
-        lines.push(format!("    Source: {}", src_mac));
-        lines.push(format!("    Destination: {}", dst_mac));
-    }
-    
-    // Layer 3
-    if let Some(proto_name) = &l3.proto {
-        match proto_name.as_str() {
-            "IPv4" => {
-                if let (Some(src), Some(dst)) = (&l3.src, &l3.dst) {
-                    lines.push(format!("Internet Protocol Version {}, Src: {}, Dst: {}",
-                        l3.version.unwrap_or(4), src, dst));
-                }
-            },
-            "IPv6" => {
-                if let (Some(src), Some(dst)) = (&l3.src, &l3.dst) {
-                    lines.push(format!("Internet Protocol Version 6, Src: {}, Dst: {}",
-                        src, dst));
-                }
-            },
-            _ => {}
-        }
-    }
-    
-    // Layer 4
-    if let Some(proto) = &l4.proto {
-        match proto.as_str() {
-            "TCP" => {
-                if let (Some(sp), Some(dp)) = (l4.src_port, l4.dst_port) {
-                    lines.push(format!("Transmission Control Protocol, Src Port: {}, Dst Port: {}",
-                        sp, dp));
-                }
-            },
-            "UDP" => {
-                if let (Some(sp), Some(dp)) = (l4.src_port, l4.dst_port) {
-                    lines.push(format!("User Datagram Protocol, Src Port: {}, Dst Port: {}",
-                        sp, dp));
-                }
-            },
-            "ICMP" => {
-                if let (Some(t), Some(c)) = (l4.icmp_type, l4.icmp_code) {
-                    lines.push(format!("Internet Control Message Protocol, Type: {}, Code: {}",
-                        t, c));
-                }
-            },
-            "ICMPv6" => {
-                if let (Some(t), Some(c)) = (l4.icmp_type, l4.icmp_code) {
-                    lines.push(format!("Internet Control Message Protocol v6, Type: {}, Code: {}",
-                        t, c));
-                }
-            },
-            _ => {}
-        }
-    }
-    
-    // Check for DNS (port 53)
-    if let (Some(sp), Some(dp)) = (l4.src_port, l4.dst_port) {
-        if sp == 53 || dp == 53 {
-            lines.push("Domain Name System (query)".to_string());
-        }
-    }
-    
-    lines.join("\n")
-}
-
-fn tcp_flags(b: u8) -> String {
-    let mut v = Vec::new();
-    if b & 0x01 != 0 { v.push("FIN"); }
-    if b & 0x02 != 0 { v.push("SYN"); }
-    if b & 0x04 != 0 { v.push("RST"); }
-    if b & 0x08 != 0 { v.push("PSH"); }
-    if b & 0x10 != 0 { v.push("ACK"); }
-    if b & 0x20 != 0 { v.push("URG"); }
-    if b & 0x40 != 0 { v.push("ECE"); }
-    if b & 0x80 != 0 { v.push("CWR"); }
-    if v.is_empty() { "NONE".into() } else { v.join(",") }
+fn layer<'a>(layers: &'a [Layer], name: &str) -> Option<&'a Layer> {
+    layers.iter().find(|l| l.name == name)
 }
 
-fn sniff_http1_first_line(payload: &[u8]) -> Option<String> {
-    if payload.len() < 5 { return None; }
-    let max = payload.len().min(256);
-    let s = payload[0..max].iter().map(|&b| b as char).collect::<String>();
-    let line = s.split(|c| c == '\n' || c == '\r').next().unwrap_or("").trim().to_string();
-    if line.is_empty() { return None; }
-    const METHODS: [&str; 8] = ["GET","POST","PUT","DELETE","HEAD","OPTIONS","TRACE","PATCH"];
-    if METHODS.iter().any(|m| line.starts_with(&format!("{} ", m))) { return Some(format!("HTTP {}", line)); }
-    if line.starts_with("HTTP/") { return Some(line); }
-    None
+/// Value of a field of the first layer with this name
+fn value<'a>(layers: &'a [Layer], name: &str, field: &str) -> Option<&'a str> {
+    layer(layers, name)?
+        .fields
+        .iter()
+        .find(|f| f.name == field)
+        .map(|f| f.value.as_str())
 }
 
-fn sniff_tls_client_hello(payload: &[u8]) -> Option<Option<String>> {
-    if payload.len() < 5 { return None; }
-    if payload[0] != 0x16 || payload[1] != 0x03 { return None; }
-    let len = ((payload[3] as usize) << 8) | payload[4] as usize;
-    if payload.len() < 5 + len { return None; }
-    if payload.len() < 9 { return None; }
-    if payload[5] != 0x01 { return None; } // ClientHello
-    let mut p = 9; // after hs header (type+len3)
-    if payload.len() < p + 2 + 32 + 1 { return Some(None); }
-    p += 2 + 32; // version + random
-    let sid_len = payload[p] as usize; p += 1 + sid_len;
-    if payload.len() < p + 2 { return Some(None); }
-    let cs_len = ((payload[p] as usize) << 8) | payload[p+1] as usize; p += 2 + cs_len;
-    if payload.len() < p + 1 { return Some(None); }
-    let comp_len = payload[p] as usize; p += 1 + comp_len;
-    if payload.len() < p + 2 { return Some(None); }
-    let ext_len = ((payload[p] as usize) << 8) | payload[p+1] as usize; p += 2;
-    let end = p + ext_len;
-    while p + 4 <= payload.len().min(end) {
-        let et = ((payload[p] as usize) << 8) | payload[p+1] as usize;
-        let el = ((payload[p+2] as usize) << 8) | payload[p+3] as usize; p += 4;
-        if p + el > end { break; }
-        if et == 0x0000 && el >= 5 { // server_name
-            let mut q = p + 2; // list len
-            while q + 3 <= p + el {
-                let name_type = payload[q]; q += 1;
-                let nl = ((payload[q] as usize) << 8) | payload[q+1] as usize; q += 2;
-                if name_type == 0 && q + nl <= p + el {
-                    let sni = std::str::from_utf8(&payload[q..q+nl]).ok().map(|s| s.to_string());
-                    return Some(sni);
-                }
-                q += nl;
-            }
-        }
-        p += el;
+/// Tag of a layer in the packet list, with the protocol names the dashboard uses
+fn tag(name: &str) -> String {
+    match name {
+        "ethernet" => "ETH".into(),
+        "ipv4" => "IPv4".into(),
+        "ipv6" => "IPv6".into(),
+        "icmpv4" => "ICMP".into(),
+        "icmpv6" => "ICMPv6".into(),
+        "pppoe" => "PPPoE".into(),
+        name => name.to_uppercase(),
     }
-    Some(None)
 }
 
-fn udp_app_tag(sp: u16, dp: u16) -> Option<String> {
-    let a = sp; let b = dp;
-    let port = |p| a == p || b == p;
-    if port(53) { return Some("DNS".into()); }
-    if port(5353) { return Some("mDNS".into()); }
-    if port(67) || port(68) { return Some("DHCP".into()); }
-    if port(123) { return Some("NTP".into()); }
-    if port(161) || port(162) { return Some("SNMP".into()); }
-    if port(514) { return Some("Syslog".into()); }
-    if port(69) { return Some("TFTP".into()); }
-    if port(137) || port(138) { return Some("NetBIOS".into()); }
-    if port(546) || port(547) { return Some("DHCPv6".into()); }
-    if port(520) { return Some("RIP".into()); }
-    if port(5060) { return Some("SIP".into()); }
-    if port(443) { return Some("QUIC".into()); }
-    if port(5683) { return Some("CoAP".into()); }
-    if port(1900) { return Some("SSDP".into()); }
-    if port(5355) { return Some("LLMNR".into()); }
-    if port(88) { return Some("Kerberos".into()); }
-    if port(3478) || port(5349) { return Some("STUN/TURN".into()); }
-    None
-}
-
-fn tcp_app_tag(sp: u16, dp: u16, payload: &[u8]) -> Option<String> {
-    let a = sp; let b = dp;
-    let port = |p| a == p || b == p;
-    if port(179) { return Some("BGP".into()); }
-    if port(22) { return Some("SSH".into()); }
-    if port(23) { return Some("Telnet".into()); }
-    if port(21) { return Some("FTP".into()); }
-    if port(990) {
-        if sniff_tls_client_hello(payload).is_some() { return Some("FTPS".into()); }
-        return Some("FTP".into());
-    }
-    if port(25) || port(587) { return Some("SMTP".into()); }
-    if port(465) { return Some("SMTPS".into()); }
-    if port(110) { return Some("POP3".into()); }
-    if port(143) { return Some("IMAP".into()); }
-    if port(993) {
-        if sniff_tls_client_hello(payload).is_some() { return Some("IMAPS".into()); }
-        return Some("IMAP".into());
-    }
-    if port(389) { return Some("LDAP".into()); }
-    if port(636) { return Some("LDAPS".into()); }
-    if port(853) { return Some("DoT".into()); }
-    if port(3389) { return Some("RDP".into()); }
-    if port(139) || port(445) { return Some("SMB".into()); }
-    if port(554) { return Some("RTSP".into()); }
-    if port(5060) { return Some("SIP".into()); }
-    if port(443) || port(8443) {
-        if sniff_tls_client_hello(payload).is_some() { return Some("TLS/HTTPS".into()); }
-        return Some("HTTP".into());
-    }
-    if port(80) || port(8080) || port(8000) { return Some("HTTP".into()); }
-    if port(1883) { return Some("MQTT".into()); }
-    if port(8883) {
-        if sniff_tls_client_hello(payload).is_some() { return Some("MQTTS".into()); }
-        return Some("MQTT".into());
-    }
-    if port(5672) { return Some("AMQP".into()); }
-    if port(5671) {
-        if sniff_tls_client_hello(payload).is_some() { return Some("AMQPS".into()); }
-        return Some("AMQP".into());
+/// Text of the tree, as Wireshark's detail pane
+fn describe(bytes: &[u8], layers: &[Layer]) -> String {
+    let bits = bytes.len() * 8;
+    let mut lines = vec![format!(
+        "Frame: Packet, {} bytes on wire ({} bits), {} bytes captured ({} bits)",
+        bytes.len(), bits, bytes.len(), bits
+    )];
+    fn add(lines: &mut Vec<String>, fields: &[Field], depth: usize) {
+        for f in fields {
+            lines.push(format!("{:width$}{}: {}", "", f.name, f.value, width = depth * 4));
+            add(lines, &f.children, depth + 1);
+        }
     }
-    if port(61613) || port(61614) { return Some("STOMP".into()); }
-    if port(6379) { return Some("Redis".into()); }
-    if port(11211) { return Some("Memcached".into()); }
-    if port(3478) { return Some("STUN".into()); }
-    if port(5349) {
-        if sniff_tls_client_hello(payload).is_some() { return Some("STUN/TURN over TLS".into()); }
-        return Some("STUN/TURN".into());
+    for l in layers {
+        lines.push(l.summary.clone());
+        add(&mut lines, &l.fields, 1);
     }
-    if port(5355) { return Some("LLMNR".into()); }
-    None
+    lines.join("\n")
 }
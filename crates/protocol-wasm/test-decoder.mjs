@@ -66,6 +66,7 @@ try {
     console.log('\n=== DECODED FIELDS ===');
     console.log('Summary:', result.summary);
     console.log('Protocol Tag:', result.protocolTag);
+    console.log('App Tag:', result.appTag);
     
     if (result.l2) {
         console.log('\nLayer 2 (Ethernet):');
@@ -79,10 +80,6 @@ try {
         console.log('  Protocol:', result.l3.proto);
         console.log('  Source IP:', result.l3.src);
         console.log('  Dest IP:', result.l3.dst);
-        console.log('  Version:', result.l3.version);
-        console.log('  TTL:', result.l3.ttl);
-        console.log('  Total Length:', result.l3.totalLen);
-        console.log('  Protocol Number:', result.l3.protocol);
     }
     
     if (result.l4) {
@@ -90,9 +87,13 @@ try {
         console.log('  Protocol:', result.l4.proto);
         console.log('  Source Port:', result.l4.srcPort);
         console.log('  Dest Port:', result.l4.dstPort);
-        if (result.l4.udpLen) {
-            console.log('  UDP Length:', result.l4.udpLen);
-            console.log('  UDP Checksum:', `0x${result.l4.udpChecksum?.toString(16).padStart(4, '0')}`);
+    }
+
+    console.log('\nProtocol tree:');
+    for (const layer of result.layers) {
+        console.log(`  ${layer.summary}`);
+        for (const f of layer.fields) {
+            console.log(`    ${f.name}: ${f.value}`);
         }
     }
     
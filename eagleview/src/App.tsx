@@ -1,4 +1,4 @@
-import { useCallback, useEffect, useMemo, useRef, useState } from 'react'
+import { useCallback, useEffect, useMemo, useRef, useState, type ReactNode } from 'react'
 import './App.css'
 
 import { parseCapture } from './lib/parsers'
@@ -1222,6 +1222,27 @@ function App() {
                                         <span>{k}</span>: <span>{String(v)}</span>
                                       </div>
                                     ))
+                                // protocol tree of the Rust decoders, with the bytes of each field
+                                const renderFields = (fields: import('./lib/decoders').Field[], depth: number): ReactNode[] =>
+                                  fields.flatMap((f, i) => [
+                                    <div key={`${depth}-${i}-${f.name}`} style={{ paddingLeft: depth * 12 }} title={f.offset != null ? `bytes ${f.offset}-${f.offset + f.len - 1}` : undefined}>
+                                      <span>{f.name}</span>: <span>{f.value}</span>
+                                    </div>,
+                                    ...renderFields(f.children || [], depth + 1),
+                                  ])
+                                if (dec.layers && dec.layers.length) {
+                                  return (
+                                    <div className="details" style={{ marginTop: 8 }}>
+                                      <div className="details-title">Headers</div>
+                                      {dec.layers.map((l, i) => (
+                                        <div key={i} style={{ marginTop: i ? 6 : 0 }}>
+                                          <div><strong>{l.summary}</strong></div>
+                                          <div className="mono">{renderFields(l.fields, 1)}</div>
+                                        </div>
+                                      ))}
+                                    </div>
+                                  )
+                                }
                                 return (
                                   <div className="details" style={{ marginTop: 8 }}>
                                     <div className="details-title">Headers</div>
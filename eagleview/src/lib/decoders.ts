@@ -1,5 +1,30 @@
 import type { ParsedPacket } from './parsers'
 
+export type Field = {
+  name: string
+  value: string
+  // byte span in the frame, no offset for bytes not read from it (reassembled streams)
+  offset?: number | null
+  len: number
+  children?: Field[]
+}
+
+export type Layer = {
+  name: string
+  summary: string
+  offset?: number | null
+  len: number
+  fields: Field[]
+}
+
+export type ExpertInfo = {
+  severity: 'note' | 'warning' | 'error'
+  kind: string
+  layer: string
+  offset: number
+  message: string
+}
+
 export type Decoded = {
   l2?: {
     srcMac?: string
@@ -12,48 +37,20 @@ export type Decoded = {
     proto?: 'IPv4' | 'IPv6' | 'ARP'
     src?: string
     dst?: string
-    // IPv4 specific
-    version?: number
-    headerLen?: number
-    tos?: number
-    totalLen?: number
-    identification?: number
-    flags?: number
-    fragmentOffset?: number
-    ttl?: number
-    protocol?: number
-    checksum?: number
-    // IPv6 specific
-    trafficClass?: number
-    flowLabel?: number
-    payloadLen?: number
-    nextHeader?: number
-    hopLimit?: number
   }
   l4?: {
-    proto?: 'TCP' | 'UDP' | 'ICMPv4' | 'ICMPv6'
+    proto?: string
     srcPort?: number
     dstPort?: number
-    // TCP specific
     tcpFlags?: string
-    tcpSeq?: number
-    tcpAck?: number
-    tcpWindow?: number
-    tcpChecksum?: number
-    tcpUrgent?: number
-    tcpDataOffset?: number
-    // UDP specific
-    udpLen?: number
-    udpChecksum?: number
-    // ICMP specific
-    icmpType?: number
-    icmpCode?: number
-    icmpChecksum?: number
   }
   summary: string
   protocolTag: string
   appTag?: string
   description?: string
+  // protocol tree from the Rust decoders
+  expert?: ExpertInfo[]
+  layers?: Layer[]
   meta?: {
     arp?: { op?: number; spa?: string; tpa?: string }
     dns?: { id: number; qr: boolean; name?: string; qtype?: number; qtypeName?: string }
@@ -61,9 +58,75 @@ export type Decoded = {
   }
 }
 
+// Whole-capture analysis (Analyzer of protocol-wasm)
+export type CapturePacket = {
+  index: number             // from 1
+  ts: number                // epoch seconds
+  originalLen: number
+  capturedLen: number
+  offset: number            // of the packet data in the capture buffer
+  ifIndex: number
+  flowId?: string | null    // uid of its conversation
+  decoded: Decoded
+}
+
+export type CaptureExchange = {
+  id: string
+  protocol: 'arp' | 'dns' | 'icmp' | 'http1'
+  flowId?: string | null
+  flow?: { a: string; b: string } | null
+  request?: { startTime: number; endTime: number; summary: string; packetIds: number[] } | null
+  response?: { startTime: number; endTime: number; summary: string; packetIds: number[] } | null
+  timing?: { rttMs: number } | null
+  status: 'complete' | 'partial'
+}
+
+// as `eagleeyes flows --json`
+export type Conversation = {
+  uid: string
+  proto: number             // IP protocol number
+  src: string
+  src_port: number
+  dst: string
+  dst_port: number
+  service?: string
+  first_ts: number
+  last_ts: number
+  orig_packets: number
+  orig_bytes: number
+  resp_packets: number
+  resp_bytes: number
+  tcp_flags?: number
+}
+
+export type HierarchyNode = { name: string; packets: number; bytes: number; children: HierarchyNode[] }
+
+export type CaptureStats = {
+  format?: string | null
+  packets: number
+  bytes: number
+  capturedBytes: number
+  firstTs?: number | null
+  lastTs?: number | null
+  duration: number
+  truncatedBytes: number
+  hierarchy: HierarchyNode[]
+}
+
+export type Progress = { bytes: number; total?: number | null; packets: number; done: boolean; cancelled: boolean }
+
+export type CaptureUpdate = {
+  packets: CapturePacket[]
+  exchanges: CaptureExchange[]
+  alerts: any[]             // events of the CLI, with their event_type
+  progress: Progress
+  flows?: Conversation[]    // on the last update
+  stats?: CaptureStats
+}
+
 // Use WASM decoder (protocol-wasm) when available; Vite will bundle it.
 // eslint-disable-next-line import/no-unresolved
-import { decode_packet as wasmDecode } from 'protocol-wasm'
+import { decode_packet as wasmDecode, Analyzer } from 'protocol-wasm'
 
 export function decodePacket(p: ParsedPacket): Decoded {
   if (wasmDecode) {
@@ -430,6 +493,37 @@ function qtypeToStr(t?: number): string {
   return t && m[t] ? m[t] : (t ? `TYPE${t}` : '')
 }
 
+/**
+ * Analyze a pcap/pcapng capture with the Rust decoders, a chunk at a time so the page stays
+ * responsive: `onUpdate` gets the packets, exchanges and alerts of each chunk, and the last
+ * update has the flows and stats. Aborting the signal stops the analysis; the last update
+ * then has what was found so far.
+ */
+export async function analyzeCapture(
+  buf: ArrayBuffer,
+  opts: { onUpdate?: (u: CaptureUpdate) => void; signal?: AbortSignal; chunkSize?: number; layers?: boolean } = {},
+): Promise<CaptureUpdate> {
+  const chunk = opts.chunkSize ?? 1 << 20
+  const analyzer = new Analyzer({ size: buf.byteLength, layers: !!opts.layers })
+  try {
+    for (let off = 0; off < buf.byteLength; off += chunk) {
+      if (opts.signal?.aborted) {
+        analyzer.cancel()
+        break
+      }
+      const update = analyzer.push(new Uint8Array(buf, off, Math.min(chunk, buf.byteLength - off))) as CaptureUpdate
+      opts.onUpdate?.(update)
+      if (update.progress.cancelled) break
+      await new Promise(r => setTimeout(r, 0))
+    }
+    const last = analyzer.finish() as CaptureUpdate
+    opts.onUpdate?.(last)
+    return last
+  } finally {
+    analyzer.free()
+  }
+}
+
 // Best-effort extraction of L4 payload (TCP/UDP) from an Ethernet frame
 export function extractL4Payload(u: Uint8Array): { proto: 'TCP' | 'UDP' | 'OTHER'; offset: number; length: number } | null {
   if (u.length < 14) return null
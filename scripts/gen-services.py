@@ -0,0 +1,110 @@
+#!/usr/bin/env python3
+"""Generate src/protocols/services.rs from the IANA service names and port numbers registry.
+
+Usage:
+    curl -sO https://www.iana.org/assignments/service-names-port-numbers/service-names-port-numbers.csv
+    scripts/gen-services.py service-names-port-numbers.csv > src/protocols/services.rs
+
+Without access to the registry, a services(5) file such as the /etc/services of Debian's
+netbase, which is taken from it, is read instead:
+    scripts/gen-services.py /etc/services > src/protocols/services.rs
+
+Only the well-known ports (0-1023) are kept. When several services are registered on the
+same port, the first one of the registry wins, as it is the one the port was assigned to.
+"""
+import csv
+import sys
+
+WELL_KNOWN = range(0, 1024)
+TRANSPORTS = ["tcp", "udp", "sctp", "dccp"]
+MAX_WIDTH = 100
+
+
+def rust_str(s):
+    return '"' + s.replace("\\", "\\\\").replace('"', '\\"') + '"'
+
+
+def ports(field):
+    if "-" in field:
+        low, high = field.split("-", 1)
+        return range(int(low), int(high) + 1)
+    return [int(field)]
+
+
+def registry(f):
+    """(name, port field, transport) of the rows of the IANA CSV"""
+    for row in csv.DictReader(f):
+        yield row["Service Name"], row["Port Number"], row["Transport Protocol"]
+
+
+def services(f):
+    """(name, port, transport) of the lines of a services(5) file"""
+    for line in f:
+        fields = line.split("#", 1)[0].split()
+        if len(fields) >= 2 and "/" in fields[1]:
+            port, transport = fields[1].split("/", 1)
+            yield fields[0], port, transport
+
+
+def main(path):
+    tables = {t: {} for t in TRANSPORTS}
+    with open(path, newline="", encoding="utf-8") as f:
+        is_csv = f.readline().startswith("Service Name,")
+        f.seek(0)
+        # the header of /etc/services says which revision of the registry it was taken from
+        header = [] if is_csv else [l[1:].strip() for l in f if l.startswith("#")][:3]
+        f.seek(0)
+        for name, port, transport in (registry if is_csv else services)(f):
+            name, port, transport = name.strip(), port.strip(), transport.strip().lower()
+            if not name or not port or transport not in tables:
+                continue
+            for p in ports(port):
+                if p in WELL_KNOWN:
+                    tables[transport].setdefault(p, name)
+
+    out = sys.stdout
+    if is_csv:
+        out.write("// Generated by scripts/gen-services.py from the IANA service name and transport\n")
+        out.write("// protocol port number registry. Do not edit.\n")
+        out.write("//\n")
+        out.write("// Source:\n")
+        out.write("// * https://www.iana.org/assignments/service-names-port-numbers/service-names-port-numbers.csv\n")
+    else:
+        out.write("// Generated by scripts/gen-services.py from a services(5) file taken from the IANA\n")
+        out.write("// service name and transport protocol port number registry. Do not edit.\n")
+        out.write("//\n")
+        out.write("// Source: %s, whose header reads\n" % path)
+        for l in header:
+            if l:
+                out.write("// > %s\n" % l)
+    out.write("use super::ip::IpProtocol;\n\n")
+    out.write("/// Service name registered by IANA for a well-known port (0 to 1023)\n")
+    out.write("pub fn service_name(transport: IpProtocol, port: u16) -> Option<&'static str> {\n")
+    out.write("    let table = match transport {\n")
+    for t in TRANSPORTS:
+        out.write("        IpProtocol::%s => %s,\n" % (t.capitalize(), t.upper()))
+    out.write("        _ => return None,\n")
+    out.write("    };\n")
+    out.write("    table\n")
+    out.write("        .binary_search_by_key(&port, |&(p, _)| p)\n")
+    out.write("        .ok()\n")
+    out.write("        .map(|i| table[i].1)\n")
+    out.write("}\n")
+    for t in TRANSPORTS:
+        entries = ["(%d, %s)" % (p, rust_str(name)) for p, name in sorted(tables[t].items())]
+        head = "const %s: &[(u16, &str)] = &[" % t.upper()
+        # the layout rustfmt gives, so that the output is committed as is
+        line = "\n%s%s];\n" % (head, ", ".join(entries))
+        if len(line) - 2 <= MAX_WIDTH:
+            out.write(line)
+            continue
+        out.write("\n%s\n" % head)
+        for e in entries:
+            out.write("    %s,\n" % e)
+        out.write("];\n")
+
+
+if __name__ == "__main__":
+    if len(sys.argv) != 2:
+        sys.exit("usage: gen-services.py service-names-port-numbers.csv|/etc/services")
+    main(sys.argv[1])
@@ -2,6 +2,6 @@
 set -euo pipefail
 
 # Prefer generating a tiny sample pcap locally to avoid flaky external URLs
-cargo run --quiet --bin gen_sample
+cargo run --quiet -- gen
 
 echo "Sample written to samples/http.cap"
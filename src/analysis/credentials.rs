@@ -0,0 +1,200 @@
+//! Credentials sent in the clear
+//!
+//! Decoders report the logins they see going through unencrypted protocols: FTP USER/PASS,
+//! POP3 and IMAP logins and SASL PLAIN/LOGIN exchanges, telnet logins typed after a prompt,
+//! HTTP Basic authorization, LDAP simple binds, SNMP v1/v2c communities and PPP PAP
+//! requests. Each one is reported once per client, server and username as a `Credential`
+//! event, so legacy systems still accepting cleartext passwords stand out.
+//!
+//! Secrets are masked unless `set_reveal(true)` was called, decoders printing a secret go
+//! through `mask` as well.
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use crate::event::{self, Event};
+use crate::flow::FlowKey;
+use crate::protocols::mime;
+
+/// Credentials remembered per thread to report each one once
+const MAX_SEEN: usize = 65536;
+/// Replaces a secret when it is masked, whatever its length
+const MASK: &str = "********";
+
+/// A login seen in the clear
+#[derive(Debug, Clone, Serialize)]
+pub struct Credential {
+    /// "ftp", "pop3", "imap", "telnet", "http", "ldap", "snmp" or "ppp"
+    pub protocol: &'static str,
+    /// how the secret was sent ("USER/PASS", "LOGIN", "SASL PLAIN", "Basic", ...)
+    pub mechanism: &'static str,
+    /// flow oriented from the client to the server, when known
+    pub flow: Option<FlowKey>,
+    pub client: Option<IpAddr>,
+    /// server address and port
+    pub server: Option<String>,
+    /// none for secrets alone (SNMP community)
+    pub username: Option<String>,
+    pub secret: String,
+    /// the secret was replaced by a mask
+    pub masked: bool,
+}
+
+/// protocol, client, server, username and secret of a reported credential
+type Seen = (&'static str, Option<IpAddr>, Option<String>, String, String);
+
+thread_local! {
+    static REVEAL: Cell<bool> = const { Cell::new(false) };
+    static SEEN: RefCell<HashSet<Seen>> = RefCell::new(HashSet::new());
+}
+
+/// Report secrets in the clear instead of masking them
+pub fn set_reveal(reveal: bool) {
+    REVEAL.with(|r| r.set(reveal));
+}
+
+/// The secret as it may be printed: masked unless revealing
+pub fn mask(secret: &str) -> &str {
+    if REVEAL.with(Cell::get) {
+        secret
+    } else {
+        MASK
+    }
+}
+
+fn server_endpoint(flow: &FlowKey) -> String {
+    match flow.dst {
+        IpAddr::V4(a) => format!("{}:{}", a, flow.dst_port),
+        IpAddr::V6(a) => format!("[{}]:{}", a, flow.dst_port),
+    }
+}
+
+/// Report a credential, `flow` going from the client to the server
+pub fn on_credential(
+    protocol: &'static str,
+    mechanism: &'static str,
+    flow: Option<&FlowKey>,
+    username: Option<&str>,
+    secret: &str,
+) {
+    let client = flow.map(|f| f.src);
+    let server = flow.map(server_endpoint);
+    let key = (
+        protocol,
+        client,
+        server.clone(),
+        username.unwrap_or_default().to_string(),
+        secret.to_string(),
+    );
+    let new = SEEN.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.len() >= MAX_SEEN {
+            s.clear();
+        }
+        s.insert(key)
+    });
+    if !new {
+        return;
+    }
+    let masked = !REVEAL.with(Cell::get);
+    let secret = mask(secret).to_string();
+    println!(
+        "Cleartext credential {} {} {} user={} secret={}",
+        protocol,
+        mechanism,
+        flow.map_or("-".to_string(), |f| f.to_string()),
+        username.unwrap_or("-"),
+        secret
+    );
+    event::emit(Event::Credential(Credential {
+        protocol,
+        mechanism,
+        flow: flow.copied(),
+        client,
+        server,
+        username: username.map(str::to_string),
+        secret,
+        masked,
+    }));
+}
+
+/// `user:password` of an HTTP Basic authorization
+pub fn basic_auth(value: &str) -> Option<(String, String)> {
+    let (scheme, token) = value.trim().split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+    let decoded = mime::base64_decode(token.trim().as_bytes());
+    let decoded = String::from_utf8_lossy(&decoded);
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_string(), password.to_string()))
+}
+
+/// SASL exchange of a line based protocol (POP3 AUTH, IMAP AUTHENTICATE)
+///
+/// Only PLAIN and LOGIN carry the password, other mechanisms are left alone.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum Sasl {
+    #[default]
+    Idle,
+    /// waiting for the PLAIN response
+    Plain,
+    /// waiting for the LOGIN username
+    LoginUser,
+    /// waiting for the LOGIN password
+    LoginPassword(String),
+}
+
+impl Sasl {
+    /// An authentication starts, with its initial response if any
+    pub fn start(
+        &mut self,
+        protocol: &'static str,
+        flow: &FlowKey,
+        mechanism: &str,
+        initial: Option<&str>,
+    ) {
+        *self = match mechanism.to_ascii_uppercase().as_str() {
+            "PLAIN" => Sasl::Plain,
+            "LOGIN" => Sasl::LoginUser,
+            _ => Sasl::Idle,
+        };
+        // "=" is an empty initial response
+        if let Some(initial) = initial.filter(|i| *i != "=") {
+            self.on_line(protocol, flow, initial);
+        }
+    }
+
+    /// Whether the client line was a response of the exchange
+    pub fn on_line(&mut self, protocol: &'static str, flow: &FlowKey, line: &str) -> bool {
+        if *self == Sasl::Idle {
+            return false;
+        }
+        let line = line.trim();
+        // "*" aborts the exchange
+        if line == "*" {
+            *self = Sasl::Idle;
+            return true;
+        }
+        let decoded = mime::base64_decode(line.as_bytes());
+        let decoded = String::from_utf8_lossy(&decoded).into_owned();
+        *self = match std::mem::take(self) {
+            Sasl::Plain => {
+                // authorization identity, authentication identity, password
+                let fields: Vec<&str> = decoded.split('\0').collect();
+                if let [_, user, password] = fields[..] {
+                    on_credential(protocol, "SASL PLAIN", Some(flow), Some(user), password);
+                }
+                Sasl::Idle
+            }
+            Sasl::LoginUser => Sasl::LoginPassword(decoded),
+            Sasl::LoginPassword(user) => {
+                on_credential(protocol, "SASL LOGIN", Some(flow), Some(&user), &decoded);
+                Sasl::Idle
+            }
+            Sasl::Idle => Sasl::Idle,
+        };
+        true
+    }
+}
@@ -0,0 +1,314 @@
+//! Extraction of transferred files
+//!
+//! Decoders hand over the objects they see going through a connection (HTTP bodies, SMB2
+//! reads and writes, FTP data connections, TFTP transfers, SMTP attachments). When an
+//! output directory is set they are written there, named after their SHA-256 and
+//! filename, and described by a line of `files.jsonl` and a `File` event: source flow, protocol,
+//! filename, MIME type, size and hashes.
+//!
+//! Objects are also collected without an output directory while indicators of compromise
+//! include file hashes: they are then only hashed and checked against them.
+//!
+//! Extraction is off by default, decoders check `enabled()` before buffering anything. What
+//! they buffer goes in a `Buffer`, counted against a budget shared by the whole thread so
+//! that many large transfers at once cannot exhaust the memory.
+use md5::Md5;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use crate::analysis::ioc;
+use crate::event::{self, Event};
+use crate::expert;
+use crate::flow::FlowKey;
+
+/// Largest object kept in memory by a decoder, longer ones are truncated
+pub const MAX_FILE: usize = 16 * 1024 * 1024;
+/// Bytes kept by all the buffers of a thread together
+pub const MAX_BUFFERED: usize = 256 * 1024 * 1024;
+
+/// Metadata of an extracted object
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedFile {
+    /// flow oriented from the client to the server
+    pub flow: FlowKey,
+    /// "http", "smb2", "ftp", "tftp" or "smtp"
+    pub protocol: &'static str,
+    pub filename: Option<String>,
+    /// type sniffed from the content, the declared one when unknown
+    pub mime: String,
+    /// type announced by the protocol (Content-Type, ...)
+    pub declared_mime: Option<String>,
+    pub size: u64,
+    /// only the first MAX_FILE bytes (or the bytes seen) were written
+    pub truncated: bool,
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+    /// where the content was written
+    pub path: Option<PathBuf>,
+}
+
+thread_local! {
+    static OUTPUT: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    static BUFFERED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Directory the objects are written to, `None` to stop extracting
+pub fn set_output(dir: Option<PathBuf>) {
+    OUTPUT.with(|o| *o.borrow_mut() = dir);
+}
+
+/// Whether decoders should collect objects
+pub fn enabled() -> bool {
+    OUTPUT.with(|o| o.borrow().is_some()) || ioc::file_hashes()
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// MIME type from the first bytes of a content
+pub fn sniff_mime(data: &[u8]) -> Option<&'static str> {
+    const MAGIC: [(&[u8], &str); 21] = [
+        (b"%PDF-", "application/pdf"),
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"MZ", "application/x-msdownload"),
+        (b"\x7fELF", "application/x-elf"),
+        (b"\xca\xfe\xba\xbe", "application/java-vm"),
+        (
+            b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1",
+            "application/x-ole-storage",
+        ),
+        (b"BZh", "application/x-bzip2"),
+        (b"\xfd7zXZ\x00", "application/x-xz"),
+        (b"OggS", "audio/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"{\\rtf", "application/rtf"),
+        (b"<?xml", "application/xml"),
+        (b"\x00\x00\x01\x00", "image/x-icon"),
+        (b"wOFF", "font/woff"),
+    ];
+    if let Some((_, mime)) = MAGIC.iter().find(|(m, _)| data.starts_with(m)) {
+        return Some(mime);
+    }
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    let head = String::from_utf8_lossy(&data[..data.len().min(256)]).to_ascii_lowercase();
+    let head = head.trim_start();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") {
+        return Some("text/html");
+    }
+    if !data.is_empty() && std::str::from_utf8(&data[..data.len().min(512)]).is_ok() {
+        return Some("text/plain");
+    }
+    None
+}
+
+/// Keep the last path component of a name, without anything a file system would mind
+pub fn sanitize(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let clean: String = base
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || "._-+=@,".contains(c) => c,
+            _ => '_',
+        })
+        .take(100)
+        .collect();
+    match clean.trim_matches('.') {
+        "" => "object".to_string(),
+        c => c.to_string(),
+    }
+}
+
+/// Write the content and its manifest line, recording the path in `file`
+fn write(dir: &Path, file: &mut ExtractedFile, data: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let name = sanitize(file.filename.as_deref().unwrap_or(""));
+    let path = dir.join(format!("{}-{}", &file.sha256[..16], name));
+    if !path.exists() {
+        fs::write(&path, data)?;
+    }
+    file.path = Some(path);
+    let mut manifest = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join("files.jsonl"))?;
+    let mut line = serde_json::to_string(file).map_err(io::Error::other)?;
+    line.push('\n');
+    manifest.write_all(line.as_bytes())
+}
+
+/// An object went through `flow`; `size` is its full length when only part of it was seen
+pub fn on_file(
+    protocol: &'static str,
+    flow: &FlowKey,
+    filename: Option<&str>,
+    declared_mime: Option<&str>,
+    data: &[u8],
+    size: u64,
+) {
+    if data.is_empty() {
+        return;
+    }
+    // parameters (charset, boundary, ...) are not part of the type
+    let declared_mime = declared_mime
+        .and_then(|m| m.split(';').next())
+        .map(|m| m.trim().to_ascii_lowercase())
+        .filter(|m| !m.is_empty());
+    let mime = sniff_mime(data)
+        .filter(|m| *m != "text/plain" || declared_mime.is_none())
+        .map(str::to_string)
+        .or(declared_mime.clone())
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let filename = filename.filter(|f| !f.is_empty()).map(str::to_string);
+    let mut file = ExtractedFile {
+        flow: *flow,
+        protocol,
+        filename,
+        mime,
+        declared_mime,
+        size: size.max(data.len() as u64),
+        truncated: size > data.len() as u64,
+        md5: hex(&Md5::digest(data)),
+        sha1: hex(&Sha1::digest(data)),
+        sha256: hex(&Sha256::digest(data)),
+        path: None,
+    };
+    let dir = OUTPUT.with(|o| o.borrow().clone());
+    let dir = match dir {
+        Some(dir) => dir,
+        None => {
+            ioc::on_file(&file);
+            return;
+        }
+    };
+    if let Err(e) = write(&dir, &mut file, data) {
+        eprintln!("error extracting to {}: {}", dir.display(), e);
+    }
+    println!(
+        "FILE {} \"{}\" {} {}B{} sha256={}",
+        protocol,
+        file.filename.as_deref().unwrap_or("-"),
+        file.mime,
+        file.size,
+        if file.truncated { " (truncated)" } else { "" },
+        file.sha256
+    );
+    ioc::on_file(&file);
+    event::emit(Event::File(file));
+}
+
+/// Content collected by a decoder, up to MAX_FILE bytes and what is left of MAX_BUFFERED
+#[derive(Debug, Default)]
+pub struct Buffer {
+    data: Vec<u8>,
+    /// the budget ran out while filling this buffer
+    starved: bool,
+}
+
+impl Buffer {
+    /// Grow the buffer towards `len` bytes, returning the length reached
+    fn grow(&mut self, len: usize) -> usize {
+        let want = len.min(MAX_FILE);
+        if want <= self.data.len() {
+            return self.data.len();
+        }
+        let left = MAX_BUFFERED.saturating_sub(BUFFERED.with(Cell::get));
+        let grant = (want - self.data.len()).min(left);
+        if grant < want - self.data.len() && !self.starved {
+            self.starved = true;
+            expert::warning(
+                "extract",
+                0,
+                "memory",
+                format!(
+                    "{} MiB already buffered, the object is truncated",
+                    MAX_BUFFERED >> 20
+                ),
+            );
+        }
+        BUFFERED.with(|b| b.set(b.get() + grant));
+        self.data.resize(self.data.len() + grant, 0);
+        self.data.len()
+    }
+
+    /// Append what fits of `chunk`
+    pub fn extend(&mut self, chunk: &[u8]) {
+        let start = self.data.len();
+        let end = self.grow(start + chunk.len());
+        self.data[start..end].copy_from_slice(&chunk[..end - start]);
+    }
+
+    /// Copy what fits of `chunk` at `offset`, the gaps left are zeroes
+    pub fn write_at(&mut self, offset: u64, chunk: &[u8]) -> usize {
+        let start = match usize::try_from(offset) {
+            Ok(s) if s < MAX_FILE => s,
+            _ => return 0,
+        };
+        let end = self.grow(start + chunk.len()).min(start + chunk.len());
+        if end <= start {
+            return 0;
+        }
+        self.data[start..end].copy_from_slice(&chunk[..end - start]);
+        end - start
+    }
+
+    /// Hand the content over, it is no longer counted
+    pub fn take(&mut self) -> Vec<u8> {
+        BUFFERED.with(|b| b.set(b.get().saturating_sub(self.data.len())));
+        self.starved = false;
+        std::mem::take(&mut self.data)
+    }
+
+    pub fn clear(&mut self) {
+        self.take();
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        self.take();
+    }
+}
+
+/// Object assembled from chunks at known offsets (SMB2 reads and writes)
+#[derive(Debug, Default)]
+pub struct Sparse {
+    data: Buffer,
+    /// bytes received, overlaps included
+    pub received: u64,
+}
+
+impl Sparse {
+    /// Copy a chunk at `offset`, ignoring what lies past MAX_FILE
+    pub fn write(&mut self, offset: u64, chunk: &[u8]) {
+        self.received += self.data.write_at(offset, chunk) as u64;
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
@@ -0,0 +1,499 @@
+//! JA3/JA3S and JA4/JA4S TLS fingerprints
+//!
+//! A fingerprint summarizes how a client (or server) builds its hello message: versions,
+//! cipher suites and extensions. Threat intel feeds key malware clients by these values.
+//! GREASE values (RFC 8701) are random by design and are left out of every fingerprint.
+//!
+//! Sources:
+//! * https://github.com/salesforce/ja3
+//! * https://github.com/FoxIO-LLC/ja4/blob/main/technical_details/JA4.md
+use md5::Md5;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use crate::analysis::ioc;
+use crate::context;
+use crate::event::{self, Event};
+use crate::flow::FlowKey;
+use crate::protocols::tls::{self, is_grease, ClientHello, ServerHello, EXT};
+
+/// Transport carrying the TLS handshake, first character of a JA4 fingerprint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Quic,
+    Dtls,
+}
+
+impl Transport {
+    fn code(self) -> char {
+        match self {
+            Transport::Tcp => 't',
+            Transport::Quic => 'q',
+            Transport::Dtls => 'd',
+        }
+    }
+}
+
+fn join<T: ToString>(values: impl Iterator<Item = T>, sep: &str) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<_>>().join(sep)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn md5_hex(s: &str) -> String {
+    hex(&Md5::digest(s.as_bytes()))
+}
+
+/// First 12 hex chars of the sha256 of `s`, zeros for an empty input
+fn sha256_12(s: &str) -> String {
+    if s.is_empty() {
+        return "000000000000".to_string();
+    }
+    hex(&Sha256::digest(s.as_bytes()))[..12].to_string()
+}
+
+fn ja4_version(v: u16) -> &'static str {
+    match v {
+        0x0304 => "13",
+        0x0303 => "12",
+        0x0302 => "11",
+        0x0301 => "10",
+        0x0300 => "s3",
+        0x0002 => "s2",
+        0xfeff => "d1",
+        0xfefd => "d2",
+        0xfefc => "d3",
+        _ => "00",
+    }
+}
+
+/// First and last characters of the ALPN value, or of its hex form when they are
+/// not alphanumeric
+fn ja4_alpn(alpn: Option<&[u8]>) -> String {
+    let (first, last) = match alpn {
+        Some(v) if !v.is_empty() => (v[0], v[v.len() - 1]),
+        _ => return "00".to_string(),
+    };
+    if first.is_ascii_alphanumeric() && last.is_ascii_alphanumeric() {
+        format!("{}{}", first as char, last as char)
+    } else {
+        let h = hex(&[first, last]);
+        format!("{}{}", &h[..1], &h[3..])
+    }
+}
+
+/// JA3 string: SSLVersion,Ciphers,Extensions,EllipticCurves,EllipticCurvePointFormats
+pub fn ja3(ch: &ClientHello) -> String {
+    format!(
+        "{},{},{},{},{}",
+        ch.version,
+        join(ch.cipher_suites.iter().filter(|c| !is_grease(**c)), "-"),
+        join(
+            ch.extensions
+                .iter()
+                .map(|e| e.ext_type)
+                .filter(|e| !is_grease(*e)),
+            "-"
+        ),
+        join(
+            ch.supported_groups().into_iter().filter(|g| !is_grease(*g)),
+            "-"
+        ),
+        join(ch.ec_point_formats().iter(), "-")
+    )
+}
+
+/// JA3S string: SSLVersion,Cipher,Extensions
+pub fn ja3s(sh: &ServerHello) -> String {
+    format!(
+        "{},{},{}",
+        sh.version,
+        sh.cipher_suite,
+        join(sh.extensions.iter().map(|e| e.ext_type), "-")
+    )
+}
+
+/// JA4 fingerprint of a ClientHello, e.g. `t13d1516h2_8daaf6152771_e5627efa2ab1`
+pub fn ja4(ch: &ClientHello, transport: Transport) -> String {
+    let mut ciphers: Vec<u16> = ch
+        .cipher_suites
+        .iter()
+        .copied()
+        .filter(|c| !is_grease(*c))
+        .collect();
+    let exts: Vec<u16> = ch
+        .extensions
+        .iter()
+        .map(|e| e.ext_type)
+        .filter(|e| !is_grease(*e))
+        .collect();
+    let sni = if ch.server_name().is_some() { 'd' } else { 'i' };
+    let prefix = format!(
+        "{}{}{}{:02}{:02}{}",
+        transport.code(),
+        ja4_version(ch.max_version()),
+        sni,
+        ciphers.len().min(99),
+        exts.len().min(99),
+        ja4_alpn(ch.alpn())
+    );
+
+    ciphers.sort_unstable();
+    let cipher_hash = sha256_12(&join(ciphers.iter().map(|c| format!("{:04x}", c)), ","));
+
+    // SNI and ALPN are already part of the prefix
+    let mut sorted_exts: Vec<u16> = exts
+        .into_iter()
+        .filter(|e| *e != EXT::SERVER_NAME && *e != EXT::ALPN)
+        .collect();
+    sorted_exts.sort_unstable();
+    let mut ext_str = join(sorted_exts.iter().map(|e| format!("{:04x}", e)), ",");
+    let sig_algs: Vec<u16> = ch
+        .signature_algorithms()
+        .into_iter()
+        .filter(|s| !is_grease(*s))
+        .collect();
+    if !ext_str.is_empty() && !sig_algs.is_empty() {
+        ext_str.push('_');
+        ext_str.push_str(&join(sig_algs.iter().map(|s| format!("{:04x}", s)), ","));
+    }
+    format!("{}_{}_{}", prefix, cipher_hash, sha256_12(&ext_str))
+}
+
+/// JA4S fingerprint of a ServerHello, e.g. `t130200_1301_234ea6891581`
+pub fn ja4s(sh: &ServerHello, transport: Transport) -> String {
+    let exts = join(
+        sh.extensions.iter().map(|e| format!("{:04x}", e.ext_type)),
+        ",",
+    );
+    format!(
+        "{}{}{:02}{}_{:04x}_{}",
+        transport.code(),
+        ja4_version(sh.selected_version()),
+        sh.extensions.len().min(99),
+        ja4_alpn(sh.alpn()),
+        sh.cipher_suite,
+        sha256_12(&exts)
+    )
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientFingerprint {
+    pub ja3: String,
+    pub ja3_hash: String,
+    pub ja4: String,
+}
+
+impl ClientFingerprint {
+    pub fn compute(ch: &ClientHello, transport: Transport) -> Self {
+        let ja3 = ja3(ch);
+        ClientFingerprint {
+            ja3_hash: md5_hex(&ja3),
+            ja3,
+            ja4: ja4(ch, transport),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerFingerprint {
+    pub ja3s: String,
+    pub ja3s_hash: String,
+    pub ja4s: String,
+}
+
+impl ServerFingerprint {
+    pub fn compute(sh: &ServerHello, transport: Transport) -> Self {
+        let ja3s = ja3s(sh);
+        ServerFingerprint {
+            ja3s_hash: md5_hex(&ja3s),
+            ja3s,
+            ja4s: ja4s(sh, transport),
+        }
+    }
+}
+
+/// Fingerprints of a TLS session, the server part is missing until a ServerHello is seen
+#[derive(Debug, Clone, Serialize)]
+pub struct TlsSession {
+    /// flow oriented from the client to the server
+    pub flow: Option<FlowKey>,
+    pub sni: Option<String>,
+    pub alpn: Option<String>,
+    /// negotiated version
+    pub version: Option<&'static str>,
+    pub cipher: Option<u16>,
+    pub client: Option<ClientFingerprint>,
+    pub server: Option<ServerFingerprint>,
+}
+
+/// Upper bound of sessions waiting for their ServerHello
+const MAX_PENDING: usize = 65536;
+
+thread_local! {
+    static PENDING: RefCell<HashMap<FlowKey, TlsSession>> = RefCell::new(HashMap::new());
+}
+
+/// Record a ClientHello; the session is emitted once the ServerHello is seen
+pub fn on_client_hello(ch: &ClientHello, transport: Transport) {
+    let session = TlsSession {
+        flow: context::flow(),
+        sni: ch.server_name().map(str::to_string),
+        alpn: ch.alpn().map(|a| String::from_utf8_lossy(a).into_owned()),
+        version: None,
+        cipher: None,
+        client: Some(ClientFingerprint::compute(ch, transport)),
+        server: None,
+    };
+    if let Some(client) = &session.client {
+        ioc::on_client_hello(session.flow, session.sni.as_deref(), client);
+    }
+    let flow = match session.flow {
+        Some(flow) => flow,
+        // without a flow there is nothing to pair the answer with
+        None => return event::emit(Event::Tls(session)),
+    };
+    PENDING.with(|p| {
+        let mut p = p.borrow_mut();
+        if p.len() >= MAX_PENDING {
+            for (_, s) in p.drain() {
+                event::emit(Event::Tls(s));
+            }
+        }
+        p.insert(flow.canonical().0, session);
+    });
+}
+
+/// Complete the session of the flow with its ServerHello and emit it
+pub fn on_server_hello(sh: &ServerHello, transport: Transport) {
+    let flow = context::flow();
+    let pending = flow.and_then(|f| PENDING.with(|p| p.borrow_mut().remove(&f.canonical().0)));
+    let mut session = pending.unwrap_or(TlsSession {
+        flow: flow.map(|f| f.reversed()),
+        sni: None,
+        alpn: None,
+        version: None,
+        cipher: None,
+        client: None,
+        server: None,
+    });
+    session.version = Some(tls::version_as_str(sh.selected_version()));
+    session.cipher = Some(sh.cipher_suite);
+    if let Some(alpn) = sh.alpn() {
+        session.alpn = Some(String::from_utf8_lossy(alpn).into_owned());
+    }
+    session.server = Some(ServerFingerprint::compute(sh, transport));
+    event::emit(Event::Tls(session));
+}
+
+/// Emit the sessions that never saw a ServerHello, typically at the end of a capture
+pub fn flush() {
+    PENDING.with(|p| {
+        for (_, s) in p.borrow_mut().drain() {
+            event::emit(Event::Tls(s));
+        }
+    });
+}
+
+/// A session fingerprint found in the local fingerprint list
+#[derive(Debug, Clone, Serialize)]
+pub struct FingerprintMatch {
+    pub flow: Option<FlowKey>,
+    pub sni: Option<String>,
+    pub fingerprint: String,
+    pub label: String,
+}
+
+/// Local list of known fingerprints
+///
+/// One fingerprint per line (JA3/JA3S md5, JA4 or JA4S), optionally followed by a label
+/// separated by a comma or whitespace. Lines starting with `#` are comments.
+///
+/// ```text
+/// # trickbot
+/// 6734f37431670b3ab4292b8f60f29984,Trickbot
+/// t13d190900_9dc949149365_97f8aa674fd9 SliverC2
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct FingerprintDb {
+    entries: HashMap<String, String>,
+}
+
+impl FingerprintDb {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::parse(&std::fs::read_to_string(path)?))
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let mut entries = HashMap::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (fp, label) = match line.find(|c: char| c == ',' || c.is_whitespace()) {
+                Some(i) => (&line[..i], line[i + 1..].trim()),
+                None => (line, ""),
+            };
+            entries.insert(fp.to_ascii_lowercase(), label.to_string());
+        }
+        FingerprintDb { entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn lookup(&self, fingerprint: &str) -> Option<&str> {
+        self.entries
+            .get(&fingerprint.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// Every fingerprint of the session present in the list
+    pub fn check(&self, session: &TlsSession) -> Vec<FingerprintMatch> {
+        let mut fps: Vec<&str> = Vec::new();
+        if let Some(c) = &session.client {
+            fps.extend([c.ja3_hash.as_str(), c.ja4.as_str()]);
+        }
+        if let Some(s) = &session.server {
+            fps.extend([s.ja3s_hash.as_str(), s.ja4s.as_str()]);
+        }
+        fps.into_iter()
+            .filter_map(|fp| {
+                self.lookup(fp).map(|label| FingerprintMatch {
+                    flow: session.flow,
+                    sni: session.sni.clone(),
+                    fingerprint: fp.to_string(),
+                    label: label.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16s(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    /// Hello body: version, random, empty session id, `middle` (suites and compression, or
+    /// suite and compression method), extensions
+    fn hello(version: u16, middle: &[u8], extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut body = version.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0; 32]);
+        body.push(0);
+        body.extend_from_slice(middle);
+        if !extensions.is_empty() {
+            let mut exts = Vec::new();
+            for (t, data) in extensions {
+                exts.extend_from_slice(&t.to_be_bytes());
+                exts.extend_from_slice(&(data.len() as u16).to_be_bytes());
+                exts.extend_from_slice(data);
+            }
+            body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+            body.extend_from_slice(&exts);
+        }
+        body
+    }
+
+    fn client_hello(version: u16, suites: &[u16], extensions: &[(u16, Vec<u8>)]) -> Vec<u8> {
+        let mut middle = ((suites.len() * 2) as u16).to_be_bytes().to_vec();
+        middle.extend_from_slice(&u16s(suites));
+        middle.extend_from_slice(&[1, 0]);
+        hello(version, &middle, extensions)
+    }
+
+    /// Vector of 16-bit values with a length prefix of `len_bytes`
+    fn list(len_bytes: usize, values: &[u16]) -> Vec<u8> {
+        let data = u16s(values);
+        let mut out = (data.len() as u16).to_be_bytes()[2 - len_bytes..].to_vec();
+        out.extend_from_slice(&data);
+        out
+    }
+
+    /// ClientHello of a TLS 1.3 client with GREASE values in every list
+    fn modern_client() -> Vec<u8> {
+        let mut sni = vec![0, 14, 0, 0, 11];
+        sni.extend_from_slice(b"example.com");
+        let alpn = b"\x00\x0c\x02h2\x08http/1.1".to_vec();
+        client_hello(
+            0x0303,
+            &[0x0a0a, 0x1301, 0x1302, 0xc02b, 0x002f],
+            &[
+                (0x1a1a, Vec::new()),
+                (EXT::SERVER_NAME, sni),
+                (EXT::SUPPORTED_GROUPS, list(2, &[0x2a2a, 0x001d, 0x0017])),
+                (EXT::EC_POINT_FORMATS, vec![1, 0]),
+                (
+                    EXT::SIGNATURE_ALGORITHMS,
+                    list(2, &[0x0403, 0x0804, 0x0401]),
+                ),
+                (EXT::ALPN, alpn),
+                (EXT::SUPPORTED_VERSIONS, list(1, &[0x3a3a, 0x0304, 0x0303])),
+            ],
+        )
+    }
+
+    #[test]
+    fn client_without_grease() {
+        let body = modern_client();
+        let ch = ClientHello::parse(&body).unwrap();
+        let fp = ClientFingerprint::compute(&ch, Transport::Tcp);
+        assert_eq!(fp.ja3, "771,4865-4866-49195-47,0-10-11-13-16-43,29-23,0");
+        assert_eq!(fp.ja3_hash, "85dfd04b48599755b61acdcbd24dbec8");
+        // suites 002f,1301,1302,c02b; extensions 000a,000b,000d,002b_0403,0804,0401
+        assert_eq!(fp.ja4, "t13d0406h2_52f89ac5ce33_0d385148b956");
+        assert!(ja4(&ch, Transport::Quic).starts_with("q13d0406h2_"));
+    }
+
+    #[test]
+    fn client_without_extensions() {
+        let body = client_hello(0x0301, &[0x002f, 0x0035], &[]);
+        let ch = ClientHello::parse(&body).unwrap();
+        let fp = ClientFingerprint::compute(&ch, Transport::Tcp);
+        assert_eq!(fp.ja3, "769,47-53,,,");
+        assert_eq!(fp.ja3_hash, "dac4920d4335e769327dbf4e1b759e15");
+        assert_eq!(fp.ja4, "t10i020000_f54dd463d39b_000000000000");
+    }
+
+    #[test]
+    fn server() {
+        let mut middle = 0x1301u16.to_be_bytes().to_vec();
+        middle.push(0);
+        let body = hello(
+            0x0303,
+            &middle,
+            &[
+                (EXT::SUPPORTED_VERSIONS, vec![0x03, 0x04]),
+                // key_share
+                (51, vec![0x00, 0x1d, 0x00, 0x00]),
+            ],
+        );
+        let sh = ServerHello::parse(&body).unwrap();
+        let fp = ServerFingerprint::compute(&sh, Transport::Tcp);
+        assert_eq!(fp.ja3s, "771,4865,43-51");
+        assert_eq!(fp.ja3s_hash, "f4febc55ea12b31ae17cfb7e614afda8");
+        assert_eq!(fp.ja4s, "t130200_1301_a56c5b993250");
+    }
+
+    #[test]
+    fn alpn_characters() {
+        assert_eq!(ja4_alpn(None), "00");
+        assert_eq!(ja4_alpn(Some(b"http/1.1")), "h1");
+        assert_eq!(ja4_alpn(Some(b"\xab\x01")), "a1");
+    }
+}
@@ -0,0 +1,215 @@
+//! GeoIP and ASN enrichment of addresses
+//!
+//! Addresses are looked up in local MaxMind databases (GeoLite2/GeoIP2 City or Country, and
+//! ASN, in the MMDB format), read once in memory. Whatever the databases, the special
+//! purpose ranges of the IANA registries are recognized: private, shared (CGNAT),
+//! loopback, link-local, multicast, documentation, benchmarking and reserved addresses,
+//! which are all bogons: not expected on the public internet.
+use maxminddb::{geoip2, Reader};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::sync::Arc;
+
+/// What is known of an address
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GeoInfo {
+    /// "public", "private", "shared", "loopback", "link-local", "multicast",
+    /// "documentation", "benchmarking", "unspecified", "broadcast" or "reserved"
+    pub scope: &'static str,
+    /// not routable on the public internet
+    pub bogon: bool,
+    /// ISO 3166 code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asn: Option<u32>,
+    /// organisation owning the AS
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org: Option<String>,
+}
+
+fn v4_scope(a: Ipv4Addr) -> &'static str {
+    let in_net = |net: [u8; 4], prefix: u32| {
+        let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+        u32::from(a) & mask == u32::from(Ipv4Addr::from(net)) & mask
+    };
+    if a.is_unspecified() {
+        "unspecified"
+    } else if a.is_broadcast() {
+        "broadcast"
+    } else if a.is_private() {
+        "private"
+    } else if in_net([100, 64, 0, 0], 10) {
+        "shared"
+    } else if a.is_loopback() {
+        "loopback"
+    } else if a.is_link_local() {
+        "link-local"
+    } else if a.is_multicast() {
+        "multicast"
+    } else if a.is_documentation() {
+        "documentation"
+    } else if in_net([198, 18, 0, 0], 15) {
+        "benchmarking"
+    } else if in_net([0, 0, 0, 0], 8) || in_net([192, 0, 0, 0], 24) || in_net([240, 0, 0, 0], 4) {
+        "reserved"
+    } else {
+        "public"
+    }
+}
+
+fn v6_scope(a: Ipv6Addr) -> &'static str {
+    let segments = a.segments();
+    if let Some(v4) = a.to_ipv4_mapped() {
+        return v4_scope(v4);
+    }
+    if a.is_unspecified() {
+        "unspecified"
+    } else if a.is_loopback() {
+        "loopback"
+    } else if segments[0] & 0xfe00 == 0xfc00 {
+        // unique local
+        "private"
+    } else if segments[0] & 0xffc0 == 0xfe80 {
+        "link-local"
+    } else if a.is_multicast() {
+        "multicast"
+    } else if segments[0] == 0x2001 && segments[1] == 0x0db8 {
+        "documentation"
+    } else if segments[0] == 0x2001 && segments[1] == 0x0002 && segments[2] == 0 {
+        "benchmarking"
+    } else if segments[0] & 0xe000 == 0x2000 || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        // global unicast, NAT64
+        "public"
+    } else {
+        "reserved"
+    }
+}
+
+/// Range an address belongs to, "public" when it is none of the special purpose ones
+pub fn scope(addr: IpAddr) -> &'static str {
+    match addr {
+        IpAddr::V4(a) => v4_scope(a),
+        IpAddr::V6(a) => v6_scope(a),
+    }
+}
+
+/// GeoIP and ASN databases
+#[derive(Default, Clone)]
+pub struct GeoDb {
+    /// City or Country databases
+    locations: Vec<Arc<Reader<Vec<u8>>>>,
+    asns: Vec<Arc<Reader<Vec<u8>>>>,
+}
+
+impl GeoDb {
+    /// Add a database file, its kind being read from its metadata
+    pub fn open<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.add(std::fs::read(path)?)
+    }
+
+    /// Add a database from its content
+    pub fn add(&mut self, bytes: Vec<u8>) -> io::Result<()> {
+        let reader = Reader::from_source(bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let kind = reader.metadata.database_type.to_ascii_lowercase();
+        if kind.contains("asn") {
+            self.asns.push(Arc::new(reader));
+        } else if kind.contains("city") || kind.contains("country") {
+            self.locations.push(Arc::new(reader));
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "unsupported database type {}",
+                    reader.metadata.database_type
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty() && self.asns.is_empty()
+    }
+
+    /// Scope of the address and what the databases know of it
+    pub fn lookup(&self, addr: IpAddr) -> GeoInfo {
+        let scope = scope(addr);
+        let mut info = GeoInfo {
+            scope,
+            bogon: scope != "public",
+            ..GeoInfo::default()
+        };
+        if info.bogon {
+            return info;
+        }
+        let english = |names: Option<std::collections::BTreeMap<&str, &str>>| {
+            names.and_then(|n| n.get("en").map(|s| s.to_string()))
+        };
+        for db in &self.locations {
+            // a City record is a superset of a Country one
+            if let Ok(city) = db.lookup::<geoip2::City>(addr) {
+                let country = city.country.or(city.registered_country);
+                info.country = country
+                    .as_ref()
+                    .and_then(|c| c.iso_code.map(str::to_string));
+                info.country_name = english(country.and_then(|c| c.names));
+                info.city = english(city.city.and_then(|c| c.names));
+                break;
+            }
+        }
+        for db in &self.asns {
+            if let Ok(asn) = db.lookup::<geoip2::Asn>(addr) {
+                info.asn = asn.autonomous_system_number;
+                info.org = asn.autonomous_system_organization.map(str::to_string);
+                break;
+            }
+        }
+        info
+    }
+}
+
+thread_local! {
+    static DB: RefCell<Option<Arc<GeoDb>>> = const { RefCell::new(None) };
+}
+
+/// Enrich addresses with the databases, for the current thread; threads share them
+pub fn set_db(db: Arc<GeoDb>) {
+    DB.with(|d| *d.borrow_mut() = Some(db));
+}
+
+/// Add databases to those of the current thread
+pub fn add_db(db: GeoDb) {
+    DB.with(|d| {
+        let mut d = d.borrow_mut();
+        match d.as_mut() {
+            Some(current) => {
+                let current = Arc::make_mut(current);
+                current.locations.extend(db.locations);
+                current.asns.extend(db.asns);
+            }
+            None => *d = Some(Arc::new(db)),
+        }
+    });
+}
+
+/// Whether enrichment was asked for
+pub fn enabled() -> bool {
+    DB.with(|d| d.borrow().is_some())
+}
+
+/// What is known of an address: its scope, and its location and AS when databases are set
+pub fn enrich(addr: IpAddr) -> GeoInfo {
+    DB.with(|d| match d.borrow().as_ref() {
+        Some(db) => db.lookup(addr),
+        None => GeoDb::default().lookup(addr),
+    })
+}
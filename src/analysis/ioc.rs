@@ -0,0 +1,522 @@
+//! Indicators of compromise from local feeds
+//!
+//! A feed is a flat file with one indicator per line (`#` lines are comments, anything after
+//! the first blank is ignored), named after the file. The kind of each indicator is
+//! recognized from its form:
+//! - IPv4/IPv6 addresses and CIDR networks, checked against the addresses of every packet
+//!   and the addresses of DNS answers
+//! - domains, matching the domain and its subdomains (`evil.test` matches `a.evil.test`),
+//!   checked against DNS queries and answers, TLS SNI and HTTP Host
+//! - URLs (`http://host/path`, or a bare `/path`), matching the requests starting with them,
+//!   anywhere in the URI for a bare path
+//! - MD5, SHA-1 and SHA-256 hashes, checked against transferred files (extracted or not) and
+//!   JA3 hashes
+//! - JA4 fingerprints, checked against ClientHellos
+//!
+//! Each match is reported once per feed, indicator and flow as an `IocMatch` event.
+//! Feeds can be reloaded while capturing: `refresh` reloads them when a file changed.
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::analysis::extract::ExtractedFile;
+use crate::analysis::fingerprint::ClientFingerprint;
+use crate::context;
+use crate::event::{self, Event};
+use crate::flow::FlowKey;
+use crate::matcher::{Matcher, Pattern};
+use crate::protocols::http::Exchange;
+use crate::protocols::ip::IpProtocol;
+
+/// Matches remembered per thread to report each one once
+const MAX_SEEN: usize = 65536;
+/// Feeds are checked for changes at most this often
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// An indicator seen in the traffic
+#[derive(Debug, Clone, Serialize)]
+pub struct IocMatch {
+    /// name of the feed listing the indicator
+    pub feed: String,
+    /// "ip", "domain", "url", "hash" or "ja4"
+    pub kind: &'static str,
+    /// the indicator as listed
+    pub indicator: String,
+    /// where it was seen ("ip.src", "dns.query", "tls.sni", "http.host", "file.sha256", ...)
+    pub field: &'static str,
+    /// the value that matched
+    pub value: String,
+    pub flow: Option<FlowKey>,
+}
+
+/// Indicators and the feeds listing them
+type Feeds = Vec<usize>;
+
+/// Networks by prefix length, addresses masked
+#[derive(Debug, Default)]
+struct Networks {
+    v4: BTreeMap<u8, HashMap<u32, (String, Feeds)>>,
+    v6: BTreeMap<u8, HashMap<u128, (String, Feeds)>>,
+}
+
+fn mask4(a: u32, prefix: u8) -> u32 {
+    a & u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0)
+}
+
+fn mask6(a: u128, prefix: u8) -> u128 {
+    a & u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0)
+}
+
+impl Networks {
+    fn insert(&mut self, addr: IpAddr, prefix: u8, indicator: &str, feed: usize) {
+        let (_, feeds) = match addr {
+            IpAddr::V4(a) => self
+                .v4
+                .entry(prefix)
+                .or_default()
+                .entry(mask4(a.into(), prefix))
+                .or_insert_with(|| (indicator.to_string(), Vec::new())),
+            IpAddr::V6(a) => self
+                .v6
+                .entry(prefix)
+                .or_default()
+                .entry(mask6(a.into(), prefix))
+                .or_insert_with(|| (indicator.to_string(), Vec::new())),
+        };
+        feeds.push(feed);
+    }
+
+    /// Most specific network containing the address
+    fn lookup(&self, addr: IpAddr) -> Option<&(String, Feeds)> {
+        match addr {
+            IpAddr::V4(a) => self
+                .v4
+                .iter()
+                .rev()
+                .find_map(|(p, nets)| nets.get(&mask4(a.into(), *p))),
+            IpAddr::V6(a) => self
+                .v6
+                .iter()
+                .rev()
+                .find_map(|(p, nets)| nets.get(&mask6(a.into(), *p))),
+        }
+    }
+}
+
+/// A loaded feed
+#[derive(Debug, Clone)]
+pub struct Feed {
+    pub name: String,
+    pub path: PathBuf,
+    pub indicators: usize,
+    /// lines that are no indicator
+    pub skipped: usize,
+    modified: Option<SystemTime>,
+}
+
+/// Indicators of a set of feeds
+#[derive(Debug, Default)]
+pub struct Iocs {
+    pub feeds: Vec<Feed>,
+    networks: Networks,
+    domains: HashMap<String, Feeds>,
+    hashes: HashMap<String, Feeds>,
+    ja4: HashMap<String, Feeds>,
+    /// URLs without their scheme and the host lowercased, as listed
+    urls: Vec<(String, String, Feeds)>,
+    url_matcher: Option<Matcher>,
+}
+
+fn is_hex(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn is_ja4(s: &str) -> bool {
+    let parts: Vec<&str> = s.split('_').collect();
+    matches!(parts[..], [a, b, c] if a.len() == 10
+        && b.len() == 12
+        && c.len() == 12
+        && a.is_ascii()
+        && is_hex(b)
+        && is_hex(c))
+}
+
+fn is_domain(s: &str) -> bool {
+    s.contains('.')
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'.' || b == b'_')
+}
+
+/// `host/path` of a URL, the host lowercased
+fn normalize_url(url: &str) -> String {
+    let rest = url.split_once("://").map_or(url, |(_, r)| r);
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    format!("{}{}", host.to_ascii_lowercase(), path)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+impl Iocs {
+    /// Load feeds, each named after its file
+    pub fn load<P: AsRef<Path>>(paths: &[P]) -> io::Result<Self> {
+        let mut iocs = Iocs::default();
+        for path in paths {
+            let path = path.as_ref();
+            let modified = fs::metadata(path)?.modified().ok();
+            let text = fs::read_to_string(path)?;
+            iocs.add(file_name(path), path.to_path_buf(), modified, &text);
+        }
+        iocs.build();
+        Ok(iocs)
+    }
+
+    /// Add the indicators of a feed
+    fn add(&mut self, name: String, path: PathBuf, modified: Option<SystemTime>, text: &str) {
+        let feed = self.feeds.len();
+        let (mut indicators, mut skipped) = (0, 0);
+        for line in text.lines() {
+            let value = match line.split_whitespace().next() {
+                Some(v) if !v.starts_with('#') => v,
+                _ => continue,
+            };
+            if self.add_indicator(value, feed) {
+                indicators += 1;
+            } else {
+                skipped += 1;
+            }
+        }
+        self.feeds.push(Feed {
+            name,
+            path,
+            indicators,
+            skipped,
+            modified,
+        });
+    }
+
+    fn add_indicator(&mut self, value: &str, feed: usize) -> bool {
+        let (addr, prefix) = value.split_once('/').unwrap_or((value, ""));
+        if let Ok(addr) = addr.parse::<IpAddr>() {
+            let max = if addr.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                "" => max,
+                p => match p.parse() {
+                    Ok(p) if p <= max => p,
+                    _ => return false,
+                },
+            };
+            self.networks.insert(addr, prefix, value, feed);
+            return true;
+        }
+        let lower = value.to_ascii_lowercase();
+        let map = if value.contains("://") || value.starts_with('/') {
+            let url = if value.starts_with('/') {
+                value.to_string()
+            } else {
+                normalize_url(value)
+            };
+            match self.urls.iter_mut().find(|(u, _, _)| *u == url) {
+                Some((_, _, feeds)) => feeds.push(feed),
+                None => self.urls.push((url, value.to_string(), vec![feed])),
+            }
+            return true;
+        } else if matches!(value.len(), 32 | 40 | 64) && is_hex(value) {
+            &mut self.hashes
+        } else if is_ja4(&lower) {
+            &mut self.ja4
+        } else {
+            let domain = lower
+                .trim_start_matches("*.")
+                .trim_start_matches('.')
+                .trim_end_matches('.');
+            if !is_domain(domain) {
+                return false;
+            }
+            self.domains
+                .entry(domain.to_string())
+                .or_default()
+                .push(feed);
+            return true;
+        };
+        map.entry(lower).or_default().push(feed);
+        true
+    }
+
+    fn build(&mut self) {
+        if self.urls.is_empty() {
+            return;
+        }
+        let patterns = self
+            .urls
+            .iter()
+            .map(|(u, _, _)| Pattern::literal(u.as_bytes(), false))
+            .collect();
+        self.url_matcher = Some(Matcher::new(patterns));
+    }
+
+    pub fn len(&self) -> usize {
+        self.feeds.iter().map(|f| f.indicators).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether a feed file changed since it was loaded
+    fn changed(&self) -> bool {
+        self.feeds.iter().any(|f| {
+            let modified = fs::metadata(&f.path).and_then(|m| m.modified()).ok();
+            modified != f.modified
+        })
+    }
+
+    /// Feeds listing the domain or one of its parents, with the listed domain
+    fn domain(&self, name: &str) -> Option<(&str, &Feeds)> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        let mut candidate = name.as_str();
+        loop {
+            if let Some((listed, feeds)) = self.domains.get_key_value(candidate) {
+                return Some((listed, feeds));
+            }
+            candidate = candidate.split_once('.')?.1;
+        }
+    }
+}
+
+/// feed, indicator, kind and flow of a reported match
+type Seen = (String, String, &'static str, Option<FlowKey>);
+
+thread_local! {
+    static IOCS: RefCell<Option<Arc<Iocs>>> = const { RefCell::new(None) };
+    static SEEN: RefCell<HashSet<Seen>> = RefCell::new(HashSet::new());
+    static LAST_REFRESH: RefCell<Option<Instant>> = const { RefCell::new(None) };
+}
+
+/// Replace the indicators of the current thread; threads share them until the feeds are
+/// reloaded
+pub fn set_iocs(iocs: Arc<Iocs>) {
+    IOCS.with(|i| *i.borrow_mut() = Some(iocs));
+    SEEN.with(|s| s.borrow_mut().clear());
+}
+
+/// Whether indicators are loaded
+pub fn enabled() -> bool {
+    IOCS.with(|i| i.borrow().as_ref().is_some_and(|i| !i.feeds.is_empty()))
+}
+
+/// Whether hashes are loaded, transferred files are then collected to check them
+pub fn file_hashes() -> bool {
+    IOCS.with(|i| i.borrow().as_ref().is_some_and(|i| !i.hashes.is_empty()))
+}
+
+/// Reload the feeds when one of their files changed, checking at most once a second
+///
+/// A feed failing to load keeps the indicators loaded before. Returns whether the feeds
+/// were reloaded.
+pub fn refresh() -> bool {
+    let due = LAST_REFRESH.with(|l| {
+        let mut l = l.borrow_mut();
+        let due = l.is_none_or(|t| t.elapsed() >= REFRESH_INTERVAL);
+        if due {
+            *l = Some(Instant::now());
+        }
+        due
+    });
+    if !due {
+        return false;
+    }
+    let paths = IOCS.with(|i| match i.borrow().as_ref() {
+        Some(iocs) if iocs.changed() => Some(
+            iocs.feeds
+                .iter()
+                .map(|f| f.path.clone())
+                .collect::<Vec<_>>(),
+        ),
+        _ => None,
+    });
+    let paths = match paths {
+        Some(p) => p,
+        None => return false,
+    };
+    match Iocs::load(&paths) {
+        Ok(iocs) => {
+            eprintln!("reloaded {} indicators", iocs.len());
+            set_iocs(Arc::new(iocs));
+            true
+        }
+        Err(e) => {
+            eprintln!("error reloading indicators: {}", e);
+            false
+        }
+    }
+}
+
+fn report(
+    iocs: &Iocs,
+    kind: &'static str,
+    indicator: &str,
+    feeds: &Feeds,
+    field: &'static str,
+    value: &str,
+    flow: Option<FlowKey>,
+) {
+    for &feed in feeds {
+        let name = &iocs.feeds[feed].name;
+        let key = (
+            name.clone(),
+            indicator.to_string(),
+            kind,
+            flow.map(|f| f.canonical().0),
+        );
+        let new = SEEN.with(|s| {
+            let mut s = s.borrow_mut();
+            if s.len() >= MAX_SEEN {
+                s.clear();
+            }
+            s.insert(key)
+        });
+        if !new {
+            continue;
+        }
+        println!(
+            "IOC {} {} {} in {} {} {}",
+            name,
+            kind,
+            indicator,
+            field,
+            value,
+            flow.map_or("-".to_string(), |f| f.to_string())
+        );
+        event::emit(Event::IocMatch(IocMatch {
+            feed: name.clone(),
+            kind,
+            indicator: indicator.to_string(),
+            field,
+            value: value.to_string(),
+            flow,
+        }));
+    }
+}
+
+fn with_iocs(f: impl FnOnce(&Iocs)) {
+    IOCS.with(|i| {
+        if let Some(iocs) = i.borrow().as_ref() {
+            f(iocs)
+        }
+    });
+}
+
+fn check_ip(iocs: &Iocs, field: &'static str, addr: IpAddr, flow: Option<FlowKey>) {
+    if let Some((listed, feeds)) = iocs.networks.lookup(addr) {
+        report(iocs, "ip", listed, feeds, field, &addr.to_string(), flow);
+    }
+}
+
+fn check_domain(iocs: &Iocs, field: &'static str, name: &str, flow: Option<FlowKey>) {
+    if let Some((listed, feeds)) = iocs.domain(name) {
+        report(iocs, "domain", listed, feeds, field, name, flow);
+    }
+}
+
+fn check_hash(iocs: &Iocs, field: &'static str, hash: &str, flow: Option<FlowKey>) {
+    let hash = hash.to_ascii_lowercase();
+    if let Some(feeds) = iocs.hashes.get(&hash) {
+        report(iocs, "hash", &hash, feeds, field, &hash, flow);
+    }
+}
+
+/// Check the addresses of the packet being decoded
+pub fn on_addrs(proto: IpProtocol, src: IpAddr, dst: IpAddr) {
+    with_iocs(|iocs| {
+        let flow = Some(FlowKey::new(proto, src, 0, dst, 0));
+        check_ip(iocs, "ip.src", src, flow);
+        check_ip(iocs, "ip.dst", dst, flow);
+    });
+}
+
+/// Check an address seen in the packet being decoded (DNS answer, ...)
+pub fn on_ip(field: &'static str, addr: IpAddr) {
+    with_iocs(|iocs| check_ip(iocs, field, addr, context::flow()));
+}
+
+/// Check a domain seen in the packet being decoded (DNS query or answer, ...)
+pub fn on_domain(field: &'static str, name: &str) {
+    with_iocs(|iocs| check_domain(iocs, field, name, context::flow()));
+}
+
+/// Check the SNI and fingerprints of a ClientHello
+pub fn on_client_hello(flow: Option<FlowKey>, sni: Option<&str>, fp: &ClientFingerprint) {
+    with_iocs(|iocs| {
+        if let Some(sni) = sni {
+            check_domain(iocs, "tls.sni", sni, flow);
+        }
+        check_hash(iocs, "tls.ja3", &fp.ja3_hash, flow);
+        if let Some(feeds) = iocs.ja4.get(&fp.ja4) {
+            report(iocs, "ja4", &fp.ja4, feeds, "tls.ja4", &fp.ja4, flow);
+        }
+    });
+}
+
+/// Check the Host and URL of an HTTP request
+pub fn on_http(exchange: &Exchange) {
+    with_iocs(|iocs| {
+        let flow = Some(exchange.flow);
+        let host = exchange.host.as_deref().map(|h| {
+            // the port is not part of the domain
+            let h = h.trim();
+            match h.rsplit_once(':') {
+                Some((name, port)) if !name.contains(':') && port.parse::<u16>().is_ok() => name,
+                _ => h,
+            }
+        });
+        if let Some(host) = host {
+            check_domain(iocs, "http.host", host, flow);
+        }
+        let matcher = match &iocs.url_matcher {
+            Some(m) => m,
+            None => return,
+        };
+        let host = exchange
+            .host
+            .as_deref()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        // absolute form, through a proxy
+        let url = if exchange.uri.contains("://") {
+            normalize_url(&exchange.uri)
+        } else {
+            format!("{}{}", host, exchange.uri)
+        };
+        let path_start = url.find('/').unwrap_or(url.len());
+        for m in matcher.find_iter(url.as_bytes()) {
+            let (normalized, listed, feeds) = &iocs.urls[m.pattern];
+            // a full URL matches from the start, a path anywhere in the URI
+            if m.start == 0 || (normalized.starts_with('/') && m.start >= path_start) {
+                report(iocs, "url", listed, feeds, "http.url", &url, flow);
+            }
+        }
+    });
+}
+
+/// Check the hashes of a transferred file
+pub fn on_file(file: &ExtractedFile) {
+    with_iocs(|iocs| {
+        check_hash(iocs, "file.md5", &file.md5, Some(file.flow));
+        check_hash(iocs, "file.sha1", &file.sha1, Some(file.flow));
+        check_hash(iocs, "file.sha256", &file.sha256, Some(file.flow));
+    });
+}
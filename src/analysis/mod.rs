@@ -0,0 +1,8 @@
+pub mod credentials;
+pub mod extract;
+pub mod fingerprint;
+pub mod geoip;
+pub mod ioc;
+pub mod rules;
+pub mod tls_policy;
+pub mod x509;
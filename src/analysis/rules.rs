@@ -0,0 +1,1568 @@
+//! Signature engine for a subset of the Snort/Suricata rule language
+//!
+//! Rules are loaded from a rules file and run against the decoded packets, the reassembled
+//! TCP streams and a few application layer buffers. Supported:
+//! - header: action, protocol (ip, tcp, udp, icmp, http, dns, tls), addresses and ports
+//!   (any, lists, negation, CIDR, ranges, `ipvar`/`portvar` variables) and direction
+//!   (`->`, `<>`)
+//! - `content` with `nocase`, `offset`, `depth`, `distance`, `within` and negation, `pcre`
+//! - `flow` (established, not_established, to_server, to_client, from_client, from_server,
+//!   only_stream, no_stream), `flowbits` (set, unset, toggle, isset, isnotset, noalert),
+//!   `dsize`
+//! - `msg`, `sid`, `rev`, `gid`, `classtype`, `priority`
+//! - sticky buffers `pkt_data`, `http.uri`, `http.method`, `http.host`, `http.user_agent`,
+//!   `http.header`, `http.stat_code`, `dns.query` and `tls.sni`, and the older content
+//!   modifiers (`http_uri`, `http_method`, ...)
+//!
+//! TCP rules inspecting the payload run on the reassembled stream, so that a content split
+//! across segments still matches; other rules run on each packet. A rule using a sticky
+//! buffer runs when the application decoder has it: once per HTTP request, DNS query or
+//! TLS ClientHello. Rules with unsupported keywords are skipped when loading, rather than
+//! run with a looser meaning.
+//!
+//! Matches are emitted as `Alert` events, shaped like Suricata's EVE JSON alerts.
+use regex::bytes::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use crate::context;
+use crate::event::{self, Event};
+use crate::flow::{Direction, FlowKey};
+use crate::matcher::{self, Matcher, Pattern};
+use crate::protocols::http::Exchange;
+use crate::protocols::ip::IpProtocol;
+use crate::reassembly::FLAGS;
+use crate::timestamp;
+
+/// Flows tracked per thread for flow and flowbits
+const MAX_FLOWS: usize = 65536;
+/// Bytes of each direction of a stream kept to match across segments
+const STREAM_WINDOW: usize = 4096;
+/// Candidate positions tried per rule and input
+const MATCH_BUDGET: usize = 4096;
+/// Variables referring to variables
+const MAX_VAR_DEPTH: usize = 8;
+
+/// Variables of a rules file before any `ipvar`/`portvar` line
+const DEFAULT_VARS: &[(&str, &str)] = &[
+    ("HOME_NET", "[10.0.0.0/8,172.16.0.0/12,192.168.0.0/16]"),
+    ("EXTERNAL_NET", "any"),
+    ("HTTP_SERVERS", "$HOME_NET"),
+    ("SMTP_SERVERS", "$HOME_NET"),
+    ("SQL_SERVERS", "$HOME_NET"),
+    ("DNS_SERVERS", "$HOME_NET"),
+    ("TELNET_SERVERS", "$HOME_NET"),
+    ("HTTP_PORTS", "[80,8000,8080,8888]"),
+    ("SHELLCODE_PORTS", "!80"),
+    ("ORACLE_PORTS", "1521"),
+    ("SSH_PORTS", "22"),
+    ("FTP_PORTS", "21"),
+    ("FILE_DATA_PORTS", "[$HTTP_PORTS,110,143]"),
+];
+
+/// Keywords accepted and ignored: they don't change what a rule matches
+const IGNORED_KEYWORDS: &[&str] = &["reference", "metadata", "rawbytes", "target", "tag"];
+
+/// Application layer of the sticky buffers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppProto {
+    Http,
+    Dns,
+    Tls,
+}
+
+impl AppProto {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AppProto::Http => "http",
+            AppProto::Dns => "dns",
+            AppProto::Tls => "tls",
+        }
+    }
+}
+
+/// Data a content or pcre looks at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Buffer {
+    /// packet payload, or the reassembled stream
+    Packet,
+    HttpUri,
+    HttpMethod,
+    HttpHost,
+    HttpUserAgent,
+    /// request headers, one `Name: value\r\n` line each
+    HttpHeader,
+    HttpStatCode,
+    DnsQuery,
+    TlsSni,
+}
+
+impl Buffer {
+    /// Sticky buffer keyword
+    fn sticky(name: &str) -> Option<Self> {
+        Some(match name {
+            "pkt_data" => Buffer::Packet,
+            "http.uri" => Buffer::HttpUri,
+            "http.method" => Buffer::HttpMethod,
+            "http.host" => Buffer::HttpHost,
+            "http.user_agent" => Buffer::HttpUserAgent,
+            "http.header" => Buffer::HttpHeader,
+            "http.stat_code" => Buffer::HttpStatCode,
+            "dns.query" | "dns_query" => Buffer::DnsQuery,
+            "tls.sni" | "tls_sni" => Buffer::TlsSni,
+            _ => return None,
+        })
+    }
+
+    /// Content modifier moving the previous content to a buffer
+    fn modifier(name: &str) -> Option<Self> {
+        Some(match name {
+            "http_uri" | "http_raw_uri" => Buffer::HttpUri,
+            "http_method" => Buffer::HttpMethod,
+            "http_host" | "http_raw_host" => Buffer::HttpHost,
+            "http_user_agent" => Buffer::HttpUserAgent,
+            "http_header" | "http_raw_header" => Buffer::HttpHeader,
+            "http_stat_code" => Buffer::HttpStatCode,
+            _ => return None,
+        })
+    }
+
+    pub fn app(&self) -> Option<AppProto> {
+        match self {
+            Buffer::Packet => None,
+            Buffer::DnsQuery => Some(AppProto::Dns),
+            Buffer::TlsSni => Some(AppProto::Tls),
+            _ => Some(AppProto::Http),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Proto {
+    Ip,
+    Tcp,
+    Udp,
+    Icmp,
+    App(AppProto),
+}
+
+impl Proto {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_ascii_lowercase().as_str() {
+            "ip" | "pkthdr" => Proto::Ip,
+            "tcp" | "tcp-pkt" | "tcp-stream" => Proto::Tcp,
+            "udp" => Proto::Udp,
+            "icmp" => Proto::Icmp,
+            "http" => Proto::App(AppProto::Http),
+            "dns" => Proto::App(AppProto::Dns),
+            "tls" | "ssl" => Proto::App(AppProto::Tls),
+            _ => return None,
+        })
+    }
+
+    /// Whether packets of transport `proto` may carry the protocol
+    fn transport(&self, proto: IpProtocol) -> bool {
+        match self {
+            Proto::Ip => true,
+            Proto::Tcp | Proto::App(AppProto::Http) => proto == IpProtocol::Tcp,
+            Proto::Udp => proto == IpProtocol::Udp,
+            Proto::Icmp => matches!(proto, IpProtocol::Icmp | IpProtocol::Ipv6Icmp),
+            // DNS over TCP, TLS over QUIC
+            Proto::App(_) => matches!(proto, IpProtocol::Tcp | IpProtocol::Udp),
+        }
+    }
+}
+
+/// Network of an address list
+#[derive(Debug, Clone, Copy)]
+struct Net {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Net {
+    fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((a, p)) => (a.parse().ok()?, Some(p.parse().ok()?)),
+            None => (s.parse().ok()?, None),
+        };
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Net { addr, prefix })
+    }
+
+    fn contains(&self, a: IpAddr) -> bool {
+        match (self.addr, a) {
+            (IpAddr::V4(n), IpAddr::V4(a)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(n) & mask == u32::from(a) & mask
+            }
+            (IpAddr::V6(n), IpAddr::V6(a)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(n) & mask == u128::from(a) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Addresses of a rule header, everything when nothing is included
+#[derive(Debug, Clone, Default)]
+struct AddrSpec {
+    include: Vec<Net>,
+    exclude: Vec<Net>,
+}
+
+impl AddrSpec {
+    fn matches(&self, a: IpAddr) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|n| n.contains(a)))
+            && !self.exclude.iter().any(|n| n.contains(a))
+    }
+}
+
+/// Ports of a rule header, everything when nothing is included
+#[derive(Debug, Clone, Default)]
+struct PortSpec {
+    include: Vec<(u16, u16)>,
+    exclude: Vec<(u16, u16)>,
+}
+
+impl PortSpec {
+    fn matches(&self, p: u16) -> bool {
+        let within = |(lo, hi): &(u16, u16)| (*lo..=*hi).contains(&p);
+        (self.include.is_empty() || self.include.iter().any(within))
+            && !self.exclude.iter().any(within)
+    }
+}
+
+/// Split a list on the commas that are not nested in brackets
+fn split_list(s: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(s[start..].trim());
+    items
+}
+
+/// Parse an address or port list, `item` handling a single value
+fn parse_list<T>(
+    s: &str,
+    vars: &HashMap<String, String>,
+    negated: bool,
+    depth: usize,
+    out: &mut (Vec<T>, Vec<T>),
+    item: &dyn Fn(&str) -> Result<Vec<T>, String>,
+) -> Result<(), String> {
+    let s = s.trim();
+    if depth > MAX_VAR_DEPTH {
+        return Err(format!("variables nested too deep in {:?}", s));
+    }
+    if let Some(rest) = s.strip_prefix('!') {
+        return parse_list(rest, vars, !negated, depth, out, item);
+    }
+    if let Some(inner) = s.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+        for part in split_list(inner) {
+            parse_list(part, vars, negated, depth, out, item)?;
+        }
+        return Ok(());
+    }
+    if let Some(name) = s.strip_prefix('$') {
+        let value = vars
+            .get(name)
+            .ok_or(format!("unknown variable ${}", name))?;
+        return parse_list(value, vars, negated, depth + 1, out, item);
+    }
+    let values = item(s)?;
+    if negated {
+        out.1.extend(values);
+    } else {
+        out.0.extend(values);
+    }
+    Ok(())
+}
+
+fn parse_addrs(s: &str, vars: &HashMap<String, String>) -> Result<AddrSpec, String> {
+    let mut out = (Vec::new(), Vec::new());
+    parse_list(s, vars, false, 0, &mut out, &|v| match v {
+        "any" => Ok(vec![
+            Net::parse("0.0.0.0/0").expect("valid"),
+            Net::parse("::/0").expect("valid"),
+        ]),
+        _ => Net::parse(v)
+            .map(|n| vec![n])
+            .ok_or(format!("bad address {:?}", v)),
+    })?;
+    Ok(AddrSpec {
+        include: out.0,
+        exclude: out.1,
+    })
+}
+
+fn parse_ports(s: &str, vars: &HashMap<String, String>) -> Result<PortSpec, String> {
+    let mut out = (Vec::new(), Vec::new());
+    parse_list(s, vars, false, 0, &mut out, &|v| {
+        if v == "any" {
+            return Ok(vec![(0, u16::MAX)]);
+        }
+        let bad = || format!("bad port {:?}", v);
+        let port = |p: &str, default: u16| match p {
+            "" => Ok(default),
+            _ => p.parse().map_err(|_| bad()),
+        };
+        Ok(vec![match v.split_once(':') {
+            Some((lo, hi)) => (port(lo, 0)?, port(hi, u16::MAX)?),
+            None => (port(v, 0)?, port(v, 0)?),
+        }])
+    })?;
+    Ok(PortSpec {
+        include: out.0,
+        exclude: out.1,
+    })
+}
+
+/// A `content` and its modifiers
+#[derive(Debug, Clone)]
+struct Content {
+    pattern: Vec<u8>,
+    negated: bool,
+    nocase: bool,
+    offset: usize,
+    depth: Option<usize>,
+    distance: Option<i64>,
+    within: Option<usize>,
+    /// picked for the prefilter
+    fast_pattern: bool,
+}
+
+impl Content {
+    /// Where the pattern may be, given the end of the previous match
+    fn window(&self, prev_end: usize, len: usize) -> (usize, usize) {
+        let (start, end) = if self.distance.is_some() || self.within.is_some() {
+            let start = (prev_end as i64 + self.distance.unwrap_or(0)).max(0) as usize;
+            (start, self.within.map(|w| start.saturating_add(w)))
+        } else {
+            (
+                self.offset,
+                self.depth.map(|d| self.offset.saturating_add(d)),
+            )
+        };
+        (start.min(len), end.unwrap_or(len).min(len))
+    }
+
+    /// First occurrence starting at or after `from` and ending before `end`
+    fn find(&self, data: &[u8], from: usize, end: usize) -> Option<usize> {
+        let n = self.pattern.len();
+        if n == 0 || end < n {
+            return None;
+        }
+        (from..=end - n).find(|&i| {
+            let candidate = &data[i..i + n];
+            if self.nocase {
+                candidate.eq_ignore_ascii_case(&self.pattern)
+            } else {
+                candidate == self.pattern.as_slice()
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Test {
+    Content(Content),
+    Pcre {
+        regex: Regex,
+        negated: bool,
+        /// match after the previous content
+        relative: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct Match {
+    buffer: Buffer,
+    test: Test,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct FlowCheck {
+    established: Option<bool>,
+    to_server: Option<bool>,
+    /// only the stream, only packets
+    stream: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+enum Flowbit {
+    Set(String),
+    Unset(String),
+    Toggle(String),
+    /// names, all of them (`&`) or any (`|`)
+    IsSet(Vec<String>, bool),
+    IsNotSet(Vec<String>, bool),
+    NoAlert,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Dsize {
+    Eq(usize),
+    Lt(usize),
+    Gt(usize),
+    /// both bounds excluded
+    Between(usize, usize),
+}
+
+impl Dsize {
+    fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        Some(if let Some((lo, hi)) = s.split_once("<>") {
+            Dsize::Between(lo.trim().parse().ok()?, hi.trim().parse().ok()?)
+        } else if let Some(v) = s.strip_prefix('<') {
+            Dsize::Lt(v.trim().parse().ok()?)
+        } else if let Some(v) = s.strip_prefix('>') {
+            Dsize::Gt(v.trim().parse().ok()?)
+        } else {
+            Dsize::Eq(s.strip_prefix('=').unwrap_or(s).trim().parse().ok()?)
+        })
+    }
+
+    fn matches(&self, n: usize) -> bool {
+        match *self {
+            Dsize::Eq(v) => n == v,
+            Dsize::Lt(v) => n < v,
+            Dsize::Gt(v) => n > v,
+            Dsize::Between(lo, hi) => n > lo && n < hi,
+        }
+    }
+}
+
+/// A parsed rule
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// alert, log, pass, drop or reject
+    pub action: String,
+    proto: Proto,
+    src: AddrSpec,
+    src_ports: PortSpec,
+    dst: AddrSpec,
+    dst_ports: PortSpec,
+    bidirectional: bool,
+    pub gid: u32,
+    pub sid: u32,
+    pub rev: u32,
+    pub msg: String,
+    pub classtype: Option<String>,
+    pub priority: u8,
+    matches: Vec<Match>,
+    flow: FlowCheck,
+    flowbits: Vec<Flowbit>,
+    dsize: Option<Dsize>,
+}
+
+/// Split the options of a rule on the semicolons outside of quotes
+fn split_options(s: &str) -> Vec<String> {
+    let mut options = Vec::new();
+    let mut current = String::new();
+    let (mut quoted, mut escaped) = (false, false);
+    for c in s.chars() {
+        if escaped {
+            current.push(c);
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => {
+                current.push(c);
+                escaped = true;
+            }
+            '"' => {
+                current.push(c);
+                quoted = !quoted;
+            }
+            ';' if !quoted => options.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    options.push(current);
+    options
+        .into_iter()
+        .map(|o| o.trim().to_string())
+        .filter(|o| !o.is_empty())
+        .collect()
+}
+
+/// Text of a quoted value, escapes removed
+fn unquote(s: &str) -> String {
+    let s = s.trim();
+    let s = s
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(s);
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Bytes of a quoted content
+fn content_bytes(s: &str) -> Result<Vec<u8>, String> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or(format!("content not quoted: {}", s))?;
+    matcher::parse_bytes(s)
+}
+
+/// `/regex/flags` of a pcre, with the buffer named by the flags if any
+fn pcre(value: &str) -> Result<(Regex, bool, Option<Buffer>), String> {
+    // escapes belong to the regex, only the quotes go
+    let s = value.trim();
+    let s = s
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(s);
+    let body = s.strip_prefix('/').ok_or(format!("bad pcre {:?}", s))?;
+    let end = body.rfind('/').ok_or(format!("bad pcre {:?}", s))?;
+    let (pattern, flags) = (&body[..end], &body[end + 1..]);
+    let source = if flags.contains('A') {
+        // anchored at the start of the buffer
+        format!("^(?:{})", pattern)
+    } else {
+        pattern.to_string()
+    };
+    let mut builder = RegexBuilder::new(&source);
+    builder.unicode(false);
+    let (mut relative, mut buffer) = (false, None);
+    for f in flags.chars() {
+        match f {
+            'i' => {
+                builder.case_insensitive(true);
+            }
+            's' => {
+                builder.dot_matches_new_line(true);
+            }
+            'm' => {
+                builder.multi_line(true);
+            }
+            'x' => {
+                builder.ignore_whitespace(true);
+            }
+            'R' => relative = true,
+            'U' | 'I' => buffer = Some(Buffer::HttpUri),
+            'H' | 'D' => buffer = Some(Buffer::HttpHeader),
+            'M' => buffer = Some(Buffer::HttpMethod),
+            'W' => buffer = Some(Buffer::HttpHost),
+            'V' => buffer = Some(Buffer::HttpUserAgent),
+            'S' => buffer = Some(Buffer::HttpStatCode),
+            // rawbytes
+            'B' | 'A' => {}
+            _ => return Err(format!("unsupported pcre flag {:?}", f)),
+        }
+    }
+    let regex = builder
+        .build()
+        .map_err(|e| format!("pcre {:?}: {}", pattern, e))?;
+    Ok((regex, relative, buffer))
+}
+
+fn number<T: std::str::FromStr>(keyword: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("bad {} value {:?}", keyword, value))
+}
+
+impl Rule {
+    /// Parse a rule line
+    pub fn parse(line: &str, vars: &HashMap<String, String>) -> Result<Self, String> {
+        let open = line.find('(').ok_or("missing options")?;
+        let close = line.rfind(')').ok_or("missing closing parenthesis")?;
+        if close < open {
+            return Err("missing options".to_string());
+        }
+        let header: Vec<&str> = line[..open].split_whitespace().collect();
+        let [action, proto, src, src_ports, direction, dst, dst_ports] = header[..] else {
+            return Err(format!("bad rule header {:?}", line[..open].trim()));
+        };
+        if !matches!(action, "alert" | "log" | "pass" | "drop" | "reject") {
+            return Err(format!("unknown action {:?}", action));
+        }
+        let bidirectional = match direction {
+            "->" => false,
+            "<>" => true,
+            _ => return Err(format!("bad direction {:?}", direction)),
+        };
+        let mut rule = Rule {
+            action: action.to_string(),
+            proto: Proto::parse(proto).ok_or(format!("unsupported protocol {:?}", proto))?,
+            src: parse_addrs(src, vars)?,
+            src_ports: parse_ports(src_ports, vars)?,
+            dst: parse_addrs(dst, vars)?,
+            dst_ports: parse_ports(dst_ports, vars)?,
+            bidirectional,
+            gid: 1,
+            sid: 0,
+            rev: 0,
+            msg: String::new(),
+            classtype: None,
+            priority: 3,
+            matches: Vec::new(),
+            flow: FlowCheck::default(),
+            flowbits: Vec::new(),
+            dsize: None,
+        };
+        let mut buffer = Buffer::Packet;
+        for option in split_options(&line[open + 1..close]) {
+            let (keyword, value) = match option.split_once(':') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => (option.as_str(), ""),
+            };
+            rule.option(keyword, value, &mut buffer)?;
+        }
+        if rule.sid == 0 {
+            return Err("missing sid".to_string());
+        }
+        let apps: HashSet<AppProto> = rule.matches.iter().filter_map(|m| m.buffer.app()).collect();
+        if apps.len() > 1 || (!apps.is_empty() && rule.uses(Buffer::Packet)) {
+            return Err("buffers of different protocols in one rule".to_string());
+        }
+        Ok(rule)
+    }
+
+    /// Apply an option, `buffer` being the current sticky buffer
+    fn option(&mut self, keyword: &str, value: &str, buffer: &mut Buffer) -> Result<(), String> {
+        fn last_content<'a>(rule: &'a mut Rule, keyword: &str) -> Result<&'a mut Content, String> {
+            match rule.matches.last_mut() {
+                Some(Match {
+                    test: Test::Content(c),
+                    ..
+                }) => Ok(c),
+                _ => Err(format!("{} without a content", keyword)),
+            }
+        }
+        if let Some(b) = Buffer::sticky(keyword) {
+            *buffer = b;
+            return Ok(());
+        }
+        if let Some(b) = Buffer::modifier(keyword) {
+            last_content(self, keyword)?;
+            if let Some(m) = self.matches.last_mut() {
+                m.buffer = b;
+            }
+            return Ok(());
+        }
+        match keyword {
+            "msg" => self.msg = unquote(value),
+            "sid" => self.sid = number(keyword, value)?,
+            "rev" => self.rev = number(keyword, value)?,
+            "gid" => self.gid = number(keyword, value)?,
+            "classtype" => self.classtype = Some(value.to_string()),
+            "priority" => self.priority = number(keyword, value)?,
+            "content" => {
+                let (negated, quoted) = match value.strip_prefix('!') {
+                    Some(v) => (true, v),
+                    None => (false, value),
+                };
+                self.matches.push(Match {
+                    buffer: *buffer,
+                    test: Test::Content(Content {
+                        pattern: content_bytes(quoted)?,
+                        negated,
+                        nocase: false,
+                        offset: 0,
+                        depth: None,
+                        distance: None,
+                        within: None,
+                        fast_pattern: false,
+                    }),
+                });
+            }
+            "nocase" => last_content(self, keyword)?.nocase = true,
+            "offset" => last_content(self, keyword)?.offset = number(keyword, value)?,
+            "depth" => last_content(self, keyword)?.depth = Some(number(keyword, value)?),
+            "distance" => last_content(self, keyword)?.distance = Some(number(keyword, value)?),
+            "within" => last_content(self, keyword)?.within = Some(number(keyword, value)?),
+            // the value ("only", "<offset>,<length>") only tunes the prefilter
+            "fast_pattern" => last_content(self, keyword)?.fast_pattern = true,
+            "pcre" => {
+                let (negated, quoted) = match value.strip_prefix('!') {
+                    Some(v) => (true, v),
+                    None => (false, value),
+                };
+                let (regex, relative, flag_buffer) = pcre(quoted)?;
+                self.matches.push(Match {
+                    buffer: flag_buffer.unwrap_or(*buffer),
+                    test: Test::Pcre {
+                        regex,
+                        negated,
+                        relative,
+                    },
+                });
+            }
+            "flow" => {
+                for f in value.split(',').map(str::trim) {
+                    match f {
+                        "established" => self.flow.established = Some(true),
+                        "not_established" => self.flow.established = Some(false),
+                        "to_server" | "from_client" => self.flow.to_server = Some(true),
+                        "to_client" | "from_server" => self.flow.to_server = Some(false),
+                        "only_stream" => self.flow.stream = Some(true),
+                        "no_stream" => self.flow.stream = Some(false),
+                        "stateless" => {}
+                        _ => return Err(format!("unsupported flow option {:?}", f)),
+                    }
+                }
+            }
+            "flowbits" => {
+                let (op, names) = value.split_once(',').unwrap_or((value, ""));
+                let names = names.trim();
+                let list = |sep: char| names.split(sep).map(|n| n.trim().to_string()).collect();
+                let all = !names.contains('|');
+                let name = || {
+                    if names.is_empty() {
+                        Err(format!("flowbits {} without a name", op))
+                    } else {
+                        Ok(names.to_string())
+                    }
+                };
+                self.flowbits.push(match op.trim() {
+                    "set" => Flowbit::Set(name()?),
+                    "unset" => Flowbit::Unset(name()?),
+                    "toggle" => Flowbit::Toggle(name()?),
+                    "isset" => Flowbit::IsSet(list(if all { '&' } else { '|' }), all),
+                    "isnotset" => Flowbit::IsNotSet(list(if all { '&' } else { '|' }), all),
+                    "noalert" => Flowbit::NoAlert,
+                    op => return Err(format!("unsupported flowbits operation {:?}", op)),
+                });
+            }
+            "dsize" => {
+                self.dsize = Some(Dsize::parse(value).ok_or(format!("bad dsize {:?}", value))?)
+            }
+            _ if IGNORED_KEYWORDS.contains(&keyword) => {}
+            _ => return Err(format!("unsupported keyword {:?}", keyword)),
+        }
+        Ok(())
+    }
+
+    /// Content that must be in the input for the rule to match: the one marked
+    /// `fast_pattern`, else the longest
+    fn fast_pattern(&self) -> Option<(Buffer, &Content)> {
+        let positive = self.matches.iter().filter_map(|m| match &m.test {
+            Test::Content(c) if !c.negated => Some((m.buffer, c)),
+            _ => None,
+        });
+        positive
+            .clone()
+            .find(|(_, c)| c.fast_pattern)
+            .or_else(|| positive.max_by_key(|(_, c)| c.pattern.len()))
+    }
+
+    fn uses(&self, buffer: Buffer) -> bool {
+        self.matches.iter().any(|m| m.buffer == buffer)
+    }
+
+    /// Application layer of the sticky buffers of the rule
+    fn app(&self) -> Option<AppProto> {
+        self.matches.iter().find_map(|m| m.buffer.app())
+    }
+
+    /// Whether the rule runs on reassembled TCP streams rather than on packets
+    fn on_stream(&self) -> bool {
+        self.proto.transport(IpProtocol::Tcp)
+            && self.uses(Buffer::Packet)
+            && self.dsize.is_none()
+            && self.flow.stream != Some(false)
+    }
+
+    fn header_matches(&self, flow: &FlowKey) -> bool {
+        let forward = |f: &FlowKey| {
+            self.src.matches(f.src)
+                && self.src_ports.matches(f.src_port)
+                && self.dst.matches(f.dst)
+                && self.dst_ports.matches(f.dst_port)
+        };
+        forward(flow) || (self.bidirectional && forward(&flow.reversed()))
+    }
+
+    /// Whether the rule runs on this input, before looking at the payload
+    fn applies(&self, input: &Input) -> bool {
+        let stage = match (self.app(), input.app) {
+            (Some(rule), Some(app)) => rule == app,
+            (None, None) => self.on_stream() == input.stream,
+            _ => false,
+        };
+        stage
+            && match (self.proto, input.app) {
+                (Proto::App(p), Some(app)) => p == app,
+                _ => self.proto.transport(input.flow.proto),
+            }
+            && self.header_matches(&input.flow)
+            && self.flow.established.is_none_or(|e| e == input.established)
+            && self.flow.to_server.is_none_or(|t| t == input.to_server)
+            && self
+                .dsize
+                .is_none_or(|d| input.dsize.is_some_and(|n| d.matches(n)))
+    }
+
+    /// Whether the contents and pcres match the buffers of the input
+    fn payload_matches(&self, input: &Input) -> bool {
+        let mut buffers: Vec<Buffer> = Vec::new();
+        for m in &self.matches {
+            if !buffers.contains(&m.buffer) {
+                buffers.push(m.buffer);
+            }
+        }
+        buffers.into_iter().all(|buffer| {
+            let data = match input.buffers.iter().find(|(b, _)| *b == buffer) {
+                Some((_, data)) => *data,
+                None => return false,
+            };
+            let tests: Vec<&Test> = self
+                .matches
+                .iter()
+                .filter(|m| m.buffer == buffer)
+                .map(|m| &m.test)
+                .collect();
+            let mut budget = MATCH_BUDGET;
+            eval(&tests, data, 0, &mut budget)
+        })
+    }
+
+    fn flowbits_match(&self, bits: &HashSet<String>) -> bool {
+        self.flowbits.iter().all(|f| match f {
+            Flowbit::IsSet(names, true) => names.iter().all(|n| bits.contains(n)),
+            Flowbit::IsSet(names, false) => names.iter().any(|n| bits.contains(n)),
+            Flowbit::IsNotSet(names, true) => names.iter().all(|n| !bits.contains(n)),
+            Flowbit::IsNotSet(names, false) => names.iter().any(|n| !bits.contains(n)),
+            _ => true,
+        })
+    }
+
+    /// Apply the flowbits operations of a matching rule, whether it should alert
+    fn apply_flowbits(&self, bits: &mut HashSet<String>) -> bool {
+        let mut alert = true;
+        for f in &self.flowbits {
+            match f {
+                Flowbit::Set(n) => {
+                    bits.insert(n.clone());
+                }
+                Flowbit::Unset(n) => {
+                    bits.remove(n);
+                }
+                Flowbit::Toggle(n) if !bits.remove(n) => {
+                    bits.insert(n.clone());
+                }
+                Flowbit::NoAlert => alert = false,
+                _ => {}
+            }
+        }
+        alert
+    }
+}
+
+/// Match the tests of one buffer in order from `prev_end`, trying every position of a
+/// content before giving up (a later relative content may only match after another one)
+fn eval(tests: &[&Test], data: &[u8], prev_end: usize, budget: &mut usize) -> bool {
+    let (test, rest) = match tests.split_first() {
+        Some(t) => t,
+        None => return true,
+    };
+    match test {
+        Test::Content(c) => {
+            let (start, end) = c.window(prev_end, data.len());
+            if c.negated {
+                return c.find(data, start, end).is_none() && eval(rest, data, prev_end, budget);
+            }
+            let mut from = start;
+            while let Some(pos) = c.find(data, from, end) {
+                if *budget == 0 {
+                    return false;
+                }
+                *budget -= 1;
+                if eval(rest, data, pos + c.pattern.len(), budget) {
+                    return true;
+                }
+                from = pos + 1;
+            }
+            false
+        }
+        Test::Pcre {
+            regex,
+            negated,
+            relative,
+        } => {
+            let from = if *relative {
+                prev_end.min(data.len())
+            } else {
+                0
+            };
+            match (regex.find(&data[from..]), negated) {
+                (Some(_), true) => false,
+                (None, true) => eval(rest, data, prev_end, budget),
+                (Some(m), false) => eval(rest, data, from + m.end(), budget),
+                (None, false) => false,
+            }
+        }
+    }
+}
+
+/// Fast patterns of the rules inspecting a buffer
+#[derive(Debug, Clone)]
+struct Prefilter {
+    buffer: Buffer,
+    matcher: Matcher,
+    /// rule of each pattern
+    rules: Vec<usize>,
+}
+
+/// Rules of a rules file
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    /// rules that could not be loaded, with the reason
+    pub skipped: Vec<String>,
+    prefilters: Vec<Prefilter>,
+    /// rules only evaluated when their fast pattern was found
+    gated: Vec<bool>,
+}
+
+impl RuleSet {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Parse a rules file: one rule per line (a trailing `\` continues it), `#` comments,
+    /// and `ipvar`/`portvar` lines defining variables
+    pub fn parse(text: &str) -> Self {
+        let mut vars: HashMap<String, String> = DEFAULT_VARS
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let mut set = RuleSet::default();
+        let mut pending = String::new();
+        let mut first_line = 0;
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if pending.is_empty() {
+                first_line = n + 1;
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+            }
+            if let Some(start) = line.strip_suffix('\\') {
+                pending.push_str(start);
+                continue;
+            }
+            pending.push_str(line);
+            let line = std::mem::take(&mut pending);
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("ipvar" | "portvar" | "var") => match (words.next(), words.next()) {
+                    (Some(name), Some(value)) => {
+                        vars.insert(name.to_string(), value.to_string());
+                    }
+                    _ => set
+                        .skipped
+                        .push(format!("line {}: bad variable {:?}", first_line, line)),
+                },
+                _ => match Rule::parse(&line, &vars) {
+                    Ok(rule) => set.rules.push(rule),
+                    Err(e) => set.skipped.push(format!("line {}: {}", first_line, e)),
+                },
+            }
+        }
+        // pass rules take precedence over the others
+        set.rules.sort_by_key(|r| r.action != "pass");
+        set.build_prefilters();
+        set
+    }
+
+    /// Compile the fast patterns of the rules, one matcher per buffer
+    fn build_prefilters(&mut self) {
+        let mut patterns: Vec<(Buffer, Vec<Pattern>, Vec<usize>)> = Vec::new();
+        self.gated = vec![false; self.rules.len()];
+        for (i, rule) in self.rules.iter().enumerate() {
+            let (buffer, content) = match rule.fast_pattern() {
+                Some(f) => f,
+                None => continue,
+            };
+            let at = match patterns.iter().position(|(b, _, _)| *b == buffer) {
+                Some(at) => at,
+                None => {
+                    patterns.push((buffer, Vec::new(), Vec::new()));
+                    patterns.len() - 1
+                }
+            };
+            patterns[at]
+                .1
+                .push(Pattern::literal(&content.pattern, content.nocase));
+            patterns[at].2.push(i);
+            self.gated[i] = true;
+        }
+        self.prefilters = patterns
+            .into_iter()
+            .map(|(buffer, patterns, rules)| Prefilter {
+                buffer,
+                matcher: Matcher::new(patterns),
+                rules,
+            })
+            .collect();
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Rules worth evaluating on the buffers: the fast pattern of a gated rule occurs in
+    /// its buffer
+    fn candidates(&self, buffers: &[(Buffer, &[u8])]) -> Vec<bool> {
+        let mut hit: Vec<bool> = self.gated.iter().map(|g| !g).collect();
+        for p in &self.prefilters {
+            if let Some((_, data)) = buffers.iter().find(|(b, _)| *b == p.buffer) {
+                for m in p.matcher.find_iter(data) {
+                    hit[p.rules[m.pattern]] = true;
+                }
+            }
+        }
+        hit
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+/// EVE `alert` object
+#[derive(Debug, Clone, Serialize)]
+pub struct AlertInfo {
+    /// "allowed": the engine only watches
+    pub action: &'static str,
+    pub gid: u32,
+    pub signature_id: u32,
+    pub rev: u32,
+    pub signature: String,
+    pub category: String,
+    pub severity: u8,
+}
+
+/// A rule matched, in the shape of an EVE JSON alert
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub timestamp: String,
+    /// same for both directions of a flow
+    pub flow_id: u64,
+    pub src_ip: IpAddr,
+    pub src_port: u16,
+    pub dest_ip: IpAddr,
+    pub dest_port: u16,
+    pub proto: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app_proto: Option<AppProto>,
+    pub alert: AlertInfo,
+}
+
+/// What the rules are matched against
+struct Input<'a> {
+    /// oriented in the direction of the data
+    flow: FlowKey,
+    to_server: bool,
+    established: bool,
+    stream: bool,
+    app: Option<AppProto>,
+    buffers: &'a [(Buffer, &'a [u8])],
+    /// payload size of a packet
+    dsize: Option<usize>,
+}
+
+#[derive(Default)]
+struct FlowState {
+    client: Option<Direction>,
+    syn_ack: bool,
+    established: bool,
+    seen: [bool; 2],
+    bits: HashSet<String>,
+    /// last bytes of each direction of the stream
+    tail: [Vec<u8>; 2],
+}
+
+#[derive(Default)]
+struct State {
+    rules: Option<Arc<RuleSet>>,
+    flows: HashMap<FlowKey, FlowState>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+/// Replace the rules of the current thread; threads share them
+pub fn set_rules(rules: Arc<RuleSet>) {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.rules = Some(rules);
+        s.flows.clear();
+    });
+}
+
+/// Whether rules are loaded
+pub fn enabled() -> bool {
+    STATE.with(|s| s.borrow().rules.as_ref().is_some_and(|r| !r.is_empty()))
+}
+
+fn index(d: Direction) -> usize {
+    match d {
+        Direction::Forward => 0,
+        Direction::Reverse => 1,
+    }
+}
+
+fn timestamp() -> String {
+    let d = context::time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let date = timestamp::format_time(d.as_secs() as i64);
+    format!(
+        "{}.{:06}+0000",
+        date.trim_end_matches('Z'),
+        d.subsec_micros()
+    )
+}
+
+fn alert(rule: &Rule, input: &Input) {
+    let flow = &input.flow;
+    let category = rule.classtype.clone().unwrap_or_default();
+    println!(
+        "[**] [{}:{}:{}] {} [**] [Classification: {}] [Priority: {}] {}",
+        rule.gid, rule.sid, rule.rev, rule.msg, category, rule.priority, flow
+    );
+    event::emit(Event::Alert(Alert {
+        timestamp: timestamp(),
+        flow_id: flow.id(),
+        src_ip: flow.src,
+        src_port: flow.src_port,
+        dest_ip: flow.dst,
+        dest_port: flow.dst_port,
+        proto: flow.proto.to_string(),
+        app_proto: input.app,
+        alert: AlertInfo {
+            action: "allowed",
+            gid: rule.gid,
+            signature_id: rule.sid,
+            rev: rule.rev,
+            signature: rule.msg.clone(),
+            category,
+            severity: rule.priority,
+        },
+    }));
+}
+
+/// Run the rules on an input, `fired` filtering the rules that matched
+fn inspect(input: &Input, fired: &dyn Fn(&Rule, &HashSet<String>) -> bool) {
+    let rules = match STATE.with(|s| s.borrow().rules.clone()) {
+        Some(r) => r,
+        None => return,
+    };
+    let key = input.flow.canonical().0;
+    let candidates = rules.candidates(input.buffers);
+    for (rule, _) in rules.rules.iter().zip(candidates).filter(|(_, c)| *c) {
+        if !rule.applies(input) {
+            continue;
+        }
+        let matched = STATE.with(|s| {
+            let s = s.borrow();
+            let empty = HashSet::new();
+            let bits = s.flows.get(&key).map_or(&empty, |f| &f.bits);
+            rule.flowbits_match(bits) && rule.payload_matches(input) && fired(rule, bits)
+        });
+        if !matched {
+            continue;
+        }
+        if rule.action == "pass" {
+            return;
+        }
+        let alerting = STATE.with(|s| {
+            let mut s = s.borrow_mut();
+            let state = s.flows.entry(key).or_default();
+            rule.apply_flowbits(&mut state.bits)
+        });
+        if alerting {
+            alert(rule, input);
+        }
+    }
+}
+
+/// Update the state of the flow of a packet, returning (to_server, established)
+fn track(flow: &FlowKey, tcp_flags: Option<u8>) -> (bool, bool) {
+    let (key, dir) = flow.canonical();
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.flows.len() >= MAX_FLOWS && !s.flows.contains_key(&key) {
+            s.flows.clear();
+        }
+        let state = s.flows.entry(key).or_default();
+        let opposite = match dir {
+            Direction::Forward => Direction::Reverse,
+            Direction::Reverse => Direction::Forward,
+        };
+        let client = *state.client.get_or_insert(match tcp_flags {
+            Some(f) if f & FLAGS::SYN != 0 && f & FLAGS::ACK == 0 => dir,
+            Some(f) if f & FLAGS::SYN != 0 => opposite,
+            // picked up in the middle: the client likely uses the higher port, and the
+            // handshake was missed rather than absent
+            Some(_) => {
+                state.established = true;
+                if flow.src_port >= flow.dst_port {
+                    dir
+                } else {
+                    opposite
+                }
+            }
+            // the first sender of a datagram flow is the client
+            None => dir,
+        });
+        match tcp_flags {
+            Some(f) if f & FLAGS::SYN != 0 && f & FLAGS::ACK != 0 => state.syn_ack = true,
+            Some(f) if f & FLAGS::ACK != 0 && state.syn_ack && dir == client => {
+                state.established = true
+            }
+            Some(_) => {}
+            None => {
+                state.seen[index(dir)] = true;
+                state.established = state.seen[0] && state.seen[1];
+            }
+        }
+        (dir == client, state.established)
+    })
+}
+
+/// Run the packet rules on the transport payload of the packet being decoded
+pub fn on_packet(proto: IpProtocol, payload: &[u8], tcp_flags: Option<u8>) {
+    if !enabled() {
+        return;
+    }
+    // ICMP has no ports
+    let flow = context::flow()
+        .filter(|f| f.proto == proto)
+        .or_else(|| context::addrs().map(|(src, dst)| FlowKey::new(proto, src, 0, dst, 0)));
+    let flow = match flow {
+        Some(f) => f,
+        None => return,
+    };
+    let (to_server, established) = track(&flow, tcp_flags);
+    let buffers = [(Buffer::Packet, payload)];
+    let input = Input {
+        flow,
+        to_server,
+        established,
+        stream: false,
+        app: None,
+        buffers: &buffers,
+        dsize: Some(payload.len()),
+    };
+    inspect(&input, &|_, _| true);
+}
+
+/// Run the stream rules on reassembled bytes, `flow` going from the client to the server
+///
+/// The bytes are matched along with the end of what the direction already carried, a rule
+/// fires when it needs the new bytes to match.
+pub fn on_stream(flow: &FlowKey, from_client: bool, data: &[u8]) {
+    if !enabled() {
+        return;
+    }
+    let (key, client) = flow.canonical();
+    let side = if from_client {
+        index(client)
+    } else {
+        1 - index(client)
+    };
+    let tail = STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.flows
+            .get_mut(&key)
+            .map(|f| std::mem::take(&mut f.tail[side]))
+            .unwrap_or_default()
+    });
+    let mut window = tail;
+    let seen = window.len();
+    window.extend_from_slice(data);
+    let (head, _) = window.split_at(seen);
+    let tail_buffers = [(Buffer::Packet, head)];
+    let buffers = [(Buffer::Packet, window.as_slice())];
+    let input = Input {
+        flow: if from_client { *flow } else { flow.reversed() },
+        to_server: from_client,
+        established: true,
+        stream: true,
+        app: None,
+        buffers: &buffers,
+        dsize: None,
+    };
+    // the window without the new data, where a match was already reported
+    let tail_input = Input {
+        buffers: &tail_buffers,
+        ..input
+    };
+    inspect(&input, &|rule, _| {
+        seen == 0 || !rule.payload_matches(&tail_input)
+    });
+    let keep = window.len().saturating_sub(STREAM_WINDOW);
+    window.drain(..keep);
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        if let Some(f) = s.flows.get_mut(&key) {
+            f.tail[side] = window;
+        }
+    });
+}
+
+/// Run the HTTP rules on a request and its response
+pub fn on_http(exchange: &Exchange) {
+    if !enabled() {
+        return;
+    }
+    let header: String = exchange
+        .request_headers
+        .0
+        .iter()
+        .filter(|(n, _)| !n.starts_with(':'))
+        .map(|(n, v)| format!("{}: {}\r\n", n, v))
+        .collect();
+    let status = exchange.status.map(|s| s.to_string());
+    let mut buffers = vec![
+        (Buffer::HttpUri, exchange.uri.as_bytes()),
+        (Buffer::HttpMethod, exchange.method.as_bytes()),
+        (Buffer::HttpHeader, header.as_bytes()),
+    ];
+    if let Some(host) = &exchange.host {
+        buffers.push((Buffer::HttpHost, host.as_bytes()));
+    }
+    if let Some(ua) = &exchange.user_agent {
+        buffers.push((Buffer::HttpUserAgent, ua.as_bytes()));
+    }
+    if let Some(status) = &status {
+        buffers.push((Buffer::HttpStatCode, status.as_bytes()));
+    }
+    app_inspect(&exchange.flow, AppProto::Http, &buffers);
+}
+
+/// Run the DNS rules on a query name of the packet being decoded
+pub fn on_dns_query(name: &str) {
+    if !enabled() {
+        return;
+    }
+    if let Some(flow) = context::flow() {
+        app_inspect(&flow, AppProto::Dns, &[(Buffer::DnsQuery, name.as_bytes())]);
+    }
+}
+
+/// Run the TLS rules on the SNI of a ClientHello, `flow` going from the client to the server
+pub fn on_tls_sni(flow: &FlowKey, sni: &str) {
+    if enabled() {
+        app_inspect(flow, AppProto::Tls, &[(Buffer::TlsSni, sni.as_bytes())]);
+    }
+}
+
+fn app_inspect(flow: &FlowKey, app: AppProto, buffers: &[(Buffer, &[u8])]) {
+    let input = Input {
+        flow: *flow,
+        to_server: true,
+        established: true,
+        stream: false,
+        app: Some(app),
+        buffers,
+        dsize: None,
+    };
+    inspect(&input, &|_, _| true);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> HashMap<String, String> {
+        DEFAULT_VARS
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn rule(options: &str) -> Rule {
+        let line = format!("alert tcp any any -> any any ({} sid:1;)", options);
+        Rule::parse(&line, &vars()).unwrap()
+    }
+
+    fn flow(src: &str, src_port: u16, dst: &str, dst_port: u16) -> FlowKey {
+        FlowKey::new(
+            IpProtocol::Tcp,
+            src.parse().unwrap(),
+            src_port,
+            dst.parse().unwrap(),
+            dst_port,
+        )
+    }
+
+    fn payload_matches(rule: &Rule, data: &[u8]) -> bool {
+        let buffers = [(Buffer::Packet, data)];
+        let input = Input {
+            flow: flow("10.0.0.1", 40000, "192.0.2.1", 80),
+            to_server: true,
+            established: true,
+            stream: true,
+            app: None,
+            buffers: &buffers,
+            dsize: None,
+        };
+        rule.payload_matches(&input)
+    }
+
+    #[test]
+    fn header_and_options() {
+        let r = Rule::parse(
+            "alert tcp $HOME_NET any -> !10.0.0.0/8 [$HTTP_PORTS,443] \
+             (msg:\"a \\\"quoted\\\" message\"; content:\"GET\"; sid:1000; rev:2; \
+             classtype:trojan-activity; priority:1;)",
+            &vars(),
+        )
+        .unwrap();
+        assert_eq!((r.sid, r.rev, r.gid, r.priority), (1000, 2, 1, 1));
+        assert_eq!(r.msg, "a \"quoted\" message");
+        assert_eq!(r.classtype.as_deref(), Some("trojan-activity"));
+        assert!(r.header_matches(&flow("192.168.1.2", 5000, "198.51.100.7", 8080)));
+        assert!(!r.header_matches(&flow("192.168.1.2", 5000, "10.1.1.1", 8080)));
+        assert!(!r.header_matches(&flow("198.51.100.7", 5000, "192.168.1.2", 8080)));
+        assert!(!r.header_matches(&flow("192.168.1.2", 5000, "198.51.100.7", 22)));
+        // `<>` matches both ways
+        let both = Rule::parse("alert tcp 10.0.0.1 any <> any 22 (sid:2;)", &vars()).unwrap();
+        assert!(both.header_matches(&flow("10.0.0.1", 5000, "10.0.0.2", 22)));
+        assert!(both.header_matches(&flow("10.0.0.2", 22, "10.0.0.1", 5000)));
+        assert!(!both.header_matches(&flow("10.0.0.3", 5000, "10.0.0.2", 22)));
+    }
+
+    #[test]
+    fn rejected_rules() {
+        for (line, error) in [
+            ("alert tcp any any -> any any (msg:\"x\";)", "missing sid"),
+            (
+                "alert tcp any any -> any any (content:\"x\"; isdataat:1; sid:1;)",
+                "unsupported keyword \"isdataat\"",
+            ),
+            (
+                "alert tcp any any => any any (sid:1;)",
+                "bad direction \"=>\"",
+            ),
+            (
+                "alert tcp $NOPE any -> any any (sid:1;)",
+                "unknown variable $NOPE",
+            ),
+            (
+                "alert tcp any any -> any any (http.uri; content:\"a\"; dns.query; \
+                 content:\"b\"; sid:1;)",
+                "buffers of different protocols in one rule",
+            ),
+            (
+                "alert tcp any any -> any any (nocase; sid:1;)",
+                "nocase without a content",
+            ),
+        ] {
+            assert_eq!(Rule::parse(line, &vars()).unwrap_err(), error, "{}", line);
+        }
+    }
+
+    #[test]
+    fn rules_file() {
+        let set = RuleSet::parse(
+            "# comment\n\
+             ipvar SERVERS [192.0.2.0/24]\n\
+             alert tcp any any -> $SERVERS 80 (content:\"a\"; \\\n\
+             sid:1;)\n\
+             \n\
+             alert tcp any any -> any any (bogus; sid:2;)\n\
+             pass tcp any any -> any any (sid:3;)\n",
+        );
+        assert_eq!(set.len(), 2);
+        assert_eq!(set.skipped, ["line 6: unsupported keyword \"bogus\""]);
+        // pass rules come first
+        assert_eq!(set.rules()[0].sid, 3);
+        assert!(set.rules()[1].header_matches(&flow("10.0.0.1", 1, "192.0.2.9", 80)));
+    }
+
+    #[test]
+    fn contents() {
+        let r = rule("content:\"GET\"; depth:3; content:\"/admin\"; distance:1; within:6;");
+        assert!(payload_matches(&r, b"GET /admin HTTP/1.1"));
+        assert!(!payload_matches(&r, b" GET /admin"));
+        assert!(!payload_matches(&r, b"GET  /admin"));
+
+        let r = rule("content:\"user\"; nocase; content:!\"anonymous\";");
+        assert!(payload_matches(&r, b"USER root"));
+        assert!(!payload_matches(&r, b"USER anonymous"));
+
+        let r = rule("content:\"|de ad|be|ef|\"; offset:2;");
+        assert!(payload_matches(&r, b"xx\xde\xadbe\xef"));
+        assert!(!payload_matches(&r, b"\xde\xadbe\xefxx"));
+    }
+
+    #[test]
+    fn later_occurrence_of_a_relative_content() {
+        // the first "a" is not followed by "b", the second is
+        let r = rule("content:\"a\"; content:\"b\"; distance:0; within:1;");
+        assert!(payload_matches(&r, b"a-ab"));
+        assert!(!payload_matches(&r, b"a-a-b"));
+    }
+
+    #[test]
+    fn pcre() {
+        let r = rule("content:\"id=\"; pcre:\"/^\\d{4}\\b/R\";");
+        assert!(payload_matches(&r, b"GET /?id=1234&x"));
+        assert!(!payload_matches(&r, b"GET /?id=12345"));
+        let r = rule("pcre:!\"/passw(or)?d/i\";");
+        assert!(payload_matches(&r, b"user=x"));
+        assert!(!payload_matches(&r, b"PASSWD=x"));
+    }
+
+    #[test]
+    fn dsize_and_flowbits() {
+        let r = rule("dsize:10<>20;");
+        let d = r.dsize.unwrap();
+        assert!(!d.matches(10) && d.matches(11) && d.matches(19) && !d.matches(20));
+
+        let set = rule("flowbits:set,login; flowbits:noalert;");
+        let check = rule("flowbits:isset,login&admin;");
+        let mut bits = HashSet::new();
+        assert!(!check.flowbits_match(&bits));
+        assert!(!set.apply_flowbits(&mut bits));
+        bits.insert("admin".to_string());
+        assert!(check.flowbits_match(&bits));
+    }
+}
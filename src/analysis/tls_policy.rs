@@ -0,0 +1,476 @@
+//! TLS version and cipher suite policy
+//!
+//! Every negotiated session is checked against a policy: a minimum protocol version and
+//! a list of denied cipher suites or cipher families (RC4, 3DES, EXPORT, NULL, ...).
+//! Downgrade indicators are reported as well: a ClientHello carrying TLS_FALLBACK_SCSV,
+//! a client retrying a server with a lower version shortly after a first hello, and
+//! SSL 2.0 hellos.
+//!
+//! A session negotiating a suite id missing from the IANA registry is reported as well,
+//! whatever the policy: the families of such a suite cannot be checked.
+//!
+//! Violations are emitted as events when they happen and counted per server (address and
+//! port) and per client (address) for the report produced by `flush`. Threads decoding
+//! apart take their counts with `take_report`, merged and printed once with `report`.
+//!
+//! Policy file, one rule per line:
+//! ```text
+//! # lowest version a session may negotiate
+//! min_version TLS1.2
+//! # cipher family (a component of the IANA name) or suite id
+//! deny_cipher RC4
+//! deny_cipher 0x000a
+//! ```
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::context;
+use crate::event::{self, Event};
+use crate::flow::FlowKey;
+use crate::protocols::ssl::ClientHelloV2;
+use crate::protocols::tls::{self, ClientHello, ServerHello};
+
+/// A client retrying the same server within this delay with a lower version is downgrading
+const FALLBACK_WINDOW: Duration = Duration::from_secs(30);
+/// Upper bound of the hellos remembered for the fallback detection
+const MAX_HELLOS: usize = 65536;
+/// Servers, and clients, counted for the report; past it the endpoints without violations
+/// are forgotten, then new endpoints are no longer counted
+const MAX_ENDPOINTS: usize = 65536;
+
+/// Versions a policy can refer to
+const VERSIONS: [u16; 9] = [
+    0x0002, 0x0300, 0x0301, 0x0302, 0x0303, 0x0304, 0xfeff, 0xfefd, 0xfefc,
+];
+
+/// Rank of a version, DTLS versions being mapped to the TLS version they derive from
+fn rank(v: u16) -> u16 {
+    match v {
+        0xfeff => 0x0302,
+        0xfefd => 0x0303,
+        0xfefc => 0x0304,
+        v => v,
+    }
+}
+
+/// Integrity-only suites (RFC 9150): no cipher, denied along with the `NULL` family
+const INTEGRITY_ONLY: [u16; 2] = [0xc0b4, 0xc0b5];
+
+/// Cipher suites denied by a policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CipherRule {
+    /// component of the IANA name, `DES` also matching `DES40` or `EXPORT` `EXPORT1024`
+    Family(String),
+    Suite(u16),
+}
+
+impl CipherRule {
+    pub fn matches(&self, suite: u16) -> bool {
+        match self {
+            CipherRule::Suite(s) => *s == suite,
+            CipherRule::Family(family) if family == "NULL" && INTEGRITY_ONLY.contains(&suite) => {
+                true
+            }
+            CipherRule::Family(family) => tls::cipher_suite_as_str(suite).split('_').any(|c| {
+                c.strip_prefix(family.as_str())
+                    .is_some_and(|rest| rest.bytes().all(|b| b.is_ascii_digit()))
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Policy {
+    pub min_version: u16,
+    pub denied_ciphers: Vec<CipherRule>,
+}
+
+impl Default for Policy {
+    /// TLS 1.2 or better, no RC4, (3)DES, EXPORT, NULL or anonymous suites
+    fn default() -> Self {
+        Policy {
+            min_version: 0x0303,
+            denied_ciphers: ["RC4", "3DES", "DES", "EXPORT", "NULL", "anon"]
+                .iter()
+                .map(|f| CipherRule::Family(f.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Policy {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Parse a policy file; an empty file denies nothing
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut policy = Policy {
+            min_version: 0,
+            denied_ciphers: Vec::new(),
+        };
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (rule, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let value = value.trim();
+            match rule {
+                "min_version" => {
+                    policy.min_version = VERSIONS
+                        .iter()
+                        .copied()
+                        .find(|v| tls::version_as_str(*v).eq_ignore_ascii_case(value))
+                        .ok_or(format!("line {}: unknown version {:?}", n + 1, value))?;
+                }
+                "deny_cipher" if value.is_empty() => {
+                    return Err(format!("line {}: missing cipher", n + 1));
+                }
+                "deny_cipher" => {
+                    let id = value
+                        .strip_prefix("0x")
+                        .and_then(|h| u16::from_str_radix(h, 16).ok());
+                    policy.denied_ciphers.push(match id {
+                        Some(id) => CipherRule::Suite(id),
+                        None => CipherRule::Family(value.to_string()),
+                    });
+                }
+                _ => return Err(format!("line {}: unknown rule {:?}", n + 1, rule)),
+            }
+        }
+        Ok(policy)
+    }
+
+    pub fn version_allowed(&self, version: u16) -> bool {
+        rank(version) >= rank(self.min_version)
+    }
+
+    pub fn cipher_allowed(&self, suite: u16) -> bool {
+        !self.denied_ciphers.iter().any(|r| r.matches(suite))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    /// negotiated version below the policy minimum
+    WeakVersion,
+    /// negotiated cipher suite denied by the policy
+    WeakCipher,
+    /// negotiated cipher suite missing from the IANA registry
+    UnknownCipher,
+    /// client signalled a fallback retry (TLS_FALLBACK_SCSV)
+    FallbackScsv,
+    /// client retried the server offering a lower version
+    VersionFallback,
+    /// client opened with an SSL 2.0 hello
+    Sslv2Hello,
+}
+
+impl ViolationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ViolationKind::WeakVersion => "weak-version",
+            ViolationKind::WeakCipher => "weak-cipher",
+            ViolationKind::UnknownCipher => "unknown-cipher",
+            ViolationKind::FallbackScsv => "fallback-scsv",
+            ViolationKind::VersionFallback => "version-fallback",
+            ViolationKind::Sslv2Hello => "sslv2-hello",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Violation {
+    /// flow oriented from the client to the server
+    pub flow: FlowKey,
+    pub sni: Option<String>,
+    pub kind: ViolationKind,
+    pub detail: String,
+}
+
+/// Sessions and violations of one server or client
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EndpointReport {
+    pub endpoint: String,
+    pub sessions: u64,
+    pub violations: BTreeMap<ViolationKind, u64>,
+}
+
+/// Compliance summary of the sessions seen since the last report
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PolicyReport {
+    pub sessions: u64,
+    pub violating_sessions: u64,
+    /// servers (address:port) and clients (address) with at least one violation
+    pub servers: Vec<EndpointReport>,
+    pub clients: Vec<EndpointReport>,
+}
+
+#[derive(Default)]
+struct State {
+    policy: Arc<Policy>,
+    /// highest version offered and time of the last hello per client and server
+    hellos: HashMap<(IpAddr, IpAddr, u16), (u16, SystemTime)>,
+    sessions: u64,
+    violating_sessions: u64,
+    servers: HashMap<String, EndpointReport>,
+    clients: HashMap<String, EndpointReport>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+fn server_endpoint(flow: &FlowKey) -> String {
+    match flow.dst {
+        IpAddr::V4(a) => format!("{}:{}", a, flow.dst_port),
+        IpAddr::V6(a) => format!("[{}]:{}", a, flow.dst_port),
+    }
+}
+
+fn entry(map: &mut HashMap<String, EndpointReport>, key: String) -> Option<&mut EndpointReport> {
+    if map.len() >= MAX_ENDPOINTS && !map.contains_key(&key) {
+        // only the endpoints with violations make it to the report
+        map.retain(|_, r| !r.violations.is_empty());
+        if map.len() >= MAX_ENDPOINTS {
+            return None;
+        }
+    }
+    Some(map.entry(key.clone()).or_insert_with(|| EndpointReport {
+        endpoint: key,
+        ..EndpointReport::default()
+    }))
+}
+
+/// Replace the policy of the current thread
+pub fn set_policy(policy: Arc<Policy>) {
+    STATE.with(|s| s.borrow_mut().policy = policy);
+}
+
+fn violation(flow: &FlowKey, sni: Option<&str>, kind: ViolationKind, detail: String) {
+    println!(
+        "TLS policy violation {} {}: {}",
+        kind.as_str(),
+        flow,
+        detail
+    );
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        if let Some(r) = entry(&mut s.servers, server_endpoint(flow)) {
+            *r.violations.entry(kind).or_default() += 1;
+        }
+        if let Some(r) = entry(&mut s.clients, flow.src.to_string()) {
+            *r.violations.entry(kind).or_default() += 1;
+        }
+    });
+    event::emit(Event::TlsPolicy(Violation {
+        flow: *flow,
+        sni: sni.map(str::to_string),
+        kind,
+        detail,
+    }));
+}
+
+/// Check a ClientHello for downgrade indicators
+pub fn on_client_hello(flow: &FlowKey, ch: &ClientHello) {
+    let sni = ch.server_name();
+    if ch.cipher_suites.contains(&tls::FALLBACK_SCSV) {
+        violation(
+            flow,
+            sni,
+            ViolationKind::FallbackScsv,
+            format!(
+                "retry offering {} at most",
+                tls::version_as_str(ch.max_version())
+            ),
+        );
+    }
+    let offered = ch.max_version();
+    let now = context::time();
+    let key = (flow.src, flow.dst, flow.dst_port);
+    let previous = STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.hellos.len() >= MAX_HELLOS {
+            s.hellos.clear();
+        }
+        let recent = s.hellos.get(&key).copied().filter(|(_, at)| {
+            now.duration_since(*at)
+                .is_ok_and(|elapsed| elapsed <= FALLBACK_WINDOW)
+        });
+        let highest = recent.map_or(offered, |(v, _)| v.max(offered));
+        s.hellos.insert(key, (highest, now));
+        recent.map(|(v, _)| v)
+    });
+    if let Some(previous) = previous.filter(|v| rank(*v) > rank(offered)) {
+        violation(
+            flow,
+            sni,
+            ViolationKind::VersionFallback,
+            format!(
+                "retry offering {} after {}",
+                tls::version_as_str(offered),
+                tls::version_as_str(previous)
+            ),
+        );
+    }
+}
+
+/// Check the version and cipher suite negotiated by a ServerHello
+pub fn on_server_hello(flow: &FlowKey, sni: Option<&str>, sh: &ServerHello) {
+    let version = sh.selected_version();
+    let known = tls::cipher_suite_name(sh.cipher_suite).is_some();
+    let (version_ok, cipher_ok) = STATE.with(|s| {
+        let s = s.borrow();
+        (
+            s.policy.version_allowed(version),
+            s.policy.cipher_allowed(sh.cipher_suite),
+        )
+    });
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.sessions += 1;
+        if !(version_ok && cipher_ok && known) {
+            s.violating_sessions += 1;
+        }
+        if let Some(r) = entry(&mut s.servers, server_endpoint(flow)) {
+            r.sessions += 1;
+        }
+        if let Some(r) = entry(&mut s.clients, flow.src.to_string()) {
+            r.sessions += 1;
+        }
+    });
+    if !version_ok {
+        violation(
+            flow,
+            sni,
+            ViolationKind::WeakVersion,
+            tls::version_as_str(version).to_string(),
+        );
+    }
+    if !cipher_ok {
+        violation(
+            flow,
+            sni,
+            ViolationKind::WeakCipher,
+            format!(
+                "{} (0x{:04x})",
+                tls::cipher_suite_as_str(sh.cipher_suite),
+                sh.cipher_suite
+            ),
+        );
+    }
+    if !known {
+        violation(
+            flow,
+            sni,
+            ViolationKind::UnknownCipher,
+            format!("0x{:04x}", sh.cipher_suite),
+        );
+    }
+}
+
+/// Report a connection opened with an SSL 2.0 ClientHello
+pub fn on_sslv2_hello(flow: &FlowKey, ch: &ClientHelloV2) {
+    violation(
+        flow,
+        None,
+        ViolationKind::Sslv2Hello,
+        format!("offering {} at most", tls::version_as_str(ch.version)),
+    );
+}
+
+impl PolicyReport {
+    /// Add the counts of another report, taken on another thread
+    pub fn merge(&mut self, other: PolicyReport) {
+        fn add(into: &mut Vec<EndpointReport>, from: Vec<EndpointReport>) {
+            let mut index: HashMap<String, usize> = into
+                .iter()
+                .enumerate()
+                .map(|(i, r)| (r.endpoint.clone(), i))
+                .collect();
+            for r in from {
+                match index.get(&r.endpoint) {
+                    Some(&i) => {
+                        let e = &mut into[i];
+                        e.sessions += r.sessions;
+                        for (kind, n) in r.violations {
+                            *e.violations.entry(kind).or_default() += n;
+                        }
+                    }
+                    None => {
+                        index.insert(r.endpoint.clone(), into.len());
+                        into.push(r);
+                    }
+                }
+            }
+        }
+        self.sessions += other.sessions;
+        self.violating_sessions += other.violating_sessions;
+        add(&mut self.servers, other.servers);
+        add(&mut self.clients, other.clients);
+    }
+}
+
+/// Counts of the sessions seen by the current thread so far, every endpoint included;
+/// the counters are reset
+pub fn take_report() -> PolicyReport {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        let report = PolicyReport {
+            sessions: s.sessions,
+            violating_sessions: s.violating_sessions,
+            servers: s.servers.drain().map(|(_, r)| r).collect(),
+            clients: s.clients.drain().map(|(_, r)| r).collect(),
+        };
+        s.sessions = 0;
+        s.violating_sessions = 0;
+        report
+    })
+}
+
+/// Emit and print the report of the sessions seen so far, then reset the counters
+pub fn flush() {
+    report(take_report());
+}
+
+/// Emit and print a report, keeping the endpoints with violations
+pub fn report(mut report: PolicyReport) {
+    for endpoints in [&mut report.servers, &mut report.clients] {
+        endpoints.retain(|r| !r.violations.is_empty());
+        endpoints.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+    }
+    if report.sessions == 0 && report.servers.is_empty() {
+        return;
+    }
+    println!(
+        "TLS policy: {} sessions, {} violating",
+        report.sessions, report.violating_sessions
+    );
+    for (role, endpoints) in [("server", &report.servers), ("client", &report.clients)] {
+        for r in endpoints {
+            let violations: Vec<String> = r
+                .violations
+                .iter()
+                .map(|(k, n)| format!("{}={}", k.as_str(), n))
+                .collect();
+            println!(
+                "TLS policy {} {} sessions={} {}",
+                role,
+                r.endpoint,
+                r.sessions,
+                violations.join(" ")
+            );
+        }
+    }
+    event::emit(Event::TlsPolicyReport(report));
+}
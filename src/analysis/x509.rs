@@ -0,0 +1,365 @@
+//! X.509 certificates of TLS sessions
+//!
+//! The certificate chain sent by a server (TLS 1.2 and below, TLS 1.3 encrypts it) is
+//! summarized and audited: expired or not yet valid certificates, self-signed leaf,
+//! weak keys, SHA-1/MD5 signatures and a leaf that does not cover the SNI of the client.
+//!
+//! Sources:
+//! * https://www.rfc-editor.org/rfc/rfc5280
+//! * https://www.rfc-editor.org/rfc/rfc6125#section-6.4.3 (wildcard matching)
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use x509_parser::extensions::GeneralName;
+use x509_parser::objects::{oid2sn, oid_registry};
+use x509_parser::prelude::{FromDer, X509Certificate};
+use x509_parser::public_key::PublicKey;
+
+use crate::context;
+use crate::event::{self, Event};
+use crate::flow::FlowKey;
+use crate::protocols::mime;
+use crate::timestamp;
+
+/// Smallest RSA/DSA modulus considered safe
+const MIN_RSA_BITS: usize = 2048;
+/// Smallest EC key considered safe
+const MIN_EC_BITS: usize = 224;
+
+/// Summary of a certificate
+#[derive(Debug, Clone, Serialize)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    /// serial number, hex bytes separated by ':'
+    pub serial: String,
+    /// DNS names and ip addresses of the subjectAltName extension
+    pub sans: Vec<String>,
+    /// validity bounds, seconds since the unix epoch
+    pub not_before: i64,
+    pub not_after: i64,
+    /// "RSA", "EC", "DSA" or the algorithm oid
+    pub key_type: String,
+    pub key_bits: usize,
+    pub signature_algorithm: String,
+    /// SHA-256 of the DER encoding, as shown by most tools
+    pub sha256: String,
+    pub self_signed: bool,
+    /// DER encoding, kept for export
+    #[serde(skip)]
+    pub der: Vec<u8>,
+}
+
+/// Number of significant bits of a big endian unsigned integer
+fn int_bits(data: &[u8]) -> usize {
+    let data = match data.iter().position(|b| *b != 0) {
+        Some(i) => &data[i..],
+        None => return 0,
+    };
+    data.len() * 8 - data[0].leading_zeros() as usize
+}
+
+fn ip_from_san(data: &[u8]) -> Option<IpAddr> {
+    match data.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(data).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(data).ok()?)),
+        _ => None,
+    }
+}
+
+impl Certificate {
+    /// Parse a DER encoded certificate
+    pub fn parse(der: &[u8]) -> Option<Self> {
+        let (_, cert) = X509Certificate::from_der(der).ok()?;
+        let tbs = &cert.tbs_certificate;
+
+        let mut sans = Vec::new();
+        if let Ok(Some(ext)) = tbs.subject_alternative_name() {
+            for name in &ext.value.general_names {
+                match name {
+                    GeneralName::DNSName(n) => sans.push(n.to_string()),
+                    GeneralName::IPAddress(a) => {
+                        if let Some(ip) = ip_from_san(a) {
+                            sans.push(ip.to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let spki = tbs.public_key();
+        let (key_type, key_bits) = match spki.parsed() {
+            Ok(PublicKey::RSA(rsa)) => ("RSA".to_string(), int_bits(rsa.modulus)),
+            Ok(PublicKey::EC(point)) => ("EC".to_string(), point.key_size()),
+            Ok(PublicKey::DSA(y)) => ("DSA".to_string(), int_bits(y)),
+            _ => (spki.algorithm.algorithm.to_id_string(), 0),
+        };
+
+        let sig_oid = &cert.signature_algorithm.algorithm;
+        let signature_algorithm = oid2sn(sig_oid, oid_registry())
+            .map(str::to_string)
+            .unwrap_or_else(|_| sig_oid.to_id_string());
+
+        Some(Certificate {
+            subject: tbs.subject().to_string(),
+            issuer: tbs.issuer().to_string(),
+            serial: tbs.raw_serial_as_string(),
+            sans,
+            not_before: tbs.validity().not_before.timestamp(),
+            not_after: tbs.validity().not_after.timestamp(),
+            key_type,
+            key_bits,
+            signature_algorithm,
+            sha256: Sha256::digest(der)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            self_signed: tbs.subject().as_raw() == tbs.issuer().as_raw(),
+            der: der.to_vec(),
+        })
+    }
+
+    /// Common name of the subject, if any
+    pub fn common_name(&self) -> Option<&str> {
+        self.subject
+            .split(", ")
+            .find_map(|rdn| rdn.strip_prefix("CN="))
+    }
+
+    /// Whether the certificate is valid for `host` (SANs, or the CN without SANs)
+    pub fn matches_host(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.');
+        if self.sans.is_empty() {
+            return self.common_name().is_some_and(|cn| name_matches(cn, host));
+        }
+        self.sans.iter().any(|san| name_matches(san, host))
+    }
+
+    /// Key too small for today's standards
+    pub fn weak_key(&self) -> bool {
+        match self.key_type.as_str() {
+            "RSA" | "DSA" => self.key_bits < MIN_RSA_BITS,
+            "EC" => self.key_bits < MIN_EC_BITS,
+            _ => false,
+        }
+    }
+
+    /// Signed with SHA-1 or MD5
+    pub fn weak_signature(&self) -> bool {
+        let alg = self.signature_algorithm.to_ascii_lowercase();
+        alg.contains("sha1") || alg.contains("md5") || alg.contains("md2")
+    }
+}
+
+/// Match a certificate name against a host name, a leading `*.` covering one label
+fn name_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.trim_end_matches('.');
+    if let Some(suffix) = pattern.strip_prefix("*.") {
+        return match host.split_once('.') {
+            Some((label, rest)) => !label.is_empty() && rest.eq_ignore_ascii_case(suffix),
+            None => false,
+        };
+    }
+    pattern.eq_ignore_ascii_case(host)
+}
+
+/// Issue found while auditing a chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FindingKind {
+    Expired,
+    NotYetValid,
+    SelfSigned,
+    WeakKey,
+    WeakSignature,
+    SniMismatch,
+    /// the DER encoding could not be parsed
+    Unparsable,
+}
+
+impl FindingKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FindingKind::Expired => "expired",
+            FindingKind::NotYetValid => "not-yet-valid",
+            FindingKind::SelfSigned => "self-signed",
+            FindingKind::WeakKey => "weak-key",
+            FindingKind::WeakSignature => "weak-signature",
+            FindingKind::SniMismatch => "sni-mismatch",
+            FindingKind::Unparsable => "unparsable",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub kind: FindingKind,
+    /// position of the certificate in the chain, 0 being the leaf
+    pub position: usize,
+    pub detail: String,
+}
+
+/// Certificate chain sent by a server
+#[derive(Debug, Clone, Serialize)]
+pub struct CertificateChain {
+    /// flow oriented from the client to the server
+    pub flow: Option<FlowKey>,
+    pub sni: Option<String>,
+    /// leaf first, none where a certificate could not be parsed
+    pub certificates: Vec<Option<Certificate>>,
+    pub findings: Vec<Finding>,
+}
+
+fn unix_time(t: SystemTime) -> i64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Audit a chain (leaf first) at time `now`; certificates that could not be parsed are
+/// reported at their position, and the checks of the leaf are skipped without one
+pub fn audit(chain: &[Option<Certificate>], sni: Option<&str>, now: SystemTime) -> Vec<Finding> {
+    let now = unix_time(now);
+    let mut findings = Vec::new();
+    let mut add = |kind, position, detail: String| {
+        findings.push(Finding {
+            kind,
+            position,
+            detail,
+        })
+    };
+    for (i, cert) in chain.iter().enumerate() {
+        let cert = match cert {
+            Some(c) => c,
+            None => {
+                add(
+                    FindingKind::Unparsable,
+                    i,
+                    "could not be parsed".to_string(),
+                );
+                continue;
+            }
+        };
+        if now > cert.not_after {
+            add(
+                FindingKind::Expired,
+                i,
+                format!("expired on {}", timestamp::format_time(cert.not_after)),
+            );
+        }
+        if now < cert.not_before {
+            add(
+                FindingKind::NotYetValid,
+                i,
+                format!("valid from {}", timestamp::format_time(cert.not_before)),
+            );
+        }
+        if cert.weak_key() {
+            add(
+                FindingKind::WeakKey,
+                i,
+                format!("{} {} bits", cert.key_type, cert.key_bits),
+            );
+        }
+        // the signature of a trust anchor is never checked, only its key matters
+        if cert.weak_signature() && (i == 0 || !cert.self_signed) {
+            add(
+                FindingKind::WeakSignature,
+                i,
+                cert.signature_algorithm.clone(),
+            );
+        }
+    }
+    if let Some(Some(leaf)) = chain.first() {
+        if leaf.self_signed {
+            add(FindingKind::SelfSigned, 0, leaf.subject.clone());
+        }
+        if let Some(sni) = sni {
+            if !leaf.matches_host(sni) {
+                add(
+                    FindingKind::SniMismatch,
+                    0,
+                    format!("{} not in {}", sni, leaf.sans.join(",")),
+                );
+            }
+        }
+    }
+    findings
+}
+
+/// Summarize and audit the DER certificates of a Certificate message, then emit the chain
+pub fn on_certificates(flow: Option<FlowKey>, sni: Option<&str>, ders: &[&[u8]]) {
+    // positions are kept for the certificates that cannot be parsed
+    let certificates: Vec<Option<Certificate>> =
+        ders.iter().map(|d| Certificate::parse(d)).collect();
+    if certificates.is_empty() {
+        return;
+    }
+    for (i, c) in certificates.iter().enumerate() {
+        let c = match c {
+            Some(c) => c,
+            None => {
+                println!("TLS Certificate #{} unparsable ({}B)", i, ders[i].len());
+                continue;
+            }
+        };
+        println!(
+            "TLS Certificate #{} subject=\"{}\" issuer=\"{}\" key={}/{} sig={} not_after={}",
+            i,
+            c.subject,
+            c.issuer,
+            c.key_type,
+            c.key_bits,
+            c.signature_algorithm,
+            timestamp::format_time(c.not_after)
+        );
+    }
+    let findings = audit(&certificates, sni, context::time());
+    for f in &findings {
+        println!(
+            "TLS Certificate #{} {}: {}",
+            f.position,
+            f.kind.as_str(),
+            f.detail
+        );
+    }
+    event::emit(Event::Certificates(CertificateChain {
+        flow,
+        sni: sni.map(str::to_string),
+        certificates,
+        findings,
+    }));
+}
+
+/// Write a certificate to `dir` as `<sha256>.der` and `<sha256>.pem`, returning the path
+/// of the DER file
+pub fn export(dir: &Path, cert: &Certificate) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.der", cert.sha256));
+    if !path.exists() {
+        fs::write(&path, &cert.der)?;
+    }
+    let pem = dir.join(format!("{}.pem", cert.sha256));
+    if !pem.exists() {
+        fs::write(&pem, to_pem(&cert.der))?;
+    }
+    Ok(path)
+}
+
+/// PEM armor of a DER certificate, in lines of 64 characters
+fn to_pem(der: &[u8]) -> String {
+    let base64 = mime::base64_encode(der);
+    let mut out = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in base64.as_bytes().chunks(64) {
+        out.push_str(std::str::from_utf8(line).unwrap_or_default());
+        out.push('\n');
+    }
+    out.push_str("-----END CERTIFICATE-----\n");
+    out
+}
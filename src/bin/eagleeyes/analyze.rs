@@ -0,0 +1,697 @@
+use clap::Args;
+use pcap::Packet;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use eagleeyes::analysis::fingerprint;
+use eagleeyes::analysis::ioc;
+use eagleeyes::analysis::tls_policy::{self, PolicyReport};
+use eagleeyes::analysis::x509;
+use eagleeyes::context;
+use eagleeyes::event::{self, Event};
+use eagleeyes::expert::{self, ExpertInfo, Severity};
+use eagleeyes::flow;
+use eagleeyes::pipeline::{self, Overflow, Pipeline, Worker};
+use eagleeyes::protocols::decode_by_datalink;
+use eagleeyes::reassembly;
+use eagleeyes::timestamp::Timestamp;
+use eagleeyes::tree::{self, Field, Layer};
+use eagleeyes::zeek::ZeekLogs;
+
+use crate::options::{
+    Analysis, AnalysisOptions, Format, LiveOptions, OutputOptions, PacketOptions, PipelineOptions,
+};
+use crate::output::Output;
+use crate::source::{self, InputArgs, Source};
+use crate::Error;
+
+#[derive(Args)]
+pub struct CaptureArgs {
+    /// Device to capture from, as given by `eagleeyes list`
+    pub device: String,
+    #[command(flatten)]
+    pub live: LiveOptions,
+    #[command(flatten)]
+    pub packets: PacketOptions,
+    #[command(flatten)]
+    pub output: OutputOptions,
+    #[command(flatten)]
+    pub pipeline: PipelineOptions,
+    #[command(flatten)]
+    pub analysis: AnalysisOptions,
+}
+
+#[derive(Args)]
+pub struct ReadArgs {
+    /// pcap or pcapng file
+    pub file: PathBuf,
+    #[command(flatten)]
+    pub packets: PacketOptions,
+    #[command(flatten)]
+    pub output: OutputOptions,
+    #[command(flatten)]
+    pub pipeline: PipelineOptions,
+    #[command(flatten)]
+    pub analysis: AnalysisOptions,
+    /// Show the protocol tree of each packet, with the bytes of each field, and a hex dump
+    /// annotated with the layers
+    #[arg(long)]
+    pub detail: bool,
+}
+
+#[derive(Args)]
+pub struct ExtractArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Directory the files are written to
+    #[arg(short = 'o', long, value_name = "DIR")]
+    pub output_dir: PathBuf,
+    #[command(flatten)]
+    pub packets: PacketOptions,
+    #[command(flatten)]
+    pub output: OutputOptions,
+    #[command(flatten)]
+    pub pipeline: PipelineOptions,
+    /// Decrypt TLS with an NSS key log (SSLKEYLOGFILE), to extract from HTTPS
+    #[arg(long, value_name = "FILE")]
+    pub keylog: Option<PathBuf>,
+}
+
+/// Where the events go: standard output, Zeek logs, certificate files
+pub struct Reporter {
+    pub format: Format,
+    pub out: Output,
+    pub analysis: Analysis,
+    /// only report extracted files
+    pub files_only: bool,
+    /// expert info seen, by severity, layer and kind
+    pub expert: BTreeMap<(Severity, &'static str, &'static str), u64>,
+    /// report the protocol tree of each packet
+    pub detail: bool,
+    /// TLS policy counts of the decoding threads
+    policy: PolicyReport,
+}
+
+/// JSON line of an expert info item
+#[derive(Serialize)]
+struct ExpertLine<'a> {
+    event_type: &'static str,
+    /// number of the packet in the capture, from 1; none for what is found once the
+    /// input ends
+    #[serde(skip_serializing_if = "Option::is_none")]
+    packet: Option<u64>,
+    #[serde(flatten)]
+    item: &'a ExpertInfo,
+}
+
+/// JSON line of the protocol tree of a packet
+#[derive(Serialize)]
+struct TreeLine<'a> {
+    event_type: &'static str,
+    packet: u64,
+    len: usize,
+    layers: &'a [Layer],
+}
+
+impl Reporter {
+    pub fn new(format: Format, out: Output, analysis: Analysis, files_only: bool) -> Self {
+        Reporter {
+            format,
+            out,
+            analysis,
+            files_only,
+            expert: BTreeMap::new(),
+            detail: false,
+            policy: PolicyReport::default(),
+        }
+    }
+
+    /// Report the expert info and the events queued while decoding `packet`
+    pub fn report(&mut self, packet: Option<u64>) -> Result<(), Error> {
+        self.expert_info(packet, expert::drain())?;
+        self.events(event::drain())
+    }
+
+    /// Count the expert info of a packet and report what is severe enough
+    fn expert_info(&mut self, packet: Option<u64>, items: Vec<ExpertInfo>) -> Result<(), Error> {
+        for item in items {
+            *self
+                .expert
+                .entry((item.severity, item.layer, item.kind))
+                .or_default() += 1;
+            let shown = match self.analysis.expert {
+                Some(min) => item.severity >= min && !self.files_only,
+                None => false,
+            };
+            if !shown {
+                continue;
+            }
+            match self.format {
+                Format::Json => self.out.json(&ExpertLine {
+                    event_type: "expert",
+                    packet,
+                    item: &item,
+                })?,
+                Format::Text => match packet {
+                    Some(n) => self.out.line(&format!("expert pkt={} {}", n, item))?,
+                    None => self.out.line(&format!("expert {}", item))?,
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Report the protocol tree of a packet
+    fn tree(&mut self, packet: u64, frame: &[u8], layers: &[Layer]) -> Result<(), Error> {
+        match self.format {
+            Format::Json => self.out.json(&TreeLine {
+                event_type: "packet",
+                packet,
+                len: frame.len(),
+                layers,
+            }),
+            Format::Text => {
+                for line in tree_lines(layers).iter().chain(&hexdump(frame, layers)) {
+                    self.out.line(line)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Summary of the expert info seen, for the text output
+    fn expert_summary(&mut self) -> Result<(), Error> {
+        if self.expert.is_empty() {
+            return Ok(());
+        }
+        let total = |severity| {
+            self.expert
+                .iter()
+                .filter(|((s, _, _), _)| *s == severity)
+                .map(|(_, n)| n)
+                .sum::<u64>()
+        };
+        let line = format!(
+            "expert: errors={}, warnings={}, notes={}",
+            total(Severity::Error),
+            total(Severity::Warning),
+            total(Severity::Note)
+        );
+        self.out.line(&line)?;
+        for ((severity, layer, kind), n) in self.expert.iter().rev() {
+            self.out
+                .line(&format!("  {} {} {}: {}", severity, layer, kind, n))?;
+        }
+        Ok(())
+    }
+
+    /// Report events, flagging known fingerprints
+    fn events(&mut self, events: Vec<Event>) -> Result<(), Error> {
+        for e in events {
+            if let (Event::Certificates(chain), Some(dir)) = (&e, &self.analysis.certs_dir) {
+                for cert in chain.certificates.iter().flatten() {
+                    if let Err(err) = x509::export(dir, cert) {
+                        eprintln!("error exporting certificate to {}: {}", dir.display(), err);
+                    }
+                }
+            }
+            let mut matches = Vec::new();
+            if let (Event::Tls(session), Some(db)) = (&e, &self.analysis.fingerprints) {
+                matches = db.check(session);
+            }
+            self.emit(&e)?;
+            for m in matches {
+                self.emit(&Event::FingerprintMatch(m))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, e: &Event) -> Result<(), Error> {
+        if let Some(logs) = self.analysis.zeek.as_mut() {
+            logs.write(e)
+                .map_err(|err| Error::Output(format!("writing zeek logs: {}", err)))?;
+        }
+        if self.files_only {
+            return match (e, self.format) {
+                (Event::File(_), Format::Json) => self.out.line(&event::to_json(e)),
+                (Event::File(f), Format::Text) => self.out.line(&format!(
+                    "{} {} {}B sha256={} {}",
+                    f.path
+                        .as_ref()
+                        .map(|p| p.display().to_string())
+                        .or_else(|| f.filename.clone())
+                        .unwrap_or_else(|| "-".to_string()),
+                    f.mime,
+                    f.size,
+                    f.sha256,
+                    f.protocol
+                )),
+                _ => Ok(()),
+            };
+        }
+        match (e, self.format) {
+            (_, Format::Json) => self.out.line(&event::to_json(e)),
+            (Event::FingerprintMatch(m), Format::Text) => self.out.line(&format!(
+                "ALERT known TLS fingerprint {} ({}) sni={}",
+                m.fingerprint,
+                m.label,
+                m.sni.as_deref().unwrap_or("-")
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Decode the packet numbered `n`, captured at `ts`, and report its expert info and
+    /// events
+    pub fn packet(
+        &mut self,
+        n: u64,
+        linktype: i32,
+        ts: Timestamp,
+        packet: &Packet,
+    ) -> Result<(), Error> {
+        context::begin_packet();
+        if let Some(time) = ts.system_time() {
+            context::set_time(time);
+        }
+        if self.detail {
+            let layers = tree::dissect(packet.data, |data| decode_by_datalink(linktype, data));
+            self.tree(n, packet.data, &layers)?;
+        } else {
+            decode_by_datalink(linktype, packet.data);
+        }
+        self.report(Some(n))
+    }
+
+    /// Report what a worker made of a packet, at the time of the packet
+    fn decoded(&mut self, decoded: Decoded) -> Result<(), Error> {
+        if let Some(time) = decoded.time {
+            context::set_time(time);
+        }
+        if let Some(line) = decoded.line {
+            self.out.line(&line)?;
+        }
+        if let (Some(n), Some((frame, layers))) = (decoded.packet, &decoded.tree) {
+            self.tree(n, frame, layers)?;
+        }
+        self.expert_info(decoded.packet, decoded.expert)?;
+        if let Some(policy) = decoded.policy {
+            self.policy.merge(policy);
+        }
+        self.events(decoded.events)
+    }
+
+    /// Close the logs
+    pub fn close(&mut self) -> Result<(), Error> {
+        if let Some(logs) = self.analysis.zeek.take() {
+            logs.close()
+                .map_err(|e| Error::Output(format!("writing zeek logs: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        match self.analysis.zeek.as_mut().map(ZeekLogs::flush) {
+            Some(Err(e)) => Err(Error::Output(format!("writing zeek logs: {}", e))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Flush the state the decoders and analyses of the current thread keep across packets
+fn flush_state() {
+    reassembly::flush();
+    fingerprint::flush();
+    tls_policy::flush();
+}
+
+/// Bytes of a layer or field in the frame, as `[14..34]`
+fn span(offset: Option<usize>, len: usize) -> String {
+    match offset {
+        Some(offset) => format!("[{}..{}]", offset, offset + len),
+        // read from reassembled data
+        None => format!("[{} bytes]", len),
+    }
+}
+
+/// Protocol tree of a packet, a line per layer and field with the bytes it was read from
+fn tree_lines(layers: &[Layer]) -> Vec<String> {
+    fn add(lines: &mut Vec<String>, fields: &[Field], depth: usize) {
+        for f in fields {
+            lines.push(format!(
+                "{:indent$}{}: {} {}",
+                "",
+                f.name,
+                f.value,
+                span(f.offset, f.len),
+                indent = depth * 4
+            ));
+            add(lines, &f.children, depth + 1);
+        }
+    }
+    let mut lines = Vec::new();
+    for layer in layers {
+        lines.push(format!(
+            "  {} {}",
+            layer.summary,
+            span(layer.offset, layer.len)
+        ));
+        add(&mut lines, &layer.fields, 1);
+    }
+    lines
+}
+
+/// Hex dump of a frame, each row followed by the layers its bytes belong to
+fn hexdump(frame: &[u8], layers: &[Layer]) -> Vec<String> {
+    frame
+        .chunks(16)
+        .enumerate()
+        .map(|(i, row)| {
+            let start = i * 16;
+            let hex: Vec<String> = row.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = row
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            let names: Vec<&str> = layers
+                .iter()
+                .filter(|l| {
+                    l.offset
+                        .is_some_and(|o| o < start + row.len() && start < o + l.len)
+                })
+                .map(|l| l.name)
+                .collect();
+            format!(
+                "  {:04x}  {:<47}  {:<16}  {}",
+                start,
+                hex.join(" "),
+                ascii,
+                names.join(", ")
+            )
+        })
+        .collect()
+}
+
+/// Summary line of a packet, for the text output of `read`
+fn packet_line(n: u64, ts: Timestamp, len: u32, linktype: i32, data: &[u8]) -> String {
+    let mut line = format!("pkt {} ts={} caplen={} len={}", n, ts, data.len(), len);
+    let summary = flow::summarize(linktype, data);
+    if let Some(t) = summary.ether_type {
+        line.push_str(&format!(" eth_type={}", t));
+    }
+    if let Some(f) = summary.flow {
+        line.push_str(&format!(" {}", f));
+    }
+    line
+}
+
+/// What a worker thread made of a packet
+struct Decoded {
+    /// number of the packet, none for what a worker flushes at the end
+    packet: Option<u64>,
+    time: Option<SystemTime>,
+    /// summary line of the packet, when asked for
+    line: Option<String>,
+    /// frame and protocol tree of the packet, when asked for
+    tree: Option<(Vec<u8>, Vec<Layer>)>,
+    expert: Vec<ExpertInfo>,
+    events: Vec<Event>,
+    /// TLS policy counts of the worker, merged into a single report at the end
+    policy: Option<PolicyReport>,
+}
+
+/// Decoder of the packets of a pipeline worker thread
+struct DecodeWorker {
+    live: bool,
+    lines: bool,
+    detail: bool,
+}
+
+impl Worker for DecodeWorker {
+    type Output = Decoded;
+
+    fn packet(&mut self, packet: &pipeline::Packet) -> Decoded {
+        if self.live {
+            ioc::refresh();
+        }
+        let time = packet.ts.system_time();
+        context::begin_packet();
+        if let Some(time) = time {
+            context::set_time(time);
+        }
+        let tree = if self.detail {
+            let layers = tree::dissect(&packet.data, |data| {
+                decode_by_datalink(packet.linktype, data)
+            });
+            Some((packet.data.to_vec(), layers))
+        } else {
+            decode_by_datalink(packet.linktype, &packet.data);
+            None
+        };
+        Decoded {
+            packet: Some(packet.seq + 1),
+            time,
+            line: self.lines.then(|| {
+                packet_line(
+                    packet.seq + 1,
+                    packet.ts,
+                    packet.len,
+                    packet.linktype,
+                    &packet.data,
+                )
+            }),
+            tree,
+            expert: expert::drain(),
+            events: event::drain(),
+            policy: None,
+        }
+    }
+
+    fn finish(&mut self) -> Option<Decoded> {
+        reassembly::flush();
+        fingerprint::flush();
+        Some(Decoded {
+            packet: None,
+            time: None,
+            line: None,
+            tree: None,
+            expert: expert::drain(),
+            events: event::drain(),
+            policy: Some(tls_policy::take_report()),
+        })
+    }
+}
+
+/// Packets, bytes and time span of what was read
+#[derive(Default)]
+struct Totals {
+    packets: u64,
+    bytes: u64,
+    first: Option<Timestamp>,
+    last: Option<Timestamp>,
+}
+
+impl Totals {
+    fn add(&mut self, ts: Timestamp, packet: &Packet) {
+        self.packets += 1;
+        self.bytes += u64::from(packet.header.len);
+        self.first.get_or_insert(ts);
+        self.last = Some(ts);
+    }
+}
+
+/// Decode and analyze every packet of a source, on the worker threads asked for; `lines`
+/// reports a summary line per packet
+fn analyze(
+    mut source: Source,
+    packets: &PacketOptions,
+    workers: &PipelineOptions,
+    reporter: &mut Reporter,
+    lines: bool,
+) -> Result<Totals, Error> {
+    if workers.workers > 1 {
+        return analyze_parallel(source, packets, workers, reporter, lines);
+    }
+    let linktype = source.linktype();
+    let live = source.live;
+    let precision = source.precision;
+    let mut totals = Totals::default();
+    source.each(packets, |packet| {
+        let Some(packet) = packet else {
+            return reporter.flush();
+        };
+        let ts = source::timestamp(packet.header, precision);
+        totals.add(ts, packet);
+        if lines {
+            println!(
+                "{}",
+                packet_line(totals.packets, ts, packet.header.len, linktype, packet.data)
+            );
+        }
+        if live {
+            // feeds updated on disk apply to the next packets
+            ioc::refresh();
+        }
+        reporter.packet(totals.packets, linktype, ts, packet)?;
+        if live {
+            reporter.flush()?;
+        }
+        Ok(())
+    })?;
+    flush_state();
+    reporter.report(None)?;
+    reporter.close()?;
+    Ok(totals)
+}
+
+/// Decode on worker threads sharded by flow, reporting in capture order
+///
+/// The decoder lines of the workers would interleave, so they are not printed: the
+/// output is made of the events and of the packet lines.
+fn analyze_parallel(
+    mut source: Source,
+    packets: &PacketOptions,
+    workers: &PipelineOptions,
+    reporter: &mut Reporter,
+    lines: bool,
+) -> Result<Totals, Error> {
+    let linktype = source.linktype();
+    let live = source.live;
+    let config = pipeline::Config {
+        workers: usize::from(workers.workers),
+        queue: workers.queue as usize,
+        // waiting would only move the loss to the kernel buffer
+        overflow: if live {
+            Overflow::Drop
+        } else {
+            Overflow::Block
+        },
+    };
+    // the files were loaded once on the main thread, the workers share them
+    let loaded = reporter.analysis.loaded.clone();
+    let detail = reporter.detail;
+    let mut pipeline = Pipeline::new(&config, move |_| {
+        loaded.enable();
+        DecodeWorker {
+            live,
+            lines,
+            detail,
+        }
+    });
+    let precision = source.precision;
+    let mut totals = Totals::default();
+    source.each(packets, |packet| {
+        if let Some(packet) = packet {
+            let ts = source::timestamp(packet.header, precision);
+            totals.add(ts, packet);
+            pipeline.submit(linktype, ts, packet.header.len, packet.data);
+        }
+        pipeline.poll(|decoded| reporter.decoded(decoded))?;
+        if live {
+            reporter.flush()?;
+        }
+        Ok(())
+    })?;
+    let stats = pipeline.finish(|decoded| reporter.decoded(decoded))?;
+    tls_policy::report(std::mem::take(&mut reporter.policy));
+    reporter.report(None)?;
+    reporter.close()?;
+    eprintln!(
+        "decoded on {} threads: {} packets, {} dropped on full queues, {} waits for room, {} failed",
+        stats.workers.len(),
+        stats.packets,
+        stats.dropped,
+        stats.stalls,
+        stats.failed
+    );
+    for (i, w) in stats.workers.iter().enumerate() {
+        eprintln!(
+            "  thread {}: {} packets, {} dropped, up to {} queued",
+            i, w.packets, w.dropped, w.max_queued
+        );
+    }
+    Ok(totals)
+}
+
+pub fn capture(args: CaptureArgs) -> Result<(), Error> {
+    let source = source::open_live(&args.device, &args.live, &args.packets)?;
+    let format = args.output.format();
+    let mut reporter = Reporter::new(
+        format,
+        Output::new(format == Format::Json || args.pipeline.workers > 1)?,
+        args.analysis.setup()?,
+        false,
+    );
+    analyze(source, &args.packets, &args.pipeline, &mut reporter, false)?;
+    Ok(())
+}
+
+pub fn read(args: ReadArgs) -> Result<(), Error> {
+    let source = source::open_file(&args.file, &args.packets)?;
+    let format = args.output.format();
+    let mut reporter = Reporter::new(
+        format,
+        Output::new(format == Format::Json || args.pipeline.workers > 1)?,
+        args.analysis.setup()?,
+        false,
+    );
+    reporter.detail = args.detail;
+    let text = format == Format::Text;
+    if text {
+        // capture metadata, a line per packet and a summary around the decoder lines
+        reporter
+            .out
+            .line(&format!("input: {}", args.file.display()))?;
+        reporter.out.line(&format!(
+            "format: {}",
+            source::file_format(&args.file).unwrap_or("unknown")
+        ))?;
+        reporter
+            .out
+            .line(&format!("datalink: {:?}", source.cap.get_datalink()))?;
+    }
+    let totals = analyze(source, &args.packets, &args.pipeline, &mut reporter, text)?;
+    if text {
+        let out = &mut reporter.out;
+        out.line(&format!(
+            "summary: packets={}, bytes={}",
+            totals.packets, totals.bytes
+        ))?;
+        if let Some(ts) = totals.first {
+            out.line(&format!("first_ts: {}", ts))?;
+        }
+        if let Some(ts) = totals.last {
+            out.line(&format!("last_ts: {}", ts))?;
+        }
+        reporter.expert_summary()?;
+    }
+    Ok(())
+}
+
+pub fn extract(args: ExtractArgs) -> Result<(), Error> {
+    std::fs::create_dir_all(&args.output_dir)
+        .map_err(|e| Error::Output(format!("creating {}: {}", args.output_dir.display(), e)))?;
+    let source = args.input.open(&args.packets)?;
+    let analysis = AnalysisOptions {
+        extract: Some(args.output_dir.clone()),
+        keylog: args.keylog.clone(),
+        ..AnalysisOptions::default()
+    };
+    let mut reporter = Reporter::new(
+        args.output.format(),
+        Output::new(true)?,
+        analysis.setup()?,
+        true,
+    );
+    analyze(source, &args.packets, &args.pipeline, &mut reporter, false)?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use clap::Args;
+
+use eagleeyes::savefile::{self, Compression, RotatingWriter, Rotation};
+
+use crate::options::{LiveOptions, PacketOptions};
+use crate::source;
+use crate::Error;
+
+#[derive(Args)]
+pub struct DumpArgs {
+    /// Device to record, as given by `eagleeyes list`
+    pub device: String,
+    /// File name, may hold strftime conversions (%Y %m %d %H %M %S %j %s), in UTC
+    #[arg(short = 'w', long, value_name = "FILE")]
+    pub write: String,
+    /// Start a new file once it reaches this size (millions of bytes, or a k/m/g suffix)
+    #[arg(short = 'C', long, value_name = "SIZE", value_parser = parse_size)]
+    pub file_size: Option<u64>,
+    /// Start a new file every this many seconds
+    #[arg(short = 'G', long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub rotate_seconds: Option<u64>,
+    /// Keep only the last files, as a ring buffer
+    #[arg(short = 'W', long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    pub files: Option<u64>,
+    /// Compress files once they are closed
+    #[arg(short = 'z', long, value_name = "gzip", value_parser = parse_compression)]
+    pub compress: Option<Compression>,
+    #[command(flatten)]
+    pub live: LiveOptions,
+    #[command(flatten)]
+    pub packets: PacketOptions,
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    match savefile::parse_size(s) {
+        Some(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid size {:?}", s)),
+    }
+}
+
+fn parse_compression(s: &str) -> Result<Compression, String> {
+    Compression::parse(s).ok_or_else(|| format!("unsupported compression {:?}", s))
+}
+
+pub fn run(args: DumpArgs) -> Result<(), Error> {
+    let mut source = source::open_live(&args.device, &args.live, &args.packets)?;
+    let rotation = Rotation {
+        max_bytes: args.file_size,
+        max_seconds: args.rotate_seconds,
+        max_files: args.files.map(|n| n as usize),
+        compression: args.compress,
+    };
+    let linktype = source.linktype() as u32;
+    let out = args.write.as_str();
+    let precision = source.precision;
+    let snaplen = args.live.snaplen as u32;
+    let mut dump = RotatingWriter::new(out, linktype, snaplen, precision, rotation);
+    let error = |e: std::io::Error| Error::Output(format!("writing savefile {}: {}", out, e));
+
+    let written = source.each(&args.packets, |packet| match packet {
+        Some(packet) => {
+            let ts = source::timestamp(packet.header, precision);
+            dump.write(ts, packet.header.len, packet.data)
+                .map_err(error)
+        }
+        // keep what was captured on disk while the link is idle
+        None => dump.flush().map_err(error),
+    })?;
+
+    let files = dump.files();
+    dump.close().map_err(error)?;
+    eprintln!(
+        "wrote {} packets to {} file(s) from {}",
+        written, files, out
+    );
+    Ok(())
+}
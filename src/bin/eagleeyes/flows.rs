@@ -0,0 +1,107 @@
+use clap::{Args, ValueEnum};
+
+use eagleeyes::flow::{self, Conversations};
+use eagleeyes::protocols::detect;
+
+use crate::options::{Format, OutputOptions, PacketOptions};
+use crate::output::Output;
+use crate::source::{self, InputArgs};
+use crate::Error;
+
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Sort {
+    /// by first packet
+    #[default]
+    First,
+    /// by decreasing packets
+    Packets,
+    /// by decreasing bytes
+    Bytes,
+}
+
+#[derive(Args)]
+pub struct FlowsArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    #[command(flatten)]
+    pub packets: PacketOptions,
+    #[command(flatten)]
+    pub output: OutputOptions,
+    /// Order of the flows
+    #[arg(long, value_enum, default_value_t = Sort::First)]
+    pub sort: Sort,
+}
+
+/// Letters of the TCP flags, as in tcpdump
+fn tcp_flags_as_str(flags: u8) -> String {
+    const LETTERS: [(u8, char); 8] = [
+        (0x02, 'S'),
+        (0x01, 'F'),
+        (0x04, 'R'),
+        (0x08, 'P'),
+        (0x10, '.'),
+        (0x20, 'U'),
+        (0x40, 'E'),
+        (0x80, 'W'),
+    ];
+    LETTERS
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, c)| *c)
+        .collect()
+}
+
+pub fn run(args: FlowsArgs) -> Result<(), Error> {
+    let mut source = args.input.open(&args.packets)?;
+    let linktype = source.linktype();
+    let precision = source.precision;
+    let mut flows = Conversations::default();
+
+    source.each(&args.packets, |packet| {
+        let Some(packet) = packet else {
+            return Ok(());
+        };
+        let summary = flow::summarize(linktype, packet.data);
+        let ts = source::timestamp(packet.header, precision);
+        flows.add(&summary, ts, u64::from(packet.header.len));
+        Ok(())
+    })?;
+
+    let mut flows = flows.to_vec();
+    match args.sort {
+        Sort::First => {}
+        Sort::Packets => flows.sort_by_key(|c| std::cmp::Reverse(c.packets())),
+        Sort::Bytes => flows.sort_by_key(|c| std::cmp::Reverse(c.bytes())),
+    }
+
+    let mut out = Output::new(false)?;
+    for c in &flows {
+        if args.output.format() == Format::Json {
+            out.json(c)?;
+            continue;
+        }
+        let mut line = format!(
+            "{} {:>10.3}s {} {} {}:{} -> {}:{} {}/{} pkts {}/{} bytes",
+            c.first_ts,
+            c.duration(),
+            c.uid,
+            c.flow.proto,
+            c.flow.src,
+            c.flow.src_port,
+            c.flow.dst,
+            c.flow.dst_port,
+            c.orig_packets,
+            c.resp_packets,
+            c.orig_bytes,
+            c.resp_bytes
+        );
+        if let Some(service) = c.service {
+            line.push_str(&format!(" {}", detect::protocol_as_str(service)));
+        }
+        if let Some(flags) = c.tcp_flags {
+            line.push_str(&format!(" [{}]", tcp_flags_as_str(flags)));
+        }
+        out.line(&line)?;
+    }
+    Ok(())
+}
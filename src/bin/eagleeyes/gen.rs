@@ -1,23 +1,36 @@
+use clap::Args;
 use std::fs::File;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use crate::Error;
+
+#[derive(Args)]
+pub struct GenArgs {
+    /// Directory the samples are written to
+    #[arg(short = 'o', long, default_value = "samples", value_name = "DIR")]
+    pub output_dir: PathBuf,
+}
+
+/// Writes a sample capture to a path
+type Writer = fn(&Path) -> std::io::Result<()>;
 
 fn pcap_global(f: &mut File) -> std::io::Result<()> {
     f.write_all(&0xa1b2c3d4u32.to_le_bytes())?; // magic (LE)
-    f.write_all(&2u16.to_le_bytes())?;
-    f.write_all(&4u16.to_le_bytes())?;
-    f.write_all(&0i32.to_le_bytes())?;
-    f.write_all(&0u32.to_le_bytes())?;
-    f.write_all(&65535u32.to_le_bytes())?;
-    f.write_all(&1u32.to_le_bytes())?; // DLT_EN10MB
+    f.write_all(&2u16.to_le_bytes())?; // major
+    f.write_all(&4u16.to_le_bytes())?; // minor
+    f.write_all(&0i32.to_le_bytes())?; // thiszone
+    f.write_all(&0u32.to_le_bytes())?; // sigfigs
+    f.write_all(&65535u32.to_le_bytes())?; // snaplen
+    f.write_all(&1u32.to_le_bytes())?; // linktype: DLT_EN10MB
     Ok(())
 }
 
 fn emit_pkt(f: &mut File, payload: &[u8]) -> std::io::Result<()> {
     f.write_all(&0u32.to_le_bytes())?; // ts_sec
     f.write_all(&0u32.to_le_bytes())?; // ts_usec
-    f.write_all(&(payload.len() as u32).to_le_bytes())?;
-    f.write_all(&(payload.len() as u32).to_le_bytes())?;
+    f.write_all(&(payload.len() as u32).to_le_bytes())?; // incl_len
+    f.write_all(&(payload.len() as u32).to_le_bytes())?; // orig_len
     f.write_all(payload)?;
     Ok(())
 }
@@ -72,7 +85,7 @@ fn eth_ipv4_tcp(sport: u16, dport: u16, tpayload: &[u8]) -> Vec<u8> {
     let eth = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0x08, 0x00];
     v.extend_from_slice(&eth);
     let ip_len = 20u16;
-    let tcp_hdr_len = 34u16; // our struct size
+    let tcp_hdr_len = 20u16;
     let total_len = ip_len + tcp_hdr_len + tpayload.len() as u16;
     let ipv4 = [
         0x45,
@@ -97,56 +110,66 @@ fn eth_ipv4_tcp(sport: u16, dport: u16, tpayload: &[u8]) -> Vec<u8> {
         2,
     ];
     v.extend_from_slice(&ipv4);
-    // TCP header (34 bytes per project)
-    let mut tcp = vec![0u8; 34];
+    // TCP header without options
+    let mut tcp = vec![0u8; 20];
     tcp[0] = (sport >> 8) as u8;
     tcp[1] = (sport & 0xff) as u8;
     tcp[2] = (dport >> 8) as u8;
     tcp[3] = (dport & 0xff) as u8;
-    tcp[12] = 0x50; // data offset nibble in first byte of this 4-byte field
-    tcp[33] = 0x02; // SYN in last byte of control_flag array
+    tcp[12] = 0x50; // data offset = 5 words
+    tcp[13] = 0x02; // SYN
     v.extend_from_slice(&tcp);
     v.extend_from_slice(tpayload);
     v
 }
 
-fn main() -> std::io::Result<()> {
-    let samples_dir = Path::new("samples");
-    std::fs::create_dir_all(samples_dir)?;
-    let path = samples_dir.join("proto.cap");
-    let mut f = File::create(&path)?;
+/// A single Ethernet/IPv4/TCP SYN
+fn write_http(path: &Path) -> std::io::Result<()> {
+    let mut f = File::create(path)?;
+    pcap_global(&mut f)?;
+    emit_pkt(&mut f, &eth_ipv4_tcp(80, 443, &[]))
+}
+
+/// One packet of each of a few protocols, over UDP and TCP
+fn write_protocols(path: &Path) -> std::io::Result<()> {
+    let mut f = File::create(path)?;
     pcap_global(&mut f)?;
 
     // SIP over UDP 5060
     let sip = b"INVITE sip:a@b SIP/2.0\r\nVia: SIP/2.0/UDP host\r\n\r\n";
-    let pkt = eth_ipv4_udp(40000, 5060, sip);
-    emit_pkt(&mut f, &pkt)?;
+    emit_pkt(&mut f, &eth_ipv4_udp(40000, 5060, sip))?;
 
     // RTP (UDP) port 40002
-    let rtp = vec![0x80, 96, 0x12, 0x34, 0, 0, 0, 1, 0, 0, 0, 1];
-    let pkt = eth_ipv4_udp(40000, 40002, &rtp);
-    emit_pkt(&mut f, &pkt)?;
+    let rtp = [0x80, 96, 0x12, 0x34, 0, 0, 0, 1, 0, 0, 0, 1];
+    emit_pkt(&mut f, &eth_ipv4_udp(40000, 40002, &rtp))?;
 
     // RTCP (SR) PT=200
-    let rtcp = vec![0x80, 200, 0x00, 0x06, 0, 0, 0, 1];
-    let pkt = eth_ipv4_udp(40000, 40003, &rtcp);
-    emit_pkt(&mut f, &pkt)?;
+    let rtcp = [0x80, 200, 0x00, 0x06, 0, 0, 0, 1];
+    emit_pkt(&mut f, &eth_ipv4_udp(40000, 40003, &rtcp))?;
 
     // SMB over TCP/445
-    let smb = b"\xFFSMB";
-    let pkt = eth_ipv4_tcp(50000, 445, smb);
-    emit_pkt(&mut f, &pkt)?;
+    emit_pkt(&mut f, &eth_ipv4_tcp(50000, 445, b"\xFFSMB"))?;
 
     // Syslog UDP/514
     let syslog = b"<13>Oct  1 12:00:00 host app: test";
-    let pkt = eth_ipv4_udp(40000, 514, syslog);
-    emit_pkt(&mut f, &pkt)?;
+    emit_pkt(&mut f, &eth_ipv4_udp(40000, 514, syslog))?;
 
     // SNMP UDP/161
     let snmp = [0x30, 0x10, 0x02, 0x01, 0x01];
-    let pkt = eth_ipv4_udp(40000, 161, &snmp);
-    emit_pkt(&mut f, &pkt)?;
+    emit_pkt(&mut f, &eth_ipv4_udp(40000, 161, &snmp))
+}
 
-    eprintln!("Wrote {}", path.display());
+pub fn run(args: GenArgs) -> Result<(), Error> {
+    let dir = &args.output_dir;
+    let error = |path: &Path, e: std::io::Error| {
+        Error::Output(format!("writing {}: {}", path.display(), e))
+    };
+    std::fs::create_dir_all(dir).map_err(|e| error(dir, e))?;
+    let samples: [(&str, Writer); 2] = [("http.cap", write_http), ("proto.cap", write_protocols)];
+    for (name, write) in samples {
+        let path = dir.join(name);
+        write(&path).map_err(|e| error(&path, e))?;
+        eprintln!("wrote {}", path.display());
+    }
     Ok(())
 }
@@ -0,0 +1,119 @@
+//! `eagleeyes`: capture, read, record and summarize network traffic
+//!
+//! Every subcommand shares the same capture options (snaplen, promiscuous mode, read
+//! timeout, BPF filter, packet count and time limits), output format, error messages and
+//! exit codes.
+use clap::{Parser, Subcommand};
+use std::fmt;
+use std::process;
+
+mod analyze;
+mod dump;
+mod flows;
+mod gen;
+mod options;
+mod output;
+mod source;
+mod stats;
+
+/// Exit codes of the subcommands
+#[non_exhaustive]
+pub struct EXIT;
+
+impl EXIT {
+    pub const OK: i32 = 0;
+    /// invalid arguments, as reported by the parser
+    pub const USAGE: i32 = 2;
+    /// the device or file to read could not be opened or read
+    pub const INPUT: i32 = 3;
+    /// a rule, feed, database or policy file could not be loaded
+    pub const CONFIG: i32 = 4;
+    /// results could not be written
+    pub const OUTPUT: i32 = 5;
+}
+
+/// Failure of a subcommand, with the exit code it maps to
+#[derive(Debug)]
+pub enum Error {
+    Usage(String),
+    Input(String),
+    Config(String),
+    Output(String),
+}
+
+impl Error {
+    pub fn code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => EXIT::USAGE,
+            Error::Input(_) => EXIT::INPUT,
+            Error::Config(_) => EXIT::CONFIG,
+            Error::Output(_) => EXIT::OUTPUT,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Usage(m) | Error::Input(m) | Error::Config(m) | Error::Output(m) => {
+                f.write_str(m)
+            }
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(
+    name = "eagleeyes",
+    version,
+    about = "Network traffic decoder and analyzer"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Decode and analyze live traffic of an interface
+    Capture(analyze::CaptureArgs),
+    /// Decode and analyze a pcap or pcapng file
+    Read(analyze::ReadArgs),
+    /// List the capture devices
+    List(ListArgs),
+    /// Record an interface to pcap files, rotated by size or time
+    Dump(dump::DumpArgs),
+    /// Summarize a capture: packets, bytes, protocols, talkers and ports
+    Stats(stats::StatsArgs),
+    /// List the conversations of a capture
+    Flows(flows::FlowsArgs),
+    /// Extract the files carried by a capture
+    Extract(analyze::ExtractArgs),
+    /// Write sample captures
+    Gen(gen::GenArgs),
+}
+
+#[derive(clap::Args)]
+struct ListArgs {
+    #[command(flatten)]
+    output: options::OutputOptions,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Capture(args) => analyze::capture(args),
+        Command::Read(args) => analyze::read(args),
+        Command::List(args) => source::list(&args.output),
+        Command::Dump(args) => dump::run(args),
+        Command::Stats(args) => stats::run(args),
+        Command::Flows(args) => flows::run(args),
+        Command::Extract(args) => analyze::extract(args),
+        Command::Gen(args) => gen::run(args),
+    };
+    if let Err(e) = result {
+        eprintln!("eagleeyes: error: {}", e);
+        process::exit(e.code());
+    }
+    process::exit(EXIT::OK);
+}
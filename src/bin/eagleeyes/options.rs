@@ -0,0 +1,276 @@
+use clap::{Args, ValueEnum};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use eagleeyes::analysis::credentials;
+use eagleeyes::analysis::extract;
+use eagleeyes::analysis::fingerprint::FingerprintDb;
+use eagleeyes::analysis::geoip::{self, GeoDb};
+use eagleeyes::analysis::ioc::{self, Iocs};
+use eagleeyes::analysis::rules::{self, RuleSet};
+use eagleeyes::analysis::tls_policy::{self, Policy};
+use eagleeyes::expert::Severity;
+use eagleeyes::protocols::tls_decrypt::{self, KeyLog};
+use eagleeyes::zeek::ZeekLogs;
+
+use crate::Error;
+
+/// How the device is opened
+#[derive(Args, Clone)]
+pub struct LiveOptions {
+    /// Bytes kept of each packet
+    #[arg(short = 's', long, default_value_t = 262144, value_name = "BYTES")]
+    pub snaplen: i32,
+    /// Do not put the interface in promiscuous mode
+    #[arg(short = 'p', long)]
+    pub no_promisc: bool,
+    /// Milliseconds a read waits for packets before giving control back
+    #[arg(long, default_value_t = 500, value_name = "MS")]
+    pub timeout: i32,
+    /// Kernel buffer size
+    #[arg(short = 'B', long, value_name = "BYTES")]
+    pub buffer_size: Option<i32>,
+}
+
+/// Which packets are read, and when reading stops
+#[derive(Args, Clone, Default)]
+pub struct PacketOptions {
+    /// BPF filter, as in tcpdump
+    #[arg(short = 'f', long, value_name = "EXPR")]
+    pub filter: Option<String>,
+    /// Stop after this many packets
+    #[arg(short = 'c', long, value_name = "N")]
+    pub count: Option<u64>,
+    /// Stop after this many seconds (of wall clock time live, of packet time in a file)
+    #[arg(short = 'd', long, value_name = "SECONDS")]
+    pub duration: Option<u64>,
+}
+
+/// How packets are decoded
+#[derive(Args, Clone)]
+pub struct PipelineOptions {
+    /// Decode on this many threads, sharded by flow; the decoder lines are then not printed
+    #[arg(short = 'j', long, default_value_t = 1, value_name = "N",
+          value_parser = clap::value_parser!(u16).range(1..))]
+    pub workers: u16,
+    /// Packets waiting per thread; a live capture drops the packets of a full queue
+    #[arg(long, default_value_t = 4096, value_name = "N",
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub queue: u32,
+}
+
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// decoder lines and summaries
+    #[default]
+    Text,
+    /// one JSON object per line, and nothing else on standard output
+    Json,
+}
+
+#[derive(Args, Clone, Default)]
+pub struct OutputOptions {
+    /// Output format
+    #[arg(short = 'F', long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+    /// Shorthand for --format json
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl OutputOptions {
+    pub fn format(&self) -> Format {
+        if self.json {
+            Format::Json
+        } else {
+            self.format
+        }
+    }
+}
+
+/// Lowest severity of the expert info reported
+#[derive(ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExpertLevel {
+    /// notes, warnings and errors
+    #[default]
+    Note,
+    /// warnings and errors
+    Warning,
+    Error,
+    /// no expert info
+    Off,
+}
+
+impl ExpertLevel {
+    pub fn severity(self) -> Option<Severity> {
+        match self {
+            ExpertLevel::Note => Some(Severity::Note),
+            ExpertLevel::Warning => Some(Severity::Warning),
+            ExpertLevel::Error => Some(Severity::Error),
+            ExpertLevel::Off => None,
+        }
+    }
+}
+
+/// Analyses applied while decoding
+#[derive(Args, Clone, Default)]
+pub struct AnalysisOptions {
+    /// Flag TLS sessions matching a list of JA3/JA4 fingerprints
+    #[arg(long, value_name = "FILE")]
+    pub fingerprints: Option<PathBuf>,
+    /// Write the certificates seen in DER and PEM
+    #[arg(long, value_name = "DIR")]
+    pub export_certs: Option<PathBuf>,
+    /// Check TLS versions and ciphers against a policy
+    #[arg(long, value_name = "FILE")]
+    pub tls_policy: Option<PathBuf>,
+    /// Decrypt TLS with an NSS key log (SSLKEYLOGFILE)
+    #[arg(long, value_name = "FILE")]
+    pub keylog: Option<PathBuf>,
+    /// Write the files carried by HTTP, SMTP, FTP and SMB
+    #[arg(long, value_name = "DIR")]
+    pub extract: Option<PathBuf>,
+    /// Show cleartext credentials instead of masking them
+    #[arg(long)]
+    pub show_secrets: bool,
+    /// Match Snort/Suricata rules
+    #[arg(long, value_name = "FILE")]
+    pub rules: Option<PathBuf>,
+    /// Match indicators of a feed (repeatable)
+    #[arg(long, value_name = "FILE")]
+    pub ioc: Vec<PathBuf>,
+    /// Enrich endpoints with a MaxMind database (repeatable)
+    #[arg(long, value_name = "MMDB")]
+    pub geoip: Vec<PathBuf>,
+    /// Write Zeek TSV logs
+    #[arg(long, value_name = "DIR")]
+    pub zeek_logs: Option<PathBuf>,
+    /// Report the malformed or unusual packets of this severity and above
+    #[arg(long, value_enum, default_value_t = ExpertLevel::Note, value_name = "LEVEL")]
+    pub expert: ExpertLevel,
+}
+
+/// The files of the analyses, loaded once and shared by the decoding threads
+#[derive(Clone, Default)]
+pub struct Loaded {
+    tls_policy: Option<Arc<Policy>>,
+    keylog: Option<Arc<KeyLog>>,
+    rules: Option<Arc<RuleSet>>,
+    iocs: Option<Arc<Iocs>>,
+    geoip: Option<Arc<GeoDb>>,
+    show_secrets: bool,
+    extract: Option<PathBuf>,
+}
+
+impl Loaded {
+    /// Enable the analyses of the current thread
+    pub fn enable(&self) {
+        if let Some(policy) = &self.tls_policy {
+            tls_policy::set_policy(policy.clone());
+        }
+        if let Some(log) = &self.keylog {
+            tls_decrypt::set_keylog(log.clone());
+        }
+        if let Some(set) = &self.rules {
+            rules::set_rules(set.clone());
+        }
+        if let Some(iocs) = &self.iocs {
+            ioc::set_iocs(iocs.clone());
+        }
+        if let Some(db) = &self.geoip {
+            geoip::set_db(db.clone());
+        }
+        credentials::set_reveal(self.show_secrets);
+        extract::set_output(self.extract.clone());
+    }
+}
+
+/// What the analyses need once set up
+pub struct Analysis {
+    /// to enable the analyses on other threads
+    pub loaded: Loaded,
+    pub fingerprints: Option<FingerprintDb>,
+    pub certs_dir: Option<PathBuf>,
+    pub zeek: Option<ZeekLogs>,
+    /// lowest severity of the expert info reported
+    pub expert: Option<Severity>,
+}
+
+impl AnalysisOptions {
+    /// Load the files given and enable the analyses of the current thread, and what the
+    /// results need
+    pub fn setup(&self) -> Result<Analysis, Error> {
+        let fingerprints = match &self.fingerprints {
+            Some(path) => Some(FingerprintDb::load(path).map_err(|e| {
+                Error::Config(format!("loading fingerprints {}: {}", path.display(), e))
+            })?),
+            None => None,
+        };
+        let loaded = self.load()?;
+        loaded.enable();
+        // created once the databases are set, for the GeoIP columns
+        let zeek = match &self.zeek_logs {
+            Some(dir) => Some(ZeekLogs::create(dir).map_err(|e| {
+                Error::Output(format!("creating zeek logs in {}: {}", dir.display(), e))
+            })?),
+            None => None,
+        };
+        Ok(Analysis {
+            loaded,
+            fingerprints,
+            certs_dir: self.export_certs.clone(),
+            zeek,
+            expert: self.expert.severity(),
+        })
+    }
+
+    /// Load the files given, reporting what was loaded
+    fn load(&self) -> Result<Loaded, Error> {
+        let mut loaded = Loaded {
+            show_secrets: self.show_secrets,
+            extract: self.extract.clone(),
+            ..Loaded::default()
+        };
+        if let Some(path) = &self.tls_policy {
+            let policy = Policy::load(path).map_err(|e| {
+                Error::Config(format!("loading tls policy {}: {}", path.display(), e))
+            })?;
+            loaded.tls_policy = Some(Arc::new(policy));
+        }
+        if let Some(path) = &self.keylog {
+            let log = KeyLog::load(path)
+                .map_err(|e| Error::Config(format!("loading key log {}: {}", path.display(), e)))?;
+            loaded.keylog = Some(Arc::new(log));
+        }
+        if let Some(path) = &self.rules {
+            let set = RuleSet::load(path)
+                .map_err(|e| Error::Config(format!("loading rules {}: {}", path.display(), e)))?;
+            for reason in &set.skipped {
+                eprintln!("skipped rule in {}: {}", path.display(), reason);
+            }
+            eprintln!("loaded {} rules from {}", set.len(), path.display());
+            loaded.rules = Some(Arc::new(set));
+        }
+        if !self.ioc.is_empty() {
+            let iocs = Iocs::load(&self.ioc)
+                .map_err(|e| Error::Config(format!("loading indicators: {}", e)))?;
+            for feed in &iocs.feeds {
+                eprintln!(
+                    "loaded {} indicators from {} ({} lines skipped)",
+                    feed.indicators, feed.name, feed.skipped
+                );
+            }
+            loaded.iocs = Some(Arc::new(iocs));
+        }
+        if !self.geoip.is_empty() {
+            let mut db = GeoDb::default();
+            for path in &self.geoip {
+                db.open(path).map_err(|e| {
+                    Error::Config(format!("loading geoip database {}: {}", path.display(), e))
+                })?;
+            }
+            loaded.geoip = Some(Arc::new(db));
+        }
+        Ok(loaded)
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs::File;
+use std::io::{self, LineWriter, Write};
+
+use crate::Error;
+
+/// Where results go
+///
+/// Decoders print their lines on standard output. When only results are wanted (JSON,
+/// extraction), standard output is pointed to the null device and results are written to
+/// a duplicate of the original one.
+pub enum Output {
+    Stdout,
+    Results(LineWriter<File>),
+}
+
+impl Output {
+    pub fn new(quiet_decoders: bool) -> Result<Self, Error> {
+        if quiet_decoders {
+            Self::results_only()
+        } else {
+            Ok(Output::Stdout)
+        }
+    }
+
+    #[cfg(unix)]
+    fn results_only() -> Result<Self, Error> {
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+        let error = |e: io::Error| Error::Output(format!("redirecting standard output: {}", e));
+        io::stdout().flush().map_err(error)?;
+        let null = File::options()
+            .write(true)
+            .open("/dev/null")
+            .map_err(error)?;
+        unsafe {
+            let results = libc::dup(libc::STDOUT_FILENO);
+            if results < 0 {
+                return Err(error(io::Error::last_os_error()));
+            }
+            if libc::dup2(null.as_raw_fd(), libc::STDOUT_FILENO) < 0 {
+                return Err(error(io::Error::last_os_error()));
+            }
+            Ok(Output::Results(LineWriter::new(File::from_raw_fd(results))))
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn results_only() -> Result<Self, Error> {
+        Ok(Output::Stdout)
+    }
+
+    /// Write a line of results
+    pub fn line(&mut self, line: &str) -> Result<(), Error> {
+        let result = match self {
+            Output::Stdout => writeln!(io::stdout(), "{}", line),
+            Output::Results(out) => writeln!(out, "{}", line),
+        };
+        result.map_err(|e| Error::Output(format!("writing results: {}", e)))
+    }
+
+    /// Write a result as a JSON line
+    pub fn json<T: serde::Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        let line = serde_json::to_string(value)
+            .map_err(|e| Error::Output(format!("serializing results: {}", e)))?;
+        self.line(&line)
+    }
+}
@@ -0,0 +1,231 @@
+use clap::Args;
+use pcap::{Activated, Capture, Device, Packet, PacketHeader};
+use serde::Serialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use eagleeyes::capture;
+use eagleeyes::timestamp::{Precision, Timestamp};
+
+use crate::options::{Format, LiveOptions, OutputOptions, PacketOptions};
+use crate::output::Output;
+use crate::Error;
+
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_signal(_: libc::c_int) {
+    STOP.store(true, Ordering::SeqCst);
+}
+
+/// Stop reading on SIGINT and SIGTERM, so that results are flushed and files closed
+fn handle_signals() {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// A capture file, or a device with `-i`
+#[derive(Args, Clone)]
+pub struct InputArgs {
+    /// pcap or pcapng file
+    #[arg(required_unless_present = "interface", conflicts_with = "interface")]
+    pub file: Option<PathBuf>,
+    /// Read live traffic of a device instead
+    #[arg(short = 'i', long, value_name = "DEVICE")]
+    pub interface: Option<String>,
+    #[command(flatten)]
+    pub live: LiveOptions,
+}
+
+impl InputArgs {
+    pub fn open(&self, packets: &PacketOptions) -> Result<Source, Error> {
+        match (&self.interface, &self.file) {
+            (Some(device), _) => open_live(device, &self.live, packets),
+            (None, Some(path)) => open_file(path, packets),
+            (None, None) => Err(Error::Usage("a file or an interface is needed".into())),
+        }
+    }
+}
+
+/// An opened capture and how to read it
+pub struct Source {
+    pub cap: Capture<dyn Activated>,
+    pub live: bool,
+    pub name: String,
+    /// resolution of the timestamps libpcap hands out
+    pub precision: Precision,
+}
+
+fn apply_filter(cap: &mut Capture<dyn Activated>, packets: &PacketOptions) -> Result<(), Error> {
+    if let Some(filter) = &packets.filter {
+        cap.filter(filter)
+            .map_err(|e| Error::Usage(format!("invalid filter {:?}: {}", filter, e)))?;
+    }
+    Ok(())
+}
+
+pub fn open_live(
+    device: &str,
+    live: &LiveOptions,
+    packets: &PacketOptions,
+) -> Result<Source, Error> {
+    let error = |e: pcap::Error| Error::Input(format!("opening device {}: {}", device, e));
+    let mut inactive = Capture::from_device(device)
+        .map_err(error)?
+        .snaplen(live.snaplen)
+        .promisc(!live.no_promisc)
+        .timeout(live.timeout);
+    if let Some(size) = live.buffer_size {
+        inactive = inactive.buffer_size(size);
+    }
+    let mut cap: Capture<dyn Activated> = inactive.open().map_err(error)?.into();
+    apply_filter(&mut cap, packets)?;
+    Ok(Source {
+        cap,
+        live: true,
+        name: device.to_string(),
+        // what the device stamps; files are read in nanoseconds, whatever they hold
+        precision: Precision::Micro,
+    })
+}
+
+fn sniff_format(path: &Path) -> std::io::Result<Option<&'static str>> {
+    let mut f = File::open(path)?;
+    let mut magic = [0u8; 4];
+    let n = f.read(&mut magic)?;
+    if n < 4 {
+        return Ok(None);
+    }
+    Ok(capture::sniff(magic).map(capture::Format::name))
+}
+
+/// Format of a capture file, from its magic
+pub fn file_format(path: &Path) -> Option<&'static str> {
+    sniff_format(path).ok().flatten()
+}
+
+pub fn open_file(path: &Path, packets: &PacketOptions) -> Result<Source, Error> {
+    // libpcap scales every file to the nanosecond, pcapng resolutions and offsets included
+    let opened = Capture::from_file_with_precision(path, pcap::Precision::Nano);
+    let mut cap: Capture<dyn Activated> = match opened {
+        Ok(cap) => cap.into(),
+        Err(e) => {
+            // a clearer hint when the input does not look like a pcap/pcapng file
+            return Err(match sniff_format(path) {
+                Ok(Some(_)) => Error::Input(format!("opening {}: {}", path.display(), e)),
+                Ok(None) => Error::Input(format!(
+                    "{} does not look like a pcap/pcapng file (record one with tcpdump -i <iface> -w out.pcap, or write a sample with eagleeyes gen)",
+                    path.display()
+                )),
+                Err(io) => Error::Input(format!("opening {}: {}", path.display(), io)),
+            });
+        }
+    };
+    apply_filter(&mut cap, packets)?;
+    Ok(Source {
+        cap,
+        live: false,
+        name: path.display().to_string(),
+        precision: Precision::Nano,
+    })
+}
+
+/// Time of a packet; the fraction of its timeval is in the precision of the capture
+pub fn timestamp(header: &PacketHeader, precision: Precision) -> Timestamp {
+    // timeval field types differ across platforms
+    #[allow(clippy::unnecessary_cast)]
+    let (secs, fraction) = (header.ts.tv_sec as i64, header.ts.tv_usec as u32);
+    match precision {
+        Precision::Micro => Timestamp::from_micros(secs, fraction),
+        Precision::Nano => Timestamp::new(secs, fraction),
+    }
+}
+
+impl Source {
+    /// Datalink type of the capture
+    pub fn linktype(&self) -> i32 {
+        self.cap.get_datalink().0
+    }
+
+    /// Call `f` on each packet until the input ends, a limit is reached or a signal
+    /// arrives, and with `None` when a live read times out. Returns the packets read
+    pub fn each<F>(&mut self, limits: &PacketOptions, mut f: F) -> Result<u64, Error>
+    where
+        F: FnMut(Option<&Packet>) -> Result<(), Error>,
+    {
+        handle_signals();
+        let started = Instant::now();
+        let mut first: Option<i64> = None;
+        let mut count = 0u64;
+        let max_count = limits.count.unwrap_or(u64::MAX);
+        let max_duration = limits.duration.map(Duration::from_secs);
+        while count < max_count && !STOP.load(Ordering::SeqCst) {
+            if self.live && max_duration.is_some_and(|d| started.elapsed() >= d) {
+                break;
+            }
+            match self.cap.next() {
+                Ok(packet) => {
+                    if !self.live {
+                        let secs = timestamp(packet.header, self.precision).secs;
+                        let first = *first.get_or_insert(secs);
+                        if max_duration.is_some_and(|d| secs - first >= d.as_secs() as i64) {
+                            break;
+                        }
+                    }
+                    f(Some(&packet))?;
+                    count += 1;
+                }
+                Err(pcap::Error::TimeoutExpired) => f(None)?,
+                Err(pcap::Error::NoMorePackets) => break,
+                Err(e) if self.live => {
+                    return Err(Error::Input(format!("reading {}: {}", self.name, e)))
+                }
+                Err(e) => {
+                    // a truncated file ends the read, what was read is kept
+                    eprintln!("eagleeyes: warning: reading {}: {}", self.name, e);
+                    break;
+                }
+            }
+        }
+        if self.live {
+            if let Ok(stats) = self.cap.stats() {
+                eprintln!(
+                    "{} packets received, {} dropped by the kernel, {} dropped by the interface",
+                    stats.received, stats.dropped, stats.if_dropped
+                );
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[derive(Serialize)]
+struct DeviceInfo<'a> {
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+}
+
+/// List the capture devices
+pub fn list(output: &OutputOptions) -> Result<(), Error> {
+    let devices = Device::list().map_err(|e| Error::Input(format!("listing devices: {}", e)))?;
+    let mut out = Output::new(false)?;
+    for d in &devices {
+        match output.format() {
+            Format::Text => match &d.desc {
+                Some(desc) => out.line(&format!("{}\t{}", d.name, desc))?,
+                None => out.line(&d.name)?,
+            },
+            Format::Json => out.json(&DeviceInfo {
+                name: &d.name,
+                description: d.desc.as_deref(),
+            })?,
+        }
+    }
+    Ok(())
+}
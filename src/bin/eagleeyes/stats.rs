@@ -0,0 +1,211 @@
+use clap::Args;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use eagleeyes::flow::{self, FlowKey};
+use eagleeyes::protocols::ethernet::EtherType;
+use eagleeyes::protocols::ip::IpProtocol;
+use eagleeyes::protocols::{detect, services};
+use eagleeyes::timestamp::Timestamp;
+
+use crate::options::{Format, OutputOptions, PacketOptions};
+use crate::output::Output;
+use crate::source::{self, InputArgs};
+use crate::Error;
+
+#[derive(Args)]
+pub struct StatsArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    #[command(flatten)]
+    pub packets: PacketOptions,
+    #[command(flatten)]
+    pub output: OutputOptions,
+    /// Entries of the talkers and ports tables
+    #[arg(long, default_value_t = 10, value_name = "N")]
+    pub top: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+struct Count {
+    packets: u64,
+    bytes: u64,
+}
+
+impl Count {
+    fn add(&mut self, bytes: u64) {
+        self.packets += 1;
+        self.bytes += bytes;
+    }
+}
+
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    packets: u64,
+    bytes: u64,
+}
+
+#[derive(Serialize)]
+struct Stats {
+    input: String,
+    packets: u64,
+    bytes: u64,
+    captured_bytes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    first_ts: Option<Timestamp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_ts: Option<Timestamp>,
+    duration: f64,
+    packets_per_second: f64,
+    bits_per_second: f64,
+    network: Vec<Entry>,
+    transport: Vec<Entry>,
+    talkers: Vec<Entry>,
+    ports: Vec<Entry>,
+}
+
+/// Port of the server side of a flow: the one a protocol is registered on, else the lowest
+fn service_port(flow: &FlowKey) -> u16 {
+    let registered = |port| !detect::by_port(flow.proto, port, port).is_empty();
+    match (registered(flow.src_port), registered(flow.dst_port)) {
+        (false, true) => flow.dst_port,
+        (true, false) => flow.src_port,
+        _ => flow.src_port.min(flow.dst_port),
+    }
+}
+
+/// Entries by decreasing bytes, the first `top` ones
+fn ranked<K>(counts: HashMap<K, Count>, top: usize, name: impl Fn(&K) -> String) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = counts
+        .iter()
+        .map(|(k, c)| Entry {
+            name: name(k),
+            packets: c.packets,
+            bytes: c.bytes,
+        })
+        .collect();
+    entries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    entries.truncate(top);
+    entries
+}
+
+fn table(out: &mut Output, title: &str, entries: &[Entry], total: &Count) -> Result<(), Error> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    out.line(&format!("{}:", title))?;
+    let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    for e in entries {
+        let share = match total.bytes {
+            0 => 0.0,
+            t => 100.0 * e.bytes as f64 / t as f64,
+        };
+        out.line(&format!(
+            "  {:width$}  {:>10} pkts  {:>14} bytes  {:5.1}%",
+            e.name,
+            e.packets,
+            e.bytes,
+            share,
+            width = width
+        ))?;
+    }
+    Ok(())
+}
+
+pub fn run(args: StatsArgs) -> Result<(), Error> {
+    let mut source = args.input.open(&args.packets)?;
+    let linktype = source.linktype();
+    let mut total = Count::default();
+    let mut captured = 0u64;
+    let precision = source.precision;
+    let mut first: Option<Timestamp> = None;
+    let mut last: Option<Timestamp> = None;
+    let mut network: HashMap<EtherType, Count> = HashMap::new();
+    let mut transport: HashMap<IpProtocol, Count> = HashMap::new();
+    let mut talkers: HashMap<IpAddr, Count> = HashMap::new();
+    let mut ports: HashMap<(IpProtocol, u16), Count> = HashMap::new();
+
+    source.each(&args.packets, |packet| {
+        let Some(packet) = packet else {
+            return Ok(());
+        };
+        let len = u64::from(packet.header.len);
+        let ts = source::timestamp(packet.header, precision);
+        first.get_or_insert(ts);
+        last = Some(ts);
+        total.add(len);
+        captured += u64::from(packet.header.caplen);
+        let summary = flow::summarize(linktype, packet.data);
+        if let Some(t) = summary.ether_type {
+            network.entry(t).or_default().add(len);
+        }
+        if let Some(f) = summary.flow {
+            transport.entry(f.proto).or_default().add(len);
+            talkers.entry(f.src).or_default().add(len);
+            talkers.entry(f.dst).or_default().add(len);
+            if f.src_port != 0 || f.dst_port != 0 {
+                ports
+                    .entry((f.proto, service_port(&f)))
+                    .or_default()
+                    .add(len);
+            }
+        }
+        Ok(())
+    })?;
+
+    let duration = match (first, last) {
+        (Some(f), Some(l)) => l.seconds_since(f),
+        _ => 0.0,
+    };
+    let rate = |n: f64| if duration > 0.0 { n / duration } else { 0.0 };
+    let stats = Stats {
+        input: source.name.clone(),
+        packets: total.packets,
+        bytes: total.bytes,
+        captured_bytes: captured,
+        first_ts: first,
+        last_ts: last,
+        duration,
+        packets_per_second: rate(total.packets as f64),
+        bits_per_second: rate(total.bytes as f64 * 8.0),
+        network: ranked(network, usize::MAX, |t| t.to_string()),
+        transport: ranked(transport, usize::MAX, |p| p.to_string()),
+        talkers: ranked(talkers, args.top, |a| a.to_string()),
+        ports: ranked(ports, args.top, |(proto, port)| {
+            let name = format!("{}/{}", proto, port);
+            match detect::by_port(*proto, *port, *port).first() {
+                Some(p) => format!("{} ({})", name, detect::protocol_as_str(*p)),
+                None => match services::service_name(*proto, *port) {
+                    Some(service) => format!("{} ({})", name, service),
+                    None => name,
+                },
+            }
+        }),
+    };
+
+    let mut out = Output::new(false)?;
+    if args.output.format() == Format::Json {
+        return out.json(&stats);
+    }
+    out.line(&format!("input: {}", stats.input))?;
+    out.line(&format!(
+        "packets: {}  bytes: {}  captured: {}",
+        stats.packets, stats.bytes, stats.captured_bytes
+    ))?;
+    if let (Some(f), Some(l)) = (stats.first_ts, stats.last_ts) {
+        out.line(&format!(
+            "first: {}  last: {}  duration: {:.3}s",
+            f, l, stats.duration
+        ))?;
+    }
+    out.line(&format!(
+        "rate: {:.1} pkts/s  {:.1} bits/s",
+        stats.packets_per_second, stats.bits_per_second
+    ))?;
+    table(&mut out, "network", &stats.network, &total)?;
+    table(&mut out, "transport", &stats.transport, &total)?;
+    table(&mut out, "talkers", &stats.talkers, &total)?;
+    table(&mut out, "ports", &stats.ports, &total)
+}
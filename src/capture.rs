@@ -0,0 +1,465 @@
+//! Reader of pcap and pcapng captures, fed by chunks
+//!
+//! The CLI reads captures with libpcap. This reader is for where there is none, such as
+//! the WebAssembly build, and for captures that arrive in pieces (file uploads, streams):
+//! bytes are pushed as they come, and records are taken out once complete, so a capture
+//! never needs to be held whole in memory. Classic pcap is read in either byte order, with
+//! micro or nanosecond timestamps; pcapng sections may each have their byte order and
+//! interfaces, and each interface its link type, timestamp resolution and offset.
+use std::fmt;
+
+use crate::timestamp::Timestamp;
+
+/// Magic of a classic pcap file, with microsecond timestamps
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+/// Magic of a classic pcap file, with nanosecond timestamps
+const PCAP_NS_MAGIC: u32 = 0xa1b2_3c4d;
+/// Type of the Section Header Block, the first block of a pcapng file
+const SHB: u32 = 0x0a0d_0d0a;
+/// Magic of a Section Header Block, giving the byte order of the section
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+/// Interface Description, Packet (obsolete), Simple Packet and Enhanced Packet Blocks
+const IDB: u32 = 1;
+const PB: u32 = 2;
+const SPB: u32 = 3;
+const EPB: u32 = 6;
+/// Option of an Interface Description Block giving its timestamp resolution
+const IF_TSRESOL: u16 = 9;
+/// Option of an Interface Description Block giving seconds to add to its timestamps
+const IF_TSOFFSET: u16 = 14;
+/// Larger records or blocks are taken for garbage rather than waited for
+const MAX_RECORD: usize = 64 * 1024 * 1024;
+
+/// Format of a capture, from its magic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Pcap,
+    /// pcap with nanosecond timestamps
+    PcapNs,
+    Pcapng,
+}
+
+impl Format {
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Pcap => "pcap",
+            Format::PcapNs => "pcap (ns)",
+            Format::Pcapng => "pcapng",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Format of a capture from its first 4 bytes
+pub fn sniff(magic: [u8; 4]) -> Option<Format> {
+    let (be, le) = (u32::from_be_bytes(magic), u32::from_le_bytes(magic));
+    if be == PCAP_MAGIC || le == PCAP_MAGIC {
+        Some(Format::Pcap)
+    } else if be == PCAP_NS_MAGIC || le == PCAP_NS_MAGIC {
+        Some(Format::PcapNs)
+    } else if be == SHB {
+        Some(Format::Pcapng)
+    } else {
+        None
+    }
+}
+
+/// Why a capture cannot be read further
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// the data does not start with a pcap or pcapng magic
+    Format,
+    /// a record or block header makes no sense, at this offset of the capture
+    Malformed { offset: u64, reason: &'static str },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Format => f.write_str("not a pcap or pcapng capture"),
+            Error::Malformed { offset, reason } => {
+                write!(f, "malformed capture at byte {}: {}", offset, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A packet of a capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// offset of the packet data in the capture
+    pub offset: u64,
+    /// interface of a pcapng capture, 0 for pcap
+    pub interface: u32,
+    pub linktype: i32,
+    pub ts: Timestamp,
+    /// length on the wire
+    pub len: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    linktype: i32,
+    /// timestamp units per second
+    units: u64,
+    /// seconds added to the timestamps
+    offset: i64,
+}
+
+#[derive(Debug)]
+enum State {
+    /// before the magic
+    Start,
+    Pcap {
+        little_endian: bool,
+        nanos: bool,
+        linktype: i32,
+    },
+    Pcapng {
+        little_endian: bool,
+        interfaces: Vec<Interface>,
+    },
+    /// an error was returned, nothing more is read
+    Failed,
+}
+
+/// Incremental reader of a capture
+#[derive(Debug)]
+pub struct Reader {
+    /// bytes pushed and not read yet, from `start`
+    buf: Vec<u8>,
+    start: usize,
+    /// offset in the capture of `buf[start]`
+    position: u64,
+    state: State,
+}
+
+impl Default for Reader {
+    fn default() -> Self {
+        Reader::new()
+    }
+}
+
+impl Reader {
+    pub fn new() -> Self {
+        Reader {
+            buf: Vec::new(),
+            start: 0,
+            position: 0,
+            state: State::Start,
+        }
+    }
+
+    /// Add the next bytes of the capture
+    pub fn push(&mut self, chunk: &[u8]) {
+        // drop what was read before growing the buffer
+        if self.start > 0 {
+            self.buf.drain(..self.start);
+            self.start = 0;
+        }
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Format of the capture, once its magic was read
+    pub fn format(&self) -> Option<Format> {
+        match self.state {
+            State::Pcap { nanos: false, .. } => Some(Format::Pcap),
+            State::Pcap { nanos: true, .. } => Some(Format::PcapNs),
+            State::Pcapng { .. } => Some(Format::Pcapng),
+            State::Start | State::Failed => None,
+        }
+    }
+
+    /// Bytes of the capture read so far
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Bytes pushed and not read yet: at the end of the capture, a truncated record
+    pub fn buffered(&self) -> usize {
+        self.buf.len() - self.start
+    }
+
+    /// Next packet of the capture, none until more bytes are pushed
+    pub fn read(&mut self) -> Result<Option<Record>, Error> {
+        let result = self.next_record();
+        if result.is_err() {
+            self.state = State::Failed;
+        }
+        result
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>, Error> {
+        loop {
+            let data = &self.buf[self.start..];
+            let (used, mut record) = match &mut self.state {
+                State::Start => match start(data)? {
+                    Some((used, state)) => {
+                        self.state = state;
+                        (used, None)
+                    }
+                    None => return Ok(None),
+                },
+                State::Pcap {
+                    little_endian,
+                    nanos,
+                    linktype,
+                } => match pcap_record(data, *little_endian, *nanos, *linktype) {
+                    Some(Ok(read)) => read,
+                    Some(Err(reason)) => return Err(self.malformed(reason)),
+                    None => return Ok(None),
+                },
+                State::Pcapng {
+                    little_endian,
+                    interfaces,
+                } => match pcapng_block(data, little_endian, interfaces) {
+                    Some(Ok(read)) => read,
+                    Some(Err(reason)) => return Err(self.malformed(reason)),
+                    None => return Ok(None),
+                },
+                State::Failed => return Ok(None),
+            };
+            if let Some(r) = record.as_mut() {
+                r.offset += self.position;
+            }
+            self.start += used;
+            self.position += used as u64;
+            if record.is_some() {
+                return Ok(record);
+            }
+        }
+    }
+
+    fn malformed(&self, reason: &'static str) -> Error {
+        Error::Malformed {
+            offset: self.position,
+            reason,
+        }
+    }
+}
+
+fn u16_at(data: &[u8], at: usize, little_endian: bool) -> u16 {
+    let b = [data[at], data[at + 1]];
+    if little_endian {
+        u16::from_le_bytes(b)
+    } else {
+        u16::from_be_bytes(b)
+    }
+}
+
+fn u32_at(data: &[u8], at: usize, little_endian: bool) -> u32 {
+    let b = [data[at], data[at + 1], data[at + 2], data[at + 3]];
+    if little_endian {
+        u32::from_le_bytes(b)
+    } else {
+        u32::from_be_bytes(b)
+    }
+}
+
+fn u64_at(data: &[u8], at: usize, little_endian: bool) -> u64 {
+    let mut b = [0u8; 8];
+    b.copy_from_slice(&data[at..at + 8]);
+    if little_endian {
+        u64::from_le_bytes(b)
+    } else {
+        u64::from_be_bytes(b)
+    }
+}
+
+/// Read the file header, or the Section Header Block of a pcapng file
+fn start(data: &[u8]) -> Result<Option<(usize, State)>, Error> {
+    let magic = match data.get(..4) {
+        Some(m) => [m[0], m[1], m[2], m[3]],
+        None => return Ok(None),
+    };
+    match sniff(magic).ok_or(Error::Format)? {
+        Format::Pcap | Format::PcapNs => {
+            if data.len() < 24 {
+                return Ok(None);
+            }
+            let little_endian = matches!(u32::from_le_bytes(magic), PCAP_MAGIC | PCAP_NS_MAGIC);
+            let state = State::Pcap {
+                little_endian,
+                nanos: sniff(magic) == Some(Format::PcapNs),
+                // the upper bits may hold the FCS length
+                linktype: (u32_at(data, 20, little_endian) & 0x0fff_ffff) as i32,
+            };
+            Ok(Some((24, state)))
+        }
+        Format::Pcapng => {
+            let mut little_endian = true;
+            let mut interfaces = Vec::new();
+            match pcapng_block(data, &mut little_endian, &mut interfaces) {
+                Some(Ok((used, _))) => Ok(Some((
+                    used,
+                    State::Pcapng {
+                        little_endian,
+                        interfaces,
+                    },
+                ))),
+                Some(Err(reason)) => Err(Error::Malformed { offset: 0, reason }),
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+/// Bytes used and record read
+type Read = (usize, Option<Record>);
+
+/// Read a record of a classic pcap file, none until it is complete
+fn pcap_record(
+    data: &[u8],
+    little_endian: bool,
+    nanos: bool,
+    linktype: i32,
+) -> Option<Result<Read, &'static str>> {
+    if data.len() < 16 {
+        return None;
+    }
+    let caplen = u32_at(data, 8, little_endian) as usize;
+    if caplen > MAX_RECORD {
+        return Some(Err("record larger than any snapshot length"));
+    }
+    let packet = data.get(16..16 + caplen)?;
+    let secs = i64::from(u32_at(data, 0, little_endian));
+    let fraction = u32_at(data, 4, little_endian);
+    let record = Record {
+        offset: 16,
+        interface: 0,
+        linktype,
+        ts: if nanos {
+            Timestamp::new(secs, fraction)
+        } else {
+            Timestamp::from_micros(secs, fraction)
+        },
+        len: u32_at(data, 12, little_endian),
+        data: packet.to_vec(),
+    };
+    Some(Ok((16 + caplen, Some(record))))
+}
+
+/// Read a block of a pcapng file, none until it is complete; the blocks that describe the
+/// capture update the byte order and interfaces of the section
+fn pcapng_block(
+    data: &[u8],
+    little_endian: &mut bool,
+    interfaces: &mut Vec<Interface>,
+) -> Option<Result<Read, &'static str>> {
+    if data.len() < 12 {
+        return None;
+    }
+    let kind = u32_at(data, 0, *little_endian);
+    if kind == SHB {
+        // a new section, with its own byte order and interfaces
+        *little_endian = match u32_at(data, 8, true) {
+            BYTE_ORDER_MAGIC => true,
+            m if m.swap_bytes() == BYTE_ORDER_MAGIC => false,
+            _ => return Some(Err("bad byte-order magic")),
+        };
+        interfaces.clear();
+    }
+    let len = u32_at(data, 4, *little_endian) as usize;
+    if len < 12 || !len.is_multiple_of(4) || len > MAX_RECORD {
+        return Some(Err("bad block length"));
+    }
+    let block = data.get(..len)?;
+    // between the type and length, and the trailing copy of the length
+    let body = &block[8..len - 4];
+    let le = *little_endian;
+    let record = match kind {
+        IDB if body.len() >= 8 => {
+            let (mut units, mut offset) = (1_000_000, 0);
+            let mut options = &body[8..];
+            while options.len() >= 4 {
+                let (code, olen) = (u16_at(options, 0, le), usize::from(u16_at(options, 2, le)));
+                let value = match options.get(4..4 + olen) {
+                    Some(v) => v,
+                    None => break,
+                };
+                if code == IF_TSRESOL && olen == 1 {
+                    units = tsresol_units(value[0]);
+                }
+                if code == IF_TSOFFSET && olen == 8 {
+                    offset = u64_at(value, 0, le) as i64;
+                }
+                if code == 0 {
+                    break;
+                }
+                options = options.get(4 + olen.div_ceil(4) * 4..).unwrap_or_default();
+            }
+            interfaces.push(Interface {
+                linktype: i32::from(u16_at(body, 0, le)),
+                units,
+                offset,
+            });
+            None
+        }
+        IDB => return Some(Err("truncated interface description")),
+        EPB | PB if body.len() >= 20 => {
+            let interface = match kind {
+                EPB => u32_at(body, 0, le),
+                _ => u32::from(u16_at(body, 0, le)),
+            };
+            let caplen = u32_at(body, 12, le) as usize;
+            let packet = match body.get(20..20 + caplen) {
+                Some(p) => p,
+                None => return Some(Err("packet larger than its block")),
+            };
+            let ts = (u64::from(u32_at(body, 4, le)) << 32) | u64::from(u32_at(body, 8, le));
+            let iface = match interfaces.get(interface as usize) {
+                Some(i) => *i,
+                None => return Some(Err("packet of an undescribed interface")),
+            };
+            Some(Record {
+                offset: 28,
+                interface,
+                linktype: iface.linktype,
+                ts: Timestamp::from_units(ts, iface.units, iface.offset),
+                len: u32_at(body, 16, le),
+                data: packet.to_vec(),
+            })
+        }
+        SPB if body.len() >= 4 => {
+            let iface = match interfaces.first() {
+                Some(i) => *i,
+                None => return Some(Err("packet of an undescribed interface")),
+            };
+            let len = u32_at(body, 0, le);
+            // the block is padded: the captured length is the smaller one
+            let caplen = (len as usize).min(body.len() - 4);
+            Some(Record {
+                offset: 12,
+                interface: 0,
+                linktype: iface.linktype,
+                // simple packets have no timestamp
+                ts: Timestamp::default(),
+                len,
+                data: body[4..4 + caplen].to_vec(),
+            })
+        }
+        EPB | PB | SPB => return Some(Err("truncated packet block")),
+        // statistics, name resolution, custom blocks...
+        _ => None,
+    };
+    Some(Ok((len, record)))
+}
+
+/// Timestamp units per second of an `if_tsresol` option: a negative power of 10, or of 2
+/// when the high bit is set
+fn tsresol_units(tsresol: u8) -> u64 {
+    let exponent = u32::from(tsresol & 0x7f);
+    match tsresol & 0x80 {
+        0 => 10u64.checked_pow(exponent),
+        _ => 2u64.checked_pow(exponent),
+    }
+    .unwrap_or(1_000_000)
+}
@@ -0,0 +1,71 @@
+//! Per-packet decode context
+//!
+//! Decoders are plain `decode(&[u8])` functions and don't know anything about the layers
+//! below them. The network and transport decoders record what they learned (addresses,
+//! ports) here, so that application decoders can key per-flow state on the packet being
+//! decoded without passing extra arguments through every layer.
+//!
+//! The context is thread local: a thread decodes one packet at a time.
+use std::cell::RefCell;
+use std::net::IpAddr;
+use std::time::SystemTime;
+
+use crate::flow::FlowKey;
+use crate::protocols::ip::IpProtocol;
+
+#[derive(Default)]
+struct Current {
+    addrs: Option<(IpAddr, IpAddr)>,
+    flow: Option<FlowKey>,
+    time: Option<SystemTime>,
+}
+
+thread_local! {
+    static CURRENT: RefCell<Current> = RefCell::new(Current::default());
+}
+
+/// Reset the context, to be called before decoding a new frame
+pub fn begin_packet() {
+    CURRENT.with(|c| *c.borrow_mut() = Current::default());
+}
+
+/// Record the network addresses of the packet (the innermost ip header wins)
+pub fn set_addrs(src: IpAddr, dst: IpAddr) {
+    CURRENT.with(|c| {
+        let mut c = c.borrow_mut();
+        c.addrs = Some((src, dst));
+        c.flow = None;
+    });
+}
+
+/// Network addresses of the packet being decoded, source first
+pub fn addrs() -> Option<(IpAddr, IpAddr)> {
+    CURRENT.with(|c| c.borrow().addrs)
+}
+
+/// Record the transport protocol and ports, completing the flow key
+pub fn set_ports(proto: IpProtocol, src_port: u16, dst_port: u16) {
+    CURRENT.with(|c| {
+        let mut c = c.borrow_mut();
+        if let Some((src, dst)) = c.addrs {
+            c.flow = Some(FlowKey::new(proto, src, src_port, dst, dst_port));
+        }
+    });
+}
+
+/// Flow of the packet being decoded, oriented as seen on the wire
+pub fn flow() -> Option<FlowKey> {
+    CURRENT.with(|c| c.borrow().flow)
+}
+
+/// Record the capture timestamp of the packet
+pub fn set_time(time: SystemTime) {
+    CURRENT.with(|c| c.borrow_mut().time = Some(time));
+}
+
+/// Capture timestamp of the packet being decoded, the current time if unknown
+pub fn time() -> SystemTime {
+    CURRENT
+        .with(|c| c.borrow().time)
+        .unwrap_or_else(SystemTime::now)
+}
@@ -0,0 +1,113 @@
+//! Structured events produced while decoding
+//!
+//! Decoders print a human readable line per layer; anything worth keeping beyond
+//! that line (fingerprints, findings, ...) is emitted here as an `Event`. The binaries
+//! drain the queue after each packet and print the events, as JSON lines if asked to.
+use serde::Serialize;
+use std::cell::RefCell;
+use std::net::SocketAddr;
+
+use crate::analysis::credentials::Credential;
+use crate::analysis::extract::ExtractedFile;
+use crate::analysis::fingerprint::{FingerprintMatch, TlsSession};
+use crate::analysis::geoip::{self, GeoInfo};
+use crate::analysis::ioc::IocMatch;
+use crate::analysis::rules::Alert;
+use crate::analysis::tls_policy::{PolicyReport, Violation};
+use crate::analysis::x509::CertificateChain;
+use crate::protocols::detect::Mismatch;
+use crate::protocols::http::Exchange;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+pub enum Event {
+    /// TLS hello fingerprints of a session
+    Tls(TlsSession),
+    /// A TLS fingerprint matched the local fingerprint list
+    FingerprintMatch(FingerprintMatch),
+    /// Certificate chain of a server and its audit findings
+    Certificates(CertificateChain),
+    /// A TLS session or hello broke the version/cipher policy
+    TlsPolicy(Violation),
+    /// Per server and client summary of the policy checks
+    TlsPolicyReport(PolicyReport),
+    /// HTTP/1.x request and its response
+    Http(Exchange),
+    /// Object carried by a connection, written to the extraction directory
+    File(ExtractedFile),
+    /// Login or secret sent in the clear
+    Credential(Credential),
+    /// A signature matched
+    Alert(Alert),
+    /// Traffic matched an indicator of a feed
+    IocMatch(IocMatch),
+    /// A port announced another protocol than its flow carries
+    ProtocolMismatch(Mismatch),
+}
+
+impl Event {
+    /// Client and server of the event, or source and destination of an alert
+    pub fn endpoints(&self) -> Option<(SocketAddr, SocketAddr)> {
+        let flow = match self {
+            Event::Tls(e) => e.flow,
+            Event::FingerprintMatch(e) => e.flow,
+            Event::Certificates(e) => e.flow,
+            Event::TlsPolicy(e) => Some(e.flow),
+            Event::TlsPolicyReport(_) => None,
+            Event::Http(e) => Some(e.flow),
+            Event::File(e) => Some(e.flow),
+            Event::Credential(e) => e.flow,
+            Event::Alert(e) => {
+                return Some((
+                    SocketAddr::new(e.src_ip, e.src_port),
+                    SocketAddr::new(e.dest_ip, e.dest_port),
+                ))
+            }
+            Event::IocMatch(e) => e.flow,
+            Event::ProtocolMismatch(e) => Some(e.flow),
+        }?;
+        Some((
+            SocketAddr::new(flow.src, flow.src_port),
+            SocketAddr::new(flow.dst, flow.dst_port),
+        ))
+    }
+}
+
+/// Endpoints of an event as described by the GeoIP databases
+#[derive(Serialize)]
+struct Geo {
+    src: GeoInfo,
+    dst: GeoInfo,
+}
+
+/// JSON line of an event, with a `geo` object describing its endpoints when GeoIP
+/// databases are loaded
+pub fn to_json(event: &Event) -> String {
+    let mut json = serde_json::to_string(event).unwrap_or_default();
+    if let (true, Some((src, dst))) = (geoip::enabled(), event.endpoints()) {
+        let geo = serde_json::to_string(&Geo {
+            src: geoip::enrich(src.ip()),
+            dst: geoip::enrich(dst.ip()),
+        })
+        .unwrap_or_default();
+        // events are objects: append the member, keeping the order of the others
+        if json.pop() == Some('}') {
+            json = format!("{},\"geo\":{}}}", json, geo);
+        }
+    }
+    json
+}
+
+thread_local! {
+    static QUEUE: RefCell<Vec<Event>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Queue an event for the current thread
+pub fn emit(event: Event) {
+    QUEUE.with(|q| q.borrow_mut().push(event));
+}
+
+/// Take all the queued events
+pub fn drain() -> Vec<Event> {
+    QUEUE.with(|q| std::mem::take(&mut *q.borrow_mut()))
+}
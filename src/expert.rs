@@ -0,0 +1,220 @@
+//! Expert info: what a decoder found wrong or worth noticing in a packet
+//!
+//! Decoders report malformed, truncated or unusual data here instead of printing it, as
+//! Wireshark's expert info does. Items carry a severity, the layer and the offset of the
+//! byte they are about, and a kind to count and filter them by. Like events, they are
+//! queued per thread and drained after each packet, so they stay attached to the packet
+//! being decoded.
+use serde::Serialize;
+use std::cell::RefCell;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// unusual but valid, such as a fragment
+    Note,
+    /// suspicious, or an error that may come from the capture, such as a checksum
+    /// computed by the network card
+    Warning,
+    /// the packet breaks its protocol
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// Why a layer could not be decoded
+///
+/// `layer` names the decoder (`ipv4`, `dns`, ...) and `offset` is the byte the error is
+/// about, from the start of that layer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodeError {
+    /// the data ended before a field
+    Truncated { layer: &'static str, offset: usize },
+    /// a field has a value the protocol does not allow
+    Malformed {
+        layer: &'static str,
+        offset: usize,
+        reason: &'static str,
+    },
+    UnsupportedVersion {
+        layer: &'static str,
+        offset: usize,
+        version: u32,
+    },
+    BadChecksum {
+        layer: &'static str,
+        offset: usize,
+        expected: u32,
+        found: u32,
+    },
+    /// a length field disagrees with the data it describes
+    LengthMismatch {
+        layer: &'static str,
+        offset: usize,
+        declared: usize,
+        actual: usize,
+    },
+}
+
+impl DecodeError {
+    pub fn layer(&self) -> &'static str {
+        match self {
+            DecodeError::Truncated { layer, .. }
+            | DecodeError::Malformed { layer, .. }
+            | DecodeError::UnsupportedVersion { layer, .. }
+            | DecodeError::BadChecksum { layer, .. }
+            | DecodeError::LengthMismatch { layer, .. } => layer,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        match self {
+            DecodeError::Truncated { offset, .. }
+            | DecodeError::Malformed { offset, .. }
+            | DecodeError::UnsupportedVersion { offset, .. }
+            | DecodeError::BadChecksum { offset, .. }
+            | DecodeError::LengthMismatch { offset, .. } => *offset,
+        }
+    }
+
+    /// Name of the variant, as reported in expert info
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DecodeError::Truncated { .. } => "truncated",
+            DecodeError::Malformed { .. } => "malformed",
+            DecodeError::UnsupportedVersion { .. } => "unsupported_version",
+            DecodeError::BadChecksum { .. } => "bad_checksum",
+            DecodeError::LengthMismatch { .. } => "length_mismatch",
+        }
+    }
+
+    /// Severity of the error as expert info: checksums are often left to the network card
+    /// on the capturing host, so a bad one is only a warning
+    pub fn severity(&self) -> Severity {
+        match self {
+            DecodeError::BadChecksum { .. } => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated { layer, offset } => {
+                write!(f, "{}: truncated at byte {}", layer, offset)
+            }
+            DecodeError::Malformed {
+                layer,
+                offset,
+                reason,
+            } => write!(f, "{}: malformed at byte {}: {}", layer, offset, reason),
+            DecodeError::UnsupportedVersion { layer, version, .. } => {
+                write!(f, "{}: unsupported version {}", layer, version)
+            }
+            DecodeError::BadChecksum {
+                layer,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "{}: bad checksum 0x{:04x}, expected 0x{:04x}",
+                layer, found, expected
+            ),
+            DecodeError::LengthMismatch {
+                layer,
+                offset,
+                declared,
+                actual,
+            } => write!(
+                f,
+                "{}: length at byte {} is {} but {} bytes follow",
+                layer, offset, declared, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// An expert info item of a packet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExpertInfo {
+    pub severity: Severity,
+    /// what the item is about: the kind of a `DecodeError`, or of a note
+    pub kind: &'static str,
+    pub layer: &'static str,
+    /// from the start of the layer
+    pub offset: usize,
+    pub message: String,
+}
+
+impl From<DecodeError> for ExpertInfo {
+    fn from(e: DecodeError) -> Self {
+        ExpertInfo {
+            severity: e.severity(),
+            kind: e.kind(),
+            layer: e.layer(),
+            offset: e.offset(),
+            message: e.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ExpertInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.severity, self.message)
+    }
+}
+
+thread_local! {
+    static ITEMS: RefCell<Vec<ExpertInfo>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Attach an item to the packet being decoded
+pub fn add(item: ExpertInfo) {
+    ITEMS.with(|i| i.borrow_mut().push(item));
+}
+
+/// Report an error of the packet being decoded
+pub fn error(e: DecodeError) {
+    add(e.into());
+}
+
+/// Report something unusual but valid in the packet being decoded
+pub fn note(layer: &'static str, offset: usize, kind: &'static str, message: String) {
+    add(ExpertInfo {
+        severity: Severity::Note,
+        kind,
+        layer,
+        offset,
+        message: format!("{}: {}", layer, message),
+    });
+}
+
+/// Report something valid that the decoder could not follow in the packet being decoded
+pub fn warning(layer: &'static str, offset: usize, kind: &'static str, message: String) {
+    add(ExpertInfo {
+        severity: Severity::Warning,
+        kind,
+        layer,
+        offset,
+        message: format!("{}: {}", layer, message),
+    });
+}
+
+/// Take the items of the packet decoded
+pub fn drain() -> Vec<ExpertInfo> {
+    ITEMS.with(|i| std::mem::take(&mut *i.borrow_mut()))
+}
@@ -0,0 +1,329 @@
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::protocols::detect::{self, Protocol};
+use crate::protocols::ethernet::EtherType;
+use crate::protocols::ip::IpProtocol;
+use crate::timestamp::Timestamp;
+use crate::zeek;
+
+/// Direction of a packet relative to the canonical orientation of its flow
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// packet goes from the lower endpoint to the higher one
+    Forward,
+    /// packet goes from the higher endpoint to the lower one
+    Reverse,
+}
+
+/// Transport 5-tuple of a packet
+///
+/// The key is oriented as seen on the wire: `src` is the sender of the packet.
+/// Use `canonical` to get a key shared by both directions of the conversation,
+/// usable as a map key for per-flow state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct FlowKey {
+    pub proto: IpProtocol,
+    pub src: IpAddr,
+    pub src_port: u16,
+    pub dst: IpAddr,
+    pub dst_port: u16,
+}
+
+impl FlowKey {
+    pub fn new(proto: IpProtocol, src: IpAddr, src_port: u16, dst: IpAddr, dst_port: u16) -> Self {
+        FlowKey {
+            proto,
+            src,
+            src_port,
+            dst,
+            dst_port,
+        }
+    }
+
+    /// Same flow seen from the other endpoint
+    pub fn reversed(&self) -> Self {
+        FlowKey::new(self.proto, self.dst, self.dst_port, self.src, self.src_port)
+    }
+
+    /// Orientation independent key and the direction of this packet within it
+    pub fn canonical(&self) -> (FlowKey, Direction) {
+        if (self.src, self.src_port) <= (self.dst, self.dst_port) {
+            (*self, Direction::Forward)
+        } else {
+            (self.reversed(), Direction::Reverse)
+        }
+    }
+
+    /// Identifier of the conversation, the same for both directions
+    pub fn id(&self) -> u64 {
+        let mut h = DefaultHasher::new();
+        self.canonical().0.hash(&mut h);
+        // JSON consumers read numbers as doubles
+        h.finish() & ((1 << 53) - 1)
+    }
+}
+
+impl fmt::Display for FlowKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} -> {}",
+            self.proto,
+            endpoint(self.src, self.src_port),
+            endpoint(self.dst, self.dst_port)
+        )
+    }
+}
+
+fn endpoint(addr: IpAddr, port: u16) -> String {
+    match addr {
+        IpAddr::V4(a) => format!("{}:{}", a, port),
+        IpAddr::V6(a) => format!("[{}]:{}", a, port),
+    }
+}
+
+/// Network and transport layers of a packet, read without decoding its payload
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    /// EtherType of the network layer, after VLAN tags
+    pub ether_type: Option<EtherType>,
+    /// flow of IP packets; ports are zero for transports without them and for fragments
+    pub flow: Option<FlowKey>,
+    pub tcp_flags: Option<u8>,
+}
+
+/// Read the layers of a packet up to its flow, for the usual pcap link types
+pub fn summarize(linktype: i32, data: &[u8]) -> Summary {
+    let (ether_type, network) = match linktype {
+        // LINKTYPE_ETHERNET, with 802.1Q/802.1ad tags
+        1 => {
+            let mut offset = 12;
+            let mut ether_type = match data.get(offset..offset + 2) {
+                Some(t) => EtherType::from(u16::from_be_bytes([t[0], t[1]])),
+                None => return Summary::default(),
+            };
+            while let EtherType::Vlan | EtherType::ServiceVlan = ether_type {
+                offset += 4;
+                ether_type = match data.get(offset..offset + 2) {
+                    Some(t) => EtherType::from(u16::from_be_bytes([t[0], t[1]])),
+                    None => return Summary::default(),
+                };
+            }
+            (ether_type, &data[offset + 2..])
+        }
+        // LINKTYPE_NULL and LINKTYPE_LOOP: address family in host or network order
+        0 | 108 => match data.get(..4) {
+            Some(family) => {
+                let family = u32::from_le_bytes([family[0], family[1], family[2], family[3]]);
+                let ether_type = match family {
+                    2 | 0x0200_0000 => EtherType::Ipv4,
+                    24 | 28 | 30 | 0x1800_0000 | 0x1c00_0000 | 0x1e00_0000 => EtherType::Ipv6,
+                    _ => return Summary::default(),
+                };
+                (ether_type, &data[4..])
+            }
+            None => return Summary::default(),
+        },
+        // LINKTYPE_LINUX_SLL
+        113 => match data.get(14..16) {
+            Some(t) => (
+                EtherType::from(u16::from_be_bytes([t[0], t[1]])),
+                &data[16..],
+            ),
+            None => return Summary::default(),
+        },
+        // LINKTYPE_RAW, LINKTYPE_IPV4, LINKTYPE_IPV6
+        12 | 14 | 101 | 228 | 229 => match data.first().map(|b| b >> 4) {
+            Some(4) => (EtherType::Ipv4, data),
+            Some(6) => (EtherType::Ipv6, data),
+            _ => return Summary::default(),
+        },
+        _ => return Summary::default(),
+    };
+    let mut summary = Summary {
+        ether_type: Some(ether_type),
+        ..Summary::default()
+    };
+    let (proto, src, dst, transport) = match ether_type {
+        EtherType::Ipv4 => match network {
+            [vihl, _, _, _, _, _, frag_hi, frag_lo, _, proto, _, _, rest @ ..]
+                if rest.len() >= 8 && vihl >> 4 == 4 =>
+            {
+                let src = Ipv4Addr::new(rest[0], rest[1], rest[2], rest[3]);
+                let dst = Ipv4Addr::new(rest[4], rest[5], rest[6], rest[7]);
+                let ihl = usize::from(vihl & 0x0f) * 4;
+                // only the first fragment has the transport header
+                let offset = u16::from_be_bytes([*frag_hi, *frag_lo]) & 0x1fff;
+                let transport = match offset {
+                    0 => network.get(ihl..).unwrap_or_default(),
+                    _ => &[],
+                };
+                (
+                    IpProtocol::from(*proto),
+                    IpAddr::V4(src),
+                    IpAddr::V4(dst),
+                    transport,
+                )
+            }
+            _ => return summary,
+        },
+        EtherType::Ipv6 if network.len() >= 40 => {
+            let src: [u8; 16] = network[8..24].try_into().unwrap_or_default();
+            let dst: [u8; 16] = network[24..40].try_into().unwrap_or_default();
+            let mut proto = IpProtocol::from(network[6]);
+            let mut offset = 40;
+            // skip the extension headers to reach the transport
+            loop {
+                match proto {
+                    IpProtocol::Hopopt | IpProtocol::Ipv6Route | IpProtocol::Ipv6Opts => {
+                        match network.get(offset..offset + 2) {
+                            Some(h) => {
+                                proto = IpProtocol::from(h[0]);
+                                offset += (usize::from(h[1]) + 1) * 8;
+                            }
+                            None => break,
+                        }
+                    }
+                    IpProtocol::Ipv6Frag => match network.get(offset..offset + 4) {
+                        Some(h) => {
+                            proto = IpProtocol::from(h[0]);
+                            offset += 8;
+                            if u16::from_be_bytes([h[2], h[3]]) & 0xfff8 != 0 {
+                                offset = network.len();
+                            }
+                        }
+                        None => break,
+                    },
+                    _ => break,
+                }
+            }
+            let transport = network.get(offset..).unwrap_or_default();
+            (
+                proto,
+                IpAddr::V6(Ipv6Addr::from(src)),
+                IpAddr::V6(Ipv6Addr::from(dst)),
+                transport,
+            )
+        }
+        _ => return summary,
+    };
+    let (src_port, dst_port) = match (proto, transport) {
+        (
+            IpProtocol::Tcp | IpProtocol::Udp | IpProtocol::Sctp | IpProtocol::Dccp,
+            [a, b, c, d, ..],
+        ) => (u16::from_be_bytes([*a, *b]), u16::from_be_bytes([*c, *d])),
+        _ => (0, 0),
+    };
+    if proto == IpProtocol::Tcp {
+        summary.tcp_flags = transport.get(13).copied();
+    }
+    summary.flow = Some(FlowKey::new(proto, src, src_port, dst, dst_port));
+    summary
+}
+
+/// A conversation, oriented from the endpoint that sent the first packet
+#[derive(Debug, Clone, Serialize)]
+pub struct Conversation {
+    pub uid: String,
+    #[serde(flatten)]
+    pub flow: FlowKey,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service: Option<Protocol>,
+    pub first_ts: Timestamp,
+    pub last_ts: Timestamp,
+    /// packets and bytes sent by the originator
+    pub orig_packets: u64,
+    pub orig_bytes: u64,
+    /// packets and bytes sent by the responder
+    pub resp_packets: u64,
+    pub resp_bytes: u64,
+    /// TCP flags seen in either direction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcp_flags: Option<u8>,
+}
+
+impl Conversation {
+    pub fn packets(&self) -> u64 {
+        self.orig_packets + self.resp_packets
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.orig_bytes + self.resp_bytes
+    }
+
+    /// Seconds from the first packet to the last
+    pub fn duration(&self) -> f64 {
+        self.last_ts.seconds_since(self.first_ts)
+    }
+}
+
+/// Conversations of a capture, from the summaries of its packets
+#[derive(Debug, Default)]
+pub struct Conversations {
+    /// canonical key -> conversation and the direction of its originator
+    flows: HashMap<FlowKey, (Conversation, Direction)>,
+}
+
+impl Conversations {
+    /// Count a packet of `len` bytes on the wire, returning its conversation
+    pub fn add(&mut self, summary: &Summary, ts: Timestamp, len: u64) -> Option<&Conversation> {
+        let key = summary.flow?;
+        let (canonical, direction) = key.canonical();
+        let (c, orig) = self.flows.entry(canonical).or_insert_with(|| {
+            let service = detect::by_port(key.proto, key.src_port, key.dst_port)
+                .first()
+                .copied();
+            let conversation = Conversation {
+                uid: zeek::uid(&key),
+                flow: key,
+                service,
+                first_ts: ts,
+                last_ts: ts,
+                orig_packets: 0,
+                orig_bytes: 0,
+                resp_packets: 0,
+                resp_bytes: 0,
+                tcp_flags: None,
+            };
+            (conversation, direction)
+        });
+        c.last_ts = ts;
+        if direction == *orig {
+            c.orig_packets += 1;
+            c.orig_bytes += len;
+        } else {
+            c.resp_packets += 1;
+            c.resp_bytes += len;
+        }
+        if let Some(flags) = summary.tcp_flags {
+            c.tcp_flags = Some(c.tcp_flags.unwrap_or(0) | flags);
+        }
+        Some(c)
+    }
+
+    /// Conversation of a flow, in either direction
+    pub fn get(&self, key: &FlowKey) -> Option<&Conversation> {
+        self.flows.get(&key.canonical().0).map(|(c, _)| c)
+    }
+
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+
+    /// The conversations, by first packet
+    pub fn to_vec(&self) -> Vec<Conversation> {
+        let mut flows: Vec<Conversation> = self.flows.values().map(|(c, _)| c.clone()).collect();
+        flows.sort_by(|a, b| a.first_ts.cmp(&b.first_ts).then_with(|| a.uid.cmp(&b.uid)));
+        flows
+    }
+}
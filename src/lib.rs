@@ -1,2 +1,15 @@
+pub mod analysis;
+pub mod capture;
+pub mod context;
+pub mod event;
+pub mod expert;
+pub mod flow;
+pub mod matcher;
+pub mod pipeline;
 pub mod protocols;
+pub mod reassembly;
+pub mod savefile;
+pub mod timestamp;
+pub mod tree;
 pub mod utils;
+pub mod zeek;
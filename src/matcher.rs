@@ -0,0 +1,451 @@
+//! Multi-pattern matcher (Aho-Corasick)
+//!
+//! Thousands of literal patterns are compiled once into an automaton, then each payload is
+//! scanned a single time whatever the number of patterns, reporting every occurrence with
+//! its offsets. Scanning borrows the payload and allocates nothing; a `Scanner` carries the
+//! automaton state from one chunk of a stream to the next, so reassembled data is matched
+//! across chunks without being put back together.
+//!
+//! Case sensitive and `nocase` patterns live in two automata, the second one fed with
+//! ASCII lowercased bytes. Patterns are written as in Snort/Suricata contents: text with
+//! `|de ad be ef|` hex blocks.
+
+/// Automaton state
+type StateId = u32;
+
+const ROOT: StateId = 0;
+
+/// A pattern to look for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    pub bytes: Vec<u8>,
+    /// match whatever the ASCII case
+    pub nocase: bool,
+}
+
+impl Pattern {
+    pub fn literal(bytes: &[u8], nocase: bool) -> Self {
+        Pattern {
+            bytes: bytes.to_vec(),
+            nocase,
+        }
+    }
+
+    /// Pattern in content notation, `"GET |20|/"` being the bytes `GET /`
+    pub fn parse(s: &str, nocase: bool) -> Result<Self, String> {
+        Ok(Pattern {
+            bytes: parse_bytes(s)?,
+            nocase,
+        })
+    }
+}
+
+/// Bytes of a pattern in content notation: text with `|xx xx|` hex blocks and `\` escapes
+pub fn parse_bytes(s: &str) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(s.len());
+    let mut hex = false;
+    let mut digits = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (hex, c) {
+            (false, '|') => hex = true,
+            (false, '\\') => {
+                let e = chars.next().ok_or("pattern ends with an escape")?;
+                let mut buf = [0u8; 4];
+                out.extend_from_slice(e.encode_utf8(&mut buf).as_bytes());
+            }
+            (false, _) => {
+                let mut buf = [0u8; 4];
+                out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+            (true, '|') => {
+                if !digits.is_empty() {
+                    return Err(format!("odd hex digits in {:?}", s));
+                }
+                hex = false;
+            }
+            (true, ' ') => {}
+            (true, _) => {
+                digits.push(c);
+                if digits.len() == 2 {
+                    let b = u8::from_str_radix(&digits, 16)
+                        .map_err(|_| format!("bad hex {:?} in {:?}", digits, s))?;
+                    out.push(b);
+                    digits.clear();
+                }
+            }
+        }
+    }
+    if hex {
+        return Err(format!("unterminated hex block in {:?}", s));
+    }
+    if out.is_empty() {
+        return Err("empty pattern".to_string());
+    }
+    Ok(out)
+}
+
+/// An occurrence of a pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Match {
+    /// index of the pattern, in the order given to `Matcher::new`
+    pub pattern: usize,
+    /// offset of the first byte; for a `Scanner`, from the start of the stream
+    pub start: usize,
+    /// offset after the last byte
+    pub end: usize,
+}
+
+#[derive(Debug, Default, Clone)]
+struct State {
+    /// sorted by byte
+    next: Vec<(u8, StateId)>,
+    /// longest proper suffix of the state which is also a state
+    fail: StateId,
+    /// patterns ending here, including those of the suffixes
+    out: Vec<u32>,
+}
+
+/// Trie of the patterns with failure links
+#[derive(Debug, Clone)]
+struct Automaton {
+    states: Vec<State>,
+    /// transitions of the root, taken on every mismatch
+    root: Box<[StateId; 256]>,
+    /// ASCII lowercase the input first
+    fold: bool,
+}
+
+impl Automaton {
+    fn new(fold: bool) -> Self {
+        Automaton {
+            states: vec![State::default()],
+            root: Box::new([ROOT; 256]),
+            fold,
+        }
+    }
+
+    fn insert(&mut self, bytes: &[u8], id: u32) {
+        let mut s = ROOT;
+        for &b in bytes {
+            let b = if self.fold { b.to_ascii_lowercase() } else { b };
+            let next = &self.states[s as usize].next;
+            s = match next.binary_search_by_key(&b, |t| t.0) {
+                Ok(i) => next[i].1,
+                Err(i) => {
+                    let id = self.states.len() as StateId;
+                    self.states.push(State::default());
+                    self.states[s as usize].next.insert(i, (b, id));
+                    id
+                }
+            };
+        }
+        self.states[s as usize].out.push(id);
+    }
+
+    /// Compute the failure links breadth first, and the root table
+    fn build(&mut self) {
+        let mut queue = std::collections::VecDeque::new();
+        for &(b, s) in &self.states[ROOT as usize].next {
+            self.root[b as usize] = s;
+            queue.push_back(s);
+        }
+        while let Some(s) = queue.pop_front() {
+            let next = self.states[s as usize].next.clone();
+            for (b, child) in next {
+                let mut f = self.states[s as usize].fail;
+                let fail = loop {
+                    if let Some(t) = self.transition(f, b) {
+                        break t;
+                    }
+                    if f == ROOT {
+                        break ROOT;
+                    }
+                    f = self.states[f as usize].fail;
+                };
+                self.states[child as usize].fail = fail;
+                let inherited = self.states[fail as usize].out.clone();
+                self.states[child as usize].out.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+    }
+
+    fn transition(&self, s: StateId, b: u8) -> Option<StateId> {
+        if s == ROOT {
+            return Some(self.root[b as usize]).filter(|&t| t != ROOT);
+        }
+        let next = &self.states[s as usize].next;
+        next.binary_search_by_key(&b, |t| t.0)
+            .ok()
+            .map(|i| next[i].1)
+    }
+
+    #[inline]
+    fn step(&self, mut s: StateId, b: u8) -> StateId {
+        // no pattern
+        if self.states.len() == 1 {
+            return ROOT;
+        }
+        let b = if self.fold { b.to_ascii_lowercase() } else { b };
+        loop {
+            if s == ROOT {
+                return self.root[b as usize];
+            }
+            if let Some(t) = self.transition(s, b) {
+                return t;
+            }
+            s = self.states[s as usize].fail;
+        }
+    }
+
+    fn outputs(&self, s: StateId) -> &[u32] {
+        &self.states[s as usize].out
+    }
+}
+
+/// Compiled set of patterns
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    patterns: Vec<Pattern>,
+    exact: Automaton,
+    folded: Automaton,
+}
+
+impl Matcher {
+    /// Compile the patterns, empty ones never match
+    pub fn new(patterns: Vec<Pattern>) -> Self {
+        let mut exact = Automaton::new(false);
+        let mut folded = Automaton::new(true);
+        for (id, p) in patterns.iter().enumerate() {
+            if p.bytes.is_empty() {
+                continue;
+            }
+            if p.nocase {
+                folded.insert(&p.bytes, id as u32);
+            } else {
+                exact.insert(&p.bytes, id as u32);
+            }
+        }
+        exact.build();
+        folded.build();
+        Matcher {
+            patterns,
+            exact,
+            folded,
+        }
+    }
+
+    pub fn pattern(&self, id: usize) -> &Pattern {
+        &self.patterns[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Every occurrence of the patterns in `data`, by end offset
+    pub fn find_iter<'a>(&'a self, data: &'a [u8]) -> Matches<'a> {
+        Matches::new(self, data, Cursor::Owned(Scanner::new()))
+    }
+
+    /// Whether any pattern occurs in `data`
+    pub fn is_match(&self, data: &[u8]) -> bool {
+        self.find_iter(data).next().is_some()
+    }
+}
+
+/// Position of a scan in a stream
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scanner {
+    exact: StateId,
+    folded: StateId,
+    /// bytes scanned so far
+    offset: usize,
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan the next chunk of the stream, matches may start in previous chunks
+    ///
+    /// The state moves as the iterator goes, it has to be run to the end before feeding
+    /// the next chunk.
+    pub fn feed<'a>(&'a mut self, matcher: &'a Matcher, data: &'a [u8]) -> Matches<'a> {
+        Matches::new(matcher, data, Cursor::Borrowed(self))
+    }
+
+    /// Bytes of the stream scanned so far
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+enum Cursor<'a> {
+    Owned(Scanner),
+    Borrowed(&'a mut Scanner),
+}
+
+impl Cursor<'_> {
+    fn get(&mut self) -> &mut Scanner {
+        match self {
+            Cursor::Owned(s) => s,
+            Cursor::Borrowed(s) => s,
+        }
+    }
+}
+
+/// Iterator over the matches of a scan
+pub struct Matches<'a> {
+    matcher: &'a Matcher,
+    data: &'a [u8],
+    pos: usize,
+    cursor: Cursor<'a>,
+    /// matches ending at the last byte not yet returned
+    pending: (&'a [u32], &'a [u32]),
+    end: usize,
+}
+
+impl<'a> Matches<'a> {
+    fn new(matcher: &'a Matcher, data: &'a [u8], cursor: Cursor<'a>) -> Self {
+        Matches {
+            matcher,
+            data,
+            pos: 0,
+            cursor,
+            pending: (&[], &[]),
+            end: 0,
+        }
+    }
+
+    fn found(&self, id: u32) -> Match {
+        let len = self.matcher.patterns[id as usize].bytes.len();
+        Match {
+            pattern: id as usize,
+            start: self.end - len,
+            end: self.end,
+        }
+    }
+}
+
+impl Iterator for Matches<'_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        loop {
+            if let Some((&id, rest)) = self.pending.0.split_first() {
+                self.pending.0 = rest;
+                return Some(self.found(id));
+            }
+            if let Some((&id, rest)) = self.pending.1.split_first() {
+                self.pending.1 = rest;
+                return Some(self.found(id));
+            }
+            let &b = self.data.get(self.pos)?;
+            self.pos += 1;
+            let matcher = self.matcher;
+            let scanner = self.cursor.get();
+            scanner.exact = matcher.exact.step(scanner.exact, b);
+            scanner.folded = matcher.folded.step(scanner.folded, b);
+            scanner.offset += 1;
+            self.end = scanner.offset;
+            self.pending = (
+                matcher.exact.outputs(scanner.exact),
+                matcher.folded.outputs(scanner.folded),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(patterns: &[(&str, bool)]) -> Matcher {
+        Matcher::new(
+            patterns
+                .iter()
+                .map(|(p, nocase)| Pattern::literal(p.as_bytes(), *nocase))
+                .collect(),
+        )
+    }
+
+    /// Matches as (pattern, start, end), by end then pattern
+    fn found(m: &Matcher, data: &[u8]) -> Vec<(usize, usize, usize)> {
+        let mut v: Vec<_> = m
+            .find_iter(data)
+            .map(|m| (m.pattern, m.start, m.end))
+            .collect();
+        v.sort_by_key(|&(p, _, end)| (end, p));
+        v
+    }
+
+    #[test]
+    fn overlapping_patterns() {
+        let m = matcher(&[
+            ("he", false),
+            ("she", false),
+            ("his", false),
+            ("hers", false),
+        ]);
+        assert_eq!(found(&m, b"ushers"), [(0, 2, 4), (1, 1, 4), (3, 2, 6)]);
+        assert_eq!(found(&m, b"hishe"), [(2, 0, 3), (0, 3, 5), (1, 2, 5)]);
+        // a pattern inside another one, and repeated occurrences
+        let m = matcher(&[("aa", false), ("a", false)]);
+        assert_eq!(
+            found(&m, b"aaa"),
+            [(1, 0, 1), (0, 0, 2), (1, 1, 2), (0, 1, 3), (1, 2, 3)]
+        );
+    }
+
+    #[test]
+    fn case() {
+        let m = matcher(&[("abc", true), ("ABC", false)]);
+        assert_eq!(
+            found(&m, b"xAbC ABC abc"),
+            [(0, 1, 4), (0, 5, 8), (1, 5, 8), (0, 9, 12)]
+        );
+        // only ASCII letters fold
+        let m = matcher(&[("\u{e9}t\u{e9}", true)]);
+        assert!(m.is_match("\u{e9}T\u{e9}".as_bytes()));
+        assert!(!m.is_match("\u{c9}T\u{c9}".as_bytes()));
+        assert!(!matcher(&[("x", true)]).is_match(b""));
+    }
+
+    #[test]
+    fn scanner_across_chunks() {
+        let m = matcher(&[("hello", false), ("lo w", true)]);
+        let mut scanner = Scanner::new();
+        assert_eq!(scanner.feed(&m, b"say hel").count(), 0);
+        let second: Vec<Match> = scanner.feed(&m, b"LO W").collect();
+        assert_eq!(
+            second,
+            [Match {
+                pattern: 1,
+                start: 7,
+                end: 11
+            }]
+        );
+        let third: Vec<Match> = scanner.feed(&m, b"orld hello").collect();
+        assert_eq!(third.len(), 1);
+        assert_eq!((third[0].pattern, third[0].start), (0, 16));
+        assert_eq!(scanner.offset(), 21);
+    }
+
+    #[test]
+    fn content_notation() {
+        assert_eq!(parse_bytes("GET|20 2f|").unwrap(), b"GET /");
+        assert_eq!(parse_bytes(r"a\|b\;").unwrap(), b"a|b;");
+        assert_eq!(parse_bytes("|de ad|be|ef|").unwrap(), b"\xde\xadbe\xef");
+        assert!(parse_bytes("|abc|").is_err());
+        assert!(parse_bytes("|ab").is_err());
+        assert!(parse_bytes("|zz|").is_err());
+        assert!(parse_bytes("").is_err());
+    }
+}
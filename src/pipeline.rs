@@ -0,0 +1,312 @@
+//! Multi-threaded decoding, sharded by flow
+//!
+//! The thread reading the capture hands every packet to one of N workers through a bounded
+//! queue and the workers decode in parallel. Their results are put back in capture order
+//! and delivered on the reading thread, where the output state (logs, thread local time and
+//! databases) lives.
+//!
+//! Decoder state is kept per thread (reassembly, sessions, the analyses), so all the
+//! packets of a conversation must reach the same worker. Packets are sharded by a symmetric
+//! hash of their two IP addresses: both directions of a flow, the fragments of a datagram and
+//! the connections a protocol opens between the same hosts (FTP data, TFTP transfers, TLS
+//! retries) stay together. Packets without an IP layer go to the first worker.
+//!
+//! A full queue either blocks the reader, which suits files, or drops the packet, which
+//! suits live capture where blocking would only move the loss to the kernel buffer. Both
+//! are counted in `Stats`.
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::flow;
+use crate::timestamp::Timestamp;
+
+/// What to do with a packet whose worker queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    /// wait for room, slowing the reader down
+    Block,
+    /// drop the packet and count it
+    Drop,
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// worker threads
+    pub workers: usize,
+    /// packets queued per worker
+    pub queue: usize,
+    pub overflow: Overflow,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            queue: 1024,
+            overflow: Overflow::Block,
+        }
+    }
+}
+
+/// A packet copied out of the capture buffer
+#[derive(Debug, Clone)]
+pub struct Packet {
+    /// position in the capture, from 0
+    pub seq: u64,
+    pub linktype: i32,
+    pub ts: Timestamp,
+    /// length on the wire
+    pub len: u32,
+    pub data: Vec<u8>,
+}
+
+/// Decoding done by a worker thread
+///
+/// A worker is created on its own thread, so it can set up the thread local state of the
+/// decoders and analyses before the first packet.
+pub trait Worker {
+    type Output;
+
+    fn packet(&mut self, packet: &Packet) -> Self::Output;
+
+    /// Called once the input ends, to flush the state kept across packets
+    fn finish(&mut self) -> Option<Self::Output>;
+}
+
+/// Counters of the pipeline
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Stats {
+    /// packets handed to the pipeline
+    pub packets: u64,
+    /// packets dropped because the queue of their worker was full
+    pub dropped: u64,
+    /// times the reader waited for room in a queue
+    pub stalls: u64,
+    /// packets whose decoding panicked
+    pub failed: u64,
+    pub workers: Vec<WorkerStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WorkerStats {
+    /// packets decoded
+    pub packets: u64,
+    pub dropped: u64,
+    /// highest number of packets waiting in the queue
+    pub max_queued: usize,
+}
+
+#[derive(Default)]
+struct Counters {
+    decoded: AtomicU64,
+    dropped: AtomicU64,
+    stalls: AtomicU64,
+    failed: AtomicU64,
+    queued: AtomicUsize,
+    max_queued: AtomicUsize,
+}
+
+enum Message<T> {
+    /// result of the packet with this sequence number, `None` if decoding it panicked
+    Decoded(u64, Option<T>),
+    /// result of a worker flushing its state
+    Finished(T),
+}
+
+/// Worker of a packet: the same for both directions of a flow and for a host pair
+fn shard(packet: &Packet, workers: usize) -> usize {
+    let summary = flow::summarize(packet.linktype, &packet.data);
+    match summary.flow {
+        Some(f) => {
+            let mut h = DefaultHasher::new();
+            (f.src.min(f.dst), f.src.max(f.dst)).hash(&mut h);
+            (h.finish() % workers as u64) as usize
+        }
+        None => 0,
+    }
+}
+
+/// Packets decoded on worker threads, results delivered in capture order
+pub struct Pipeline<T> {
+    queues: Vec<SyncSender<Packet>>,
+    results: Receiver<Message<T>>,
+    workers: Vec<JoinHandle<()>>,
+    counters: Arc<Vec<Counters>>,
+    overflow: Overflow,
+    /// results waiting for an earlier one, `None` for packets dropped or failed
+    pending: BTreeMap<u64, Option<T>>,
+    /// sequence number of the next packet submitted, and of the next one delivered
+    submitted: u64,
+    delivered: u64,
+}
+
+impl<T: Send + 'static> Pipeline<T> {
+    /// Start `config.workers` workers, made by `worker` (given their index) on their thread
+    pub fn new<W, F>(config: &Config, worker: F) -> Self
+    where
+        W: Worker<Output = T>,
+        F: Fn(usize) -> W + Send + Sync + 'static,
+    {
+        let n = config.workers.max(1);
+        let counters: Arc<Vec<Counters>> = Arc::new((0..n).map(|_| Counters::default()).collect());
+        let (sender, results) = mpsc::channel();
+        let worker = Arc::new(worker);
+        let mut queues = Vec::with_capacity(n);
+        let mut workers = Vec::with_capacity(n);
+        for index in 0..n {
+            let (queue, packets) = mpsc::sync_channel(config.queue.max(1));
+            let (worker, counters, sender) = (worker.clone(), counters.clone(), sender.clone());
+            workers.push(thread::spawn(move || {
+                run_worker(worker(index), packets, &counters[index], sender)
+            }));
+            queues.push(queue);
+        }
+        Pipeline {
+            queues,
+            results,
+            workers,
+            counters,
+            overflow: config.overflow,
+            pending: BTreeMap::new(),
+            submitted: 0,
+            delivered: 0,
+        }
+    }
+
+    /// Queue a packet for decoding; returns false when it was dropped
+    pub fn submit(&mut self, linktype: i32, ts: Timestamp, len: u32, data: &[u8]) -> bool {
+        let packet = Packet {
+            seq: self.submitted,
+            linktype,
+            ts,
+            len,
+            data: data.to_vec(),
+        };
+        self.submitted += 1;
+        let index = shard(&packet, self.queues.len());
+        let counters = &self.counters[index];
+        let seq = packet.seq;
+        let depth = counters.queued.fetch_add(1, Ordering::Relaxed) + 1;
+        counters.max_queued.fetch_max(depth, Ordering::Relaxed);
+        let sent = match self.queues[index].try_send(packet) {
+            Ok(()) => true,
+            Err(TrySendError::Full(packet)) if self.overflow == Overflow::Block => {
+                counters.stalls.fetch_add(1, Ordering::Relaxed);
+                self.queues[index].send(packet).is_ok()
+            }
+            Err(_) => false,
+        };
+        if !sent {
+            counters.queued.fetch_sub(1, Ordering::Relaxed);
+            counters.dropped.fetch_add(1, Ordering::Relaxed);
+            self.pending.insert(seq, None);
+        }
+        sent
+    }
+
+    /// Deliver the results that are ready and whose predecessors were all delivered,
+    /// without waiting for the others
+    pub fn poll<E>(&mut self, mut deliver: impl FnMut(T) -> Result<(), E>) -> Result<(), E> {
+        while let Ok(message) = self.results.try_recv() {
+            match message {
+                Message::Decoded(seq, output) => {
+                    self.pending.insert(seq, output);
+                }
+                // workers only finish once the queues are closed
+                Message::Finished(_) => unreachable!(),
+            }
+        }
+        self.deliver_ready(&mut deliver)
+    }
+
+    fn deliver_ready<E>(&mut self, deliver: &mut impl FnMut(T) -> Result<(), E>) -> Result<(), E> {
+        while let Some(output) = self.pending.remove(&self.delivered) {
+            self.delivered += 1;
+            if let Some(output) = output {
+                deliver(output)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            packets: self.submitted,
+            ..Stats::default()
+        };
+        for c in self.counters.iter() {
+            let worker = WorkerStats {
+                packets: c.decoded.load(Ordering::Relaxed),
+                dropped: c.dropped.load(Ordering::Relaxed),
+                max_queued: c.max_queued.load(Ordering::Relaxed),
+            };
+            stats.dropped += worker.dropped;
+            stats.stalls += c.stalls.load(Ordering::Relaxed);
+            stats.failed += c.failed.load(Ordering::Relaxed);
+            stats.workers.push(worker);
+        }
+        stats
+    }
+
+    /// Deliver the results of the queued packets, then what the workers flush once the
+    /// input ends
+    pub fn finish<E>(mut self, mut deliver: impl FnMut(T) -> Result<(), E>) -> Result<Stats, E> {
+        self.queues.clear();
+        let mut finished = Vec::new();
+        // the channel closes once every worker is done
+        while let Ok(message) = self.results.recv() {
+            match message {
+                Message::Decoded(seq, output) => {
+                    self.pending.insert(seq, output);
+                    self.deliver_ready(&mut deliver)?;
+                }
+                Message::Finished(output) => finished.push(output),
+            }
+        }
+        for w in self.workers.drain(..) {
+            // a worker thread only ends early on a panic, already reported by the hook
+            let _ = w.join();
+        }
+        // only left when a worker died with packets in its queue
+        for output in std::mem::take(&mut self.pending).into_values().flatten() {
+            deliver(output)?;
+        }
+        for output in finished {
+            deliver(output)?;
+        }
+        Ok(self.stats())
+    }
+}
+
+fn run_worker<W: Worker>(
+    mut worker: W,
+    packets: Receiver<Packet>,
+    counters: &Counters,
+    results: Sender<Message<W::Output>>,
+) {
+    for packet in packets {
+        counters.queued.fetch_sub(1, Ordering::Relaxed);
+        // a decoder bug loses the packet, not the worker and the flows it holds
+        let output = match panic::catch_unwind(AssertUnwindSafe(|| worker.packet(&packet))) {
+            Ok(output) => Some(output),
+            Err(_) => {
+                counters.failed.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        };
+        counters.decoded.fetch_add(1, Ordering::Relaxed);
+        if results.send(Message::Decoded(packet.seq, output)).is_err() {
+            return;
+        }
+    }
+    if let Some(output) = worker.finish() {
+        let _ = results.send(Message::Finished(output));
+    }
+}
@@ -1,33 +1,33 @@
-use crate::utils::cow_struct;
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be};
 use std::mem::size_of;
 
-use super::ip;
-
-/// Authentication header
-///
-/// The IP protocol allow to add extra header to the request
-/// Its use to provide connectionless intergrity and data origin for IP datagram
-/// and provide protection against replay
-/// Its following by the authentication data, that we didn't represent in this structure
-/// since its length is variable.
-///
-/// Sources
-/// * https://tools.ietf.org/html/rfc4302
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    /// type of the next header
-    pub next_header: u8,
-    /// length of the authentication data following the header
-    pub payload_len: u16,
-    /// reserved for future used
-    pub reserved: u16,
-    /// security parameters index, random value to combine with the destination ip address and
-    /// security protocol
-    pub spi: u32,
-    /// sequence number field, counter value increase at each node, proccess of this field is at
-    /// the discretion of the receiver.
-    pub seq_number: u32,
+wire_struct! {
+    /// Authentication header
+    ///
+    /// The IP protocol allow to add extra header to the request
+    /// Its use to provide connectionless intergrity and data origin for IP datagram
+    /// and provide protection against replay
+    /// Its following by the authentication data, that we didn't represent in this structure
+    /// since its length is variable.
+    ///
+    /// Sources
+    /// * https://tools.ietf.org/html/rfc4302
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        /// type of the next header
+        pub next_header: u8,
+        /// length of the header in 32-bit words, minus 2
+        pub payload_len: u8,
+        /// reserved for future used
+        pub reserved: U16Be,
+        /// security parameters index, random value to combine with the destination ip address and
+        /// security protocol
+        pub spi: U32Be,
+        /// sequence number field, counter value increase at each node, proccess of this field is at
+        /// the discretion of the receiver.
+        pub seq_number: U32Be,
+    }
 }
 
 impl Header {
@@ -37,20 +37,32 @@ impl Header {
 pub fn display(h: &Header) -> String {
     format!(
         "[AH] next={} spi={} seq={}",
-        super::ip::protocol_as_str(h.next_header.to_be()),
-        h.spi.to_be(),
-        h.seq_number.to_be()
+        super::ip::IpProtocol::from(h.next_header),
+        h.spi,
+        h.seq_number
     )
 }
 
 pub fn decode(data: &[u8]) {
-    if data.len() >= Header::SIZE {
-        let (slice, _data) = data.split_at(Header::SIZE);
-        match cow_struct::<Header>(slice) {
-            Some(header) => {
-                println!("{}", display(&header));
-            }
-            None => println!("ip decode error: {:?}", "Truncated payload"),
+    if let Some(header) = utils::view::<Header>(data) {
+        println!("{}", display(header));
+        // the authentication data follows the fixed part of the header
+        let len = ((usize::from(header.payload_len) + 2) * 4).clamp(Header::SIZE, data.len());
+        tree::layer("ah", &data[..len], display(header));
+        tree::field(
+            "Next header",
+            &header.next_header,
+            super::ip::IpProtocol::from(header.next_header),
+        );
+        tree::field("Length", &header.payload_len, header.payload_len);
+        tree::field("SPI", &header.spi, header.spi);
+        tree::field("Sequence number", &header.seq_number, header.seq_number);
+        if len > Header::SIZE {
+            tree::field(
+                "Integrity check value",
+                &data[Header::SIZE..len],
+                tree::hex(&data[Header::SIZE..len]),
+            );
         }
     }
 }
@@ -1,6 +1,19 @@
 // Minimal AMQP header detector (0-9-1 and 1.0)
 // AMQP 0-9-1: "AMQP\x00\x00\x09\x01"
 // AMQP 1.0:   "AMQP\x00\x01\x00\x00"
+use super::detect::confidence;
+use crate::tree;
+
+/// Confidence that the payload is an AMQP protocol header
+pub fn probe(data: &[u8]) -> u8 {
+    match data.get(..8) {
+        Some([b'A', b'M', b'Q', b'P', 0, 0, 9, 1]) | Some([b'A', b'M', b'Q', b'P', 0, 1, 0, 0]) => {
+            confidence::CERTAIN
+        }
+        Some(h) if h.starts_with(b"AMQP") => confidence::HIGH,
+        _ => confidence::NONE,
+    }
+}
 
 pub fn decode(data: &[u8]) {
     if data.len() >= 8 && &data[0..4] == b"AMQP" {
@@ -12,8 +25,14 @@ pub fn decode(data: &[u8]) {
         } else {
             "unknown"
         };
-        println!("AMQP header {} ({}B)", desc, data.len());
+        let line = format!("AMQP header {} ({}B)", desc, data.len());
+        println!("{}", line);
+        tree::layer("amqp", data, line);
+        tree::field("Protocol", &data[..4], "AMQP");
+        tree::field("Version", v, desc);
         return;
     }
-    println!("AMQP frame ({}B)", data.len());
+    let line = format!("AMQP frame ({}B)", data.len());
+    println!("{}", line);
+    tree::layer("amqp", data, line);
 }
@@ -1,7 +1,10 @@
-use super::ethernet;
-use crate::utils::cow_struct;
+use super::ethernet::{self, EtherType};
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, ByteReader, U16Be};
 use std::fmt;
 use std::mem::size_of;
+use std::net::Ipv4Addr;
 
 /// ARP op code
 ///
@@ -50,27 +53,28 @@ fn op_as_str(op: u16) -> &'static str {
     }
 }
 
-/// ARP Header structure
-///
-/// The Header define all the field of an arp request header
-/// * `h_type` for the harware type, like ethernet for example
-/// * `p_type` for the protocol type, like ip for example
-/// * `h_len` for the hardware address length, corresponding of the number of bytes for the hardware address, example 6 for mac addresses
-/// * `p_len` for the protocol address length, corresponding of the number of bytes for the protocol address, example 4 for ipv4 addresses
-/// * `op_code` for the operation code, defined by the OP struct
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    /// hardware type
-    pub h_type: u16,
-    /// protocol type
-    pub p_type: u16,
-    /// hardware length (ex: 6 for mac addr)
-    pub h_len: u8,
-    /// protocol length (ex: 4 for ipv4 addr)
-    pub p_len: u8,
-    /// operation code
-    pub op_code: u16,
+wire_struct! {
+    /// ARP Header structure
+    ///
+    /// The Header define all the field of an arp request header
+    /// * `h_type` for the harware type, like ethernet for example
+    /// * `p_type` for the protocol type, like ip for example
+    /// * `h_len` for the hardware address length, corresponding of the number of bytes for the hardware address, example 6 for mac addresses
+    /// * `p_len` for the protocol address length, corresponding of the number of bytes for the protocol address, example 4 for ipv4 addresses
+    /// * `op_code` for the operation code, defined by the OP struct
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        /// hardware type
+        pub h_type: U16Be,
+        /// protocol type
+        pub p_type: U16Be,
+        /// hardware length (ex: 6 for mac addr)
+        pub h_len: u8,
+        /// protocol length (ex: 4 for ipv4 addr)
+        pub p_len: u8,
+        /// operation code
+        pub op_code: U16Be,
+    }
 }
 
 impl Header {
@@ -80,11 +84,11 @@ impl Header {
 impl fmt::Debug for Header {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("Header")
-            .field("h_type", &self.h_type.to_be())
-            .field("p_type", &ethernet::ether_type_as_str(self.p_type.to_be()))
-            .field("h_len", &self.h_len.to_be())
-            .field("p_len", &self.p_len.to_be())
-            .field("op_code", &op_as_str(self.op_code.to_be()))
+            .field("h_type", &self.h_type)
+            .field("p_type", &EtherType::from(self.p_type.get()))
+            .field("h_len", &self.h_len)
+            .field("p_len", &self.p_len)
+            .field("op_code", &op_as_str(self.op_code.get()))
             .finish()
     }
 }
@@ -99,33 +103,70 @@ impl fmt::Debug for Header {
 /// # Examples:
 ///
 ///```
-/// let (header_bytes, next_data) = data.split_at(EthernetHeader::SIZE);
-/// match cow_struct::<EthernetHeader>(header_bytes) {
-///     Some(header) => {
-///         let t = header.ether_type.to_be();
-///         match t {
-///             PROTO::ARP => arp::decode(next_data),
-///             _ => println!("ether type: {:?}", ether_type_as_str(t)),
-///         }
-///     None => println!("Error::ethernet {:?}", "Truncated payload"),
+/// if let Some((header, next_data)) = utils::split::<EthernetHeader>(data) {
+///     match EtherType::from(header.ether_type.get()) {
+///         EtherType::Arp => arp::decode(next_data),
+///         t => println!("ether type: {}", t),
+///     }
 /// }
 ///```
 pub fn display(h: &Header) -> String {
     format!(
         "[ARP] op={} hlen={} plen={} ptype={}",
-        op_as_str(h.op_code.to_be()),
+        op_as_str(h.op_code.get()),
         h.h_len,
         h.p_len,
-        ethernet::ether_type_as_str(h.p_type.to_be())
+        EtherType::from(h.p_type.get())
     )
 }
 
+/// Sender and target addresses, for the protocol tree
+fn addresses(h: &Header, data: &[u8]) {
+    let (hlen, plen) = (usize::from(h.h_len), usize::from(h.p_len));
+    let fields = [
+        ("Sender hardware address", hlen),
+        ("Sender protocol address", plen),
+        ("Target hardware address", hlen),
+        ("Target protocol address", plen),
+    ];
+    let mut r = ByteReader::new(data);
+    for (name, len) in fields {
+        let bytes = match r.bytes(len) {
+            Ok(bytes) => bytes,
+            Err(_) => return,
+        };
+        match (len, <[u8; 6]>::try_from(bytes), <[u8; 4]>::try_from(bytes)) {
+            (6, Ok(mac), _) => tree::field(name, bytes, ethernet::mac_to_str(&mac)),
+            (4, _, Ok(ip)) => tree::field(name, bytes, Ipv4Addr::from(ip)),
+            _ => tree::field(name, bytes, tree::hex(bytes)),
+        }
+    }
+}
+
 pub fn decode(data: &[u8]) {
-    if data.len() >= Header::SIZE {
-        let (header_bytes, _data) = data.split_at(Header::SIZE);
-        match cow_struct::<Header>(header_bytes) {
-            Some(header) => println!("{}", display(&header)),
-            None => println!("Error::arp {:?}", "Truncated payload"),
+    match utils::split::<Header>(data) {
+        Some((header, rest)) => {
+            println!("{}", display(header));
+            let (hlen, plen) = (usize::from(header.h_len), usize::from(header.p_len));
+            let len = (Header::SIZE + 2 * (hlen + plen)).min(data.len());
+            tree::layer("arp", &data[..len], display(header));
+            tree::field("Hardware type", &header.h_type, header.h_type);
+            let p_type = header.p_type.get();
+            tree::field(
+                "Protocol type",
+                &header.p_type,
+                format_args!("{} (0x{:04x})", EtherType::from(p_type), p_type),
+            );
+            tree::field("Hardware size", &header.h_len, hlen);
+            tree::field("Protocol size", &header.p_len, plen);
+            tree::field("Opcode", &header.op_code, op_as_str(header.op_code.get()));
+            if tree::enabled() {
+                addresses(header, rest);
+            }
         }
+        None => expert::error(DecodeError::Truncated {
+            layer: "arp",
+            offset: 0,
+        }),
     }
 }
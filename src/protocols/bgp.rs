@@ -1,14 +1,45 @@
 // BGP-4 minimal decoder over TCP/179
-pub fn decode(data: &[u8]) {
-    if data.len() < 19 {
-        // 16 marker + 2 len + 1 type
-        println!("BGP (truncated) {}B", data.len());
-        return;
+use super::detect::confidence;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be};
+use std::mem::size_of;
+
+wire_struct! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct Header {
+        pub marker: [u8; 16],
+        pub len: U16Be,
+        pub typ: u8,
+    }
+}
+
+/// Confidence that the payload is a BGP message: marker, length and type
+pub fn probe(data: &[u8]) -> u8 {
+    let h = match utils::view::<Header>(data) {
+        Some(h) if h.marker == [0xff; 16] => h,
+        _ => return confidence::NONE,
+    };
+    if (19..=4096).contains(&h.len.get()) && (1..=5).contains(&h.typ) {
+        confidence::CERTAIN
+    } else {
+        confidence::LOW
     }
-    let marker_ffff = data[..16].iter().all(|&b| b == 0xff);
-    let len = u16::from_be_bytes([data[16], data[17]]) as usize;
-    let typ = data[18];
-    let tname = match typ {
+}
+
+pub fn decode(data: &[u8]) {
+    let h = match utils::view::<Header>(data) {
+        Some(h) => h,
+        None => {
+            // 16 marker + 2 len + 1 type
+            expert::error(DecodeError::Truncated {
+                layer: "bgp",
+                offset: 0,
+            });
+            return;
+        }
+    };
+    let tname = match h.typ {
         1 => "OPEN",
         2 => "UPDATE",
         3 => "NOTIF",
@@ -16,10 +47,16 @@ pub fn decode(data: &[u8]) {
         5 => "ROUTE-REFRESH",
         _ => "UNKNOWN",
     };
-    println!(
+    let line = format!(
         "BGP {} len={} marker={}B",
         tname,
-        len,
-        if marker_ffff { 16 } else { 0 }
+        h.len,
+        if h.marker == [0xff; 16] { 16 } else { 0 }
     );
+    println!("{}", line);
+    let len = usize::from(h.len.get()).clamp(size_of::<Header>(), data.len());
+    tree::layer("bgp", &data[..len], line);
+    tree::field("Marker", &h.marker, tree::hex(&h.marker));
+    tree::field("Length", &h.len, h.len);
+    tree::field("Type", &h.typ, format_args!("{} ({})", tname, h.typ));
 }
@@ -1,5 +1,7 @@
 // Minimal Bluetooth HCI H4 decoder
 // First byte is packet type: 1=CMD,2=ACL,3=SYNC,4=EVENT
+use crate::tree;
+
 pub fn decode(data: &[u8]) {
     if data.is_empty() {
         println!("BT HCI (empty)");
@@ -13,5 +15,8 @@ pub fn decode(data: &[u8]) {
         4 => "EVENT",
         _ => "?",
     };
-    println!("Bluetooth HCI {} ({}B)", name, data.len());
+    let line = format!("Bluetooth HCI {} ({}B)", name, data.len());
+    println!("{}", line);
+    tree::layer("bluetooth", data, line);
+    tree::field("Packet type", &data[..1], format_args!("{} ({})", name, t));
 }
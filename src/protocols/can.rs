@@ -1,16 +1,21 @@
 // Minimal SocketCAN decoder
 // SocketCAN frame: 4B can_id, 1B dlc, 3B pad, 8B data
+use crate::tree;
+
 pub fn decode(data: &[u8]) {
     if data.len() >= 16 {
         let id = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
         let dlc = data[4] & 0x0F;
-        println!(
-            "CAN id=0x{:08x} dlc={} data={:02x?}",
-            id,
-            dlc,
-            &data[8..(8 + (dlc as usize)).min(16)]
-        );
+        let payload = &data[8..(8 + (dlc as usize)).min(16)];
+        let line = format!("CAN id=0x{:08x} dlc={} data={:02x?}", id, dlc, payload);
+        println!("{}", line);
+        tree::layer("can", &data[..16], line);
+        tree::field("Identifier", &data[..4], format_args!("0x{:08x}", id));
+        tree::field("Data length", &data[4..5], dlc);
+        tree::field("Data", payload, format_args!("{:02x?}", payload));
     } else {
-        println!("CAN ({}B)", data.len());
+        let line = format!("CAN ({}B)", data.len());
+        println!("{}", line);
+        tree::layer("can", data, line);
     }
 }
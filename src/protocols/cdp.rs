@@ -1,20 +1,88 @@
 // Cisco Discovery Protocol minimal decoder
-pub fn decode(mut data: &[u8]) {
-    if data.len() < 4 {
-        return;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
+fn tlv_name(t: u16) -> &'static str {
+    match t {
+        0x0001 => "Device ID",
+        0x0002 => "Addresses",
+        0x0003 => "Port ID",
+        0x0004 => "Capabilities",
+        0x0005 => "Software Version",
+        0x0006 => "Platform",
+        _ => "TLV",
     }
-    let ver = data[0];
-    let ttl = data[1];
-    let _cksum = u16::from_be_bytes([data[2], data[3]]);
-    println!("CDP v{} ttl={}", ver, ttl);
-    data = &data[4..];
-    while data.len() >= 4 {
-        let t = u16::from_be_bytes([data[0], data[1]]);
-        let l = u16::from_be_bytes([data[2], data[3]]) as usize;
-        if l < 4 || data.len() < l {
-            break;
+}
+
+fn tlv_value(t: u16, v: &[u8]) -> String {
+    match t {
+        0x0001 | 0x0003 | 0x0005 | 0x0006 => String::from_utf8_lossy(v).into_owned(),
+        _ => tree::hex(v),
+    }
+}
+
+pub fn decode(data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    let (ver, ttl) = match (r.u8(), r.u8(), r.u16()) {
+        (Ok(ver), Ok(ttl), Ok(_cksum)) => (ver, ttl),
+        _ => {
+            expert::error(DecodeError::Truncated {
+                layer: "cdp",
+                offset: 0,
+            });
+            return;
         }
-        let v = &data[4..l];
+    };
+    println!("CDP v{} ttl={}", ver, ttl);
+    tree::layer("cdp", data, format_args!("CDP v{} ttl={}", ver, ttl));
+    tree::field("Version", &data[..1], ver);
+    tree::field("TTL", &data[1..2], format_args!("{}s", ttl));
+    tree::field(
+        "Checksum",
+        &data[2..4],
+        format_args!("0x{:04x}", u16::from_be_bytes([data[2], data[3]])),
+    );
+    // the length of a TLV includes its 4 bytes header
+    while !r.is_empty() {
+        let tlv = r.position();
+        let (t, l) = match (r.u16(), r.u16()) {
+            (Ok(t), Ok(l)) => (t, usize::from(l)),
+            _ => {
+                expert::error(DecodeError::Truncated {
+                    layer: "cdp",
+                    offset: tlv,
+                });
+                break;
+            }
+        };
+        let v = match l.checked_sub(4).map(|n| r.bytes(n)) {
+            Some(Ok(v)) => v,
+            Some(Err(_)) => {
+                expert::error(DecodeError::Truncated {
+                    layer: "cdp",
+                    offset: r.position(),
+                });
+                break;
+            }
+            None => {
+                expert::error(DecodeError::Malformed {
+                    layer: "cdp",
+                    offset: tlv + 2,
+                    reason: "TLV length shorter than its header",
+                });
+                break;
+            }
+        };
+        tree::open(
+            tlv_name(t),
+            &data[tlv..r.position()],
+            format_args!("{} bytes", l),
+        );
+        tree::field("Type", &data[tlv..tlv + 2], format_args!("0x{:04x}", t));
+        tree::field("Length", &data[tlv + 2..tlv + 4], l);
+        tree::field("Value", v, tlv_value(t, v));
+        tree::close();
         match t {
             0x0001 => {
                 // Device ID
@@ -37,6 +105,5 @@ pub fn decode(mut data: &[u8]) {
             0x0002 => println!("CDP Addresses ({}B)", v.len()),
             _ => println!("CDP TLV type=0x{:04x} len={}", t, l),
         }
-        data = &data[l..];
     }
 }
@@ -1,5 +1,9 @@
 // Minimal CoAP decoder (RFC 7252)
 // Header: 1B ver(2)|type(2)|tkl(4), 1B code, 2B message ID, then token/options/payload
+use super::detect::confidence;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
 
 fn coap_type_name(t: u8) -> &'static str {
     match t {
@@ -11,20 +15,40 @@ fn coap_type_name(t: u8) -> &'static str {
     }
 }
 
-pub fn decode(data: &[u8]) {
+/// Confidence that the payload is a CoAP message; the header is too short to be sure
+pub fn probe(data: &[u8]) -> u8 {
     if data.len() < 4 {
-        println!("CoAP (truncated) {}B", data.len());
-        return;
+        return confidence::NONE;
+    }
+    let ver = (data[0] >> 6) & 0x03;
+    let tkl = (data[0] & 0x0F) as usize;
+    let class = data[1] >> 5;
+    // an empty message has no token nor content
+    let empty = data[1] == 0 && (data.len() > 4 || tkl != 0);
+    if ver != 1 || tkl > 8 || data.len() < 4 + tkl || !matches!(class, 0 | 2 | 4 | 5) || empty {
+        return confidence::NONE;
     }
-    let b0 = data[0];
+    confidence::LOW
+}
+
+pub fn decode(data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    let (b0, code, mid) = match (r.u8(), r.u8(), r.u16()) {
+        (Ok(b0), Ok(code), Ok(mid)) => (b0, code, mid),
+        _ => {
+            expert::error(DecodeError::Truncated {
+                layer: "coap",
+                offset: r.position(),
+            });
+            return;
+        }
+    };
     let ver = (b0 >> 6) & 0x03;
     let typ = (b0 >> 4) & 0x03;
     let tkl = b0 & 0x0F;
-    let code = data[1];
-    let mid = u16::from_be_bytes([data[2], data[3]]);
     let cls = code >> 5; // class
     let detail = code & 0x1F;
-    println!(
+    let line = format!(
         "CoAP v{} {} tkl={} code={}.{} mid={}",
         ver,
         coap_type_name(typ),
@@ -33,4 +57,14 @@ pub fn decode(data: &[u8]) {
         detail,
         mid
     );
+    println!("{}", line);
+    tree::layer("coap", data, line);
+    tree::field("Version", &data[..1], ver);
+    tree::field("Type", &data[..1], coap_type_name(typ));
+    tree::field("Token length", &data[..1], tkl);
+    tree::field("Code", &data[1..2], format_args!("{}.{:02}", cls, detail));
+    tree::field("Message ID", &data[2..4], mid);
+    if let Ok(token) = r.bytes(usize::from(tkl)) {
+        tree::field("Token", token, tree::hex(token));
+    }
 }
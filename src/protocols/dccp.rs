@@ -1,16 +1,22 @@
 // DCCP minimal decoder (RFC 4340)
-use crate::utils::cow_struct;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be};
 
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header8 {
-    pub src_port: u16,
-    pub dst_port: u16,
-    pub data_offset_ccval_cscov: u8,
-    pub checksum: u16,
-    pub res_type_x: u8,
-    pub seq_hi: u16,
-    pub seq_lo: u16,
+wire_struct! {
+    #[derive(Default, Clone, Copy)]
+    pub struct Header8 {
+        pub src_port: U16Be,
+        pub dst_port: U16Be,
+        /// offset of the payload in 32-bit words
+        pub data_offset: u8,
+        pub ccval_cscov: u8,
+        pub checksum: U16Be,
+        /// reserved bits, packet type and extended sequence numbers flag
+        pub res_type_x: u8,
+        /// 24-bit sequence number, or a reserved byte and the top of a 48-bit one with X set
+        pub seq: [u8; 3],
+    }
 }
 
 impl Header8 {
@@ -18,18 +24,27 @@ impl Header8 {
 }
 
 pub fn decode(data: &[u8]) {
-    if data.len() < Header8::SIZE {
-        println!("DCCP (truncated) {}B", data.len());
-        return;
-    }
-    let (hdr, _) = data.split_at(Header8::SIZE);
-    if let Some(h) = cow_struct::<Header8>(hdr) {
-        let src = u16::from_be(h.src_port);
-        let dst = u16::from_be(h.dst_port);
-        let doff = (h.data_offset_ccval_cscov & 0xF0) >> 4;
-        let typ = h.res_type_x & 0x0F;
-        println!("DCCP {} -> {} type={} doff={}", src, dst, typ, doff);
+    if let Some(h) = utils::view::<Header8>(data) {
+        let src = h.src_port.get();
+        let dst = h.dst_port.get();
+        let doff = h.data_offset;
+        let typ = (h.res_type_x >> 1) & 0x0F;
+        let line = format!("DCCP {} -> {} type={} doff={}", src, dst, typ, doff);
+        println!("{}", line);
+        tree::layer("dccp", h, line);
+        tree::field("Source port", &h.src_port, src);
+        tree::field("Destination port", &h.dst_port, dst);
+        tree::field("Data offset", &h.data_offset, doff);
+        tree::field(
+            "Checksum",
+            &h.checksum,
+            format_args!("0x{:04x}", h.checksum.get()),
+        );
+        tree::field("Packet type", &h.res_type_x, typ);
     } else {
-        println!("DCCP (truncated) {}B", data.len());
+        expert::error(DecodeError::Truncated {
+            layer: "dccp",
+            offset: 0,
+        });
     }
 }
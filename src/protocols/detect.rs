@@ -0,0 +1,579 @@
+// Content based protocol detection
+// Dispatch by port misses services on other ports (SSH on 443, HTTP on 8888, Redis on a
+// random port) and trusts whatever runs on a well-known one. Each application decoder has a
+// `probe(payload)` scoring how much a payload looks like its protocol, from
+// `confidence::NONE` to `confidence::CERTAIN`. The first payloads of a flow are probed
+// until a protocol stands out, and the decision is remembered for the rest of the flow.
+//
+// A protocol registered for one of the ports of the flow wins as soon as its own probe is
+// confident enough, so that well-known ports keep their decoders. Otherwise the flow is
+// decoded as the protocol its content shows; if the port announced another protocol, a
+// `protocol_mismatch` event is emitted. Protocols carried by another one (mDNS, LLMNR,
+// DoT, SMTPS, LDAPS, TURN, WebSocket, DoH) are detected as the protocol they are carried
+// by.
+use serde::Serialize;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::context;
+use crate::event::{self, Event};
+use crate::flow::FlowKey;
+
+use super::ip::IpProtocol;
+
+/// Confidence of a probe that a payload is of its protocol
+pub mod confidence {
+    /// the payload cannot be of the protocol
+    pub const NONE: u8 = 0;
+    /// a few bytes match, common in other protocols
+    pub const LOW: u8 = 25;
+    /// the structure matches
+    pub const MEDIUM: u8 = 50;
+    /// the structure and its values match
+    pub const HIGH: u8 = 75;
+    /// a signature of the protocol (banner, magic, preface)
+    pub const CERTAIN: u8 = 100;
+}
+
+/// Payloads of a flow probed before giving up and relying on its ports
+const MAX_PROBES: u8 = 4;
+/// Decisions remembered per thread
+const MAX_FLOWS: usize = 65536;
+
+/// Protocols recognized from their content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Amqp,
+    Bgp,
+    Coap,
+    Dhcp,
+    Dhcpv6,
+    Dns,
+    Ftp,
+    Http,
+    Http2,
+    Imap,
+    Kerberos,
+    Ldap,
+    Memcached,
+    Mqtt,
+    Netbios,
+    Ntp,
+    Pop3,
+    Quic,
+    Rdp,
+    Redis,
+    Rip,
+    Rtcp,
+    Rtp,
+    Rtsp,
+    Sip,
+    Smb,
+    Smtp,
+    Snmp,
+    Ssdp,
+    Ssh,
+    Stomp,
+    Stun,
+    Syslog,
+    Telnet,
+    Tftp,
+    Tls,
+}
+
+pub fn protocol_as_str(p: Protocol) -> &'static str {
+    match p {
+        Protocol::Amqp => "AMQP",
+        Protocol::Bgp => "BGP",
+        Protocol::Coap => "CoAP",
+        Protocol::Dhcp => "DHCP",
+        Protocol::Dhcpv6 => "DHCPv6",
+        Protocol::Dns => "DNS",
+        Protocol::Ftp => "FTP",
+        Protocol::Http => "HTTP",
+        Protocol::Http2 => "HTTP/2",
+        Protocol::Imap => "IMAP",
+        Protocol::Kerberos => "Kerberos",
+        Protocol::Ldap => "LDAP",
+        Protocol::Memcached => "Memcached",
+        Protocol::Mqtt => "MQTT",
+        Protocol::Netbios => "NetBIOS",
+        Protocol::Ntp => "NTP",
+        Protocol::Pop3 => "POP3",
+        Protocol::Quic => "QUIC",
+        Protocol::Rdp => "RDP",
+        Protocol::Redis => "Redis",
+        Protocol::Rip => "RIP",
+        Protocol::Rtcp => "RTCP",
+        Protocol::Rtp => "RTP",
+        Protocol::Rtsp => "RTSP",
+        Protocol::Sip => "SIP",
+        Protocol::Smb => "SMB",
+        Protocol::Smtp => "SMTP",
+        Protocol::Snmp => "SNMP",
+        Protocol::Ssdp => "SSDP",
+        Protocol::Ssh => "SSH",
+        Protocol::Stomp => "STOMP",
+        Protocol::Stun => "STUN",
+        Protocol::Syslog => "Syslog",
+        Protocol::Telnet => "Telnet",
+        Protocol::Tftp => "TFTP",
+        Protocol::Tls => "TLS",
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(protocol_as_str(*self))
+    }
+}
+
+/// A protocol, the ports it is registered on and its decoder, for one transport
+struct Probe {
+    protocol: Protocol,
+    transport: IpProtocol,
+    ports: &'static [u16],
+    probe: fn(&[u8]) -> u8,
+    decode: fn(&[u8]),
+}
+
+const TCP: IpProtocol = IpProtocol::Tcp;
+const UDP: IpProtocol = IpProtocol::Udp;
+
+/// In order of preference between equally confident probes
+const PROBES: &[Probe] = &[
+    Probe {
+        protocol: Protocol::Tls,
+        transport: TCP,
+        ports: &[443, 8443, 465, 636, 853, 990, 993, 995, 5349, 5671, 8883],
+        probe: super::tls::probe,
+        decode: |d| {
+            super::tls::decode(d);
+        },
+    },
+    Probe {
+        protocol: Protocol::Http2,
+        transport: TCP,
+        ports: &[80, 8000, 8080],
+        probe: super::http2::probe,
+        // frames span segments: the stream handler of the connection decodes them
+        decode: |_| {},
+    },
+    Probe {
+        protocol: Protocol::Http,
+        transport: TCP,
+        ports: &[80, 8000, 8080],
+        probe: super::http::probe,
+        decode: super::http::decode,
+    },
+    Probe {
+        protocol: Protocol::Ssh,
+        transport: TCP,
+        ports: &[22],
+        probe: super::ssh::probe,
+        decode: super::ssh::decode,
+    },
+    Probe {
+        protocol: Protocol::Rtsp,
+        transport: TCP,
+        ports: &[554],
+        probe: super::rtsp::probe,
+        decode: super::rtsp::decode,
+    },
+    Probe {
+        protocol: Protocol::Sip,
+        transport: TCP,
+        ports: &[5060],
+        probe: super::sip::probe,
+        decode: super::sip::decode,
+    },
+    Probe {
+        protocol: Protocol::Smtp,
+        transport: TCP,
+        ports: &[25, 465, 587],
+        probe: super::smtp::probe,
+        decode: super::smtp::decode,
+    },
+    Probe {
+        protocol: Protocol::Ftp,
+        transport: TCP,
+        ports: &[21, 990],
+        probe: super::ftp::probe,
+        decode: super::ftp::decode,
+    },
+    Probe {
+        protocol: Protocol::Pop3,
+        transport: TCP,
+        ports: &[110, 995],
+        probe: super::pop3::probe,
+        decode: super::pop3::decode,
+    },
+    Probe {
+        protocol: Protocol::Imap,
+        transport: TCP,
+        ports: &[143, 993],
+        probe: super::imap::probe,
+        decode: super::imap::decode,
+    },
+    Probe {
+        protocol: Protocol::Smb,
+        transport: TCP,
+        ports: &[139, 445],
+        probe: super::smb::probe,
+        decode: super::smb::decode,
+    },
+    Probe {
+        protocol: Protocol::Rdp,
+        transport: TCP,
+        ports: &[3389],
+        probe: super::rdp::probe,
+        decode: super::rdp::decode,
+    },
+    Probe {
+        protocol: Protocol::Bgp,
+        transport: TCP,
+        ports: &[179],
+        probe: super::bgp::probe,
+        decode: super::bgp::decode,
+    },
+    Probe {
+        protocol: Protocol::Mqtt,
+        transport: TCP,
+        ports: &[1883, 8883],
+        probe: super::mqtt::probe,
+        decode: super::mqtt::decode,
+    },
+    Probe {
+        protocol: Protocol::Amqp,
+        transport: TCP,
+        ports: &[5671, 5672],
+        probe: super::amqp::probe,
+        decode: super::amqp::decode,
+    },
+    Probe {
+        protocol: Protocol::Stomp,
+        transport: TCP,
+        ports: &[61613, 61614],
+        probe: super::stomp::probe,
+        decode: super::stomp::decode,
+    },
+    Probe {
+        protocol: Protocol::Redis,
+        transport: TCP,
+        ports: &[6379],
+        probe: super::redis::probe,
+        decode: super::redis::decode,
+    },
+    Probe {
+        protocol: Protocol::Memcached,
+        transport: TCP,
+        ports: &[11211],
+        probe: super::memcached::probe,
+        decode: super::memcached::decode,
+    },
+    Probe {
+        protocol: Protocol::Ldap,
+        transport: TCP,
+        ports: &[389, 636],
+        probe: super::ldap::probe,
+        decode: super::ldap::decode,
+    },
+    Probe {
+        protocol: Protocol::Kerberos,
+        transport: TCP,
+        ports: &[88],
+        probe: super::kerberos::probe,
+        decode: super::kerberos::decode,
+    },
+    Probe {
+        protocol: Protocol::Dns,
+        transport: TCP,
+        ports: &[53, 853, 5355],
+        probe: super::dns::probe_stream,
+        decode: super::dns::decode_stream,
+    },
+    Probe {
+        protocol: Protocol::Stun,
+        transport: TCP,
+        ports: &[3478, 5349],
+        probe: super::stun::probe,
+        decode: |d| {
+            super::stun::decode(d);
+        },
+    },
+    Probe {
+        protocol: Protocol::Telnet,
+        transport: TCP,
+        ports: &[23],
+        probe: super::telnet::probe,
+        decode: super::telnet::decode,
+    },
+    Probe {
+        protocol: Protocol::Quic,
+        transport: UDP,
+        ports: &[443],
+        probe: super::quic::probe,
+        decode: super::quic::decode,
+    },
+    Probe {
+        protocol: Protocol::Dhcp,
+        transport: UDP,
+        ports: &[67, 68],
+        probe: super::dhcp::probe,
+        decode: super::dhcp::decode,
+    },
+    Probe {
+        protocol: Protocol::Stun,
+        transport: UDP,
+        ports: &[3478, 5349],
+        probe: super::stun::probe,
+        decode: |d| {
+            super::stun::decode(d);
+        },
+    },
+    Probe {
+        protocol: Protocol::Sip,
+        transport: UDP,
+        ports: &[5060],
+        probe: super::sip::probe,
+        decode: super::sip::decode,
+    },
+    Probe {
+        protocol: Protocol::Ssdp,
+        transport: UDP,
+        ports: &[1900],
+        probe: super::ssdp::probe,
+        decode: super::ssdp::decode,
+    },
+    Probe {
+        protocol: Protocol::Netbios,
+        transport: UDP,
+        ports: &[137, 138],
+        probe: super::netbios::probe,
+        decode: super::netbios::decode,
+    },
+    Probe {
+        protocol: Protocol::Dns,
+        transport: UDP,
+        ports: &[53, 5353, 5355],
+        probe: super::dns::probe,
+        decode: super::dns::decode,
+    },
+    Probe {
+        protocol: Protocol::Tftp,
+        transport: UDP,
+        ports: &[69],
+        probe: super::tftp::probe,
+        decode: super::tftp::decode,
+    },
+    Probe {
+        protocol: Protocol::Snmp,
+        transport: UDP,
+        ports: &[161, 162],
+        probe: super::snmp::probe,
+        decode: super::snmp::decode,
+    },
+    Probe {
+        protocol: Protocol::Kerberos,
+        transport: UDP,
+        ports: &[88],
+        probe: super::kerberos::probe,
+        decode: super::kerberos::decode,
+    },
+    Probe {
+        protocol: Protocol::Syslog,
+        transport: UDP,
+        ports: &[514],
+        probe: super::syslog::probe,
+        decode: super::syslog::decode,
+    },
+    Probe {
+        protocol: Protocol::Rip,
+        transport: UDP,
+        ports: &[520],
+        probe: super::rip::probe,
+        decode: super::rip::decode,
+    },
+    Probe {
+        protocol: Protocol::Rtcp,
+        transport: UDP,
+        ports: &[],
+        probe: super::rtcp::probe,
+        decode: super::rtcp::decode,
+    },
+    Probe {
+        protocol: Protocol::Ntp,
+        transport: UDP,
+        ports: &[123],
+        probe: super::ntp::probe,
+        decode: super::ntp::decode,
+    },
+    Probe {
+        protocol: Protocol::Dhcpv6,
+        transport: UDP,
+        ports: &[546, 547],
+        probe: super::dhcpv6::probe,
+        decode: super::dhcpv6::decode,
+    },
+    Probe {
+        protocol: Protocol::Coap,
+        transport: UDP,
+        ports: &[5683],
+        probe: super::coap::probe,
+        decode: super::coap::decode,
+    },
+    Probe {
+        protocol: Protocol::Rtp,
+        transport: UDP,
+        ports: &[],
+        probe: super::rtp::probe,
+        decode: super::rtp::decode,
+    },
+];
+
+/// A port announced another protocol than the one its flow carries
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    /// oriented as the first probed packet
+    pub flow: FlowKey,
+    /// the registered port of the flow
+    pub port: u16,
+    /// protocol registered for the port
+    pub expected: Protocol,
+    pub detected: Protocol,
+    pub confidence: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Decision {
+    /// payloads probed so far without a confident answer
+    Pending(u8),
+    /// decoded by port
+    Port,
+    /// decoded by content
+    Content(Protocol),
+}
+
+thread_local! {
+    static FLOWS: RefCell<HashMap<FlowKey, Decision>> = RefCell::new(HashMap::new());
+}
+
+/// Protocols registered for the ports of a flow
+pub fn by_port(transport: IpProtocol, src: u16, dst: u16) -> Vec<Protocol> {
+    PROBES
+        .iter()
+        .filter(|p| p.transport == transport)
+        .filter(|p| p.ports.contains(&src) || p.ports.contains(&dst))
+        .map(|p| p.protocol)
+        .collect()
+}
+
+/// Most likely protocol of a payload and the confidence of its probe
+pub fn probe(transport: IpProtocol, payload: &[u8]) -> Option<(Protocol, u8)> {
+    let mut best: Option<(Protocol, u8)> = None;
+    for p in PROBES.iter().filter(|p| p.transport == transport) {
+        let score = (p.probe)(payload);
+        if score > best.map_or(confidence::NONE, |b| b.1) {
+            best = Some((p.protocol, score));
+        }
+    }
+    best
+}
+
+/// Probe a payload of a flow not decided yet
+fn decide(flow: &FlowKey, tries: u8, payload: &[u8]) -> Decision {
+    let registered: Vec<&Probe> = PROBES
+        .iter()
+        .filter(|p| p.transport == flow.proto)
+        .filter(|p| p.ports.contains(&flow.src_port) || p.ports.contains(&flow.dst_port))
+        .collect();
+    // the protocol of the port wins when its content agrees
+    if registered
+        .iter()
+        .any(|p| (p.probe)(payload) >= confidence::MEDIUM)
+    {
+        return Decision::Port;
+    }
+    match probe(flow.proto, payload) {
+        Some((protocol, confidence)) if confidence >= confidence::MEDIUM => {
+            match registered.first() {
+                // content too weak to overrule the port
+                Some(_) if confidence < confidence::HIGH => Decision::Port,
+                Some(expected) => {
+                    let port = if expected.ports.contains(&flow.dst_port) {
+                        flow.dst_port
+                    } else {
+                        flow.src_port
+                    };
+                    println!(
+                        "DPI mismatch: port {} is {} but {} carries {} (confidence {})",
+                        port, expected.protocol, flow, protocol, confidence
+                    );
+                    event::emit(Event::ProtocolMismatch(Mismatch {
+                        flow: *flow,
+                        port,
+                        expected: expected.protocol,
+                        detected: protocol,
+                        confidence,
+                    }));
+                    Decision::Content(protocol)
+                }
+                None => {
+                    println!("DPI {} is {} (confidence {})", flow, protocol, confidence);
+                    Decision::Content(protocol)
+                }
+            }
+        }
+        _ if tries + 1 >= MAX_PROBES => Decision::Port,
+        _ => Decision::Pending(tries + 1),
+    }
+}
+
+/// Protocol the flow of the packet being decoded carries, when its content overrules its
+/// ports; `None` leaves the payload to the dispatch by port
+pub fn detect(payload: &[u8]) -> Option<Protocol> {
+    if payload.is_empty() {
+        return None;
+    }
+    let flow = context::flow()?;
+    let key = flow.canonical().0;
+    let known = FLOWS.with(|f| f.borrow().get(&key).copied());
+    let decision = match known {
+        Some(Decision::Port) => return None,
+        Some(Decision::Content(p)) => return Some(p),
+        Some(Decision::Pending(tries)) => decide(&flow, tries, payload),
+        None => decide(&flow, 0, payload),
+    };
+    FLOWS.with(|f| {
+        let mut f = f.borrow_mut();
+        if f.len() >= MAX_FLOWS && !f.contains_key(&key) {
+            f.clear();
+        }
+        f.insert(key, decision);
+    });
+    match decision {
+        Decision::Content(p) => Some(p),
+        _ => None,
+    }
+}
+
+/// Decode the payload with the decoder of the protocol detected for its flow, returning
+/// whether it was
+pub fn dispatch(transport: IpProtocol, payload: &[u8]) -> bool {
+    let protocol = match detect(payload) {
+        Some(p) => p,
+        None => return false,
+    };
+    match PROBES
+        .iter()
+        .find(|p| p.transport == transport && p.protocol == protocol)
+    {
+        Some(p) => {
+            (p.decode)(payload);
+            true
+        }
+        None => false,
+    }
+}
@@ -1,22 +1,26 @@
-use crate::utils::cow_struct;
+use super::detect::confidence;
+use crate::tree;
+use crate::utils::{self, wire_struct, ByteReader, U16Be, U32Be};
 use std::mem::size_of;
+use std::net::Ipv4Addr;
 
-// Minimal BOOTP/DHCP header (no options parsed here)
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub op: u8,
-    pub htype: u8,
-    pub hlen: u8,
-    pub hops: u8,
-    pub xid: u32,
-    pub secs: u16,
-    pub flags: u16,
-    pub ciaddr: u32,
-    pub yiaddr: u32,
-    pub siaddr: u32,
-    pub giaddr: u32,
-    pub chaddr: [u8; 16],
+wire_struct! {
+    // Minimal BOOTP/DHCP header (no options parsed here)
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        pub op: u8,
+        pub htype: u8,
+        pub hlen: u8,
+        pub hops: u8,
+        pub xid: U32Be,
+        pub secs: U16Be,
+        pub flags: U16Be,
+        pub ciaddr: U32Be,
+        pub yiaddr: U32Be,
+        pub siaddr: U32Be,
+        pub giaddr: U32Be,
+        pub chaddr: [u8; 16],
+    }
 }
 
 impl Header {
@@ -24,29 +28,160 @@ impl Header {
 }
 
 pub fn display(h: &Header) -> String {
-    let yi = h.yiaddr.to_be_bytes();
-    let ci = h.ciaddr.to_be_bytes();
     format!(
-        "DHCP op={} xid=0x{:08x} ciaddr={}.{}.{}.{} yiaddr={}.{}.{}.{}",
+        "DHCP op={} xid=0x{:08x} ciaddr={} yiaddr={}",
         h.op,
-        h.xid.to_be(),
-        ci[0],
-        ci[1],
-        ci[2],
-        ci[3],
-        yi[0],
-        yi[1],
-        yi[2],
-        yi[3]
+        h.xid.get(),
+        Ipv4Addr::from(h.ciaddr.get()),
+        Ipv4Addr::from(h.yiaddr.get())
     )
 }
 
+/// Options start with this magic cookie (RFC 2131)
+const MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+/// Offset of the magic cookie: header, then the server host name and boot file name
+const OPTIONS_OFFSET: usize = 236;
+
+/// Name of the common options (RFC 2132)
+fn option_as_str(code: u8) -> &'static str {
+    match code {
+        1 => "Subnet mask",
+        3 => "Router",
+        6 => "Domain name server",
+        12 => "Host name",
+        15 => "Domain name",
+        50 => "Requested IP address",
+        51 => "Lease time",
+        53 => "Message type",
+        54 => "Server identifier",
+        55 => "Parameter request list",
+        57 => "Maximum message size",
+        60 => "Vendor class identifier",
+        61 => "Client identifier",
+        _ => "UNKNOW",
+    }
+}
+
+fn message_type_as_str(t: u8) -> &'static str {
+    match t {
+        1 => "DISCOVER",
+        2 => "OFFER",
+        3 => "REQUEST",
+        4 => "DECLINE",
+        5 => "ACK",
+        6 => "NAK",
+        7 => "RELEASE",
+        8 => "INFORM",
+        _ => "UNKNOW",
+    }
+}
+
+fn option_value(code: u8, value: &[u8]) -> String {
+    match (code, value) {
+        (53, [t]) => format!("{} ({})", message_type_as_str(*t), t),
+        (12 | 15 | 60, _) => String::from_utf8_lossy(value).into_owned(),
+        (1 | 3 | 6 | 50 | 54, _) if !value.is_empty() && value.len().is_multiple_of(4) => value
+            .chunks_exact(4)
+            .map(|a| Ipv4Addr::from([a[0], a[1], a[2], a[3]]).to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+        (51, [a, b, c, d]) => format!("{}s", u32::from_be_bytes([*a, *b, *c, *d])),
+        _ => tree::hex(value),
+    }
+}
+
+/// Add the options after the magic cookie to the tree
+fn options(data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    while let Ok(code) = r.u8() {
+        let start = r.position() - 1;
+        match code {
+            // padding
+            0 => continue,
+            255 => {
+                tree::field("Option", &data[start..r.position()], "End");
+                break;
+            }
+            _ => {}
+        }
+        let value = match r.bytes_u8() {
+            Ok(value) => value,
+            Err(_) => break,
+        };
+        tree::field(
+            "Option",
+            &data[start..r.position()],
+            format_args!(
+                "({}) {}: {}",
+                code,
+                option_as_str(code),
+                option_value(code, value)
+            ),
+        );
+    }
+}
+
+/// Confidence that the payload is a BOOTP/DHCP message
+pub fn probe(data: &[u8]) -> u8 {
+    if data.len() < Header::SIZE || !matches!(data[0], 1 | 2) {
+        return confidence::NONE;
+    }
+    if data.get(OPTIONS_OFFSET..OPTIONS_OFFSET + 4) == Some(&MAGIC_COOKIE) {
+        confidence::CERTAIN
+    } else if data[1] == 1 && data[2] == 6 {
+        confidence::MEDIUM
+    } else {
+        confidence::NONE
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if data.len() < Header::SIZE {
         return;
     }
-    let (hdr, _rest) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        println!("{}", display(&h));
+    if let Some(h) = utils::view::<Header>(data) {
+        println!("{}", display(h));
+        tree::layer("dhcp", data, display(h));
+        tree::field(
+            "Message type",
+            &h.op,
+            match h.op {
+                1 => "Boot Request (1)",
+                2 => "Boot Reply (2)",
+                _ => "UNKNOW",
+            },
+        );
+        tree::field("Hardware type", &h.htype, h.htype);
+        tree::field("Hardware address length", &h.hlen, h.hlen);
+        tree::field("Hops", &h.hops, h.hops);
+        tree::field(
+            "Transaction ID",
+            &h.xid,
+            format_args!("0x{:08x}", h.xid.get()),
+        );
+        tree::field("Seconds elapsed", &h.secs, h.secs);
+        tree::field("Flags", &h.flags, format_args!("0x{:04x}", h.flags.get()));
+        for (name, addr) in [
+            ("Client IP address", &h.ciaddr),
+            ("Your IP address", &h.yiaddr),
+            ("Next server IP address", &h.siaddr),
+            ("Relay agent IP address", &h.giaddr),
+        ] {
+            tree::field(name, addr, Ipv4Addr::from(addr.get()));
+        }
+        let hlen = usize::from(h.hlen).min(h.chaddr.len());
+        tree::field(
+            "Client hardware address",
+            &h.chaddr[..hlen],
+            tree::hex(&h.chaddr[..hlen]),
+        );
+        if tree::enabled() && data.get(OPTIONS_OFFSET..OPTIONS_OFFSET + 4) == Some(&MAGIC_COOKIE) {
+            tree::field(
+                "Magic cookie",
+                &data[OPTIONS_OFFSET..OPTIONS_OFFSET + 4],
+                "DHCP",
+            );
+            options(&data[OPTIONS_OFFSET + 4..]);
+        }
     }
 }
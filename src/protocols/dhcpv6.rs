@@ -1,24 +1,74 @@
 // DHCPv6 minimal decoder (RFC 8415)
-use crate::utils::cow_struct;
+use super::detect::confidence;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, ByteReader};
 
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub msg_type: u8,
-    pub xid: [u8; 3],
+wire_struct! {
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        pub msg_type: u8,
+        pub xid: [u8; 3],
+    }
 }
 
 impl Header {
     pub const SIZE: usize = 4;
 }
 
-pub fn decode(data: &[u8]) {
-    if data.len() < Header::SIZE {
-        return;
+/// Confidence that the payload is a DHCPv6 client/server message: a message type, then
+/// options filling the payload exactly
+pub fn probe(data: &[u8]) -> u8 {
+    let mut r = ByteReader::new(data);
+    match r.view::<Header>() {
+        Ok(h) if (1..=13).contains(&h.msg_type) => {}
+        _ => return confidence::NONE,
+    }
+    let mut client_id = false;
+    while let Ok((code, _)) = r.tlv16() {
+        client_id |= code == 1;
+    }
+    match (r.is_empty(), client_id) {
+        (true, true) => confidence::HIGH,
+        (true, false) if r.position() > Header::SIZE => confidence::LOW,
+        _ => confidence::NONE,
     }
-    let (hdr, _) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        let xid = u32::from_be_bytes([0, h.xid[0], h.xid[1], h.xid[2]]);
-        println!("DHCPv6 type={} xid=0x{:06x}", h.msg_type, xid);
+}
+
+pub fn decode(data: &[u8]) {
+    let h = match utils::view::<Header>(data) {
+        Some(h) => h,
+        None => {
+            expert::error(DecodeError::Truncated {
+                layer: "dhcpv6",
+                offset: data.len(),
+            });
+            return;
+        }
+    };
+    let xid = u32::from_be_bytes([0, h.xid[0], h.xid[1], h.xid[2]]);
+    let line = format!("DHCPv6 type={} xid=0x{:06x}", h.msg_type, xid);
+    println!("{}", line);
+    tree::layer("dhcpv6", data, line);
+    tree::field("Message type", &h.msg_type, h.msg_type);
+    tree::field("Transaction ID", &h.xid, format_args!("0x{:06x}", xid));
+    let options = &data[Header::SIZE..];
+    let mut r = ByteReader::new(options);
+    while !r.is_empty() {
+        let start = r.position();
+        match r.tlv16() {
+            Ok((code, value)) => tree::field(
+                "Option",
+                &options[start..r.position()],
+                format_args!("({}) {}", code, tree::hex(value)),
+            ),
+            Err(_) => {
+                expert::error(DecodeError::Truncated {
+                    layer: "dhcpv6",
+                    offset: Header::SIZE + start,
+                });
+                break;
+            }
+        }
     }
 }
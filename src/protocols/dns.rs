@@ -1,43 +1,306 @@
-use crate::utils::cow_struct;
+use super::detect::confidence;
+use crate::analysis::{ioc, rules};
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, ByteReader, Truncated, U16Be};
 use std::mem::size_of;
+use std::net::IpAddr;
 
-/// Minimal DNS header (RFC 1035)
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub id: u16,
-    pub flags: u16,
-    pub qdcount: u16,
-    pub ancount: u16,
-    pub nscount: u16,
-    pub arcount: u16,
+wire_struct! {
+    /// Minimal DNS header (RFC 1035)
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        pub id: U16Be,
+        pub flags: U16Be,
+        pub qdcount: U16Be,
+        pub ancount: U16Be,
+        pub nscount: U16Be,
+        pub arcount: U16Be,
+    }
 }
 
 impl Header {
     pub const SIZE: usize = size_of::<Self>();
 }
 
+/// Compression pointers followed while reading a name
+const MAX_POINTERS: usize = 16;
+
+/// Name at `offset` of the message, and the offset after it
+///
+/// Compression pointers (RFC 1035 4.1.4) are followed, the returned offset is the one
+/// after the name as written at `offset`.
+pub fn name(msg: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let (mut i, mut end, mut pointers) = (offset, None, 0);
+    loop {
+        let len = *msg.get(i)? as usize;
+        match len & 0xc0 {
+            0 if len == 0 => break,
+            0 => {
+                let label = msg.get(i + 1..i + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                i += 1 + len;
+            }
+            0xc0 => {
+                let target = ((len & 0x3f) << 8) | *msg.get(i + 1)? as usize;
+                end.get_or_insert(i + 2);
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    return None;
+                }
+                i = target;
+            }
+            _ => return None,
+        }
+    }
+    Some((labels.join("."), end.unwrap_or(i + 1)))
+}
+
+/// Record types
+#[non_exhaustive]
+pub struct TYPE;
+
+impl TYPE {
+    pub const A: u16 = 1;
+    pub const NS: u16 = 2;
+    pub const CNAME: u16 = 5;
+    pub const PTR: u16 = 12;
+    pub const MX: u16 = 15;
+    pub const AAAA: u16 = 28;
+}
+
+/// Content of a resource record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RData {
+    Addr(IpAddr),
+    /// CNAME, NS, PTR, or the exchange of an MX
+    Name(String),
+    Other(u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub name: String,
+    pub rtype: u16,
+    pub ttl: u32,
+    pub data: RData,
+}
+
+/// Name, type and class of the question at the reader
+fn question(msg: &[u8], r: &mut ByteReader) -> Result<(String, u16, u16), Truncated> {
+    let (qname, next) = name(msg, r.position()).ok_or(Truncated)?;
+    r.seek(next)?;
+    Ok((qname, r.u16()?, r.u16()?))
+}
+
+/// Name, type and class of each question of a message, and the offset after them
+pub fn questions(msg: &[u8], count: u16) -> (Vec<(String, u16, u16)>, usize) {
+    let mut out = Vec::new();
+    let mut r = ByteReader::new(msg);
+    let mut end = Header::SIZE;
+    if r.seek(end).is_err() {
+        return (out, end);
+    }
+    for _ in 0..count {
+        match question(msg, &mut r) {
+            Ok(q) => out.push(q),
+            Err(_) => break,
+        }
+        end = r.position();
+    }
+    (out, end)
+}
+
+/// Resource record at the reader
+fn record(msg: &[u8], r: &mut ByteReader) -> Result<Record, Truncated> {
+    let (rname, next) = name(msg, r.position()).ok_or(Truncated)?;
+    r.seek(next)?;
+    let rtype = r.u16()?;
+    let _class = r.u16()?;
+    let ttl = r.u32()?;
+    let start = r.position() + 2;
+    let rdata = r.bytes_u16()?;
+    let data = match (rtype, rdata.len()) {
+        (TYPE::A, 4) => RData::Addr(IpAddr::from(<[u8; 4]>::try_from(rdata).expect("4 bytes"))),
+        (TYPE::AAAA, 16) => {
+            RData::Addr(IpAddr::from(<[u8; 16]>::try_from(rdata).expect("16 bytes")))
+        }
+        (TYPE::CNAME | TYPE::NS | TYPE::PTR, _) => match name(msg, start) {
+            Some((n, _)) => RData::Name(n),
+            None => RData::Other(rtype),
+        },
+        (TYPE::MX, _) => match name(msg, start + 2) {
+            Some((n, _)) => RData::Name(n),
+            None => RData::Other(rtype),
+        },
+        _ => RData::Other(rtype),
+    };
+    Ok(Record {
+        name: rname,
+        rtype,
+        ttl,
+        data,
+    })
+}
+
+/// `count` resource records starting at `offset`
+pub fn records(msg: &[u8], offset: usize, count: u16) -> Vec<Record> {
+    let mut out = Vec::new();
+    let mut r = ByteReader::new(msg);
+    if r.seek(offset).is_err() {
+        return out;
+    }
+    for _ in 0..count {
+        match record(msg, &mut r) {
+            Ok(rr) => out.push(rr),
+            Err(_) => break,
+        }
+    }
+    out
+}
+
 pub fn display(h: &Header) -> String {
     format!(
         "DNS id={} qd={} an={} ns={} ar={}",
-        u16::from_be(h.id),
-        u16::from_be(h.qdcount),
-        u16::from_be(h.ancount),
-        u16::from_be(h.nscount),
-        u16::from_be(h.arcount)
+        h.id, h.qdcount, h.ancount, h.nscount, h.arcount
     )
 }
 
-pub fn decode(data: &[u8]) {
-    if data.len() < Header::SIZE {
-        println!("dns: truncated");
+/// Confidence that the payload is a DNS query or response: sane header and questions
+/// filling the start of the message
+pub fn probe(data: &[u8]) -> u8 {
+    let h = match utils::view::<Header>(data) {
+        Some(h) if data.len() >= Header::SIZE + 5 => h,
+        _ => return confidence::NONE,
+    };
+    let opcode = (h.flags.get() >> 11) & 0x0f;
+    let qdcount = h.qdcount.get();
+    if !matches!(opcode, 0 | 1 | 2 | 4 | 5) || !(1..=4).contains(&qdcount) {
+        return confidence::NONE;
+    }
+    let (questions, end) = questions(data, qdcount);
+    // class IN, CH, HS or ANY, mDNS sets the top bit for unicast answers
+    let sane = questions
+        .iter()
+        .all(|(_, qtype, qclass)| *qtype != 0 && matches!(qclass & 0x7fff, 1 | 3 | 4 | 255));
+    if questions.len() == qdcount as usize && sane && end <= data.len() {
+        confidence::HIGH
+    } else {
+        confidence::NONE
+    }
+}
+
+/// Confidence that the payload is a DNS message over a stream, after its length
+pub fn probe_stream(data: &[u8]) -> u8 {
+    let mut r = ByteReader::new(data);
+    match r.bytes_u16() {
+        Ok(msg) if r.is_empty() => probe(msg),
+        _ => confidence::NONE,
+    }
+}
+
+/// Decode messages over TCP, prefixed with their length
+pub fn decode_stream(payload: &[u8]) {
+    match ByteReader::new(payload).bytes_u16() {
+        Ok(msg) => {
+            println!("DNS/TCP len={}", msg.len());
+            decode(msg);
+        }
+        // the message goes on in the next segments
+        Err(_) if payload.len() >= 2 => expert::error(DecodeError::Truncated {
+            layer: "dns",
+            offset: payload.len(),
+        }),
+        Err(_) => {}
+    }
+}
+
+/// Add the header, questions and records of a message to the protocol tree
+fn annotate(data: &[u8], h: &Header) {
+    tree::layer("dns", data, display(h));
+    tree::field(
+        "Transaction ID",
+        &h.id,
+        format_args!("0x{:04x}", h.id.get()),
+    );
+    tree::field("Flags", &h.flags, format_args!("0x{:04x}", h.flags.get()));
+    tree::field("Questions", &h.qdcount, h.qdcount);
+    tree::field("Answer RRs", &h.ancount, h.ancount);
+    tree::field("Authority RRs", &h.nscount, h.nscount);
+    tree::field("Additional RRs", &h.arcount, h.arcount);
+    let mut r = ByteReader::new(data);
+    if r.seek(Header::SIZE).is_err() {
         return;
     }
-    let (hdr_bytes, _rest) = data.split_at(Header::SIZE);
-    match cow_struct::<Header>(hdr_bytes) {
+    let mut start = r.position();
+    for _ in 0..h.qdcount.get() {
+        match question(data, &mut r) {
+            Ok((qname, qtype, qclass)) => tree::field(
+                "Question",
+                &data[start..r.position()],
+                format_args!("{} type {} class {}", qname, qtype, qclass),
+            ),
+            Err(_) => return,
+        }
+        start = r.position();
+    }
+    let sections = [
+        ("Answer", h.ancount.get()),
+        ("Authority", h.nscount.get()),
+        ("Additional", h.arcount.get()),
+    ];
+    for (section, count) in sections {
+        for _ in 0..count {
+            let rr = match record(data, &mut r) {
+                Ok(rr) => rr,
+                Err(_) => return,
+            };
+            let value = match &rr.data {
+                RData::Addr(a) => format!("{} type {} ttl {} {}", rr.name, rr.rtype, rr.ttl, a),
+                RData::Name(n) => format!("{} type {} ttl {} {}", rr.name, rr.rtype, rr.ttl, n),
+                RData::Other(_) => format!("{} type {} ttl {}", rr.name, rr.rtype, rr.ttl),
+            };
+            tree::field(section, &data[start..r.position()], value);
+            start = r.position();
+        }
+    }
+}
+
+pub fn decode(data: &[u8]) {
+    match utils::view::<Header>(data) {
         Some(h) => {
-            println!("{}", display(&h));
+            println!("{}", display(h));
+            if tree::enabled() {
+                annotate(data, h);
+            }
+            let response = h.flags.get() & 0x8000 != 0;
+            let (questions, end) = questions(data, h.qdcount.get());
+            for (qname, qtype, _) in questions {
+                println!("DNS question {} type={}", qname, qtype);
+                if !response {
+                    rules::on_dns_query(&qname);
+                    ioc::on_domain("dns.query", &qname);
+                }
+            }
+            for r in records(data, end, h.ancount.get()) {
+                match &r.data {
+                    RData::Addr(a) => {
+                        println!("DNS answer {} type={} {}", r.name, r.rtype, a);
+                        ioc::on_ip("dns.answer", *a);
+                    }
+                    RData::Name(n) => {
+                        println!("DNS answer {} type={} {}", r.name, r.rtype, n);
+                        ioc::on_domain("dns.answer", n);
+                    }
+                    RData::Other(_) => println!("DNS answer {} type={}", r.name, r.rtype),
+                }
+                ioc::on_domain("dns.answer", &r.name);
+            }
         }
-        None => println!("dns decode error: {:?}", "Truncated payload"),
+        None => expert::error(DecodeError::Truncated {
+            layer: "dns",
+            offset: 0,
+        }),
     }
 }
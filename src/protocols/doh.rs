@@ -1,6 +1,7 @@
 // Minimal DNS-over-HTTPS (DoH) detector for HTTP/1.x plaintext
 // Looks for request path or content-type indicating DoH.
 // Returns true if it looked like DoH and printed something.
+use crate::tree;
 
 pub fn decode(data: &[u8]) -> bool {
     let max = core::cmp::min(1024, data.len());
@@ -27,7 +28,13 @@ pub fn decode(data: &[u8]) -> bool {
         }
     }
     if is_doh {
-        println!("DoH {} {}", first.trim(), ct);
+        let line = format!("DoH {} {}", first.trim(), ct);
+        println!("{}", line);
+        tree::layer("doh", data, line);
+        tree::field("Start line", first.as_bytes(), first.trim());
+        if !ct.is_empty() {
+            tree::field("Content-Type", ct.as_bytes(), ct);
+        }
         return true;
     }
     false
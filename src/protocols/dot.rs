@@ -1,15 +1,21 @@
 // Minimal DNS-over-TLS (DoT) helper
 // If plaintext (non-TLS) is seen (non-standard), try to parse 2-byte length-prefixed DNS message.
+use crate::tree;
 
 pub fn decode(data: &[u8]) {
     // Try to parse length-prefixed DNS; if too short, just label DoT
     if data.len() >= 2 {
         let len = u16::from_be_bytes([data[0], data[1]]) as usize;
         if data.len() >= 2 + len {
-            println!("DoT DNS len={}", len);
+            let line = format!("DoT DNS len={}", len);
+            println!("{}", line);
+            tree::layer("dot", &data[..2], line);
+            tree::field("Length", &data[..2], len);
             super::dns::decode(&data[2..2 + len]);
             return;
         }
     }
-    println!("DoT (TLS likely) {}B", data.len());
+    let line = format!("DoT (TLS likely) {}B", data.len());
+    println!("{}", line);
+    tree::layer("dot", data, line);
 }
@@ -1,16 +1,19 @@
 // EIGRP minimal header decoder
-use crate::utils::cow_struct;
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be};
 
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub version: u8,
-    pub opcode: u8,
-    pub checksum: u16,
-    pub flags: u32,
-    pub seq: u32,
-    pub ack: u32,
-    pub asn: u32,
+wire_struct! {
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        pub version: u8,
+        pub opcode: u8,
+        pub checksum: U16Be,
+        pub flags: U32Be,
+        pub seq: U32Be,
+        pub ack: U32Be,
+        pub router_id: U16Be,
+        pub asn: U16Be,
+    }
 }
 
 impl Header {
@@ -21,16 +24,29 @@ pub fn decode(data: &[u8]) {
     if data.len() < Header::SIZE {
         return;
     }
-    let (hdr, _) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        println!(
+    if let Some(h) = utils::view::<Header>(data) {
+        let line = format!(
             "EIGRP v{} op={} flags=0x{:08x} seq={} ack={} asn={}",
             h.version,
             h.opcode,
-            u32::from_be(h.flags),
-            u32::from_be(h.seq),
-            u32::from_be(h.ack),
-            u32::from_be(h.asn)
+            h.flags.get(),
+            h.seq,
+            h.ack,
+            h.asn
+        );
+        println!("{}", line);
+        tree::layer("eigrp", h, line);
+        tree::field("Version", &h.version, h.version);
+        tree::field("Opcode", &h.opcode, h.opcode);
+        tree::field(
+            "Checksum",
+            &h.checksum,
+            format_args!("0x{:04x}", h.checksum.get()),
         );
+        tree::field("Flags", &h.flags, format_args!("0x{:08x}", h.flags.get()));
+        tree::field("Sequence", &h.seq, h.seq);
+        tree::field("Acknowledge", &h.ack, h.ack);
+        tree::field("Virtual router ID", &h.router_id, h.router_id);
+        tree::field("Autonomous system", &h.asn, h.asn);
     }
 }
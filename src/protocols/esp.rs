@@ -1,24 +1,26 @@
-use crate::utils::cow_struct;
+use crate::tree;
+use crate::utils::{self, wire_struct, U32Be};
 use std::mem::size_of;
 
-/// Encapsulation Security payload
-///
-/// Design to provide security services, confidentiality, data origin authentification,
-/// connectionless integrity, anti replay and limited traffic flow confidentiality
-/// TODO: see how to get the size variable data and payload length to get next_data header
-///
-/// Sources:
-/// * https://tools.ietf.org/html/rfc4303
-/// * https://tools.ietf.org/html/rfc8221
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    /// security parameters index, random value to combine with the destination ip address and
-    /// security protocol
-    pub spi: u32,
-    /// sequence number field, counter value increase at each node, proccess of this field is at
-    /// the discretion of the receiver.
-    pub seq_number: u32,
+wire_struct! {
+    /// Encapsulation Security payload
+    ///
+    /// Design to provide security services, confidentiality, data origin authentification,
+    /// connectionless integrity, anti replay and limited traffic flow confidentiality
+    /// TODO: see how to get the size variable data and payload length to get next_data header
+    ///
+    /// Sources:
+    /// * https://tools.ietf.org/html/rfc4303
+    /// * https://tools.ietf.org/html/rfc8221
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        /// security parameters index, random value to combine with the destination ip address and
+        /// security protocol
+        pub spi: U32Be,
+        /// sequence number field, counter value increase at each node, proccess of this field is at
+        /// the discretion of the receiver.
+        pub seq_number: U32Be,
+    }
 }
 
 impl Header {
@@ -26,17 +28,14 @@ impl Header {
 }
 
 pub fn display(h: &Header) -> String {
-    format!("ESP spi={} seq={}", h.spi.to_be(), h.seq_number.to_be())
+    format!("ESP spi={} seq={}", h.spi, h.seq_number)
 }
 
 pub fn decode(data: &[u8]) {
-    if data.len() >= Header::SIZE {
-        let (header_bytes, _next_data) = data.split_at(Header::SIZE);
-        match cow_struct::<Header>(header_bytes) {
-            Some(h) => {
-                println!("{}", display(&h));
-            }
-            None => println!("ip::esp decode error: {:?}", "Truncated payload"),
-        }
+    if let Some(h) = utils::view::<Header>(data) {
+        println!("{}", display(h));
+        tree::layer("esp", data, display(h));
+        tree::field("SPI", &h.spi, h.spi);
+        tree::field("Sequence number", &h.seq_number, h.seq_number);
     }
 }
@@ -1,84 +1,390 @@
-use crate::utils::cow_struct;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, registry, wire_struct, U16Be};
 use std::mem::size_of;
 
 use super::arp;
 use super::ipv4;
 use super::ipv6;
 
-#[derive(Debug, Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub dhost: [u8; 6],
-    pub shost: [u8; 6],
-    pub ether_type: u16,
+wire_struct! {
+    #[derive(Debug, Default, Clone, Copy)]
+    pub struct Header {
+        pub dhost: [u8; 6],
+        pub shost: [u8; 6],
+        pub ether_type: U16Be,
+    }
 }
 
 impl Header {
     pub const SIZE: usize = size_of::<Self>();
 }
 
-/// Ether type value for protocol encapsulation
-///
-/// This define values use to determine the protocol encapsulated in the ethernet frame
-/// Value are defined by Internet Assigned Numbers Authority (IANA)
-///
-/// Source:
-/// * https://www.iana.org/assignments/ieee-802-numbers/ieee-802-numbers.xhtml
-#[non_exhaustive]
-pub struct PROTO;
-
-impl PROTO {
-    pub const PUP: u16 = 0x0200;
-    pub const SPRITE: u16 = 0x0500;
-    pub const IPV4: u16 = 0x0800;
-    pub const ARP: u16 = 0x0806;
-    pub const REVARP: u16 = 0x8035;
-    pub const AT: u16 = 0x809B;
-    pub const AARP: u16 = 0x80F3;
-    pub const VLAN: u16 = 0x8100;
-    pub const IPX: u16 = 0x8137;
-    pub const IPV6: u16 = 0x86dd;
-    pub const LLDP: u16 = 0x88cc;
-    pub const MPLS_U: u16 = 0x8847; // MPLS Unicast
-    pub const MPLS_M: u16 = 0x8848; // MPLS Multicast
-    pub const PPPOE_DISC: u16 = 0x8863;
-    pub const PPPOE_SESS: u16 = 0x8864;
-    pub const LOOPBACK: u16 = 0x9000;
-}
-
-/// ether type protocol to str
-///
-/// Transform an u16 to a humain readable str
-/// if the value of the given u16 match one of the value in ethernet::PROTO
-/// then a str corresponding to the op code is returned
-/// # Examples
-/// ```
-/// println!(op_as_str(0x1));   // will print REQUEST
-/// println!(op_as_str(0x2a));  // will print UNKNOW
-/// ```
-pub fn ether_type_as_str(ether_type: u16) -> &'static str {
-    match ether_type {
-        PROTO::PUP => "PUP",
-        PROTO::SPRITE => "SPRITE",
-        PROTO::IPV4 => "IPV4",
-        PROTO::ARP => "ARP",
-        PROTO::REVARP => "REVARP",
-        PROTO::AT => "AT",
-        PROTO::AARP => "AARP",
-        PROTO::VLAN => "VLAN",
-        PROTO::IPX => "IPX",
-        PROTO::IPV6 => "IPV6",
-        PROTO::LLDP => "LLDP",
-        PROTO::MPLS_U => "MPLS_U",
-        PROTO::MPLS_M => "MPLS_M",
-        PROTO::PPPOE_DISC => "PPPoE-Discovery",
-        PROTO::PPPOE_SESS => "PPPoE-Session",
-        PROTO::LOOPBACK => "LOOPBACK",
-        _ => "UNKNOW",
+registry! {
+    /// EtherType of the protocol encapsulated in an ethernet frame
+    ///
+    /// Values up to 1500 are not EtherTypes but the length of an IEEE 802.3 frame, followed
+    /// by an LLC header. Blocks of values assigned to a vendor as a whole are left
+    /// `Unknown`.
+    ///
+    /// Sources:
+    /// * https://www.iana.org/assignments/ieee-802-numbers/ieee-802-numbers.xhtml
+    /// * https://standards-oui.ieee.org/ethertype/eth.txt
+    pub enum EtherType: u16, unknown = "0x{:04x}" {
+        /// XEROX PUP
+        Pup = 0x0200 => "PUP",
+        /// PUP Addr Trans
+        PupAddrTrans = 0x0201 => "PUPAT",
+        /// Nixdorf
+        Nixdorf = 0x0400 => "Nixdorf",
+        /// Sprite, as in BSD
+        Sprite = 0x0500 => "Sprite",
+        /// XEROX NS IDP
+        XnsIdp = 0x0600 => "XNS-IDP",
+        /// DLOG
+        Dlog = 0x0660 => "DLOG",
+        /// DLOG
+        Dlog2 = 0x0661 => "DLOG-2",
+        /// Internet Protocol version 4
+        Ipv4 = 0x0800 => "IPv4",
+        /// X.75 Internet
+        X75 = 0x0801 => "X.75",
+        /// NBS Internet
+        NbsInternet = 0x0802 => "NBS",
+        /// ECMA Internet
+        EcmaInternet = 0x0803 => "ECMA",
+        /// Chaosnet
+        Chaosnet = 0x0804 => "Chaosnet",
+        /// X.25 Level 3
+        X25 = 0x0805 => "X.25",
+        /// Address Resolution Protocol
+        Arp = 0x0806 => "ARP",
+        /// XNS Compatibility
+        XnsCompatibility = 0x0807 => "XNS-Compat",
+        /// Frame Relay ARP
+        FrameRelayArp = 0x0808 => "FR-ARP",
+        /// Symbolics Private
+        Symbolics = 0x081C => "Symbolics",
+        /// Ungermann-Bass net debugger
+        UngermannBassDebug = 0x0900 => "UB-Debug",
+        /// Xerox IEEE 802.3 PUP
+        XeroxPup8023 = 0x0A00 => "PUP-802.3",
+        /// PUP Addr Trans over IEEE 802.3
+        PupAddrTrans8023 = 0x0A01 => "PUPAT-802.3",
+        /// Banyan VINES
+        Vines = 0x0BAD => "VINES",
+        /// VINES Loopback
+        VinesLoopback = 0x0BAE => "VINES-Loopback",
+        /// VINES Echo
+        VinesEcho = 0x0BAF => "VINES-Echo",
+        /// Berkeley Trailer negotiation
+        BerkeleyTrailer = 0x1000 => "Trailer",
+        /// Valid Systems
+        ValidSystems = 0x1600 => "Valid",
+        /// IEEE 1722 Audio Video Transport Protocol
+        Avtp = 0x22F0 => "AVTP",
+        /// TRILL
+        Trill = 0x22F3 => "TRILL",
+        /// L2-IS-IS
+        L2IsIs = 0x22F4 => "L2-IS-IS",
+        /// PCS Basic Block Protocol
+        PcsBasicBlock = 0x4242 => "PCS",
+        /// BBN Simnet
+        BbnSimnet = 0x5208 => "Simnet",
+        /// DEC MOP Dump/Load
+        DecMopDump = 0x6001 => "MOP-DL",
+        /// DEC MOP Remote Console
+        DecMopRemoteConsole = 0x6002 => "MOP-RC",
+        /// DEC DECNET Phase IV Route
+        DecnetRoute = 0x6003 => "DECnet",
+        /// DEC LAT
+        DecLat = 0x6004 => "LAT",
+        /// DEC Diagnostic Protocol
+        DecDiagnostic = 0x6005 => "DEC-Diag",
+        /// DEC Customer Protocol
+        DecCustomer = 0x6006 => "DEC-Customer",
+        /// DEC LAVC, SCA
+        DecLavc = 0x6007 => "LAVC",
+        /// Trans Ether Bridging
+        TransEtherBridging = 0x6558 => "TEB",
+        /// Raw Frame Relay
+        RawFrameRelay = 0x6559 => "Raw-FR",
+        /// Ungermann-Bass download
+        UngermannBassDownload = 0x7000 => "UB-Download",
+        /// Ungermann-Bass dia/loop
+        UngermannBassLoop = 0x7002 => "UB-Loop",
+        /// Proteon
+        Proteon = 0x7030 => "Proteon",
+        /// Cabletron
+        Cabletron = 0x7034 => "Cabletron",
+        /// Cronus VLN
+        CronusVln = 0x8003 => "Cronus-VLN",
+        /// Cronus Direct
+        CronusDirect = 0x8004 => "Cronus-Direct",
+        /// HP Probe
+        HpProbe = 0x8005 => "HP-Probe",
+        /// Nestar
+        Nestar = 0x8006 => "Nestar",
+        /// AT&T
+        AtT = 0x8008 => "AT&T",
+        /// Excelan
+        Excelan = 0x8010 => "Excelan",
+        /// SGI diagnostics
+        SgiDiagnostics = 0x8013 => "SGI-Diag",
+        /// SGI network games
+        SgiNetworkGames = 0x8014 => "SGI-Games",
+        /// SGI reserved
+        SgiReserved = 0x8015 => "SGI-Reserved",
+        /// SGI bounce server
+        SgiBounceServer = 0x8016 => "SGI-Bounce",
+        /// Apollo Domain
+        ApolloDomain = 0x8019 => "Apollo-Domain",
+        /// Tymshare
+        Tymshare = 0x802E => "Tymshare",
+        /// Tigan, Inc.
+        Tigan = 0x802F => "Tigan",
+        /// Reverse Address Resolution Protocol
+        Rarp = 0x8035 => "RARP",
+        /// Aeonic Systems
+        Aeonic = 0x8036 => "Aeonic",
+        /// DEC LANBridge
+        DecLanBridge = 0x8038 => "DEC-LANBridge",
+        /// DEC Ethernet Encryption
+        DecEncryption = 0x803D => "DEC-Encryption",
+        /// DEC LAN Traffic Monitor
+        DecLanTrafficMonitor = 0x803F => "DEC-LTM",
+        /// Planning Research Corp.
+        PlanningResearch = 0x8044 => "PRC",
+        /// ExperData
+        ExperData = 0x8049 => "ExperData",
+        /// Stanford V Kernel experimental
+        VKernelExp = 0x805B => "V-Kernel-Exp",
+        /// Stanford V Kernel production
+        VKernelProd = 0x805C => "V-Kernel",
+        /// Evans & Sutherland
+        EvansSutherland = 0x805D => "E&S",
+        /// Little Machines
+        LittleMachines = 0x8060 => "Little-Machines",
+        /// Counterpoint Computers
+        Counterpoint = 0x8062 => "Counterpoint",
+        /// Veeco Integrated Auto.
+        Veeco = 0x8067 => "Veeco",
+        /// General Dynamics
+        GeneralDynamics = 0x8068 => "General-Dynamics",
+        /// AT&T
+        AtT2 = 0x8069 => "AT&T-2",
+        /// Autophon
+        Autophon = 0x806A => "Autophon",
+        /// ComDesign
+        ComDesign = 0x806C => "ComDesign",
+        /// Computgraphic Corp.
+        Computgraphic = 0x806D => "Computgraphic",
+        /// Matra
+        Matra = 0x807A => "Matra",
+        /// Dansk Data Elektronik
+        DanskData = 0x807B => "DDE",
+        /// Merit Internodal
+        MeritInternodal = 0x807C => "Merit",
+        /// Vitalink TransLAN III
+        VitalinkTransLan = 0x8080 => "TransLAN",
+        /// AppleTalk (EtherTalk)
+        AppleTalk = 0x809B => "AppleTalk",
+        /// Spider Systems Ltd.
+        SpiderSystems = 0x809F => "Spider",
+        /// Nixdorf Computers
+        NixdorfComputers = 0x80A3 => "Nixdorf-Computers",
+        /// Pacer Software
+        Pacer = 0x80C6 => "Pacer",
+        /// Applitek Corporation
+        Applitek = 0x80C7 => "Applitek",
+        /// IBM SNA Service on Ether
+        IbmSna = 0x80D5 => "SNA",
+        /// Varian Associates
+        Varian = 0x80DD => "Varian",
+        /// Retix
+        Retix = 0x80F2 => "Retix",
+        /// AppleTalk Address Resolution Protocol
+        Aarp = 0x80F3 => "AARP",
+        /// Apollo Computer
+        ApolloComputer = 0x80F7 => "Apollo",
+        /// Wellfleet Communications
+        Wellfleet = 0x80FF => "Wellfleet",
+        /// IEEE 802.1Q customer VLAN tag (C-Tag)
+        Vlan = 0x8100 => "VLAN",
+        /// Hayes Microcomputers
+        Hayes = 0x8130 => "Hayes",
+        /// VG Laboratory Systems
+        VgLaboratory = 0x8131 => "VG-Lab",
+        /// Novell IPX
+        Ipx = 0x8137 => "IPX",
+        /// Novell, Inc.
+        Novell = 0x8138 => "Novell",
+        /// Logicraft
+        Logicraft = 0x8148 => "Logicraft",
+        /// Network Computing Devices
+        Ncd = 0x8149 => "NCD",
+        /// Alpha Micro
+        AlphaMicro = 0x814A => "Alpha-Micro",
+        /// SNMP over Ethernet
+        Snmp = 0x814C => "SNMP",
+        /// BIIN
+        Biin = 0x814D => "BIIN",
+        /// BIIN
+        Biin2 = 0x814E => "BIIN-2",
+        /// Technically Elite Concept
+        EliteConcept = 0x814F => "TEC",
+        /// Rational Corp
+        Rational = 0x8150 => "Rational",
+        /// XTP
+        Xtp = 0x817D => "XTP",
+        /// SGI/Time Warner proprietary
+        SgiTimeWarner = 0x817E => "SGI-TW",
+        /// HIPPI-FP encapsulation
+        HippiFp = 0x8180 => "HIPPI-FP",
+        /// STP, HIPPI-ST
+        HippiSt = 0x8181 => "HIPPI-ST",
+        /// Reserved for HIPPI-6400
+        Hippi6400 = 0x8182 => "HIPPI-6400",
+        /// Reserved for HIPPI-6400
+        Hippi6400Second = 0x8183 => "HIPPI-6400-2",
+        /// Motorola Computer
+        Motorola = 0x818D => "Motorola",
+        /// ARAI Bunkichi
+        AraiBunkichi = 0x81A4 => "ARAI",
+        /// SECTRA
+        Sectra = 0x86DB => "SECTRA",
+        /// Internet Protocol version 6
+        Ipv6 = 0x86DD => "IPv6",
+        /// Delta Controls
+        DeltaControls = 0x86DE => "Delta",
+        /// ATOMIC
+        Atomic = 0x86DF => "ATOMIC",
+        /// TCP/IP Compression
+        TcpIpCompression = 0x876B => "TCP/IP-Compression",
+        /// IP Autonomous Systems
+        IpAutonomousSystems = 0x876C => "IP-AS",
+        /// Secure Data
+        SecureData = 0x876D => "Secure-Data",
+        /// IEEE 802.3 MAC control, Ethernet Passive Optical Network (EPON)
+        Epon = 0x8808 => "EPON",
+        /// IEEE 802.3 Slow Protocols (LACP, OAM)
+        SlowProtocols = 0x8809 => "Slow",
+        /// Point-to-Point Protocol
+        Ppp = 0x880B => "PPP",
+        /// General Switch Management Protocol
+        Gsmp = 0x880C => "GSMP",
+        /// Ethernet NIC hardware and software testing
+        NicTest = 0x8822 => "NIC-Test",
+        /// MPLS
+        Mpls = 0x8847 => "MPLS",
+        /// MPLS with upstream-assigned label, formerly MPLS multicast
+        MplsUpstream = 0x8848 => "MPLS-UA",
+        /// Multicast Channel Allocation Protocol
+        Mcap = 0x8861 => "MCAP",
+        /// PPPoE Discovery Stage
+        PppoeDiscovery = 0x8863 => "PPPoE-Discovery",
+        /// PPPoE Session Stage
+        PppoeSession = 0x8864 => "PPPoE-Session",
+        /// HomePlug 1.0 MME
+        HomePlug = 0x887B => "HomePlug",
+        /// IEEE 802.1X port-based network access control
+        Eapol = 0x888E => "EAPOL",
+        /// PROFINET
+        Profinet = 0x8892 => "PROFINET",
+        /// HyperSCSI
+        HyperScsi = 0x889A => "HyperSCSI",
+        /// ATA over Ethernet
+        AtaOverEthernet = 0x88A2 => "AoE",
+        /// EtherCAT
+        EtherCat = 0x88A4 => "EtherCAT",
+        /// IEEE 802.1Q service VLAN tag (S-Tag)
+        ServiceVlan = 0x88A8 => "S-VLAN",
+        /// Ethernet Powerlink
+        Powerlink = 0x88AB => "Powerlink",
+        /// IEEE 802 local experimental EtherType
+        LocalExperimental1 = 0x88B5 => "Local-Experimental-1",
+        /// IEEE 802 local experimental EtherType
+        LocalExperimental2 = 0x88B6 => "Local-Experimental-2",
+        /// IEEE 802 OUI extended EtherType
+        OuiExtended = 0x88B7 => "OUI-Extended",
+        /// IEC 61850 GOOSE
+        Goose = 0x88B8 => "GOOSE",
+        /// IEC 61850 sampled values
+        SampledValues = 0x88BA => "SV",
+        /// IEEE 802.11i pre-authentication
+        PreAuthentication = 0x88C7 => "802.11-PreAuth",
+        /// IEEE 802.1AB Link Layer Discovery Protocol
+        Lldp = 0x88CC => "LLDP",
+        /// SERCOS III
+        SercosIII = 0x88CD => "SERCOS-III",
+        /// HomePlug AV MME
+        HomePlugAv = 0x88E1 => "HomePlug-AV",
+        /// Media Redundancy Protocol (IEC 62439-2)
+        Mrp = 0x88E3 => "MRP",
+        /// IEEE 802.1AE MAC security
+        Macsec = 0x88E5 => "MACsec",
+        /// Provider Backbone Bridging instance tag (I-Tag)
+        Pbb = 0x88E7 => "PBB",
+        /// IEEE 802.1Q Multiple VLAN Registration Protocol
+        Mvrp = 0x88F5 => "MVRP",
+        /// IEEE 802.1Q Multiple Multicast Registration Protocol
+        Mmrp = 0x88F6 => "MMRP",
+        /// Precision Time Protocol (IEEE 1588)
+        Ptp = 0x88F7 => "PTP",
+        /// Network Controller Sideband Interface
+        NcSi = 0x88F8 => "NC-SI",
+        /// Parallel Redundancy Protocol (IEC 62439-3)
+        Prp = 0x88FB => "PRP",
+        /// IEEE 802.1ag connectivity fault management
+        Cfm = 0x8902 => "CFM",
+        /// Fibre Channel over Ethernet
+        Fcoe = 0x8906 => "FCoE",
+        /// IEEE 802.11r fast roaming remote request
+        FastRoaming = 0x890D => "802.11r",
+        /// FCoE Initialization Protocol
+        Fip = 0x8914 => "FIP",
+        /// RDMA over Converged Ethernet
+        Roce = 0x8915 => "RoCE",
+        /// IEEE 802.21 Media Independent Handover Protocol
+        Mih = 0x8917 => "MIH",
+        /// IEEE 802.1Qbe Multiple I-SID Registration Protocol
+        Misrp = 0x8929 => "MIRP",
+        /// High-availability Seamless Redundancy (IEC 62439-3)
+        Hsr = 0x892F => "HSR",
+        /// IEEE 1905.1
+        Ieee1905 = 0x893A => "1905.1",
+        /// TRILL Fine Grained Labeling
+        TrillFgl = 0x893B => "TRILL-FGL",
+        /// IEEE 802.1Qbg Edge Control Protocol
+        Ecp = 0x8940 => "ECP",
+        /// TRILL RBridge Channel
+        TrillChannel = 0x8946 => "TRILL-Channel",
+        /// GeoNetworking (ETSI EN 302 636-4-1)
+        GeoNetworking = 0x8947 => "GeoNetworking",
+        /// Network Service Header
+        Nsh = 0x894F => "NSH",
+        /// Configuration Testing Protocol (loopback)
+        Loopback = 0x9000 => "Loopback",
+        /// 3Com (Bridge) XNS Sys Mgmt
+        BridgeXnsManagement = 0x9001 => "3Com-XNS-Mgmt",
+        /// 3Com (Bridge) TCP-IP Sys
+        BridgeTcpIp = 0x9002 => "3Com-TCP-IP",
+        /// 3Com (Bridge) loop detect
+        BridgeLoopDetect = 0x9003 => "3Com-Loop",
+        /// Multi-Topology
+        MultiTopology = 0x9A22 => "Multi-Topology",
+        /// LoWPAN encapsulation
+        Lowpan = 0xA0ED => "LoWPAN",
+        /// GRE control channel
+        GreControl = 0xB7EA => "GRE-Control",
+        /// BBN VITAL-LanBridge cache
+        VitalLanBridge = 0xFF00 => "VITAL",
+        /// Reserved
+        Reserved = 0xFFFF => "Reserved",
     }
 }
 
-fn mac_to_str(m: &[u8; 6]) -> String {
+pub fn mac_to_str(m: &[u8; 6]) -> String {
     format!(
         "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
         m[0], m[1], m[2], m[3], m[4], m[5]
@@ -86,38 +392,47 @@ fn mac_to_str(m: &[u8; 6]) -> String {
 }
 
 pub fn display(h: &Header) -> String {
-    let et = h.ether_type.to_be();
     format!(
         "Ether src={} dst={} eth_type={}",
         mac_to_str(&h.shost),
         mac_to_str(&h.dhost),
-        ether_type_as_str(et)
+        EtherType::from(h.ether_type.get())
     )
 }
 
 pub fn decode(data: &[u8]) {
-    if data.len() >= Header::SIZE {
-        let (header_bytes, next_data) = data.split_at(Header::SIZE);
-        match cow_struct::<Header>(header_bytes) {
-            Some(header) => {
-                let t = header.ether_type.to_be();
-                println!("{}", display(&header));
-                // IEEE 802.3: if value <= 1500, it's a length field and an LLC header follows
-                if t <= 1500 {
-                    return super::llc::decode(next_data);
-                }
-                match t {
-                    PROTO::ARP => arp::decode(next_data),
-                    PROTO::IPV4 => ipv4::decode(next_data),
-                    PROTO::IPV6 => ipv6::decode(next_data),
-                    PROTO::VLAN => super::vlan::decode(next_data),
-                    PROTO::LLDP => super::lldp::decode(next_data),
-                    PROTO::MPLS_U | PROTO::MPLS_M => super::mpls::decode(next_data),
-                    PROTO::PPPOE_DISC | PROTO::PPPOE_SESS => super::pppoe::decode(t, next_data),
-                    _ => println!("ether type: {:?}", ether_type_as_str(t)),
-                }
+    if let Some((header, next_data)) = utils::split::<Header>(data) {
+        let t = header.ether_type.get();
+        println!("{}", display(header));
+        tree::layer("ethernet", &data[..Header::SIZE], display(header));
+        tree::field("Destination", &header.dhost, mac_to_str(&header.dhost));
+        tree::field("Source", &header.shost, mac_to_str(&header.shost));
+        // IEEE 802.3: if value <= 1500, it's a length field and an LLC header follows
+        if t <= 1500 {
+            tree::field("Length", &header.ether_type, t);
+            return super::llc::decode(next_data);
+        }
+        tree::field(
+            "Type",
+            &header.ether_type,
+            format_args!("{} (0x{:04x})", EtherType::from(t), t),
+        );
+        match EtherType::from(t) {
+            EtherType::Arp => arp::decode(next_data),
+            EtherType::Ipv4 => ipv4::decode(next_data),
+            EtherType::Ipv6 => ipv6::decode(next_data),
+            EtherType::Vlan => super::vlan::decode(next_data),
+            EtherType::Lldp => super::lldp::decode(next_data),
+            EtherType::Mpls | EtherType::MplsUpstream => super::mpls::decode(next_data),
+            t @ (EtherType::PppoeDiscovery | EtherType::PppoeSession) => {
+                super::pppoe::decode(t, next_data)
             }
-            None => println!("Error::ethernet {:?}", "Truncated payload"),
+            t => println!("ether type: {}", t),
         }
+    } else {
+        expert::error(DecodeError::Truncated {
+            layer: "ethernet",
+            offset: 0,
+        });
     }
 }
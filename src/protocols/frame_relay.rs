@@ -1,4 +1,6 @@
 // Minimal Frame Relay (RFC 2427) decoder
+use crate::tree;
+
 pub fn decode(data: &[u8]) {
     if data.len() < 2 {
         return;
@@ -10,10 +12,16 @@ pub fn decode(data: &[u8]) {
     let fecn = (b1 & 0x08) != 0;
     let becn = (b1 & 0x04) != 0;
     let de = (b1 & 0x02) != 0;
-    println!(
+    let line = format!(
         "FR DLCI={} FECN={} BECN={} DE={}",
         dlci, fecn as u8, becn as u8, de as u8
     );
+    println!("{}", line);
+    tree::layer("frame_relay", &data[..2], line);
+    tree::field("DLCI", &data[..2], dlci);
+    tree::field("FECN", &data[1..2], fecn);
+    tree::field("BECN", &data[1..2], becn);
+    tree::field("Discard eligible", &data[1..2], de);
     // Heuristic payload: many FR carry NLPID or SNAP
     if data.len() > 2 {
         println!("FR payload ({}B)", data.len() - 2);
@@ -1,15 +1,271 @@
 // Minimal FTP control channel decoder
+// `Control` follows the control connection to report the USER/PASS logins. With file
+// extraction enabled, it also follows the control connection to learn the
+// data connections announced by PASV/EPSV replies and PORT/EPRT commands and the file
+// each one carries (RETR, STOR, APPE, STOU); `DataConnection` collects its bytes.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc959
+// * https://www.rfc-editor.org/rfc/rfc2428 (EPRT, EPSV)
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::rc::Rc;
+
+use super::detect::confidence;
+use crate::analysis::credentials;
+use crate::analysis::extract::{self, Buffer};
+use crate::flow::FlowKey;
+use crate::reassembly::StreamHandler;
+use crate::tree;
+
+/// Data connections announced but not opened yet, per thread
+const MAX_EXPECTED: usize = 4096;
+
+/// File moved by a data connection
+#[derive(Debug, Default)]
+pub struct Transfer {
+    /// command naming the file (RETR, STOR, ...)
+    pub command: Option<String>,
+    pub filename: Option<String>,
+}
+
+/// Transfer shared by the control connection and its data connection
+type Shared = Rc<RefCell<Transfer>>;
+
+thread_local! {
+    /// announced data connections by listening endpoint
+    static EXPECTED: RefCell<HashMap<(IpAddr, u16), Shared>> = RefCell::new(HashMap::new());
+}
+
+fn expect(ip: IpAddr, port: u16) -> Shared {
+    let transfer = Rc::new(RefCell::new(Transfer::default()));
+    if !extract::enabled() {
+        return transfer;
+    }
+    EXPECTED.with(|e| {
+        let mut e = e.borrow_mut();
+        if e.len() >= MAX_EXPECTED {
+            e.clear();
+        }
+        e.insert((ip, port), transfer.clone());
+    });
+    transfer
+}
+
+/// `h1,h2,h3,h4,p1,p2` of PORT and of the 227 reply
+fn host_port(s: &str) -> Option<(IpAddr, u16)> {
+    let n: Vec<u8> = s
+        .split(',')
+        .map(|v| v.trim().parse())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    if n.len() != 6 {
+        return None;
+    }
+    let ip = IpAddr::from([n[0], n[1], n[2], n[3]]);
+    Some((ip, u16::from_be_bytes([n[4], n[5]])))
+}
+
+/// `|proto|ip|port|` of EPRT (with an ip) and of the 229 reply (without)
+fn extended(s: &str) -> Option<(Option<IpAddr>, u16)> {
+    let delim = s.chars().next()?;
+    let fields: Vec<&str> = s[delim.len_utf8()..].split(delim).collect();
+    let ip = fields.get(1)?.parse().ok();
+    Some((ip, fields.get(2)?.parse().ok()?))
+}
+
+/// Follows an FTP control connection
+#[derive(Default)]
+pub struct Control {
+    buf: [Vec<u8>; 2],
+    /// data connection announced last
+    current: Option<Shared>,
+    /// argument of the last USER command
+    user: Option<String>,
+}
+
+impl Control {
+    fn on_command(&mut self, flow: &FlowKey, line: &str) {
+        let (verb, arg) = line.split_once(' ').unwrap_or((line, ""));
+        let verb = verb.to_ascii_uppercase();
+        match verb.as_str() {
+            "USER" => self.user = Some(arg.trim().to_string()),
+            "PASS" => credentials::on_credential(
+                "ftp",
+                "USER/PASS",
+                Some(flow),
+                self.user.as_deref(),
+                arg,
+            ),
+            "PORT" => {
+                if let Some((ip, port)) = host_port(arg) {
+                    self.current = Some(expect(ip, port));
+                }
+            }
+            "EPRT" => {
+                if let Some((ip, port)) = extended(arg.trim()) {
+                    self.current = Some(expect(ip.unwrap_or(flow.src), port));
+                }
+            }
+            "RETR" | "STOR" | "APPE" | "STOU" => {
+                if let Some(t) = &self.current {
+                    let mut t = t.borrow_mut();
+                    t.command = Some(verb.clone());
+                    t.filename = Some(arg.trim().to_string()).filter(|f| !f.is_empty());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_reply(&mut self, flow: &FlowKey, line: &str) {
+        if let Some(rest) = line.strip_prefix("227") {
+            let inner = rest
+                .split_once('(')
+                .map(|(_, r)| r.split(')').next().unwrap_or(r))
+                .or_else(|| rest.rsplit(' ').next());
+            // servers behind NAT announce a private address, the flow is more reliable
+            if let Some((_, port)) = inner.and_then(host_port) {
+                self.current = Some(expect(flow.dst, port));
+            }
+        } else if let Some(rest) = line.strip_prefix("229") {
+            let inner = rest.split_once('(').and_then(|(_, r)| r.split(')').next());
+            if let Some((_, port)) = inner.and_then(extended) {
+                self.current = Some(expect(flow.dst, port));
+            }
+        }
+    }
+}
+
+impl StreamHandler for Control {
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]) {
+        let dir = usize::from(!from_client);
+        self.buf[dir].extend_from_slice(data);
+        let mut used = 0;
+        while let Some(eol) = self.buf[dir][used..].iter().position(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(&self.buf[dir][used..used + eol]);
+            let line = line.trim_end_matches('\r').to_string();
+            used += eol + 1;
+            if from_client {
+                self.on_command(flow, &line);
+            } else {
+                self.on_reply(flow, &line);
+            }
+        }
+        self.buf[dir].drain(..used);
+        // a line never ends, this is not FTP
+        if self.buf[dir].len() > 4096 {
+            self.buf[dir] = Vec::new();
+        }
+    }
+}
+
+/// Collects the bytes of a data connection
+pub struct DataConnection {
+    transfer: Shared,
+    data: Buffer,
+    len: u64,
+}
+
+impl StreamHandler for DataConnection {
+    fn on_data(&mut self, _flow: &FlowKey, _from_client: bool, data: &[u8]) {
+        self.len += data.len() as u64;
+        self.data.extend(data);
+    }
+
+    fn on_close(&mut self, flow: &FlowKey) {
+        let transfer = self.transfer.borrow();
+        // directory listings have no command naming a file
+        if transfer.command.is_some() {
+            extract::on_file(
+                "ftp",
+                flow,
+                transfer.filename.as_deref(),
+                None,
+                &self.data,
+                self.len,
+            );
+        }
+    }
+}
+
+/// Stream handler of an FTP connection: the control connection, or a data connection
+/// announced on one when extracting files
+pub fn connection(flow: &FlowKey) -> Option<Box<dyn StreamHandler>> {
+    let expected = if extract::enabled() {
+        EXPECTED.with(|e| e.borrow_mut().remove(&(flow.dst, flow.dst_port)))
+    } else {
+        None
+    };
+    if let Some(transfer) = expected {
+        return Some(Box::new(DataConnection {
+            transfer,
+            data: Buffer::default(),
+            len: 0,
+        }));
+    }
+    (flow.dst_port == 21).then(|| Box::new(Control::default()) as Box<dyn StreamHandler>)
+}
+
+/// Commands only sent on an FTP control connection
+const COMMANDS: [&str; 11] = [
+    "PASV", "EPSV", "PORT ", "EPRT ", "SYST", "FEAT", "RETR ", "STOR ", "CWD ", "PWD", "TYPE ",
+];
+
+/// Confidence that the payload is an FTP greeting, reply or command
+pub fn probe(data: &[u8]) -> u8 {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = String::from_utf8_lossy(&line[..line.len().min(128)]).to_ascii_uppercase();
+    if line.starts_with("220") && line.contains("FTP") {
+        confidence::HIGH
+    } else if line.starts_with("227 ENTERING PASSIVE") || line.starts_with("229 ENTERING EXT") {
+        confidence::CERTAIN
+    } else if COMMANDS.iter().any(|c| line.starts_with(c)) {
+        confidence::MEDIUM
+    } else if line.starts_with("USER ") || line.starts_with("220") {
+        confidence::LOW
+    } else {
+        confidence::NONE
+    }
+}
+
 pub fn decode(data: &[u8]) {
     // FTP control is ASCII lines
     let s = match std::str::from_utf8(data) {
         Ok(v) => v,
         Err(_) => {
             println!("FTP (binary) {}B", data.len());
+            tree::layer("ftp", data, format_args!("FTP (binary) {}B", data.len()));
             return;
         }
     };
     if let Some(line) = s.lines().next() {
-        println!("FTP: {}", line.trim());
+        let line = line.trim();
+        let shown = match line.get(..5) {
+            Some(verb) if verb.eq_ignore_ascii_case("PASS ") => {
+                format!("{}{}", verb, credentials::mask(&line[5..]))
+            }
+            _ => line.to_string(),
+        };
+        println!("FTP: {}", shown);
+        tree::layer("ftp", data, format_args!("FTP: {}", shown));
+        // a reply starts with its code, a request with its command
+        match line.split_once(' ') {
+            Some((code, arg)) if code.len() == 3 && code.bytes().all(|b| b.is_ascii_digit()) => {
+                tree::field("Response code", code.as_bytes(), code);
+                tree::field("Response arg", arg.as_bytes(), arg);
+            }
+            Some((cmd, arg)) if cmd.eq_ignore_ascii_case("PASS") => {
+                tree::field("Request command", cmd.as_bytes(), cmd);
+                tree::field("Request arg", arg.as_bytes(), credentials::mask(arg));
+            }
+            Some((cmd, arg)) => {
+                tree::field("Request command", cmd.as_bytes(), cmd);
+                tree::field("Request arg", arg.as_bytes(), arg);
+            }
+            None => tree::field("Request command", line.as_bytes(), line),
+        }
         if line.to_uppercase().starts_with("AUTH TLS") {
             println!("FTPS explicit requested");
         }
@@ -1,11 +1,13 @@
-use crate::utils::cow_struct;
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be};
 use std::mem::size_of;
 
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub flags_version: u16,
-    pub protocol_type: u16,
+wire_struct! {
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        pub flags_version: U16Be,
+        pub protocol_type: U16Be,
+    }
 }
 
 impl Header {
@@ -15,8 +17,8 @@ impl Header {
 pub fn display(h: &Header) -> String {
     format!(
         "[GRE] proto=0x{:04x} flags=0x{:04x}",
-        h.protocol_type.to_be(),
-        h.flags_version.to_be()
+        h.protocol_type.get(),
+        h.flags_version.get()
     )
 }
 
@@ -24,8 +26,18 @@ pub fn decode(data: &[u8]) {
     if data.len() < Header::SIZE {
         return;
     }
-    let (hdr, _rest) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        println!("{}", display(&h));
+    if let Some(h) = utils::view::<Header>(data) {
+        println!("{}", display(h));
+        tree::layer("gre", h, display(h));
+        tree::field(
+            "Flags and version",
+            &h.flags_version,
+            format_args!("0x{:04x}", h.flags_version.get()),
+        );
+        tree::field(
+            "Protocol type",
+            &h.protocol_type,
+            format_args!("0x{:04x}", h.protocol_type.get()),
+        );
     }
 }
@@ -0,0 +1,106 @@
+// gRPC over HTTP/2
+// Requests and responses are streams of messages, each prefixed by a compressed flag and
+// a 4 bytes big endian length. The method is the :path of the request and the status of
+// the call comes in the trailers (grpc-status, grpc-message).
+//
+// Sources:
+// * https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+// * https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+
+/// Messages larger than this are counted but not kept
+const MAX_MESSAGE: usize = 4 * 1024 * 1024;
+
+/// Whether a content-type designates gRPC (application/grpc, application/grpc+proto, ...)
+pub fn is_grpc(content_type: &str) -> bool {
+    let ct = content_type.trim().to_ascii_lowercase();
+    ct == "application/grpc"
+        || ct.starts_with("application/grpc+")
+        || ct.starts_with("application/grpc;")
+}
+
+pub fn status_as_str(code: u32) -> &'static str {
+    match code {
+        0 => "OK",
+        1 => "CANCELLED",
+        2 => "UNKNOWN",
+        3 => "INVALID_ARGUMENT",
+        4 => "DEADLINE_EXCEEDED",
+        5 => "NOT_FOUND",
+        6 => "ALREADY_EXISTS",
+        7 => "PERMISSION_DENIED",
+        8 => "RESOURCE_EXHAUSTED",
+        9 => "FAILED_PRECONDITION",
+        10 => "ABORTED",
+        11 => "OUT_OF_RANGE",
+        12 => "UNIMPLEMENTED",
+        13 => "INTERNAL",
+        14 => "UNAVAILABLE",
+        15 => "DATA_LOSS",
+        16 => "UNAUTHENTICATED",
+        _ => "UNKNOW",
+    }
+}
+
+/// A length-prefixed message
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// compressed with the grpc-encoding of the call
+    pub compressed: bool,
+    pub len: usize,
+    /// empty when the message is larger than MAX_MESSAGE
+    pub data: Vec<u8>,
+}
+
+/// Splits the DATA of one direction of a call into messages
+#[derive(Debug, Default)]
+pub struct Splitter {
+    buf: Vec<u8>,
+    /// bytes of an oversized message still to skip
+    skip: usize,
+}
+
+impl Splitter {
+    /// Add stream bytes, returning the messages completed by them
+    pub fn push(&mut self, mut data: &[u8]) -> Vec<Message> {
+        if self.skip > 0 {
+            let n = self.skip.min(data.len());
+            self.skip -= n;
+            data = &data[n..];
+        }
+        self.buf.extend_from_slice(data);
+        let mut messages = Vec::new();
+        let mut used = 0;
+        while let Some(prefix) = self.buf.get(used..used + 5) {
+            let compressed = prefix[0] & 1 != 0;
+            let len = u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as usize;
+            if len > MAX_MESSAGE {
+                let available = self.buf.len() - used - 5;
+                self.skip = len.saturating_sub(available);
+                used += 5 + len.min(available);
+                messages.push(Message {
+                    compressed,
+                    len,
+                    data: Vec::new(),
+                });
+                continue;
+            }
+            let data = match self.buf.get(used + 5..used + 5 + len) {
+                Some(d) => d.to_vec(),
+                None => break,
+            };
+            used += 5 + len;
+            messages.push(Message {
+                compressed,
+                len,
+                data,
+            });
+        }
+        self.buf.drain(..used);
+        messages
+    }
+
+    /// Bytes of an incomplete message left at the end of the stream
+    pub fn pending(&self) -> usize {
+        self.buf.len() + self.skip
+    }
+}
@@ -1,20 +1,32 @@
 // Minimal HDLC decoder (Cisco/PPP HDLC-like)
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
 pub fn decode(data: &[u8]) {
-    if data.len() < 4 {
-        return;
-    }
     // Most captures store HDLC without flags/bit-stuffing at L2+; here show first bytes
-    let addr = data[0];
-    let control = data[1];
-    println!(
+    let mut r = ByteReader::new(data);
+    let (addr, control, proto) = match (r.u8(), r.u8(), r.u16()) {
+        (Ok(addr), Ok(control), Ok(proto)) => (addr, control, proto),
+        _ => {
+            expert::error(DecodeError::Truncated {
+                layer: "hdlc",
+                offset: 0,
+            });
+            return;
+        }
+    };
+    let line = format!(
         "HDLC addr=0x{:02x} ctrl=0x{:02x} ({}B)",
         addr,
         control,
         data.len()
     );
+    println!("{}", line);
+    tree::layer("hdlc", &data[..4], line);
+    tree::field("Address", &data[..1], format_args!("0x{:02x}", addr));
+    tree::field("Control", &data[1..2], format_args!("0x{:02x}", control));
+    tree::field("Protocol", &data[2..4], format_args!("0x{:04x}", proto));
     // If PPP over HDLC, next is PPP protocol
-    if data.len() >= 4 {
-        let proto = u16::from_be_bytes([data[2], data[3]]);
-        println!("HDLC PPP proto=0x{:04x}", proto);
-    }
+    println!("HDLC PPP proto=0x{:04x}", proto);
 }
@@ -0,0 +1,620 @@
+// HPACK header compression for HTTP/2
+// Header blocks are decoded with the static table, a dynamic table that follows the
+// insertions of the encoder (one per direction of a connection) and the Huffman code of
+// the specification.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc7541
+use std::collections::{HashMap, VecDeque};
+use std::sync::OnceLock;
+
+use crate::expert::{self, DecodeError};
+use crate::utils::ByteReader;
+
+/// Static table (RFC 7541 Appendix A), index 1 first
+pub const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+/// Huffman code (code, bit length) of each symbol, 256 being EOS (RFC 7541 Appendix B)
+const HUFFMAN: [(u32, u8); 257] = [
+    (0x1ff8, 13),
+    (0x7fffd8, 23),
+    (0xfffffe2, 28),
+    (0xfffffe3, 28),
+    (0xfffffe4, 28),
+    (0xfffffe5, 28),
+    (0xfffffe6, 28),
+    (0xfffffe7, 28),
+    (0xfffffe8, 28),
+    (0xffffea, 24),
+    (0x3ffffffc, 30),
+    (0xfffffe9, 28),
+    (0xfffffea, 28),
+    (0x3ffffffd, 30),
+    (0xfffffeb, 28),
+    (0xfffffec, 28),
+    (0xfffffed, 28),
+    (0xfffffee, 28),
+    (0xfffffef, 28),
+    (0xffffff0, 28),
+    (0xffffff1, 28),
+    (0xffffff2, 28),
+    (0x3ffffffe, 30),
+    (0xffffff3, 28),
+    (0xffffff4, 28),
+    (0xffffff5, 28),
+    (0xffffff6, 28),
+    (0xffffff7, 28),
+    (0xffffff8, 28),
+    (0xffffff9, 28),
+    (0xffffffa, 28),
+    (0xffffffb, 28),
+    (0x14, 6),
+    (0x3f8, 10),
+    (0x3f9, 10),
+    (0xffa, 12),
+    (0x1ff9, 13),
+    (0x15, 6),
+    (0xf8, 8),
+    (0x7fa, 11),
+    (0x3fa, 10),
+    (0x3fb, 10),
+    (0xf9, 8),
+    (0x7fb, 11),
+    (0xfa, 8),
+    (0x16, 6),
+    (0x17, 6),
+    (0x18, 6),
+    (0x0, 5),
+    (0x1, 5),
+    (0x2, 5),
+    (0x19, 6),
+    (0x1a, 6),
+    (0x1b, 6),
+    (0x1c, 6),
+    (0x1d, 6),
+    (0x1e, 6),
+    (0x1f, 6),
+    (0x5c, 7),
+    (0xfb, 8),
+    (0x7ffc, 15),
+    (0x20, 6),
+    (0xffb, 12),
+    (0x3fc, 10),
+    (0x1ffa, 13),
+    (0x21, 6),
+    (0x5d, 7),
+    (0x5e, 7),
+    (0x5f, 7),
+    (0x60, 7),
+    (0x61, 7),
+    (0x62, 7),
+    (0x63, 7),
+    (0x64, 7),
+    (0x65, 7),
+    (0x66, 7),
+    (0x67, 7),
+    (0x68, 7),
+    (0x69, 7),
+    (0x6a, 7),
+    (0x6b, 7),
+    (0x6c, 7),
+    (0x6d, 7),
+    (0x6e, 7),
+    (0x6f, 7),
+    (0x70, 7),
+    (0x71, 7),
+    (0x72, 7),
+    (0xfc, 8),
+    (0x73, 7),
+    (0xfd, 8),
+    (0x1ffb, 13),
+    (0x7fff0, 19),
+    (0x1ffc, 13),
+    (0x3ffc, 14),
+    (0x22, 6),
+    (0x7ffd, 15),
+    (0x3, 5),
+    (0x23, 6),
+    (0x4, 5),
+    (0x24, 6),
+    (0x5, 5),
+    (0x25, 6),
+    (0x26, 6),
+    (0x27, 6),
+    (0x6, 5),
+    (0x74, 7),
+    (0x75, 7),
+    (0x28, 6),
+    (0x29, 6),
+    (0x2a, 6),
+    (0x7, 5),
+    (0x2b, 6),
+    (0x76, 7),
+    (0x2c, 6),
+    (0x8, 5),
+    (0x9, 5),
+    (0x2d, 6),
+    (0x77, 7),
+    (0x78, 7),
+    (0x79, 7),
+    (0x7a, 7),
+    (0x7b, 7),
+    (0x7ffe, 15),
+    (0x7fc, 11),
+    (0x3ffd, 14),
+    (0x1ffd, 13),
+    (0xffffffc, 28),
+    (0xfffe6, 20),
+    (0x3fffd2, 22),
+    (0xfffe7, 20),
+    (0xfffe8, 20),
+    (0x3fffd3, 22),
+    (0x3fffd4, 22),
+    (0x3fffd5, 22),
+    (0x7fffd9, 23),
+    (0x3fffd6, 22),
+    (0x7fffda, 23),
+    (0x7fffdb, 23),
+    (0x7fffdc, 23),
+    (0x7fffdd, 23),
+    (0x7fffde, 23),
+    (0xffffeb, 24),
+    (0x7fffdf, 23),
+    (0xffffec, 24),
+    (0xffffed, 24),
+    (0x3fffd7, 22),
+    (0x7fffe0, 23),
+    (0xffffee, 24),
+    (0x7fffe1, 23),
+    (0x7fffe2, 23),
+    (0x7fffe3, 23),
+    (0x7fffe4, 23),
+    (0x1fffdc, 21),
+    (0x3fffd8, 22),
+    (0x7fffe5, 23),
+    (0x3fffd9, 22),
+    (0x7fffe6, 23),
+    (0x7fffe7, 23),
+    (0xffffef, 24),
+    (0x3fffda, 22),
+    (0x1fffdd, 21),
+    (0xfffe9, 20),
+    (0x3fffdb, 22),
+    (0x3fffdc, 22),
+    (0x7fffe8, 23),
+    (0x7fffe9, 23),
+    (0x1fffde, 21),
+    (0x7fffea, 23),
+    (0x3fffdd, 22),
+    (0x3fffde, 22),
+    (0xfffff0, 24),
+    (0x1fffdf, 21),
+    (0x3fffdf, 22),
+    (0x7fffeb, 23),
+    (0x7fffec, 23),
+    (0x1fffe0, 21),
+    (0x1fffe1, 21),
+    (0x3fffe0, 22),
+    (0x1fffe2, 21),
+    (0x7fffed, 23),
+    (0x3fffe1, 22),
+    (0x7fffee, 23),
+    (0x7fffef, 23),
+    (0xfffea, 20),
+    (0x3fffe2, 22),
+    (0x3fffe3, 22),
+    (0x3fffe4, 22),
+    (0x7ffff0, 23),
+    (0x3fffe5, 22),
+    (0x3fffe6, 22),
+    (0x7ffff1, 23),
+    (0x3ffffe0, 26),
+    (0x3ffffe1, 26),
+    (0xfffeb, 20),
+    (0x7fff1, 19),
+    (0x3fffe7, 22),
+    (0x7ffff2, 23),
+    (0x3fffe8, 22),
+    (0x1ffffec, 25),
+    (0x3ffffe2, 26),
+    (0x3ffffe3, 26),
+    (0x3ffffe4, 26),
+    (0x7ffffde, 27),
+    (0x7ffffdf, 27),
+    (0x3ffffe5, 26),
+    (0xfffff1, 24),
+    (0x1ffffed, 25),
+    (0x7fff2, 19),
+    (0x1fffe3, 21),
+    (0x3ffffe6, 26),
+    (0x7ffffe0, 27),
+    (0x7ffffe1, 27),
+    (0x3ffffe7, 26),
+    (0x7ffffe2, 27),
+    (0xfffff2, 24),
+    (0x1fffe4, 21),
+    (0x1fffe5, 21),
+    (0x3ffffe8, 26),
+    (0x3ffffe9, 26),
+    (0xffffffd, 28),
+    (0x7ffffe3, 27),
+    (0x7ffffe4, 27),
+    (0x7ffffe5, 27),
+    (0xfffec, 20),
+    (0xfffff3, 24),
+    (0xfffed, 20),
+    (0x1fffe6, 21),
+    (0x3fffe9, 22),
+    (0x1fffe7, 21),
+    (0x1fffe8, 21),
+    (0x7ffff3, 23),
+    (0x3fffea, 22),
+    (0x3fffeb, 22),
+    (0x1ffffee, 25),
+    (0x1ffffef, 25),
+    (0xfffff4, 24),
+    (0xfffff5, 24),
+    (0x3ffffea, 26),
+    (0x7ffff4, 23),
+    (0x3ffffeb, 26),
+    (0x7ffffe6, 27),
+    (0x3ffffec, 26),
+    (0x3ffffed, 26),
+    (0x7ffffe7, 27),
+    (0x7ffffe8, 27),
+    (0x7ffffe9, 27),
+    (0x7ffffea, 27),
+    (0x7ffffeb, 27),
+    (0xffffffe, 28),
+    (0x7ffffec, 27),
+    (0x7ffffed, 27),
+    (0x7ffffee, 27),
+    (0x7ffffef, 27),
+    (0x7fffff0, 27),
+    (0x3ffffee, 26),
+    (0x3fffffff, 30),
+];
+
+/// Default size of the dynamic table (SETTINGS_HEADER_TABLE_SIZE)
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+/// Largest dynamic table size accepted from a size update
+const MAX_TABLE_SIZE: usize = 1 << 20;
+
+fn huffman_codes() -> &'static HashMap<(u8, u32), u16> {
+    static CODES: OnceLock<HashMap<(u8, u32), u16>> = OnceLock::new();
+    CODES.get_or_init(|| {
+        HUFFMAN
+            .iter()
+            .enumerate()
+            .map(|(sym, (code, len))| ((*len, *code), sym as u16))
+            .collect()
+    })
+}
+
+/// Decode a Huffman encoded string
+pub fn huffman_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let codes = huffman_codes();
+    let mut out = Vec::with_capacity(data.len() * 8 / 5);
+    let (mut code, mut len) = (0u32, 0u8);
+    for byte in data {
+        for bit in (0..8).rev() {
+            code = (code << 1) | ((byte >> bit) & 1) as u32;
+            len += 1;
+            if let Some(sym) = codes.get(&(len, code)) {
+                // EOS inside a string is an error
+                out.push(u8::try_from(*sym).ok()?);
+                (code, len) = (0, 0);
+            } else if len >= 30 {
+                return None;
+            }
+        }
+    }
+    // padding is the most significant bits of EOS, at most 7 ones
+    if len > 7 || code != (1 << len) - 1 {
+        return None;
+    }
+    Some(out)
+}
+
+/// Integer with an N bit prefix (RFC 7541 5.1)
+pub fn integer(r: &mut ByteReader, prefix: u8) -> Option<usize> {
+    let max = (1usize << prefix) - 1;
+    let mut value = (r.u8().ok()? as usize) & max;
+    if value < max {
+        return Some(value);
+    }
+    let mut shift = 0;
+    loop {
+        let b = r.u8().ok()?;
+        value = value.checked_add(((b & 0x7f) as usize).checked_shl(shift)?)?;
+        if b & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+        if shift > 28 {
+            return None;
+        }
+    }
+}
+
+/// String literal (RFC 7541 5.2), as the bytes sent
+fn string(r: &mut ByteReader) -> Option<Vec<u8>> {
+    let huffman = r.peek()? & 0x80 != 0;
+    let len = integer(r, 7)?;
+    let raw = r.bytes(len).ok()?;
+    if huffman {
+        huffman_decode(raw)
+    } else {
+        Some(raw.to_vec())
+    }
+}
+
+/// Header field as shown, names and values that are not UTF-8 made lossy
+fn text((name, value): (Vec<u8>, Vec<u8>)) -> (String, String) {
+    (
+        String::from_utf8_lossy(&name).into_owned(),
+        String::from_utf8_lossy(&value).into_owned(),
+    )
+}
+
+/// Decoding context of one direction of a connection
+#[derive(Debug, Clone)]
+pub struct Decoder {
+    /// newest entry first, as the bytes sent: entry sizes count them, and must match the
+    /// sizes of the encoder for the tables to stay in sync
+    dynamic: VecDeque<(Vec<u8>, Vec<u8>)>,
+    size: usize,
+    max_size: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder {
+            dynamic: VecDeque::new(),
+            size: 0,
+            max_size: DEFAULT_TABLE_SIZE,
+        }
+    }
+}
+
+impl Decoder {
+    /// Size of the dynamic table as defined by the specification
+    pub fn table_size(&self) -> usize {
+        self.size
+    }
+
+    fn entry(&self, index: usize) -> Option<(Vec<u8>, Vec<u8>)> {
+        match index {
+            0 => None,
+            1..=61 => {
+                let (n, v) = STATIC_TABLE[index - 1];
+                Some((n.as_bytes().to_vec(), v.as_bytes().to_vec()))
+            }
+            _ => self.dynamic.get(index - 62).cloned(),
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            match self.dynamic.pop_back() {
+                Some((n, v)) => self.size -= n.len() + v.len() + 32,
+                None => break,
+            }
+        }
+    }
+
+    fn insert(&mut self, name: &[u8], value: &[u8]) {
+        self.size += name.len() + value.len() + 32;
+        self.dynamic.push_front((name.to_vec(), value.to_vec()));
+        self.evict();
+    }
+
+    /// Decode a complete header block
+    ///
+    /// Returns `None` on a malformed block, reported as expert info, after which the
+    /// dynamic table can no longer be trusted.
+    pub fn decode(&mut self, block: &[u8]) -> Option<Vec<(String, String)>> {
+        let mut headers = Vec::new();
+        let mut r = ByteReader::new(block);
+        while let Some(b) = r.peek() {
+            let field = r.position();
+            if let Err(reason) = self.field(&mut r, b, &mut headers) {
+                expert::error(DecodeError::Malformed {
+                    layer: "hpack",
+                    offset: field,
+                    reason,
+                });
+                return None;
+            }
+        }
+        Some(headers)
+    }
+
+    /// Decode the field representation starting with `b`
+    fn field(
+        &mut self,
+        r: &mut ByteReader,
+        b: u8,
+        headers: &mut Vec<(String, String)>,
+    ) -> Result<(), &'static str> {
+        const INTEGER: &str = "truncated or overflowing integer";
+        const INDEX: &str = "index outside the static and dynamic tables";
+        const STRING: &str = "truncated string or invalid Huffman code";
+        if b & 0x80 != 0 {
+            // indexed field
+            let index = integer(r, 7).ok_or(INTEGER)?;
+            headers.push(text(self.entry(index).ok_or(INDEX)?));
+        } else if b & 0xe0 == 0x20 {
+            // dynamic table size update
+            let size = integer(r, 5).ok_or(INTEGER)?;
+            if size > MAX_TABLE_SIZE {
+                return Err("dynamic table size update above 1 MiB");
+            }
+            self.max_size = size;
+            self.evict();
+        } else {
+            // literal with incremental indexing (6 bits), without indexing or never
+            // indexed (4 bits)
+            let indexing = b & 0xc0 == 0x40;
+            let index = integer(r, if indexing { 6 } else { 4 }).ok_or(INTEGER)?;
+            let name = match index {
+                0 => string(r).ok_or(STRING)?,
+                n => self.entry(n).ok_or(INDEX)?.0,
+            };
+            let value = string(r).ok_or(STRING)?;
+            if indexing {
+                self.insert(&name, &value);
+            }
+            headers.push(text((name, value)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn headers(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter()
+            .map(|(n, v)| (n.to_string(), v.to_string()))
+            .collect()
+    }
+
+    /// Decode the blocks of a connection in turn, checking the headers and table size
+    fn check(blocks: [(&str, usize); 3]) {
+        let expected = [
+            headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+            ]),
+            headers(&[
+                (":method", "GET"),
+                (":scheme", "http"),
+                (":path", "/"),
+                (":authority", "www.example.com"),
+                ("cache-control", "no-cache"),
+            ]),
+            headers(&[
+                (":method", "GET"),
+                (":scheme", "https"),
+                (":path", "/index.html"),
+                (":authority", "www.example.com"),
+                ("custom-key", "custom-value"),
+            ]),
+        ];
+        let mut decoder = Decoder::default();
+        for ((block, size), expected) in blocks.iter().zip(expected) {
+            assert_eq!(decoder.decode(&hex(block)).unwrap(), expected);
+            assert_eq!(decoder.table_size(), *size);
+        }
+    }
+
+    // RFC 7541 C.1.2, 1337 on a 5-bit prefix
+    #[test]
+    fn integer_on_prefix() {
+        let data = [0x1f, 0x9a, 0x0a];
+        let mut r = ByteReader::new(&data);
+        assert_eq!(integer(&mut r, 5), Some(1337));
+    }
+
+    // RFC 7541 C.3, requests without Huffman coding
+    #[test]
+    fn requests() {
+        check([
+            ("828684410f7777772e6578616d706c652e636f6d", 57),
+            ("828684be58086e6f2d6361636865", 110),
+            (
+                "828785bf400a637573746f6d2d6b65790c637573746f6d2d76616c7565",
+                164,
+            ),
+        ]);
+    }
+
+    // RFC 7541 C.4, the same requests with Huffman coding
+    #[test]
+    fn requests_huffman() {
+        check([
+            ("828684418cf1e3c2e5f23a6ba0ab90f4ff", 57),
+            ("828684be5886a8eb10649cbf", 110),
+            ("828785bf408825a849e95ba97d7f8925a849e95bb8e8b4bf", 164),
+        ]);
+    }
+
+    #[test]
+    fn unknown_index() {
+        let mut decoder = Decoder::default();
+        assert!(decoder.decode(&[0xbe]).is_none());
+    }
+}
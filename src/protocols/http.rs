@@ -1,3 +1,870 @@
+// HTTP/1.x decoder
+// `decode` prints the first line of a segment. `Connection` follows a reassembled
+// connection: request and response heads, bodies (Content-Length, chunked or up to the
+// close), content codings, pipelined requests and the switch to another protocol after
+// CONNECT or an Upgrade.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc9112 (HTTP/1.1 messages)
+// * https://www.rfc-editor.org/rfc/rfc9110#section-8.4 (content codings)
+// * https://www.rfc-editor.org/rfc/rfc9110#section-9.3.6 (CONNECT)
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::Read;
+
+use super::detect::confidence;
+use crate::analysis::credentials;
+use crate::analysis::extract::{self, Buffer, MAX_FILE};
+use crate::analysis::{ioc, rules};
+use crate::event::{self, Event};
+use crate::expert;
+use crate::flow::FlowKey;
+use crate::reassembly::StreamHandler;
+use crate::tree;
+
+/// Largest head (start line and headers) accepted
+const MAX_HEAD: usize = 64 * 1024;
+/// Requests waiting for their response, past which responses are no longer paired
+const MAX_PENDING: usize = 128;
+
+/// Methods recognized at the start of a connection
+pub const METHODS: [&str; 9] = [
+    "GET", "POST", "HEAD", "PUT", "DELETE", "OPTIONS", "PATCH", "CONNECT", "TRACE",
+];
+
+/// Header fields in the order they were sent
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Headers(pub Vec<(String, String)>);
+
+impl Headers {
+    /// First value of a field, name compared case insensitively
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Comma separated tokens of all the values of a field, lower cased
+    pub fn tokens(&self, name: &str) -> Vec<String> {
+        self.0
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(name))
+            .flat_map(|(_, v)| v.split(','))
+            .map(|t| t.trim().to_ascii_lowercase())
+            .filter(|t| !t.is_empty())
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Start line of a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartLine {
+    Request {
+        method: String,
+        uri: String,
+        version: String,
+    },
+    Response {
+        version: String,
+        status: u16,
+        reason: String,
+    },
+}
+
+/// Start line and headers of a message
+#[derive(Debug, Clone)]
+pub struct Head {
+    pub start: StartLine,
+    pub headers: Headers,
+}
+
+/// Outcome of parsing a head at the start of a buffer
+#[derive(Debug)]
+pub enum Parsed {
+    /// the head and its length, blank line included
+    Complete(Head, usize),
+    /// more bytes needed
+    Partial,
+    Invalid,
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+fn parse_start_line(line: &str) -> Option<StartLine> {
+    if let Some(rest) = line.strip_prefix("HTTP/") {
+        let mut parts = rest.splitn(3, ' ');
+        let version = parts.next()?;
+        let status = parts.next()?;
+        if status.len() != 3 {
+            return None;
+        }
+        return Some(StartLine::Response {
+            version: format!("HTTP/{}", version),
+            status: status.parse().ok()?,
+            reason: parts.next().unwrap_or("").to_string(),
+        });
+    }
+    let mut parts = line.split(' ');
+    let (method, uri, version) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() || !is_token(method) || !version.starts_with("HTTP/1.") {
+        return None;
+    }
+    Some(StartLine::Request {
+        method: method.to_string(),
+        uri: uri.to_string(),
+        version: version.to_string(),
+    })
+}
+
+/// Length of the head up to the blank line, bare LF line endings tolerated as most
+/// implementations do
+fn head_end(data: &[u8]) -> Option<usize> {
+    data.iter()
+        .enumerate()
+        .filter(|(_, b)| **b == b'\n')
+        .find_map(|(i, _)| match data.get(i + 1) {
+            Some(b'\n') => Some(i + 2),
+            Some(b'\r') if data.get(i + 2) == Some(&b'\n') => Some(i + 3),
+            _ => None,
+        })
+}
+
+/// Parse the head of a message at the start of `data`
+pub fn parse_head(data: &[u8]) -> Parsed {
+    let end = match head_end(data) {
+        Some(end) => end,
+        None if data.len() > MAX_HEAD => return Parsed::Invalid,
+        None => return Parsed::Partial,
+    };
+    // header values may be latin-1, they are only looked at as text
+    parse_lines(&String::from_utf8_lossy(&data[..end]), end)
+}
+
+fn parse_lines(text: &str, end: usize) -> Parsed {
+    let mut lines = text.split('\n').map(|l| l.strip_suffix('\r').unwrap_or(l));
+    let start = match lines.next().and_then(parse_start_line) {
+        Some(s) => s,
+        None => return Parsed::Invalid,
+    };
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in lines.take_while(|l| !l.is_empty()) {
+        // obsolete line folding continues the previous value
+        if line.starts_with([' ', '\t']) {
+            match headers.last_mut() {
+                Some((_, v)) => {
+                    v.push(' ');
+                    v.push_str(line.trim());
+                    continue;
+                }
+                None => return Parsed::Invalid,
+            }
+        }
+        match line.split_once(':') {
+            Some((name, value)) if is_token(name) => {
+                headers.push((name.to_string(), value.trim().to_string()))
+            }
+            _ => return Parsed::Invalid,
+        }
+    }
+    Parsed::Complete(
+        Head {
+            start,
+            headers: Headers(headers),
+        },
+        end,
+    )
+}
+
+/// Undo the content codings of a body, last applied first
+///
+/// Returns `None` for an unknown coding or a corrupted body.
+pub fn decode_content(codings: &[String], body: &[u8]) -> Option<Vec<u8>> {
+    let mut data = body.to_vec();
+    for coding in codings.iter().rev() {
+        let mut out = Vec::new();
+        let limit = MAX_FILE as u64;
+        let res = match coding.as_str() {
+            "identity" => continue,
+            "gzip" | "x-gzip" => GzDecoder::new(&data[..]).take(limit).read_to_end(&mut out),
+            // "deflate" is a zlib stream, some servers send raw deflate
+            "deflate" => ZlibDecoder::new(&data[..])
+                .take(limit)
+                .read_to_end(&mut out)
+                .or_else(|_| {
+                    out.clear();
+                    DeflateDecoder::new(&data[..])
+                        .take(limit)
+                        .read_to_end(&mut out)
+                }),
+            "br" => brotli_decompressor::Decompressor::new(&data[..], 4096)
+                .take(limit)
+                .read_to_end(&mut out),
+            _ => return None,
+        };
+        res.ok()?;
+        data = out;
+    }
+    Some(data)
+}
+
+/// Name of the object carried by a body: filename of the Content-Disposition, else the
+/// last segment of the path of the URI
+pub fn filename(uri: &str, headers: &Headers) -> Option<String> {
+    if let Some(name) = headers
+        .get("content-disposition")
+        .and_then(|d| super::mime::param(d, "filename"))
+    {
+        return Some(name);
+    }
+    let path = uri.split(['?', '#']).next().unwrap_or("");
+    path.rsplit('/')
+        .next()
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+/// Report the credentials of a Basic (proxy) authorization
+pub fn authorization(flow: &FlowKey, headers: &Headers) {
+    for name in ["authorization", "proxy-authorization"] {
+        if let Some((user, password)) = headers.get(name).and_then(credentials::basic_auth) {
+            credentials::on_credential("http", "Basic", Some(flow), Some(&user), &password);
+        }
+    }
+}
+
+/// Hand the bodies of a transaction to the file extraction
+///
+/// Bodies are given with their content codings undone, along with their full length.
+/// Uploads in multipart/form-data are extracted part by part.
+pub fn extract_bodies(
+    flow: &FlowKey,
+    uri: &str,
+    request: (&Headers, &[u8], u64),
+    response: Option<(&Headers, &[u8], u64)>,
+) {
+    if !extract::enabled() {
+        return;
+    }
+    let (headers, body, len) = request;
+    let content_type = headers.get("content-type").unwrap_or("");
+    if content_type
+        .to_ascii_lowercase()
+        .starts_with("multipart/form-data")
+    {
+        for part in super::mime::body_parts(content_type, body) {
+            if part.filename.is_some() {
+                let ct = Some(part.content_type.as_str());
+                let size = part.data.len() as u64;
+                extract::on_file("http", flow, part.filename.as_deref(), ct, &part.data, size);
+            }
+        }
+    } else {
+        let name = filename(uri, headers);
+        let ct = headers.get("content-type");
+        extract::on_file("http", flow, name.as_deref(), ct, body, len);
+    }
+    if let Some((headers, body, len)) = response {
+        let name = filename(uri, headers);
+        let ct = headers.get("content-type");
+        extract::on_file("http", flow, name.as_deref(), ct, body, len);
+    }
+}
+
+/// A complete message and its body, content codings removed
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub head: Head,
+    /// body as sent (after chunked decoding), up to MAX_FILE bytes; only kept while
+    /// files are extracted
+    pub body: Vec<u8>,
+    /// full length of the body on the wire
+    pub body_len: u64,
+    /// body with the content codings undone, when there are any
+    pub decoded: Option<Vec<u8>>,
+}
+
+impl Message {
+    /// Body as the application sees it
+    pub fn content(&self) -> &[u8] {
+        self.decoded.as_deref().unwrap_or(&self.body)
+    }
+}
+
+/// Request and response of one transaction
+#[derive(Debug, Clone, Serialize)]
+pub struct Exchange {
+    /// flow oriented from the client to the server
+    pub flow: FlowKey,
+    pub method: String,
+    pub uri: String,
+    pub version: String,
+    pub host: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_headers: Headers,
+    pub request_body_len: u64,
+    /// missing when the connection closed before the response
+    pub status: Option<u16>,
+    pub reason: Option<String>,
+    pub response_headers: Option<Headers>,
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    pub response_body_len: u64,
+    /// response body length once the content codings are undone
+    pub response_content_len: u64,
+}
+
+/// How the body of a message ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Framing {
+    Empty,
+    Length(u64),
+    Chunked,
+    UntilClose,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Head,
+    Body(u64),
+    ChunkSize,
+    ChunkData(u64),
+    ChunkEnd,
+    Trailers,
+    UntilClose,
+    /// a request asked to switch protocol, wait for the answer
+    Wait,
+    /// bytes belong to another protocol after CONNECT or an Upgrade
+    Switched,
+    /// not HTTP (anymore), ignore the rest of the direction
+    Skip,
+}
+
+struct Side {
+    buf: Vec<u8>,
+    state: State,
+    head: Option<Head>,
+    body: Buffer,
+    body_len: u64,
+}
+
+impl Default for Side {
+    fn default() -> Self {
+        Side {
+            buf: Vec::new(),
+            state: State::Head,
+            head: None,
+            body: Buffer::default(),
+            body_len: 0,
+        }
+    }
+}
+
+impl Side {
+    fn add_body(&mut self, data: &[u8]) {
+        self.body_len += data.len() as u64;
+        if !extract::enabled() {
+            return;
+        }
+        self.body.extend(data);
+    }
+
+    /// Advance the state machine over the buffer, returning the completed message if any
+    ///
+    /// `framing` gives the body framing of a head just parsed.
+    fn step(&mut self, framing: &mut dyn FnMut(&Head) -> Framing) -> Step {
+        match self.state {
+            State::Head => match parse_head(&self.buf) {
+                Parsed::Complete(head, len) => {
+                    self.buf.drain(..len);
+                    self.body.clear();
+                    self.body_len = 0;
+                    self.state = match framing(&head) {
+                        Framing::Empty | Framing::Length(0) => {
+                            self.head = Some(head);
+                            return self.finish();
+                        }
+                        Framing::Length(n) => State::Body(n),
+                        Framing::Chunked => State::ChunkSize,
+                        Framing::UntilClose => State::UntilClose,
+                    };
+                    self.head = Some(head);
+                    Step::Continue
+                }
+                Parsed::Partial => Step::NeedMore,
+                Parsed::Invalid => {
+                    self.state = State::Skip;
+                    Step::Invalid
+                }
+            },
+            State::Body(remaining) => {
+                let n = (remaining as usize).min(self.buf.len());
+                let data: Vec<u8> = self.buf.drain(..n).collect();
+                self.add_body(&data);
+                if n as u64 == remaining {
+                    return self.finish();
+                }
+                self.state = State::Body(remaining - n as u64);
+                Step::NeedMore
+            }
+            State::ChunkSize | State::Trailers | State::ChunkEnd => {
+                let eol = match self.buf.iter().position(|b| *b == b'\n') {
+                    Some(i) => i,
+                    None if self.buf.len() > MAX_HEAD => {
+                        self.state = State::Skip;
+                        return Step::Invalid;
+                    }
+                    None => return Step::NeedMore,
+                };
+                let line: Vec<u8> = self.buf.drain(..eol + 1).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim();
+                match self.state {
+                    State::ChunkSize => {
+                        // chunk extensions follow a ';'
+                        let size = line.split(';').next().unwrap_or("").trim();
+                        match u64::from_str_radix(size, 16) {
+                            Ok(0) => self.state = State::Trailers,
+                            Ok(n) => self.state = State::ChunkData(n),
+                            Err(_) => {
+                                self.state = State::Skip;
+                                return Step::Invalid;
+                            }
+                        }
+                    }
+                    State::ChunkEnd => self.state = State::ChunkSize,
+                    _ if line.is_empty() => return self.finish(),
+                    // trailer fields are not kept
+                    _ => {}
+                }
+                Step::Continue
+            }
+            State::ChunkData(remaining) => {
+                let n = (remaining as usize).min(self.buf.len());
+                let data: Vec<u8> = self.buf.drain(..n).collect();
+                self.add_body(&data);
+                self.state = if n as u64 == remaining {
+                    State::ChunkEnd
+                } else {
+                    State::ChunkData(remaining - n as u64)
+                };
+                if self.buf.is_empty() {
+                    Step::NeedMore
+                } else {
+                    Step::Continue
+                }
+            }
+            State::UntilClose => {
+                let data = std::mem::take(&mut self.buf);
+                self.add_body(&data);
+                Step::NeedMore
+            }
+            State::Wait | State::Switched | State::Skip => Step::NeedMore,
+        }
+    }
+
+    fn finish(&mut self) -> Step {
+        self.state = State::Head;
+        let head = match self.head.take() {
+            Some(h) => h,
+            None => return Step::Continue,
+        };
+        let codings = head.headers.tokens("content-encoding");
+        let body = self.body.take();
+        let decoded = if codings.is_empty() || body.is_empty() {
+            None
+        } else {
+            decode_content(&codings, &body)
+        };
+        Step::Message(Message {
+            head,
+            body,
+            body_len: self.body_len,
+            decoded,
+        })
+    }
+}
+
+enum Step {
+    Continue,
+    NeedMore,
+    Invalid,
+    Message(Message),
+}
+
+/// Request waiting for its response
+struct Pending {
+    method: String,
+    message: Message,
+}
+
+/// Follows the HTTP/1.x messages of a reassembled connection
+#[derive(Default)]
+pub struct Connection {
+    client: Side,
+    server: Side,
+    /// requests without response yet, in order (pipelining)
+    pending: VecDeque<Pending>,
+    /// the queue of requests overflowed: responses are not paired anymore
+    desynced: bool,
+    /// decoder of the bytes after a CONNECT or an Upgrade
+    tunnel: Option<Box<dyn StreamHandler>>,
+    websocket: bool,
+}
+
+fn describe(msg: &Message) -> String {
+    let mut s = format!("{} headers, body {}B", msg.head.headers.len(), msg.body_len);
+    if let Some(coding) = msg.head.headers.get("content-encoding") {
+        match &msg.decoded {
+            Some(d) => s.push_str(&format!(" {} -> {}B", coding, d.len())),
+            // bodies are only kept to extract them
+            None if msg.body.is_empty() && msg.body_len > 0 => s.push_str(&format!(" {}", coding)),
+            None if msg.body_len > 0 => s.push_str(&format!(" {} (not decoded)", coding)),
+            None => {}
+        }
+    }
+    s
+}
+
+fn request_framing(head: &Head) -> Framing {
+    if head
+        .headers
+        .tokens("transfer-encoding")
+        .last()
+        .map(String::as_str)
+        == Some("chunked")
+    {
+        return Framing::Chunked;
+    }
+    match head.headers.get("content-length").map(str::parse::<u64>) {
+        Some(Ok(n)) => Framing::Length(n),
+        _ => Framing::Empty,
+    }
+}
+
+fn response_framing(head: &Head, method: Option<&str>) -> Framing {
+    let status = match head.start {
+        StartLine::Response { status, .. } => status,
+        StartLine::Request { .. } => return Framing::Empty,
+    };
+    if method == Some("HEAD")
+        || (100..200).contains(&status)
+        || status == 204
+        || status == 304
+        || (method == Some("CONNECT") && (200..300).contains(&status))
+    {
+        return Framing::Empty;
+    }
+    if head
+        .headers
+        .tokens("transfer-encoding")
+        .last()
+        .map(String::as_str)
+        == Some("chunked")
+    {
+        return Framing::Chunked;
+    }
+    match head.headers.get("content-length").map(str::parse::<u64>) {
+        Some(Ok(n)) => Framing::Length(n),
+        _ => Framing::UntilClose,
+    }
+}
+
+impl Connection {
+    fn side(&mut self, from_client: bool) -> &mut Side {
+        if from_client {
+            &mut self.client
+        } else {
+            &mut self.server
+        }
+    }
+
+    fn on_request(&mut self, flow: &FlowKey, msg: Message) {
+        let (method, uri, version) = match &msg.head.start {
+            StartLine::Request {
+                method,
+                uri,
+                version,
+            } => (method.clone(), uri, version),
+            StartLine::Response { .. } => return,
+        };
+        println!(
+            "HTTP request {} {} {} host={} ({})",
+            method,
+            uri,
+            version,
+            msg.head.headers.get("host").unwrap_or("-"),
+            describe(&msg)
+        );
+        if !self.desynced && self.pending.len() == MAX_PENDING {
+            // the next responses would answer other requests than the ones they are paired with
+            expert::warning(
+                "http",
+                0,
+                "desynced",
+                format!(
+                    "more than {} requests without response, responses are no longer paired",
+                    MAX_PENDING
+                ),
+            );
+            self.desynced = true;
+            while let Some(request) = self.pending.pop_front() {
+                emit(flow, &request, None);
+            }
+        }
+        let request = Pending {
+            method,
+            message: msg,
+        };
+        if self.desynced {
+            emit(flow, &request, None);
+            return;
+        }
+        // the client waits for the answer before talking another protocol
+        if request.method == "CONNECT" || !request.message.head.headers.tokens("upgrade").is_empty()
+        {
+            self.client.state = State::Wait;
+        }
+        self.pending.push_back(request);
+    }
+
+    fn on_response(&mut self, flow: &FlowKey, msg: Message) {
+        let (status, reason, version) = match &msg.head.start {
+            StartLine::Response {
+                status,
+                reason,
+                version,
+            } => (*status, reason.clone(), version.clone()),
+            StartLine::Request { .. } => return,
+        };
+        println!(
+            "HTTP response {} {} {} content-type={} ({})",
+            version,
+            status,
+            reason,
+            msg.head.headers.get("content-type").unwrap_or("-"),
+            describe(&msg)
+        );
+        // responses cannot be told apart once requests were dropped
+        if self.desynced {
+            return;
+        }
+        // interim responses precede the final one
+        if (100..200).contains(&status) && status != 101 {
+            return;
+        }
+        let request = match self.pending.pop_front() {
+            Some(r) => r,
+            None => return,
+        };
+        let switched = (status == 101
+            && !request.message.head.headers.tokens("upgrade").is_empty())
+            || (request.method == "CONNECT" && (200..300).contains(&status));
+        if switched {
+            self.switch(&request, &msg);
+        } else if self.client.state == State::Wait {
+            self.client.state = State::Head;
+        }
+        emit(flow, &request, Some(&msg));
+    }
+
+    /// Hand the rest of the connection to the protocol negotiated by CONNECT or Upgrade
+    fn switch(&mut self, request: &Pending, response: &Message) {
+        let protocol = response
+            .head
+            .headers
+            .tokens("upgrade")
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| "tunnel".to_string());
+        match &request.message.head.start {
+            StartLine::Request { uri, .. } if request.method == "CONNECT" => {
+                println!("HTTP CONNECT tunnel to {}", uri)
+            }
+            _ => println!("HTTP upgrade to {}", protocol),
+        }
+        self.websocket = protocol == "websocket";
+        if protocol == "h2c" {
+            self.tunnel = Some(Box::new(super::http2::Connection::upgraded(
+                &request.message,
+            )));
+        }
+        self.client.state = State::Switched;
+        self.server.state = State::Switched;
+    }
+
+    /// Bytes after the switch of protocol
+    fn on_switched(&mut self, flow: &FlowKey, from_client: bool) {
+        if self.websocket {
+            let side = self.side(from_client);
+            let used = super::websocket::frames(&side.buf);
+            side.buf.drain(..used);
+            return;
+        }
+        let data = std::mem::take(&mut self.side(from_client).buf);
+        if data.is_empty() {
+            return;
+        }
+        if self.tunnel.is_none() {
+            // a tunnel usually carries TLS, recognized like a fresh connection
+            self.tunnel = super::tcp::stream_handler(flow, from_client, &data);
+            if self.tunnel.is_none() {
+                self.client.state = State::Skip;
+                self.server.state = State::Skip;
+                return;
+            }
+        }
+        if let Some(tunnel) = self.tunnel.as_mut() {
+            tunnel.on_data(flow, from_client, &data);
+        }
+    }
+}
+
+fn emit(flow: &FlowKey, request: &Pending, response: Option<&Message>) {
+    let req = &request.message;
+    let (uri, version) = match &req.head.start {
+        StartLine::Request { uri, version, .. } => (uri.clone(), version.clone()),
+        StartLine::Response { .. } => return,
+    };
+    let (status, reason) = match response.map(|r| &r.head.start) {
+        Some(StartLine::Response { status, reason, .. }) => (Some(*status), Some(reason.clone())),
+        _ => (None, None),
+    };
+    let header = |name: &str| {
+        response
+            .and_then(|r| r.head.headers.get(name))
+            .map(str::to_string)
+    };
+    // lengths after decoding when the content codings could be undone
+    let size = |m: &Message| m.decoded.as_ref().map_or(m.body_len, |d| d.len() as u64);
+    authorization(flow, &req.head.headers);
+    extract_bodies(
+        flow,
+        &uri,
+        (&req.head.headers, req.content(), size(req)),
+        response.map(|r| (&r.head.headers, r.content(), size(r))),
+    );
+    let exchange = Exchange {
+        flow: *flow,
+        method: request.method.clone(),
+        uri,
+        version,
+        host: req.head.headers.get("host").map(str::to_string),
+        user_agent: req.head.headers.get("user-agent").map(str::to_string),
+        request_headers: req.head.headers.clone(),
+        request_body_len: req.body_len,
+        status,
+        reason,
+        response_headers: response.map(|r| r.head.headers.clone()),
+        content_type: header("content-type"),
+        content_encoding: header("content-encoding"),
+        response_body_len: response.map_or(0, |r| r.body_len),
+        response_content_len: response.map_or(0, size),
+    };
+    rules::on_http(&exchange);
+    ioc::on_http(&exchange);
+    event::emit(Event::Http(exchange));
+}
+
+impl StreamHandler for Connection {
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]) {
+        let side = self.side(from_client);
+        match side.state {
+            State::Skip => return,
+            State::Wait if side.buf.len() > MAX_HEAD => return,
+            _ => {}
+        }
+        side.buf.extend_from_slice(data);
+        loop {
+            if self.side(from_client).state == State::Switched {
+                self.on_switched(flow, from_client);
+                return;
+            }
+            let method = self.pending.front().map(|p| p.method.clone());
+            let step = self.side(from_client).step(&mut |head| {
+                if from_client {
+                    request_framing(head)
+                } else {
+                    response_framing(head, method.as_deref())
+                }
+            });
+            match step {
+                Step::Continue => {}
+                Step::NeedMore => return,
+                Step::Invalid => {
+                    println!("HTTP stream is not HTTP/1.x anymore, ignoring the rest");
+                    return;
+                }
+                Step::Message(msg) if from_client => self.on_request(flow, msg),
+                Step::Message(msg) => self.on_response(flow, msg),
+            }
+            // client bytes held back by an upgrade request are decoded once answered
+            if !from_client
+                && !self.client.buf.is_empty()
+                && matches!(self.client.state, State::Head | State::Switched)
+            {
+                self.on_data(flow, true, &[]);
+            }
+        }
+    }
+
+    fn on_close(&mut self, flow: &FlowKey) {
+        // a body delimited by the end of the connection is complete now
+        if self.server.state == State::UntilClose {
+            if let Step::Message(msg) = self.server.finish() {
+                self.on_response(flow, msg);
+            }
+        }
+        if let Some(tunnel) = self.tunnel.as_mut() {
+            tunnel.on_close(flow);
+        }
+        while let Some(request) = self.pending.pop_front() {
+            emit(flow, &request, None);
+        }
+    }
+}
+
+/// Stream handler for a connection starting with an HTTP/1.x request
+pub fn connection(from_client: bool, data: &[u8]) -> Option<Connection> {
+    if !from_client {
+        return None;
+    }
+    METHODS
+        .iter()
+        .any(|m| data.starts_with(m.as_bytes()) && data.get(m.len()) == Some(&b' '))
+        .then(Connection::default)
+}
+
+/// Confidence that the payload starts an HTTP/1.x request or response
+pub fn probe(data: &[u8]) -> u8 {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    let version = line.windows(7).any(|w| w == b"HTTP/1.");
+    if line.starts_with(b"HTTP/1.") && line.get(8) == Some(&b' ') {
+        return confidence::CERTAIN;
+    }
+    let method = METHODS
+        .iter()
+        .any(|m| data.starts_with(m.as_bytes()) && data.get(m.len()) == Some(&b' '));
+    match (method, version) {
+        (true, true) => confidence::CERTAIN,
+        (true, false) => confidence::MEDIUM,
+        _ => confidence::NONE,
+    }
+}
+
 pub fn decode(data: &[u8]) {
     // very naive HTTP/1.x parser: print first line
     if data.is_empty() {
@@ -7,6 +874,18 @@ pub fn decode(data: &[u8]) {
     if let Ok(s) = std::str::from_utf8(&data[..max]) {
         if let Some(line) = s.lines().next() {
             println!("HTTP: {}", line.trim());
+            if probe(data) >= confidence::MEDIUM {
+                // a status line already starts with the version
+                let summary = if line.starts_with("HTTP/") {
+                    line.trim().to_string()
+                } else {
+                    format!("HTTP {}", line.trim())
+                };
+                tree::layer("http", data, summary);
+                tree::field("Start line", line.as_bytes(), line.trim());
+            } else {
+                tree::layer("http", data, "HTTP continuation");
+            }
         }
     }
 }
@@ -0,0 +1,726 @@
+// HTTP/2 decoder
+// Follows a reassembled connection (prior knowledge h2c, h2c after an HTTP/1.1 Upgrade or
+// decrypted h2): frames, header blocks decoded with one HPACK context per direction, and
+// requests and responses rebuilt per stream. Streams carrying gRPC are split into
+// messages.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc9113
+use std::collections::HashMap;
+
+use crate::analysis::extract::{self, Buffer};
+use crate::analysis::{ioc, rules};
+use crate::event::{self, Event};
+use crate::expert::{self, DecodeError};
+use crate::flow::FlowKey;
+use crate::reassembly::StreamHandler;
+use crate::tree;
+
+use super::detect::confidence;
+use super::grpc;
+use super::hpack;
+use super::http::{self, Exchange, Headers};
+
+/// Connection preface sent by the client
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Frame types
+#[non_exhaustive]
+pub struct FRAME;
+
+impl FRAME {
+    pub const DATA: u8 = 0x0;
+    pub const HEADERS: u8 = 0x1;
+    pub const PRIORITY: u8 = 0x2;
+    pub const RST_STREAM: u8 = 0x3;
+    pub const SETTINGS: u8 = 0x4;
+    pub const PUSH_PROMISE: u8 = 0x5;
+    pub const PING: u8 = 0x6;
+    pub const GOAWAY: u8 = 0x7;
+    pub const WINDOW_UPDATE: u8 = 0x8;
+    pub const CONTINUATION: u8 = 0x9;
+}
+
+/// Frame flags
+#[non_exhaustive]
+pub struct FLAGS;
+
+impl FLAGS {
+    pub const END_STREAM: u8 = 0x01;
+    pub const ACK: u8 = 0x01;
+    pub const END_HEADERS: u8 = 0x04;
+    pub const PADDED: u8 = 0x08;
+    pub const PRIORITY: u8 = 0x20;
+}
+
+/// Largest header block accepted (CONTINUATION frames included)
+const MAX_HEADER_BLOCK: usize = 1 << 20;
+/// Open streams tracked per connection
+const MAX_STREAMS: usize = 1024;
+/// Largest frame payload until the peer allows more with SETTINGS_MAX_FRAME_SIZE
+const DEFAULT_MAX_FRAME: usize = 16_384;
+/// Largest SETTINGS_MAX_FRAME_SIZE allowed, the length field being 24 bits
+const MAX_FRAME: usize = (1 << 24) - 1;
+/// Identifier of SETTINGS_MAX_FRAME_SIZE
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+
+/// Confidence that the payload starts an HTTP/2 connection (prior knowledge preface)
+pub fn probe(data: &[u8]) -> u8 {
+    if data.starts_with(PREFACE) {
+        confidence::CERTAIN
+    } else if data.len() >= 4 && PREFACE.starts_with(&data[..data.len().min(PREFACE.len())]) {
+        confidence::HIGH
+    } else {
+        confidence::NONE
+    }
+}
+
+pub fn frame_type_as_str(t: u8) -> &'static str {
+    match t {
+        FRAME::DATA => "DATA",
+        FRAME::HEADERS => "HEADERS",
+        FRAME::PRIORITY => "PRIORITY",
+        FRAME::RST_STREAM => "RST_STREAM",
+        FRAME::SETTINGS => "SETTINGS",
+        FRAME::PUSH_PROMISE => "PUSH_PROMISE",
+        FRAME::PING => "PING",
+        FRAME::GOAWAY => "GOAWAY",
+        FRAME::WINDOW_UPDATE => "WINDOW_UPDATE",
+        FRAME::CONTINUATION => "CONTINUATION",
+        _ => "UNKNOW",
+    }
+}
+
+pub fn error_code_as_str(code: u32) -> &'static str {
+    match code {
+        0x0 => "NO_ERROR",
+        0x1 => "PROTOCOL_ERROR",
+        0x2 => "INTERNAL_ERROR",
+        0x3 => "FLOW_CONTROL_ERROR",
+        0x4 => "SETTINGS_TIMEOUT",
+        0x5 => "STREAM_CLOSED",
+        0x6 => "FRAME_SIZE_ERROR",
+        0x7 => "REFUSED_STREAM",
+        0x8 => "CANCEL",
+        0x9 => "COMPRESSION_ERROR",
+        0xa => "CONNECT_ERROR",
+        0xb => "ENHANCE_YOUR_CALM",
+        0xc => "INADEQUATE_SECURITY",
+        0xd => "HTTP_1_1_REQUIRED",
+        _ => "UNKNOW",
+    }
+}
+
+pub fn setting_as_str(id: u16) -> &'static str {
+    match id {
+        0x1 => "HEADER_TABLE_SIZE",
+        0x2 => "ENABLE_PUSH",
+        0x3 => "MAX_CONCURRENT_STREAMS",
+        0x4 => "INITIAL_WINDOW_SIZE",
+        0x5 => "MAX_FRAME_SIZE",
+        0x6 => "MAX_HEADER_LIST_SIZE",
+        0x8 => "ENABLE_CONNECT_PROTOCOL",
+        _ => "UNKNOW",
+    }
+}
+
+/// Frame header
+#[derive(Debug, Clone, Copy)]
+pub struct FrameHeader {
+    pub length: usize,
+    pub frame_type: u8,
+    pub flags: u8,
+    /// stream identifier, reserved bit cleared
+    pub stream_id: u32,
+}
+
+impl FrameHeader {
+    pub const SIZE: usize = 9;
+
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let h = data.get(..Self::SIZE)?;
+        Some(FrameHeader {
+            length: u32::from_be_bytes([0, h[0], h[1], h[2]]) as usize,
+            frame_type: h[3],
+            flags: h[4],
+            stream_id: u32::from_be_bytes([h[5], h[6], h[7], h[8]]) & 0x7fff_ffff,
+        })
+    }
+}
+
+fn be32(data: &[u8], i: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?))
+}
+
+/// Payload of a frame without its padding (PADDED flag)
+fn unpad(header: &FrameHeader, payload: &[u8]) -> Option<(usize, usize)> {
+    if header.flags & FLAGS::PADDED == 0 {
+        return Some((0, payload.len()));
+    }
+    let pad = *payload.first()? as usize;
+    let end = payload.len().checked_sub(pad)?;
+    (end >= 1).then_some((1, end))
+}
+
+/// One direction of a stream
+#[derive(Default)]
+struct Half {
+    headers: Option<Headers>,
+    trailers: Option<Headers>,
+    /// only kept while files are extracted
+    body: Buffer,
+    body_len: u64,
+    ended: bool,
+    grpc: Option<grpc::Splitter>,
+}
+
+impl Half {
+    fn add_body(&mut self, data: &[u8]) {
+        self.body_len += data.len() as u64;
+        if extract::enabled() {
+            self.body.extend(data);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stream {
+    request: Half,
+    response: Half,
+}
+
+impl Stream {
+    fn half(&mut self, from_client: bool) -> &mut Half {
+        if from_client {
+            &mut self.request
+        } else {
+            &mut self.response
+        }
+    }
+
+    fn path(&self) -> &str {
+        self.request
+            .headers
+            .as_ref()
+            .and_then(|h| h.get(":path"))
+            .unwrap_or("-")
+    }
+}
+
+/// Header block being received (HEADERS or PUSH_PROMISE, then CONTINUATION)
+struct Block {
+    stream_id: u32,
+    /// stream announced by a PUSH_PROMISE
+    promised: Option<u32>,
+    end_stream: bool,
+    data: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Side {
+    buf: Vec<u8>,
+    /// the client starts with the connection preface
+    preface: bool,
+    hpack: hpack::Decoder,
+    block: Option<Block>,
+    /// largest frame payload this side may send, as set by the SETTINGS of its peer
+    max_frame: Option<usize>,
+    /// framing or compression error, the direction is no longer followed
+    broken: bool,
+    /// bytes of an oversized frame still to be dropped
+    skip: usize,
+}
+
+/// Follows the frames of a reassembled HTTP/2 connection
+#[derive(Default)]
+pub struct Connection {
+    client: Side,
+    server: Side,
+    streams: HashMap<u32, Stream>,
+}
+
+impl Connection {
+    fn new() -> Self {
+        let mut c = Connection::default();
+        c.client.preface = true;
+        c
+    }
+
+    /// Connection after an HTTP/1.1 `Upgrade: h2c`, the request becoming stream 1
+    pub fn upgraded(request: &http::Message) -> Self {
+        let mut c = Connection::new();
+        let mut stream = Stream::default();
+        if let http::StartLine::Request { method, uri, .. } = &request.head.start {
+            let mut headers = vec![
+                (":method".to_string(), method.clone()),
+                (":path".to_string(), uri.clone()),
+            ];
+            headers.extend(request.head.headers.0.iter().cloned());
+            stream.request.headers = Some(Headers(headers));
+        }
+        stream.request.add_body(&request.body);
+        stream.request.ended = true;
+        c.streams.insert(1, stream);
+        c
+    }
+
+    fn side(&mut self, from_client: bool) -> &mut Side {
+        if from_client {
+            &mut self.client
+        } else {
+            &mut self.server
+        }
+    }
+
+    fn stream(&mut self, id: u32) -> Option<&mut Stream> {
+        if !self.streams.contains_key(&id) && self.streams.len() >= MAX_STREAMS {
+            expert::warning(
+                "http2",
+                0,
+                "too-many-streams",
+                format!(
+                    "stream {} not followed, {} streams are already open",
+                    id, MAX_STREAMS
+                ),
+            );
+            return None;
+        }
+        Some(self.streams.entry(id).or_default())
+    }
+
+    /// A complete frame, header included
+    fn on_frame(&mut self, flow: &FlowKey, from_client: bool, h: FrameHeader, frame: &[u8]) {
+        let (header, payload) = frame.split_at(FrameHeader::SIZE);
+        let mut detail = String::new();
+        match h.frame_type {
+            FRAME::SETTINGS => {
+                if h.flags & FLAGS::ACK != 0 {
+                    detail = " ACK".to_string();
+                }
+                for s in payload.chunks_exact(6) {
+                    let id = u16::from_be_bytes([s[0], s[1]]);
+                    let value = u32::from_be_bytes([s[2], s[3], s[4], s[5]]);
+                    detail.push_str(&format!(" {}={}", setting_as_str(id), value));
+                    // the receiver announces the frames it accepts from the other side
+                    let size = value as usize;
+                    if id == SETTINGS_MAX_FRAME_SIZE
+                        && (DEFAULT_MAX_FRAME..=MAX_FRAME).contains(&size)
+                    {
+                        self.side(!from_client).max_frame = Some(size);
+                    }
+                }
+            }
+            FRAME::WINDOW_UPDATE => {
+                if let Some(inc) = be32(payload, 0) {
+                    detail = format!(" increment={}", inc & 0x7fff_ffff);
+                }
+            }
+            FRAME::PING if h.flags & FLAGS::ACK != 0 => detail = " ACK".to_string(),
+            FRAME::PRIORITY => {
+                if let (Some(dep), Some(weight)) = (be32(payload, 0), payload.get(4)) {
+                    detail = format!(
+                        " depends_on={} weight={}",
+                        dep & 0x7fff_ffff,
+                        *weight as u16 + 1
+                    );
+                }
+            }
+            FRAME::RST_STREAM => {
+                if let Some(code) = be32(payload, 0) {
+                    detail = format!(" error={}", error_code_as_str(code));
+                }
+            }
+            FRAME::GOAWAY => {
+                if let (Some(last), Some(code)) = (be32(payload, 0), be32(payload, 4)) {
+                    detail = format!(
+                        " last_stream={} error={}",
+                        last & 0x7fff_ffff,
+                        error_code_as_str(code)
+                    );
+                    if payload.len() > 8 {
+                        detail
+                            .push_str(&format!(" \"{}\"", String::from_utf8_lossy(&payload[8..])));
+                    }
+                }
+            }
+            _ => {}
+        }
+        let line = format!(
+            "HTTP/2 {} stream={} {}B{}",
+            frame_type_as_str(h.frame_type),
+            h.stream_id,
+            h.length,
+            detail
+        );
+        println!("{}", line);
+        // the frames are read from the reassembled stream, their bytes have no offset
+        tree::layer("http2", frame, line);
+        tree::field("Length", &header[..3], h.length);
+        tree::field(
+            "Type",
+            &header[3..4],
+            format_args!("{} ({})", frame_type_as_str(h.frame_type), h.frame_type),
+        );
+        tree::field("Flags", &header[4..5], format_args!("0x{:02x}", h.flags));
+        tree::field("Stream identifier", &header[5..], h.stream_id);
+
+        match h.frame_type {
+            FRAME::DATA if h.stream_id != 0 => {
+                let (start, end) = match unpad(&h, payload) {
+                    Some(r) => r,
+                    None => return,
+                };
+                self.on_body(flow, from_client, h.stream_id, &payload[start..end]);
+                if h.flags & FLAGS::END_STREAM != 0 {
+                    self.end_stream(flow, from_client, h.stream_id);
+                }
+            }
+            FRAME::HEADERS | FRAME::PUSH_PROMISE => {
+                let (mut start, end) = match unpad(&h, payload) {
+                    Some(r) => r,
+                    None => return,
+                };
+                let mut promised = None;
+                if h.frame_type == FRAME::HEADERS && h.flags & FLAGS::PRIORITY != 0 {
+                    start += 5;
+                }
+                if h.frame_type == FRAME::PUSH_PROMISE {
+                    promised = be32(payload, start).map(|id| id & 0x7fff_ffff);
+                    start += 4;
+                }
+                let fragment = match payload.get(start..end) {
+                    Some(f) => f,
+                    None => return,
+                };
+                self.side(from_client).block = Some(Block {
+                    stream_id: h.stream_id,
+                    promised,
+                    end_stream: h.frame_type == FRAME::HEADERS && h.flags & FLAGS::END_STREAM != 0,
+                    data: fragment.to_vec(),
+                });
+                if h.flags & FLAGS::END_HEADERS != 0 {
+                    self.on_block(flow, from_client);
+                }
+            }
+            FRAME::CONTINUATION => {
+                let side = self.side(from_client);
+                match side.block.as_mut() {
+                    Some(b) if b.stream_id == h.stream_id => {
+                        if b.data.len() + payload.len() > MAX_HEADER_BLOCK {
+                            expert::error(DecodeError::Malformed {
+                                layer: "http2",
+                                offset: 0,
+                                reason: "header block larger than 1 MiB, the rest of the direction is ignored",
+                            });
+                            side.broken = true;
+                            return;
+                        }
+                        b.data.extend_from_slice(payload)
+                    }
+                    _ => return,
+                }
+                if h.flags & FLAGS::END_HEADERS != 0 {
+                    self.on_block(flow, from_client);
+                }
+            }
+            FRAME::RST_STREAM => {
+                if let Some(stream) = self.streams.remove(&h.stream_id) {
+                    self.finish(flow, h.stream_id, stream);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A complete header block
+    fn on_block(&mut self, flow: &FlowKey, from_client: bool) {
+        let side = self.side(from_client);
+        let block = match side.block.take() {
+            Some(b) => b,
+            None => return,
+        };
+        let headers = match side.hpack.decode(&block.data) {
+            Some(h) => Headers(h),
+            None => {
+                // reported by the decoder; the dynamic table is out of sync from now on and
+                // the rest of the direction is ignored
+                side.broken = true;
+                return;
+            }
+        };
+        // a promised stream carries the request the server pushes
+        if let Some(promised) = block.promised {
+            println!(
+                "HTTP/2 stream={} push promise {} {}",
+                promised,
+                headers.get(":method").unwrap_or("-"),
+                headers.get(":path").unwrap_or("-")
+            );
+            if let Some(stream) = self.stream(promised) {
+                stream.request.headers = Some(headers);
+                stream.request.ended = true;
+            }
+            return;
+        }
+        let id = block.stream_id;
+        let stream = match self.stream(id) {
+            Some(s) => s,
+            None => return,
+        };
+        let half = stream.half(from_client);
+        let informational = half
+            .headers
+            .as_ref()
+            .and_then(|h| h.get(":status"))
+            .is_some_and(|s| s.starts_with('1'));
+        if half.headers.is_some() && !informational {
+            println!(
+                "HTTP/2 stream={} trailers ({} headers){}",
+                id,
+                headers.len(),
+                headers
+                    .get("grpc-status")
+                    .map(|s| format!(" grpc-status={}", s))
+                    .unwrap_or_default()
+            );
+            half.trailers = Some(headers);
+        } else {
+            if from_client {
+                println!(
+                    "HTTP/2 stream={} request {} {}://{}{} ({} headers)",
+                    id,
+                    headers.get(":method").unwrap_or("-"),
+                    headers.get(":scheme").unwrap_or("-"),
+                    headers
+                        .get(":authority")
+                        .or(headers.get("host"))
+                        .unwrap_or("-"),
+                    headers.get(":path").unwrap_or(""),
+                    headers.len()
+                );
+            } else {
+                println!(
+                    "HTTP/2 stream={} response {} content-type={} ({} headers)",
+                    id,
+                    headers.get(":status").unwrap_or("-"),
+                    headers.get("content-type").unwrap_or("-"),
+                    headers.len()
+                );
+            }
+            // decompressed from the block, the values have no bytes in the frame
+            for name in [":method", ":scheme", ":authority", ":path", ":status"] {
+                if let Some(value) = headers.get(name) {
+                    tree::field(name, value.as_bytes(), value);
+                }
+            }
+            if headers.get("content-type").is_some_and(grpc::is_grpc) {
+                half.grpc = Some(grpc::Splitter::default());
+            }
+            half.headers = Some(headers);
+        }
+        if block.end_stream {
+            self.end_stream(flow, from_client, id);
+        }
+    }
+
+    fn on_body(&mut self, _flow: &FlowKey, from_client: bool, id: u32, data: &[u8]) {
+        let stream = match self.streams.get_mut(&id) {
+            Some(s) => s,
+            None => return,
+        };
+        let path = stream.path().to_string();
+        let half = stream.half(from_client);
+        half.add_body(data);
+        if let Some(splitter) = half.grpc.as_mut() {
+            for m in splitter.push(data) {
+                let line = format!(
+                    "gRPC {} {} message {}B{}",
+                    path,
+                    if from_client { "request" } else { "response" },
+                    m.len,
+                    if m.compressed { " (compressed)" } else { "" }
+                );
+                println!("{}", line);
+                // the message may span several frames, its bytes are a copy
+                tree::layer("grpc", &m.data[..], line);
+            }
+        }
+    }
+
+    fn end_stream(&mut self, flow: &FlowKey, from_client: bool, id: u32) {
+        let done = match self.streams.get_mut(&id) {
+            Some(stream) => {
+                stream.half(from_client).ended = true;
+                stream.request.ended && stream.response.ended
+            }
+            None => false,
+        };
+        if done {
+            if let Some(stream) = self.streams.remove(&id) {
+                self.finish(flow, id, stream);
+            }
+        }
+    }
+
+    /// Report a stream once both sides ended (or it was reset)
+    fn finish(&mut self, flow: &FlowKey, id: u32, stream: Stream) {
+        let request = match stream.request.headers.as_ref() {
+            Some(h) => h,
+            None => return,
+        };
+        let response = stream.response.headers.as_ref();
+        let header = |name: &str| response.and_then(|h| h.get(name)).map(str::to_string);
+        let codings = response
+            .map(|h| h.tokens("content-encoding"))
+            .unwrap_or_default();
+        let decoded = if codings.is_empty() || stream.response.body.is_empty() {
+            None
+        } else {
+            http::decode_content(&codings, &stream.response.body)
+        };
+        let content = decoded.as_deref().unwrap_or(&stream.response.body);
+        let content_len = decoded
+            .as_ref()
+            .map_or(stream.response.body_len, |d| d.len() as u64);
+        http::authorization(flow, request);
+        http::extract_bodies(
+            flow,
+            stream.path(),
+            (request, &stream.request.body, stream.request.body_len),
+            response.map(|h| (h, content, content_len)),
+        );
+        if stream.request.grpc.is_some() || stream.response.grpc.is_some() {
+            // a call failing right away only sends trailers (trailers-only response)
+            let trailers = stream.response.trailers.as_ref().or(response);
+            let status = trailers.and_then(|t| t.get("grpc-status"));
+            let code = status.and_then(|s| s.parse().ok());
+            println!(
+                "gRPC call {} stream={} status={}{}",
+                stream.path(),
+                id,
+                code.map_or("-", grpc::status_as_str),
+                trailers
+                    .and_then(|t| t.get("grpc-message"))
+                    .filter(|m| !m.is_empty())
+                    .map(|m| format!(" message=\"{}\"", m))
+                    .unwrap_or_default()
+            );
+        }
+        let exchange = Exchange {
+            flow: *flow,
+            method: request.get(":method").unwrap_or("-").to_string(),
+            uri: request.get(":path").unwrap_or("").to_string(),
+            version: "HTTP/2".to_string(),
+            host: request
+                .get(":authority")
+                .or(request.get("host"))
+                .map(str::to_string),
+            user_agent: request.get("user-agent").map(str::to_string),
+            request_headers: request.clone(),
+            request_body_len: stream.request.body_len,
+            status: response
+                .and_then(|h| h.get(":status"))
+                .and_then(|s| s.parse().ok()),
+            reason: None,
+            response_headers: response.cloned(),
+            content_type: header("content-type"),
+            content_encoding: header("content-encoding"),
+            response_body_len: stream.response.body_len,
+            response_content_len: content_len,
+        };
+        rules::on_http(&exchange);
+        ioc::on_http(&exchange);
+        event::emit(Event::Http(exchange));
+    }
+}
+
+impl StreamHandler for Connection {
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]) {
+        let side = self.side(from_client);
+        if side.broken {
+            return;
+        }
+        side.buf.extend_from_slice(data);
+        if side.preface {
+            if side.buf.len() < PREFACE.len() {
+                if !PREFACE.starts_with(&side.buf) {
+                    side.broken = true;
+                }
+                return;
+            }
+            if !side.buf.starts_with(PREFACE) {
+                side.broken = true;
+                return;
+            }
+            side.buf.drain(..PREFACE.len());
+            side.preface = false;
+            println!("HTTP/2 connection preface");
+        }
+        let mut used = 0;
+        loop {
+            let side = self.side(from_client);
+            if side.broken {
+                side.buf = Vec::new();
+                return;
+            }
+            if side.skip > 0 {
+                let n = side.skip.min(side.buf.len() - used);
+                used += n;
+                side.skip -= n;
+                if side.skip > 0 {
+                    break;
+                }
+            }
+            let h = match FrameHeader::parse(&side.buf[used..]) {
+                Some(h) => h,
+                None => break,
+            };
+            if h.length > side.max_frame.unwrap_or(DEFAULT_MAX_FRAME) {
+                // a header block cannot be skipped: the HPACK table would be out of sync
+                if matches!(
+                    h.frame_type,
+                    FRAME::HEADERS | FRAME::PUSH_PROMISE | FRAME::CONTINUATION
+                ) {
+                    expert::error(DecodeError::Malformed {
+                        layer: "http2",
+                        offset: 0,
+                        reason: "header block frame larger than SETTINGS_MAX_FRAME_SIZE, the rest of the direction is ignored",
+                    });
+                    side.broken = true;
+                    continue;
+                }
+                expert::error(DecodeError::Malformed {
+                    layer: "http2",
+                    offset: 0,
+                    reason: "frame larger than SETTINGS_MAX_FRAME_SIZE, skipped",
+                });
+                side.skip = FrameHeader::SIZE + h.length;
+                continue;
+            }
+            let start = used + FrameHeader::SIZE;
+            if side.buf.len() < start + h.length {
+                break;
+            }
+            let frame = side.buf[used..start + h.length].to_vec();
+            used = start + h.length;
+            self.on_frame(flow, from_client, h, &frame);
+        }
+        self.side(from_client).buf.drain(..used);
+    }
+
+    fn on_close(&mut self, flow: &FlowKey) {
+        let mut ids: Vec<u32> = self.streams.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids {
+            if let Some(stream) = self.streams.remove(&id) {
+                self.finish(flow, id, stream);
+            }
+        }
+    }
+}
+
+/// Stream handler for a connection starting with the HTTP/2 connection preface
+pub fn connection(from_client: bool, data: &[u8]) -> Option<Connection> {
+    let prefix = &data[..data.len().min(PREFACE.len())];
+    (from_client && prefix.len() >= 4 && PREFACE.starts_with(prefix)).then(Connection::new)
+}
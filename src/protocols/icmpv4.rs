@@ -1,28 +1,35 @@
-use crate::utils::cow_struct;
+use crate::analysis::rules;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be};
 use std::mem::size_of;
 
-/// Internet Controle Message Protocol
-///
-/// The ICMPHeader is used in the internet protocol to send error messages and operational information indicating success or failure    
-/// It is compose of 4 bytes that don't change, type, code, checksum and then 4 bytes depending on
-/// the type and code provided
-/// The strucute is the same for ipv4 and ipv6 but type and code differ
-/// * `type` type code of the control message
-/// * `code` code for the type  
-/// * `checksum` internet checksum for error handling
-/// Sources:
-/// * https://tools.ietf.org/html/rfc777
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    /// type of the control message
-    pub t: u8,
-    /// code of the controle message
-    pub code: u8,
-    /// internet checksum
-    pub checksum: u16,
-    /// rest of the header depending on type and code
-    pub rest: u32,
+use super::ip::IpProtocol;
+
+wire_struct! {
+    /// Internet Controle Message Protocol
+    ///
+    /// The ICMPHeader is used in the internet protocol to send error messages and operational information indicating success or failure
+    /// It is compose of 4 bytes that don't change, type, code, checksum and then 4 bytes depending on
+    /// the type and code provided
+    /// The strucute is the same for ipv4 and ipv6 but type and code differ
+    /// * `type` type code of the control message
+    /// * `code` code for the type
+    /// * `checksum` internet checksum for error handling
+    ///
+    /// Sources:
+    /// * https://tools.ietf.org/html/rfc777
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        /// type of the control message
+        pub t: u8,
+        /// code of the controle message
+        pub code: u8,
+        /// internet checksum
+        pub checksum: U16Be,
+        /// rest of the header depending on type and code
+        pub rest: U32Be,
+    }
 }
 
 impl Header {
@@ -88,15 +95,35 @@ fn icmp_v4_code_to_str(code: u8) -> &'static str {
 /// Usually called by the proto on top of it, like the ethernetHeader struct, that will
 /// once the ethernet type have been detected to be arp, it can then decode the arp header using this.
 pub fn display(h: &Header) -> String {
-    format!("ICMPv4 {}", icmp_v4_code_to_str(h.t.to_be()))
+    format!("ICMPv4 {}", icmp_v4_code_to_str(h.t))
 }
 
 pub fn decode(data: &[u8]) {
-    if data.len() >= Header::SIZE {
-        let (header_bytes, _data) = data.split_at(Header::SIZE);
-        match cow_struct::<Header>(header_bytes) {
-            Some(header) => println!("{}", display(&header)),
-            None => println!("Error::icmpv4 {:?}", "Truncated payload"),
+    match utils::split::<Header>(data) {
+        Some((header, payload)) => {
+            println!("{}", display(header));
+            tree::layer("icmpv4", &data[..Header::SIZE], display(header));
+            tree::field(
+                "Type",
+                &header.t,
+                format_args!("{} ({})", icmp_v4_code_to_str(header.t), header.t),
+            );
+            tree::field("Code", &header.code, header.code);
+            tree::field(
+                "Checksum",
+                &header.checksum,
+                format_args!("0x{:04x}", header.checksum.get()),
+            );
+            tree::field(
+                "Rest of header",
+                &header.rest,
+                format_args!("0x{:08x}", header.rest.get()),
+            );
+            rules::on_packet(IpProtocol::Icmp, payload, None);
         }
+        None => expert::error(DecodeError::Truncated {
+            layer: "icmpv4",
+            offset: 0,
+        }),
     }
 }
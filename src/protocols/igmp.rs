@@ -1,13 +1,16 @@
 // IGMPv2 minimal decoder
-use crate::utils::cow_struct;
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be};
+use std::net::Ipv4Addr;
 
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub typ: u8,
-    pub max_resp_time: u8,
-    pub checksum: u16,
-    pub group_addr: u32,
+wire_struct! {
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        pub typ: u8,
+        pub max_resp_time: u8,
+        pub checksum: U16Be,
+        pub group_addr: U32Be,
+    }
 }
 
 impl Header {
@@ -15,25 +18,34 @@ impl Header {
 }
 
 pub fn display(h: &Header) -> String {
-    let g = h.group_addr.to_be_bytes();
     format!(
-        "IGMP type=0x{:02x} mrt={}cs checksum=0x{:04x} group={}.{}.{}.{}",
+        "IGMP type=0x{:02x} mrt={}cs checksum=0x{:04x} group={}",
         h.typ,
         h.max_resp_time,
-        u16::from_be(h.checksum),
-        g[0],
-        g[1],
-        g[2],
-        g[3]
+        h.checksum.get(),
+        Ipv4Addr::from(h.group_addr.get())
     )
 }
 
 pub fn decode(data: &[u8]) {
-    if data.len() < Header::SIZE {
-        return;
-    }
-    let (hdr, _) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        println!("{}", display(&h));
+    if let Some(h) = utils::view::<Header>(data) {
+        println!("{}", display(h));
+        tree::layer("igmp", h, display(h));
+        tree::field("Type", &h.typ, format_args!("0x{:02x}", h.typ));
+        tree::field(
+            "Max response time",
+            &h.max_resp_time,
+            format_args!("{}cs", h.max_resp_time),
+        );
+        tree::field(
+            "Checksum",
+            &h.checksum,
+            format_args!("0x{:04x}", h.checksum.get()),
+        );
+        tree::field(
+            "Group address",
+            &h.group_addr,
+            Ipv4Addr::from(h.group_addr.get()),
+        );
     }
 }
@@ -1,4 +1,125 @@
 // IMAP line parser: print tag and command verb
+// `Session` follows the commands of the client to report the LOGIN passwords and those of
+// AUTHENTICATE PLAIN/LOGIN exchanges.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc9051#section-6.2
+// * https://www.rfc-editor.org/rfc/rfc4959 (SASL initial response)
+use super::detect::confidence;
+use crate::analysis::credentials::{self, Sasl};
+use crate::flow::FlowKey;
+use crate::reassembly::StreamHandler;
+use crate::tree;
+
+/// Longest command line accepted
+const MAX_LINE: usize = 4096;
+
+/// Atoms and quoted strings of a command line, literals are not followed
+fn arguments(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => continue,
+            '"' => {
+                let mut s = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => s.extend(chars.next()),
+                        '"' => break,
+                        _ => s.push(c),
+                    }
+                }
+                args.push(s);
+            }
+            _ => {
+                let mut s = String::from(c);
+                while let Some(c) = chars.next_if(|c| *c != ' ') {
+                    s.push(c);
+                }
+                args.push(s);
+            }
+        }
+    }
+    args
+}
+
+/// Follows the client side of an IMAP session
+#[derive(Default)]
+pub struct Session {
+    buf: Vec<u8>,
+    sasl: Sasl,
+}
+
+impl Session {
+    fn on_line(&mut self, flow: &FlowKey, line: &str) {
+        if self.sasl.on_line("imap", flow, line) {
+            return;
+        }
+        let args = arguments(line);
+        let command = args.get(1).map(|c| c.to_ascii_uppercase());
+        match (command.as_deref(), &args[..]) {
+            (Some("LOGIN"), [_, _, user, password, ..]) => {
+                credentials::on_credential("imap", "LOGIN", Some(flow), Some(user), password)
+            }
+            (Some("AUTHENTICATE"), [_, _, mechanism, rest @ ..]) => {
+                self.sasl
+                    .start("imap", flow, mechanism, rest.first().map(String::as_str))
+            }
+            _ => {}
+        }
+    }
+}
+
+impl StreamHandler for Session {
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]) {
+        if !from_client {
+            return;
+        }
+        self.buf.extend_from_slice(data);
+        let mut used = 0;
+        while let Some(eol) = self.buf[used..].iter().position(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(&self.buf[used..used + eol]);
+            let line = line.trim_end_matches('\r').to_string();
+            used += eol + 1;
+            self.on_line(flow, &line);
+        }
+        self.buf.drain(..used);
+        if self.buf.len() > MAX_LINE {
+            self.buf = Vec::new();
+        }
+    }
+}
+
+/// Stream handler of an IMAP session
+pub fn session(flow: &FlowKey) -> Option<Session> {
+    matches!(flow.dst_port, 143 | 993).then(Session::default)
+}
+
+/// Confidence that the payload is an IMAP greeting or tagged command
+pub fn probe(data: &[u8]) -> u8 {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = String::from_utf8_lossy(&line[..line.len().min(128)]).to_ascii_uppercase();
+    if line.starts_with("* OK") && line.contains("IMAP") {
+        return confidence::CERTAIN;
+    }
+    if line.starts_with("* OK ") || line.starts_with("* PREAUTH ") {
+        return confidence::HIGH;
+    }
+    let mut words = line.split_whitespace();
+    let tag = words.next().unwrap_or_default();
+    let command = words.next().unwrap_or_default();
+    let tagged = !tag.is_empty() && tag.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'.');
+    match command {
+        "CAPABILITY" | "LOGIN" | "AUTHENTICATE" | "STARTTLS" | "SELECT" | "EXAMINE" | "ID"
+            if tagged =>
+        {
+            confidence::HIGH
+        }
+        _ => confidence::NONE,
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if let Ok(s) = std::str::from_utf8(data) {
         if let Some(line) = s.lines().next() {
@@ -7,11 +128,20 @@ pub fn decode(data: &[u8]) {
             let cmd = it.next().unwrap_or("").to_uppercase();
             if !tag.is_empty() && !cmd.is_empty() {
                 println!("IMAP tag={} cmd={}", tag, cmd);
+                tree::layer("imap", data, format_args!("IMAP tag={} cmd={}", tag, cmd));
+                // the arguments are left out, a LOGIN carries the password
+                tree::field("Tag", tag.as_bytes(), tag);
+                if let Some(verb) = line.split_whitespace().nth(1) {
+                    tree::field("Command", verb.as_bytes(), &cmd);
+                }
                 return;
             }
             println!("IMAP: {}", line.trim());
+            tree::layer("imap", data, format_args!("IMAP: {}", line.trim()));
+            tree::field("Line", line.as_bytes(), line.trim());
             return;
         }
     }
     println!("IMAP ({}B)", data.len());
+    tree::layer("imap", data, format_args!("IMAP ({}B)", data.len()));
 }
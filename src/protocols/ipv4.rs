@@ -1,80 +1,217 @@
-use crate::utils::cow_struct;
+use crate::analysis::ioc;
+use crate::context;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{wire_struct, ByteReader, U16Be, U32Be};
 use std::mem::size_of;
+use std::net::Ipv4Addr;
 
 use super::esp;
 use super::icmpv4;
-use super::ip;
+use super::ip::IpProtocol;
 use super::tcp;
 
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    /// version of the ip protocol and the lenght of the header since it can be variable du to options
-    pub version_and_header_len: u8,
-    pub type_of_service: u8,
-    pub total_len: u16,
-    pub identification: u16,
-    pub fragment_offset: u16,
-    pub time_to_live: u8,
-    pub protocol: u8,
-    pub checksum: u16,
-    pub src: u32,
-    pub dst: u32,
+wire_struct! {
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        /// version of the ip protocol and the lenght of the header since it can be variable du to options
+        pub version_and_header_len: u8,
+        pub type_of_service: u8,
+        pub total_len: U16Be,
+        pub identification: U16Be,
+        pub fragment_offset: U16Be,
+        pub time_to_live: u8,
+        pub protocol: u8,
+        pub checksum: U16Be,
+        pub src: U32Be,
+        pub dst: U32Be,
+    }
 }
 
 impl Header {
     pub const SIZE: usize = size_of::<Self>();
+
+    pub fn version(&self) -> u8 {
+        self.version_and_header_len >> 4
+    }
+
+    /// Length of the header with its options, in bytes
+    pub fn header_len(&self) -> usize {
+        usize::from(self.version_and_header_len & 0x0F) * 4
+    }
+
+    pub fn src_addr(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.src.get())
+    }
+
+    pub fn dst_addr(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.dst.get())
+    }
 }
 
 pub fn display(h: &Header) -> String {
-    let src = h.src.to_be_bytes();
-    let dst = h.dst.to_be_bytes();
     format!(
-        "IPv4 {}.{}.{}.{} -> {}.{}.{}.{} proto={} ttl={} len={}",
-        src[0],
-        src[1],
-        src[2],
-        src[3],
-        dst[0],
-        dst[1],
-        dst[2],
-        dst[3],
-        super::ip::protocol_as_str(h.protocol),
+        "IPv4 {} -> {} proto={} ttl={} len={}",
+        h.src_addr(),
+        h.dst_addr(),
+        IpProtocol::from(h.protocol),
         h.time_to_live,
-        h.total_len.to_be()
+        h.total_len
     )
 }
 
+/// Internet checksum (RFC 1071) of a header with its options, skipping the checksum field
+fn checksum(header: &[u8]) -> u16 {
+    let mut sum = header
+        .chunks_exact(2)
+        .enumerate()
+        .filter(|(i, _)| *i != 5)
+        .map(|(_, w)| u32::from(u16::from_be_bytes([w[0], w[1]])))
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
 pub fn decode(data: &[u8]) {
-    if data.len() >= Header::SIZE {
-        let (header_bytes, _next_data) = data.split_at(Header::SIZE);
-        match cow_struct::<Header>(header_bytes) {
-            Some(header) => {
-                let version = (header.version_and_header_len & 0xF0) >> 4;
-                if version != 4 {
-                    println!("Invalid ip version: {:?}", version);
+    let mut reader = ByteReader::new(data);
+    let header = match reader.view::<Header>() {
+        Ok(header) => header,
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "ipv4",
+                offset: reader.position(),
+            });
+            return;
+        }
+    };
+    let version = header.version();
+    if version != 4 {
+        expert::error(DecodeError::UnsupportedVersion {
+            layer: "ipv4",
+            offset: 0,
+            version: version.into(),
+        });
+        return;
+    }
+    println!("{}", display(header));
+    if header.header_len() < Header::SIZE {
+        expert::error(DecodeError::Malformed {
+            layer: "ipv4",
+            offset: 0,
+            reason: "header length below 20 bytes",
+        });
+        return;
+    }
+    // ipv4 header with potential options included
+    if reader.skip(header.header_len() - Header::SIZE).is_err() {
+        expert::error(DecodeError::Truncated {
+            layer: "ipv4",
+            offset: reader.position(),
+        });
+        return;
+    }
+    // a zero checksum is left for the network card to compute
+    let (found, expected) = (
+        header.checksum.get(),
+        checksum(&data[..header.header_len()]),
+    );
+    if found != 0 && found != expected {
+        expert::error(DecodeError::BadChecksum {
+            layer: "ipv4",
+            offset: 10,
+            expected: expected.into(),
+            found: found.into(),
+        });
+    }
+    let fragment = header.fragment_offset.get();
+    tree::layer("ipv4", &data[..header.header_len()], display(header));
+    tree::field("Version", &header.version_and_header_len, version);
+    tree::field(
+        "Header length",
+        &header.version_and_header_len,
+        header.header_len(),
+    );
+    tree::field(
+        "Type of service",
+        &header.type_of_service,
+        format_args!("0x{:02x}", header.type_of_service),
+    );
+    tree::field("Total length", &header.total_len, header.total_len);
+    tree::field(
+        "Identification",
+        &header.identification,
+        format_args!("0x{:04x}", header.identification.get()),
+    );
+    tree::field(
+        "Flags",
+        &header.fragment_offset,
+        format_args!("0x{:x}", fragment >> 13),
+    );
+    tree::field(
+        "Fragment offset",
+        &header.fragment_offset,
+        (fragment & 0x1fff) * 8,
+    );
+    tree::field("Time to live", &header.time_to_live, header.time_to_live);
+    tree::field(
+        "Protocol",
+        &header.protocol,
+        format_args!(
+            "{} ({})",
+            IpProtocol::from(header.protocol),
+            header.protocol
+        ),
+    );
+    tree::field(
+        "Header checksum",
+        &header.checksum,
+        format_args!("0x{:04x}", found),
+    );
+    tree::field("Source address", &header.src, header.src_addr());
+    tree::field("Destination address", &header.dst, header.dst_addr());
+    if header.header_len() > Header::SIZE {
+        tree::field(
+            "Options",
+            &data[Header::SIZE..header.header_len()],
+            format_args!("{} bytes", header.header_len() - Header::SIZE),
+        );
+    }
+    if fragment & 0x3fff != 0 {
+        expert::note(
+            "ipv4",
+            6,
+            "fragment",
+            format!(
+                "fragment of datagram {} at offset {}{}",
+                header.identification,
+                (fragment & 0x1fff) * 8,
+                if fragment & 0x2000 != 0 {
+                    ", more follow"
                 } else {
-                    // ipv4 header with potential options included
-                    let len_bytes: usize = ((header.version_and_header_len & 0xF) * 32 / 8).into();
-                    let next_data = &data[len_bytes..];
-                    println!("{}", display(&header));
-                    match header.protocol {
-                        ip::PROTO::ESP => esp::decode(next_data),
-                        ip::PROTO::AH => super::ah::decode(next_data),
-                        ip::PROTO::ICMP => icmpv4::decode(next_data),
-                        ip::PROTO::IGMP => super::igmp::decode(next_data),
-                        ip::PROTO::TCP => tcp::decode(next_data),
-                        ip::PROTO::UDP => super::udp::decode(next_data),
-                        ip::PROTO::DCCP => super::dccp::decode(next_data),
-                        ip::PROTO::SCTP => super::sctp::decode(next_data),
-                        ip::PROTO::GRE => super::gre::decode(next_data),
-                        ip::PROTO::EIGRP => super::eigrp::decode(next_data),
-                        ip::PROTO::OSPFIGP => super::ospf::decode(next_data),
-                        _ => println!("protocol::ipv4 {:?}", ip::protocol_as_str(header.protocol)),
-                    }
+                    ""
                 }
-            }
-            None => println!("ip decode error: {:?}", "Truncated payload"),
-        }
+            ),
+        );
+    }
+    let next_data = reader.rest();
+    context::set_addrs(header.src_addr().into(), header.dst_addr().into());
+    if let Some((src, dst)) = context::addrs() {
+        ioc::on_addrs(IpProtocol::from(header.protocol), src, dst);
+    }
+    match IpProtocol::from(header.protocol) {
+        IpProtocol::Esp => esp::decode(next_data),
+        IpProtocol::Ah => super::ah::decode(next_data),
+        IpProtocol::Icmp => icmpv4::decode(next_data),
+        IpProtocol::Igmp => super::igmp::decode(next_data),
+        IpProtocol::Tcp => tcp::decode(next_data),
+        IpProtocol::Udp => super::udp::decode(next_data),
+        IpProtocol::Dccp => super::dccp::decode(next_data),
+        IpProtocol::Sctp => super::sctp::decode(next_data),
+        IpProtocol::Gre => super::gre::decode(next_data),
+        IpProtocol::Eigrp => super::eigrp::decode(next_data),
+        IpProtocol::Ospf => super::ospf::decode(next_data),
+        p => println!("protocol::ipv4 {}", p),
     }
 }
@@ -1,64 +1,82 @@
-use crate::utils::cow_struct;
+use crate::analysis::ioc;
+use crate::context;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, ByteReader, U16Be, U32Be};
 use std::mem::size_of;
+use std::net::Ipv6Addr;
 
 use super::ah;
 use super::esp;
-use super::ip;
+use super::icmpv6;
+use super::ip::IpProtocol;
+use super::tcp;
+use super::udp;
 
-/// IPV6 Header structure
-///
-/// The IPV6 Header structure define the field of an IPV6 message on network
-/// it have a fixed length of 40 bytes.
-/// * `version_traffic_class_flow_label` contains three field
-///   - version, constant value of 6 encoded on 4 bits
-///   - traffic_class, hold two values, 6 first bits for traffic classification called DS field and 2 bytes for explicit congestion notification use for network congestion
-///   - flow label, 20bits to labelise a numbers of packets between a source and a destination.
-/// * `payload_len`, lenght of the remaining payload, 0 for
-/// * `next_header`, specify the type of the next header usually the transport layer protocol
-/// * `hop_limit`, replace time to live field from ipv4, it's the limit of nnodes that the packet can be forwar.
-/// * `src`, protocol address of the source
-/// * `dst`, protocol address of the destination
-///
-/// Sources:
-/// * https://en.wikipedia.org/wiki/IPv6_packet#Fixed_header
-/// * https://en.wikipedia.org/wiki/Differentiated_services
-/// * https://tools.ietf.org/html/rfc6437
-/// * https://en.wikipedia.org/wiki/Explicit_Congestion_Notification
-
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    /// contains version traffic class and flow label
-    pub version_traffic_class_flow_label: u32,
-    /// length of the payload
-    pub payload_len: u16,
-    /// type of the next header
-    pub next_header: u8,
-    /// decremented at each node routing the packer
-    pub hop_limit: u8,
-    /// addresss of the source
-    pub src: [u32; 4],
-    /// address of the destination
-    pub dst: [u32; 4],
+wire_struct! {
+    /// IPV6 Header structure
+    ///
+    /// The IPV6 Header structure define the field of an IPV6 message on network
+    /// it have a fixed length of 40 bytes.
+    /// * `version_traffic_class_flow_label` contains three field
+    ///   - version, constant value of 6 encoded on 4 bits
+    ///   - traffic_class, hold two values, 6 first bits for traffic classification called DS field and 2 bytes for explicit congestion notification use for network congestion
+    ///   - flow label, 20bits to labelise a numbers of packets between a source and a destination.
+    /// * `payload_len`, lenght of the remaining payload, 0 for
+    /// * `next_header`, specify the type of the next header usually the transport layer protocol
+    /// * `hop_limit`, replace time to live field from ipv4, it's the limit of nnodes that the packet can be forwar.
+    /// * `src`, protocol address of the source
+    /// * `dst`, protocol address of the destination
+    ///
+    /// Sources:
+    /// * https://en.wikipedia.org/wiki/IPv6_packet#Fixed_header
+    /// * https://en.wikipedia.org/wiki/Differentiated_services
+    /// * https://tools.ietf.org/html/rfc6437
+    /// * https://en.wikipedia.org/wiki/Explicit_Congestion_Notification
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        /// contains version traffic class and flow label
+        pub version_traffic_class_flow_label: U32Be,
+        /// length of the payload
+        pub payload_len: U16Be,
+        /// type of the next header
+        pub next_header: u8,
+        /// decremented at each node routing the packer
+        pub hop_limit: u8,
+        /// addresss of the source
+        pub src: [u8; 16],
+        /// address of the destination
+        pub dst: [u8; 16],
+    }
 }
 
 impl Header {
     pub const SIZE: usize = size_of::<Self>();
-}
 
-fn ipv6_to_str(words: [u32; 4]) -> String {
-    let b0 = words[0].to_be_bytes();
-    let b1 = words[1].to_be_bytes();
-    let b2 = words[2].to_be_bytes();
-    let b3 = words[3].to_be_bytes();
-    let bytes = [
-        b0[0], b0[1], b0[2], b0[3], b1[0], b1[1], b1[2], b1[3], b2[0], b2[1], b2[2], b2[3], b3[0],
-        b3[1], b3[2], b3[3],
-    ];
-    let mut parts = [0u16; 8];
-    for i in 0..8 {
-        parts[i] = u16::from_be_bytes([bytes[2 * i], bytes[2 * i + 1]]);
+    pub fn version(&self) -> u8 {
+        (self.version_traffic_class_flow_label.get() >> 28) as u8
+    }
+
+    pub fn traffic_class(&self) -> u8 {
+        (self.version_traffic_class_flow_label.get() >> 20) as u8
     }
+
+    pub fn flow_label(&self) -> u32 {
+        self.version_traffic_class_flow_label.get() & 0x000F_FFFF
+    }
+
+    pub fn src_addr(&self) -> Ipv6Addr {
+        Ipv6Addr::from(self.src)
+    }
+
+    pub fn dst_addr(&self) -> Ipv6Addr {
+        Ipv6Addr::from(self.dst)
+    }
+}
+
+/// Address with each group written in full, without `::` compression
+fn ipv6_to_str(addr: Ipv6Addr) -> String {
+    let parts = addr.segments();
     format!(
         "{:x}:{:x}:{:x}:{:x}:{:x}:{:x}:{:x}:{:x}",
         parts[0], parts[1], parts[2], parts[3], parts[4], parts[5], parts[6], parts[7]
@@ -66,17 +84,70 @@ fn ipv6_to_str(words: [u32; 4]) -> String {
 }
 
 pub fn display(h: &Header) -> String {
-    let src = ipv6_to_str(h.src);
-    let dst = ipv6_to_str(h.dst);
     format!(
         "IPv6 src={} dst={} next={} hop_limit={}",
-        src,
-        dst,
-        super::ip::protocol_as_str(h.next_header),
+        ipv6_to_str(h.src_addr()),
+        ipv6_to_str(h.dst_addr()),
+        IpProtocol::from(h.next_header),
         h.hop_limit
     )
 }
 
+/// Extension header: its protocol and its bytes
+type Extension<'a> = (IpProtocol, &'a [u8]);
+
+/// Extension headers after the fixed header (RFC 8200 4), and the protocol and bytes
+/// after them
+///
+/// Each extension header is given with its protocol and bytes. Stops at the first fragment
+/// that is not the first one of its datagram, since the rest of it is not a header.
+fn extensions(mut next: IpProtocol, data: &[u8]) -> (Vec<Extension<'_>>, IpProtocol, &[u8]) {
+    let mut headers = Vec::new();
+    let mut r = ByteReader::new(data);
+    loop {
+        let start = r.position();
+        let len = match next {
+            IpProtocol::Hopopt | IpProtocol::Ipv6Route | IpProtocol::Ipv6Opts => {
+                match r.rest().get(1) {
+                    Some(&len) => (usize::from(len) + 1) * 8,
+                    None => break,
+                }
+            }
+            IpProtocol::Ipv6Frag => 8,
+            _ => break,
+        };
+        let header = match r.bytes(len) {
+            Ok(header) => header,
+            Err(_) => break,
+        };
+        headers.push((next, header));
+        let last = next;
+        next = IpProtocol::from(header[0]);
+        if last == IpProtocol::Ipv6Frag {
+            let fragment = u16::from_be_bytes([header[2], header[3]]);
+            expert::note(
+                "ipv6",
+                Header::SIZE + start,
+                "fragment",
+                format!(
+                    "fragment of datagram {} at offset {}{}",
+                    u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+                    fragment & 0xfff8,
+                    if fragment & 1 != 0 {
+                        ", more follow"
+                    } else {
+                        ""
+                    }
+                ),
+            );
+            if fragment & 0xfff8 != 0 {
+                return (headers, IpProtocol::Ipv6NoNxt, r.rest());
+            }
+        }
+    }
+    (headers, next, r.rest())
+}
+
 /// Decode an ipv6 header packet for a given &[u8]
 ///
 /// Will cast the given &[u8] into an ipv6 header struct allowing to interact with it.
@@ -87,37 +158,81 @@ pub fn display(h: &Header) -> String {
 /// # Examples:
 ///
 ///```
-/// let (header_bytes, next_data) = data.split_at(EthernetHeader::SIZE);
-/// match cow_struct::<EthernetHeader>(header_bytes) {
-///     Some(header) => {
-///         let t = header.ether_type.to_be();
-///         match t {
-///             PROTO::IPV6 => ipv6::decode(next_data),
-///             _ => println!("ether type: {:?}", ether_type_as_str(t)),
-///         }
-///     None => println!("Error::ethernet {:?}", "Truncated payload"),
+/// if let Some((header, next_data)) = utils::split::<EthernetHeader>(data) {
+///     match EtherType::from(header.ether_type.get()) {
+///         EtherType::Ipv6 => ipv6::decode(next_data),
+///         t => println!("ether type: {}", t),
+///     }
 /// }
 ///```
 pub fn decode(data: &[u8]) {
-    if data.len() >= Header::SIZE {
-        let (slice, next_data) = data.split_at(Header::SIZE);
-        match cow_struct::<Header>(slice) {
-            Some(header) => {
-                let version = (header.version_traffic_class_flow_label & 0xF0) >> 4;
-                if version != 6 {
-                    println!("Invalid ipv6 version: {:?}", version);
-                } else {
-                    match header.next_header {
-                        ip::PROTO::AH => ah::decode(next_data),
-                        ip::PROTO::ESP => esp::decode(next_data),
-                        _ => println!(
-                            "protocol::ipv6 {:?}",
-                            ip::protocol_as_str(header.next_header)
-                        ),
-                    }
+    match utils::split::<Header>(data) {
+        Some((header, next_data)) => {
+            let version = header.version();
+            if version != 6 {
+                expert::error(DecodeError::UnsupportedVersion {
+                    layer: "ipv6",
+                    offset: 0,
+                    version: version.into(),
+                });
+            } else {
+                println!("{}", display(header));
+                let (src, dst) = (header.src_addr().into(), header.dst_addr().into());
+                context::set_addrs(src, dst);
+                let next_header = IpProtocol::from(header.next_header);
+                let (headers, protocol, next_data) = extensions(next_header, next_data);
+                tree::layer(
+                    "ipv6",
+                    &data[..data.len() - next_data.len()],
+                    display(header),
+                );
+                let first = &header.version_traffic_class_flow_label;
+                tree::field("Version", first, version);
+                tree::field(
+                    "Traffic class",
+                    first,
+                    format_args!("0x{:02x}", header.traffic_class()),
+                );
+                tree::field(
+                    "Flow label",
+                    first,
+                    format_args!("0x{:05x}", header.flow_label()),
+                );
+                tree::field("Payload length", &header.payload_len, header.payload_len);
+                tree::field(
+                    "Next header",
+                    &header.next_header,
+                    format_args!("{} ({})", next_header, header.next_header),
+                );
+                tree::field("Hop limit", &header.hop_limit, header.hop_limit);
+                tree::field("Source address", &header.src, header.src_addr());
+                tree::field("Destination address", &header.dst, header.dst_addr());
+                for (kind, bytes) in headers {
+                    tree::field(
+                        "Extension header",
+                        bytes,
+                        format_args!("{}, next {}", kind, IpProtocol::from(bytes[0])),
+                    );
+                }
+                ioc::on_addrs(protocol, src, dst);
+                match protocol {
+                    IpProtocol::Ah => ah::decode(next_data),
+                    IpProtocol::Esp => esp::decode(next_data),
+                    IpProtocol::Ipv6Icmp => icmpv6::decode(next_data),
+                    IpProtocol::Tcp => tcp::decode(next_data),
+                    IpProtocol::Udp => udp::decode(next_data),
+                    IpProtocol::Dccp => super::dccp::decode(next_data),
+                    IpProtocol::Sctp => super::sctp::decode(next_data),
+                    IpProtocol::Gre => super::gre::decode(next_data),
+                    IpProtocol::Eigrp => super::eigrp::decode(next_data),
+                    IpProtocol::Ospf => super::ospf::decode(next_data),
+                    p => println!("protocol::ipv6 {}", p),
                 }
             }
-            None => println!("ip decode error: {:?}", "Truncated payload"),
         }
+        None => expert::error(DecodeError::Truncated {
+            layer: "ipv6",
+            offset: 0,
+        }),
     }
 }
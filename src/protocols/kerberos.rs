@@ -1,25 +1,125 @@
 // Minimal Kerberos decoder (very coarse ASN.1/BER peek)
-// Kerberos uses ASN.1 DER; we just detect SEQUENCE (0x30) and print length
+// Kerberos uses ASN.1 DER; the application tag of the message gives its type
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc4120#section-5.10
+use super::detect::confidence;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
+fn msg_type_as_str(tag: u8) -> &'static str {
+    match tag {
+        0x6a => "AS-REQ",
+        0x6b => "AS-REP",
+        0x6c => "TGS-REQ",
+        0x6d => "TGS-REP",
+        0x6e => "AP-REQ",
+        0x6f => "AP-REP",
+        0x74 => "KRB-SAFE",
+        0x75 => "KRB-PRIV",
+        0x76 => "KRB-CRED",
+        0x7e => "KRB-ERROR",
+        _ => "UNKNOW",
+    }
+}
+
+/// Name of a field of a message by its context tag; requests of the KDC number theirs
+/// from 1
+fn field_as_str(msg: u8, tag: u8) -> &'static str {
+    let n = match msg {
+        0x6a | 0x6c => tag.wrapping_sub(0xa1),
+        _ => tag.wrapping_sub(0xa0),
+    };
+    match n {
+        0 => "pvno",
+        1 => "msg-type",
+        _ => "Element",
+    }
+}
+
+/// Add the elements of the SEQUENCE inside the application tag to the tree
+fn fields(msg: u8, seq: &[u8]) {
+    let mut r = ByteReader::new(seq);
+    while let Ok((tag, value)) = r.ber() {
+        let name = field_as_str(msg, tag);
+        // pvno and msg-type are small INTEGERs
+        match (name, ByteReader::new(value).ber()) {
+            ("pvno" | "msg-type", Ok((0x02, [n]))) => tree::field(name, value, n),
+            _ => tree::field(
+                name,
+                value,
+                format_args!("[{}] {} bytes", tag & 0x1f, value.len()),
+            ),
+        }
+    }
+}
+
+/// The message without the 4 byte record mark it has over TCP
+fn strip_record_mark(data: &[u8]) -> &[u8] {
+    match data.get(..4) {
+        Some(mark)
+            if u32::from_be_bytes([mark[0], mark[1], mark[2], mark[3]]) as usize + 4
+                == data.len() =>
+        {
+            &data[4..]
+        }
+        _ => data,
+    }
+}
+
+/// Confidence that the payload is a Kerberos message (RFC 4120), with the record mark
+/// of TCP or not
+pub fn probe(data: &[u8]) -> u8 {
+    let data = strip_record_mark(data);
+    // AS-REQ, AS-REP, TGS-REQ, TGS-REP, AP-REQ, AP-REP, KRB-ERROR
+    if !matches!(data.first(), Some(0x6a..=0x6f | 0x7e)) {
+        return confidence::NONE;
+    }
+    let len = match data.get(1) {
+        Some(&l) if l & 0x80 == 0 => 1,
+        Some(&l) => 1 + (l & 0x7f) as usize,
+        None => return confidence::NONE,
+    };
+    match data.get(1 + len) {
+        Some(0x30) => confidence::HIGH,
+        _ => confidence::NONE,
+    }
+}
 
 pub fn decode(data: &[u8]) {
-    if data.len() >= 2 && data[0] == 0x30 {
-        // DER length: short form if bit7=0
-        let len = if data[1] & 0x80 == 0 {
-            data[1] as usize
-        } else {
-            let n = (data[1] & 0x7F) as usize;
-            if 2 + n <= data.len() {
-                let mut v: usize = 0;
-                for i in 0..n {
-                    v = (v << 8) | data[2 + i] as usize;
-                }
-                v
-            } else {
-                0
+    let msg = strip_record_mark(data);
+    let mut r = ByteReader::new(msg);
+    let (tag, body) = match r.ber() {
+        Ok((tag, body)) => (tag, body),
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "kerberos",
+                offset: msg.len(),
+            });
+            return;
+        }
+    };
+    match msg_type_as_str(tag) {
+        "UNKNOW" => expert::error(DecodeError::Malformed {
+            layer: "kerberos",
+            offset: 0,
+            reason: "not a Kerberos message tag",
+        }),
+        name => {
+            println!("Kerberos {} ({}B)", name, msg.len());
+            tree::layer("kerberos", data, format_args!("Kerberos {}", name));
+            if msg.len() < data.len() {
+                tree::field("Record mark", &data[..4], msg.len());
+            }
+            tree::field(
+                "Message type",
+                &msg[..1],
+                format_args!("{} (0x{:02x})", name, tag),
+            );
+            if let Ok((0x30, seq)) = ByteReader::new(body).ber() {
+                fields(tag, seq);
             }
-        };
-        println!("Kerberos ASN.1 SEQUENCE len~{} ({}B)", len, data.len());
-        return;
+        }
     }
-    println!("Kerberos ({}B)", data.len());
 }
@@ -1,69 +1,181 @@
 // LDAP BER peek: detect BindRequest and version/name
-fn ber_len(bytes: &[u8], i: &mut usize) -> Option<usize> {
-    if *i >= bytes.len() {
-        return None;
+// The password of a simple bind is sent in the clear and reported as a credential.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc4511#section-4.2
+use super::detect::confidence;
+use crate::analysis::credentials;
+use crate::context;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
+/// Context specific tag of the simple authentication of a BindRequest
+const AUTH_SIMPLE: u8 = 0x80;
+
+fn op_as_str(tag: u8) -> &'static str {
+    match tag {
+        0x60 => "bindRequest",
+        0x61 => "bindResponse",
+        0x42 => "unbindRequest",
+        0x63 => "searchRequest",
+        0x64 => "searchResEntry",
+        0x65 => "searchResDone",
+        0x73 => "searchResRef",
+        0x66 => "modifyRequest",
+        0x67 => "modifyResponse",
+        0x68 => "addRequest",
+        0x69 => "addResponse",
+        0x4a => "delRequest",
+        0x6b => "delResponse",
+        0x6c => "modDNRequest",
+        0x6d => "modDNResponse",
+        0x6e => "compareRequest",
+        0x6f => "compareResponse",
+        0x50 => "abandonRequest",
+        0x77 => "extendedReq",
+        0x78 => "extendedResp",
+        0x79 => "intermediateResponse",
+        _ => "UNKNOW",
     }
-    let b = bytes[*i];
-    *i += 1;
-    if b & 0x80 == 0 {
-        return Some(b as usize);
+}
+
+struct BindRequest<'a> {
+    version: u8,
+    name: &'a [u8],
+    /// simple authentication password
+    password: Option<&'a [u8]>,
+}
+
+fn bind_request<'a>(r: &mut ByteReader<'a>) -> Option<BindRequest<'a>> {
+    let version = match r.ber().ok()? {
+        (0x02, value) => value.first().copied().unwrap_or(0),
+        _ => return None,
+    };
+    let name = match r.ber().ok()? {
+        (0x04, value) => value,
+        _ => return None,
+    };
+    let password = match r.ber() {
+        Ok((AUTH_SIMPLE, value)) => Some(value),
+        _ => None,
+    };
+    Some(BindRequest {
+        version,
+        name,
+        password,
+    })
+}
+
+/// Confidence that the payload is an LDAPMessage: a SEQUENCE with a message id and an
+/// application tagged operation
+pub fn probe(data: &[u8]) -> u8 {
+    let mut r = ByteReader::new(data);
+    if !r.eat(0x30) || r.ber_len().is_err() || !r.eat(0x02) {
+        return confidence::NONE;
     }
-    let n = (b & 0x7F) as usize;
-    if n == 0 || n > 4 || *i + n > bytes.len() {
-        return None;
+    match r.ber_len() {
+        Ok(n @ 1..=4) if r.skip(n).is_ok() => {}
+        _ => return confidence::NONE,
     }
-    let mut v = 0usize;
-    for _ in 0..n {
-        v = (v << 8) | (bytes[*i] as usize);
-        *i += 1;
+    match r.peek() {
+        // constructed operations, and the primitive UnbindRequest and DelRequest
+        Some(0x60..=0x79) | Some(0x42) | Some(0x4a) => confidence::HIGH,
+        _ => confidence::NONE,
     }
-    Some(v)
 }
 
 pub fn decode(data: &[u8]) {
-    let mut i = 0usize;
-    if i >= data.len() || data[i] != 0x30 {
-        println!("LDAP ({}B)", data.len());
+    let mut r = ByteReader::new(data);
+    if !r.eat(0x30) {
+        expert::error(DecodeError::Malformed {
+            layer: "ldap",
+            offset: 0,
+            reason: "LDAPMessage is not a SEQUENCE",
+        });
         return;
     }
-    i += 1;
-    let _ = ber_len(data, &mut i).unwrap_or(0);
-    // messageID
-    if i >= data.len() || data[i] != 0x02 {
-        println!("LDAP (no msgid)");
+    if r.ber_len().is_err() {
+        expert::error(DecodeError::Malformed {
+            layer: "ldap",
+            offset: r.position(),
+            reason: "invalid message length",
+        });
         return;
     }
-    i += 1;
-    let _ = ber_len(data, &mut i).unwrap_or(0);
-    i += 1; // skip minimal id
-            // protocolOp
-    if i >= data.len() {
-        println!("LDAP");
+    // messageID
+    if r.peek() != Some(0x02) {
+        expert::error(DecodeError::Malformed {
+            layer: "ldap",
+            offset: r.position(),
+            reason: "no messageID",
+        });
         return;
     }
-    let tag = data[i];
-    i += 1;
-    let _op_len = ber_len(data, &mut i).unwrap_or(0);
+    // protocolOp
+    let id_start = r.position();
+    let (id, tag) = match r.ber().and_then(|(_, id)| Ok((id, r.u8()?))) {
+        Ok((id, tag)) => (id, tag),
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "ldap",
+                offset: r.position(),
+            });
+            return;
+        }
+    };
+    let op_start = r.position() - 1;
+    let _op_len = r.ber_len();
+    tree::layer("ldap", data, format_args!("LDAP {}", op_as_str(tag)));
+    tree::field(
+        "Message ID",
+        &data[id_start..op_start],
+        id.iter().fold(0u64, |n, b| n << 8 | u64::from(*b)),
+    );
+    tree::field(
+        "Operation",
+        &data[op_start..op_start + 1],
+        format_args!("{} (0x{:02x})", op_as_str(tag), tag),
+    );
     if tag == 0x60 {
-        // BindRequest
-        // version INTEGER
-        if i < data.len() && data[i] == 0x02 {
-            i += 1;
-            let _ = ber_len(data, &mut i);
-            let ver = data.get(i).copied().unwrap_or(0);
-            i += 1;
-            // name OCTET STRING
-            if i < data.len() && data[i] == 0x04 {
-                i += 1;
-                if let Some(nl) = ber_len(data, &mut i) {
-                    if i + nl <= data.len() {
-                        let name = std::str::from_utf8(&data[i..i + nl]).unwrap_or("");
-                        println!("LDAP BindRequest v{} name={}", ver, name);
-                        return;
-                    }
+        let start = r.position();
+        match bind_request(&mut r) {
+            Some(bind) => {
+                let name = std::str::from_utf8(bind.name).unwrap_or("");
+                println!("LDAP BindRequest v{} name={}", bind.version, name);
+                let mut version = ByteReader::new(&data[start..]);
+                let _ = version.ber();
+                tree::field(
+                    "Version",
+                    &data[start..start + version.position()],
+                    bind.version,
+                );
+                tree::field("Name", bind.name, name);
+                if let Some(password) = bind.password {
+                    tree::field(
+                        "Simple authentication",
+                        password,
+                        credentials::mask(&String::from_utf8_lossy(password)),
+                    );
+                }
+                // an empty password is an anonymous or unauthenticated bind
+                if let Some(password) = bind.password.filter(|p| !p.is_empty()) {
+                    credentials::on_credential(
+                        "ldap",
+                        "simple bind",
+                        context::flow().as_ref(),
+                        Some(name),
+                        &String::from_utf8_lossy(password),
+                    );
                 }
             }
+            None => expert::error(DecodeError::Malformed {
+                layer: "ldap",
+                offset: start,
+                reason: "BindRequest without version and name",
+            }),
         }
+        return;
     }
     println!("LDAP op=0x{:02x}", tag);
 }
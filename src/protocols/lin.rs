@@ -1,5 +1,7 @@
 // Minimal LIN decoder
 // Print frame id (6-bit), parity bits, and data length guess
+use crate::tree;
+
 pub fn decode(data: &[u8]) {
     if data.is_empty() {
         println!("LIN (empty)");
@@ -9,11 +11,14 @@ pub fn decode(data: &[u8]) {
     let id = pid & 0x3F;
     let p = (pid & 0xC0) >> 6;
     let dlen = data.len().saturating_sub(1).min(8);
-    println!(
+    let payload = &data[1..1 + dlen];
+    let line = format!(
         "LIN id=0x{:02x} parity={} len={} data={:02x?}",
-        id,
-        p,
-        dlen,
-        &data[1..1 + dlen]
+        id, p, dlen, payload
     );
+    println!("{}", line);
+    tree::layer("lin", &data[..1 + dlen], line);
+    tree::field("Identifier", &data[..1], format_args!("0x{:02x}", id));
+    tree::field("Parity", &data[..1], p);
+    tree::field("Data", payload, format_args!("{:02x?}", payload));
 }
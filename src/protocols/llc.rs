@@ -1,23 +1,52 @@
 // IEEE 802.2 LLC and SNAP
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
 pub fn decode(data: &[u8]) {
-    if data.len() < 3 {
-        return;
-    }
-    let dsap = data[0];
-    let ssap = data[1];
-    let ctrl = data[2];
-    if dsap == 0xAA && ssap == 0xAA && ctrl == 0x03 {
-        // SNAP header: OUI(3) + Proto ID(2)
-        if data.len() < 8 {
+    let mut r = ByteReader::new(data);
+    let (dsap, ssap, ctrl) = match (r.u8(), r.u8(), r.u8()) {
+        (Ok(dsap), Ok(ssap), Ok(ctrl)) => (dsap, ssap, ctrl),
+        _ => {
+            expert::error(DecodeError::Truncated {
+                layer: "llc",
+                offset: 0,
+            });
             return;
         }
-        let oui = [data[3], data[4], data[5]];
-        let pid = u16::from_be_bytes([data[6], data[7]]);
+    };
+    let snap = dsap == 0xAA && ssap == 0xAA && ctrl == 0x03;
+    let line = format!(
+        "LLC DSAP=0x{:02x} SSAP=0x{:02x} CTRL=0x{:02x}",
+        dsap, ssap, ctrl
+    );
+    tree::layer("llc", &data[..if snap { 8 } else { 3 }], &line);
+    tree::field("DSAP", &data[..1], format_args!("0x{:02x}", dsap));
+    tree::field("SSAP", &data[1..2], format_args!("0x{:02x}", ssap));
+    tree::field("Control", &data[2..3], format_args!("0x{:02x}", ctrl));
+    if snap {
+        // SNAP header: OUI(3) + Proto ID(2)
+        let (oui, pid) = match (r.array::<3>(), r.u16()) {
+            (Ok(oui), Ok(pid)) => (oui, pid),
+            _ => {
+                expert::error(DecodeError::Truncated {
+                    layer: "llc",
+                    offset: 3,
+                });
+                return;
+            }
+        };
         println!(
             "LLC SNAP OUI={:02x}:{:02x}:{:02x} PID=0x{:04x}",
             oui[0], oui[1], oui[2], pid
         );
-        let payload = &data[8..];
+        tree::field(
+            "Organization code",
+            &data[3..6],
+            format_args!("{:02x}:{:02x}:{:02x}", oui[0], oui[1], oui[2]),
+        );
+        tree::field("Protocol ID", &data[6..8], format_args!("0x{:04x}", pid));
+        let payload = r.rest();
         // CDP: Cisco OUI 00:00:0C, PID 0x2000
         if oui == [0x00, 0x00, 0x0C] && pid == 0x2000 {
             return super::cdp::decode(payload);
@@ -27,10 +56,7 @@ pub fn decode(data: &[u8]) {
     }
     // STP (Spanning Tree) uses DSAP/SSAP 0x42, ctrl 0x03
     if dsap == 0x42 && ssap == 0x42 {
-        return super::stp::decode(&data[3..]);
+        return super::stp::decode(r.rest());
     }
-    println!(
-        "LLC DSAP=0x{:02x} SSAP=0x{:02x} CTRL=0x{:02x}",
-        dsap, ssap, ctrl
-    );
+    println!("{}", line);
 }
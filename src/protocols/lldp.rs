@@ -1,10 +1,13 @@
-use crate::utils::cow_struct;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{wire_struct, ByteReader, U16Be};
 
 // LLDP TLV header: 2 bytes => T(7 bits) | L(9 bits)
-#[derive(Clone, Copy)]
-#[repr(C, packed)]
-pub struct TlvHdr {
-    pub t: u16, // raw
+wire_struct! {
+    #[derive(Clone, Copy)]
+    pub struct TlvHdr {
+        pub t: U16Be, // raw
+    }
 }
 
 fn tlv_type_len(v: u16) -> (u16, u16) {
@@ -13,16 +16,70 @@ fn tlv_type_len(v: u16) -> (u16, u16) {
     (t, l)
 }
 
-pub fn decode(mut data: &[u8]) {
+fn tlv_name(t: u16) -> &'static str {
+    match t {
+        0 => "End of LLDPDU",
+        1 => "Chassis ID",
+        2 => "Port ID",
+        3 => "Time to live",
+        4 => "Port description",
+        5 => "System name",
+        6 => "System description",
+        7 => "System capabilities",
+        8 => "Management address",
+        127 => "Organization specific",
+        _ => "TLV",
+    }
+}
+
+/// Value of a TLV as text: the strings as they are, the TTL in seconds, the rest in hex
+fn tlv_value(t: u16, val: &[u8]) -> String {
+    match t {
+        4..=6 => String::from_utf8_lossy(val).into_owned(),
+        3 => match ByteReader::new(val).u16() {
+            Ok(ttl) => format!("{}s", ttl),
+            Err(_) => String::new(),
+        },
+        _ => tree::hex(val),
+    }
+}
+
+pub fn decode(data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    tree::layer("lldp", data, "Link Layer Discovery Protocol");
     // No fixed header; iterate TLVs
-    while data.len() >= 2 {
-        let (h, rest) = data.split_at(2);
-        let raw = u16::from_be_bytes([h[0], h[1]]);
-        let (t, l) = tlv_type_len(raw);
-        if rest.len() < l as usize {
-            break;
-        }
-        let (val, next) = rest.split_at(l as usize);
+    let mut start = r.position();
+    while !r.is_empty() {
+        let (h, val) = match r.view::<TlvHdr>() {
+            Ok(h) => match r.bytes(usize::from(tlv_type_len(h.t.get()).1)) {
+                Ok(val) => (h, val),
+                Err(_) => {
+                    expert::error(DecodeError::Truncated {
+                        layer: "lldp",
+                        offset: r.position(),
+                    });
+                    break;
+                }
+            },
+            Err(_) => {
+                expert::error(DecodeError::Truncated {
+                    layer: "lldp",
+                    offset: start,
+                });
+                break;
+            }
+        };
+        let (t, l) = tlv_type_len(h.t.get());
+        tree::open(
+            tlv_name(t),
+            &data[start..r.position()],
+            format_args!("{} bytes", l),
+        );
+        tree::field("Type", h, t);
+        tree::field("Length", h, l);
+        tree::field("Value", val, tlv_value(t, val));
+        tree::close();
+        start = r.position();
         match t {
             0 => {
                 // End of LLDPDU
@@ -39,17 +96,15 @@ pub fn decode(mut data: &[u8]) {
             }
             3 => {
                 // TTL
-                if val.len() >= 2 {
-                    let ttl = u16::from_be_bytes([val[0], val[1]]);
+                if let Ok(ttl) = ByteReader::new(val).u16() {
                     println!("LLDP TTL {}s", ttl);
                 }
             }
-            5 => println!("LLDP System Description ({}B)", l),
-            6 => println!("LLDP System Name ({}B)", l),
+            5 => println!("LLDP System Name ({}B)", l),
+            6 => println!("LLDP System Description ({}B)", l),
             7 => println!("LLDP System Capabilities ({}B)", l),
             8 => println!("LLDP Management Address ({}B)", l),
             _ => println!("LLDP TLV type={} len={}", t, l),
         }
-        data = next;
     }
 }
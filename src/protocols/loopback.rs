@@ -1,6 +1,7 @@
 // Minimal Loopback/Null decoder
 // BSD NULL/LOOP: 4-byte AF family (host-endian), then payload
 use crate::protocols::{ipv4, ipv6};
+use crate::tree;
 
 pub fn decode(data: &[u8]) {
     if data.len() < 4 {
@@ -9,6 +10,12 @@ pub fn decode(data: &[u8]) {
     }
     let fam = u32::from_ne_bytes([data[0], data[1], data[2], data[3]]);
     let payload = &data[4..];
+    tree::layer(
+        "loopback",
+        &data[..4],
+        format_args!("Null/Loopback family={}", fam),
+    );
+    tree::field("Family", &data[..4], fam);
     match fam {
         2 => {
             // AF_INET
@@ -9,26 +9,109 @@
 //  8-11: total body length
 //  12-15: opaque
 //  16-23: CAS
+use super::detect::confidence;
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be, U64Be};
+use std::mem::size_of;
+
+wire_struct! {
+    /// Header of the binary protocol
+    #[derive(Debug, Clone, Copy)]
+    pub struct Header {
+        pub magic: u8,
+        pub opcode: u8,
+        pub key_len: U16Be,
+        pub extras_len: u8,
+        pub data_type: u8,
+        pub status: U16Be,
+        pub body_len: U32Be,
+        pub opaque: U32Be,
+        pub cas: U64Be,
+    }
+}
+
+/// Header of a binary request or response
+fn binary_header(data: &[u8]) -> Option<&Header> {
+    utils::view::<Header>(data).filter(|h| h.magic == 0x80 || h.magic == 0x81)
+}
+
+/// Confidence that the payload is a Memcached command or reply, text or binary
+pub fn probe(data: &[u8]) -> u8 {
+    if let Some(h) = binary_header(data) {
+        let body = h.body_len.get() as usize;
+        let keylen = usize::from(h.key_len.get());
+        let extlen = usize::from(h.extras_len);
+        return if keylen + extlen > body || h.data_type != 0 {
+            confidence::NONE
+        } else if data.len() - size_of::<Header>() == body {
+            confidence::HIGH
+        } else {
+            confidence::LOW
+        };
+    }
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    if !line.ends_with(b"\r") {
+        return confidence::NONE;
+    }
+    let word = line
+        .split(|&b| b == b' ' || b == b'\r')
+        .next()
+        .unwrap_or_default();
+    match word {
+        b"VALUE" | b"STORED" | b"NOT_STORED" | b"EXISTS" | b"NOT_FOUND" => confidence::HIGH,
+        b"get" | b"gets" | b"set" | b"add" | b"replace" | b"append" | b"prepend" | b"cas"
+        | b"delete" | b"incr" | b"decr" | b"touch" | b"stats" | b"version" => confidence::MEDIUM,
+        _ => confidence::NONE,
+    }
+}
 
 pub fn decode(data: &[u8]) {
-    if data.len() >= 24 && (data[0] == 0x80 || data[0] == 0x81) {
-        let magic = data[0];
-        let opcode = data[1];
-        let keylen = u16::from_be_bytes([data[2], data[3]]);
-        let extlen = data[4];
-        let total = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
-        println!(
+    if let Some(h) = binary_header(data) {
+        let line = format!(
             "Memcached bin magic=0x{:02x} opcode=0x{:02x} keylen={} extlen={} body={}",
-            magic, opcode, keylen, extlen, total
+            h.magic, h.opcode, h.key_len, h.extras_len, h.body_len
         );
+        println!("{}", line);
+        tree::layer("memcached", data, line);
+        tree::field("Magic", &h.magic, format_args!("0x{:02x}", h.magic));
+        tree::field("Opcode", &h.opcode, format_args!("0x{:02x}", h.opcode));
+        tree::field("Key length", &h.key_len, h.key_len);
+        tree::field("Extras length", &h.extras_len, h.extras_len);
+        tree::field("Data type", &h.data_type, h.data_type);
+        // a vbucket in requests, a status in responses
+        match h.magic {
+            0x80 => tree::field("VBucket", &h.status, h.status),
+            _ => tree::field("Status", &h.status, h.status),
+        }
+        tree::field("Total body length", &h.body_len, h.body_len);
+        tree::field(
+            "Opaque",
+            &h.opaque,
+            format_args!("0x{:08x}", h.opaque.get()),
+        );
+        tree::field("CAS", &h.cas, h.cas);
+        let key_start = size_of::<Header>() + usize::from(h.extras_len);
+        if let Some(key) = data.get(key_start..key_start + usize::from(h.key_len.get())) {
+            tree::field("Key", key, String::from_utf8_lossy(key));
+        }
         return;
     }
     // text protocol: print first token
-    let max = core::cmp::min(64, data.len());
-    if let Ok(s) = std::str::from_utf8(&data[..max]) {
+    if let Ok(s) = std::str::from_utf8(&data[..data.len().min(64)]) {
         let cmd = s.split_whitespace().next().unwrap_or("");
         println!("Memcached text cmd={}", cmd);
+        tree::layer(
+            "memcached",
+            data,
+            format_args!("Memcached text cmd={}", cmd),
+        );
+        tree::field("Command", cmd.as_bytes(), cmd);
     } else {
         println!("Memcached ({}B)", data.len());
+        tree::layer(
+            "memcached",
+            data,
+            format_args!("Memcached ({}B)", data.len()),
+        );
     }
 }
@@ -0,0 +1,277 @@
+// MIME entities (mail messages, multipart/form-data bodies)
+// Headers are unfolded, multipart bodies split on their boundary (recursively) and the
+// base64 and quoted-printable transfer encodings undone.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc2045
+// * https://www.rfc-editor.org/rfc/rfc2046#section-5.1 (multipart)
+// * https://www.rfc-editor.org/rfc/rfc2183 (Content-Disposition)
+use super::http::Headers;
+
+/// Nesting of multipart entities followed at most
+const MAX_DEPTH: usize = 8;
+
+/// A leaf entity of a message
+#[derive(Debug, Clone)]
+pub struct Part {
+    pub headers: Headers,
+    /// Content-Type without parameters, lower cased ("text/plain" by default)
+    pub content_type: String,
+    /// filename of the Content-Disposition, or name of the Content-Type
+    pub filename: Option<String>,
+    /// attachment disposition, or a filename without disposition
+    pub attachment: bool,
+    /// content with the transfer encoding undone
+    pub data: Vec<u8>,
+}
+
+/// Split a header block from the body, unfolding continuation lines
+pub fn parse_headers(data: &[u8]) -> (Headers, &[u8]) {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        let end = data[i..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(data.len(), |p| i + p + 1);
+        let line = String::from_utf8_lossy(&data[i..end]);
+        let line = line.trim_end_matches(['\r', '\n']);
+        i = end;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, v)) = headers.last_mut() {
+                v.push(' ');
+                v.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    (Headers(headers), &data[i..])
+}
+
+/// Value of a `name=value` parameter of a header value (quotes removed)
+pub fn param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|p| {
+        let (n, v) = p.split_once('=')?;
+        let n = n.trim();
+        // RFC 2231 extended value: charset'language'percent-encoded
+        if n.eq_ignore_ascii_case(&format!("{}*", name)) {
+            let v = v.trim().trim_matches('"');
+            let encoded = v.splitn(3, '\'').nth(2).unwrap_or(v);
+            return Some(percent_decode(encoded));
+        }
+        n.eq_ignore_ascii_case(name)
+            .then(|| v.trim().trim_matches('"').to_string())
+    })
+}
+
+fn percent_decode(s: &str) -> String {
+    let b = s.as_bytes();
+    let mut out = Vec::with_capacity(b.len());
+    let mut i = 0;
+    while i < b.len() {
+        let hex = (b[i] == b'%')
+            .then(|| s.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(v) => {
+                out.push(v);
+                i += 3;
+            }
+            None => {
+                out.push(b[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Decode base64, ignoring line breaks and anything outside the alphabet
+pub fn base64_decode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in data {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' => break,
+            _ => continue,
+        };
+        acc = (acc << 6) | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    out
+}
+
+/// Encode base64 with padding, on a single line
+pub fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode quoted-printable, soft line breaks removed
+pub fn quoted_printable_decode(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] != b'=' {
+            out.push(data[i]);
+            i += 1;
+            continue;
+        }
+        match (data.get(i + 1), data.get(i + 2)) {
+            (Some(b'\r'), Some(b'\n')) => i += 3,
+            (Some(b'\n'), _) => i += 2,
+            (Some(h), Some(l)) => {
+                let hex = std::str::from_utf8(&[*h, *l])
+                    .ok()
+                    .and_then(|s| u8::from_str_radix(s, 16).ok());
+                match hex {
+                    Some(v) => {
+                        out.push(v);
+                        i += 3;
+                    }
+                    None => {
+                        out.push(b'=');
+                        i += 1;
+                    }
+                }
+            }
+            _ => {
+                out.push(b'=');
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Bodies between the delimiters of a multipart body
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut i = 0;
+    while i < body.len() {
+        let line_end = body[i..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map_or(body.len(), |p| i + p + 1);
+        let line = &body[i..line_end];
+        if line.starts_with(delimiter) {
+            if let Some(s) = start {
+                // the line break before the delimiter belongs to it
+                let mut end = i;
+                if end > s && body[end - 1] == b'\n' {
+                    end -= 1;
+                    if end > s && body[end - 1] == b'\r' {
+                        end -= 1;
+                    }
+                }
+                parts.push(&body[s..end]);
+            }
+            if line[delimiter.len()..].starts_with(b"--") {
+                return parts;
+            }
+            start = Some(line_end);
+        }
+        i = line_end;
+    }
+    // unterminated multipart, keep what was seen
+    if let Some(s) = start {
+        parts.push(&body[s..]);
+    }
+    parts
+}
+
+fn collect(headers: Headers, body: &[u8], depth: usize, parts: &mut Vec<Part>) {
+    let ct_value = headers
+        .get("content-type")
+        .unwrap_or("text/plain")
+        .to_string();
+    let content_type = ct_value
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    if content_type.starts_with("multipart/") && depth < MAX_DEPTH {
+        if let Some(boundary) = param(&ct_value, "boundary") {
+            for p in split_multipart(body, &boundary) {
+                let (h, b) = parse_headers(p);
+                collect(h, b, depth + 1, parts);
+            }
+            return;
+        }
+    }
+    let disposition = headers.get("content-disposition").unwrap_or("");
+    let filename = param(disposition, "filename").or_else(|| param(&ct_value, "name"));
+    let attachment = disposition
+        .trim_start()
+        .to_ascii_lowercase()
+        .starts_with("attachment")
+        || (filename.is_some() && disposition.is_empty());
+    let data = match headers
+        .get("content-transfer-encoding")
+        .map(|e| e.trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("base64") => base64_decode(body),
+        Some("quoted-printable") => quoted_printable_decode(body),
+        _ => body.to_vec(),
+    };
+    parts.push(Part {
+        headers,
+        content_type,
+        filename,
+        attachment,
+        data,
+    });
+}
+
+/// Leaf entities of a message (headers and body)
+pub fn parts(message: &[u8]) -> Vec<Part> {
+    let (headers, body) = parse_headers(message);
+    let mut parts = Vec::new();
+    collect(headers, body, 0, &mut parts);
+    parts
+}
+
+/// Leaf entities of a body whose Content-Type is known (HTTP multipart/form-data)
+pub fn body_parts(content_type: &str, body: &[u8]) -> Vec<Part> {
+    let headers = Headers(vec![("Content-Type".to_string(), content_type.to_string())]);
+    let mut parts = Vec::new();
+    collect(headers, body, 0, &mut parts);
+    parts
+}
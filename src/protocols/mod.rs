@@ -7,6 +7,7 @@ pub mod can;
 pub mod cdp;
 pub mod coap;
 pub mod dccp;
+pub mod detect;
 pub mod dhcp;
 pub mod dhcpv6;
 pub mod dns;
@@ -18,8 +19,11 @@ pub mod ethernet;
 pub mod frame_relay;
 pub mod ftp;
 pub mod gre;
+pub mod grpc;
 pub mod hdlc;
+pub mod hpack;
 pub mod http;
+pub mod http2;
 pub mod icmpv4;
 pub mod icmpv6;
 pub mod igmp;
@@ -37,6 +41,7 @@ pub mod llmnr;
 pub mod loopback;
 pub mod mdns;
 pub mod memcached;
+pub mod mime;
 pub mod mpls;
 pub mod mqtt;
 pub mod netbios;
@@ -55,6 +60,7 @@ pub mod rtcp;
 pub mod rtp;
 pub mod rtsp;
 pub mod sctp;
+pub mod services;
 pub mod sip;
 pub mod smb;
 pub mod smtp;
@@ -71,8 +77,37 @@ pub mod tcp;
 pub mod telnet;
 pub mod tftp;
 pub mod tls;
+pub mod tls_decrypt;
 pub mod turn;
 pub mod udp;
 pub mod usb;
 pub mod vlan;
 pub mod websocket;
+
+/// Decode a frame with the decoder of its pcap link type
+pub fn decode_by_datalink(dlt: i32, data: &[u8]) {
+    match dlt {
+        1 => ethernet::decode(data),       // LINKTYPE_EN10MB
+        0 | 108 => loopback::decode(data), // LINKTYPE_NULL or LOOP
+        101 => {
+            // LINKTYPE_RAW (raw IP)
+            if !data.is_empty() {
+                let v = (data[0] & 0xF0) >> 4;
+                if v == 4 {
+                    ipv4::decode(data);
+                } else if v == 6 {
+                    ipv6::decode(data);
+                } else {
+                    println!("RAW ({}B)", data.len());
+                }
+            }
+        }
+        227 => can::decode(data),             // CAN_SOCKETCAN
+        239 => nflog::decode(data),           // NFLOG
+        187 | 201 => bluetooth::decode(data), // BT HCI H4 (with/without phdr)
+        189 | 220 => usb::decode(data),       // USB Linux
+        212 => lin::decode(data),             // LIN (if supported)
+        9 | 16 => ppp::decode(data),          // PPP / PPP_BSDOS
+        _ => ethernet::decode(data),
+    }
+}
@@ -1,10 +1,14 @@
-#[derive(Clone, Copy, Default)]
-#[repr(C, packed)]
-pub struct Shim {
-    pub b0: u8,
-    pub b1: u8,
-    pub b2: u8,
-    pub ttl: u8,
+use crate::tree;
+use crate::utils::{self, wire_struct};
+
+wire_struct! {
+    #[derive(Clone, Copy, Default)]
+    pub struct Shim {
+        pub b0: u8,
+        pub b1: u8,
+        pub b2: u8,
+        pub ttl: u8,
+    }
 }
 
 impl Shim {
@@ -21,21 +25,18 @@ fn parse_label(s: &Shim) -> (u32, u8, bool, u8) {
 }
 
 pub fn decode(mut data: &[u8]) {
-    let mut depth = 0;
-    while data.len() >= Shim::SIZE {
-        let (h, rest) = data.split_at(Shim::SIZE);
-        let s = Shim {
-            b0: h[0],
-            b1: h[1],
-            b2: h[2],
-            ttl: h[3],
-        };
-        let (label, tc, s_bit, ttl) = parse_label(&s);
-        println!(
+    while let Some((s, rest)) = utils::split::<Shim>(data) {
+        let (label, tc, s_bit, ttl) = parse_label(s);
+        let line = format!(
             "MPLS label={} tc={} s={} ttl={}",
             label, tc, s_bit as u8, ttl
         );
-        depth += 1;
+        println!("{}", line);
+        tree::layer("mpls", s, line);
+        tree::field("Label", &data[..3], label);
+        tree::field("Traffic class", &s.b2, tc);
+        tree::field("Bottom of stack", &s.b2, s_bit);
+        tree::field("Time to live", &s.ttl, ttl);
         data = rest;
         if s_bit {
             break;
@@ -45,12 +46,9 @@ pub fn decode(mut data: &[u8]) {
         return;
     }
     // Best-effort payload dispatch
-    if data.len() >= 1 {
-        let first = data[0] >> 4;
-        match first {
-            4 => super::ipv4::decode(data),
-            6 => super::ipv6::decode(data),
-            _ => println!("MPLS payload ({}B)", data.len()),
-        }
+    match data[0] >> 4 {
+        4 => super::ipv4::decode(data),
+        6 => super::ipv6::decode(data),
+        _ => println!("MPLS payload ({}B)", data.len()),
     }
 }
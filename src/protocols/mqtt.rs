@@ -1,6 +1,10 @@
 // Minimal MQTT decoder (fixed header)
 // Spec: MQTT v3.1.1/5.0 fixed header byte 1 = type(4) | flags(4)
 // Remaining Length is variable-length (1..4 bytes).
+use super::detect::confidence;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
 
 fn mqtt_type_name(t: u8) -> &'static str {
     match t {
@@ -23,34 +27,66 @@ fn mqtt_type_name(t: u8) -> &'static str {
     }
 }
 
-pub fn decode(data: &[u8]) {
-    if data.len() < 2 {
-        println!("MQTT (truncated) {}B", data.len());
-        return;
+/// Confidence that the payload is an MQTT CONNECT or CONNACK packet
+pub fn probe(data: &[u8]) -> u8 {
+    // CONNECT: remaining length, then the protocol name
+    if data.first() == Some(&0x10) {
+        let start = match data.iter().skip(1).take(4).position(|b| b & 0x80 == 0) {
+            Some(n) => n + 2,
+            None => return confidence::NONE,
+        };
+        let name = data.get(start..);
+        if name.is_some_and(|n| n.starts_with(b"\x00\x04MQTT") || n.starts_with(b"\x00\x06MQIsdp"))
+        {
+            return confidence::CERTAIN;
+        }
+    }
+    if data.len() >= 4 && data[0] == 0x20 && data[1] as usize == data.len() - 2 && data[2] <= 1 {
+        return confidence::MEDIUM;
     }
-    let byte1 = data[0];
+    confidence::NONE
+}
+
+pub fn decode(data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    let byte1 = match r.u8() {
+        Ok(b) if data.len() >= 2 => b,
+        _ => {
+            expert::error(DecodeError::Truncated {
+                layer: "mqtt",
+                offset: r.position(),
+            });
+            return;
+        }
+    };
     let pkt_type = byte1 >> 4;
     let flags = byte1 & 0x0F;
-    // Decode Remaining Length (variable length)
-    let mut multiplier: usize = 1;
+    // Decode Remaining Length (variable length, at most 4 bytes)
     let mut value: usize = 0;
-    let mut i = 1usize;
-    while i < data.len() {
-        let enc = data[i] as usize;
-        value += (enc & 0x7F) * multiplier;
-        multiplier *= 128;
-        i += 1;
+    for shift in [0, 7, 14, 21] {
+        let enc = match r.u8() {
+            Ok(b) => b,
+            Err(_) => break,
+        };
+        value |= usize::from(enc & 0x7F) << shift;
         if (enc & 0x80) == 0 {
             break;
         }
-        if i > 4 {
-            break;
-        } // at most 4 bytes
     }
-    println!(
+    let line = format!(
         "MQTT {} flags=0x{:x} remaining_len={}",
         mqtt_type_name(pkt_type),
         flags,
         value
     );
+    println!("{}", line);
+    let end = (r.position() + value).min(data.len());
+    tree::layer("mqtt", &data[..end], line);
+    tree::field(
+        "Message type",
+        &data[..1],
+        format_args!("{} ({})", mqtt_type_name(pkt_type), pkt_type),
+    );
+    tree::field("Flags", &data[..1], format_args!("0x{:x}", flags));
+    tree::field("Remaining length", &data[1..r.position()], value);
 }
@@ -1,4 +1,235 @@
-// NetBIOS minimal decoder
+// NetBIOS name (UDP 137) and datagram (UDP 138) services
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc1002#section-4.2
+// * https://www.rfc-editor.org/rfc/rfc1002#section-4.4
+use super::detect::confidence;
+use super::dns;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
+/// Length of a first level encoded name: two letters per byte of the 16 byte name
+const ENCODED_NAME: usize = 32;
+/// Header of the datagrams carrying a name: type, flags, id, source address and port,
+/// datagram length and packet offset
+const DATAGRAM_HEADER: usize = 14;
+
+fn opcode_as_str(op: u16) -> &'static str {
+    match op {
+        0 => "query",
+        5 => "registration",
+        6 => "release",
+        7 => "wack",
+        8 | 9 => "refresh",
+        _ => "UNKNOW",
+    }
+}
+
+fn datagram_type_as_str(t: u8) -> &'static str {
+    match t {
+        0x10 => "direct-unique",
+        0x11 => "direct-group",
+        0x12 => "broadcast",
+        0x13 => "error",
+        0x14 => "query-request",
+        0x15 => "positive-query-response",
+        0x16 => "negative-query-response",
+        _ => "UNKNOW",
+    }
+}
+
+/// Name and suffix (the type of the service) of a first level encoded name
+fn first_level(encoded: &[u8]) -> Option<(String, u8)> {
+    if encoded.len() != ENCODED_NAME {
+        return None;
+    }
+    let mut name = Vec::with_capacity(ENCODED_NAME / 2);
+    for pair in encoded.chunks_exact(2) {
+        match (pair[0], pair[1]) {
+            (hi @ b'A'..=b'P', lo @ b'A'..=b'P') => name.push(((hi - b'A') << 4) | (lo - b'A')),
+            _ => return None,
+        }
+    }
+    let suffix = name.pop()?;
+    let name = String::from_utf8_lossy(&name).trim_end().to_string();
+    Some((name, suffix))
+}
+
+/// Read the name at the reader, reporting it when it is not a first level encoded one
+fn read_name(r: &mut ByteReader) -> Option<(String, u8)> {
+    let start = r.position();
+    match r.bytes_u8() {
+        Ok(encoded) => {
+            let name = first_level(encoded);
+            if name.is_none() {
+                expert::error(DecodeError::Malformed {
+                    layer: "netbios",
+                    offset: start,
+                    reason: "name is not first level encoded",
+                });
+            }
+            name
+        }
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "netbios",
+                offset: start,
+            });
+            None
+        }
+    }
+}
+
+/// Confidence that the payload is a NetBIOS name service packet (RFC 1002): one question
+/// or record whose name is a 32 byte first level encoded name
+pub fn probe(data: &[u8]) -> u8 {
+    // same header as DNS
+    let mut r = ByteReader::new(data);
+    let h = match r.view::<dns::Header>() {
+        Ok(h) if data.len() >= 12 + 34 => h,
+        _ => return confidence::NONE,
+    };
+    let encoded = matches!(r.bytes_u8(),
+        Ok(name) if name.len() == 32 && name.iter().all(|b| (b'A'..=b'P').contains(b)));
+    if encoded && u32::from(h.qdcount.get()) + u32::from(h.ancount.get()) == 1 {
+        confidence::CERTAIN
+    } else {
+        confidence::NONE
+    }
+}
+
+/// Decode a name service packet
 pub fn decode(data: &[u8]) {
-    println!("NetBIOS ({}B)", data.len());
+    let mut r = ByteReader::new(data);
+    let h = match r.view::<dns::Header>() {
+        Ok(h) => h,
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "netbios",
+                offset: data.len(),
+            });
+            return;
+        }
+    };
+    let flags = h.flags.get();
+    let kind = if flags & 0x8000 != 0 {
+        "response"
+    } else {
+        "request"
+    };
+    let op = opcode_as_str((flags >> 11) & 0xf);
+    let start = r.position();
+    let name = if h.qdcount.get() == 0 && h.ancount.get() == 0 {
+        None
+    } else {
+        read_name(&mut r)
+    };
+    let line = match name {
+        Some((ref name, suffix)) => format!(
+            "NetBIOS NS {} {} id={} name={}<{:02x}>",
+            op,
+            kind,
+            h.id.get(),
+            name,
+            suffix
+        ),
+        None => format!("NetBIOS NS {} {} id={}", op, kind, h.id.get()),
+    };
+    println!("{}", line);
+    tree::layer("netbios", data, line);
+    tree::field("Transaction ID", &h.id, h.id);
+    tree::field(
+        "Flags",
+        &h.flags,
+        format_args!("0x{:04x} ({} {})", flags, op, kind),
+    );
+    tree::field("Questions", &h.qdcount, h.qdcount);
+    tree::field("Answer RRs", &h.ancount, h.ancount);
+    tree::field("Authority RRs", &h.nscount, h.nscount);
+    tree::field("Additional RRs", &h.arcount, h.arcount);
+    if let Some((name, suffix)) = name {
+        tree::field(
+            "Name",
+            &data[start..r.position()],
+            format_args!("{}<{:02x}>", name, suffix),
+        );
+    }
+}
+
+/// Decode a datagram service packet
+pub fn decode_datagram(data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    let msg_type = match r.u8() {
+        Ok(t) => t,
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "netbios",
+                offset: 0,
+            });
+            return;
+        }
+    };
+    // the source name of the datagrams carrying one follows their 14 byte header
+    if !(0x10..=0x12).contains(&msg_type) {
+        println!("NetBIOS DGM {}", datagram_type_as_str(msg_type));
+        tree::layer(
+            "netbios",
+            data,
+            format_args!("NetBIOS DGM {}", datagram_type_as_str(msg_type)),
+        );
+        tree::field(
+            "Message type",
+            &data[..1],
+            format_args!("{} (0x{:02x})", datagram_type_as_str(msg_type), msg_type),
+        );
+        return;
+    }
+    if r.seek(DATAGRAM_HEADER).is_err() {
+        expert::error(DecodeError::Truncated {
+            layer: "netbios",
+            offset: data.len(),
+        });
+        return;
+    }
+    let name = read_name(&mut r);
+    let line = match name {
+        Some((ref name, suffix)) => format!(
+            "NetBIOS DGM {} from {}<{:02x}>",
+            datagram_type_as_str(msg_type),
+            name,
+            suffix
+        ),
+        None => format!("NetBIOS DGM {}", datagram_type_as_str(msg_type)),
+    };
+    println!("{}", line);
+    tree::layer("netbios", data, line);
+    tree::field(
+        "Message type",
+        &data[..1],
+        format_args!("{} (0x{:02x})", datagram_type_as_str(msg_type), msg_type),
+    );
+    tree::field("Flags", &data[1..2], format_args!("0x{:02x}", data[1]));
+    tree::field(
+        "Datagram ID",
+        &data[2..4],
+        u16::from_be_bytes([data[2], data[3]]),
+    );
+    tree::field(
+        "Source IP",
+        &data[4..8],
+        std::net::Ipv4Addr::from([data[4], data[5], data[6], data[7]]),
+    );
+    tree::field(
+        "Source port",
+        &data[8..10],
+        u16::from_be_bytes([data[8], data[9]]),
+    );
+    if let Some((name, suffix)) = name {
+        tree::field(
+            "Source name",
+            &data[DATAGRAM_HEADER..r.position()],
+            format_args!("{}<{:02x}>", name, suffix),
+        );
+    }
 }
@@ -1,16 +1,19 @@
-use crate::utils::cow_struct;
+use super::detect::confidence;
+use crate::tree;
+use crate::utils::{self, wire_struct, U32Be};
 use std::mem::size_of;
 
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub flags: u8, // LI(2), VN(3), MODE(3)
-    pub stratum: u8,
-    pub poll: i8,
-    pub precision: i8,
-    pub root_delay: u32,
-    pub root_dispersion: u32,
-    pub ref_id: u32,
+wire_struct! {
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        pub flags: u8, // LI(2), VN(3), MODE(3)
+        pub stratum: u8,
+        pub poll: u8,
+        pub precision: u8,
+        pub root_delay: U32Be,
+        pub root_dispersion: U32Be,
+        pub ref_id: U32Be,
+    }
 }
 
 impl Header {
@@ -35,12 +38,58 @@ pub fn display(h: &Header) -> String {
     )
 }
 
-pub fn decode(data: &[u8]) {
-    if data.len() < Header::SIZE {
-        return;
+/// Confidence that the payload is an NTP packet: version, mode and size
+pub fn probe(data: &[u8]) -> u8 {
+    let flags = match data.first() {
+        Some(&f) => f,
+        None => return confidence::NONE,
+    };
+    let vn = (flags & 0b0011_1000) >> 3;
+    let mode = flags & 0b0000_0111;
+    if !(1..=4).contains(&vn) || !(1..=5).contains(&mode) {
+        return confidence::NONE;
+    }
+    // header, optionally followed by a key id and a MD5 or SHA-1 digest
+    match data.len() {
+        48 => confidence::HIGH,
+        68 | 72 => confidence::MEDIUM,
+        _ => confidence::NONE,
     }
-    let (hdr, _rest) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        println!("{}", display(&h));
+}
+
+pub fn decode(data: &[u8]) {
+    if let Some(h) = utils::view::<Header>(data) {
+        println!("{}", display(h));
+        tree::layer("ntp", data, display(h));
+        tree::field("Leap indicator", &h.flags, h.flags >> 6);
+        tree::field("Version", &h.flags, (h.flags >> 3) & 0b111);
+        tree::field("Mode", &h.flags, h.flags & 0b111);
+        tree::field("Stratum", &h.stratum, h.stratum);
+        tree::field("Poll", &h.poll, h.poll);
+        tree::field("Precision", &h.precision, h.precision as i8);
+        tree::field(
+            "Root delay",
+            &h.root_delay,
+            format_args!("0x{:08x}", h.root_delay.get()),
+        );
+        tree::field(
+            "Root dispersion",
+            &h.root_dispersion,
+            format_args!("0x{:08x}", h.root_dispersion.get()),
+        );
+        tree::field(
+            "Reference ID",
+            &h.ref_id,
+            format_args!("0x{:08x}", h.ref_id.get()),
+        );
+        let names = [
+            "Reference timestamp",
+            "Origin timestamp",
+            "Receive timestamp",
+            "Transmit timestamp",
+        ];
+        for (name, ts) in names.iter().zip(data[Header::SIZE..].chunks_exact(8)) {
+            tree::field(name, ts, tree::hex(ts));
+        }
     }
 }
@@ -1,17 +1,20 @@
 // OSPFv2 minimal decoder (RFC 2328)
-use crate::utils::cow_struct;
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be};
+use std::net::Ipv4Addr;
 
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub version: u8,
-    pub typ: u8,
-    pub pkt_len: u16,
-    pub router_id: u32,
-    pub area_id: u32,
-    pub checksum: u16,
-    pub auth_type: u16,
-    pub auth: [u8; 8],
+wire_struct! {
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        pub version: u8,
+        pub typ: u8,
+        pub pkt_len: U16Be,
+        pub router_id: U32Be,
+        pub area_id: U32Be,
+        pub checksum: U16Be,
+        pub auth_type: U16Be,
+        pub auth: [u8; 8],
+    }
 }
 
 impl Header {
@@ -19,18 +22,27 @@ impl Header {
 }
 
 pub fn decode(data: &[u8]) {
-    if data.len() < Header::SIZE {
-        return;
-    }
-    let (hdr, _) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        println!(
+    if let Some(h) = utils::view::<Header>(data) {
+        let line = format!(
             "OSPFv{} type={} len={} rid={:#010x} area={:#010x}",
             h.version,
             h.typ,
-            u16::from_be(h.pkt_len),
-            u32::from_be(h.router_id),
-            u32::from_be(h.area_id)
+            h.pkt_len.get(),
+            h.router_id.get(),
+            h.area_id.get()
+        );
+        println!("{}", line);
+        tree::layer("ospf", h, line);
+        tree::field("Version", &h.version, h.version);
+        tree::field("Message type", &h.typ, h.typ);
+        tree::field("Packet length", &h.pkt_len, h.pkt_len);
+        tree::field("Router ID", &h.router_id, Ipv4Addr::from(h.router_id.get()));
+        tree::field("Area ID", &h.area_id, Ipv4Addr::from(h.area_id.get()));
+        tree::field(
+            "Checksum",
+            &h.checksum,
+            format_args!("0x{:04x}", h.checksum.get()),
         );
+        tree::field("Auth type", &h.auth_type, h.auth_type);
     }
 }
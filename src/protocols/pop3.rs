@@ -1,10 +1,114 @@
 // POP3 minimal decoder
+// `Session` follows the commands of the client to report the USER/PASS logins and the
+// passwords of AUTH PLAIN/LOGIN exchanges.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc1939
+// * https://www.rfc-editor.org/rfc/rfc5034 (AUTH)
+use super::detect::confidence;
+use crate::analysis::credentials::{self, Sasl};
+use crate::flow::FlowKey;
+use crate::reassembly::StreamHandler;
+use crate::tree;
+
+/// Longest command line accepted
+const MAX_LINE: usize = 4096;
+
+/// Follows the client side of a POP3 session
+#[derive(Default)]
+pub struct Session {
+    buf: Vec<u8>,
+    user: Option<String>,
+    sasl: Sasl,
+}
+
+impl Session {
+    fn on_line(&mut self, flow: &FlowKey, line: &str) {
+        if self.sasl.on_line("pop3", flow, line) {
+            return;
+        }
+        let (verb, arg) = line.split_once(' ').unwrap_or((line, ""));
+        match verb.to_ascii_uppercase().as_str() {
+            "USER" => self.user = Some(arg.trim().to_string()),
+            "PASS" => credentials::on_credential(
+                "pop3",
+                "USER/PASS",
+                Some(flow),
+                self.user.as_deref(),
+                arg,
+            ),
+            "AUTH" => {
+                let mut args = arg.split_whitespace();
+                if let Some(mechanism) = args.next() {
+                    self.sasl.start("pop3", flow, mechanism, args.next());
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl StreamHandler for Session {
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]) {
+        if !from_client {
+            return;
+        }
+        self.buf.extend_from_slice(data);
+        let mut used = 0;
+        while let Some(eol) = self.buf[used..].iter().position(|b| *b == b'\n') {
+            let line = String::from_utf8_lossy(&self.buf[used..used + eol]);
+            let line = line.trim_end_matches('\r').to_string();
+            used += eol + 1;
+            self.on_line(flow, &line);
+        }
+        self.buf.drain(..used);
+        if self.buf.len() > MAX_LINE {
+            self.buf = Vec::new();
+        }
+    }
+}
+
+/// Stream handler of a POP3 session
+pub fn session(flow: &FlowKey) -> Option<Session> {
+    matches!(flow.dst_port, 110 | 995).then(Session::default)
+}
+
+/// Confidence that the payload is a POP3 greeting, reply or command
+pub fn probe(data: &[u8]) -> u8 {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = String::from_utf8_lossy(&line[..line.len().min(128)]).to_ascii_uppercase();
+    if line.starts_with("+OK") && line.contains("POP3") {
+        confidence::CERTAIN
+    } else if line.starts_with("+OK ") || line.starts_with("-ERR ") {
+        confidence::HIGH
+    } else if ["CAPA", "STAT", "UIDL", "APOP ", "STLS"]
+        .iter()
+        .any(|c| line.starts_with(c))
+    {
+        confidence::MEDIUM
+    } else if line.starts_with("USER ") {
+        confidence::LOW
+    } else {
+        confidence::NONE
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if let Ok(s) = std::str::from_utf8(data) {
         if let Some(line) = s.lines().next() {
-            println!("POP3: {}", line.trim());
+            let line = line.trim();
+            let shown = match line.get(..5) {
+                Some(verb) if verb.eq_ignore_ascii_case("PASS ") => {
+                    format!("{}{}", verb, credentials::mask(&line[5..]))
+                }
+                _ => line.to_string(),
+            };
+            println!("POP3: {}", shown);
+            tree::layer("pop3", data, format_args!("POP3: {}", shown));
+            tree::field("Line", line.as_bytes(), shown);
             return;
         }
     }
     println!("POP3 ({}B)", data.len());
+    tree::layer("pop3", data, format_args!("POP3 ({}B)", data.len()));
 }
@@ -1,6 +1,36 @@
 // Minimal PPP decoder (RFC 1661)
 // Handles optional Address/Control (0xff,0x03) and 1- or 2-byte Protocol field.
 // Dispatches IPv4/IPv6 payloads; prints LCP/IPCP/IPv6CP/PAP/CHAP otherwise.
+// PAP Authenticate-Requests carry the peer id and password in the clear (RFC 1334), they
+// are reported as credentials.
+use crate::analysis::credentials;
+use crate::context;
+use crate::expert::{self, DecodeError};
+use crate::flow::FlowKey;
+use crate::protocols::ip::IpProtocol;
+use crate::tree;
+use crate::utils::ByteReader;
+
+/// PAP Authenticate-Request code
+const PAP_AUTHENTICATE_REQUEST: u8 = 1;
+
+/// Peer id and password of a PAP Authenticate-Request
+fn pap_request(data: &[u8]) -> Option<(String, String)> {
+    let mut r = ByteReader::new(data);
+    if r.u8().ok()? != PAP_AUTHENTICATE_REQUEST {
+        return None;
+    }
+    let _identifier = r.u8().ok()?;
+    // the length covers the code, identifier and length fields
+    let len = usize::from(r.u16().ok()?);
+    let mut r = ByteReader::new(data.get(4..len.min(data.len()))?);
+    let peer_id = r.bytes_u8().ok()?;
+    let password = r.bytes_u8().ok()?;
+    Some((
+        String::from_utf8_lossy(peer_id).into_owned(),
+        String::from_utf8_lossy(password).into_owned(),
+    ))
+}
 
 fn proto_name(p: u16) -> &'static str {
     match p {
@@ -15,29 +45,55 @@ fn proto_name(p: u16) -> &'static str {
     }
 }
 
-pub fn decode(data: &[u8]) {
-    if data.len() < 1 {
-        println!("PPP (empty)");
-        return;
+/// Report the credentials of a PAP packet
+pub fn pap(data: &[u8]) {
+    if let Some((peer_id, password)) = pap_request(data) {
+        // the peer asking to be authenticated is the client; over PPTP only the addresses
+        // of the GRE tunnel are known, none over PPPoE
+        let flow = context::flow().or_else(|| {
+            context::addrs().map(|(src, dst)| FlowKey::new(IpProtocol::Gre, src, 0, dst, 0))
+        });
+        credentials::on_credential("ppp", "PAP", flow.as_ref(), Some(&peer_id), &password);
     }
-    let mut i = 0usize;
+}
+
+pub fn decode(data: &[u8]) {
     // Optional Address/Control
-    if data.len() >= 2 && data[0] == 0xff && data[1] == 0x03 {
-        i = 2;
-    }
-    if i >= data.len() {
-        println!("PPP (no protocol)");
-        return;
-    }
+    let frame = data.strip_prefix(&[0xff, 0x03]).unwrap_or(data);
+    let mut r = ByteReader::new(frame);
     // Protocol field is 1 or 2 bytes: if first byte is odd, it's 1 byte (compressed); else 2 bytes
-    let (proto, hdr_len) = if data[i] & 1 != 0 {
-        (data[i] as u16, 1usize)
-    } else if i + 1 < data.len() {
-        (((data[i] as u16) << 8) | data[i + 1] as u16, 2usize)
-    } else {
-        (data[i] as u16, 1usize)
+    let proto = match r.u8() {
+        Ok(b) if b & 1 != 0 => u16::from(b),
+        Ok(b) => match r.u8() {
+            Ok(low) => u16::from_be_bytes([b, low]),
+            Err(_) => {
+                expert::error(DecodeError::Truncated {
+                    layer: "ppp",
+                    offset: data.len(),
+                });
+                return;
+            }
+        },
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "ppp",
+                offset: data.len() - frame.len(),
+            });
+            return;
+        }
     };
-    let payload = &data[i + hdr_len..];
+    let payload = r.rest();
+    let header = &data[..data.len() - payload.len()];
+    tree::layer("ppp", header, format_args!("PPP {}", proto_name(proto)));
+    if frame.len() < data.len() {
+        tree::field("Address", &data[..1], "0xff");
+        tree::field("Control", &data[1..2], "0x03");
+    }
+    tree::field(
+        "Protocol",
+        &header[data.len() - frame.len()..],
+        format_args!("{} (0x{:04x})", proto_name(proto), proto),
+    );
     match proto {
         0x0021 => {
             // IPv4
@@ -49,6 +105,10 @@ pub fn decode(data: &[u8]) {
             println!("PPP {}", proto_name(proto));
             crate::protocols::ipv6::decode(payload);
         }
+        0xC023 => {
+            println!("PPP {} ({}B)", proto_name(proto), payload.len());
+            pap(payload);
+        }
         _ => {
             println!("PPP {} ({}B)", proto_name(proto), payload.len());
         }
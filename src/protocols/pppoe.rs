@@ -1,50 +1,113 @@
-pub fn decode(eth_type: u16, data: &[u8]) {
-    // PPPoE header: Ver/Type (1), Code (1), SessionID (2), Length (2)
-    if data.len() < 6 {
-        return;
+use super::ethernet::EtherType;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{wire_struct, ByteReader, U16Be};
+use std::mem::size_of;
+
+wire_struct! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct Header {
+        pub ver_type: u8,
+        pub code: u8,
+        pub session_id: U16Be,
+        pub len: U16Be,
     }
-    let ver_type = data[0];
-    let code = data[1];
-    let sid = u16::from_be_bytes([data[2], data[3]]);
-    let len = u16::from_be_bytes([data[4], data[5]]) as usize;
-    println!(
+}
+
+pub fn decode(eth_type: EtherType, data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    let h = match r.view::<Header>() {
+        Ok(h) => h,
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "pppoe",
+                offset: 0,
+            });
+            return;
+        }
+    };
+    let line = format!(
         "PPPoE v{} t{} code=0x{:02x} sid={} len={}",
-        ver_type >> 4,
-        ver_type & 0x0f,
-        code,
-        sid,
-        len
+        h.ver_type >> 4,
+        h.ver_type & 0x0f,
+        h.code,
+        h.session_id,
+        h.len
     );
-    if data.len() < 6 + len {
-        return;
-    }
-    let payload = &data[6..6 + len];
-    if eth_type == super::ethernet::PROTO::PPPOE_DISC {
+    println!("{}", line);
+    tree::layer("pppoe", h, line);
+    tree::field("Version", &h.ver_type, h.ver_type >> 4);
+    tree::field("Type", &h.ver_type, h.ver_type & 0x0f);
+    tree::field("Code", &h.code, format_args!("0x{:02x}", h.code));
+    tree::field(
+        "Session ID",
+        &h.session_id,
+        format_args!("0x{:04x}", h.session_id.get()),
+    );
+    tree::field("Payload length", &h.len, h.len);
+    let payload = match r.bytes(h.len.get().into()) {
+        Ok(payload) => payload,
+        Err(_) => {
+            expert::error(DecodeError::LengthMismatch {
+                layer: "pppoe",
+                offset: 4,
+                declared: h.len.get().into(),
+                actual: r.remaining(),
+            });
+            return;
+        }
+    };
+    let mut r = ByteReader::new(payload);
+    if eth_type == EtherType::PppoeDiscovery {
         // Discovery tags (Type 2, Length 2, Value)
-        let mut p = payload;
-        while p.len() >= 4 {
-            let t = u16::from_be_bytes([p[0], p[1]]);
-            let l = u16::from_be_bytes([p[2], p[3]]) as usize;
-            p = &p[4..];
-            if p.len() < l {
-                break;
-            }
-            println!("PPPoE TAG type=0x{:04x} len={}", t, l);
-            p = &p[l..];
+        let mut start = r.position();
+        while !r.is_empty() {
+            let (t, value) = match r.tlv16() {
+                Ok(tlv) => tlv,
+                Err(_) => {
+                    expert::error(DecodeError::Truncated {
+                        layer: "pppoe",
+                        offset: size_of::<Header>() + start,
+                    });
+                    break;
+                }
+            };
+            println!("PPPoE TAG type=0x{:04x} len={}", t, value.len());
+            tree::field(
+                "Tag",
+                &payload[start..r.position()],
+                format_args!("type 0x{:04x}, {} bytes", t, value.len()),
+            );
+            start = r.position();
         }
         return;
     }
     // Session: first two bytes are PPP Protocol
-    if payload.len() < 2 {
-        return;
-    }
-    let ppp_proto = u16::from_be_bytes([payload[0], payload[1]]);
+    let ppp_proto = match r.u16() {
+        Ok(proto) => proto,
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "pppoe",
+                offset: size_of::<Header>(),
+            });
+            return;
+        }
+    };
+    tree::field(
+        "PPP protocol",
+        &payload[..2],
+        format_args!("0x{:04x}", ppp_proto),
+    );
+    let payload = r.rest();
     match ppp_proto {
-        0x0021 => super::ipv4::decode(&payload[2..]), // IPv4
-        0x0057 => super::ipv6::decode(&payload[2..]), // IPv6
-        0xc021 => println!("PPP LCP ({}B)", payload.len() - 2),
-        0xc023 => println!("PPP PAP ({}B)", payload.len() - 2),
-        0xc223 => println!("PPP CHAP ({}B)", payload.len() - 2),
-        _ => println!("PPP proto=0x{:04x} ({}B)", ppp_proto, payload.len() - 2),
+        0x0021 => super::ipv4::decode(payload), // IPv4
+        0x0057 => super::ipv6::decode(payload), // IPv6
+        0xc021 => println!("PPP LCP ({}B)", payload.len()),
+        0xc023 => {
+            println!("PPP PAP ({}B)", payload.len());
+            super::ppp::pap(payload);
+        }
+        0xc223 => println!("PPP CHAP ({}B)", payload.len()),
+        _ => println!("PPP proto=0x{:04x} ({}B)", ppp_proto, payload.len()),
     }
 }
@@ -1,12 +1,471 @@
+// QUIC long header detection and Initial packet decryption
+// Initial packets are protected with keys derived from a public salt and the destination
+// connection id chosen by the client, so anybody on the path can remove the protection
+// and read the CRYPTO frames carrying the TLS ClientHello and ServerHello.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc9000#section-17.2 (long header)
+// * https://www.rfc-editor.org/rfc/rfc9001#section-5 (Initial secrets, header protection)
+// * https://www.rfc-editor.org/rfc/rfc9369 (QUIC version 2)
+use aes::cipher::BlockEncrypt;
+use aes::Aes128;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::Aes128Gcm;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::analysis::fingerprint::{self, Transport};
+use crate::analysis::{rules, tls_policy};
+use crate::context;
+use crate::expert::{self, DecodeError};
+use crate::flow::{Direction, FlowKey};
+use crate::tree;
+use crate::utils::{ByteReader, Truncated};
+
+use super::detect::confidence;
+use super::tls::{self, ClientHello, Messages, ServerHello, HANDSHAKE};
+use super::tls_decrypt::{hkdf_expand_label, Hash};
+
+/// QUIC versions with known Initial salts
+#[non_exhaustive]
+pub struct VERSION;
+
+impl VERSION {
+    pub const V1: u32 = 0x0000_0001;
+    pub const V2: u32 = 0x6b33_43cf;
+    pub const DRAFT_29: u32 = 0xff00_001d;
+}
+
+/// Long header packet types (version 1 numbering)
+#[non_exhaustive]
+pub struct TYPE;
+
+impl TYPE {
+    pub const INITIAL: u8 = 0;
+    pub const ZERO_RTT: u8 = 1;
+    pub const HANDSHAKE: u8 = 2;
+    pub const RETRY: u8 = 3;
+}
+
+/// Frame types found in Initial packets
+#[non_exhaustive]
+pub struct FRAME;
+
+impl FRAME {
+    pub const PADDING: u64 = 0x00;
+    pub const PING: u64 = 0x01;
+    pub const ACK: u64 = 0x02;
+    pub const ACK_ECN: u64 = 0x03;
+    pub const CRYPTO: u64 = 0x06;
+    pub const CONNECTION_CLOSE: u64 = 0x1c;
+    pub const CONNECTION_CLOSE_APP: u64 = 0x1d;
+}
+
+const SALT_V1: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+const SALT_V2: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb,
+    0xf9, 0xbd, 0x2e, 0xd9,
+];
+const SALT_DRAFT_29: [u8; 20] = [
+    0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c, 0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61, 0x11, 0xe0,
+    0x43, 0x90, 0xa8, 0x99,
+];
+
+/// CRYPTO bytes buffered per direction while waiting for a complete hello
+const MAX_CRYPTO: usize = 1 << 16;
+/// Connections tracked per thread
+const MAX_CONNECTIONS: usize = 65536;
+
+/// Salt and key labels of a version
+fn version_params(version: u32) -> Option<(&'static [u8], &'static str)> {
+    match version {
+        VERSION::V1 => Some((&SALT_V1, "quic")),
+        VERSION::V2 => Some((&SALT_V2, "quicv2")),
+        VERSION::DRAFT_29 => Some((&SALT_DRAFT_29, "quic")),
+        _ => None,
+    }
+}
+
+/// Long header packet type in version 1 numbering (v2 rotates the values)
+fn packet_type(version: u32, bits: u8) -> u8 {
+    match version {
+        VERSION::V2 => (bits + 3) % 4,
+        _ => bits,
+    }
+}
+
+fn type_as_str(t: u8) -> &'static str {
+    match t {
+        TYPE::INITIAL => "Initial",
+        TYPE::ZERO_RTT => "0-RTT",
+        TYPE::HANDSHAKE => "Handshake",
+        _ => "Retry",
+    }
+}
+
+/// Variable length integer (RFC 9000 16)
+pub fn varint(r: &mut ByteReader) -> Result<u64, Truncated> {
+    let first = r.peek().ok_or(Truncated)?;
+    let bytes = r.bytes(1 << (first >> 6))?;
+    let v = u64::from(first & 0x3f);
+    Ok(bytes[1..].iter().fold(v, |v, b| (v << 8) | u64::from(*b)))
+}
+
+/// Packet protection keys of one direction of the Initial space
+pub struct InitialKeys {
+    aead: Aes128Gcm,
+    iv: [u8; 12],
+    hp: Aes128,
+}
+
+impl InitialKeys {
+    /// Keys of the client (or server) Initial packets for the client's first DCID
+    pub fn new(version: u32, dcid: &[u8], client: bool) -> Option<Self> {
+        let (salt, prefix) = version_params(version)?;
+        let (initial_secret, _) = Hkdf::<Sha256>::extract(Some(salt), dcid);
+        let label = if client { "client in" } else { "server in" };
+        let secret = hkdf_expand_label(Hash::Sha256, &initial_secret, label, 32)?;
+        let key = hkdf_expand_label(Hash::Sha256, &secret, &format!("{} key", prefix), 16)?;
+        let iv = hkdf_expand_label(Hash::Sha256, &secret, &format!("{} iv", prefix), 12)?;
+        let hp = hkdf_expand_label(Hash::Sha256, &secret, &format!("{} hp", prefix), 16)?;
+        Some(InitialKeys {
+            aead: Aes128Gcm::new_from_slice(&key).ok()?,
+            iv: iv.try_into().ok()?,
+            hp: Aes128::new_from_slice(&hp).ok()?,
+        })
+    }
+
+    /// Remove the header protection and decrypt a packet whose packet number starts at
+    /// `pn_offset`, returning the packet number and the frames
+    pub fn open(&self, packet: &[u8], pn_offset: usize) -> Option<(u64, Vec<u8>)> {
+        let sample = packet.get(pn_offset + 4..pn_offset + 20)?;
+        let mut mask = aes::Block::clone_from_slice(sample);
+        self.hp.encrypt_block(&mut mask);
+        let mut header = packet.get(..pn_offset + 4)?.to_vec();
+        header[0] ^= mask[0] & 0x0f;
+        let pn_len = (header[0] & 0x03) as usize + 1;
+        header.truncate(pn_offset + pn_len);
+        let mut pn = 0u64;
+        for (i, b) in header[pn_offset..].iter_mut().enumerate() {
+            *b ^= mask[1 + i];
+            pn = (pn << 8) | *b as u64;
+        }
+        let mut nonce = self.iv;
+        for (n, p) in nonce[4..].iter_mut().zip(pn.to_be_bytes()) {
+            *n ^= p;
+        }
+        let payload = Payload {
+            msg: &packet[pn_offset + pn_len..],
+            aad: &header,
+        };
+        let frames = self.aead.decrypt((&nonce).into(), payload).ok()?;
+        Some((pn, frames))
+    }
+}
+
+/// CRYPTO frames of one direction, put back in order
+#[derive(Default)]
+struct CryptoStream {
+    data: Vec<u8>,
+    pending: BTreeMap<u64, Vec<u8>>,
+    done: bool,
+}
+
+impl CryptoStream {
+    fn push(&mut self, offset: u64, data: &[u8]) {
+        let end = offset + data.len() as u64;
+        if end as usize > MAX_CRYPTO || end <= self.data.len() as u64 {
+            return;
+        }
+        self.pending.insert(offset, data.to_vec());
+        while let Some(entry) = self.pending.first_entry() {
+            let off = *entry.key() as usize;
+            if off > self.data.len() {
+                break;
+            }
+            let chunk = entry.remove();
+            if off + chunk.len() > self.data.len() {
+                let skip = self.data.len() - off;
+                self.data.extend_from_slice(&chunk[skip..]);
+            }
+        }
+    }
+}
+
+/// Initial space of a connection
+struct Connection {
+    /// first DCID of the client, the Initial keys of both sides derive from it
+    dcid: Vec<u8>,
+    /// canonical direction of the client packets
+    client: Direction,
+    crypto: [CryptoStream; 2],
+}
+
+thread_local! {
+    static CONNECTIONS: RefCell<HashMap<FlowKey, Connection>> = RefCell::new(HashMap::new());
+}
+
+/// Read a frame of an Initial packet, keeping the CRYPTO frames; false for a frame type
+/// not allowed there
+fn initial_frame<'a>(
+    r: &mut ByteReader<'a>,
+    crypto: &mut Vec<(u64, &'a [u8])>,
+) -> Result<bool, Truncated> {
+    let frame_type = varint(r)?;
+    match frame_type {
+        FRAME::PADDING | FRAME::PING => {}
+        FRAME::ACK | FRAME::ACK_ECN => {
+            // largest, delay, range count, first range, then gap/length pairs
+            let (_largest, _delay) = (varint(r)?, varint(r)?);
+            let ranges = varint(r)?;
+            let _first_range = varint(r)?;
+            for _ in 0..ranges * 2 {
+                varint(r)?;
+            }
+            if frame_type == FRAME::ACK_ECN {
+                for _ in 0..3 {
+                    varint(r)?;
+                }
+            }
+        }
+        FRAME::CRYPTO => {
+            let offset = varint(r)?;
+            let len = varint(r)?;
+            crypto.push((
+                offset,
+                r.bytes(usize::try_from(len).map_err(|_| Truncated)?)?,
+            ));
+        }
+        FRAME::CONNECTION_CLOSE | FRAME::CONNECTION_CLOSE_APP => {
+            let _error_code = varint(r)?;
+            if frame_type == FRAME::CONNECTION_CLOSE {
+                let _frame_type = varint(r)?;
+            }
+            let len = varint(r)?;
+            r.skip(usize::try_from(len).map_err(|_| Truncated)?)?;
+        }
+        // no other frame is allowed in Initial packets
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// CRYPTO frames of a decrypted Initial payload
+fn crypto_frames(frames: &[u8]) -> Vec<(u64, &[u8])> {
+    let mut out = Vec::new();
+    let mut r = ByteReader::new(frames);
+    while !r.is_empty() {
+        if initial_frame(&mut r, &mut out) != Ok(true) {
+            break;
+        }
+    }
+    out
+}
+
+/// Look for a complete hello in the CRYPTO stream of one side
+fn on_crypto(flow: &FlowKey, from_client: bool, stream: &mut CryptoStream) {
+    if stream.done {
+        return;
+    }
+    let msg = match Messages::new(&stream.data).next() {
+        Some(msg) => msg,
+        None => return,
+    };
+    stream.done = true;
+    // policy and fingerprints expect the flow oriented from the client
+    let client_flow = if from_client { *flow } else { flow.reversed() };
+    match msg.msg_type {
+        HANDSHAKE::CLIENT_HELLO => match ClientHello::parse(msg.body) {
+            Some(ch) => {
+                let fp = fingerprint::ClientFingerprint::compute(&ch, Transport::Quic);
+                let alpn = ch.alpn().map(String::from_utf8_lossy);
+                println!(
+                    "QUIC ClientHello SNI={} ALPN={} ja4={}",
+                    ch.server_name().unwrap_or("-"),
+                    alpn.as_deref().unwrap_or("-"),
+                    fp.ja4
+                );
+                fingerprint::on_client_hello(&ch, Transport::Quic);
+                tls_policy::on_client_hello(&client_flow, &ch);
+                if let Some(sni) = ch.server_name() {
+                    rules::on_tls_sni(&client_flow, sni);
+                }
+            }
+            None => expert::error(DecodeError::Malformed {
+                layer: "quic",
+                offset: 0,
+                reason: "invalid ClientHello in CRYPTO frames",
+            }),
+        },
+        HANDSHAKE::SERVER_HELLO => match ServerHello::parse(msg.body) {
+            Some(sh) => {
+                let fp = fingerprint::ServerFingerprint::compute(&sh, Transport::Quic);
+                println!(
+                    "QUIC ServerHello version={} cipher=0x{:04x} ja4s={}",
+                    tls::version_as_str(sh.selected_version()),
+                    sh.cipher_suite,
+                    fp.ja4s
+                );
+                fingerprint::on_server_hello(&sh, Transport::Quic);
+                tls_policy::on_server_hello(&client_flow, None, &sh);
+            }
+            None => expert::error(DecodeError::Malformed {
+                layer: "quic",
+                offset: 0,
+                reason: "invalid ServerHello in CRYPTO frames",
+            }),
+        },
+        _ => {}
+    }
+}
+
+/// Decrypt an Initial packet and feed its CRYPTO frames, `pn_offset` being the offset of
+/// the packet number within `packet`
+fn on_initial(version: u32, dcid: &[u8], packet: &[u8], pn_offset: usize) {
+    let flow = match context::flow() {
+        Some(flow) => flow,
+        None => return,
+    };
+    let (key, dir) = flow.canonical();
+    CONNECTIONS.with(|c| {
+        let mut c = c.borrow_mut();
+        if !c.contains_key(&key) {
+            if c.len() >= MAX_CONNECTIONS {
+                c.clear();
+            }
+            // the client speaks first, with the DCID the Initial keys derive from
+            c.insert(
+                key,
+                Connection {
+                    dcid: dcid.to_vec(),
+                    client: dir,
+                    crypto: Default::default(),
+                },
+            );
+        }
+        let conn = c.get_mut(&key).expect("inserted above");
+        let from_client = dir == conn.client;
+        let opened = InitialKeys::new(version, &conn.dcid, from_client)
+            .and_then(|k| k.open(packet, pn_offset))
+            .or_else(|| {
+                // a new DCID after a Retry
+                let retry = InitialKeys::new(version, dcid, true)?.open(packet, pn_offset)?;
+                conn.dcid = dcid.to_vec();
+                Some(retry)
+            });
+        let (pn, frames) = match opened {
+            Some(o) => o,
+            None => {
+                expert::error(DecodeError::Malformed {
+                    layer: "quic",
+                    offset: pn_offset,
+                    reason: "Initial packet fails authentication",
+                });
+                return;
+            }
+        };
+        let crypto = crypto_frames(&frames);
+        println!(
+            "QUIC Initial pn={} crypto_frames={} {}B",
+            pn,
+            crypto.len(),
+            frames.len()
+        );
+        let stream = &mut conn.crypto[usize::from(!from_client)];
+        for (offset, data) in crypto {
+            stream.push(offset, data);
+        }
+        on_crypto(&flow, from_client, stream);
+    });
+}
+
+/// Decode the long header packet at the start of `data`, returning its length, or `None`
+/// for the packets whose length is unknown (version negotiation, Retry, other versions)
+fn long_header(data: &[u8]) -> Result<Option<usize>, Truncated> {
+    let mut r = ByteReader::new(data);
+    let first = r.u8()?;
+    let version = r.u32()?;
+    if version == 0 {
+        println!("QUIC version negotiation");
+        return Ok(None);
+    }
+    let dcid = r.bytes_u8()?;
+    let scid = r.bytes_u8()?;
+    let ptype = packet_type(version, (first & 0x30) >> 4);
+    if version_params(version).is_none() || ptype == TYPE::RETRY {
+        return Ok(None);
+    }
+    if ptype == TYPE::INITIAL {
+        let token_len = varint(&mut r)?;
+        r.skip(usize::try_from(token_len).unwrap_or(usize::MAX))?;
+    }
+    let len_start = r.position();
+    let len = varint(&mut r)?;
+    let header_len = r.position();
+    r.skip(usize::try_from(len).unwrap_or(usize::MAX))?;
+    let packet = &data[..r.position()];
+    println!(
+        "QUIC {} version=0x{:08x} dcid={}",
+        type_as_str(ptype),
+        version,
+        tree::hex(dcid)
+    );
+    tree::open("Packet", packet, type_as_str(ptype));
+    tree::field("Destination connection ID", dcid, tree::hex(dcid));
+    tree::field("Source connection ID", scid, tree::hex(scid));
+    tree::field("Length", &data[len_start..header_len], len);
+    tree::field(
+        "Packet number and payload",
+        &packet[header_len..],
+        format_args!("{} bytes", len),
+    );
+    tree::close();
+    if ptype == TYPE::INITIAL {
+        on_initial(version, dcid, packet, header_len);
+    }
+    Ok(Some(packet.len()))
+}
+
 // Very coarse QUIC detection (long header, type, version)
+/// Confidence that the payload is a QUIC long header packet of a known version
+pub fn probe(data: &[u8]) -> u8 {
+    let (first, version) = match data.get(..5) {
+        Some(h) => (h[0], u32::from_be_bytes([h[1], h[2], h[3], h[4]])),
+        None => return confidence::NONE,
+    };
+    let dcid_ok = data.get(5).is_some_and(|&l| l <= 20);
+    if first & 0x80 == 0 || !dcid_ok {
+        return confidence::NONE;
+    }
+    if first & 0x40 != 0 && version_params(version).is_some() {
+        confidence::CERTAIN
+    } else if version == 0 || data[1] == b'Q' {
+        // version negotiation, Google QUIC
+        confidence::MEDIUM
+    } else {
+        confidence::LOW
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if data.len() < 6 {
+        expert::error(DecodeError::Truncated {
+            layer: "quic",
+            offset: data.len(),
+        });
         return;
     }
     let first = data[0];
     let long_hdr = (first & 0x80) != 0;
     if !long_hdr {
         println!("QUIC short header (likely 1-RTT)");
+        tree::layer("quic", data, "QUIC short header (likely 1-RTT)");
+        tree::field("Header form", &data[..1], "short");
         return;
     }
     let pkt_type = (first & 0x30) >> 4; // 0=Initial
@@ -17,11 +476,91 @@ pub fn decode(data: &[u8]) {
         flavor = "GQUIC";
     }
     let mut note = String::new();
-    if flavor == "IETF" && (version == 1 || (version & 0xFF00_0000) == 0xFF00_0000) {
+    if flavor == "IETF"
+        && (version == VERSION::V1
+            || version == VERSION::V2
+            || (version & 0xFF00_0000) == 0xFF00_0000)
+    {
         note = " (HTTP/3 likely)".into();
     }
-    println!(
+    let line = format!(
         "QUIC {} long_hdr type={} version=0x{:08x}{}",
         flavor, pkt_type, version, note
     );
+    println!("{}", line);
+    tree::layer("quic", data, line);
+    tree::field("Header form", &data[..1], "long");
+    tree::field("Packet type", &data[..1], pkt_type);
+    tree::field("Version", &data[1..5], format_args!("0x{:08x}", version));
+    // a datagram may carry several coalesced packets
+    let mut rest = data;
+    while rest.first().is_some_and(|b| b & 0x80 != 0) {
+        match long_header(rest) {
+            Ok(Some(len)) => rest = &rest[len..],
+            Ok(None) => break,
+            Err(_) => {
+                expert::error(DecodeError::Truncated {
+                    layer: "quic",
+                    offset: data.len() - rest.len(),
+                });
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // RFC 9001 A.3, server Initial answering the client Initial of A.2
+    #[test]
+    fn server_initial() {
+        let dcid = hex("8394c8f03e515708");
+        let packet = hex(
+            "cf000000010008f067a5502a4262b5004075c0d95a482cd0991cd25b0aac406a
+             5816b6394100f37a1c69797554780bb38cc5a99f5ede4cf73c3ec2493a1839b3
+             dbcba3f6ea46c5b7684df3548e7ddeb9c3bf9c73cc3f3bded74b562bfb19fb84
+             022f8ef4cdd93795d77d06edbb7aaf2f58891850abbdca3d20398c276456cbc4
+             2158407dd074ee",
+        );
+        let keys = InitialKeys::new(VERSION::V1, &dcid, false).unwrap();
+        // first byte, version, DCID and SCID with their lengths, token length, length
+        let pn_offset = 1 + 4 + 1 + 1 + 8 + 1 + 2;
+        let (pn, frames) = keys.open(&packet, pn_offset).unwrap();
+        assert_eq!(pn, 1);
+        assert_eq!(
+            frames,
+            hex(
+                "02000000000600405a020000560303eefce7f7b37ba1d1632e96677825ddf7
+                 3988cfc79825df566dc5430b9a045a1200130100002e00330024001d00209d3c
+                 940d89690b84d08a60993c144eca684d1081287c834d5311bcf32bb9da1a002b
+                 00020304"
+            )
+        );
+        let crypto = crypto_frames(&frames);
+        assert_eq!(crypto.len(), 1);
+        assert_eq!(crypto[0].0, 0);
+        assert_eq!(crypto[0].1.len(), 90);
+    }
+
+    #[test]
+    fn client_keys_do_not_open_server_packets() {
+        let dcid = hex("8394c8f03e515708");
+        let packet = hex(
+            "cf000000010008f067a5502a4262b5004075c0d95a482cd0991cd25b0aac406a
+             5816b6394100f37a1c69797554780bb38cc5a99f5ede4cf73c3ec2493a1839b3",
+        );
+        let keys = InitialKeys::new(VERSION::V1, &dcid, true).unwrap();
+        assert!(keys.open(&packet, 18).is_none());
+        assert!(InitialKeys::new(0x1a2a_3a4a, &dcid, true).is_none());
+    }
 }
@@ -1,4 +1,114 @@
-// RDP minimal placeholder
+// RDP: the TPKT and X.224 headers of the connection sequence
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc1006#section-6
+// * https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-rdpbcgr/18a27ef9-6f9a-4501-b000-94b1fe3c2c10
+use super::detect::confidence;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
+const COOKIE: &[u8] = b"Cookie: mstshash=";
+
+fn x224_code_as_str(code: u8) -> &'static str {
+    match code & 0xf0 {
+        0xe0 => "Connection Request",
+        0xd0 => "Connection Confirm",
+        0xf0 => "Data",
+        0x80 => "Disconnect Request",
+        0x70 => "Error",
+        _ => "UNKNOW",
+    }
+}
+
+/// Confidence that the payload is an RDP connection request or confirm: a TPKT carrying
+/// an X.224 connection PDU
+pub fn probe(data: &[u8]) -> u8 {
+    let mut r = ByteReader::new(data);
+    // TPKT version 3 and length, then the X.224 length indicator and PDU code
+    let code = match (r.u8(), r.u8(), r.u16(), r.u8(), r.u8()) {
+        (Ok(3), Ok(0), Ok(len), Ok(li), Ok(code))
+            if data.len() >= 11
+                && usize::from(len) == data.len()
+                && usize::from(li) + 5 <= data.len() =>
+        {
+            code
+        }
+        _ => return confidence::NONE,
+    };
+    let cookie = data.windows(COOKIE.len()).any(|w| w == COOKIE);
+    match code & 0xf0 {
+        0xe0 | 0xd0 if cookie => confidence::CERTAIN,
+        0xe0 | 0xd0 => confidence::HIGH,
+        _ => confidence::LOW,
+    }
+}
+
 pub fn decode(data: &[u8]) {
-    println!("RDP ({}B)", data.len());
+    let mut r = ByteReader::new(data);
+    let len = match (r.u8(), r.u8(), r.u16()) {
+        (Ok(3), Ok(_), Ok(len)) => usize::from(len),
+        (Ok(version), Ok(_), Ok(_)) => {
+            expert::error(DecodeError::UnsupportedVersion {
+                layer: "rdp",
+                offset: 0,
+                version: version.into(),
+            });
+            return;
+        }
+        _ => {
+            expert::error(DecodeError::Truncated {
+                layer: "rdp",
+                offset: 0,
+            });
+            return;
+        }
+    };
+    if len > data.len() {
+        expert::error(DecodeError::Truncated {
+            layer: "rdp",
+            offset: data.len(),
+        });
+    }
+    let (li, code) = match (r.u8(), r.u8()) {
+        (Ok(li), Ok(code)) => (li, code),
+        _ => {
+            expert::error(DecodeError::Truncated {
+                layer: "rdp",
+                offset: r.position(),
+            });
+            return;
+        }
+    };
+    // the routing token of a Connection Request: a line naming the user
+    let cookie = data
+        .windows(COOKIE.len())
+        .position(|w| w == COOKIE)
+        .map(|i| &data[i + COOKIE.len()..])
+        .map(|rest| {
+            let end = rest.windows(2).position(|w| w == b"\r\n");
+            &rest[..end.unwrap_or(rest.len())]
+        })
+        .filter(|_| code & 0xf0 == 0xe0);
+    let line = match cookie {
+        Some(user) => format!(
+            "RDP X.224 {} mstshash={}",
+            x224_code_as_str(code),
+            String::from_utf8_lossy(user)
+        ),
+        None => format!("RDP X.224 {} ({}B)", x224_code_as_str(code), len),
+    };
+    println!("{}", line);
+    tree::layer("rdp", &data[..len.clamp(6, data.len())], line);
+    tree::field("TPKT version", &data[..1], 3);
+    tree::field("TPKT length", &data[2..4], len);
+    tree::field("X.224 length", &data[4..5], li);
+    tree::field(
+        "X.224 PDU type",
+        &data[5..6],
+        format_args!("{} (0x{:02x})", x224_code_as_str(code), code & 0xf0),
+    );
+    if let Some(user) = cookie {
+        tree::field("Cookie", user, String::from_utf8_lossy(user));
+    }
 }
@@ -1,5 +1,7 @@
 // Minimal Redis (RESP) decoder
 // RESP prefixes: + - : $ * (RESP2) and RESP3 adds _ , # , ~ , = , > , %
+use super::detect::confidence;
+use crate::tree;
 
 fn resp_type_name(b: u8) -> &'static str {
     match b as char {
@@ -18,6 +20,28 @@ fn resp_type_name(b: u8) -> &'static str {
     }
 }
 
+/// Confidence that the payload is a RESP command or reply
+pub fn probe(data: &[u8]) -> u8 {
+    let end = match data.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => end,
+        None => return confidence::NONE,
+    };
+    let (first, rest) = match data[..end].split_first() {
+        Some(split) => split,
+        None => return confidence::NONE,
+    };
+    let number = !rest.is_empty() && rest.iter().all(|b| b.is_ascii_digit() || *b == b'-');
+    match first {
+        // commands are arrays of bulk strings
+        b'*' if number && data.get(end + 2) == Some(&b'$') => confidence::CERTAIN,
+        b'*' | b'$' | b':' if number => confidence::MEDIUM,
+        b'+' | b'-' if rest.iter().all(|b| b.is_ascii_graphic() || *b == b' ') => {
+            confidence::MEDIUM
+        }
+        _ => confidence::NONE,
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if data.is_empty() {
         println!("Redis (empty)");
@@ -36,6 +60,9 @@ pub fn decode(data: &[u8]) {
             break;
         }
     }
-    let preview = std::str::from_utf8(&data.get(0..end).unwrap_or(&[])).unwrap_or("");
+    let preview = std::str::from_utf8(data.get(0..end).unwrap_or(&[])).unwrap_or("");
     println!("Redis {}: {}", kind, preview);
+    tree::layer("redis", data, format_args!("Redis {}: {}", kind, preview));
+    tree::field("Type", &data[..1], kind);
+    tree::field("First line", &data[..end], preview);
 }
@@ -1,24 +1,51 @@
 // RIP v2 minimal decoder (UDP/520)
-use crate::utils::cow_struct;
+use super::detect::confidence;
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be};
+use std::net::Ipv4Addr;
 
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub cmd: u8,
-    pub ver: u8,
-    pub zero: u16,
+wire_struct! {
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        pub cmd: u8,
+        pub ver: u8,
+        pub zero: U16Be,
+    }
 }
 
 impl Header {
     pub const SIZE: usize = 4;
 }
 
-pub fn decode(data: &[u8]) {
-    if data.len() < Header::SIZE {
-        return;
+/// Confidence that the payload is a RIP message: command, version and 20 byte entries
+pub fn probe(data: &[u8]) -> u8 {
+    if data.len() < Header::SIZE + 20 || !(data.len() - Header::SIZE).is_multiple_of(20) {
+        return confidence::NONE;
+    }
+    match (data[0], data[1], data[2], data[3]) {
+        (1 | 2, 1 | 2, 0, 0) => confidence::HIGH,
+        _ => confidence::NONE,
     }
-    let (hdr, _) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        println!("RIP cmd={} ver={}", h.cmd, h.ver);
+}
+
+pub fn decode(data: &[u8]) {
+    if let Some(h) = utils::view::<Header>(data) {
+        let line = format!("RIP cmd={} ver={}", h.cmd, h.ver);
+        println!("{}", line);
+        tree::layer("rip", data, line);
+        tree::field("Command", &h.cmd, h.cmd);
+        tree::field("Version", &h.ver, h.ver);
+        for entry in data[Header::SIZE..].chunks_exact(20) {
+            tree::field(
+                "Route",
+                entry,
+                format_args!(
+                    "{}/{} metric={}",
+                    Ipv4Addr::from([entry[4], entry[5], entry[6], entry[7]]),
+                    Ipv4Addr::from([entry[8], entry[9], entry[10], entry[11]]),
+                    u32::from_be_bytes([entry[16], entry[17], entry[18], entry[19]])
+                ),
+            );
+        }
     }
 }
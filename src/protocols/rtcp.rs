@@ -1,12 +1,16 @@
 // RTCP minimal decoder (RFC 3550)
-use crate::utils::cow_struct;
+use super::detect::confidence;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, ByteReader, U16Be};
 
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub v_p_count: u8,
-    pub pt: u8,
-    pub length: u16,
+wire_struct! {
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        pub v_p_count: u8,
+        pub pt: u8,
+        pub length: U16Be,
+    }
 }
 
 impl Header {
@@ -22,16 +26,43 @@ pub fn looks_like(data: &[u8]) -> bool {
     v == 0x80 && (200..=204).contains(&pt)
 }
 
-pub fn decode(data: &[u8]) {
-    if data.len() < Header::SIZE {
-        println!("RTCP (truncated) {}B", data.len());
-        return;
+/// Confidence that the payload is a compound RTCP packet, lengths chaining to its end
+pub fn probe(data: &[u8]) -> u8 {
+    if !looks_like(data) {
+        return confidence::NONE;
     }
-    let (hdr, _) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        let length = u16::from_be(h.length);
-        println!("RTCP pt={} len={}", h.pt, length);
+    let mut r = ByteReader::new(data);
+    let mut chained = true;
+    while chained && r.peek().is_some_and(|b| b & 0xc0 == 0x80) {
+        // length in 32 bit words, minus one
+        chained = r
+            .view::<Header>()
+            .and_then(|h| r.skip(usize::from(h.length.get()) * 4))
+            .is_ok();
+    }
+    if chained && r.is_empty() {
+        confidence::HIGH
+    } else {
+        confidence::LOW
+    }
+}
+
+pub fn decode(data: &[u8]) {
+    if let Some(h) = utils::view::<Header>(data) {
+        let line = format!("RTCP pt={} len={}", h.pt, h.length);
+        println!("{}", line);
+        // length in 32 bit words, minus one
+        let len = ((usize::from(h.length.get()) + 1) * 4).min(data.len());
+        tree::layer("rtcp", &data[..len], line);
+        tree::field("Version", &h.v_p_count, h.v_p_count >> 6);
+        tree::field("Padding", &h.v_p_count, h.v_p_count & 0x20 != 0);
+        tree::field("Count", &h.v_p_count, h.v_p_count & 0x1f);
+        tree::field("Packet type", &h.pt, h.pt);
+        tree::field("Length", &h.length, h.length);
     } else {
-        println!("RTCP (truncated) {}B", data.len());
+        expert::error(DecodeError::Truncated {
+            layer: "rtcp",
+            offset: 0,
+        });
     }
 }
@@ -1,14 +1,18 @@
 // RTP minimal decoder (RFC 3550)
-use crate::utils::cow_struct;
+use super::detect::confidence;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be};
 
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub v_p_x_cc: u8,
-    pub m_pt: u8,
-    pub seq: u16,
-    pub timestamp: u32,
-    pub ssrc: u32,
+wire_struct! {
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        pub v_p_x_cc: u8,
+        pub m_pt: u8,
+        pub seq: U16Be,
+        pub timestamp: U32Be,
+        pub ssrc: U32Be,
+    }
 }
 
 impl Header {
@@ -31,27 +35,53 @@ pub fn looks_like(data: &[u8]) -> bool {
     true
 }
 
-pub fn decode(data: &[u8]) {
-    if data.len() < Header::SIZE {
-        println!("RTP (truncated) {}B", data.len());
-        return;
+/// Confidence that the payload is an RTP packet; any byte with version 2 looks like one
+pub fn probe(data: &[u8]) -> u8 {
+    if !looks_like(data) {
+        return confidence::NONE;
+    }
+    let cc = (data[0] & 0x0f) as usize;
+    if data.len() >= Header::SIZE + 4 * cc {
+        confidence::LOW
+    } else {
+        confidence::NONE
     }
-    let (hdr, _) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
+}
+
+pub fn decode(data: &[u8]) {
+    if let Some(h) = utils::view::<Header>(data) {
         let m = (h.m_pt & 0x80) != 0;
         let pt = h.m_pt & 0x7F;
         let cc = h.v_p_x_cc & 0x0F;
-        println!(
+        let line = format!(
             "RTP v={} pt={} M={} CC={} seq={} ts={} ssrc=0x{:08x}",
             (h.v_p_x_cc >> 6) & 3,
             pt,
             m as u8,
             cc,
-            u16::from_be(h.seq),
-            u32::from_be(h.timestamp),
-            u32::from_be(h.ssrc)
+            h.seq,
+            h.timestamp,
+            h.ssrc.get()
         );
+        println!("{}", line);
+        let len = (Header::SIZE + 4 * usize::from(cc)).min(data.len());
+        tree::layer("rtp", &data[..len], line);
+        tree::field("Version", &h.v_p_x_cc, (h.v_p_x_cc >> 6) & 3);
+        tree::field("Padding", &h.v_p_x_cc, h.v_p_x_cc & 0x20 != 0);
+        tree::field("Extension", &h.v_p_x_cc, h.v_p_x_cc & 0x10 != 0);
+        tree::field("CSRC count", &h.v_p_x_cc, cc);
+        tree::field("Marker", &h.m_pt, m);
+        tree::field("Payload type", &h.m_pt, pt);
+        tree::field("Sequence number", &h.seq, h.seq);
+        tree::field("Timestamp", &h.timestamp, h.timestamp);
+        tree::field("SSRC", &h.ssrc, format_args!("0x{:08x}", h.ssrc.get()));
+        for csrc in data[Header::SIZE..len].chunks_exact(4) {
+            tree::field("CSRC", csrc, tree::hex(csrc));
+        }
     } else {
-        println!("RTP (truncated) {}B", data.len());
+        expert::error(DecodeError::Truncated {
+            layer: "rtp",
+            offset: 0,
+        });
     }
 }
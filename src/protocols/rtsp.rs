@@ -1,10 +1,30 @@
 // RTSP minimal decoder (HTTP-like)
+use super::detect::confidence;
+use crate::tree;
+
+/// Confidence that the payload is an RTSP request or response
+pub fn probe(data: &[u8]) -> u8 {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.starts_with(b"RTSP/1.0 ")
+        || line.starts_with(b"RTSP/2.0 ")
+        || line.ends_with(b" RTSP/1.0")
+    {
+        confidence::CERTAIN
+    } else {
+        confidence::NONE
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if let Ok(s) = std::str::from_utf8(data) {
         if let Some(line) = s.lines().next() {
             println!("RTSP: {}", line.trim());
+            tree::layer("rtsp", data, format_args!("RTSP: {}", line.trim()));
+            tree::field("Start line", line.as_bytes(), line.trim());
             return;
         }
     }
     println!("RTSP ({}B)", data.len());
+    tree::layer("rtsp", data, format_args!("RTSP ({}B)", data.len()));
 }
@@ -1,13 +1,16 @@
 // SCTP minimal common header (RFC 4960)
-use crate::utils::cow_struct;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be};
 
-#[derive(Default, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub src_port: u16,
-    pub dst_port: u16,
-    pub vtag: u32,
-    pub checksum: u32,
+wire_struct! {
+    #[derive(Default, Clone, Copy)]
+    pub struct Header {
+        pub src_port: U16Be,
+        pub dst_port: U16Be,
+        pub vtag: U32Be,
+        pub checksum: U32Be,
+    }
 }
 
 impl Header {
@@ -15,19 +18,31 @@ impl Header {
 }
 
 pub fn decode(data: &[u8]) {
-    if data.len() < Header::SIZE {
-        println!("SCTP (truncated) {}B", data.len());
-        return;
-    }
-    let (hdr, _) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        println!(
+    if let Some(h) = utils::view::<Header>(data) {
+        let line = format!(
             "SCTP {} -> {} vtag=0x{:08x}",
-            u16::from_be(h.src_port),
-            u16::from_be(h.dst_port),
-            u32::from_be(h.vtag)
+            h.src_port,
+            h.dst_port,
+            h.vtag.get()
+        );
+        println!("{}", line);
+        tree::layer("sctp", h, line);
+        tree::field("Source port", &h.src_port, h.src_port);
+        tree::field("Destination port", &h.dst_port, h.dst_port);
+        tree::field(
+            "Verification tag",
+            &h.vtag,
+            format_args!("0x{:08x}", h.vtag.get()),
+        );
+        tree::field(
+            "Checksum",
+            &h.checksum,
+            format_args!("0x{:08x}", h.checksum.get()),
         );
     } else {
-        println!("SCTP (truncated) {}B", data.len());
+        expert::error(DecodeError::Truncated {
+            layer: "sctp",
+            offset: 0,
+        });
     }
 }
@@ -0,0 +1,169 @@
+// Generated by scripts/gen-services.py from a services(5) file taken from the IANA
+// service name and transport protocol port number registry. Do not edit.
+//
+// Source: /etc/services, whose header reads
+// > Network services, Internet style
+// > Updated from https://www.iana.org/assignments/service-names-port-numbers/service-names-port-numbers.xhtml .
+use super::ip::IpProtocol;
+
+/// Service name registered by IANA for a well-known port (0 to 1023)
+pub fn service_name(transport: IpProtocol, port: u16) -> Option<&'static str> {
+    let table = match transport {
+        IpProtocol::Tcp => TCP,
+        IpProtocol::Udp => UDP,
+        IpProtocol::Sctp => SCTP,
+        IpProtocol::Dccp => DCCP,
+        _ => return None,
+    };
+    table
+        .binary_search_by_key(&port, |&(p, _)| p)
+        .ok()
+        .map(|i| table[i].1)
+}
+
+const TCP: &[(u16, &str)] = &[
+    (1, "tcpmux"),
+    (7, "echo"),
+    (9, "discard"),
+    (11, "systat"),
+    (13, "daytime"),
+    (15, "netstat"),
+    (17, "qotd"),
+    (19, "chargen"),
+    (20, "ftp-data"),
+    (21, "ftp"),
+    (22, "ssh"),
+    (23, "telnet"),
+    (25, "smtp"),
+    (37, "time"),
+    (43, "whois"),
+    (49, "tacacs"),
+    (53, "domain"),
+    (70, "gopher"),
+    (79, "finger"),
+    (80, "http"),
+    (88, "kerberos"),
+    (102, "iso-tsap"),
+    (104, "acr-nema"),
+    (106, "poppassd"),
+    (110, "pop3"),
+    (111, "sunrpc"),
+    (113, "auth"),
+    (119, "nntp"),
+    (135, "epmap"),
+    (139, "netbios-ssn"),
+    (143, "imap2"),
+    (161, "snmp"),
+    (162, "snmp-trap"),
+    (163, "cmip-man"),
+    (164, "cmip-agent"),
+    (174, "mailq"),
+    (179, "bgp"),
+    (199, "smux"),
+    (209, "qmtp"),
+    (210, "z3950"),
+    (345, "pawserv"),
+    (346, "zserv"),
+    (369, "rpc2portmap"),
+    (370, "codaauth2"),
+    (389, "ldap"),
+    (427, "svrloc"),
+    (443, "https"),
+    (444, "snpp"),
+    (445, "microsoft-ds"),
+    (464, "kpasswd"),
+    (465, "submissions"),
+    (487, "saft"),
+    (512, "exec"),
+    (513, "login"),
+    (514, "shell"),
+    (515, "printer"),
+    (538, "gdomap"),
+    (540, "uucp"),
+    (543, "klogin"),
+    (544, "kshell"),
+    (548, "afpovertcp"),
+    (554, "rtsp"),
+    (563, "nntps"),
+    (587, "submission"),
+    (607, "nqs"),
+    (628, "qmqp"),
+    (631, "ipp"),
+    (636, "ldaps"),
+    (646, "ldp"),
+    (655, "tinc"),
+    (706, "silc"),
+    (749, "kerberos-adm"),
+    (750, "kerberos4"),
+    (751, "kerberos-master"),
+    (754, "krb-prop"),
+    (775, "moira-db"),
+    (777, "moira-update"),
+    (783, "spamd"),
+    (853, "domain-s"),
+    (871, "supfilesrv"),
+    (873, "rsync"),
+    (989, "ftps-data"),
+    (990, "ftps"),
+    (992, "telnets"),
+    (993, "imaps"),
+    (995, "pop3s"),
+];
+
+const UDP: &[(u16, &str)] = &[
+    (7, "echo"),
+    (9, "discard"),
+    (13, "daytime"),
+    (19, "chargen"),
+    (21, "fsp"),
+    (37, "time"),
+    (49, "tacacs"),
+    (53, "domain"),
+    (67, "bootps"),
+    (68, "bootpc"),
+    (69, "tftp"),
+    (88, "kerberos"),
+    (111, "sunrpc"),
+    (123, "ntp"),
+    (137, "netbios-ns"),
+    (138, "netbios-dgm"),
+    (161, "snmp"),
+    (162, "snmp-trap"),
+    (163, "cmip-man"),
+    (164, "cmip-agent"),
+    (177, "xdmcp"),
+    (213, "ipx"),
+    (319, "ptp-event"),
+    (320, "ptp-general"),
+    (369, "rpc2portmap"),
+    (370, "codaauth2"),
+    (371, "clearcase"),
+    (389, "ldap"),
+    (427, "svrloc"),
+    (443, "https"),
+    (464, "kpasswd"),
+    (500, "isakmp"),
+    (512, "biff"),
+    (513, "who"),
+    (514, "syslog"),
+    (517, "talk"),
+    (518, "ntalk"),
+    (520, "route"),
+    (538, "gdomap"),
+    (546, "dhcpv6-client"),
+    (547, "dhcpv6-server"),
+    (554, "rtsp"),
+    (623, "asf-rmcp"),
+    (636, "ldaps"),
+    (646, "ldp"),
+    (655, "tinc"),
+    (750, "kerberos4"),
+    (751, "kerberos-master"),
+    (752, "passwd-server"),
+    (779, "moira-ureg"),
+    (853, "domain-s"),
+];
+
+const SCTP: &[(u16, &str)] = &[];
+
+const DCCP: &[(u16, &str)] = &[];
@@ -1,4 +1,40 @@
 // SIP parse: print request/response line and core headers
+use super::detect::confidence;
+use crate::tree;
+
+/// Request methods (RFC 3261 and extensions)
+const METHODS: [&str; 14] = [
+    "INVITE",
+    "REGISTER",
+    "ACK",
+    "BYE",
+    "CANCEL",
+    "OPTIONS",
+    "SUBSCRIBE",
+    "NOTIFY",
+    "MESSAGE",
+    "INFO",
+    "PRACK",
+    "UPDATE",
+    "REFER",
+    "PUBLISH",
+];
+
+/// Confidence that the payload is a SIP request or response
+pub fn probe(data: &[u8]) -> u8 {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if line.starts_with(b"SIP/2.0 ") {
+        return confidence::CERTAIN;
+    }
+    let method = line.split(|&b| b == b' ').next().unwrap_or_default();
+    if METHODS.iter().any(|m| m.as_bytes() == method) && line.ends_with(b" SIP/2.0") {
+        confidence::CERTAIN
+    } else {
+        confidence::NONE
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if let Ok(s) = std::str::from_utf8(data) {
         let mut lines = s.lines();
@@ -31,7 +67,20 @@ pub fn decode(data: &[u8]) {
                         cseq = l.trim();
                     }
                 }
-                println!("SIP {} | {} | {} | {}", first.trim(), from, to, call_id);
+                let line = format!("SIP {} | {} | {} | {}", first.trim(), from, to, call_id);
+                println!("{}", line);
+                tree::layer("sip", data, line);
+                tree::field("Start line", first.as_bytes(), first.trim());
+                for (name, header) in [
+                    ("From", from),
+                    ("To", to),
+                    ("Call-ID", call_id),
+                    ("CSeq", cseq),
+                ] {
+                    if !header.is_empty() {
+                        tree::field(name, header.as_bytes(), header);
+                    }
+                }
                 if !cseq.is_empty() {
                     println!("SIP {}", cseq);
                 }
@@ -40,4 +89,5 @@ pub fn decode(data: &[u8]) {
         }
     }
     println!("SIP ({}B)", data.len());
+    tree::layer("sip", data, format_args!("SIP ({}B)", data.len()));
 }
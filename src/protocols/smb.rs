@@ -1,20 +1,394 @@
 // SMB decoder: differentiate SMB1 and SMB2/3 and show command
+// With file extraction enabled, `Connection` follows an SMB2 connection to rebuild the
+// files read and written: CREATE gives the name of a FileId, READ responses and WRITE
+// requests carry chunks at an offset, CLOSE ends the file.
+//
+// Sources:
+// * https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-smb2
+use std::collections::HashMap;
+
+use super::detect::confidence;
+use crate::analysis::extract::{self, Sparse};
+use crate::flow::FlowKey;
+use crate::reassembly::StreamHandler;
+use crate::tree;
+
+/// SMB2 commands
+#[non_exhaustive]
+pub struct COMMAND;
+
+impl COMMAND {
+    pub const NEGOTIATE: u16 = 0x00;
+    pub const SESSION_SETUP: u16 = 0x01;
+    pub const TREE_CONNECT: u16 = 0x03;
+    pub const CREATE: u16 = 0x05;
+    pub const CLOSE: u16 = 0x06;
+    pub const READ: u16 = 0x08;
+    pub const WRITE: u16 = 0x09;
+}
+
+/// Size of the SMB2 header
+pub const HEADER_SIZE: usize = 64;
+/// SMB2 header flag of responses
+const FLAGS_SERVER_TO_REDIR: u32 = 0x1;
+/// Interim response of an asynchronous operation
+const STATUS_PENDING: u32 = 0x0000_0103;
+/// Files tracked per connection
+const MAX_FILES: usize = 256;
+/// Requests waiting for their response per connection, the oldest is forgotten past it
+const MAX_PENDING: usize = 1024;
+/// NetBIOS session messages buffered at most
+const MAX_MESSAGE: usize = 16 * 1024 * 1024;
+
+fn command_as_str(cmd: u16) -> &'static str {
+    match cmd {
+        0x00 => "NEGOTIATE",
+        0x01 => "SESSION_SETUP",
+        0x02 => "LOGOFF",
+        0x03 => "TREE_CONNECT",
+        0x04 => "TREE_DISCONNECT",
+        0x05 => "CREATE",
+        0x06 => "CLOSE",
+        0x07 => "FLUSH",
+        0x08 => "READ",
+        0x09 => "WRITE",
+        0x0a => "LOCK",
+        0x0b => "IOCTL",
+        0x0c => "CANCEL",
+        0x0d => "ECHO",
+        0x0e => "QUERY_DIRECTORY",
+        0x0f => "CHANGE_NOTIFY",
+        0x10 => "QUERY_INFO",
+        0x11 => "SET_INFO",
+        0x12 => "OPLOCK_BREAK",
+        _ => "UNKNOW",
+    }
+}
+
+/// Add the fields of an SMB2 header to the tree
+fn header_fields(data: &[u8], h: &Header) {
+    tree::field("Protocol", &data[..4], "SMB2");
+    if let Some(len) = le16(data, 4) {
+        tree::field("Header length", &data[4..6], len);
+    }
+    tree::field("Status", &data[8..12], format_args!("0x{:08x}", h.status));
+    tree::field(
+        "Command",
+        &data[12..14],
+        format_args!("{} ({})", command_as_str(h.command), h.command),
+    );
+    tree::field(
+        "Flags",
+        &data[16..20],
+        format_args!(
+            "0x{:08x} ({})",
+            h.flags,
+            if h.is_response() {
+                "response"
+            } else {
+                "request"
+            }
+        ),
+    );
+    tree::field("Chain offset", &data[20..24], h.next_command);
+    tree::field("Message ID", &data[24..32], h.message_id);
+    if let Some(session) = le64(data, 40) {
+        tree::field(
+            "Session ID",
+            &data[40..48],
+            format_args!("0x{:016x}", session),
+        );
+    }
+    if let Some(signature) = data.get(48..HEADER_SIZE) {
+        tree::field("Signature", signature, tree::hex(signature));
+    }
+}
+
+fn le16(d: &[u8], i: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(d.get(i..i + 2)?.try_into().ok()?))
+}
+
+fn le32(d: &[u8], i: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(d.get(i..i + 4)?.try_into().ok()?))
+}
+
+fn le64(d: &[u8], i: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(d.get(i..i + 8)?.try_into().ok()?))
+}
+
+fn file_id(d: &[u8], i: usize) -> Option<[u8; 16]> {
+    d.get(i..i + 16)?.try_into().ok()
+}
+
+/// Fields of the SMB2 header used to pair requests and responses
+#[derive(Debug, Clone, Copy)]
+pub struct Header {
+    pub command: u16,
+    pub status: u32,
+    pub flags: u32,
+    /// offset of the next message of a compound, 0 for the last one
+    pub next_command: u32,
+    pub message_id: u64,
+}
+
+impl Header {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.get(..4)? != b"\xfeSMB" {
+            return None;
+        }
+        Some(Header {
+            status: le32(data, 8)?,
+            command: le16(data, 12)?,
+            flags: le32(data, 16)?,
+            next_command: le32(data, 20)?,
+            message_id: le64(data, 24)?,
+        })
+    }
+
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAGS_SERVER_TO_REDIR != 0
+    }
+}
+
+/// Request waiting for its response
+enum Pending {
+    Create(String),
+    Read { file: [u8; 16], offset: u64 },
+}
+
+#[derive(Default)]
+struct File {
+    name: String,
+    /// end of file announced by the CREATE response
+    size: u64,
+    content: Sparse,
+}
+
+/// Follows the files of an SMB2 connection
+#[derive(Default)]
+pub struct Connection {
+    buf: [Vec<u8>; 2],
+    pending: HashMap<u64, Pending>,
+    files: HashMap<[u8; 16], File>,
+}
+
+impl Connection {
+    fn expect(&mut self, message_id: u64, pending: Pending) {
+        // message ids grow, the lowest belongs to a response that will not come
+        if self.pending.len() >= MAX_PENDING {
+            if let Some(oldest) = self.pending.keys().min().copied() {
+                self.pending.remove(&oldest);
+            }
+        }
+        self.pending.insert(message_id, pending);
+    }
+
+    fn on_message(&mut self, flow: &FlowKey, msg: &[u8]) {
+        let h = match Header::parse(msg) {
+            Some(h) => h,
+            None => return,
+        };
+        let body = HEADER_SIZE;
+        if !h.is_response() {
+            match h.command {
+                COMMAND::CREATE => {
+                    let name = (|| {
+                        let off = le16(msg, body + 44)? as usize;
+                        let len = le16(msg, body + 46)? as usize;
+                        let raw = msg.get(off..off + len)?;
+                        let units: Vec<u16> = raw
+                            .chunks_exact(2)
+                            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                            .collect();
+                        Some(String::from_utf16_lossy(&units))
+                    })();
+                    if let Some(name) = name {
+                        self.expect(h.message_id, Pending::Create(name));
+                    }
+                }
+                COMMAND::READ => {
+                    if let (Some(offset), Some(file)) =
+                        (le64(msg, body + 8), file_id(msg, body + 16))
+                    {
+                        self.expect(h.message_id, Pending::Read { file, offset });
+                    }
+                }
+                COMMAND::WRITE => {
+                    let chunk = (|| {
+                        let off = le16(msg, body + 2)? as usize;
+                        let len = le32(msg, body + 4)? as usize;
+                        Some((
+                            le64(msg, body + 8)?,
+                            file_id(msg, body + 16)?,
+                            msg.get(off..off + len)?,
+                        ))
+                    })();
+                    if let Some((offset, id, data)) = chunk {
+                        if let Some(file) = self.files.get_mut(&id) {
+                            file.content.write(offset, data);
+                        }
+                    }
+                }
+                COMMAND::CLOSE => {
+                    if let Some(id) = file_id(msg, body + 8) {
+                        if let Some(file) = self.files.remove(&id) {
+                            emit(flow, file);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            return;
+        }
+        if h.status == STATUS_PENDING {
+            return;
+        }
+        let pending = match self.pending.remove(&h.message_id) {
+            Some(p) => p,
+            None => return,
+        };
+        if h.status != 0 {
+            return;
+        }
+        match (h.command, pending) {
+            (COMMAND::CREATE, Pending::Create(name)) => {
+                if let (Some(size), Some(id)) = (le64(msg, body + 48), file_id(msg, body + 64)) {
+                    if self.files.len() < MAX_FILES {
+                        self.files.insert(
+                            id,
+                            File {
+                                name,
+                                size,
+                                content: Sparse::default(),
+                            },
+                        );
+                    }
+                }
+            }
+            (COMMAND::READ, Pending::Read { file, offset }) => {
+                let data = (|| {
+                    let off = *msg.get(body + 2)? as usize;
+                    let len = le32(msg, body + 4)? as usize;
+                    msg.get(off..off + len)
+                })();
+                if let (Some(data), Some(f)) = (data, self.files.get_mut(&file)) {
+                    f.content.write(offset, data);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn emit(flow: &FlowKey, file: File) {
+    if file.content.received == 0 {
+        return;
+    }
+    // the file may have grown with the writes
+    let size = file.size.max(file.content.data().len() as u64);
+    extract::on_file(
+        "smb2",
+        flow,
+        Some(&file.name),
+        None,
+        file.content.data(),
+        size,
+    );
+}
+
+impl StreamHandler for Connection {
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]) {
+        let dir = usize::from(!from_client);
+        self.buf[dir].extend_from_slice(data);
+        let mut used = 0;
+        // NetBIOS session service framing: type, 3 bytes length
+        while let Some(hdr) = self.buf[dir].get(used..used + 4) {
+            let len = u32::from_be_bytes([0, hdr[1], hdr[2], hdr[3]]) as usize;
+            if len > MAX_MESSAGE {
+                self.buf[dir] = Vec::new();
+                return;
+            }
+            let msg = match self.buf[dir].get(used + 4..used + 4 + len) {
+                Some(m) => m.to_vec(),
+                None => break,
+            };
+            used += 4 + len;
+            // compounded requests and responses share the frame
+            let mut offset = 0;
+            while let Some(part) = msg.get(offset..) {
+                let next = Header::parse(part).map_or(0, |h| h.next_command as usize);
+                let end = if next > 0 {
+                    next.min(part.len())
+                } else {
+                    part.len()
+                };
+                self.on_message(flow, &part[..end]);
+                if next == 0 {
+                    break;
+                }
+                offset += next;
+            }
+        }
+        self.buf[dir].drain(..used);
+    }
+
+    fn on_close(&mut self, flow: &FlowKey) {
+        for (_, file) in self.files.drain() {
+            emit(flow, file);
+        }
+    }
+}
+
+/// Stream handler following the files of an SMB2 connection, when extracting files
+pub fn connection(data: &[u8]) -> Option<Connection> {
+    let smb2 = data.get(4..8) == Some(b"\xfeSMB") || data.get(4..8) == Some(b"\xffSMB");
+    (extract::enabled() && data.first() == Some(&0) && smb2).then(Connection::default)
+}
+
+/// Confidence that the payload is an SMB message, after its NetBIOS session header or not
+pub fn probe(data: &[u8]) -> u8 {
+    let magic = |d: Option<&[u8]>| matches!(d, Some(b"\xffSMB" | b"\xfeSMB" | b"\xfdSMB"));
+    if data.first() == Some(&0) && magic(data.get(4..8)) {
+        confidence::CERTAIN
+    } else if magic(data.get(..4)) {
+        confidence::HIGH
+    } else {
+        confidence::NONE
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if data.len() >= 4 {
         if &data[0..4] == b"\xffSMB" {
             let cmd = data.get(4).copied().unwrap_or(0);
             println!("SMB1 cmd=0x{:02x}", cmd);
+            tree::layer("smb", data, format_args!("SMB1 cmd=0x{:02x}", cmd));
+            tree::field("Protocol", &data[..4], "SMB1");
+            if let Some(cmd) = data.get(4..5) {
+                tree::field("Command", cmd, format_args!("0x{:02x}", cmd[0]));
+            }
             return;
         }
         if data[0] == 0xfe && &data[1..4] == b"SMB" {
             if data.len() >= 16 {
                 let cmd = u16::from_le_bytes([data[12], data[13]]);
                 println!("SMB2/3 cmd=0x{:04x}", cmd);
+                tree::layer("smb", data, format_args!("SMB2/3 {}", command_as_str(cmd)));
+                match Header::parse(data) {
+                    Some(h) => header_fields(data, &h),
+                    None => tree::field(
+                        "Command",
+                        &data[12..14],
+                        format_args!("{} ({})", command_as_str(cmd), cmd),
+                    ),
+                }
             } else {
                 println!("SMB2/3");
+                tree::layer("smb", data, "SMB2/3");
             }
             return;
         }
     }
     println!("SMB/CIFS ({}B)", data.len());
+    tree::layer("smb", data, format_args!("SMB/CIFS ({}B)", data.len()));
 }
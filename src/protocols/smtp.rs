@@ -1,10 +1,159 @@
 // Minimal SMTP decoder
+// With file extraction enabled, `Session` follows the commands of the client to collect
+// the messages sent with DATA or BDAT and extracts their MIME attachments.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc5321#section-4.5.2 (dot stuffing)
+// * https://www.rfc-editor.org/rfc/rfc3030 (BDAT)
+use crate::analysis::extract::{self, Buffer};
+use crate::flow::FlowKey;
+use crate::reassembly::StreamHandler;
+use crate::tree;
+
+use super::detect::confidence;
+use super::mime;
+
+/// Longest command line accepted
+const MAX_LINE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Command,
+    /// message up to a line holding a single dot
+    Data,
+    /// BDAT chunk: bytes left, last chunk
+    Chunk(usize, bool),
+}
+
+/// Follows the client side of an SMTP session
+pub struct Session {
+    buf: Vec<u8>,
+    state: State,
+    message: Buffer,
+}
+
+impl Session {
+    fn on_message(&mut self, flow: &FlowKey) {
+        let message = self.message.take();
+        for part in mime::parts(&message) {
+            if part.attachment {
+                let size = part.data.len() as u64;
+                extract::on_file(
+                    "smtp",
+                    flow,
+                    part.filename.as_deref(),
+                    Some(&part.content_type),
+                    &part.data,
+                    size,
+                );
+            }
+        }
+    }
+
+    fn add(&mut self, data: &[u8]) {
+        self.message.extend(data);
+    }
+}
+
+impl StreamHandler for Session {
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]) {
+        if !from_client {
+            return;
+        }
+        self.buf.extend_from_slice(data);
+        let mut used = 0;
+        loop {
+            if let State::Chunk(left, last) = self.state {
+                let n = left.min(self.buf.len() - used);
+                let chunk = self.buf[used..used + n].to_vec();
+                self.add(&chunk);
+                used += n;
+                if n < left {
+                    self.state = State::Chunk(left - n, last);
+                    break;
+                }
+                self.state = State::Command;
+                if last {
+                    self.on_message(flow);
+                }
+                continue;
+            }
+            let eol = match self.buf[used..].iter().position(|b| *b == b'\n') {
+                Some(p) => used + p + 1,
+                None => break,
+            };
+            let line = self.buf[used..eol].to_vec();
+            used = eol;
+            if self.state == State::Data {
+                let text = line.strip_suffix(b"\n").unwrap_or(&line);
+                let text = text.strip_suffix(b"\r").unwrap_or(text);
+                if text == b"." {
+                    self.state = State::Command;
+                    self.on_message(flow);
+                } else {
+                    // a leading dot of the content was doubled
+                    self.add(line.strip_prefix(b".").unwrap_or(&line));
+                }
+                continue;
+            }
+            let cmd = String::from_utf8_lossy(&line).trim().to_ascii_uppercase();
+            let mut words = cmd.split_whitespace();
+            match words.next() {
+                Some("DATA") => {
+                    self.message.clear();
+                    self.state = State::Data;
+                }
+                Some("BDAT") => {
+                    let size = words.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+                    let last = words.next() == Some("LAST");
+                    self.state = State::Chunk(size, last);
+                }
+                Some("RSET") => self.message.clear(),
+                _ => {}
+            }
+        }
+        self.buf.drain(..used);
+        if self.state == State::Command && self.buf.len() > MAX_LINE {
+            self.buf.clear();
+        }
+    }
+}
+
+/// Stream handler of an SMTP session when extracting files
+pub fn session(flow: &FlowKey) -> Option<Session> {
+    let port = matches!(flow.dst_port, 25 | 465 | 587);
+    (extract::enabled() && port).then(|| Session {
+        buf: Vec::new(),
+        state: State::Command,
+        message: Buffer::default(),
+    })
+}
+
+/// Confidence that the payload is an SMTP greeting or command
+pub fn probe(data: &[u8]) -> u8 {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    let line = String::from_utf8_lossy(&line[..line.len().min(128)]).to_ascii_uppercase();
+    let greeting = line.starts_with("220") && line.contains("SMTP");
+    if line.starts_with("EHLO ") || line.starts_with("HELO ") || greeting {
+        confidence::CERTAIN
+    } else if line.starts_with("MAIL FROM:") || line.starts_with("RCPT TO:") {
+        confidence::HIGH
+    } else if line.starts_with("220 ") || line.starts_with("220-") {
+        confidence::LOW
+    } else {
+        confidence::NONE
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if let Ok(s) = std::str::from_utf8(data) {
         if let Some(line) = s.lines().next() {
             println!("SMTP: {}", line.trim());
+            tree::layer("smtp", data, format_args!("SMTP: {}", line.trim()));
+            tree::field("Line", line.as_bytes(), line.trim());
             return;
         }
     }
     println!("SMTP ({}B)", data.len());
+    tree::layer("smtp", data, format_args!("SMTP ({}B)", data.len()));
 }
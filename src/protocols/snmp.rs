@@ -1,81 +1,134 @@
 // SNMP BER peek: version, community, PDU type
-fn ber_len(bytes: &[u8], i: &mut usize) -> Option<usize> {
-    if *i >= bytes.len() {
-        return None;
+// The community of SNMPv1 and v2c requests and traps is a password sent in the clear, it
+// is reported as a credential.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc3416#section-3
+use super::detect::confidence;
+use crate::analysis::credentials;
+use crate::context;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
+/// PDU type of responses, which repeat the community of the request
+const GET_RESPONSE: u8 = 0xa2;
+
+fn pdu_as_str(pdu: u8) -> &'static str {
+    match pdu {
+        0xa0 => "get-request",
+        0xa1 => "get-next-request",
+        0xa2 => "get-response",
+        0xa3 => "set-request",
+        0xa4 => "trap",
+        0xa5 => "getBulkRequest",
+        0xa6 => "inform-request",
+        0xa7 => "snmpV2-trap",
+        0xa8 => "report",
+        _ => "UNKNOW",
     }
-    let b = bytes[*i];
-    *i += 1;
-    if b & 0x80 == 0 {
-        return Some(b as usize);
+}
+
+/// Confidence that the payload is an SNMP message: a SEQUENCE starting with the version,
+/// followed by the community (v1, v2c) or the header data (v3)
+pub fn probe(data: &[u8]) -> u8 {
+    let mut r = ByteReader::new(data);
+    if !r.eat(0x30) {
+        return confidence::NONE;
     }
-    let n = (b & 0x7F) as usize;
-    if n == 0 || *i + n > bytes.len() || n > 4 {
-        return None;
+    match r.ber_len() {
+        Ok(len) if len == r.remaining() => {}
+        _ => return confidence::NONE,
     }
-    let mut v = 0usize;
-    for _ in 0..n {
-        v = (v << 8) | (bytes[*i] as usize);
-        *i += 1;
+    match r.rest().get(..4) {
+        Some([0x02, 0x01, 0 | 1, 0x04]) | Some([0x02, 0x01, 3, 0x30]) => confidence::HIGH,
+        _ => confidence::NONE,
     }
-    Some(v)
 }
 
 pub fn decode(data: &[u8]) {
-    let mut i = 0usize;
-    if i >= data.len() || data[i] != 0x30 {
-        println!("SNMP ({}B)", data.len());
+    let mut r = ByteReader::new(data);
+    if !r.eat(0x30) {
+        expert::error(DecodeError::Malformed {
+            layer: "snmp",
+            offset: 0,
+            reason: "message is not a SEQUENCE",
+        });
         return;
     }
-    i += 1;
-    let _seq_len = match ber_len(data, &mut i) {
-        Some(v) => v,
-        None => {
-            println!("SNMP (bad len)");
-            return;
-        }
-    };
-    // version: INTEGER
-    if i >= data.len() || data[i] != 0x02 {
-        println!("SNMP (no version)");
+    if r.ber_len().is_err() {
+        expert::error(DecodeError::Malformed {
+            layer: "snmp",
+            offset: r.position(),
+            reason: "invalid message length",
+        });
         return;
     }
-    i += 1;
-    let vlen = match ber_len(data, &mut i) {
-        Some(v) => v,
-        None => {
-            println!("SNMP (bad ver)");
+    // version: INTEGER
+    let version_at = r.position();
+    let version = match r.ber() {
+        Ok((0x02, [v])) => u64::from(*v),
+        Ok((0x02, _)) => 0,
+        _ => {
+            expert::error(DecodeError::Malformed {
+                layer: "snmp",
+                offset: version_at,
+                reason: "no version INTEGER",
+            });
             return;
         }
     };
-    if i + vlen > data.len() {
-        println!("SNMP (trunc ver)");
-        return;
-    }
-    let version = if vlen == 1 { data[i] as u64 } else { 0 };
-    i += vlen;
-    // community: OCTET STRING
-    if i >= data.len() || data[i] != 0x04 {
-        println!("SNMP v{} (no community)", version);
-        return;
-    }
-    i += 1;
-    let clen = match ber_len(data, &mut i) {
-        Some(v) => v,
-        None => {
-            println!("SNMP v{} (bad comm)", version);
+    // community: OCTET STRING; SNMPv3 has header data instead
+    let at = r.position();
+    let community = match r.ber() {
+        Ok((0x04, community)) => std::str::from_utf8(community).unwrap_or(""),
+        Ok((0x30, _)) if version == 3 => {
+            println!("SNMP v3");
+            tree::layer("snmp", data, "SNMP v3");
+            tree::field("Version", &data[version_at..at], version);
+            tree::field("Header data", &data[at..r.position()], "msgGlobalData");
+            return;
+        }
+        _ => {
+            expert::error(DecodeError::Malformed {
+                layer: "snmp",
+                offset: at,
+                reason: "no community OCTET STRING",
+            });
             return;
         }
     };
-    if i + clen > data.len() {
-        println!("SNMP v{} (trunc comm)", version);
-        return;
-    }
-    let community = std::str::from_utf8(&data[i..i + clen]).unwrap_or("");
-    i += clen;
     // PDU type: context-specific (0xA0..)
-    let pdu = data.get(i).copied().unwrap_or(0);
-    println!(
+    let pdu = r.peek().unwrap_or(0);
+    let line = format!(
         "SNMP v{} community={} pdu=0x{:02x}",
-        version, community, pdu
+        version,
+        credentials::mask(community),
+        pdu
+    );
+    println!("{}", line);
+    tree::layer("snmp", data, line);
+    tree::field("Version", &data[version_at..at], version);
+    tree::field(
+        "Community",
+        &data[at..r.position()],
+        credentials::mask(community),
     );
+    if !r.is_empty() {
+        tree::field(
+            "PDU type",
+            r.rest(),
+            format_args!("{} (0x{:02x})", pdu_as_str(pdu), pdu),
+        );
+    }
+    // version 0 is SNMPv1, 1 is SNMPv2c; SNMPv3 has no community
+    if version <= 1 && pdu != GET_RESPONSE {
+        credentials::on_credential(
+            "snmp",
+            "community",
+            context::flow().as_ref(),
+            None,
+            community,
+        );
+    }
 }
@@ -1,5 +1,21 @@
 // Minimal SSDP decoder (UPnP over UDP/1900)
 // SSDP is HTTPU: print request/response line and ST/NT/USN headers if present.
+use super::detect::confidence;
+use crate::tree;
+
+/// Confidence that the payload is an SSDP search, notification or search response
+pub fn probe(data: &[u8]) -> u8 {
+    if data.starts_with(b"M-SEARCH * HTTP/1.1") || data.starts_with(b"NOTIFY * HTTP/1.1") {
+        return confidence::CERTAIN;
+    }
+    let head = &data[..data.len().min(512)];
+    let usn = head.windows(5).any(|w| w.eq_ignore_ascii_case(b"\nUSN:"));
+    if data.starts_with(b"HTTP/1.1 200") && usn {
+        confidence::HIGH
+    } else {
+        confidence::NONE
+    }
+}
 
 pub fn decode(data: &[u8]) {
     let max = core::cmp::min(512, data.len());
@@ -20,14 +36,23 @@ pub fn decode(data: &[u8]) {
                     usn = l.trim();
                 }
             }
-            println!(
+            let line = format!(
                 "SSDP {} {} {}",
                 first.trim(),
                 st,
                 if !nt.is_empty() { nt } else { usn }
             );
+            println!("{}", line);
+            tree::layer("ssdp", data, line);
+            tree::field("Start line", first.as_bytes(), first.trim());
+            for (name, header) in [("ST", st), ("NT", nt), ("USN", usn)] {
+                if !header.is_empty() {
+                    tree::field(name, header.as_bytes(), header);
+                }
+            }
             return;
         }
     }
     println!("SSDP ({}B)", data.len());
+    tree::layer("ssdp", data, format_args!("SSDP ({}B)", data.len()));
 }
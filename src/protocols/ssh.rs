@@ -1,9 +1,25 @@
 // SSH: banner or binary packet length
+use super::detect::confidence;
+use crate::tree;
+
+/// Confidence that the payload is SSH: the version banner starts both directions
+pub fn probe(data: &[u8]) -> u8 {
+    if data.starts_with(b"SSH-2.0-") || data.starts_with(b"SSH-1.99-") {
+        confidence::CERTAIN
+    } else if data.starts_with(b"SSH-") {
+        confidence::HIGH
+    } else {
+        confidence::NONE
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if let Ok(s) = std::str::from_utf8(data) {
         if let Some(line) = s.lines().next() {
             if line.starts_with("SSH-") {
                 println!("SSH banner: {}", line.trim());
+                tree::layer("ssh", data, format_args!("SSH banner: {}", line.trim()));
+                tree::field("Protocol version", line.as_bytes(), line.trim());
                 return;
             }
         }
@@ -12,8 +28,16 @@ pub fn decode(data: &[u8]) {
         let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
         if len + 4 <= data.len() && len < 35000 {
             println!("SSH binary packet len={}", len);
+            tree::layer(
+                "ssh",
+                &data[..4 + len],
+                format_args!("SSH binary packet len={}", len),
+            );
+            tree::field("Packet length", &data[..4], len);
+            tree::field("Padding length", &data[4..5], data[4]);
             return;
         }
     }
     println!("SSH ({}B)", data.len());
+    tree::layer("ssh", data, format_args!("SSH ({}B)", data.len()));
 }
@@ -1,5 +1,129 @@
 // SSL alias module: reuse TLS decoder
+// Also understands the SSL 2.0 ClientHello that very old clients (or clients configured
+// for maximum compatibility) still send before the first TLS record.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc5246#appendix-E.2
+// * https://www.rfc-editor.org/rfc/rfc6176
+use super::tls;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
+/// SSL 2.0 message types
+#[non_exhaustive]
+pub struct MSG;
+
+impl MSG {
+    pub const CLIENT_HELLO: u8 = 1;
+}
+
+/// SSL 2.0 ClientHello, possibly offering TLS versions (v2 compatible hello)
+#[derive(Debug, Clone)]
+pub struct ClientHelloV2<'a> {
+    /// highest version supported by the client, 0x0002 for SSL 2.0 only
+    pub version: u16,
+    /// 3 bytes cipher specs; TLS suites are encoded with a leading zero byte
+    pub cipher_specs: Vec<u32>,
+    pub session_id: &'a [u8],
+    pub challenge: &'a [u8],
+}
+
+impl<'a> ClientHelloV2<'a> {
+    /// Parse a record holding an SSL 2.0 ClientHello (2 bytes record header, no padding)
+    pub fn parse(data: &'a [u8]) -> Option<Self> {
+        let mut r = ByteReader::new(data);
+        let hdr = r.u16().ok()?;
+        if hdr & 0x8000 == 0 {
+            return None;
+        }
+        let mut r = ByteReader::new(r.bytes(usize::from(hdr & 0x7fff)).ok()?);
+        if r.u8().ok()? != MSG::CLIENT_HELLO {
+            return None;
+        }
+        let version = r.u16().ok()?;
+        if version != 0x0002 && (version >> 8) != 3 {
+            return None;
+        }
+        let (specs_len, session_len, challenge_len) = (r.u16().ok()?, r.u16().ok()?, r.u16().ok()?);
+        if specs_len % 3 != 0 {
+            return None;
+        }
+        let specs = r.bytes(specs_len.into()).ok()?;
+        let session_id = r.bytes(session_len.into()).ok()?;
+        let challenge = r.bytes(challenge_len.into()).ok()?;
+        let cipher_specs = specs
+            .chunks_exact(3)
+            .map(|c| u32::from_be_bytes([0, c[0], c[1], c[2]]))
+            .collect();
+        Some(ClientHelloV2 {
+            version,
+            cipher_specs,
+            session_id,
+            challenge,
+        })
+    }
+
+    /// TLS cipher suites among the cipher specs
+    pub fn tls_cipher_suites(&self) -> impl Iterator<Item = u16> + '_ {
+        self.cipher_specs
+            .iter()
+            .filter(|s| **s >> 16 == 0)
+            .map(|s| *s as u16)
+    }
+}
+
 // Returns true if it looked like TLS ClientHello.
 pub fn decode(data: &[u8]) -> bool {
-    super::tls::decode(data)
+    if tls::decode(data) {
+        return true;
+    }
+    match ClientHelloV2::parse(data) {
+        Some(ch) => {
+            let line = format!(
+                "SSLv2 ClientHello version={} cipher_specs={}",
+                tls::version_as_str(ch.version),
+                ch.cipher_specs.len()
+            );
+            println!("{}", line);
+            tree::layer("ssl", data, line);
+            tree::field(
+                "Length",
+                &data[..2],
+                u16::from_be_bytes([data[0] & 0x7f, data[1]]),
+            );
+            tree::field("Message type", &data[2..3], "ClientHello");
+            tree::field("Version", &data[3..5], tls::version_as_str(ch.version));
+            tree::field(
+                "Cipher specs",
+                &data[5..7],
+                format_args!("{} specs", ch.cipher_specs.len()),
+            );
+            tree::field("Session ID", ch.session_id, tree::hex(ch.session_id));
+            tree::field("Challenge", ch.challenge, tree::hex(ch.challenge));
+            true
+        }
+        // a 2 bytes record header then the ClientHello type and an SSL 2.0 or 3.x version
+        None => match data {
+            [h, l, MSG::CLIENT_HELLO, 0x00, 0x02, ..] | [h, l, MSG::CLIENT_HELLO, 0x03, ..]
+                if h & 0x80 != 0 =>
+            {
+                let len = usize::from(u16::from_be_bytes([h & 0x7f, *l]));
+                if len + 2 > data.len() {
+                    expert::error(DecodeError::Truncated {
+                        layer: "ssl",
+                        offset: data.len(),
+                    });
+                } else {
+                    expert::error(DecodeError::Malformed {
+                        layer: "ssl",
+                        offset: 2,
+                        reason: "SSL 2.0 ClientHello lengths do not fit the record",
+                    });
+                }
+                true
+            }
+            _ => false,
+        },
+    }
 }
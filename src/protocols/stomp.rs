@@ -1,5 +1,42 @@
 // Minimal STOMP decoder
 // STOMP frames are text-based, first line is the command (e.g., CONNECT, SEND)
+use super::detect::confidence;
+use crate::tree;
+
+/// Frame commands of STOMP 1.2
+const COMMANDS: [&str; 15] = [
+    "CONNECT",
+    "STOMP",
+    "CONNECTED",
+    "SEND",
+    "SUBSCRIBE",
+    "UNSUBSCRIBE",
+    "MESSAGE",
+    "RECEIPT",
+    "ERROR",
+    "ACK",
+    "NACK",
+    "BEGIN",
+    "COMMIT",
+    "ABORT",
+    "DISCONNECT",
+];
+
+/// Confidence that the payload is a STOMP frame: a command alone on its line
+pub fn probe(data: &[u8]) -> u8 {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or_default();
+    if line.len() == data.len() {
+        return confidence::NONE;
+    }
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    if !COMMANDS.iter().any(|c| c.as_bytes() == line) {
+        confidence::NONE
+    } else if data.contains(&0) {
+        confidence::CERTAIN
+    } else {
+        confidence::HIGH
+    }
+}
 
 pub fn decode(data: &[u8]) {
     let max = core::cmp::min(256, data.len());
@@ -7,8 +44,11 @@ pub fn decode(data: &[u8]) {
         if let Some(line) = s.lines().next() {
             let cmd = line.trim();
             println!("STOMP {}", cmd);
+            tree::layer("stomp", data, format_args!("STOMP {}", cmd));
+            tree::field("Command", cmd.as_bytes(), cmd);
             return;
         }
     }
     println!("STOMP ({}B)", data.len());
+    tree::layer("stomp", data, format_args!("STOMP ({}B)", data.len()));
 }
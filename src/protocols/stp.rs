@@ -1,34 +1,111 @@
 // STP BPDU minimal decoder
-pub fn decode(data: &[u8]) {
-    if data.len() < 4 {
-        return;
+use super::ethernet::mac_to_str;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{wire_struct, ByteReader, U16Be, U32Be};
+
+wire_struct! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct Header {
+        pub proto_id: U16Be,
+        pub version: u8,
+        pub bpdu_type: u8,
+    }
+}
+
+// Configuration BPDU, after the header; times are in 1/256 of a second
+wire_struct! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct Config {
+        pub flags: u8,
+        pub root_prio: U16Be,
+        pub root_mac: [u8; 6],
+        pub path_cost: U32Be,
+        pub bridge_prio: U16Be,
+        pub bridge_mac: [u8; 6],
+        pub port_id: U16Be,
+        pub message_age: U16Be,
+        pub max_age: U16Be,
+        pub hello_time: U16Be,
+        pub forward_delay: U16Be,
     }
-    let proto_id = u16::from_be_bytes([data[0], data[1]]);
-    let ver = data[2];
-    let typ = data[3];
-    println!(
+}
+
+pub fn decode(data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    let h = match r.view::<Header>() {
+        Ok(h) => h,
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "stp",
+                offset: 0,
+            });
+            return;
+        }
+    };
+    let line = format!(
         "STP proto_id=0x{:04x} ver={} type=0x{:02x}",
-        proto_id, ver, typ
+        h.proto_id.get(),
+        h.version,
+        h.bpdu_type
     );
-    if typ == 0x00 {
-        // Configuration BPDU
-        if data.len() < 35 {
-            return;
+    println!("{}", line);
+    tree::layer("stp", data, line);
+    tree::field(
+        "Protocol identifier",
+        &h.proto_id,
+        format_args!("0x{:04x}", h.proto_id.get()),
+    );
+    tree::field("Protocol version", &h.version, h.version);
+    tree::field(
+        "BPDU type",
+        &h.bpdu_type,
+        format_args!("0x{:02x}", h.bpdu_type),
+    );
+    if h.bpdu_type == 0x00 {
+        match r.view::<Config>() {
+            Ok(c) => {
+                println!(
+                    "STP cfg flags=0x{:02x} root={}:{} cost={} bridge={}:{} port=0x{:04x}",
+                    c.flags,
+                    c.root_prio,
+                    mac_to_str(&c.root_mac),
+                    c.path_cost,
+                    c.bridge_prio,
+                    mac_to_str(&c.bridge_mac),
+                    c.port_id.get()
+                );
+                tree::field("Flags", &c.flags, format_args!("0x{:02x}", c.flags));
+                tree::field(
+                    "Root identifier",
+                    &data[5..13],
+                    format_args!("{}:{}", c.root_prio, mac_to_str(&c.root_mac)),
+                );
+                tree::field("Root path cost", &c.path_cost, c.path_cost);
+                tree::field(
+                    "Bridge identifier",
+                    &data[17..25],
+                    format_args!("{}:{}", c.bridge_prio, mac_to_str(&c.bridge_mac)),
+                );
+                tree::field(
+                    "Port identifier",
+                    &c.port_id,
+                    format_args!("0x{:04x}", c.port_id.get()),
+                );
+                // times are in 1/256 of a second
+                for (name, time) in [
+                    ("Message age", &c.message_age),
+                    ("Max age", &c.max_age),
+                    ("Hello time", &c.hello_time),
+                    ("Forward delay", &c.forward_delay),
+                ] {
+                    tree::field(name, time, format_args!("{}s", time.get() / 256));
+                }
+            }
+            Err(_) => expert::error(DecodeError::Truncated {
+                layer: "stp",
+                offset: r.position(),
+            }),
         }
-        let flags = data[4];
-        let root_prio = u16::from_be_bytes([data[5], data[6]]);
-        let root_mac = &data[7..13];
-        let path_cost = u32::from_be_bytes([data[13], data[14], data[15], data[16]]);
-        let bridge_prio = u16::from_be_bytes([data[17], data[18]]);
-        let bridge_mac = &data[19..25];
-        let port_id = u16::from_be_bytes([data[25], data[26]]);
-        println!("STP cfg flags=0x{:02x} root={}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x} cost={} bridge={}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x} port=0x{:04x}",
-            flags,
-            root_prio,
-            root_mac[0], root_mac[1], root_mac[2], root_mac[3], root_mac[4],
-            path_cost,
-            bridge_prio,
-            bridge_mac[0], bridge_mac[1], bridge_mac[2], bridge_mac[3], bridge_mac[4],
-            port_id);
     }
 }
@@ -1,20 +1,66 @@
 // Minimal STUN decoder (RFC 5389)
 // Message: 2B type, 2B length, 4B magic cookie = 0x2112A442, 12B transaction ID
+use super::detect::confidence;
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be};
+use std::mem::size_of;
 
-fn is_stun(data: &[u8]) -> bool {
-    if data.len() < 20 {
-        return false;
+const MAGIC_COOKIE: u32 = 0x2112A442;
+
+wire_struct! {
+    #[derive(Debug, Clone, Copy)]
+    pub struct Header {
+        pub msg_type: U16Be,
+        pub len: U16Be,
+        pub cookie: U32Be,
+        pub transaction_id: [u8; 12],
+    }
+}
+
+/// Header of a STUN message, recognized by its magic cookie
+fn header(data: &[u8]) -> Option<&Header> {
+    utils::view::<Header>(data).filter(|h| h.cookie.get() == MAGIC_COOKIE)
+}
+
+/// Confidence that the payload is a STUN message: magic cookie and consistent length
+pub fn probe(data: &[u8]) -> u8 {
+    let h = match header(data) {
+        Some(h) => h,
+        None => return confidence::NONE,
+    };
+    let len = usize::from(h.len.get());
+    if h.msg_type.get() & 0xc000 == 0 && len + size_of::<Header>() == data.len() {
+        confidence::CERTAIN
+    } else {
+        confidence::MEDIUM
     }
-    let cookie = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
-    cookie == 0x2112A442
 }
 
 pub fn decode(data: &[u8]) -> bool {
-    if !is_stun(data) {
-        return false;
+    match header(data) {
+        Some(h) => {
+            let line = format!("STUN type=0x{:04x} len={}", h.msg_type.get(), h.len);
+            println!("{}", line);
+            let end = (size_of::<Header>() + usize::from(h.len.get())).min(data.len());
+            tree::layer("stun", &data[..end], line);
+            tree::field(
+                "Message type",
+                &h.msg_type,
+                format_args!("0x{:04x}", h.msg_type.get()),
+            );
+            tree::field("Message length", &h.len, h.len);
+            tree::field(
+                "Magic cookie",
+                &h.cookie,
+                format_args!("0x{:08x}", h.cookie.get()),
+            );
+            tree::field(
+                "Transaction ID",
+                &h.transaction_id,
+                tree::hex(&h.transaction_id),
+            );
+            true
+        }
+        None => false,
     }
-    let mtype = u16::from_be_bytes([data[0], data[1]]);
-    let mlen = u16::from_be_bytes([data[2], data[3]]);
-    println!("STUN type=0x{:04x} len={}", mtype, mlen);
-    true
 }
@@ -1,10 +1,46 @@
 // Syslog minimal decoder
+use super::detect::confidence;
+use crate::tree;
+
+/// Confidence that the payload is a syslog message: a priority, then an RFC 5424 version
+/// or an RFC 3164 date
+pub fn probe(data: &[u8]) -> u8 {
+    let end = match data.iter().take(5).position(|&b| b == b'>') {
+        Some(end) if end >= 2 && data[0] == b'<' => end,
+        _ => return confidence::NONE,
+    };
+    let pri = std::str::from_utf8(&data[1..end])
+        .ok()
+        .and_then(|p| p.parse::<u8>().ok());
+    if pri.is_none_or(|p| p > 191) {
+        return confidence::NONE;
+    }
+    let rest = &data[end + 1..];
+    let month = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ]
+    .iter()
+    .any(|m| rest.starts_with(m.as_bytes()));
+    if rest.starts_with(b"1 ") || month {
+        confidence::HIGH
+    } else {
+        confidence::MEDIUM
+    }
+}
+
 pub fn decode(data: &[u8]) {
     if let Ok(s) = std::str::from_utf8(data) {
         if let Some(line) = s.lines().next() {
             println!("Syslog: {}", line.trim());
+            tree::layer("syslog", data, format_args!("Syslog: {}", line.trim()));
+            if let Some(end) = line.find('>').filter(|_| line.starts_with('<')) {
+                let (priority, message) = line.split_at(end + 1);
+                tree::field("Priority", priority.as_bytes(), &line[1..end]);
+                tree::field("Message", message.as_bytes(), message.trim());
+            }
             return;
         }
     }
     println!("Syslog ({}B)", data.len());
+    tree::layer("syslog", data, format_args!("Syslog ({}B)", data.len()));
 }
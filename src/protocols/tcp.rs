@@ -1,175 +1,284 @@
-use crate::utils::cow_struct;
+use crate::analysis::{rules, tls_policy};
+use crate::context;
+use crate::expert::{self, DecodeError};
+use crate::flow::FlowKey;
+use crate::reassembly::{self, StreamHandler, FLAGS};
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be, U32Be};
 use std::mem::size_of;
 
-/// TCP header
-///
-/// The Transmission Control Protocol provide an established and maintained connexion, and exange data stream between machine
-/// and use check to ensure the delivery of packets.
-/// Its the most wild use protocol nowdays
-///
-/// Sources
-/// https://www.lifewire.com/tcp-headers-and-udp-headers-explained-817970
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    /// Port of the source
-    pub src_port: u16,
-    /// Port of the destination
-    pub dest_port: u16,
-    /// Message senders use sequence numbers to mark the ordering of a group of messages.
-    pub seq_number: u32,
-    /// Both senders and receivers use the acknowledgment numbers field to communicate the sequence numbers of messages that are either recently received or expected to be sent.
-    pub seq_ack: u32,
-    /// Size of the header in 4 bytes (header size 20bytes. data_offset egal to 5)
-    pub data_offset: [u8; 4],
-    /// Use for padding and allign memory
-    pub reserved: [u8; 3],
-    /// use to manage data flow
-    pub control_flag: [u8; 9],
-    /// Regulate how much data they send to a receiver before requiring an acknowledgment in return.
-    pub win_size: u16,
-    /// The checksum value is use to help the receiver detect messages that are corrupted or tampered with.
-    pub checksum: u16,
-    /// Can be used as a data offset to mark a subset of a message as requiring priority processing.
-    pub urgent_ptr: u16,
+use super::ip::IpProtocol;
+
+wire_struct! {
+    /// TCP header
+    ///
+    /// The Transmission Control Protocol provide an established and maintained connexion, and exange data stream between machine
+    /// and use check to ensure the delivery of packets.
+    /// Its the most wild use protocol nowdays
+    ///
+    /// Sources
+    /// https://www.lifewire.com/tcp-headers-and-udp-headers-explained-817970
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        /// Port of the source
+        pub src_port: U16Be,
+        /// Port of the destination
+        pub dest_port: U16Be,
+        /// Message senders use sequence numbers to mark the ordering of a group of messages.
+        pub seq_number: U32Be,
+        /// Both senders and receivers use the acknowledgment numbers field to communicate the sequence numbers of messages that are either recently received or expected to be sent.
+        pub seq_ack: U32Be,
+        /// Size of the header in 4 bytes words on the high nibble (header size 20bytes. data_offset egal to 5)
+        pub data_offset: u8,
+        /// use to manage data flow (CWR ECE URG ACK PSH RST SYN FIN)
+        pub control_flag: u8,
+        /// Regulate how much data they send to a receiver before requiring an acknowledgment in return.
+        pub win_size: U16Be,
+        /// The checksum value is use to help the receiver detect messages that are corrupted or tampered with.
+        pub checksum: U16Be,
+        /// Can be used as a data offset to mark a subset of a message as requiring priority processing.
+        pub urgent_ptr: U16Be,
+    }
 }
 
 impl Header {
     pub const SIZE: usize = size_of::<Self>();
+
+    /// Length of the header including options
+    pub fn header_len(&self) -> usize {
+        ((self.data_offset >> 4) as usize) * 4
+    }
 }
 
 pub fn display(h: &Header) -> String {
-    format!(
-        "TCP {} -> {} win={}",
-        h.src_port.to_be(),
-        h.dest_port.to_be(),
-        h.win_size.to_be()
-    )
+    format!("TCP {} -> {} win={}", h.src_port, h.dest_port, h.win_size)
+}
+
+/// Names of the control flags set, as `SYN,ACK`
+pub fn flags_to_str(flags: u8) -> String {
+    let names = [
+        (FLAGS::FIN, "FIN"),
+        (FLAGS::SYN, "SYN"),
+        (FLAGS::RST, "RST"),
+        (FLAGS::PSH, "PSH"),
+        (FLAGS::ACK, "ACK"),
+        (FLAGS::URG, "URG"),
+        (FLAGS::ECE, "ECE"),
+        (FLAGS::CWR, "CWR"),
+    ];
+    let set: Vec<&str> = names
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    if set.is_empty() {
+        "NONE".to_string()
+    } else {
+        set.join(",")
+    }
+}
+
+/// Pick the stream decoder of a connection from its first bytes
+pub fn stream_handler(
+    flow: &FlowKey,
+    from_client: bool,
+    data: &[u8],
+) -> Option<Box<dyn StreamHandler>> {
+    // data connections announced on an FTP control connection go first, whatever they carry
+    if let Some(handler) = super::ftp::connection(flow) {
+        return Some(handler);
+    }
+    if let Some(tracker) = super::tls::tracker(data) {
+        return Some(Box::new(tracker));
+    }
+    if let Some(session) = super::smtp::session(flow) {
+        return Some(Box::new(session));
+    }
+    if let Some(session) = super::pop3::session(flow) {
+        return Some(Box::new(session));
+    }
+    if let Some(session) = super::imap::session(flow) {
+        return Some(Box::new(session));
+    }
+    if let Some(session) = super::telnet::session(flow) {
+        return Some(Box::new(session));
+    }
+    if let Some(connection) = super::smb::connection(data) {
+        return Some(Box::new(connection));
+    }
+    if let Some(connection) = super::http2::connection(from_client, data) {
+        return Some(Box::new(connection));
+    }
+    if let Some(connection) = super::http::connection(from_client, data) {
+        return Some(Box::new(connection));
+    }
+    if from_client {
+        if let Some(hello) = super::ssl::ClientHelloV2::parse(data) {
+            tls_policy::on_sslv2_hello(flow, &hello);
+        }
+    }
+    None
+}
+
+/// Decode the application payload of a segment (or decrypted stream bytes) by port
+pub fn decode_payload(src: u16, dst: u16, payload: &[u8]) {
+    // pure ACKs and other segments without data have nothing to decode
+    if payload.is_empty() {
+        return;
+    }
+    if src == 179 || dst == 179 {
+        super::bgp::decode(payload);
+    } else if src == 22 || dst == 22 {
+        super::ssh::decode(payload);
+    } else if src == 23 || dst == 23 {
+        super::telnet::decode(payload);
+    } else if src == 21 || dst == 21 {
+        super::ftp::decode(payload);
+    } else if src == 990 || dst == 990 {
+        // Implicit FTPS
+        if !super::tls::decode(payload) {
+            super::ftp::decode(payload);
+        }
+    } else if src == 25 || dst == 25 || src == 587 || dst == 587 {
+        super::smtp::decode(payload);
+    } else if src == 465 || dst == 465 {
+        super::smtps::decode(payload);
+    } else if src == 110 || dst == 110 {
+        super::pop3::decode(payload);
+    } else if src == 143 || dst == 143 {
+        super::imap::decode(payload);
+    } else if src == 993 || dst == 993 {
+        if !super::tls::decode(payload) {
+            super::imap::decode(payload);
+        }
+    } else if src == 389 || dst == 389 {
+        super::ldap::decode(payload);
+    } else if src == 636 || dst == 636 {
+        super::ldaps::decode(payload);
+    } else if src == 53 || dst == 53 {
+        // DNS over TCP (2-byte length prefix)
+        super::dns::decode_stream(payload);
+    } else if src == 853 || dst == 853 {
+        // DNS over TLS (DoT)
+        if !super::tls::decode(payload) {
+            super::dot::decode(payload);
+        }
+    } else if src == 3389 || dst == 3389 {
+        super::rdp::decode(payload);
+    } else if src == 139 || dst == 139 || src == 445 || dst == 445 {
+        super::smb::decode(payload);
+    } else if src == 554 || dst == 554 {
+        super::rtsp::decode(payload);
+    } else if src == 5060 || dst == 5060 {
+        super::sip::decode(payload);
+    } else if src == 443 || dst == 443 || src == 8443 || dst == 8443 {
+        // try TLS first (HTTPS)
+        if !super::tls::decode(payload) {
+            // WebSocket/HTTP over 443/8443 in clear (rare) and DoH without TLS (non-standard)
+            if !super::websocket::decode(payload) && !super::doh::decode(payload) {
+                super::http::decode(payload);
+            }
+        }
+    } else if src == 80 || dst == 80 || src == 8080 || dst == 8080 || src == 8000 || dst == 8000 {
+        // try WebSocket or DoH; if not, fall back to HTTP
+        if !super::websocket::decode(payload) && !super::doh::decode(payload) {
+            super::http::decode(payload);
+        }
+    } else if src == 1883 || dst == 1883 {
+        super::mqtt::decode(payload);
+    } else if src == 8883 || dst == 8883 {
+        // MQTT over TLS
+        if !super::tls::decode(payload) {
+            super::mqtt::decode(payload);
+        }
+    } else if src == 5672 || dst == 5672 {
+        super::amqp::decode(payload);
+    } else if src == 5671 || dst == 5671 {
+        // AMQP over TLS
+        if !super::tls::decode(payload) {
+            super::amqp::decode(payload);
+        }
+    } else if src == 61613 || dst == 61613 || src == 61614 || dst == 61614 {
+        super::stomp::decode(payload);
+    } else if src == 6379 || dst == 6379 {
+        super::redis::decode(payload);
+    } else if src == 11211 || dst == 11211 {
+        super::memcached::decode(payload);
+    } else if src == 88 || dst == 88 {
+        super::kerberos::decode(payload);
+    } else if src == 5355 || dst == 5355 {
+        super::llmnr::decode(payload);
+    } else if src == 3478 || dst == 3478 {
+        super::stun::decode(payload);
+    } else if src == 5349 || dst == 5349 {
+        // STUN/TURN over TLS
+        if !super::tls::decode(payload) {
+            super::stun::decode(payload);
+        }
+    }
 }
 
 pub fn decode(data: &[u8]) {
     if data.len() >= Header::SIZE {
-        let (slice, _data) = data.split_at(Header::SIZE);
-        match cow_struct::<Header>(slice) {
+        match utils::view::<Header>(data) {
             Some(header) => {
-                println!("{}", display(&header));
-                let src = header.src_port.to_be();
-                let dst = header.dest_port.to_be();
-                let payload = if data.len() > Header::SIZE {
-                    &data[Header::SIZE..]
-                } else {
-                    &data[0..0]
-                };
-                if src == 179 || dst == 179 {
-                    super::bgp::decode(payload);
-                } else if src == 22 || dst == 22 {
-                    super::ssh::decode(payload);
-                } else if src == 23 || dst == 23 {
-                    super::telnet::decode(payload);
-                } else if src == 21 || dst == 21 {
-                    super::ftp::decode(payload);
-                } else if src == 990 || dst == 990 {
-                    // Implicit FTPS
-                    if !super::tls::decode(payload) {
-                        super::ftp::decode(payload);
-                    }
-                } else if src == 25 || dst == 25 || src == 587 || dst == 587 {
-                    super::smtp::decode(payload);
-                } else if src == 465 || dst == 465 {
-                    super::smtps::decode(payload);
-                } else if src == 110 || dst == 110 {
-                    super::pop3::decode(payload);
-                } else if src == 143 || dst == 143 {
-                    super::imap::decode(payload);
-                } else if src == 993 || dst == 993 {
-                    if !super::tls::decode(payload) {
-                        super::imap::decode(payload);
-                    }
-                } else if src == 389 || dst == 389 {
-                    super::ldap::decode(payload);
-                } else if src == 636 || dst == 636 {
-                    super::ldaps::decode(payload);
-                } else if src == 53 || dst == 53 {
-                    // DNS over TCP (2-byte length prefix)
-                    if payload.len() >= 2 {
-                        let len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
-                        if payload.len() >= 2 + len {
-                            println!("DNS/TCP len={}", len);
-                            super::dns::decode(&payload[2..2 + len]);
-                        } else {
-                            println!("DNS/TCP (truncated) {}B", payload.len());
-                        }
-                    }
-                } else if src == 853 || dst == 853 {
-                    // DNS over TLS (DoT)
-                    if !super::tls::decode(payload) {
-                        super::dot::decode(payload);
-                    }
-                } else if src == 3389 || dst == 3389 {
-                    super::rdp::decode(payload);
-                } else if src == 139 || dst == 139 || src == 445 || dst == 445 {
-                    super::smb::decode(payload);
-                } else if src == 554 || dst == 554 {
-                    super::rtsp::decode(payload);
-                } else if src == 5060 || dst == 5060 {
-                    super::sip::decode(payload);
-                } else if src == 443 || dst == 443 || src == 8443 || dst == 8443 {
-                    // try TLS first (HTTPS)
-                    if !super::tls::decode(payload) {
-                        // WebSocket/HTTP over 443/8443 in clear (rare) and DoH without TLS (non-standard)
-                        if !super::websocket::decode(payload) {
-                            if !super::doh::decode(payload) {
-                                super::http::decode(payload);
-                            }
-                        }
-                    }
-                } else if src == 80
-                    || dst == 80
-                    || src == 8080
-                    || dst == 8080
-                    || src == 8000
-                    || dst == 8000
-                {
-                    // try WebSocket or DoH; if not, fall back to HTTP
-                    if !super::websocket::decode(payload) {
-                        if !super::doh::decode(payload) {
-                            super::http::decode(payload);
-                        }
-                    }
-                } else if src == 1883 || dst == 1883 {
-                    super::mqtt::decode(payload);
-                } else if src == 8883 || dst == 8883 {
-                    // MQTT over TLS
-                    if !super::tls::decode(payload) {
-                        super::mqtt::decode(payload);
-                    }
-                } else if src == 5672 || dst == 5672 {
-                    super::amqp::decode(payload);
-                } else if src == 5671 || dst == 5671 {
-                    // AMQP over TLS
-                    if !super::tls::decode(payload) {
-                        super::amqp::decode(payload);
-                    }
-                } else if src == 61613 || dst == 61613 || src == 61614 || dst == 61614 {
-                    super::stomp::decode(payload);
-                } else if src == 6379 || dst == 6379 {
-                    super::redis::decode(payload);
-                } else if src == 11211 || dst == 11211 {
-                    super::memcached::decode(payload);
-                } else if src == 88 || dst == 88 {
-                    super::kerberos::decode(payload);
-                } else if src == 5355 || dst == 5355 {
-                    super::llmnr::decode(payload);
-                } else if src == 3478 || dst == 3478 {
-                    super::stun::decode(payload);
-                } else if src == 5349 || dst == 5349 {
-                    // STUN/TURN over TLS
-                    if !super::tls::decode(payload) {
-                        super::stun::decode(payload);
-                    }
+                println!("{}", display(header));
+                let src = header.src_port.get();
+                let dst = header.dest_port.get();
+                let header_len = header.header_len().clamp(Header::SIZE, data.len());
+                tree::layer("tcp", &data[..header_len], display(header));
+                tree::field("Source port", &header.src_port, src);
+                tree::field("Destination port", &header.dest_port, dst);
+                tree::field("Sequence number", &header.seq_number, header.seq_number);
+                tree::field("Acknowledgment number", &header.seq_ack, header.seq_ack);
+                tree::field("Header length", &header.data_offset, header.header_len());
+                tree::field(
+                    "Flags",
+                    &header.control_flag,
+                    format_args!(
+                        "0x{:02x} ({})",
+                        header.control_flag,
+                        flags_to_str(header.control_flag)
+                    ),
+                );
+                tree::field("Window", &header.win_size, header.win_size);
+                tree::field(
+                    "Checksum",
+                    &header.checksum,
+                    format_args!("0x{:04x}", header.checksum.get()),
+                );
+                tree::field("Urgent pointer", &header.urgent_ptr, header.urgent_ptr);
+                if header_len > Header::SIZE {
+                    tree::field(
+                        "Options",
+                        &data[Header::SIZE..header_len],
+                        format_args!("{} bytes", header_len - Header::SIZE),
+                    );
+                }
+                context::set_ports(IpProtocol::Tcp, src, dst);
+                let payload = data
+                    .get(header.header_len().max(Header::SIZE)..)
+                    .unwrap_or(&[]);
+                rules::on_packet(IpProtocol::Tcp, payload, Some(header.control_flag));
+                // the content of the flow may overrule its ports
+                if !super::detect::dispatch(IpProtocol::Tcp, payload) {
+                    decode_payload(src, dst, payload);
+                }
+                // stream decoders see the reassembled bytes, after the segment was printed
+                if let Some(flow) = context::flow() {
+                    reassembly::push(
+                        &flow,
+                        header.seq_number.get(),
+                        header.control_flag,
+                        payload,
+                        stream_handler,
+                    );
                 }
             }
-            None => println!("ip decode error: {:?}", "Truncated payload"),
+            None => expert::error(DecodeError::Truncated {
+                layer: "tcp",
+                offset: 0,
+            }),
         }
     }
 }
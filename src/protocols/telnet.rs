@@ -1,32 +1,251 @@
 // Telnet: summarize IAC negotiations
+// `Session` rebuilds the lines typed by the client (negotiations removed, erased characters
+// dropped) and reports the password typed after a password prompt, with the line typed
+// after the login prompt before it.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc854
+use super::detect::confidence;
+use crate::analysis::credentials;
+use crate::expert::{self, DecodeError};
+use crate::flow::FlowKey;
+use crate::reassembly::StreamHandler;
+use crate::tree;
+use crate::utils::ByteReader;
+
+/// Interpret As Command
+const IAC: u8 = 0xff;
+/// Subnegotiation begin and end
+const SB: u8 = 0xfa;
+const SE: u8 = 0xf0;
+/// Longest line kept, prompts are looked for in the last bytes sent by the server
+const MAX_LINE: usize = 256;
+
+fn command_as_str(cmd: u8) -> &'static str {
+    match cmd {
+        0xf0 => "SE",
+        0xf1 => "NOP",
+        0xf2 => "Data Mark",
+        0xf3 => "Break",
+        0xf4 => "Interrupt Process",
+        0xf5 => "Abort Output",
+        0xf6 => "Are You There",
+        0xf7 => "Erase Character",
+        0xf8 => "Erase Line",
+        0xf9 => "Go Ahead",
+        0xfa => "SB",
+        0xfb => "WILL",
+        0xfc => "WONT",
+        0xfd => "DO",
+        0xfe => "DONT",
+        _ => "UNKNOW",
+    }
+}
+
+/// Prompt the server showed last
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    #[default]
+    None,
+    Login,
+    Password,
+}
+
+/// Follows the logins of a telnet session
+#[derive(Default)]
+pub struct Session {
+    /// text of each side, client first; the client side is the line being typed
+    text: [Vec<u8>; 2],
+    /// bytes of a command split across segments
+    partial: [Vec<u8>; 2],
+    prompt: Prompt,
+    user: Option<String>,
+}
+
+/// Data bytes of a segment, commands and subnegotiations removed. An incomplete command at
+/// the end is left in `partial`.
+fn strip_commands(partial: &mut Vec<u8>, data: &[u8]) -> Vec<u8> {
+    let mut input = std::mem::take(partial);
+    input.extend_from_slice(data);
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        if input[i] != IAC {
+            out.push(input[i]);
+            i += 1;
+            continue;
+        }
+        let len = match input.get(i + 1) {
+            None => 0,
+            Some(&IAC) => {
+                out.push(IAC);
+                2
+            }
+            Some(&SB) => input[i + 2..]
+                .windows(2)
+                .position(|w| w == [IAC, SE])
+                .map_or(0, |p| p + 4),
+            // WILL, WONT, DO, DONT carry an option
+            Some(0xfb..=0xfe) if i + 2 < input.len() => 3,
+            Some(0xfb..=0xfe) => 0,
+            Some(_) => 2,
+        };
+        if len == 0 {
+            if input.len() - i < MAX_LINE {
+                *partial = input[i..].to_vec();
+            }
+            break;
+        }
+        i += len;
+    }
+    out
+}
+
+impl Session {
+    fn on_line(&mut self, flow: &FlowKey, line: String) {
+        match self.prompt {
+            Prompt::Login => self.user = Some(line),
+            Prompt::Password => {
+                credentials::on_credential(
+                    "telnet",
+                    "login prompt",
+                    Some(flow),
+                    self.user.as_deref(),
+                    &line,
+                );
+                self.user = None;
+            }
+            Prompt::None => {}
+        }
+        self.prompt = Prompt::None;
+    }
+}
+
+impl StreamHandler for Session {
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]) {
+        let dir = usize::from(!from_client);
+        let bytes = strip_commands(&mut self.partial[dir], data);
+        if !from_client {
+            let text = &mut self.text[1];
+            text.extend_from_slice(&bytes);
+            if text.len() > MAX_LINE {
+                text.drain(..text.len() - MAX_LINE);
+            }
+            let tail = String::from_utf8_lossy(text).to_ascii_lowercase();
+            let tail = tail.trim_end();
+            if tail.ends_with("password:") || tail.ends_with("passcode:") {
+                self.prompt = Prompt::Password;
+            } else if tail.ends_with("login:") || tail.ends_with("username:") {
+                self.prompt = Prompt::Login;
+            }
+            return;
+        }
+        if bytes.is_empty() {
+            return;
+        }
+        // the prompt is being answered, the server output that follows is new
+        self.text[1].clear();
+        for b in bytes {
+            match b {
+                b'\r' | b'\n' if !self.text[0].is_empty() => {
+                    let line = String::from_utf8_lossy(&self.text[0]).into_owned();
+                    self.text[0].clear();
+                    self.on_line(flow, line);
+                }
+                b'\r' | b'\n' => {}
+                // backspace, delete
+                0x08 | 0x7f => {
+                    self.text[0].pop();
+                }
+                0 => {}
+                _ if self.text[0].len() < MAX_LINE => self.text[0].push(b),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Stream handler of a telnet session
+pub fn session(flow: &FlowKey) -> Option<Session> {
+    (flow.dst_port == 23).then(Session::default)
+}
+
+/// Confidence that the payload starts with a telnet option negotiation
+pub fn probe(data: &[u8]) -> u8 {
+    match data {
+        [IAC, 0xfb..=0xfe, _, IAC, 0xfb..=0xfe, ..] => confidence::HIGH,
+        [IAC, 0xfb..=0xfe, _, ..] => confidence::MEDIUM,
+        _ => confidence::NONE,
+    }
+}
+
 pub fn decode(data: &[u8]) {
-    let mut i = 0usize;
+    let mut r = ByteReader::new(data);
     let mut do_n = 0;
     let mut dont_n = 0;
     let mut will_n = 0;
     let mut wont_n = 0;
     let mut opts: Vec<u8> = Vec::new();
-    while i + 2 < data.len() {
-        if data[i] == 0xff {
-            let cmd = data[i + 1];
-            let opt = data[i + 2];
-            match cmd {
-                0xfd => do_n += 1,
-                0xfe => dont_n += 1,
-                0xfb => will_n += 1,
-                0xfc => wont_n += 1,
-                _ => {}
+    // bytes, command and option of the commands, for the tree
+    let mut commands: Vec<(std::ops::Range<usize>, u8, Option<u8>)> = Vec::new();
+    while !r.is_empty() {
+        if !r.eat(IAC) {
+            let _ = r.skip(1);
+            continue;
+        }
+        let start = r.position() - 1;
+        match r.u8() {
+            // an escaped 0xff data byte
+            Ok(IAC) => {}
+            Ok(cmd @ 0xfb..=0xfe) => match r.u8() {
+                Ok(opt) => {
+                    match cmd {
+                        0xfd => do_n += 1,
+                        0xfe => dont_n += 1,
+                        0xfb => will_n += 1,
+                        _ => wont_n += 1,
+                    }
+                    opts.push(opt);
+                    commands.push((start..r.position(), cmd, Some(opt)));
+                }
+                Err(_) => expert::error(DecodeError::Truncated {
+                    layer: "telnet",
+                    offset: start,
+                }),
+            },
+            Ok(SB) => {
+                let rest = r.rest();
+                match rest.windows(2).position(|w| w == [IAC, SE]) {
+                    Some(end) => {
+                        let _ = r.skip(end + 2);
+                        commands.push((start..r.position(), SB, rest.first().copied()));
+                    }
+                    None => {
+                        expert::error(DecodeError::Truncated {
+                            layer: "telnet",
+                            offset: start,
+                        });
+                        break;
+                    }
+                }
             }
-            opts.push(opt);
-            i += 3;
-        } else {
-            i += 1;
+            // commands without option: SE, NOP, Data Mark, BRK, IP, AO, AYT, EC, EL, GA
+            Ok(cmd @ 0xf0..=0xf9) => commands.push((start..r.position(), cmd, None)),
+            Ok(_) => expert::error(DecodeError::Malformed {
+                layer: "telnet",
+                offset: start + 1,
+                reason: "unknown command after IAC",
+            }),
+            Err(_) => expert::error(DecodeError::Truncated {
+                layer: "telnet",
+                offset: start,
+            }),
         }
         if (do_n + dont_n + will_n + wont_n) > 10 {
             break;
         }
     }
-    println!(
+    let line = format!(
         "Telnet bytes={} DO/DOnt={}/{} WILL/WONT={}/{} opts={:?}",
         data.len(),
         do_n,
@@ -35,4 +254,16 @@ pub fn decode(data: &[u8]) {
         wont_n,
         &opts[..opts.len().min(6)]
     );
+    println!("{}", line);
+    tree::layer("telnet", data, line);
+    for (bytes, cmd, opt) in commands {
+        match opt {
+            Some(opt) => tree::field(
+                "Command",
+                &data[bytes],
+                format_args!("{} {}", command_as_str(cmd), opt),
+            ),
+            None => tree::field("Command", &data[bytes], command_as_str(cmd)),
+        }
+    }
 }
@@ -1,17 +1,284 @@
 // TFTP minimal decoder (RFC 1350)
-pub fn decode(data: &[u8]) {
-    if data.len() < 2 {
-        println!("TFTP ({}B)", data.len());
-        return;
+// Only requests go to port 69, the transfer itself uses a port picked by the server. The
+// requests are remembered by client endpoint so that DATA blocks are recognized, and with
+// file extraction enabled the blocks are put together until the last (short) one.
+//
+// Sources:
+// * https://www.rfc-editor.org/rfc/rfc1350
+// * https://www.rfc-editor.org/rfc/rfc2348 (blksize option)
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use super::detect::confidence;
+use crate::analysis::extract::{self, Buffer};
+use crate::context;
+use crate::expert::{self, DecodeError};
+use crate::flow::FlowKey;
+use crate::tree;
+use crate::utils::ByteReader;
+
+/// TFTP opcodes
+#[non_exhaustive]
+pub struct OPCODE;
+
+impl OPCODE {
+    pub const RRQ: u16 = 1;
+    pub const WRQ: u16 = 2;
+    pub const DATA: u16 = 3;
+    pub const ACK: u16 = 4;
+    pub const ERROR: u16 = 5;
+    pub const OACK: u16 = 6;
+}
+
+const DEFAULT_BLKSIZE: usize = 512;
+/// Transfers tracked per thread
+const MAX_TRANSFERS: usize = 4096;
+
+struct Transfer {
+    /// request flow, from the client to port 69
+    flow: FlowKey,
+    filename: String,
+    blksize: usize,
+    /// next block expected, retransmissions are dropped
+    next_block: u16,
+    data: Buffer,
+    len: u64,
+}
+
+thread_local! {
+    /// transfers by client endpoint
+    static TRANSFERS: RefCell<HashMap<(IpAddr, u16), Transfer>> = RefCell::new(HashMap::new());
+}
+
+/// Whether a packet belongs to a transfer requested earlier
+pub fn is_transfer(flow: &FlowKey) -> bool {
+    TRANSFERS.with(|t| {
+        let t = t.borrow();
+        t.contains_key(&(flow.src, flow.src_port)) || t.contains_key(&(flow.dst, flow.dst_port))
+    })
+}
+
+/// NUL terminated strings of a request: filename, mode, then option/value pairs
+fn strings(data: &[u8]) -> Vec<String> {
+    data.split(|b| *b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+fn on_request(flow: &FlowKey, args: &[String]) {
+    let filename = match args.first() {
+        Some(f) => f.clone(),
+        None => return,
+    };
+    let blksize = args
+        .iter()
+        .skip(2)
+        .collect::<Vec<_>>()
+        .chunks_exact(2)
+        .find(|o| o[0].eq_ignore_ascii_case("blksize"))
+        .and_then(|o| o[1].parse().ok())
+        .unwrap_or(DEFAULT_BLKSIZE);
+    TRANSFERS.with(|t| {
+        let mut t = t.borrow_mut();
+        if t.len() >= MAX_TRANSFERS {
+            t.clear();
+        }
+        t.insert(
+            (flow.src, flow.src_port),
+            Transfer {
+                flow: *flow,
+                filename,
+                blksize,
+                next_block: 1,
+                data: Buffer::default(),
+                len: 0,
+            },
+        );
+    });
+}
+
+fn on_data(flow: &FlowKey, block: u16, data: &[u8]) {
+    TRANSFERS.with(|t| {
+        let mut t = t.borrow_mut();
+        let key = if t.contains_key(&(flow.src, flow.src_port)) {
+            (flow.src, flow.src_port)
+        } else {
+            (flow.dst, flow.dst_port)
+        };
+        let transfer = match t.get_mut(&key) {
+            Some(tr) => tr,
+            None => return,
+        };
+        if block != transfer.next_block {
+            return;
+        }
+        transfer.next_block = transfer.next_block.wrapping_add(1);
+        transfer.len += data.len() as u64;
+        if extract::enabled() {
+            transfer.data.extend(data);
+        }
+        // a short block ends the transfer
+        if data.len() < transfer.blksize {
+            if let Some(done) = t.remove(&key) {
+                extract::on_file(
+                    "tftp",
+                    &done.flow,
+                    Some(&done.filename),
+                    None,
+                    &done.data,
+                    done.len,
+                );
+            }
+        }
+    });
+}
+
+/// Confidence that the payload is a TFTP request, or a packet of a transfer
+pub fn probe(data: &[u8]) -> u8 {
+    let op = match data.get(..2) {
+        Some(op) => u16::from_be_bytes([op[0], op[1]]),
+        None => return confidence::NONE,
+    };
+    match op {
+        OPCODE::RRQ | OPCODE::WRQ => {
+            let args = strings(&data[2..]);
+            let mode = args.get(1).map(|m| m.to_ascii_lowercase());
+            let known = matches!(mode.as_deref(), Some("netascii" | "octet" | "mail"));
+            if known && data.ends_with(&[0]) {
+                confidence::CERTAIN
+            } else {
+                confidence::NONE
+            }
+        }
+        OPCODE::ERROR
+            if data.len() >= 5 && data[2] == 0 && data[3] <= 8 && data.ends_with(&[0]) =>
+        {
+            confidence::MEDIUM
+        }
+        OPCODE::ACK if data.len() == 4 => confidence::LOW,
+        OPCODE::DATA if data.len() <= 4 + DEFAULT_BLKSIZE => confidence::LOW,
+        _ => confidence::NONE,
     }
-    let op = u16::from_be_bytes([data[0], data[1]]);
+}
+
+pub fn decode(data: &[u8]) {
+    let mut r = ByteReader::new(data);
+    let op = match r.u16() {
+        Ok(op) => op,
+        Err(_) => {
+            expert::error(DecodeError::Truncated {
+                layer: "tftp",
+                offset: 0,
+            });
+            return;
+        }
+    };
     let name = match op {
-        1 => "RRQ",
-        2 => "WRQ",
-        3 => "DATA",
-        4 => "ACK",
-        5 => "ERROR",
+        OPCODE::RRQ => "RRQ",
+        OPCODE::WRQ => "WRQ",
+        OPCODE::DATA => "DATA",
+        OPCODE::ACK => "ACK",
+        OPCODE::ERROR => "ERROR",
+        OPCODE::OACK => "OACK",
         _ => "UNKNOWN",
     };
-    println!("TFTP {}", name);
+    let flow = context::flow();
+    match op {
+        OPCODE::RRQ | OPCODE::WRQ => {
+            if !data.ends_with(&[0]) {
+                expert::error(DecodeError::Malformed {
+                    layer: "tftp",
+                    offset: data.len(),
+                    reason: "request not NUL terminated",
+                });
+            }
+            let args = strings(r.rest());
+            let line = format!(
+                "TFTP {} file={} mode={}",
+                name,
+                args.first().map_or("-", String::as_str),
+                args.get(1).map_or("-", String::as_str)
+            );
+            println!("{}", line);
+            tree::layer("tftp", data, line);
+            tree::field("Opcode", &data[..2], format_args!("{} ({})", name, op));
+            let mut fields = r.rest().split(|b| *b == 0);
+            for name in ["Source file", "Type"] {
+                if let Some(value) = fields.next().filter(|v| !v.is_empty()) {
+                    tree::field(name, value, String::from_utf8_lossy(value));
+                }
+            }
+            if let Some(flow) = flow {
+                on_request(&flow, &args);
+            }
+        }
+        OPCODE::DATA => match r.u16() {
+            Ok(block) => {
+                println!("TFTP DATA block={} {}B", block, r.remaining());
+                tree::layer(
+                    "tftp",
+                    data,
+                    format_args!("TFTP DATA block={} {}B", block, r.remaining()),
+                );
+                tree::field("Opcode", &data[..2], format_args!("{} ({})", name, op));
+                tree::field("Block", &data[2..4], block);
+                tree::field("Data", r.rest(), format_args!("{} bytes", r.remaining()));
+                if let Some(flow) = flow {
+                    on_data(&flow, block, r.rest());
+                }
+            }
+            Err(_) => expert::error(DecodeError::Truncated {
+                layer: "tftp",
+                offset: 2,
+            }),
+        },
+        OPCODE::ERROR => {
+            let msg = strings(data.get(4..).unwrap_or(&[]));
+            println!("TFTP ERROR {}", msg.first().map_or("", String::as_str));
+            tree::layer(
+                "tftp",
+                data,
+                format_args!("TFTP ERROR {}", msg.first().map_or("", String::as_str)),
+            );
+            tree::field("Opcode", &data[..2], format_args!("{} ({})", name, op));
+            if let Ok(code) = r.u16() {
+                tree::field("Error code", &data[2..4], code);
+            }
+            if let Some(text) = r.rest().split(|b| *b == 0).next() {
+                tree::field("Error message", text, String::from_utf8_lossy(text));
+            }
+            if let Some(flow) = flow {
+                TRANSFERS.with(|t| {
+                    let mut t = t.borrow_mut();
+                    t.remove(&(flow.src, flow.src_port));
+                    t.remove(&(flow.dst, flow.dst_port));
+                });
+            }
+        }
+        OPCODE::ACK | OPCODE::OACK => {
+            println!("TFTP {}", name);
+            tree::layer("tftp", data, format_args!("TFTP {}", name));
+            tree::field("Opcode", &data[..2], format_args!("{} ({})", name, op));
+            match op {
+                OPCODE::ACK => {
+                    if let Ok(block) = r.u16() {
+                        tree::field("Block", &data[2..4], block);
+                    }
+                }
+                // option names and values alternate
+                _ => {
+                    for option in r.rest().split(|b| *b == 0).filter(|s| !s.is_empty()) {
+                        tree::field("Option", option, String::from_utf8_lossy(option));
+                    }
+                }
+            }
+        }
+        _ => expert::error(DecodeError::Malformed {
+            layer: "tftp",
+            offset: 0,
+            reason: "unknown opcode",
+        }),
+    }
 }
@@ -1,71 +1,1177 @@
-// returns true if looked like TLS ClientHello and we printed it
+// TLS record layer and hello parsing
+// Hellos are parsed into borrowed structs (no copy of the payload) and fingerprinted.
+// `Tracker` follows the handshake over the reassembled TCP stream, where the hellos
+// and the certificate chain can span several segments.
+use std::borrow::Cow;
+
+use crate::analysis::fingerprint::{self, Transport};
+use crate::analysis::{rules, tls_policy, x509};
+use crate::expert::{self, DecodeError};
+use crate::flow::FlowKey;
+use crate::reassembly::StreamHandler;
+use crate::tree;
+use crate::utils::ByteReader;
+
+use super::detect::confidence;
+use super::tls_decrypt::{self, RecordCipher, LABEL};
+
+/// TLS record content types
+///
+/// Source:
+/// * https://www.iana.org/assignments/tls-parameters/tls-parameters.xhtml#tls-parameters-5
+#[non_exhaustive]
+pub struct CONTENT;
+
+impl CONTENT {
+    pub const CHANGE_CIPHER_SPEC: u8 = 20;
+    pub const ALERT: u8 = 21;
+    pub const HANDSHAKE: u8 = 22;
+    pub const APPLICATION_DATA: u8 = 23;
+    pub const HEARTBEAT: u8 = 24;
+}
+
+/// TLS handshake message types
+#[non_exhaustive]
+pub struct HANDSHAKE;
+
+impl HANDSHAKE {
+    pub const CLIENT_HELLO: u8 = 1;
+    pub const SERVER_HELLO: u8 = 2;
+    pub const NEW_SESSION_TICKET: u8 = 4;
+    pub const ENCRYPTED_EXTENSIONS: u8 = 8;
+    pub const CERTIFICATE: u8 = 11;
+    pub const SERVER_KEY_EXCHANGE: u8 = 12;
+    pub const CERTIFICATE_REQUEST: u8 = 13;
+    pub const SERVER_HELLO_DONE: u8 = 14;
+    pub const CERTIFICATE_VERIFY: u8 = 15;
+    pub const CLIENT_KEY_EXCHANGE: u8 = 16;
+    pub const FINISHED: u8 = 20;
+    pub const KEY_UPDATE: u8 = 24;
+}
+
+/// TLS extension types used by the decoder
+#[non_exhaustive]
+pub struct EXT;
+
+impl EXT {
+    pub const SERVER_NAME: u16 = 0;
+    pub const SUPPORTED_GROUPS: u16 = 10;
+    pub const EC_POINT_FORMATS: u16 = 11;
+    pub const SIGNATURE_ALGORITHMS: u16 = 13;
+    pub const ALPN: u16 = 16;
+    pub const SUPPORTED_VERSIONS: u16 = 43;
+}
+
+/// Cipher suite value a client adds when it retries a handshake with a lower version (RFC 7507)
+pub const FALLBACK_SCSV: u16 = 0x5600;
+
+/// Record header size: content type(1) + version(2) + length(2)
+pub const RECORD_HEADER_SIZE: usize = 5;
+
+/// Largest record payload allowed by RFC 8446 (2^14 + 256 for AEAD expansion)
+const MAX_RECORD_LEN: usize = (1 << 14) + 256;
+
+/// Bound of a handshake message being reassembled (certificate chains are the largest)
+const MAX_HANDSHAKE_LEN: usize = 1 << 18;
+
+/// GREASE values (RFC 8701) are random reserved values a client sends to keep the
+/// ecosystem tolerant to unknown values: 0x0a0a, 0x1a1a, ..., 0xfafa
+pub fn is_grease(v: u16) -> bool {
+    (v & 0x0f0f) == 0x0a0a && (v >> 8) == (v & 0xff)
+}
+
+/// Protocol version to str
+pub fn version_as_str(v: u16) -> &'static str {
+    match v {
+        0x0002 => "SSLv2",
+        0x0300 => "SSLv3",
+        0x0301 => "TLS1.0",
+        0x0302 => "TLS1.1",
+        0x0303 => "TLS1.2",
+        0x0304 => "TLS1.3",
+        0xfeff => "DTLS1.0",
+        0xfefd => "DTLS1.2",
+        0xfefc => "DTLS1.3",
+        _ => "UNKNOW",
+    }
+}
+
+/// Cipher suite id to its IANA name, `None` for unassigned ids
+///
+/// Source:
+/// * https://www.iana.org/assignments/tls-parameters/tls-parameters.xhtml#tls-parameters-4
+/// * 0x0060-0x0066 are only marked reserved by IANA; they are the EXPORT1024 suites of
+///   draft-ietf-tls-56-bit-ciphersuites, which old stacks still negotiate
+pub fn cipher_suite_name(v: u16) -> Option<&'static str> {
+    Some(match v {
+        0x0000 => "TLS_NULL_WITH_NULL_NULL",
+        0x0001 => "TLS_RSA_WITH_NULL_MD5",
+        0x0002 => "TLS_RSA_WITH_NULL_SHA",
+        0x0003 => "TLS_RSA_EXPORT_WITH_RC4_40_MD5",
+        0x0004 => "TLS_RSA_WITH_RC4_128_MD5",
+        0x0005 => "TLS_RSA_WITH_RC4_128_SHA",
+        0x0006 => "TLS_RSA_EXPORT_WITH_RC2_CBC_40_MD5",
+        0x0007 => "TLS_RSA_WITH_IDEA_CBC_SHA",
+        0x0008 => "TLS_RSA_EXPORT_WITH_DES40_CBC_SHA",
+        0x0009 => "TLS_RSA_WITH_DES_CBC_SHA",
+        0x000a => "TLS_RSA_WITH_3DES_EDE_CBC_SHA",
+        0x000b => "TLS_DH_DSS_EXPORT_WITH_DES40_CBC_SHA",
+        0x000c => "TLS_DH_DSS_WITH_DES_CBC_SHA",
+        0x000d => "TLS_DH_DSS_WITH_3DES_EDE_CBC_SHA",
+        0x000e => "TLS_DH_RSA_EXPORT_WITH_DES40_CBC_SHA",
+        0x000f => "TLS_DH_RSA_WITH_DES_CBC_SHA",
+        0x0010 => "TLS_DH_RSA_WITH_3DES_EDE_CBC_SHA",
+        0x0011 => "TLS_DHE_DSS_EXPORT_WITH_DES40_CBC_SHA",
+        0x0012 => "TLS_DHE_DSS_WITH_DES_CBC_SHA",
+        0x0013 => "TLS_DHE_DSS_WITH_3DES_EDE_CBC_SHA",
+        0x0014 => "TLS_DHE_RSA_EXPORT_WITH_DES40_CBC_SHA",
+        0x0015 => "TLS_DHE_RSA_WITH_DES_CBC_SHA",
+        0x0016 => "TLS_DHE_RSA_WITH_3DES_EDE_CBC_SHA",
+        0x0017 => "TLS_DH_anon_EXPORT_WITH_RC4_40_MD5",
+        0x0018 => "TLS_DH_anon_WITH_RC4_128_MD5",
+        0x0019 => "TLS_DH_anon_EXPORT_WITH_DES40_CBC_SHA",
+        0x001a => "TLS_DH_anon_WITH_DES_CBC_SHA",
+        0x001b => "TLS_DH_anon_WITH_3DES_EDE_CBC_SHA",
+        0x001e => "TLS_KRB5_WITH_DES_CBC_SHA",
+        0x001f => "TLS_KRB5_WITH_3DES_EDE_CBC_SHA",
+        0x0020 => "TLS_KRB5_WITH_RC4_128_SHA",
+        0x0021 => "TLS_KRB5_WITH_IDEA_CBC_SHA",
+        0x0022 => "TLS_KRB5_WITH_DES_CBC_MD5",
+        0x0023 => "TLS_KRB5_WITH_3DES_EDE_CBC_MD5",
+        0x0024 => "TLS_KRB5_WITH_RC4_128_MD5",
+        0x0025 => "TLS_KRB5_WITH_IDEA_CBC_MD5",
+        0x0026 => "TLS_KRB5_EXPORT_WITH_DES_CBC_40_SHA",
+        0x0027 => "TLS_KRB5_EXPORT_WITH_RC2_CBC_40_SHA",
+        0x0028 => "TLS_KRB5_EXPORT_WITH_RC4_40_SHA",
+        0x0029 => "TLS_KRB5_EXPORT_WITH_DES_CBC_40_MD5",
+        0x002a => "TLS_KRB5_EXPORT_WITH_RC2_CBC_40_MD5",
+        0x002b => "TLS_KRB5_EXPORT_WITH_RC4_40_MD5",
+        0x002c => "TLS_PSK_WITH_NULL_SHA",
+        0x002d => "TLS_DHE_PSK_WITH_NULL_SHA",
+        0x002e => "TLS_RSA_PSK_WITH_NULL_SHA",
+        0x002f => "TLS_RSA_WITH_AES_128_CBC_SHA",
+        0x0030 => "TLS_DH_DSS_WITH_AES_128_CBC_SHA",
+        0x0031 => "TLS_DH_RSA_WITH_AES_128_CBC_SHA",
+        0x0032 => "TLS_DHE_DSS_WITH_AES_128_CBC_SHA",
+        0x0033 => "TLS_DHE_RSA_WITH_AES_128_CBC_SHA",
+        0x0034 => "TLS_DH_anon_WITH_AES_128_CBC_SHA",
+        0x0035 => "TLS_RSA_WITH_AES_256_CBC_SHA",
+        0x0036 => "TLS_DH_DSS_WITH_AES_256_CBC_SHA",
+        0x0037 => "TLS_DH_RSA_WITH_AES_256_CBC_SHA",
+        0x0038 => "TLS_DHE_DSS_WITH_AES_256_CBC_SHA",
+        0x0039 => "TLS_DHE_RSA_WITH_AES_256_CBC_SHA",
+        0x003a => "TLS_DH_anon_WITH_AES_256_CBC_SHA",
+        0x003b => "TLS_RSA_WITH_NULL_SHA256",
+        0x003c => "TLS_RSA_WITH_AES_128_CBC_SHA256",
+        0x003d => "TLS_RSA_WITH_AES_256_CBC_SHA256",
+        0x003e => "TLS_DH_DSS_WITH_AES_128_CBC_SHA256",
+        0x003f => "TLS_DH_RSA_WITH_AES_128_CBC_SHA256",
+        0x0040 => "TLS_DHE_DSS_WITH_AES_128_CBC_SHA256",
+        0x0041 => "TLS_RSA_WITH_CAMELLIA_128_CBC_SHA",
+        0x0042 => "TLS_DH_DSS_WITH_CAMELLIA_128_CBC_SHA",
+        0x0043 => "TLS_DH_RSA_WITH_CAMELLIA_128_CBC_SHA",
+        0x0044 => "TLS_DHE_DSS_WITH_CAMELLIA_128_CBC_SHA",
+        0x0045 => "TLS_DHE_RSA_WITH_CAMELLIA_128_CBC_SHA",
+        0x0046 => "TLS_DH_anon_WITH_CAMELLIA_128_CBC_SHA",
+        0x0060 => "TLS_RSA_EXPORT1024_WITH_RC4_56_MD5",
+        0x0061 => "TLS_RSA_EXPORT1024_WITH_RC2_CBC_56_MD5",
+        0x0062 => "TLS_RSA_EXPORT1024_WITH_DES_CBC_SHA",
+        0x0063 => "TLS_DHE_DSS_EXPORT1024_WITH_DES_CBC_SHA",
+        0x0064 => "TLS_RSA_EXPORT1024_WITH_RC4_56_SHA",
+        0x0065 => "TLS_DHE_DSS_EXPORT1024_WITH_RC4_56_SHA",
+        0x0066 => "TLS_DHE_DSS_WITH_RC4_128_SHA",
+        0x0067 => "TLS_DHE_RSA_WITH_AES_128_CBC_SHA256",
+        0x0068 => "TLS_DH_DSS_WITH_AES_256_CBC_SHA256",
+        0x0069 => "TLS_DH_RSA_WITH_AES_256_CBC_SHA256",
+        0x006a => "TLS_DHE_DSS_WITH_AES_256_CBC_SHA256",
+        0x006b => "TLS_DHE_RSA_WITH_AES_256_CBC_SHA256",
+        0x006c => "TLS_DH_anon_WITH_AES_128_CBC_SHA256",
+        0x006d => "TLS_DH_anon_WITH_AES_256_CBC_SHA256",
+        0x0084 => "TLS_RSA_WITH_CAMELLIA_256_CBC_SHA",
+        0x0085 => "TLS_DH_DSS_WITH_CAMELLIA_256_CBC_SHA",
+        0x0086 => "TLS_DH_RSA_WITH_CAMELLIA_256_CBC_SHA",
+        0x0087 => "TLS_DHE_DSS_WITH_CAMELLIA_256_CBC_SHA",
+        0x0088 => "TLS_DHE_RSA_WITH_CAMELLIA_256_CBC_SHA",
+        0x0089 => "TLS_DH_anon_WITH_CAMELLIA_256_CBC_SHA",
+        0x008a => "TLS_PSK_WITH_RC4_128_SHA",
+        0x008b => "TLS_PSK_WITH_3DES_EDE_CBC_SHA",
+        0x008c => "TLS_PSK_WITH_AES_128_CBC_SHA",
+        0x008d => "TLS_PSK_WITH_AES_256_CBC_SHA",
+        0x008e => "TLS_DHE_PSK_WITH_RC4_128_SHA",
+        0x008f => "TLS_DHE_PSK_WITH_3DES_EDE_CBC_SHA",
+        0x0090 => "TLS_DHE_PSK_WITH_AES_128_CBC_SHA",
+        0x0091 => "TLS_DHE_PSK_WITH_AES_256_CBC_SHA",
+        0x0092 => "TLS_RSA_PSK_WITH_RC4_128_SHA",
+        0x0093 => "TLS_RSA_PSK_WITH_3DES_EDE_CBC_SHA",
+        0x0094 => "TLS_RSA_PSK_WITH_AES_128_CBC_SHA",
+        0x0095 => "TLS_RSA_PSK_WITH_AES_256_CBC_SHA",
+        0x0096 => "TLS_RSA_WITH_SEED_CBC_SHA",
+        0x0097 => "TLS_DH_DSS_WITH_SEED_CBC_SHA",
+        0x0098 => "TLS_DH_RSA_WITH_SEED_CBC_SHA",
+        0x0099 => "TLS_DHE_DSS_WITH_SEED_CBC_SHA",
+        0x009a => "TLS_DHE_RSA_WITH_SEED_CBC_SHA",
+        0x009b => "TLS_DH_anon_WITH_SEED_CBC_SHA",
+        0x009c => "TLS_RSA_WITH_AES_128_GCM_SHA256",
+        0x009d => "TLS_RSA_WITH_AES_256_GCM_SHA384",
+        0x009e => "TLS_DHE_RSA_WITH_AES_128_GCM_SHA256",
+        0x009f => "TLS_DHE_RSA_WITH_AES_256_GCM_SHA384",
+        0x00a0 => "TLS_DH_RSA_WITH_AES_128_GCM_SHA256",
+        0x00a1 => "TLS_DH_RSA_WITH_AES_256_GCM_SHA384",
+        0x00a2 => "TLS_DHE_DSS_WITH_AES_128_GCM_SHA256",
+        0x00a3 => "TLS_DHE_DSS_WITH_AES_256_GCM_SHA384",
+        0x00a4 => "TLS_DH_DSS_WITH_AES_128_GCM_SHA256",
+        0x00a5 => "TLS_DH_DSS_WITH_AES_256_GCM_SHA384",
+        0x00a6 => "TLS_DH_anon_WITH_AES_128_GCM_SHA256",
+        0x00a7 => "TLS_DH_anon_WITH_AES_256_GCM_SHA384",
+        0x00a8 => "TLS_PSK_WITH_AES_128_GCM_SHA256",
+        0x00a9 => "TLS_PSK_WITH_AES_256_GCM_SHA384",
+        0x00aa => "TLS_DHE_PSK_WITH_AES_128_GCM_SHA256",
+        0x00ab => "TLS_DHE_PSK_WITH_AES_256_GCM_SHA384",
+        0x00ac => "TLS_RSA_PSK_WITH_AES_128_GCM_SHA256",
+        0x00ad => "TLS_RSA_PSK_WITH_AES_256_GCM_SHA384",
+        0x00ae => "TLS_PSK_WITH_AES_128_CBC_SHA256",
+        0x00af => "TLS_PSK_WITH_AES_256_CBC_SHA384",
+        0x00b0 => "TLS_PSK_WITH_NULL_SHA256",
+        0x00b1 => "TLS_PSK_WITH_NULL_SHA384",
+        0x00b2 => "TLS_DHE_PSK_WITH_AES_128_CBC_SHA256",
+        0x00b3 => "TLS_DHE_PSK_WITH_AES_256_CBC_SHA384",
+        0x00b4 => "TLS_DHE_PSK_WITH_NULL_SHA256",
+        0x00b5 => "TLS_DHE_PSK_WITH_NULL_SHA384",
+        0x00b6 => "TLS_RSA_PSK_WITH_AES_128_CBC_SHA256",
+        0x00b7 => "TLS_RSA_PSK_WITH_AES_256_CBC_SHA384",
+        0x00b8 => "TLS_RSA_PSK_WITH_NULL_SHA256",
+        0x00b9 => "TLS_RSA_PSK_WITH_NULL_SHA384",
+        0x00ba => "TLS_RSA_WITH_CAMELLIA_128_CBC_SHA256",
+        0x00bb => "TLS_DH_DSS_WITH_CAMELLIA_128_CBC_SHA256",
+        0x00bc => "TLS_DH_RSA_WITH_CAMELLIA_128_CBC_SHA256",
+        0x00bd => "TLS_DHE_DSS_WITH_CAMELLIA_128_CBC_SHA256",
+        0x00be => "TLS_DHE_RSA_WITH_CAMELLIA_128_CBC_SHA256",
+        0x00bf => "TLS_DH_anon_WITH_CAMELLIA_128_CBC_SHA256",
+        0x00c0 => "TLS_RSA_WITH_CAMELLIA_256_CBC_SHA256",
+        0x00c1 => "TLS_DH_DSS_WITH_CAMELLIA_256_CBC_SHA256",
+        0x00c2 => "TLS_DH_RSA_WITH_CAMELLIA_256_CBC_SHA256",
+        0x00c3 => "TLS_DHE_DSS_WITH_CAMELLIA_256_CBC_SHA256",
+        0x00c4 => "TLS_DHE_RSA_WITH_CAMELLIA_256_CBC_SHA256",
+        0x00c5 => "TLS_DH_anon_WITH_CAMELLIA_256_CBC_SHA256",
+        0x00c6 => "TLS_SM4_GCM_SM3",
+        0x00c7 => "TLS_SM4_CCM_SM3",
+        0x00ff => "TLS_EMPTY_RENEGOTIATION_INFO_SCSV",
+        0x1301 => "TLS_AES_128_GCM_SHA256",
+        0x1302 => "TLS_AES_256_GCM_SHA384",
+        0x1303 => "TLS_CHACHA20_POLY1305_SHA256",
+        0x1304 => "TLS_AES_128_CCM_SHA256",
+        0x1305 => "TLS_AES_128_CCM_8_SHA256",
+        0x1306 => "TLS_AEGIS_256_SHA512",
+        0x1307 => "TLS_AEGIS_128L_SHA256",
+        0x5600 => "TLS_FALLBACK_SCSV",
+        0xc001 => "TLS_ECDH_ECDSA_WITH_NULL_SHA",
+        0xc002 => "TLS_ECDH_ECDSA_WITH_RC4_128_SHA",
+        0xc003 => "TLS_ECDH_ECDSA_WITH_3DES_EDE_CBC_SHA",
+        0xc004 => "TLS_ECDH_ECDSA_WITH_AES_128_CBC_SHA",
+        0xc005 => "TLS_ECDH_ECDSA_WITH_AES_256_CBC_SHA",
+        0xc006 => "TLS_ECDHE_ECDSA_WITH_NULL_SHA",
+        0xc007 => "TLS_ECDHE_ECDSA_WITH_RC4_128_SHA",
+        0xc008 => "TLS_ECDHE_ECDSA_WITH_3DES_EDE_CBC_SHA",
+        0xc009 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA",
+        0xc00a => "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA",
+        0xc00b => "TLS_ECDH_RSA_WITH_NULL_SHA",
+        0xc00c => "TLS_ECDH_RSA_WITH_RC4_128_SHA",
+        0xc00d => "TLS_ECDH_RSA_WITH_3DES_EDE_CBC_SHA",
+        0xc00e => "TLS_ECDH_RSA_WITH_AES_128_CBC_SHA",
+        0xc00f => "TLS_ECDH_RSA_WITH_AES_256_CBC_SHA",
+        0xc010 => "TLS_ECDHE_RSA_WITH_NULL_SHA",
+        0xc011 => "TLS_ECDHE_RSA_WITH_RC4_128_SHA",
+        0xc012 => "TLS_ECDHE_RSA_WITH_3DES_EDE_CBC_SHA",
+        0xc013 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA",
+        0xc014 => "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA",
+        0xc015 => "TLS_ECDH_anon_WITH_NULL_SHA",
+        0xc016 => "TLS_ECDH_anon_WITH_RC4_128_SHA",
+        0xc017 => "TLS_ECDH_anon_WITH_3DES_EDE_CBC_SHA",
+        0xc018 => "TLS_ECDH_anon_WITH_AES_128_CBC_SHA",
+        0xc019 => "TLS_ECDH_anon_WITH_AES_256_CBC_SHA",
+        0xc01a => "TLS_SRP_SHA_WITH_3DES_EDE_CBC_SHA",
+        0xc01b => "TLS_SRP_SHA_RSA_WITH_3DES_EDE_CBC_SHA",
+        0xc01c => "TLS_SRP_SHA_DSS_WITH_3DES_EDE_CBC_SHA",
+        0xc01d => "TLS_SRP_SHA_WITH_AES_128_CBC_SHA",
+        0xc01e => "TLS_SRP_SHA_RSA_WITH_AES_128_CBC_SHA",
+        0xc01f => "TLS_SRP_SHA_DSS_WITH_AES_128_CBC_SHA",
+        0xc020 => "TLS_SRP_SHA_WITH_AES_256_CBC_SHA",
+        0xc021 => "TLS_SRP_SHA_RSA_WITH_AES_256_CBC_SHA",
+        0xc022 => "TLS_SRP_SHA_DSS_WITH_AES_256_CBC_SHA",
+        0xc023 => "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA256",
+        0xc024 => "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA384",
+        0xc025 => "TLS_ECDH_ECDSA_WITH_AES_128_CBC_SHA256",
+        0xc026 => "TLS_ECDH_ECDSA_WITH_AES_256_CBC_SHA384",
+        0xc027 => "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA256",
+        0xc028 => "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA384",
+        0xc029 => "TLS_ECDH_RSA_WITH_AES_128_CBC_SHA256",
+        0xc02a => "TLS_ECDH_RSA_WITH_AES_256_CBC_SHA384",
+        0xc02b => "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+        0xc02c => "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384",
+        0xc02d => "TLS_ECDH_ECDSA_WITH_AES_128_GCM_SHA256",
+        0xc02e => "TLS_ECDH_ECDSA_WITH_AES_256_GCM_SHA384",
+        0xc02f => "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256",
+        0xc030 => "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384",
+        0xc031 => "TLS_ECDH_RSA_WITH_AES_128_GCM_SHA256",
+        0xc032 => "TLS_ECDH_RSA_WITH_AES_256_GCM_SHA384",
+        0xc033 => "TLS_ECDHE_PSK_WITH_RC4_128_SHA",
+        0xc034 => "TLS_ECDHE_PSK_WITH_3DES_EDE_CBC_SHA",
+        0xc035 => "TLS_ECDHE_PSK_WITH_AES_128_CBC_SHA",
+        0xc036 => "TLS_ECDHE_PSK_WITH_AES_256_CBC_SHA",
+        0xc037 => "TLS_ECDHE_PSK_WITH_AES_128_CBC_SHA256",
+        0xc038 => "TLS_ECDHE_PSK_WITH_AES_256_CBC_SHA384",
+        0xc039 => "TLS_ECDHE_PSK_WITH_NULL_SHA",
+        0xc03a => "TLS_ECDHE_PSK_WITH_NULL_SHA256",
+        0xc03b => "TLS_ECDHE_PSK_WITH_NULL_SHA384",
+        0xc03c => "TLS_RSA_WITH_ARIA_128_CBC_SHA256",
+        0xc03d => "TLS_RSA_WITH_ARIA_256_CBC_SHA384",
+        0xc03e => "TLS_DH_DSS_WITH_ARIA_128_CBC_SHA256",
+        0xc03f => "TLS_DH_DSS_WITH_ARIA_256_CBC_SHA384",
+        0xc040 => "TLS_DH_RSA_WITH_ARIA_128_CBC_SHA256",
+        0xc041 => "TLS_DH_RSA_WITH_ARIA_256_CBC_SHA384",
+        0xc042 => "TLS_DHE_DSS_WITH_ARIA_128_CBC_SHA256",
+        0xc043 => "TLS_DHE_DSS_WITH_ARIA_256_CBC_SHA384",
+        0xc044 => "TLS_DHE_RSA_WITH_ARIA_128_CBC_SHA256",
+        0xc045 => "TLS_DHE_RSA_WITH_ARIA_256_CBC_SHA384",
+        0xc046 => "TLS_DH_anon_WITH_ARIA_128_CBC_SHA256",
+        0xc047 => "TLS_DH_anon_WITH_ARIA_256_CBC_SHA384",
+        0xc048 => "TLS_ECDHE_ECDSA_WITH_ARIA_128_CBC_SHA256",
+        0xc049 => "TLS_ECDHE_ECDSA_WITH_ARIA_256_CBC_SHA384",
+        0xc04a => "TLS_ECDH_ECDSA_WITH_ARIA_128_CBC_SHA256",
+        0xc04b => "TLS_ECDH_ECDSA_WITH_ARIA_256_CBC_SHA384",
+        0xc04c => "TLS_ECDHE_RSA_WITH_ARIA_128_CBC_SHA256",
+        0xc04d => "TLS_ECDHE_RSA_WITH_ARIA_256_CBC_SHA384",
+        0xc04e => "TLS_ECDH_RSA_WITH_ARIA_128_CBC_SHA256",
+        0xc04f => "TLS_ECDH_RSA_WITH_ARIA_256_CBC_SHA384",
+        0xc050 => "TLS_RSA_WITH_ARIA_128_GCM_SHA256",
+        0xc051 => "TLS_RSA_WITH_ARIA_256_GCM_SHA384",
+        0xc052 => "TLS_DHE_RSA_WITH_ARIA_128_GCM_SHA256",
+        0xc053 => "TLS_DHE_RSA_WITH_ARIA_256_GCM_SHA384",
+        0xc054 => "TLS_DH_RSA_WITH_ARIA_128_GCM_SHA256",
+        0xc055 => "TLS_DH_RSA_WITH_ARIA_256_GCM_SHA384",
+        0xc056 => "TLS_DHE_DSS_WITH_ARIA_128_GCM_SHA256",
+        0xc057 => "TLS_DHE_DSS_WITH_ARIA_256_GCM_SHA384",
+        0xc058 => "TLS_DH_DSS_WITH_ARIA_128_GCM_SHA256",
+        0xc059 => "TLS_DH_DSS_WITH_ARIA_256_GCM_SHA384",
+        0xc05a => "TLS_DH_anon_WITH_ARIA_128_GCM_SHA256",
+        0xc05b => "TLS_DH_anon_WITH_ARIA_256_GCM_SHA384",
+        0xc05c => "TLS_ECDHE_ECDSA_WITH_ARIA_128_GCM_SHA256",
+        0xc05d => "TLS_ECDHE_ECDSA_WITH_ARIA_256_GCM_SHA384",
+        0xc05e => "TLS_ECDH_ECDSA_WITH_ARIA_128_GCM_SHA256",
+        0xc05f => "TLS_ECDH_ECDSA_WITH_ARIA_256_GCM_SHA384",
+        0xc060 => "TLS_ECDHE_RSA_WITH_ARIA_128_GCM_SHA256",
+        0xc061 => "TLS_ECDHE_RSA_WITH_ARIA_256_GCM_SHA384",
+        0xc062 => "TLS_ECDH_RSA_WITH_ARIA_128_GCM_SHA256",
+        0xc063 => "TLS_ECDH_RSA_WITH_ARIA_256_GCM_SHA384",
+        0xc064 => "TLS_PSK_WITH_ARIA_128_CBC_SHA256",
+        0xc065 => "TLS_PSK_WITH_ARIA_256_CBC_SHA384",
+        0xc066 => "TLS_DHE_PSK_WITH_ARIA_128_CBC_SHA256",
+        0xc067 => "TLS_DHE_PSK_WITH_ARIA_256_CBC_SHA384",
+        0xc068 => "TLS_RSA_PSK_WITH_ARIA_128_CBC_SHA256",
+        0xc069 => "TLS_RSA_PSK_WITH_ARIA_256_CBC_SHA384",
+        0xc06a => "TLS_PSK_WITH_ARIA_128_GCM_SHA256",
+        0xc06b => "TLS_PSK_WITH_ARIA_256_GCM_SHA384",
+        0xc06c => "TLS_DHE_PSK_WITH_ARIA_128_GCM_SHA256",
+        0xc06d => "TLS_DHE_PSK_WITH_ARIA_256_GCM_SHA384",
+        0xc06e => "TLS_RSA_PSK_WITH_ARIA_128_GCM_SHA256",
+        0xc06f => "TLS_RSA_PSK_WITH_ARIA_256_GCM_SHA384",
+        0xc070 => "TLS_ECDHE_PSK_WITH_ARIA_128_CBC_SHA256",
+        0xc071 => "TLS_ECDHE_PSK_WITH_ARIA_256_CBC_SHA384",
+        0xc072 => "TLS_ECDHE_ECDSA_WITH_CAMELLIA_128_CBC_SHA256",
+        0xc073 => "TLS_ECDHE_ECDSA_WITH_CAMELLIA_256_CBC_SHA384",
+        0xc074 => "TLS_ECDH_ECDSA_WITH_CAMELLIA_128_CBC_SHA256",
+        0xc075 => "TLS_ECDH_ECDSA_WITH_CAMELLIA_256_CBC_SHA384",
+        0xc076 => "TLS_ECDHE_RSA_WITH_CAMELLIA_128_CBC_SHA256",
+        0xc077 => "TLS_ECDHE_RSA_WITH_CAMELLIA_256_CBC_SHA384",
+        0xc078 => "TLS_ECDH_RSA_WITH_CAMELLIA_128_CBC_SHA256",
+        0xc079 => "TLS_ECDH_RSA_WITH_CAMELLIA_256_CBC_SHA384",
+        0xc07a => "TLS_RSA_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc07b => "TLS_RSA_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc07c => "TLS_DHE_RSA_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc07d => "TLS_DHE_RSA_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc07e => "TLS_DH_RSA_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc07f => "TLS_DH_RSA_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc080 => "TLS_DHE_DSS_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc081 => "TLS_DHE_DSS_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc082 => "TLS_DH_DSS_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc083 => "TLS_DH_DSS_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc084 => "TLS_DH_anon_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc085 => "TLS_DH_anon_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc086 => "TLS_ECDHE_ECDSA_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc087 => "TLS_ECDHE_ECDSA_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc088 => "TLS_ECDH_ECDSA_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc089 => "TLS_ECDH_ECDSA_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc08a => "TLS_ECDHE_RSA_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc08b => "TLS_ECDHE_RSA_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc08c => "TLS_ECDH_RSA_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc08d => "TLS_ECDH_RSA_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc08e => "TLS_PSK_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc08f => "TLS_PSK_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc090 => "TLS_DHE_PSK_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc091 => "TLS_DHE_PSK_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc092 => "TLS_RSA_PSK_WITH_CAMELLIA_128_GCM_SHA256",
+        0xc093 => "TLS_RSA_PSK_WITH_CAMELLIA_256_GCM_SHA384",
+        0xc094 => "TLS_PSK_WITH_CAMELLIA_128_CBC_SHA256",
+        0xc095 => "TLS_PSK_WITH_CAMELLIA_256_CBC_SHA384",
+        0xc096 => "TLS_DHE_PSK_WITH_CAMELLIA_128_CBC_SHA256",
+        0xc097 => "TLS_DHE_PSK_WITH_CAMELLIA_256_CBC_SHA384",
+        0xc098 => "TLS_RSA_PSK_WITH_CAMELLIA_128_CBC_SHA256",
+        0xc099 => "TLS_RSA_PSK_WITH_CAMELLIA_256_CBC_SHA384",
+        0xc09a => "TLS_ECDHE_PSK_WITH_CAMELLIA_128_CBC_SHA256",
+        0xc09b => "TLS_ECDHE_PSK_WITH_CAMELLIA_256_CBC_SHA384",
+        0xc09c => "TLS_RSA_WITH_AES_128_CCM",
+        0xc09d => "TLS_RSA_WITH_AES_256_CCM",
+        0xc09e => "TLS_DHE_RSA_WITH_AES_128_CCM",
+        0xc09f => "TLS_DHE_RSA_WITH_AES_256_CCM",
+        0xc0a0 => "TLS_RSA_WITH_AES_128_CCM_8",
+        0xc0a1 => "TLS_RSA_WITH_AES_256_CCM_8",
+        0xc0a2 => "TLS_DHE_RSA_WITH_AES_128_CCM_8",
+        0xc0a3 => "TLS_DHE_RSA_WITH_AES_256_CCM_8",
+        0xc0a4 => "TLS_PSK_WITH_AES_128_CCM",
+        0xc0a5 => "TLS_PSK_WITH_AES_256_CCM",
+        0xc0a6 => "TLS_DHE_PSK_WITH_AES_128_CCM",
+        0xc0a7 => "TLS_DHE_PSK_WITH_AES_256_CCM",
+        0xc0a8 => "TLS_PSK_WITH_AES_128_CCM_8",
+        0xc0a9 => "TLS_PSK_WITH_AES_256_CCM_8",
+        0xc0aa => "TLS_PSK_DHE_WITH_AES_128_CCM_8",
+        0xc0ab => "TLS_PSK_DHE_WITH_AES_256_CCM_8",
+        0xc0ac => "TLS_ECDHE_ECDSA_WITH_AES_128_CCM",
+        0xc0ad => "TLS_ECDHE_ECDSA_WITH_AES_256_CCM",
+        0xc0ae => "TLS_ECDHE_ECDSA_WITH_AES_128_CCM_8",
+        0xc0af => "TLS_ECDHE_ECDSA_WITH_AES_256_CCM_8",
+        0xc0b0 => "TLS_ECCPWD_WITH_AES_128_GCM_SHA256",
+        0xc0b1 => "TLS_ECCPWD_WITH_AES_256_GCM_SHA384",
+        0xc0b2 => "TLS_ECCPWD_WITH_AES_128_CCM_SHA256",
+        0xc0b3 => "TLS_ECCPWD_WITH_AES_256_CCM_SHA384",
+        0xc0b4 => "TLS_SHA256_SHA256",
+        0xc0b5 => "TLS_SHA384_SHA384",
+        0xc100 => "TLS_GOSTR341112_256_WITH_KUZNYECHIK_CTR_OMAC",
+        0xc101 => "TLS_GOSTR341112_256_WITH_MAGMA_CTR_OMAC",
+        0xc102 => "TLS_GOSTR341112_256_WITH_28147_CNT_IMIT",
+        0xc103 => "TLS_GOSTR341112_256_WITH_KUZNYECHIK_MGM_L",
+        0xc104 => "TLS_GOSTR341112_256_WITH_MAGMA_MGM_L",
+        0xc105 => "TLS_GOSTR341112_256_WITH_KUZNYECHIK_MGM_S",
+        0xc106 => "TLS_GOSTR341112_256_WITH_MAGMA_MGM_S",
+        0xcca8 => "TLS_ECDHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xcca9 => "TLS_ECDHE_ECDSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xccaa => "TLS_DHE_RSA_WITH_CHACHA20_POLY1305_SHA256",
+        0xccab => "TLS_PSK_WITH_CHACHA20_POLY1305_SHA256",
+        0xccac => "TLS_ECDHE_PSK_WITH_CHACHA20_POLY1305_SHA256",
+        0xccad => "TLS_DHE_PSK_WITH_CHACHA20_POLY1305_SHA256",
+        0xccae => "TLS_RSA_PSK_WITH_CHACHA20_POLY1305_SHA256",
+        0xd001 => "TLS_ECDHE_PSK_WITH_AES_128_GCM_SHA256",
+        0xd002 => "TLS_ECDHE_PSK_WITH_AES_256_GCM_SHA384",
+        0xd003 => "TLS_ECDHE_PSK_WITH_AES_128_CCM_8_SHA256",
+        0xd005 => "TLS_ECDHE_PSK_WITH_AES_128_CCM_SHA256",
+        _ => return None,
+    })
+}
+
+/// Cipher suite id to its IANA name
+pub fn cipher_suite_as_str(v: u16) -> &'static str {
+    cipher_suite_name(v).unwrap_or("UNKNOW")
+}
+
+fn u16_list(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+        .collect()
+}
+
+/// Record header if `data` starts with something that looks like a TLS record
+pub fn record_header(data: &[u8]) -> Option<(u8, u16, usize)> {
+    let mut r = ByteReader::new(data);
+    let content_type = r.u8().ok()?;
+    let version = r.u16().ok()?;
+    let len = r.u16().ok()? as usize;
+    let valid_type = (CONTENT::CHANGE_CIPHER_SPEC..=CONTENT::HEARTBEAT).contains(&content_type);
+    if !valid_type || (version >> 8) != 3 || len > MAX_RECORD_LEN {
+        return None;
+    }
+    Some((content_type, version, len))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Extension<'a> {
+    pub ext_type: u16,
+    pub data: &'a [u8],
+}
+
+fn parse_extensions<'a>(r: &mut ByteReader<'a>) -> Option<Vec<Extension<'a>>> {
+    // extensions are optional in hellos before TLS 1.2
+    if r.is_empty() {
+        return Some(Vec::new());
+    }
+    let mut block = ByteReader::new(r.bytes_u16().ok()?);
+    let mut exts = Vec::new();
+    while !block.is_empty() {
+        let (ext_type, data) = block.tlv16().ok()?;
+        exts.push(Extension { ext_type, data });
+    }
+    Some(exts)
+}
+
+fn find_extension<'a>(exts: &[Extension<'a>], ext_type: u16) -> Option<&'a [u8]> {
+    exts.iter().find(|e| e.ext_type == ext_type).map(|e| e.data)
+}
+
+/// First entry of an ALPN protocol name list
+fn first_alpn(ext: &[u8]) -> Option<&[u8]> {
+    let list = ByteReader::new(ext).bytes_u16().ok()?;
+    ByteReader::new(list).bytes_u8().ok()
+}
+
+/// ClientHello handshake message (RFC 8446 4.1.2)
+#[derive(Debug, Clone)]
+pub struct ClientHello<'a> {
+    /// legacy_version field, the highest version for TLS <= 1.2
+    pub version: u16,
+    pub random: &'a [u8],
+    pub session_id: &'a [u8],
+    pub cipher_suites: Vec<u16>,
+    pub compression_methods: &'a [u8],
+    /// extensions in the order sent by the client
+    pub extensions: Vec<Extension<'a>>,
+}
+
+impl<'a> ClientHello<'a> {
+    /// Parse the body of a ClientHello handshake message (without the 4 bytes header)
+    pub fn parse(body: &'a [u8]) -> Option<Self> {
+        let mut r = ByteReader::new(body);
+        let version = r.u16().ok()?;
+        let random = r.bytes(32).ok()?;
+        let session_id = r.bytes_u8().ok()?;
+        let cipher_suites = u16_list(r.bytes_u16().ok()?);
+        let compression_methods = r.bytes_u8().ok()?;
+        let extensions = parse_extensions(&mut r)?;
+        Some(ClientHello {
+            version,
+            random,
+            session_id,
+            cipher_suites,
+            compression_methods,
+            extensions,
+        })
+    }
+
+    pub fn extension(&self, ext_type: u16) -> Option<&'a [u8]> {
+        find_extension(&self.extensions, ext_type)
+    }
+
+    /// host_name entry of the server_name extension
+    pub fn server_name(&self) -> Option<&'a str> {
+        let ext = self.extension(EXT::SERVER_NAME)?;
+        let mut list = ByteReader::new(ByteReader::new(ext).bytes_u16().ok()?);
+        while !list.is_empty() {
+            let name_type = list.u8().ok()?;
+            let name = list.bytes_u16().ok()?;
+            if name_type == 0 {
+                return std::str::from_utf8(name).ok();
+            }
+        }
+        None
+    }
+
+    /// First protocol offered in the ALPN extension
+    pub fn alpn(&self) -> Option<&'a [u8]> {
+        first_alpn(self.extension(EXT::ALPN)?)
+    }
+
+    pub fn supported_versions(&self) -> Vec<u16> {
+        self.extension(EXT::SUPPORTED_VERSIONS)
+            .and_then(|ext| ByteReader::new(ext).bytes_u8().ok())
+            .map(u16_list)
+            .unwrap_or_default()
+    }
+
+    pub fn supported_groups(&self) -> Vec<u16> {
+        self.extension(EXT::SUPPORTED_GROUPS)
+            .and_then(|ext| ByteReader::new(ext).bytes_u16().ok())
+            .map(u16_list)
+            .unwrap_or_default()
+    }
+
+    pub fn ec_point_formats(&self) -> &'a [u8] {
+        self.extension(EXT::EC_POINT_FORMATS)
+            .and_then(|ext| ByteReader::new(ext).bytes_u8().ok())
+            .unwrap_or(&[])
+    }
+
+    pub fn signature_algorithms(&self) -> Vec<u16> {
+        self.extension(EXT::SIGNATURE_ALGORITHMS)
+            .and_then(|ext| ByteReader::new(ext).bytes_u16().ok())
+            .map(u16_list)
+            .unwrap_or_default()
+    }
+
+    /// Highest non GREASE version offered, looking at supported_versions first
+    pub fn max_version(&self) -> u16 {
+        self.supported_versions()
+            .into_iter()
+            .filter(|v| !is_grease(*v))
+            .max()
+            .unwrap_or(self.version)
+    }
+}
+
+/// ServerHello handshake message (RFC 8446 4.1.3)
+#[derive(Debug, Clone)]
+pub struct ServerHello<'a> {
+    /// legacy_version field, 0x0303 for TLS 1.3
+    pub version: u16,
+    pub random: &'a [u8],
+    pub session_id: &'a [u8],
+    pub cipher_suite: u16,
+    pub compression_method: u8,
+    pub extensions: Vec<Extension<'a>>,
+}
+
+impl<'a> ServerHello<'a> {
+    /// Parse the body of a ServerHello handshake message (without the 4 bytes header)
+    pub fn parse(body: &'a [u8]) -> Option<Self> {
+        let mut r = ByteReader::new(body);
+        let version = r.u16().ok()?;
+        let random = r.bytes(32).ok()?;
+        let session_id = r.bytes_u8().ok()?;
+        let cipher_suite = r.u16().ok()?;
+        let compression_method = r.u8().ok()?;
+        let extensions = parse_extensions(&mut r)?;
+        Some(ServerHello {
+            version,
+            random,
+            session_id,
+            cipher_suite,
+            compression_method,
+            extensions,
+        })
+    }
+
+    pub fn extension(&self, ext_type: u16) -> Option<&'a [u8]> {
+        find_extension(&self.extensions, ext_type)
+    }
+
+    /// Negotiated version: supported_versions extension (TLS 1.3) or legacy version
+    pub fn selected_version(&self) -> u16 {
+        self.extension(EXT::SUPPORTED_VERSIONS)
+            .and_then(|ext| ByteReader::new(ext).u16().ok())
+            .unwrap_or(self.version)
+    }
+
+    /// Protocol selected through ALPN (only visible here up to TLS 1.2)
+    pub fn alpn(&self) -> Option<&'a [u8]> {
+        first_alpn(self.extension(EXT::ALPN)?)
+    }
+}
+
+/// Handshake message: type and body
+#[derive(Debug, Clone, Copy)]
+pub struct Handshake<'a> {
+    pub msg_type: u8,
+    pub body: &'a [u8],
+}
+
+/// Iterator over the complete handshake messages of a handshake byte stream
+pub struct Messages<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Messages<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Messages { data }
+    }
+
+    /// Bytes left after the last complete message
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> Iterator for Messages<'a> {
+    type Item = Handshake<'a>;
+
+    fn next(&mut self) -> Option<Handshake<'a>> {
+        let mut r = ByteReader::new(self.data);
+        let msg_type = r.u8().ok()?;
+        let len = r.u24().ok()? as usize;
+        let body = r.bytes(len).ok()?;
+        self.data = r.rest();
+        Some(Handshake { msg_type, body })
+    }
+}
+
+/// Handshake bytes carried by the leading handshake records of a segment
+///
+/// Borrowed when a single record carries them, which is the common case.
+pub fn handshake_payload(data: &[u8]) -> Option<Cow<'_, [u8]>> {
+    let mut out: Option<Cow<[u8]>> = None;
+    let mut rest = data;
+    while let Some((content_type, _, len)) = record_header(rest) {
+        if content_type != CONTENT::HANDSHAKE {
+            break;
+        }
+        let end = (RECORD_HEADER_SIZE + len).min(rest.len());
+        let fragment = &rest[RECORD_HEADER_SIZE..end];
+        out = Some(match out {
+            None => Cow::Borrowed(fragment),
+            Some(prev) => {
+                let mut v = prev.into_owned();
+                v.extend_from_slice(fragment);
+                Cow::Owned(v)
+            }
+        });
+        rest = &rest[end..];
+    }
+    out
+}
+
+fn client_hello_line(ch: &ClientHello) -> String {
+    let fp = fingerprint::ClientFingerprint::compute(ch, Transport::Tcp);
+    match ch.server_name() {
+        Some(sni) => format!(
+            "TLS ClientHello SNI={} ja3={} ja4={}",
+            sni, fp.ja3_hash, fp.ja4
+        ),
+        None => format!("TLS ClientHello ja3={} ja4={}", fp.ja3_hash, fp.ja4),
+    }
+}
+
+fn server_hello_line(sh: &ServerHello) -> String {
+    let fp = fingerprint::ServerFingerprint::compute(sh, Transport::Tcp);
+    format!(
+        "TLS ServerHello version={} cipher=0x{:04x} ja3s={} ja4s={}",
+        version_as_str(sh.selected_version()),
+        sh.cipher_suite,
+        fp.ja3s_hash,
+        fp.ja4s
+    )
+}
+
+/// Confidence that the payload starts with a TLS record
+pub fn probe(data: &[u8]) -> u8 {
+    match record_header(data) {
+        Some((CONTENT::HANDSHAKE, _, _))
+            if matches!(
+                data.get(RECORD_HEADER_SIZE),
+                Some(&(HANDSHAKE::CLIENT_HELLO | HANDSHAKE::SERVER_HELLO))
+            ) =>
+        {
+            confidence::CERTAIN
+        }
+        Some((_, version, _)) if version <= 0x0304 => confidence::HIGH,
+        Some(_) => confidence::LOW,
+        None => confidence::NONE,
+    }
+}
+
 pub fn decode(data: &[u8]) -> bool {
-    if data.len() < 5 {
-        return false;
-    }
-    // TLS record header
-    let content_type = data[0];
-    let _ver = u16::from_be_bytes([data[1], data[2]]);
-    let len = u16::from_be_bytes([data[3], data[4]]) as usize;
-    if content_type != 0x16 || data.len() < 5 + len {
-        return false;
-    }
-    if data.len() < 10 {
-        return false;
-    }
-    let hs_type = data[5];
-    if hs_type != 0x01 {
-        return false;
-    }
-    // very rough SNI parse
-    // skip record(5) + hs header(4) + client_version(2) + random(32)
-    let mut i = 5 + 4 + 2 + 32;
-    if data.len() <= i {
-        return true;
-    }
-    let sid_len = data[i] as usize;
-    i += 1 + sid_len;
-    if data.len() <= i + 2 {
-        return true;
-    }
-    let cs_len = u16::from_be_bytes([data[i], data[i + 1]]) as usize;
-    i += 2 + cs_len;
-    if data.len() <= i + 1 {
-        return true;
-    }
-    let comp_len = data[i] as usize;
-    i += 1 + comp_len;
-    if data.len() <= i + 2 {
-        return true;
-    }
-    let ext_len = u16::from_be_bytes([data[i], data[i + 1]]) as usize;
-    i += 2;
-    let end = core::cmp::min(data.len(), i + ext_len);
-    let mut sni: Option<String> = None;
-    let mut j = i;
-    while j + 4 <= end {
-        let et = u16::from_be_bytes([data[j], data[j + 1]]);
-        j += 2;
-        let el = u16::from_be_bytes([data[j], data[j + 1]]) as usize;
-        j += 2;
-        if j + el > end {
+    let hs = match handshake_payload(data) {
+        Some(hs) => hs,
+        None => return false,
+    };
+    let mut seen = false;
+    let mut messages = Messages::new(&hs);
+    loop {
+        // offset of the message, as if the handshake bytes were in a single record
+        let at = RECORD_HEADER_SIZE + hs.len() - messages.remaining().len();
+        let msg = match messages.next() {
+            Some(msg) => msg,
+            None => break,
+        };
+        match msg.msg_type {
+            HANDSHAKE::CLIENT_HELLO => match ClientHello::parse(msg.body) {
+                Some(ch) => {
+                    let line = client_hello_line(&ch);
+                    println!("{}", line);
+                    if !seen {
+                        tree::layer("tls", data, &line);
+                    }
+                    tree::open(
+                        "Client Hello",
+                        msg.body,
+                        format_args!("{} bytes", msg.body.len()),
+                    );
+                    tree::field("Version", &msg.body[..2], version_as_str(ch.version));
+                    if let Some(sni) = ch.server_name() {
+                        tree::field("Server name", sni.as_bytes(), sni);
+                    }
+                    tree::close();
+                    seen = true;
+                }
+                None => expert::error(DecodeError::Malformed {
+                    layer: "tls",
+                    offset: at,
+                    reason: "invalid ClientHello",
+                }),
+            },
+            HANDSHAKE::SERVER_HELLO => {
+                match ServerHello::parse(msg.body) {
+                    Some(sh) => {
+                        let line = server_hello_line(&sh);
+                        println!("{}", line);
+                        if !seen {
+                            tree::layer("tls", data, &line);
+                        }
+                        tree::open(
+                            "Server Hello",
+                            msg.body,
+                            format_args!("{} bytes", msg.body.len()),
+                        );
+                        tree::field(
+                            "Version",
+                            &msg.body[..2],
+                            version_as_str(sh.selected_version()),
+                        );
+                        // after the version, the random and the session id
+                        let at = 2 + 32 + 1 + sh.session_id.len();
+                        if let Some(bytes) = msg.body.get(at..at + 2) {
+                            tree::field(
+                                "Cipher suite",
+                                bytes,
+                                format_args!("0x{:04x}", sh.cipher_suite),
+                            );
+                        }
+                        tree::close();
+                        seen = true;
+                    }
+                    None => expert::error(DecodeError::Malformed {
+                        layer: "tls",
+                        offset: at,
+                        reason: "invalid ServerHello",
+                    }),
+                }
+            }
+            _ => {}
+        }
+    }
+    // hello split over several segments
+    if !seen && messages.remaining().first() == Some(&HANDSHAKE::CLIENT_HELLO) {
+        println!("TLS ClientHello (partial)");
+        tree::layer("tls", data, "TLS ClientHello (partial)");
+        seen = true;
+    }
+    seen
+}
+
+/// DER entries of a Certificate message body
+///
+/// TLS 1.3 adds a request context before the list and extensions after each entry.
+pub fn certificate_list(body: &[u8], tls13: bool) -> Vec<&[u8]> {
+    let mut certs = Vec::new();
+    let mut r = ByteReader::new(body);
+    // certificate_request_context
+    if tls13 && r.bytes_u8().is_err() {
+        return certs;
+    }
+    let mut list = match r.u24().and_then(|len| r.bytes(len as usize)) {
+        Ok(list) => ByteReader::new(list),
+        Err(_) => return certs,
+    };
+    while let Ok(len) = list.u24() {
+        match list.bytes(len as usize) {
+            Ok(der) => certs.push(der),
+            Err(_) => break,
+        }
+        // extensions of the entry
+        if tls13 && list.bytes_u16().is_err() {
             break;
         }
-        if et == 0x0000 && el >= 5 {
-            // server_name ext; skip list len(2) + name_type(1) + name_len(2)
-            let nl = u16::from_be_bytes([data[j + 3], data[j + 4]]) as usize;
-            if j + 5 + nl <= end {
-                if let Ok(host) = std::str::from_utf8(&data[j + 5..j + 5 + nl]) {
-                    sni = Some(host.to_string());
+    }
+    certs
+}
+
+/// ServerHello.random of a HelloRetryRequest (RFC 8446 4.1.3)
+const HELLO_RETRY_REQUEST: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+/// Record state of one direction
+#[derive(Default)]
+struct Side {
+    /// bytes of an incomplete record
+    records: Vec<u8>,
+    /// bytes of an incomplete handshake message
+    handshake: Vec<u8>,
+    /// records are protected from now on (ChangeCipherSpec, or ServerHello in TLS 1.3)
+    encrypted: bool,
+    /// keys of the direction, when a key log has them
+    cipher: Option<RecordCipher>,
+    /// encrypted without keys or not TLS anymore, nothing left to look at
+    done: bool,
+}
+
+impl Side {
+    /// Next complete record: content type, version and fragment
+    fn next_record(&mut self) -> Option<(u8, u16, Vec<u8>)> {
+        let (content_type, version, len) = match record_header(&self.records) {
+            Some(h) => h,
+            None => {
+                self.done = self.records.len() >= RECORD_HEADER_SIZE;
+                if self.done {
+                    expert::error(DecodeError::Malformed {
+                        layer: "tls",
+                        offset: 0,
+                        reason: "not a TLS record, the rest of the direction is ignored",
+                    });
+                }
+                return None;
+            }
+        };
+        let end = RECORD_HEADER_SIZE + len;
+        let fragment = self.records.get(RECORD_HEADER_SIZE..end)?.to_vec();
+        self.records.drain(..end);
+        Some((content_type, version, fragment))
+    }
+
+    /// Append handshake bytes, returning the complete handshake messages
+    fn handshake_messages(&mut self, fragment: &[u8]) -> Vec<u8> {
+        self.handshake.extend_from_slice(fragment);
+        if self.handshake.len() > MAX_HANDSHAKE_LEN {
+            expert::error(DecodeError::Malformed {
+                layer: "tls",
+                offset: 0,
+                reason:
+                    "handshake message larger than 256 KiB, the rest of the direction is ignored",
+            });
+            self.done = true;
+        }
+        let mut messages = Messages::new(&self.handshake);
+        messages.by_ref().for_each(drop);
+        let complete = self.handshake.len() - messages.remaining().len();
+        let rest = self.handshake.split_off(complete);
+        std::mem::replace(&mut self.handshake, rest)
+    }
+}
+
+/// Follows a TLS handshake over a reassembled TCP connection
+///
+/// Fingerprints the hellos, checks them against the TLS policy and audits the certificate
+/// chain sent by the server. With a key log, records are decrypted and the application
+/// data handed to a stream decoder recognized from the first bytes (HTTP, ...) or else
+/// to the decoder of the port.
+#[derive(Default)]
+pub struct Tracker {
+    client: Side,
+    server: Side,
+    sni: Option<String>,
+    client_random: Option<[u8; 32]>,
+    /// negotiated version and cipher suite
+    version: Option<u16>,
+    cipher_suite: u16,
+    /// decoder of the decrypted stream, picked from its first bytes
+    plaintext: Option<Box<dyn StreamHandler>>,
+    plaintext_checked: bool,
+}
+
+impl Tracker {
+    fn side(&mut self, from_client: bool) -> &mut Side {
+        if from_client {
+            &mut self.client
+        } else {
+            &mut self.server
+        }
+    }
+
+    fn tls13(&self) -> bool {
+        self.version == Some(0x0304)
+    }
+
+    /// Record protection of both directions from the key log
+    fn init_keys(&mut self, server_random: &[u8]) {
+        let client_random = match self.client_random {
+            Some(r) if tls_decrypt::has_keylog() => r,
+            _ => return,
+        };
+        let suite = self.cipher_suite;
+        if self.tls13() {
+            for (from_client, label) in [
+                (true, LABEL::CLIENT_HANDSHAKE_TRAFFIC_SECRET),
+                (false, LABEL::SERVER_HANDSHAKE_TRAFFIC_SECRET),
+            ] {
+                self.side(from_client).cipher = tls_decrypt::secret(label, &client_random)
+                    .and_then(|s| RecordCipher::tls13(suite, &s));
+            }
+        } else if let Some(master) = tls_decrypt::secret(LABEL::CLIENT_RANDOM, &client_random) {
+            for from_client in [true, false] {
+                self.side(from_client).cipher =
+                    RecordCipher::tls12(suite, &master, &client_random, server_random, from_client);
+            }
+        }
+        if self.client.cipher.is_none() && self.server.cipher.is_none() {
+            println!(
+                "TLS no decryption: {} not in key log or unsupported cipher 0x{:04x}",
+                self.sni.as_deref().unwrap_or("session"),
+                suite
+            );
+        }
+    }
+
+    /// Switch a TLS 1.3 direction to its application traffic keys
+    fn application_keys(&mut self, from_client: bool) {
+        let label = if from_client {
+            LABEL::CLIENT_TRAFFIC_SECRET_0
+        } else {
+            LABEL::SERVER_TRAFFIC_SECRET_0
+        };
+        let suite = self.cipher_suite;
+        let cipher = self
+            .client_random
+            .and_then(|r| tls_decrypt::secret(label, &r))
+            .and_then(|s| RecordCipher::tls13(suite, &s));
+        self.side(from_client).cipher = cipher;
+    }
+
+    fn on_message(&mut self, flow: &FlowKey, from_client: bool, msg: Handshake) {
+        match msg.msg_type {
+            HANDSHAKE::CLIENT_HELLO if from_client => match ClientHello::parse(msg.body) {
+                Some(ch) => {
+                    self.sni = ch.server_name().map(str::to_string);
+                    self.client_random = ch.random.try_into().ok();
+                    fingerprint::on_client_hello(&ch, Transport::Tcp);
+                    tls_policy::on_client_hello(flow, &ch);
+                    if let Some(sni) = ch.server_name() {
+                        rules::on_tls_sni(flow, sni);
+                    }
+                }
+                None => expert::error(DecodeError::Malformed {
+                    layer: "tls",
+                    offset: 0,
+                    reason: "invalid ClientHello",
+                }),
+            },
+            HANDSHAKE::SERVER_HELLO if !from_client => match ServerHello::parse(msg.body) {
+                Some(sh) => {
+                    fingerprint::on_server_hello(&sh, Transport::Tcp);
+                    tls_policy::on_server_hello(flow, self.sni.as_deref(), &sh);
+                    if sh.random == HELLO_RETRY_REQUEST {
+                        return;
+                    }
+                    self.version = Some(sh.selected_version());
+                    self.cipher_suite = sh.cipher_suite;
+                    if self.tls13() {
+                        self.client.encrypted = true;
+                        self.server.encrypted = true;
+                    }
+                    self.init_keys(sh.random);
+                }
+                None => expert::error(DecodeError::Malformed {
+                    layer: "tls",
+                    offset: 0,
+                    reason: "invalid ServerHello",
+                }),
+            },
+            // certificates are only looked at on the server side
+            HANDSHAKE::CERTIFICATE if !from_client => {
+                let chain = certificate_list(msg.body, self.tls13());
+                x509::on_certificates(Some(*flow), self.sni.as_deref(), &chain);
+            }
+            HANDSHAKE::FINISHED if self.tls13() => self.application_keys(from_client),
+            HANDSHAKE::KEY_UPDATE => {
+                if let Some(cipher) = self.side(from_client).cipher.as_mut() {
+                    cipher.key_update();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn on_record(
+        &mut self,
+        flow: &FlowKey,
+        from_client: bool,
+        content_type: u8,
+        version: u16,
+        fragment: Vec<u8>,
+    ) {
+        if content_type == CONTENT::CHANGE_CIPHER_SPEC {
+            // only sent for middlebox compatibility in TLS 1.3
+            if !self.tls13() {
+                self.side(from_client).encrypted = true;
+            }
+            return;
+        }
+        let side = self.side(from_client);
+        let encrypted = side.encrypted;
+        let (content_type, data) = match (encrypted, side.cipher.as_mut()) {
+            (false, _) => (content_type, fragment),
+            (true, Some(cipher)) => match cipher.open(content_type, version, &fragment) {
+                Some(opened) => opened,
+                None => {
+                    expert::error(DecodeError::Malformed {
+                        layer: "tls",
+                        offset: 0,
+                        reason: "record fails authentication, wrong secret in key log?",
+                    });
+                    side.done = true;
+                    return;
+                }
+            },
+            (true, None) => {
+                side.done = true;
+                return;
+            }
+        };
+        match content_type {
+            CONTENT::HANDSHAKE => {
+                let messages = self.side(from_client).handshake_messages(&data);
+                for msg in Messages::new(&messages) {
+                    self.on_message(flow, from_client, msg);
+                }
+            }
+            CONTENT::APPLICATION_DATA if encrypted => {
+                let (src, dst) = if from_client {
+                    (flow.src_port, flow.dst_port)
+                } else {
+                    (flow.dst_port, flow.src_port)
+                };
+                println!("TLS decrypted application data {}B", data.len());
+                if !self.plaintext_checked {
+                    self.plaintext_checked = true;
+                    self.plaintext = super::tcp::stream_handler(flow, from_client, &data);
+                }
+                match self.plaintext.as_mut() {
+                    Some(handler) => handler.on_data(flow, from_client, &data),
+                    None => super::tcp::decode_payload(src, dst, &data),
                 }
             }
+            // application data in clear, this is not a handshake we can follow
+            CONTENT::APPLICATION_DATA => self.side(from_client).done = true,
+            _ => {}
         }
-        j += el;
     }
-    if let Some(h) = sni {
-        println!("TLS ClientHello SNI={}", h);
-    } else {
-        println!("TLS ClientHello");
+}
+
+impl StreamHandler for Tracker {
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]) {
+        let side = self.side(from_client);
+        if side.done {
+            return;
+        }
+        side.records.extend_from_slice(data);
+        while let Some((content_type, version, fragment)) = self.side(from_client).next_record() {
+            self.on_record(flow, from_client, content_type, version, fragment);
+            if self.side(from_client).done {
+                break;
+            }
+        }
+        let side = self.side(from_client);
+        if side.done {
+            side.records = Vec::new();
+            side.handshake = Vec::new();
+        }
+    }
+
+    fn on_close(&mut self, flow: &FlowKey) {
+        if let Some(handler) = self.plaintext.as_mut() {
+            handler.on_close(flow);
+        }
+    }
+}
+
+/// Stream handler for a connection starting with a TLS handshake record
+pub fn tracker(data: &[u8]) -> Option<Tracker> {
+    match record_header(data) {
+        Some((CONTENT::HANDSHAKE, _, _)) => Some(Tracker::default()),
+        _ => None,
     }
-    true
 }
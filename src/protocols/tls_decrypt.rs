@@ -0,0 +1,500 @@
+// TLS record decryption with the secrets of an NSS key log (SSLKEYLOGFILE)
+//
+// Only AEAD suites are supported: AES-128/256-GCM and ChaCha20-Poly1305, with the
+// TLS 1.2 master secret (CLIENT_RANDOM lines) or the TLS 1.3 traffic secrets.
+//
+// Sources:
+// * https://firefox-source-docs.mozilla.org/security/nss/legacy/key_log_format/index.html
+// * https://www.rfc-editor.org/rfc/rfc5246#section-6.3 (TLS 1.2 key block)
+// * https://www.rfc-editor.org/rfc/rfc5288 (AES-GCM for TLS 1.2)
+// * https://www.rfc-editor.org/rfc/rfc7905 (ChaCha20-Poly1305 for TLS 1.2)
+// * https://www.rfc-editor.org/rfc/rfc8446#section-7 (TLS 1.3 key schedule)
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::{Sha256, Sha384};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Key log labels used by the decoder
+#[non_exhaustive]
+pub struct LABEL;
+
+impl LABEL {
+    /// TLS 1.2 and below: 48 bytes master secret
+    pub const CLIENT_RANDOM: &'static str = "CLIENT_RANDOM";
+    pub const CLIENT_HANDSHAKE_TRAFFIC_SECRET: &'static str = "CLIENT_HANDSHAKE_TRAFFIC_SECRET";
+    pub const SERVER_HANDSHAKE_TRAFFIC_SECRET: &'static str = "SERVER_HANDSHAKE_TRAFFIC_SECRET";
+    pub const CLIENT_TRAFFIC_SECRET_0: &'static str = "CLIENT_TRAFFIC_SECRET_0";
+    pub const SERVER_TRAFFIC_SECRET_0: &'static str = "SERVER_TRAFFIC_SECRET_0";
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Secrets of an NSS key log file, by label and client random
+#[derive(Default)]
+pub struct KeyLog {
+    secrets: HashMap<(String, [u8; 32]), Vec<u8>>,
+    /// file the log was read from, read again when a secret is missing and it changed
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+}
+
+impl KeyLog {
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut log = Self::parse(&fs::read_to_string(path)?);
+        log.modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        log.path = Some(path.to_path_buf());
+        Ok(log)
+    }
+
+    /// Parse `<label> <client random hex> <secret hex>` lines, ignoring anything else
+    pub fn parse(text: &str) -> Self {
+        let mut log = KeyLog::default();
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            let (label, random, secret) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(l), Some(r), Some(s)) if !l.starts_with('#') => (l, r, s),
+                _ => continue,
+            };
+            let random = from_hex(random).and_then(|r| <[u8; 32]>::try_from(r).ok());
+            if let (Some(random), Some(secret)) = (random, from_hex(secret)) {
+                log.secrets.insert((label.to_string(), random), secret);
+            }
+        }
+        log
+    }
+
+    pub fn len(&self) -> usize {
+        self.secrets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    pub fn get(&self, label: &str, client_random: &[u8; 32]) -> Option<&[u8]> {
+        self.secrets
+            .get(&(label.to_string(), *client_random))
+            .map(Vec::as_slice)
+    }
+
+    /// The file read again if it changed since it was loaded (applications append to it)
+    fn reloaded(&self) -> Option<KeyLog> {
+        let path = self.path.as_ref()?;
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        let mut log = Self::load(path).ok()?;
+        log.modified = modified;
+        Some(log)
+    }
+}
+
+thread_local! {
+    static KEYLOG: RefCell<Option<Arc<KeyLog>>> = const { RefCell::new(None) };
+}
+
+/// Use the secrets of `log` to decrypt the TLS sessions decoded by the current thread;
+/// threads share it until one of them reloads the file
+pub fn set_keylog(log: Arc<KeyLog>) {
+    KEYLOG.with(|k| *k.borrow_mut() = Some(log));
+}
+
+/// Whether a key log was configured
+pub fn has_keylog() -> bool {
+    KEYLOG.with(|k| k.borrow().is_some())
+}
+
+/// Secret of the key log, reloading the file once if it is not there yet
+pub fn secret(label: &str, client_random: &[u8; 32]) -> Option<Vec<u8>> {
+    KEYLOG.with(|k| {
+        let mut k = k.borrow_mut();
+        let log = k.as_mut()?;
+        if let Some(s) = log.get(label, client_random) {
+            return Some(s.to_vec());
+        }
+        *log = Arc::new(log.reloaded()?);
+        log.get(label, client_random).map(<[u8]>::to_vec)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hash {
+    Sha256,
+    Sha384,
+}
+
+impl Hash {
+    /// Digest size
+    pub fn output_len(&self) -> usize {
+        match self {
+            Hash::Sha256 => 32,
+            Hash::Sha384 => 48,
+        }
+    }
+
+    fn hmac(&self, key: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+        fn run<M: Mac + KeyInit>(key: &[u8], parts: &[&[u8]]) -> Vec<u8> {
+            let mut mac = <M as KeyInit>::new_from_slice(key).expect("hmac takes any key size");
+            for p in parts {
+                mac.update(p);
+            }
+            mac.finalize().into_bytes().to_vec()
+        }
+        match self {
+            Hash::Sha256 => run::<Hmac<Sha256>>(key, parts),
+            Hash::Sha384 => run::<Hmac<Sha384>>(key, parts),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Aes128Gcm,
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Algorithm {
+    pub fn key_len(&self) -> usize {
+        match self {
+            Algorithm::Aes128Gcm => 16,
+            Algorithm::Aes256Gcm | Algorithm::ChaCha20Poly1305 => 32,
+        }
+    }
+
+    /// Implicit part of the nonce in TLS 1.2 (GCM adds 8 explicit bytes per record)
+    fn tls12_iv_len(&self) -> usize {
+        match self {
+            Algorithm::Aes128Gcm | Algorithm::Aes256Gcm => 4,
+            Algorithm::ChaCha20Poly1305 => 12,
+        }
+    }
+}
+
+/// AEAD algorithm and hash of the supported cipher suites
+pub fn suite_params(suite: u16) -> Option<(Algorithm, Hash)> {
+    Some(match suite {
+        0x1301 | 0x009c | 0x009e | 0xc02b | 0xc02f => (Algorithm::Aes128Gcm, Hash::Sha256),
+        0x1302 | 0x009d | 0x009f | 0xc02c | 0xc030 => (Algorithm::Aes256Gcm, Hash::Sha384),
+        0x1303 | 0xcca8 | 0xcca9 | 0xccaa => (Algorithm::ChaCha20Poly1305, Hash::Sha256),
+        _ => return None,
+    })
+}
+
+/// TLS 1.2 PRF (P_hash of the suite hash)
+pub fn prf(hash: Hash, secret: &[u8], label: &[u8], seed: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len + hash.output_len());
+    let mut a = hash.hmac(secret, &[label, seed]);
+    while out.len() < len {
+        out.extend_from_slice(&hash.hmac(secret, &[&a, label, seed]));
+        a = hash.hmac(secret, &[&a]);
+    }
+    out.truncate(len);
+    out
+}
+
+/// TLS 1.3 HKDF-Expand-Label with an empty context
+pub fn hkdf_expand_label(hash: Hash, secret: &[u8], label: &str, len: usize) -> Option<Vec<u8>> {
+    let full = format!("tls13 {}", label);
+    let mut info = Vec::with_capacity(4 + full.len());
+    info.extend_from_slice(&(len as u16).to_be_bytes());
+    info.push(full.len() as u8);
+    info.extend_from_slice(full.as_bytes());
+    info.push(0);
+    let mut out = vec![0u8; len];
+    match hash {
+        Hash::Sha256 => Hkdf::<Sha256>::from_prk(secret)
+            .ok()?
+            .expand(&info, &mut out)
+            .ok()?,
+        Hash::Sha384 => Hkdf::<Sha384>::from_prk(secret)
+            .ok()?
+            .expand(&info, &mut out)
+            .ok()?,
+    }
+    Some(out)
+}
+
+enum Key {
+    Aes128Gcm(Box<Aes128Gcm>),
+    Aes256Gcm(Box<Aes256Gcm>),
+    ChaCha20Poly1305(Box<ChaCha20Poly1305>),
+}
+
+impl Key {
+    fn new(algorithm: Algorithm, key: &[u8]) -> Option<Self> {
+        Some(match algorithm {
+            Algorithm::Aes128Gcm => Key::Aes128Gcm(Box::new(Aes128Gcm::new_from_slice(key).ok()?)),
+            Algorithm::Aes256Gcm => Key::Aes256Gcm(Box::new(Aes256Gcm::new_from_slice(key).ok()?)),
+            Algorithm::ChaCha20Poly1305 => {
+                Key::ChaCha20Poly1305(Box::new(ChaCha20Poly1305::new_from_slice(key).ok()?))
+            }
+        })
+    }
+
+    fn open(&self, nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        match self {
+            Key::Aes128Gcm(k) => k.decrypt(nonce.into(), payload).ok(),
+            Key::Aes256Gcm(k) => k.decrypt(nonce.into(), payload).ok(),
+            Key::ChaCha20Poly1305(k) => k.decrypt(nonce.into(), payload).ok(),
+        }
+    }
+}
+
+/// AEAD tag size of every supported algorithm
+const TAG_LEN: usize = 16;
+
+/// Protection of the records sent in one direction
+pub struct RecordCipher {
+    algorithm: Algorithm,
+    hash: Hash,
+    key: Key,
+    iv: Vec<u8>,
+    seq: u64,
+    /// TLS 1.3 traffic secret, kept for key updates
+    secret: Option<Vec<u8>>,
+}
+
+impl RecordCipher {
+    /// TLS 1.2 keys of the client (or server) side from the master secret
+    pub fn tls12(
+        suite: u16,
+        master_secret: &[u8],
+        client_random: &[u8],
+        server_random: &[u8],
+        client_side: bool,
+    ) -> Option<Self> {
+        let (algorithm, hash) = suite_params(suite)?;
+        if (0x1301..=0x1305).contains(&suite) {
+            return None;
+        }
+        let (key_len, iv_len) = (algorithm.key_len(), algorithm.tls12_iv_len());
+        let seed = [server_random, client_random].concat();
+        let block = prf(
+            hash,
+            master_secret,
+            b"key expansion",
+            &seed,
+            2 * (key_len + iv_len),
+        );
+        let (keys, ivs) = block.split_at(2 * key_len);
+        let (key, iv) = if client_side {
+            (&keys[..key_len], &ivs[..iv_len])
+        } else {
+            (&keys[key_len..], &ivs[iv_len..])
+        };
+        Some(RecordCipher {
+            algorithm,
+            hash,
+            key: Key::new(algorithm, key)?,
+            iv: iv.to_vec(),
+            seq: 0,
+            secret: None,
+        })
+    }
+
+    /// TLS 1.3 keys from a traffic secret
+    pub fn tls13(suite: u16, secret: &[u8]) -> Option<Self> {
+        let (algorithm, hash) = suite_params(suite)?;
+        let key = hkdf_expand_label(hash, secret, "key", algorithm.key_len())?;
+        let iv = hkdf_expand_label(hash, secret, "iv", 12)?;
+        Some(RecordCipher {
+            algorithm,
+            hash,
+            key: Key::new(algorithm, &key)?,
+            iv,
+            seq: 0,
+            secret: Some(secret.to_vec()),
+        })
+    }
+
+    /// Move to the next TLS 1.3 traffic secret after a KeyUpdate message
+    pub fn key_update(&mut self) -> Option<()> {
+        let secret = self.secret.as_ref()?;
+        let suite = match self.algorithm {
+            Algorithm::Aes128Gcm => 0x1301,
+            Algorithm::Aes256Gcm => 0x1302,
+            Algorithm::ChaCha20Poly1305 => 0x1303,
+        };
+        let next = hkdf_expand_label(self.hash, secret, "traffic upd", self.hash.output_len())?;
+        *self = Self::tls13(suite, &next)?;
+        Some(())
+    }
+
+    /// Nonce of the current record: the iv xored with the sequence number
+    fn nonce(&self) -> [u8; 12] {
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&self.iv);
+        for (n, s) in nonce[4..].iter_mut().zip(self.seq.to_be_bytes()) {
+            *n ^= s;
+        }
+        nonce
+    }
+
+    /// Decrypt a record, returning its real content type and the plaintext
+    pub fn open(
+        &mut self,
+        content_type: u8,
+        version: u16,
+        fragment: &[u8],
+    ) -> Option<(u8, Vec<u8>)> {
+        let opened = if self.secret.is_some() {
+            // TLS 1.3: the record header is the additional data, the type is inside
+            let mut aad = vec![content_type];
+            aad.extend_from_slice(&version.to_be_bytes());
+            aad.extend_from_slice(&(fragment.len() as u16).to_be_bytes());
+            let mut plain = self.key.open(&self.nonce(), &aad, fragment)?;
+            let end = plain.iter().rposition(|b| *b != 0)?;
+            let inner_type = plain[end];
+            plain.truncate(end);
+            (inner_type, plain)
+        } else {
+            let (nonce, ciphertext) = match self.algorithm {
+                Algorithm::ChaCha20Poly1305 => (self.nonce(), fragment),
+                _ => {
+                    let explicit = fragment.get(..8)?;
+                    let mut nonce = [0u8; 12];
+                    nonce[..4].copy_from_slice(&self.iv);
+                    nonce[4..].copy_from_slice(explicit);
+                    (nonce, &fragment[8..])
+                }
+            };
+            let plain_len = ciphertext.len().checked_sub(TAG_LEN)?;
+            let mut aad = self.seq.to_be_bytes().to_vec();
+            aad.push(content_type);
+            aad.extend_from_slice(&version.to_be_bytes());
+            aad.extend_from_slice(&(plain_len as u16).to_be_bytes());
+            (content_type, self.key.open(&nonce, &aad, ciphertext)?)
+        };
+        self.seq += 1;
+        Some(opened)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        from_hex(&s.split_whitespace().collect::<String>()).unwrap()
+    }
+
+    // RFC 8448 3, traffic keys of the simple 1-RTT handshake
+    #[test]
+    fn hkdf_expand_label_rfc8448() {
+        let cases = [
+            (
+                // client handshake traffic secret
+                "b3eddb126e067f35a780b3abf45e2d8f3b1a950738f52e9600746a0e27a55a21",
+                "dbfaa693d1762c5b666af5d950258d01",
+                "5bd3c71b836e0b76bb73265f",
+            ),
+            (
+                // server handshake traffic secret
+                "b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38",
+                "3fce516009c21727d0f2e4e86ee403bc",
+                "5d313eb2671276ee13000b30",
+            ),
+            (
+                // server application traffic secret
+                "a11af9f05531f856ad47116b45a950328204b4f44bfb6b3a4b4f1f3fcb631643",
+                "9f02283b6c9c07efc26bb9f2ac92e356",
+                "cf782b88dd83549aadf1e984",
+            ),
+        ];
+        for (secret, key, iv) in cases {
+            let secret = hex(secret);
+            assert_eq!(
+                hkdf_expand_label(Hash::Sha256, &secret, "key", 16),
+                Some(hex(key))
+            );
+            assert_eq!(
+                hkdf_expand_label(Hash::Sha256, &secret, "iv", 12),
+                Some(hex(iv))
+            );
+        }
+        assert_eq!(
+            hkdf_expand_label(
+                Hash::Sha256,
+                &hex("b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38"),
+                "finished",
+                32
+            ),
+            Some(hex(
+                "008d3b66f816ea559f96b537e885c31fc068bf492c652f01f288a1d8cdc19fc8"
+            ))
+        );
+    }
+
+    // P_SHA256 and P_SHA384 test vectors of the TLS working group
+    #[test]
+    fn prf_tls12() {
+        assert_eq!(
+            prf(
+                Hash::Sha256,
+                &hex("9bbe436ba940f017b17652849a71db35"),
+                b"test label",
+                &hex("a0ba9f936cda311827a6f796ffd5198c"),
+                100
+            ),
+            hex(
+                "e3f229ba727be17b8d122620557cd453c2aab21d07c3d495329b52d4e61edb5a
+                 6b301791e90d35c9c9a46b4e14baf9af0fa022f7077def17abfd3797c0564bab
+                 4fbc91666e9def9b97fce34f796789baa48082d122ee42c5a72e5a5110fff701
+                 87347b66"
+            )
+        );
+        assert_eq!(
+            prf(
+                Hash::Sha384,
+                &hex("b80b733d6ceefcdc71566ea48e5567df"),
+                b"test label",
+                &hex("cd665cf6a8447dd6ff8b27555edb7465"),
+                148
+            ),
+            hex(
+                "7b0c18e9ced410ed1804f2cfa34a336a1c14dffb4900bb5fd7942107e81c83cd
+                 e9ca0faa60be9fe34f82b1233c9146a0e534cb400fed2700884f9dc236f80edd
+                 8bfa961144c9e8d792eca722a7b32fc3d416d473ebc2c5fd4abfdad05d918425
+                 9b5bf8cd4d90fa0d31e2dec479e4f1a26066f2eea9a69236a3e52655c9e9aee6
+                 91c8f3a26854308d5eaa3be85e0990703d73e56f"
+            )
+        );
+    }
+
+    #[test]
+    fn keylog_lines() {
+        let random = "00".repeat(32);
+        let log = KeyLog::parse(&format!(
+            "# comment\nCLIENT_RANDOM {} {}\nbroken line\nCLIENT_RANDOM zz 00\n",
+            random,
+            "ab".repeat(48)
+        ));
+        assert_eq!(log.len(), 1);
+        assert_eq!(log.get("CLIENT_RANDOM", &[0; 32]), Some(&[0xab; 48][..]));
+        assert_eq!(log.get("CLIENT_TRAFFIC_SECRET_0", &[0; 32]), None);
+    }
+}
@@ -1,6 +1,10 @@
 // Minimal TURN decoder using STUN format
+use crate::tree;
+
 pub fn decode(data: &[u8]) {
     if !super::stun::decode(data) {
-        println!("TURN (non-STUN payload) {}B", data.len());
+        let line = format!("TURN (non-STUN payload) {}B", data.len());
+        println!("{}", line);
+        tree::layer("turn", data, line);
     }
 }
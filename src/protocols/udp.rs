@@ -1,18 +1,24 @@
-use crate::utils::cow_struct;
+use crate::analysis::rules;
+use crate::context;
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be};
 use std::mem::size_of;
 
 use super::dns;
+use super::ip::IpProtocol;
 
-/// UDP header
-///
-/// User Datagram Protocol is a connection-less transport protocol.
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub src_port: u16,
-    pub dest_port: u16,
-    pub len: u16,
-    pub checksum: u16,
+wire_struct! {
+    /// UDP header
+    ///
+    /// User Datagram Protocol is a connection-less transport protocol.
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        pub src_port: U16Be,
+        pub dest_port: U16Be,
+        pub len: U16Be,
+        pub checksum: U16Be,
+    }
 }
 
 impl Header {
@@ -20,15 +26,12 @@ impl Header {
 }
 
 pub fn display(h: &Header) -> String {
-    let src = u16::from_be(h.src_port);
-    let dst = u16::from_be(h.dest_port);
-    let len = u16::from_be(h.len);
     format!(
         "[UDP] src={} dst={} len={} checksum=0x{:04x}",
-        src,
-        dst,
-        len,
-        u16::from_be(h.checksum)
+        h.src_port,
+        h.dest_port,
+        h.len,
+        h.checksum.get()
     )
 }
 
@@ -36,13 +39,28 @@ pub fn decode(data: &[u8]) {
     if data.len() < Header::SIZE {
         return;
     }
-    let (hdr_bytes, payload) = data.split_at(Header::SIZE);
-    match cow_struct::<Header>(hdr_bytes) {
-        Some(h) => {
-            let src = u16::from_be(h.src_port);
-            let dst = u16::from_be(h.dest_port);
+    match utils::split::<Header>(data) {
+        Some((h, payload)) => {
+            let src = h.src_port.get();
+            let dst = h.dest_port.get();
+            tree::layer("udp", &data[..Header::SIZE], display(h));
+            tree::field("Source port", &h.src_port, src);
+            tree::field("Destination port", &h.dest_port, dst);
+            tree::field("Length", &h.len, h.len);
+            tree::field(
+                "Checksum",
+                &h.checksum,
+                format_args!("0x{:04x}", h.checksum.get()),
+            );
+            context::set_ports(IpProtocol::Udp, src, dst);
+            rules::on_packet(IpProtocol::Udp, payload, None);
             // protocol detection by port
-            if src == 53 || dst == 53 {
+            if context::flow().is_some_and(|f| super::tftp::is_transfer(&f)) {
+                // TFTP transfers move to ports picked by the server
+                super::tftp::decode(payload);
+            } else if super::detect::dispatch(IpProtocol::Udp, payload) {
+                // the content of the flow overruled its ports
+            } else if src == 53 || dst == 53 {
                 dns::decode(payload);
             } else if src == 5353 || dst == 5353 {
                 super::mdns::decode(payload);
@@ -56,8 +74,10 @@ pub fn decode(data: &[u8]) {
                 super::syslog::decode(payload);
             } else if src == 69 || dst == 69 {
                 super::tftp::decode(payload);
-            } else if src == 137 || dst == 137 || src == 138 || dst == 138 {
+            } else if src == 137 || dst == 137 {
                 super::netbios::decode(payload);
+            } else if src == 138 || dst == 138 {
+                super::netbios::decode_datagram(payload);
             } else if src == 546 || dst == 546 || src == 547 || dst == 547 {
                 super::dhcpv6::decode(payload);
             } else if src == 520 || dst == 520 {
@@ -81,9 +101,12 @@ pub fn decode(data: &[u8]) {
             } else if src == 3478 || dst == 3478 || src == 5349 || dst == 5349 {
                 super::stun::decode(payload);
             } else {
-                println!("{}", display(&h));
+                println!("{}", display(h));
             }
         }
-        None => println!("udp decode error: {:?}", "Truncated payload"),
+        None => expert::error(DecodeError::Truncated {
+            layer: "udp",
+            offset: 0,
+        }),
     }
 }
@@ -1,7 +1,11 @@
 // Minimal USB Linux decoder
 // We don't parse full usbmon header; just show direction if recognizable.
+use crate::tree;
+
 pub fn decode(data: &[u8]) {
     // usbmon v1/v2 headers vary; just print size and first bytes
     let n = core::cmp::min(16, data.len());
-    println!("USB ({}B) head={:02x?}", data.len(), &data[..n]);
+    let line = format!("USB ({}B) head={:02x?}", data.len(), &data[..n]);
+    println!("{}", line);
+    tree::layer("usb", data, line);
 }
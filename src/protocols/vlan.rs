@@ -1,27 +1,37 @@
-use crate::utils::cow_struct;
+use crate::tree;
+use crate::utils::{self, wire_struct, U16Be};
 use std::mem::size_of;
 
-use super::ethernet;
+use super::ethernet::EtherType;
 
-#[derive(Default, Debug, Clone, Copy)]
-#[repr(C, packed)]
-pub struct Header {
-    pub tci: u16,        // PCP(3) | DEI(1) | VID(12)
-    pub ether_type: u16, // inner ether type
+wire_struct! {
+    #[derive(Default, Debug, Clone, Copy)]
+    pub struct Header {
+        pub tci: U16Be,        // PCP(3) | DEI(1) | VID(12)
+        pub ether_type: U16Be, // inner ether type
+    }
 }
 
 impl Header {
     pub const SIZE: usize = size_of::<Self>();
+
+    /// VLAN identifier
+    pub fn vid(&self) -> u16 {
+        self.tci.get() & 0x0FFF
+    }
+
+    /// priority code point
+    pub fn pcp(&self) -> u8 {
+        (self.tci.get() >> 13) as u8
+    }
 }
 
 pub fn display(h: &Header) -> String {
-    let vid = h.tci.to_be() & 0x0FFF;
-    let pcp = (h.tci.to_be() & 0xE000) >> 13;
     format!(
         "802.1Q VLAN vid={} pcp={} inner={}",
-        vid,
-        pcp,
-        ethernet::ether_type_as_str(h.ether_type.to_be())
+        h.vid(),
+        h.pcp(),
+        EtherType::from(h.ether_type.get())
     )
 }
 
@@ -29,17 +39,23 @@ pub fn decode(data: &[u8]) {
     if data.len() < Header::SIZE {
         return;
     }
-    let (hdr, payload) = data.split_at(Header::SIZE);
-    if let Some(h) = cow_struct::<Header>(hdr) {
-        println!("{}", display(&h));
-        match h.ether_type.to_be() {
-            ethernet::PROTO::IPV4 => super::ipv4::decode(payload),
-            ethernet::PROTO::IPV6 => super::ipv6::decode(payload),
-            ethernet::PROTO::ARP => super::arp::decode(payload),
-            _ => println!(
-                "vlan inner eth_type={}",
-                ethernet::ether_type_as_str(h.ether_type.to_be())
-            ),
+    if let Some((h, payload)) = utils::split::<Header>(data) {
+        println!("{}", display(h));
+        tree::layer("vlan", &data[..Header::SIZE], display(h));
+        tree::field("Priority", &h.tci, h.pcp());
+        tree::field("Drop eligible", &h.tci, h.tci.get() >> 12 & 1);
+        tree::field("VLAN ID", &h.tci, h.vid());
+        let t = h.ether_type.get();
+        tree::field(
+            "Type",
+            &h.ether_type,
+            format_args!("{} (0x{:04x})", EtherType::from(t), t),
+        );
+        match EtherType::from(h.ether_type.get()) {
+            EtherType::Ipv4 => super::ipv4::decode(payload),
+            EtherType::Ipv6 => super::ipv6::decode(payload),
+            EtherType::Arp => super::arp::decode(payload),
+            t => println!("vlan inner eth_type={}", t),
         }
     }
 }
@@ -1,5 +1,25 @@
 // Minimal WebSocket detector/decoder
 // Detect HTTP Upgrade handshake or simple data frames.
+use crate::expert::{self, DecodeError};
+use crate::tree;
+use crate::utils::ByteReader;
+
+/// Why a frame header breaks RFC 6455 5.2 and 5.5, if it does
+fn invalid_header(b0: u8, b1: u8) -> Option<&'static str> {
+    let opcode = b0 & 0x0F;
+    let control = opcode >= 0x8;
+    if !matches!(opcode, 0x0..=0x2 | 0x8..=0xA) {
+        Some("reserved opcode")
+    } else if b0 & 0x30 != 0 {
+        Some("RSV2 or RSV3 set without an extension")
+    } else if control && b0 & 0x80 == 0 {
+        Some("fragmented control frame")
+    } else if control && b1 & 0x7F > 125 {
+        Some("control frame longer than 125 bytes")
+    } else {
+        None
+    }
+}
 
 pub fn decode(data: &[u8]) -> bool {
     // Try HTTP handshake detection
@@ -13,6 +33,14 @@ pub fn decode(data: &[u8]) -> bool {
                 .map(|l| l.trim())
                 .unwrap_or("");
             println!("WebSocket handshake {}", key);
+            tree::layer(
+                "websocket",
+                data,
+                format_args!("WebSocket handshake {}", key),
+            );
+            if !key.is_empty() {
+                tree::field("Sec-WebSocket-Key", key.as_bytes(), key);
+            }
             return true;
         }
     }
@@ -23,11 +51,79 @@ pub fn decode(data: &[u8]) -> bool {
         let fin = (b0 & 0x80) != 0;
         let opcode = b0 & 0x0F;
         let masked = (b1 & 0x80) != 0;
-        println!(
+        // this keeps HTTP text out
+        if invalid_header(b0, b1).is_some() {
+            return false;
+        }
+        let line = format!(
             "WebSocket frame fin={} opcode=0x{:x} masked={}",
             fin, opcode, masked
         );
+        println!("{}", line);
+        tree::layer("websocket", data, line);
+        tree::field("Fin", &data[..1], fin);
+        tree::field("Opcode", &data[..1], format_args!("0x{:x}", opcode));
+        tree::field("Mask", &data[1..2], masked);
+        tree::field("Payload length", &data[1..2], b1 & 0x7F);
         return true;
     }
     false
 }
+
+/// Decode the complete frames at the start of a reassembled stream, returning the number
+/// of bytes used
+pub fn frames(data: &[u8]) -> usize {
+    let mut used = 0;
+    loop {
+        let mut r = ByteReader::new(&data[used..]);
+        let (b0, b1) = match (r.u8(), r.u8()) {
+            (Ok(b0), Ok(b1)) => (b0, b1),
+            _ => break,
+        };
+        // the frames cannot be found again after a broken header: the rest is dropped
+        if let Some(reason) = invalid_header(b0, b1) {
+            expert::error(DecodeError::Malformed {
+                layer: "websocket",
+                offset: used,
+                reason,
+            });
+            return data.len();
+        }
+        let len = match b1 & 0x7F {
+            126 => r.u16().map(u64::from),
+            127 => r.u64(),
+            n => Ok(n.into()),
+        };
+        let mask = if b1 & 0x80 != 0 {
+            r.array::<4>().map(Some)
+        } else {
+            Ok(None)
+        };
+        let payload = len.and_then(|len| r.bytes(usize::try_from(len).unwrap_or(usize::MAX)));
+        let (len, mask, payload) = match (len, mask, payload) {
+            (Ok(len), Ok(mask), Ok(payload)) => (len, mask, payload),
+            _ => break,
+        };
+        let opcode = b0 & 0x0F;
+        let mut text = String::new();
+        if opcode == 0x1 {
+            let unmasked: Vec<u8> = payload
+                .iter()
+                .enumerate()
+                .map(|(i, b)| mask.map_or(*b, |m| b ^ m[i % 4]))
+                .take(128)
+                .collect();
+            text = format!(" \"{}\"", String::from_utf8_lossy(&unmasked));
+        }
+        println!(
+            "WebSocket frame fin={} opcode=0x{:x} masked={} len={}{}",
+            b0 & 0x80 != 0,
+            opcode,
+            mask.is_some(),
+            len,
+            text
+        );
+        used += r.position();
+    }
+    used
+}
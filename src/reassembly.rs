@@ -0,0 +1,317 @@
+//! TCP stream reassembly
+//!
+//! Segments are put back in sequence order for each direction of a connection, so that
+//! stream decoders see the bytes the application wrote whatever the segmentation,
+//! retransmissions or reordering on the wire. In order segments are handed over without
+//! copy, only out of order data is buffered until the gap is filled.
+//!
+//! Each connection can own a `StreamHandler`, picked by the caller when the first bytes
+//! of the connection show up, which receives the reassembled data of both directions.
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::analysis::rules;
+use crate::flow::{Direction, FlowKey};
+
+/// TCP control flags
+#[non_exhaustive]
+pub struct FLAGS;
+
+impl FLAGS {
+    pub const FIN: u8 = 0x01;
+    pub const SYN: u8 = 0x02;
+    pub const RST: u8 = 0x04;
+    pub const PSH: u8 = 0x08;
+    pub const ACK: u8 = 0x10;
+    pub const URG: u8 = 0x20;
+    pub const ECE: u8 = 0x40;
+    pub const CWR: u8 = 0x80;
+}
+
+/// Consumer of a reassembled connection
+pub trait StreamHandler {
+    /// Bytes newly available on the stream sent by the client (or by the server)
+    ///
+    /// `flow` is always oriented from the client to the server.
+    fn on_data(&mut self, flow: &FlowKey, from_client: bool, data: &[u8]);
+
+    /// Connection closed (FIN from both sides, RST) or evicted from the table
+    fn on_close(&mut self, _flow: &FlowKey) {}
+}
+
+/// Out of order bytes kept per direction before giving up on a gap
+const MAX_BUFFERED: usize = 1 << 20;
+/// Connections tracked per thread
+const MAX_CONNECTIONS: usize = 65536;
+
+/// One direction of a connection
+#[derive(Default)]
+pub struct Stream {
+    /// sequence number of the next expected byte
+    next_seq: Option<u32>,
+    /// stream offset of the next expected byte
+    next_off: u64,
+    /// out of order segments by stream offset
+    pending: BTreeMap<u64, Vec<u8>>,
+    buffered: usize,
+    /// bytes skipped because a gap never got filled
+    pub missing: u64,
+}
+
+impl Stream {
+    /// Start the stream at the sequence number following a SYN
+    pub fn syn(&mut self, seq: u32) {
+        if self.next_seq.is_none() {
+            self.next_seq = Some(seq.wrapping_add(1));
+        }
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.next_off += n as u64;
+        self.next_seq = self.next_seq.map(|s| s.wrapping_add(n as u32));
+    }
+
+    /// Add a segment, returning the bytes that became contiguous
+    pub fn push<'a>(&mut self, seq: u32, payload: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        if payload.is_empty() {
+            return None;
+        }
+        let next_seq = *self.next_seq.get_or_insert(seq);
+        let off = self.next_off as i64 + seq.wrapping_sub(next_seq) as i32 as i64;
+        let end = off + payload.len() as i64;
+        if end <= self.next_off as i64 {
+            // retransmission of delivered data
+            return None;
+        }
+        if off > self.next_off as i64 {
+            if self.buffered + payload.len() <= MAX_BUFFERED {
+                let slot = self.pending.entry(off as u64).or_default();
+                if slot.len() < payload.len() {
+                    self.buffered += payload.len() - slot.len();
+                    *slot = payload.to_vec();
+                }
+                return None;
+            }
+            // the gap is not going to be filled, resume at the first buffered byte
+            let slot = self.pending.entry(off as u64).or_default();
+            if slot.len() < payload.len() {
+                self.buffered += payload.len() - slot.len();
+                *slot = payload.to_vec();
+            }
+            let resume = self.pending.keys().next().copied().unwrap_or(off as u64);
+            self.missing += resume - self.next_off;
+            self.advance((resume - self.next_off) as usize);
+            let mut out = Vec::new();
+            self.drain_pending(&mut out);
+            return Some(Cow::Owned(out));
+        }
+        let skip = (self.next_off as i64 - off) as usize;
+        let fresh = &payload[skip..];
+        self.advance(fresh.len());
+        if self.pending.is_empty() {
+            return Some(Cow::Borrowed(fresh));
+        }
+        let mut out = fresh.to_vec();
+        self.drain_pending(&mut out);
+        Some(Cow::Owned(out))
+    }
+
+    /// Move the buffered segments that are now contiguous to `out`
+    fn drain_pending(&mut self, out: &mut Vec<u8>) {
+        while let Some(entry) = self.pending.first_entry() {
+            let off = *entry.key();
+            if off > self.next_off {
+                break;
+            }
+            let data = entry.remove();
+            self.buffered -= data.len();
+            let skip = (self.next_off - off) as usize;
+            if skip < data.len() {
+                out.extend_from_slice(&data[skip..]);
+                self.advance(data.len() - skip);
+            }
+        }
+    }
+}
+
+struct Connection {
+    /// canonical direction of the packets sent by the client
+    client: Direction,
+    streams: [Stream; 2],
+    fin: [bool; 2],
+    handler: Option<Box<dyn StreamHandler>>,
+    selected: bool,
+    last_seen: u64,
+}
+
+fn index(d: Direction) -> usize {
+    match d {
+        Direction::Forward => 0,
+        Direction::Reverse => 1,
+    }
+}
+
+#[derive(Default)]
+struct Table {
+    connections: HashMap<FlowKey, Connection>,
+    clock: u64,
+}
+
+impl Table {
+    /// Drop the least recently seen tenth of the connections
+    fn evict(&mut self) {
+        let mut ages: Vec<(u64, FlowKey)> = self
+            .connections
+            .iter()
+            .map(|(k, c)| (c.last_seen, *k))
+            .collect();
+        ages.sort_unstable_by_key(|(age, _)| *age);
+        for (_, key) in ages.into_iter().take(MAX_CONNECTIONS / 10) {
+            if let Some(mut c) = self.connections.remove(&key) {
+                if let Some(h) = c.handler.as_mut() {
+                    let flow = client_flow(&key, c.client);
+                    h.on_close(&flow);
+                }
+            }
+        }
+    }
+}
+
+thread_local! {
+    static TABLE: RefCell<Table> = RefCell::new(Table::default());
+}
+
+fn client_flow(canonical: &FlowKey, client: Direction) -> FlowKey {
+    match client {
+        Direction::Forward => *canonical,
+        Direction::Reverse => canonical.reversed(),
+    }
+}
+
+/// Feed a TCP segment of `flow` (oriented as seen on the wire)
+///
+/// `select` is called once per connection with the first reassembled bytes to pick the
+/// handler of the connection; it gets the flow oriented from the client to the server.
+pub fn push<F>(flow: &FlowKey, seq: u32, flags: u8, payload: &[u8], select: F)
+where
+    F: FnOnce(&FlowKey, bool, &[u8]) -> Option<Box<dyn StreamHandler>>,
+{
+    let (key, dir) = flow.canonical();
+    let is_syn = flags & FLAGS::SYN != 0;
+    let (data, mut handler, client, closed) = TABLE.with(|t| {
+        let mut t = t.borrow_mut();
+        t.clock += 1;
+        let clock = t.clock;
+        if !t.connections.contains_key(&key) {
+            if flags & FLAGS::RST != 0 {
+                return (None, None, dir, false);
+            }
+            if t.connections.len() >= MAX_CONNECTIONS {
+                t.evict();
+            }
+            // the SYN sender is the client, otherwise guess the client uses the higher port
+            let client = if is_syn {
+                if flags & FLAGS::ACK == 0 {
+                    dir
+                } else {
+                    opposite(dir)
+                }
+            } else if flow.src_port >= flow.dst_port {
+                dir
+            } else {
+                opposite(dir)
+            };
+            t.connections.insert(
+                key,
+                Connection {
+                    client,
+                    streams: [Stream::default(), Stream::default()],
+                    fin: [false; 2],
+                    handler: None,
+                    selected: false,
+                    last_seen: clock,
+                },
+            );
+        }
+        let c = t.connections.get_mut(&key).expect("inserted above");
+        c.last_seen = clock;
+        let stream = &mut c.streams[index(dir)];
+        if is_syn {
+            stream.syn(seq);
+        }
+        let seq = if is_syn { seq.wrapping_add(1) } else { seq };
+        // nobody is interested in the content of the connection
+        let ignored = c.selected && c.handler.is_none() && !rules::enabled();
+        let data = if ignored {
+            None
+        } else {
+            stream.push(seq, payload)
+        };
+        if flags & FLAGS::FIN != 0 {
+            c.fin[index(dir)] = true;
+        }
+        let closed = flags & FLAGS::RST != 0 || (c.fin[0] && c.fin[1]);
+        // the handler runs outside of the table borrow, it may feed nested streams
+        let handler = c.handler.take();
+        let client = c.client;
+        if closed {
+            t.connections.remove(&key);
+        }
+        (data, handler, client, closed)
+    });
+
+    let cflow = client_flow(&key, client);
+    let from_client = dir == client;
+    if let Some(data) = data.as_deref() {
+        if handler.is_none() && mark_selected(&key) {
+            handler = select(&cflow, from_client, data);
+        }
+        if let Some(h) = handler.as_mut() {
+            h.on_data(&cflow, from_client, data);
+        }
+        rules::on_stream(&cflow, from_client, data);
+    }
+    if closed {
+        if let Some(h) = handler.as_mut() {
+            h.on_close(&cflow);
+        }
+        return;
+    }
+    TABLE.with(|t| {
+        if let Some(c) = t.borrow_mut().connections.get_mut(&key) {
+            c.handler = handler;
+        }
+    });
+}
+
+fn opposite(d: Direction) -> Direction {
+    match d {
+        Direction::Forward => Direction::Reverse,
+        Direction::Reverse => Direction::Forward,
+    }
+}
+
+/// Whether the handler of the connection still has to be selected, marking it as done
+fn mark_selected(key: &FlowKey) -> bool {
+    TABLE.with(|t| match t.borrow_mut().connections.get_mut(key) {
+        Some(c) if !c.selected => {
+            c.selected = true;
+            true
+        }
+        // closed by this very segment
+        None => true,
+        _ => false,
+    })
+}
+
+/// Close every tracked connection, typically at the end of a capture
+pub fn flush() {
+    let connections: Vec<(FlowKey, Connection)> =
+        TABLE.with(|t| t.borrow_mut().connections.drain().collect());
+    for (key, mut c) in connections {
+        if let Some(h) = c.handler.as_mut() {
+            h.on_close(&client_flow(&key, c.client));
+        }
+    }
+}
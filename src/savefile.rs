@@ -0,0 +1,368 @@
+//! Pcap savefiles rotated by size, time and count
+//!
+//! Mirrors tcpdump's `-C`, `-G` and `-W`: a new file is started once the current one
+//! reaches a size or has covered a number of seconds (of packet time), and only the last
+//! N files are kept. File names are strftime templates expanded at the time of their
+//! first packet, in UTC; a file number is appended when names could repeat. Closed files
+//! can be gzipped in the background so that the capture loop never waits on them.
+use flate2::write::GzEncoder;
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use crate::timestamp::{self, Precision, Timestamp};
+
+/// Bytes of the global header of a pcap file
+const GLOBAL_HEADER_SIZE: u64 = 24;
+/// Bytes of the header of a packet record
+const RECORD_HEADER_SIZE: u64 = 16;
+/// Compressions running at once; rotating beyond waits for the oldest
+const MAX_COMPRESSING: usize = 4;
+
+/// Compression applied to a file once it is closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+}
+
+impl Compression {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "gzip" | "gz" => Some(Compression::Gzip),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+        }
+    }
+}
+
+/// When to start a new file and how many to keep
+#[derive(Debug, Clone, Default)]
+pub struct Rotation {
+    /// start a new file once the current one reaches this size (`-C`)
+    pub max_bytes: Option<u64>,
+    /// start a new file once the current one covers this many seconds (`-G`)
+    pub max_seconds: Option<u64>,
+    /// keep only the last files, as a ring (`-W`)
+    pub max_files: Option<usize>,
+    pub compression: Option<Compression>,
+}
+
+/// Parse a tcpdump `-C` size: millions of bytes, or a number with a `k`, `m` or `g` suffix
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (number, unit) = match s.char_indices().last()? {
+        (i, 'k' | 'K') => (&s[..i], 1_000),
+        (i, 'm' | 'M') => (&s[..i], 1_000_000),
+        (i, 'g' | 'G') => (&s[..i], 1_000_000_000),
+        _ => (s, 1_000_000),
+    };
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// Expand the strftime conversions of a template for a time in seconds since the epoch:
+/// `%Y %y %m %d %H %M %S %j %s %F %T %%`, others are kept as they are
+pub fn strftime(template: &str, secs: i64) -> String {
+    // YYYY-MM-DDTHH:MM:SSZ
+    let t = timestamp::format_time(secs);
+    let year = &t[..t.len() - 16];
+    let (month, day) = (
+        &t[t.len() - 15..t.len() - 13],
+        &t[t.len() - 12..t.len() - 10],
+    );
+    let (hour, minute, second) = (
+        &t[t.len() - 9..t.len() - 7],
+        &t[t.len() - 6..t.len() - 4],
+        &t[t.len() - 3..t.len() - 1],
+    );
+    let day_of_year = {
+        const CUMULATIVE: [u16; 12] = [0, 31, 59, 90, 120, 151, 181, 212, 243, 273, 304, 334];
+        let y: i64 = year.parse().unwrap_or(1970);
+        let m: usize = month.parse().unwrap_or(1);
+        let d: u16 = day.parse().unwrap_or(1);
+        let leap = (y % 4 == 0 && y % 100 != 0) || y % 400 == 0;
+        CUMULATIVE[m.clamp(1, 12) - 1] + d + u16::from(leap && m > 2)
+    };
+    let mut out = String::with_capacity(template.len() + 16);
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => out.push_str(year),
+            Some('y') => out.push_str(&year[year.len().saturating_sub(2)..]),
+            Some('m') => out.push_str(month),
+            Some('d') => out.push_str(day),
+            Some('H') => out.push_str(hour),
+            Some('M') => out.push_str(minute),
+            Some('S') => out.push_str(second),
+            Some('j') => out.push_str(&format!("{:03}", day_of_year)),
+            Some('s') => out.push_str(&secs.to_string()),
+            Some('F') => out.push_str(&format!("{}-{}-{}", year, month, day)),
+            Some('T') => out.push_str(&format!("{}:{}:{}", hour, minute, second)),
+            Some('%') => out.push('%'),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// Gzip a closed file next to it and remove the original
+fn compress(path: &Path, compression: Compression) -> io::Result<PathBuf> {
+    let target = compressed_path(path, compression);
+    let mut input = File::open(path)?;
+    let output = BufWriter::new(File::create(&target)?);
+    let mut encoder = match compression {
+        Compression::Gzip => GzEncoder::new(output, flate2::Compression::default()),
+    };
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.flush()?;
+    fs::remove_file(path)?;
+    Ok(target)
+}
+
+fn join(handle: JoinHandle<io::Result<PathBuf>>) -> io::Result<()> {
+    handle
+        .join()
+        .map_err(|_| io::Error::other("compression thread panicked"))?
+        .map(|_| ())
+}
+
+fn compressed_path(path: &Path, compression: Compression) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(compression.extension());
+    PathBuf::from(name)
+}
+
+struct Current {
+    path: PathBuf,
+    out: BufWriter<File>,
+    bytes: u64,
+    /// packet time of the first record
+    start: i64,
+}
+
+/// Writer of pcap files, rotated as asked
+pub struct RotatingWriter {
+    template: String,
+    rotation: Rotation,
+    linktype: u32,
+    snaplen: u32,
+    precision: Precision,
+    current: Option<Current>,
+    /// files started so far
+    count: u64,
+    /// closed files, oldest first, as they end up on disk
+    closed: VecDeque<PathBuf>,
+    /// compressions running, oldest first
+    compressing: VecDeque<JoinHandle<io::Result<PathBuf>>>,
+}
+
+impl RotatingWriter {
+    /// Files are named after `template`, the first being created with the first packet;
+    /// their timestamps are in micro or nanoseconds as asked
+    pub fn new(
+        template: &str,
+        linktype: u32,
+        snaplen: u32,
+        precision: Precision,
+        rotation: Rotation,
+    ) -> Self {
+        RotatingWriter {
+            template: template.to_string(),
+            rotation,
+            linktype,
+            snaplen,
+            precision,
+            current: None,
+            count: 0,
+            closed: VecDeque::new(),
+            compressing: VecDeque::new(),
+        }
+    }
+
+    /// Name of the next file: the template at `secs`, numbered when names could repeat
+    fn file_name(&self, secs: i64) -> PathBuf {
+        let name = strftime(&self.template, secs);
+        let timed = name != self.template;
+        let rotating = self.rotation.max_bytes.is_some()
+            || self.rotation.max_files.is_some()
+            || (self.rotation.max_seconds.is_some() && !timed);
+        if !rotating {
+            return PathBuf::from(name);
+        }
+        match self.rotation.max_files {
+            // ring of numbered files, padded to the width of the largest number
+            Some(n) if !timed => {
+                let width = n.saturating_sub(1).max(1).to_string().len();
+                PathBuf::from(format!(
+                    "{}{:0width$}",
+                    name,
+                    self.count % n as u64,
+                    width = width
+                ))
+            }
+            _ if timed && self.rotation.max_bytes.is_none() => PathBuf::from(name),
+            _ => PathBuf::from(format!("{}{}", name, self.count)),
+        }
+    }
+
+    fn open(&mut self, secs: i64) -> io::Result<()> {
+        let path = self.file_name(secs);
+        if let Some(max) = self.rotation.max_files {
+            // the ring is full: the oldest file goes, compressed or not
+            while self.closed.len() + 1 > max.max(1) {
+                self.wait_compression()?;
+                if let Some(oldest) = self.closed.pop_front() {
+                    match fs::remove_file(&oldest) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let mut out = BufWriter::new(File::create(&path)?);
+        let magic: u32 = match self.precision {
+            Precision::Micro => 0xa1b2c3d4,
+            Precision::Nano => 0xa1b23c4d,
+        };
+        out.write_all(&magic.to_le_bytes())?;
+        out.write_all(&2u16.to_le_bytes())?; // version major
+        out.write_all(&4u16.to_le_bytes())?; // version minor
+        out.write_all(&0i32.to_le_bytes())?; // thiszone
+        out.write_all(&0u32.to_le_bytes())?; // sigfigs
+        out.write_all(&self.snaplen.to_le_bytes())?;
+        out.write_all(&self.linktype.to_le_bytes())?;
+        self.count += 1;
+        self.current = Some(Current {
+            path,
+            out,
+            bytes: GLOBAL_HEADER_SIZE,
+            start: secs,
+        });
+        Ok(())
+    }
+
+    /// Close the current file, compressing it when asked
+    fn finish(&mut self) -> io::Result<()> {
+        let current = match self.current.take() {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let mut out = current.out;
+        out.flush()?;
+        drop(out);
+        match self.rotation.compression {
+            Some(compression) => {
+                self.reap_compression()?;
+                while self.compressing.len() >= MAX_COMPRESSING {
+                    if let Some(oldest) = self.compressing.pop_front() {
+                        join(oldest)?;
+                    }
+                }
+                let path = current.path.clone();
+                self.closed.push_back(compressed_path(&path, compression));
+                self.compressing
+                    .push_back(thread::spawn(move || compress(&path, compression)));
+            }
+            None => self.closed.push_back(current.path),
+        }
+        Ok(())
+    }
+
+    /// Join the compressions that ended, returning the first error among them
+    fn reap_compression(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        let mut running = VecDeque::with_capacity(self.compressing.len());
+        for handle in self.compressing.drain(..) {
+            if handle.is_finished() {
+                result = result.and(join(handle));
+            } else {
+                running.push_back(handle);
+            }
+        }
+        self.compressing = running;
+        result
+    }
+
+    /// Wait for all the compressions, returning the first error among them
+    fn wait_compression(&mut self) -> io::Result<()> {
+        let mut result = Ok(());
+        for handle in self.compressing.drain(..) {
+            result = result.and(join(handle));
+        }
+        result
+    }
+
+    fn must_rotate(&self, secs: i64) -> bool {
+        let current = match &self.current {
+            Some(c) => c,
+            None => return false,
+        };
+        let full = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max| current.bytes >= max && current.bytes > GLOBAL_HEADER_SIZE);
+        let expired = self
+            .rotation
+            .max_seconds
+            .is_some_and(|max| secs.saturating_sub(current.start) >= max as i64);
+        full || expired
+    }
+
+    /// Append a packet captured at `ts`, `orig_len` bytes long on the wire
+    pub fn write(&mut self, ts: Timestamp, orig_len: u32, data: &[u8]) -> io::Result<()> {
+        let secs = ts.secs;
+        if self.must_rotate(secs) {
+            self.finish()?;
+        }
+        if self.current.is_none() {
+            self.open(secs)?;
+        }
+        let current = self.current.as_mut().expect("a file is open");
+        let out = &mut current.out;
+        out.write_all(&(secs as u32).to_le_bytes())?;
+        out.write_all(&self.precision.fraction(ts).to_le_bytes())?;
+        out.write_all(&(data.len() as u32).to_le_bytes())?;
+        out.write_all(&orig_len.to_le_bytes())?;
+        out.write_all(data)?;
+        current.bytes += RECORD_HEADER_SIZE + data.len() as u64;
+        Ok(())
+    }
+
+    /// Path of the file being written
+    pub fn current_path(&self) -> Option<&Path> {
+        self.current.as_ref().map(|c| c.path.as_path())
+    }
+
+    /// Files started so far
+    pub fn files(&self) -> u64 {
+        self.count
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.current.as_mut() {
+            Some(c) => c.out.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Close the last file and wait for the compressions to end
+    pub fn close(mut self) -> io::Result<()> {
+        self.finish()?;
+        self.wait_compression()
+    }
+}
@@ -0,0 +1,159 @@
+//! Packet timestamps, to the nanosecond
+//!
+//! Captures stamp packets in micro or nanoseconds (classic pcap), or in any power of 10 or
+//! 2 of a second per interface, shifted by an offset in seconds (pcapng). A `Timestamp`
+//! keeps seconds and nanoseconds since the epoch, so that none of them loses precision and
+//! differences between packets (durations, round trip times) are exact.
+//!
+//! In JSON a timestamp is a number of seconds, as it always was: a double keeps about a
+//! tenth of a microsecond for current dates, which is why differences are computed before
+//! the conversion. Text output prints all nine digits, or fewer with a precision
+//! (`{:.6}`).
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+/// Seconds and nanoseconds since the epoch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    pub secs: i64,
+    /// below a second
+    pub nanos: u32,
+}
+
+/// Resolution of the timestamps of a pcap file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    #[default]
+    Micro,
+    Nano,
+}
+
+impl Precision {
+    /// Units of the fraction of a second in a record header
+    pub fn fraction(self, ts: Timestamp) -> u32 {
+        match self {
+            Precision::Micro => ts.micros(),
+            Precision::Nano => ts.nanos,
+        }
+    }
+}
+
+impl Timestamp {
+    /// Nanoseconds beyond a second carry over into the seconds
+    pub fn new(secs: i64, nanos: u32) -> Self {
+        Timestamp {
+            secs: secs.saturating_add(i64::from(nanos / NANOS_PER_SEC)),
+            nanos: nanos % NANOS_PER_SEC,
+        }
+    }
+
+    pub fn from_micros(secs: i64, micros: u32) -> Self {
+        Timestamp::new(secs, micros.saturating_mul(1000))
+    }
+
+    /// Time of a count of `units` per second since the epoch, shifted by `offset` seconds,
+    /// as in pcapng (`if_tsresol` and `if_tsoffset`); fractions below the nanosecond are
+    /// dropped
+    pub fn from_units(count: u64, units: u64, offset: i64) -> Self {
+        let units = units.max(1);
+        let fraction = u128::from(count % units) * u128::from(NANOS_PER_SEC) / u128::from(units);
+        let secs = i64::try_from(count / units).unwrap_or(i64::MAX);
+        Timestamp::new(secs.saturating_add(offset), fraction as u32)
+    }
+
+    pub fn from_system_time(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => Timestamp::new(d.as_secs() as i64, d.subsec_nanos()),
+            Err(e) => {
+                // before the epoch: whole seconds down, the fraction counted up from them
+                let d = e.duration();
+                let secs = -(d.as_secs() as i64);
+                match d.subsec_nanos() {
+                    0 => Timestamp::new(secs, 0),
+                    n => Timestamp::new(secs - 1, NANOS_PER_SEC - n),
+                }
+            }
+        }
+    }
+
+    /// The same time as a `SystemTime`, none when it cannot hold it
+    pub fn system_time(self) -> Option<SystemTime> {
+        let time = match u64::try_from(self.secs) {
+            Ok(secs) => UNIX_EPOCH.checked_add(Duration::from_secs(secs))?,
+            Err(_) => UNIX_EPOCH.checked_sub(Duration::from_secs(self.secs.unsigned_abs()))?,
+        };
+        time.checked_add(Duration::from_nanos(self.nanos.into()))
+    }
+
+    /// Microseconds below the second, truncated
+    pub fn micros(self) -> u32 {
+        self.nanos / 1000
+    }
+
+    /// Nanoseconds since the epoch
+    pub fn as_nanos(self) -> i128 {
+        i128::from(self.secs) * i128::from(NANOS_PER_SEC) + i128::from(self.nanos)
+    }
+
+    /// Seconds since the epoch, as a double
+    pub fn as_secs_f64(self) -> f64 {
+        self.secs as f64 + f64::from(self.nanos) / 1e9
+    }
+
+    /// Seconds elapsed since `earlier`, negative when it is later; exact before the
+    /// conversion to a double
+    pub fn seconds_since(self, earlier: Timestamp) -> f64 {
+        (self.as_nanos() - earlier.as_nanos()) as f64 / 1e9
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // a negative time is whole seconds down and a fraction up: print it as a sign
+        let (sign, secs, nanos) = match (self.secs, self.nanos) {
+            (s, n) if s < 0 && n > 0 => ("-", (s + 1).unsigned_abs(), NANOS_PER_SEC - n),
+            (s, n) if s < 0 => ("-", s.unsigned_abs(), n),
+            (s, n) => ("", s as u64, n),
+        };
+        let digits = f.precision().unwrap_or(9).min(9);
+        if digits == 0 {
+            return write!(f, "{}{}", sign, secs);
+        }
+        let fraction = nanos / 10u32.pow(9 - digits as u32);
+        write!(f, "{}{}.{:0width$}", sign, secs, fraction, width = digits)
+    }
+}
+
+impl Serialize for Timestamp {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.as_secs_f64())
+    }
+}
+
+/// Format seconds since the unix epoch as an UTC ISO 8601 date
+pub fn format_time(secs: i64) -> String {
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
@@ -0,0 +1,201 @@
+//! Protocol tree of a packet: its layers and fields, with the bytes of each
+//!
+//! Decoders add the layers and fields they read here, as they would appear in Wireshark's
+//! detail pane, so that a field can be linked to its bytes in the frame: to highlight them
+//! in a hex pane, or to annotate a hex dump. Like expert info, the tree is built per thread,
+//! and only between `begin` and `finish`; the calls do nothing otherwise.
+//!
+//! Decoders give the bytes a layer or field was read from, as a slice or as a field of a
+//! header viewed in place. Its offset in the frame is found from its address, so decoders
+//! don't need to know where their data starts. Bytes outside of the frame, such as those of
+//! a reassembled stream, have no offset.
+use serde::Serialize;
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::mem::size_of_val;
+
+use crate::utils::Wire;
+
+/// A layer of a packet, such as its IPv4 or TCP header
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Layer {
+    /// name of the decoder, as in expert info: `ethernet`, `ipv4`, ...
+    pub name: &'static str,
+    /// one line description of the layer
+    pub summary: String,
+    /// first byte of the layer in the frame, none when not read from the frame
+    pub offset: Option<usize>,
+    pub len: usize,
+    pub fields: Vec<Field>,
+}
+
+/// A field of a layer, and the fields it is made of
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Field {
+    pub name: &'static str,
+    pub value: String,
+    /// first byte of the field in the frame, none when not read from the frame
+    pub offset: Option<usize>,
+    pub len: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Field>,
+}
+
+/// What a layer or a field was read from
+pub trait Bytes {
+    fn bytes(&self) -> &[u8];
+}
+
+impl Bytes for [u8] {
+    fn bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<T: Wire> Bytes for T {
+    fn bytes(&self) -> &[u8] {
+        // Safety: a Wire type has no padding, so all its bytes are initialized
+        unsafe { std::slice::from_raw_parts(self as *const T as *const u8, size_of_val(self)) }
+    }
+}
+
+struct Tree {
+    /// address and length of the frame
+    frame: (usize, usize),
+    layers: Vec<Layer>,
+    /// fields opened and not closed yet, innermost last
+    open: Vec<Field>,
+}
+
+impl Tree {
+    fn span(&self, bytes: &[u8]) -> (Option<usize>, usize) {
+        let (start, len) = self.frame;
+        let offset = (bytes.as_ptr() as usize).wrapping_sub(start);
+        if offset <= len && bytes.len() <= len - offset {
+            (Some(offset), bytes.len())
+        } else {
+            (None, bytes.len())
+        }
+    }
+
+    fn add(&mut self, field: Field) {
+        match self.open.last_mut() {
+            Some(parent) => parent.children.push(field),
+            None => {
+                if let Some(layer) = self.layers.last_mut() {
+                    layer.fields.push(field);
+                }
+            }
+        }
+    }
+
+    fn close_all(&mut self) {
+        while let Some(field) = self.open.pop() {
+            self.add(field);
+        }
+    }
+}
+
+thread_local! {
+    static TREE: RefCell<Option<Tree>> = const { RefCell::new(None) };
+}
+
+/// Start the tree of a frame, for the packet about to be decoded
+pub fn begin(frame: &[u8]) {
+    TREE.with(|t| {
+        *t.borrow_mut() = Some(Tree {
+            frame: (frame.as_ptr() as usize, frame.len()),
+            layers: Vec::new(),
+            open: Vec::new(),
+        })
+    });
+}
+
+/// Take the tree of the packet decoded, and stop building one
+pub fn finish() -> Vec<Layer> {
+    TREE.with(|t| match t.borrow_mut().take() {
+        Some(mut tree) => {
+            tree.close_all();
+            tree.layers
+        }
+        None => Vec::new(),
+    })
+}
+
+/// Decode a frame and return its tree
+pub fn dissect(frame: &[u8], decode: impl FnOnce(&[u8])) -> Vec<Layer> {
+    begin(frame);
+    decode(frame);
+    finish()
+}
+
+/// Whether a tree is being built, for decoders to skip the work of describing fields when
+/// it is costly
+pub fn enabled() -> bool {
+    TREE.with(|t| t.borrow().is_some())
+}
+
+fn with(f: impl FnOnce(&mut Tree)) {
+    TREE.with(|t| {
+        if let Some(tree) = t.borrow_mut().as_mut() {
+            f(tree);
+        }
+    });
+}
+
+/// Start a layer read from `bytes`; the fields added next belong to it
+pub fn layer(name: &'static str, bytes: &(impl Bytes + ?Sized), summary: impl Display) {
+    with(|t| {
+        t.close_all();
+        let (offset, len) = t.span(bytes.bytes());
+        t.layers.push(Layer {
+            name,
+            summary: summary.to_string(),
+            offset,
+            len,
+            fields: Vec::new(),
+        });
+    });
+}
+
+/// Add a field of the current layer, or of the field opened last
+pub fn field(name: &'static str, bytes: &(impl Bytes + ?Sized), value: impl Display) {
+    with(|t| {
+        let (offset, len) = t.span(bytes.bytes());
+        t.add(Field {
+            name,
+            value: value.to_string(),
+            offset,
+            len,
+            children: Vec::new(),
+        });
+    });
+}
+
+/// Add a field made of other fields: the fields added next belong to it, until `close`
+pub fn open(name: &'static str, bytes: &(impl Bytes + ?Sized), value: impl Display) {
+    with(|t| {
+        let (offset, len) = t.span(bytes.bytes());
+        t.open.push(Field {
+            name,
+            value: value.to_string(),
+            offset,
+            len,
+            children: Vec::new(),
+        });
+    });
+}
+
+/// End the field opened last
+pub fn close() {
+    with(|t| {
+        if let Some(field) = t.open.pop() {
+            t.add(field);
+        }
+    });
+}
+
+/// Value of a field shown as its bytes in hex
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
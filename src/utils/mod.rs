@@ -1,34 +1,232 @@
-use std::borrow::Cow;
-use std::{mem, slice};
+use std::fmt;
+use std::mem::{align_of, size_of};
 
-/// Gives a mutable slice of the bytes of the given element.
-#[inline]
-pub fn bytes_of_mut<T: 'static + Copy>(elem: &mut T) -> &mut [u8] {
-    let slice = slice::from_mut(elem);
-    let new_len = mem::size_of_val(slice);
-    unsafe { slice::from_raw_parts_mut(slice.as_ptr() as *mut u8, new_len) }
+mod reader;
+pub use reader::{ByteReader, Truncated};
+
+/// A type that can be read in place from any bytes, at any address
+///
+/// # Safety
+///
+/// Implementors must have an alignment of 1, no padding, and every bit pattern must be a
+/// valid value. Header structures get it through `wire_struct!`, which checks this.
+pub unsafe trait Wire: Copy + 'static {}
+
+unsafe impl Wire for u8 {}
+unsafe impl<T: Wire, const N: usize> Wire for [T; N] {}
+
+macro_rules! be_int {
+    ($(#[$meta:meta])* $name:ident, $int:ty, $n:literal) => {
+        $(#[$meta])*
+        #[derive(Default, Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(transparent)]
+        pub struct $name([u8; $n]);
+
+        impl $name {
+            pub const fn new(value: $int) -> Self {
+                $name(value.to_be_bytes())
+            }
+
+            /// Value in host order
+            pub const fn get(self) -> $int {
+                <$int>::from_be_bytes(self.0)
+            }
+        }
+
+        impl From<$int> for $name {
+            fn from(value: $int) -> Self {
+                $name::new(value)
+            }
+        }
+
+        impl From<$name> for $int {
+            fn from(value: $name) -> Self {
+                value.get()
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Debug::fmt(&self.get(), f)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(&self.get(), f)
+            }
+        }
+
+        unsafe impl Wire for $name {}
+    };
+}
+
+be_int!(
+    /// u16 in network byte order, as laid out on the wire
+    U16Be,
+    u16,
+    2
+);
+be_int!(
+    /// u32 in network byte order, as laid out on the wire
+    U32Be,
+    u32,
+    4
+);
+be_int!(
+    /// u64 in network byte order, as laid out on the wire
+    U64Be,
+    u64,
+    8
+);
+
+/// Declare a header structure readable in place with `view`
+///
+/// The structure is `repr(C)` and its fields must be `Wire` (bytes, byte arrays, `U16Be`,
+/// `U32Be`, `U64Be`, other wire structures), which is checked at compile time.
+macro_rules! wire_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_meta:meta])* $field_vis:vis $field:ident: $ty:ty,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[repr(C)]
+        $vis struct $name {
+            $($(#[$field_meta])* $field_vis $field: $ty,)*
+        }
+
+        // Safety: all the fields are Wire and the alignment is 1, so there is no padding
+        unsafe impl $crate::utils::Wire for $name {}
+
+        const _: () = {
+            const fn wire<T: $crate::utils::Wire>() {}
+            $(wire::<$ty>();)*
+            assert!(::std::mem::align_of::<$name>() == 1);
+        };
+    };
 }
+pub(crate) use wire_struct;
 
-/// Returns either a borrowed version of the struct if target bytes are well aligned
-/// (zero-copy, avoiding unsafe on the decode path via slice::align_to),
-/// and falls back to an owned version that involves copying the bytes.
+/// Declare an enum for a registry of numbers, such as the EtherTypes or the IP protocols
 ///
-/// Returns None in case the number of bytes doesn't match the struct size.
+/// Each variant is given its value and the name the registry uses for it, and an
+/// `Unknown` variant holds the values the registry does not assign. The enum converts
+/// from and to its number, displays as the registry name (or the number, with the given
+/// format, when unknown), and compares, hashes and serializes as its number.
+macro_rules! registry {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident: $repr:ty, unknown = $unknown:literal {
+            $($(#[$variant_meta:meta])* $variant:ident = $value:literal => $label:literal,)*
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy)]
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant,)*
+            /// a value the registry does not assign
+            Unknown($repr),
+        }
+
+        impl $name {
+            /// Name of the value in the registry
+            pub fn name(self) -> Option<&'static str> {
+                match self {
+                    $($name::$variant => Some($label),)*
+                    $name::Unknown(_) => None,
+                }
+            }
+        }
+
+        impl From<$repr> for $name {
+            fn from(value: $repr) -> Self {
+                match value {
+                    $($value => $name::$variant,)*
+                    n => $name::Unknown(n),
+                }
+            }
+        }
+
+        impl From<$name> for $repr {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $value,)*
+                    $name::Unknown(n) => n,
+                }
+            }
+        }
+
+        // compared by value, so that `Unknown` made by hand with an assigned value is still
+        // equal to its variant, and sorts among the others
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                <$repr>::from(*self) == <$repr>::from(*other)
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl ::std::hash::Hash for $name {
+            fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+                <$repr>::from(*self).hash(state)
+            }
+        }
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<::std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> ::std::cmp::Ordering {
+                <$repr>::from(*self).cmp(&<$repr>::from(*other))
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                match self.name() {
+                    Some(name) => f.write_str(name),
+                    None => write!(f, $unknown, <$repr>::from(*self)),
+                }
+            }
+        }
+
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+                <$repr>::from(*self).serialize(s)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+                <$repr>::deserialize(d).map($name::from)
+            }
+        }
+    };
+}
+pub(crate) use registry;
+
+/// View the first bytes of `data` as a `T`, without copying
+///
+/// Returns None when `data` is shorter than a `T`.
 #[inline]
-pub fn cow_struct<T: 'static + Copy + Default>(bytes: &[u8]) -> Option<Cow<'_, T>> {
-    if bytes.len() != mem::size_of::<T>() {
+pub fn view<T: Wire>(data: &[u8]) -> Option<&T> {
+    if data.len() < size_of::<T>() {
         return None;
     }
+    debug_assert_eq!(align_of::<T>(), 1);
+    // Safety: the bytes are long enough, and a Wire type has an alignment of 1 and
+    // accepts any bit pattern
+    Some(unsafe { &*(data.as_ptr() as *const T) })
+}
 
-    // Zero-copy path when the input is properly aligned for T.
-    // align_to is unsafe to call; we immediately validate perfect alignment and element count.
-    let (head, body, tail) = unsafe { bytes.align_to::<T>() };
-    if head.is_empty() && tail.is_empty() && body.len() == 1 {
-        return Some(Cow::Borrowed(&body[0]));
-    }
-
-    // Fallback: copy into an owned T when alignment doesn't permit zero-copy.
-    let mut elem = T::default();
-    bytes_of_mut(&mut elem).copy_from_slice(bytes);
-    Some(Cow::Owned(elem))
+/// View the first bytes of `data` as a `T` and return the bytes after it
+#[inline]
+pub fn split<T: Wire>(data: &[u8]) -> Option<(&T, &[u8])> {
+    let header = view::<T>(data)?;
+    Some((header, &data[size_of::<T>()..]))
 }
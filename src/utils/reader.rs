@@ -0,0 +1,190 @@
+//! Bounds-checked cursor over the bytes of a message
+//!
+//! Every read checks the length first and returns `Truncated` instead of panicking, so a
+//! parser written with `?` gives up cleanly on a short or crafted message. A read that fails
+//! leaves the cursor where it was. Integers are read in network byte order unless the method
+//! says otherwise.
+use std::fmt;
+use std::mem::size_of;
+
+use super::Wire;
+
+/// The message ended before the field being read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncated;
+
+impl fmt::Display for Truncated {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("truncated")
+    }
+}
+
+impl std::error::Error for Truncated {}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, pos: 0 }
+    }
+
+    /// Offset of the next byte read, from the start of the data
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    /// The bytes not read yet
+    pub fn rest(&self) -> &'a [u8] {
+        &self.data[self.pos..]
+    }
+
+    /// Next byte, without consuming it
+    pub fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], Truncated> {
+        let end = self.pos.checked_add(n).ok_or(Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, n: usize) -> Result<(), Truncated> {
+        self.bytes(n).map(|_| ())
+    }
+
+    /// Move to an offset from the start of the data, which may be its end
+    pub fn seek(&mut self, pos: usize) -> Result<(), Truncated> {
+        if pos > self.data.len() {
+            return Err(Truncated);
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// Consume the next byte if it is `b`
+    pub fn eat(&mut self, b: u8) -> bool {
+        let found = self.peek() == Some(b);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], Truncated> {
+        let mut a = [0u8; N];
+        a.copy_from_slice(self.bytes(N)?);
+        Ok(a)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Truncated> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Truncated> {
+        self.array().map(u16::from_be_bytes)
+    }
+
+    pub fn u24(&mut self) -> Result<u32, Truncated> {
+        let [a, b, c] = self.array()?;
+        Ok(u32::from_be_bytes([0, a, b, c]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Truncated> {
+        self.array().map(u32::from_be_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Truncated> {
+        self.array().map(u64::from_be_bytes)
+    }
+
+    pub fn u16_le(&mut self) -> Result<u16, Truncated> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    pub fn u32_le(&mut self) -> Result<u32, Truncated> {
+        self.array().map(u32::from_le_bytes)
+    }
+
+    /// A header structure, borrowed in place
+    pub fn view<T: Wire>(&mut self) -> Result<&'a T, Truncated> {
+        let bytes = self.bytes(size_of::<T>())?;
+        super::view::<T>(bytes).ok_or(Truncated)
+    }
+
+    /// Bytes prefixed by their length, on one byte
+    pub fn bytes_u8(&mut self) -> Result<&'a [u8], Truncated> {
+        self.atomic(|r| {
+            let n = r.u8()?;
+            r.bytes(n.into())
+        })
+    }
+
+    /// Bytes prefixed by their length, on two bytes
+    pub fn bytes_u16(&mut self) -> Result<&'a [u8], Truncated> {
+        self.atomic(|r| {
+            let n = r.u16()?;
+            r.bytes(n.into())
+        })
+    }
+
+    /// Type, length and value with a one byte type and length
+    pub fn tlv8(&mut self) -> Result<(u8, &'a [u8]), Truncated> {
+        self.atomic(|r| Ok((r.u8()?, r.bytes_u8()?)))
+    }
+
+    /// Type, length and value with a two bytes type and length
+    pub fn tlv16(&mut self) -> Result<(u16, &'a [u8]), Truncated> {
+        self.atomic(|r| Ok((r.u16()?, r.bytes_u16()?)))
+    }
+
+    /// Length of a BER encoded element, in the short or the long form (up to 4 bytes)
+    pub fn ber_len(&mut self) -> Result<usize, Truncated> {
+        self.atomic(|r| {
+            let b = r.u8()?;
+            if b & 0x80 == 0 {
+                return Ok(b.into());
+            }
+            let n = usize::from(b & 0x7F);
+            // the indefinite form (0x80) and lengths past 4 GB are not supported
+            if n == 0 || n > 4 {
+                return Err(Truncated);
+            }
+            let bytes = r.bytes(n)?;
+            Ok(bytes.iter().fold(0, |v, &b| (v << 8) | usize::from(b)))
+        })
+    }
+
+    /// BER element with a single byte tag: its tag and contents
+    pub fn ber(&mut self) -> Result<(u8, &'a [u8]), Truncated> {
+        self.atomic(|r| {
+            let tag = r.u8()?;
+            let len = r.ber_len()?;
+            Ok((tag, r.bytes(len)?))
+        })
+    }
+
+    /// Run a read made of several, moving the cursor only if all of them succeed
+    fn atomic<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, Truncated>,
+    ) -> Result<T, Truncated> {
+        let mut r = *self;
+        let value = read(&mut r)?;
+        *self = r;
+        Ok(value)
+    }
+}
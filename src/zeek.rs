@@ -0,0 +1,433 @@
+//! Zeek-style TSV logs
+//!
+//! Events are written to `http.log`, `ssl.log`, `files.log` and `notice.log` in a
+//! directory, with the header lines of Zeek's ASCII writer so that `zeek-cut` and the
+//! usual tooling read them. Connections are identified by a `uid` derived from the flow,
+//! the same for both directions. When GeoIP databases are loaded, the country, city, AS
+//! and scope of both endpoints are appended to every record.
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::analysis::geoip::{self, GeoInfo};
+use crate::context;
+use crate::event::Event;
+use crate::flow::FlowKey;
+use crate::timestamp;
+
+const UNSET: &str = "-";
+const EMPTY: &str = "(empty)";
+
+/// Columns shared by every log
+const CONN_FIELDS: [(&str, &str); 6] = [
+    ("ts", "time"),
+    ("uid", "string"),
+    ("id.orig_h", "addr"),
+    ("id.orig_p", "port"),
+    ("id.resp_h", "addr"),
+    ("id.resp_p", "port"),
+];
+
+const GEO_FIELDS: [(&str, &str); 5] = [
+    ("cc", "string"),
+    ("city", "string"),
+    ("asn", "count"),
+    ("org", "string"),
+    ("scope", "string"),
+];
+
+const HTTP_FIELDS: [(&str, &str); 10] = [
+    ("method", "string"),
+    ("host", "string"),
+    ("uri", "string"),
+    ("version", "string"),
+    ("user_agent", "string"),
+    ("request_body_len", "count"),
+    ("response_body_len", "count"),
+    ("status_code", "count"),
+    ("status_msg", "string"),
+    ("resp_mime_types", "vector[string]"),
+];
+
+const SSL_FIELDS: [(&str, &str); 7] = [
+    ("version", "string"),
+    ("cipher", "string"),
+    ("server_name", "string"),
+    ("next_protocol", "string"),
+    ("ja3", "string"),
+    ("ja4", "string"),
+    ("ja4s", "string"),
+];
+
+const FILES_FIELDS: [(&str, &str); 9] = [
+    ("source", "string"),
+    ("filename", "string"),
+    ("mime_type", "string"),
+    ("total_bytes", "count"),
+    ("truncated", "bool"),
+    ("md5", "string"),
+    ("sha1", "string"),
+    ("sha256", "string"),
+    ("extracted", "string"),
+];
+
+const NOTICE_FIELDS: [(&str, &str); 3] = [("note", "string"), ("msg", "string"), ("sub", "string")];
+
+/// Value of a column, written escaped
+enum Value {
+    Unset,
+    Str(String),
+    Count(u64),
+}
+
+impl From<Option<String>> for Value {
+    fn from(v: Option<String>) -> Self {
+        v.map_or(Value::Unset, Value::Str)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::Str(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::Str(v)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(v: u64) -> Self {
+        Value::Count(v)
+    }
+}
+
+/// Non printable bytes, the separator and backslashes as `\xNN`
+fn escape(s: &str) -> String {
+    if s.is_empty() {
+        return EMPTY.to_string();
+    }
+    if s == UNSET {
+        return "\\x2d".to_string();
+    }
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\t' | '\\' => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c if c.is_control() => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+impl Value {
+    fn render(&self) -> String {
+        match self {
+            Value::Unset => UNSET.to_string(),
+            Value::Str(s) => escape(s),
+            Value::Count(n) => n.to_string(),
+        }
+    }
+}
+
+/// Connection identifier in Zeek's shape: `C` and base62 digits
+pub fn uid(flow: &FlowKey) -> String {
+    uid_of(flow.id())
+}
+
+fn uid_of(id: u64) -> String {
+    const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    let mut n = id;
+    let mut out = Vec::new();
+    while n > 0 {
+        out.push(DIGITS[(n % 62) as usize]);
+        n /= 62;
+    }
+    out.push(b'C');
+    out.reverse();
+    String::from_utf8(out).unwrap_or_default()
+}
+
+fn epoch(t: SystemTime) -> String {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!("{}.{:06}", d.as_secs(), d.subsec_micros())
+}
+
+/// Date of the `#open` and `#close` lines
+fn header_time(t: SystemTime) -> String {
+    let d = t.duration_since(UNIX_EPOCH).unwrap_or_default();
+    timestamp::format_time(d.as_secs() as i64)
+        .trim_end_matches('Z')
+        .replace(['T', ':'], "-")
+}
+
+fn geo_values(info: Option<GeoInfo>) -> Vec<Value> {
+    match info {
+        Some(g) => vec![
+            g.country.into(),
+            g.city.into(),
+            g.asn.map_or(Value::Unset, |a| Value::Count(a as u64)),
+            g.org.into(),
+            g.scope.into(),
+        ],
+        None => (0..GEO_FIELDS.len()).map(|_| Value::Unset).collect(),
+    }
+}
+
+struct Log {
+    out: BufWriter<File>,
+}
+
+/// Writer of the logs of a directory, each log is created with its first record
+pub struct ZeekLogs {
+    dir: PathBuf,
+    logs: HashMap<&'static str, Log>,
+    /// append the GeoIP columns
+    geo: bool,
+}
+
+impl ZeekLogs {
+    /// Logs in `dir`, created if needed; GeoIP columns are added if databases are loaded
+    pub fn create<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(ZeekLogs {
+            dir: dir.as_ref().to_path_buf(),
+            logs: HashMap::new(),
+            geo: geoip::enabled(),
+        })
+    }
+
+    fn log(&mut self, path: &'static str, fields: &[(&str, &str)]) -> io::Result<&mut Log> {
+        if !self.logs.contains_key(path) {
+            let file = File::create(self.dir.join(format!("{}.log", path)))?;
+            let mut out = BufWriter::new(file);
+            let mut names: Vec<String> = Vec::new();
+            let mut types: Vec<&str> = Vec::new();
+            for (name, ty) in CONN_FIELDS.iter().chain(fields) {
+                names.push(name.to_string());
+                types.push(ty);
+            }
+            if self.geo {
+                for side in ["orig", "resp"] {
+                    for (name, ty) in GEO_FIELDS {
+                        names.push(format!("{}_{}", side, name));
+                        types.push(ty);
+                    }
+                }
+            }
+            writeln!(out, "#separator \\x09")?;
+            writeln!(out, "#set_separator\t,")?;
+            writeln!(out, "#empty_field\t{}", EMPTY)?;
+            writeln!(out, "#unset_field\t{}", UNSET)?;
+            writeln!(out, "#path\t{}", path)?;
+            writeln!(out, "#open\t{}", header_time(SystemTime::now()))?;
+            writeln!(out, "#fields\t{}", names.join("\t"))?;
+            writeln!(out, "#types\t{}", types.join("\t"))?;
+            self.logs.insert(path, Log { out });
+        }
+        Ok(self.logs.get_mut(path).expect("log just created"))
+    }
+
+    fn record(
+        &mut self,
+        path: &'static str,
+        fields: &[(&str, &str)],
+        endpoints: Option<(SocketAddr, SocketAddr)>,
+        uid: Option<String>,
+        values: Vec<Value>,
+    ) -> io::Result<()> {
+        let mut row = vec![Value::Str(epoch(context::time())), uid.into()];
+        match endpoints {
+            Some((orig, resp)) => {
+                row.push(orig.ip().to_string().into());
+                row.push(Value::Count(orig.port() as u64));
+                row.push(resp.ip().to_string().into());
+                row.push(Value::Count(resp.port() as u64));
+            }
+            None => row.extend((0..4).map(|_| Value::Unset)),
+        }
+        row.extend(values);
+        if self.geo {
+            row.extend(geo_values(endpoints.map(|e| geoip::enrich(e.0.ip()))));
+            row.extend(geo_values(endpoints.map(|e| geoip::enrich(e.1.ip()))));
+        }
+        let line: Vec<String> = row.iter().map(Value::render).collect();
+        let log = self.log(path, fields)?;
+        writeln!(log.out, "{}", line.join("\t"))
+    }
+
+    /// Write the event to its log, events without one are ignored
+    pub fn write(&mut self, event: &Event) -> io::Result<()> {
+        let endpoints = event.endpoints();
+        let flow_uid = |flow: Option<&FlowKey>| flow.map(uid);
+        match event {
+            Event::Http(e) => {
+                let mime = e
+                    .content_type
+                    .as_ref()
+                    .map(|t| t.split(';').next().unwrap_or_default().trim().to_string());
+                let values = vec![
+                    e.method.as_str().into(),
+                    e.host.clone().into(),
+                    e.uri.as_str().into(),
+                    e.version.trim_start_matches("HTTP/").into(),
+                    e.user_agent.clone().into(),
+                    e.request_body_len.into(),
+                    e.response_body_len.into(),
+                    e.status.map_or(Value::Unset, |s| Value::Count(s as u64)),
+                    e.reason.clone().into(),
+                    mime.into(),
+                ];
+                self.record(
+                    "http",
+                    &HTTP_FIELDS,
+                    endpoints,
+                    flow_uid(Some(&e.flow)),
+                    values,
+                )
+            }
+            Event::Tls(e) => {
+                let values = vec![
+                    e.version.map(str::to_string).into(),
+                    e.cipher.map(|c| format!("0x{:04x}", c)).into(),
+                    e.sni.clone().into(),
+                    e.alpn.clone().into(),
+                    e.client.as_ref().map(|c| c.ja3_hash.clone()).into(),
+                    e.client.as_ref().map(|c| c.ja4.clone()).into(),
+                    e.server.as_ref().map(|s| s.ja4s.clone()).into(),
+                ];
+                self.record(
+                    "ssl",
+                    &SSL_FIELDS,
+                    endpoints,
+                    flow_uid(e.flow.as_ref()),
+                    values,
+                )
+            }
+            Event::File(e) => {
+                let values = vec![
+                    e.protocol.to_ascii_uppercase().into(),
+                    e.filename.clone().into(),
+                    e.mime.as_str().into(),
+                    e.size.into(),
+                    if e.truncated { "T" } else { "F" }.into(),
+                    e.md5.as_str().into(),
+                    e.sha1.as_str().into(),
+                    e.sha256.as_str().into(),
+                    e.path.as_ref().map(|p| p.display().to_string()).into(),
+                ];
+                self.record(
+                    "files",
+                    &FILES_FIELDS,
+                    endpoints,
+                    flow_uid(Some(&e.flow)),
+                    values,
+                )
+            }
+            Event::Alert(e) => {
+                let values = vec![
+                    "Signature::Match".into(),
+                    e.alert.signature.as_str().into(),
+                    format!("{}:{}:{}", e.alert.gid, e.alert.signature_id, e.alert.rev).into(),
+                ];
+                let uid = Some(uid_of(e.flow_id));
+                self.record("notice", &NOTICE_FIELDS, endpoints, uid, values)
+            }
+            Event::IocMatch(e) => {
+                let values = vec![
+                    "Intel::Match".into(),
+                    format!("{} {} seen in {}", e.kind, e.indicator, e.field).into(),
+                    e.feed.as_str().into(),
+                ];
+                self.record(
+                    "notice",
+                    &NOTICE_FIELDS,
+                    endpoints,
+                    flow_uid(e.flow.as_ref()),
+                    values,
+                )
+            }
+            Event::ProtocolMismatch(e) => {
+                let values = vec![
+                    "Protocol::Port_Mismatch".into(),
+                    format!("{} port carries {}", e.expected, e.detected).into(),
+                    u64::from(e.confidence).into(),
+                ];
+                self.record(
+                    "notice",
+                    &NOTICE_FIELDS,
+                    endpoints,
+                    flow_uid(Some(&e.flow)),
+                    values,
+                )
+            }
+            Event::Credential(e) => {
+                let values = vec![
+                    "Credentials::Cleartext".into(),
+                    format!("{} {} login", e.protocol, e.mechanism).into(),
+                    e.username.clone().into(),
+                ];
+                self.record(
+                    "notice",
+                    &NOTICE_FIELDS,
+                    endpoints,
+                    flow_uid(e.flow.as_ref()),
+                    values,
+                )
+            }
+            Event::FingerprintMatch(e) => {
+                let values = vec![
+                    "TLS::Known_Fingerprint".into(),
+                    format!("{} ({})", e.fingerprint, e.label).into(),
+                    e.sni.clone().into(),
+                ];
+                self.record(
+                    "notice",
+                    &NOTICE_FIELDS,
+                    endpoints,
+                    flow_uid(e.flow.as_ref()),
+                    values,
+                )
+            }
+            Event::TlsPolicy(e) => {
+                let values = vec![
+                    "TLS::Policy_Violation".into(),
+                    e.detail.as_str().into(),
+                    e.sni.clone().into(),
+                ];
+                self.record(
+                    "notice",
+                    &NOTICE_FIELDS,
+                    endpoints,
+                    flow_uid(Some(&e.flow)),
+                    values,
+                )
+            }
+            Event::Certificates(_) | Event::TlsPolicyReport(_) => Ok(()),
+        }
+    }
+
+    /// Write the buffered records, for logs read while capturing
+    pub fn flush(&mut self) -> io::Result<()> {
+        for log in self.logs.values_mut() {
+            log.out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Write the `#close` lines and flush the logs
+    pub fn close(mut self) -> io::Result<()> {
+        let now = header_time(SystemTime::now());
+        for (_, mut log) in self.logs.drain() {
+            writeln!(log.out, "#close\t{}", now)?;
+            log.out.flush()?;
+        }
+        Ok(())
+    }
+}